@@ -30,13 +30,14 @@
 // TODO: I believe this example isn't tested ^ which kills the point of having it
 
 use smoldot::{
+    database::full_sqlite,
     identity::seed_phrase,
     libp2p::{
         multiaddr::{Multiaddr, ProtocolRef},
         PeerId,
     },
 };
-use std::{net::SocketAddr, path::PathBuf, str::FromStr};
+use std::{net::SocketAddr, num::NonZeroU32, path::PathBuf, str::FromStr};
 
 // Note: the doc-comments applied to this struct and its field are visible when the binary is
 // started with `--help`.
@@ -54,6 +55,12 @@ pub enum CliOptionsCommand {
     /// Connects to the chain and synchronizes the local database with the network.
     #[command(name = "run")]
     Run(Box<CliOptionsRun>),
+    /// Writes the finalized block and its storage, as found in the local database, to a file.
+    #[command(name = "export-state")]
+    ExportState(CliOptionsExportState),
+    /// Initializes the local database from a file produced with `export-state`.
+    #[command(name = "import-state")]
+    ImportState(CliOptionsImportState),
     /// Computes the 64 bits BLAKE2 hash of a string payload and prints the hexadecimal-encoded hash.
     #[command(name = "blake2-64bits-hash")]
     Blake264BitsHash(CliOptionsBlake264Hash),
@@ -85,6 +92,10 @@ pub struct CliOptionsRun {
     /// Bind point of the JSON-RPC server ("none" or <ip>:<port>).
     #[arg(long, default_value = "127.0.0.1:9944", value_parser = parse_json_rpc_address)]
     pub json_rpc_address: JsonRpcAddress,
+    /// Maximum number of JSON-RPC requests of a single client that can be queued at the same
+    /// time. Requests above this limit are answered with an error.
+    #[arg(long, default_value = "64")]
+    pub json_rpc_max_pending_requests: NonZeroU32,
     /// List of secret phrases to insert in the keystore of the node. Used to author blocks.
     #[arg(long, value_parser = decode_sr25519_private_key)]
     // TODO: also automatically add the same keys through ed25519?
@@ -95,6 +106,42 @@ pub struct CliOptionsRun {
     /// Do not load or store anything on disk.
     #[arg(long)]
     pub tmp: bool,
+    /// Run the off-chain worker of each finalized block.
+    #[arg(long)]
+    pub offchain_worker: bool,
+    /// Publish the addresses of the node under its authority discovery keys, and look up the
+    /// addresses of the current authorities.
+    #[arg(long)]
+    pub authority_discovery: bool,
+    /// Number of ancestors of the finalized block whose storage is kept, or "archive".
+    #[arg(long, default_value = "0", value_parser = parse_pruning)]
+    pub pruning: Pruning,
+    /// How to sync a new database: full (execute all blocks), or state (download the storage).
+    #[arg(long, default_value = "full")]
+    pub sync_mode: SyncMode,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsExportState {
+    /// Chain whose database to export ("Polkadot", "Kusama", "Westend", or a file path).
+    #[arg(long, default_value = "polkadot")]
+    pub chain: CliChain,
+    /// File to write the state to.
+    #[arg(long)]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsImportState {
+    /// Chain whose database to initialize ("Polkadot", "Kusama", "Westend", or a file path).
+    #[arg(long, default_value = "polkadot")]
+    pub chain: CliChain,
+    /// File produced with `export-state` to read the state from.
+    #[arg(long)]
+    pub input: PathBuf,
+    /// Number of ancestors of the finalized block whose storage is kept, or "archive".
+    #[arg(long, default_value = "0", value_parser = parse_pruning)]
+    pub pruning: Pruning,
 }
 
 #[derive(Debug, clap::Parser)]
@@ -166,6 +213,12 @@ pub enum Output {
     LogsJson,
 }
 
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum SyncMode {
+    Full,
+    State,
+}
+
 #[derive(Debug, Clone)]
 pub struct JsonRpcAddress(pub Option<SocketAddr>);
 
@@ -181,6 +234,21 @@ fn parse_json_rpc_address(string: &str) -> Result<JsonRpcAddress, String> {
     Err("Failed to parse JSON-RPC server address".into())
 }
 
+#[derive(Debug, Clone)]
+pub struct Pruning(pub full_sqlite::PruningMode);
+
+fn parse_pruning(string: &str) -> Result<Pruning, String> {
+    if string == "archive" {
+        return Ok(Pruning(full_sqlite::PruningMode::Archive));
+    }
+
+    if let Ok(keep_ancestors) = string.parse::<u64>() {
+        return Ok(Pruning(full_sqlite::PruningMode::Prune { keep_ancestors }));
+    }
+
+    Err("Pruning must be either a number of blocks or \"archive\"".into())
+}
+
 #[derive(Debug)]
 pub struct LogDirective(pub tracing_subscriber::filter::Directive);
 
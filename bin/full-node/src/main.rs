@@ -21,6 +21,7 @@
 
 mod cli;
 mod run;
+mod snapshot;
 
 fn main() {
     futures::executor::block_on(async_main())
@@ -29,6 +30,8 @@ fn main() {
 async fn async_main() {
     match <cli::CliOptions as clap::Parser>::parse().command {
         cli::CliOptionsCommand::Run(r) => run::run(*r).await,
+        cli::CliOptionsCommand::ExportState(opt) => snapshot::export_state(opt),
+        cli::CliOptionsCommand::ImportState(opt) => snapshot::import_state(opt),
         cli::CliOptionsCommand::Blake264BitsHash(opt) => {
             let hash = blake2_rfc::blake2b::blake2b(8, &[], opt.payload.as_bytes());
             println!("0x{}", hex::encode(hash));
@@ -29,14 +29,23 @@ use smoldot::{
         peer_id::{self, PeerId},
     },
 };
-use std::{borrow::Cow, fs, io, iter, path::PathBuf, sync::Arc, thread, time::Duration};
+use std::{
+    borrow::Cow,
+    fs, io, iter,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 use tracing::Instrument as _;
 
+mod authority_discovery_service;
 mod consensus_service;
 mod database_thread;
 mod jaeger_service;
 mod json_rpc_service;
 mod network_service;
+mod offchain_worker_service;
 
 /// Runs the node using the given configuration. Catches `SIGINT` signals and stops if one is
 /// detected.
@@ -89,19 +98,7 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
         }
     }
 
-    let chain_spec = {
-        let json: Cow<[u8]> = match &cli_options.chain {
-            cli::CliChain::Polkadot => (&include_bytes!("../../polkadot.json")[..]).into(),
-            cli::CliChain::Kusama => (&include_bytes!("../../kusama.json")[..]).into(),
-            cli::CliChain::Westend => (&include_bytes!("../../westend.json")[..]).into(),
-            cli::CliChain::Custom(path) => {
-                fs::read(path).expect("Failed to read chain specs").into()
-            }
-        };
-
-        smoldot::chain_spec::ChainSpec::from_json_bytes(&json)
-            .expect("Failed to decode chain specs")
-    };
+    let chain_spec = load_chain_spec(&cli_options.chain);
 
     // This warning message should be removed if/when the full node becomes mature.
     tracing::warn!(
@@ -154,28 +151,26 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
 
     // Directory where we will store everything on the disk, such as the database, secret keys,
     // etc.
-    let base_storage_directory =
-        if let Some(base) = directories::ProjectDirs::from("io", "paritytech", "smoldot") {
-            Some(base.data_dir().to_owned())
-        } else {
-            tracing::warn!(
-                "Failed to fetch $HOME directory. Falling back to storing everything in memory, \
-                meaning that everything will be lost when the node stops. If this is intended, \
-                please make this explicit by passing the `--tmp` flag instead."
-            );
-            None
-        };
+    let base_storage_directory = base_storage_directory();
+    if base_storage_directory.is_none() {
+        tracing::warn!(
+            "Failed to fetch $HOME directory. Falling back to storing everything in memory, \
+            meaning that everything will be lost when the node stops. If this is intended, \
+            please make this explicit by passing the `--tmp` flag instead."
+        );
+    }
 
     let (database, database_existed) = {
         // Directory supposed to contain the database.
         let db_path = base_storage_directory
             .as_ref()
-            .map(|d| d.join(chain_spec.id()).join("database").to_owned());
+            .map(|d| database_path(d, &chain_spec));
 
         let (db, existed) = open_database(
             &chain_spec,
             genesis_chain_information.as_ref(),
             db_path,
+            cli_options.pruning.0,
             matches!(cli_output, cli::Output::Informant),
         )
         .await;
@@ -186,13 +181,14 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
     let relay_chain_database = if let Some(relay_chain_spec) = &relay_chain_spec {
         let relay_db_path = base_storage_directory
             .as_ref()
-            .map(|d| d.join(relay_chain_spec.id()).join("database").to_owned());
+            .map(|d| database_path(d, relay_chain_spec));
 
         Some(Arc::new(database_thread::DatabaseThread::from(
             open_database(
                 relay_chain_spec,
                 relay_genesis_chain_information.as_ref().unwrap().as_ref(),
                 relay_db_path,
+                cli_options.pruning.0,
                 matches!(cli_output, cli::Output::Informant),
             )
             .await
@@ -281,7 +277,7 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
 
     let (network_service, network_events_receivers) =
         network_service::NetworkService::new(network_service::Config {
-            listen_addresses: cli_options.listen_addr,
+            listen_addresses: cli_options.listen_addr.clone(),
             num_events_receivers: 2 + if relay_chain_database.is_some() { 1 } else { 0 },
             chains: iter::once(network_service::ChainConfig {
                 protocol_id: chain_spec.protocol_id().to_owned(),
@@ -402,6 +398,7 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
 
     let mut network_events_receivers = network_events_receivers.into_iter();
 
+    let is_validator = !cli_options.keystore_memory.is_empty();
     let keystore = Arc::new({
         let mut keystore = keystore::Keystore::new(
             base_storage_directory
@@ -420,11 +417,13 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
     let consensus_service = consensus_service::ConsensusService::new(consensus_service::Config {
         tasks_executor: &mut |task| threads_pool.spawn_ok(task),
         genesis_block_hash,
+        banned_blocks: chain_spec.bad_blocks_hashes().cloned().collect(),
+        state_sync: matches!(cli_options.sync_mode, cli::SyncMode::State),
         network_events_receiver: network_events_receivers.next().unwrap(),
         network_service: (network_service.clone(), 0),
-        database,
+        database: database.clone(),
         block_number_bytes: usize::from(chain_spec.block_number_bytes()),
-        keystore,
+        keystore: keystore.clone(),
         jaeger_service: jaeger_service.clone(),
         slot_duration_author_ratio: 43691_u16,
     })
@@ -443,6 +442,13 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
                     .hash(usize::from(
                         relay_chain_spec.as_ref().unwrap().block_number_bytes(),
                     )),
+                banned_blocks: relay_chain_spec
+                    .as_ref()
+                    .unwrap()
+                    .bad_blocks_hashes()
+                    .cloned()
+                    .collect(),
+                state_sync: matches!(cli_options.sync_mode, cli::SyncMode::State),
                 network_events_receiver: network_events_receivers.next().unwrap(),
                 network_service: (network_service.clone(), 1),
                 database: relay_chain_database,
@@ -469,6 +475,43 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
         None
     };
 
+    // Start the off-chain workers service, if enabled.
+    // It only needs to be kept alive in order to function.
+    let _offchain_worker_service = if cli_options.offchain_worker {
+        Some(offchain_worker_service::OffchainWorkerService::new(
+            offchain_worker_service::Config {
+                tasks_executor: &mut |task| threads_pool.spawn_ok(task),
+                database: database.clone(),
+                consensus_service: consensus_service.clone(),
+                block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+                is_validator,
+                keystore: keystore.clone(),
+                local_peer_id: local_peer_id.clone(),
+                listen_addresses: cli_options.listen_addr.clone(),
+            },
+        ))
+    } else {
+        None
+    };
+
+    // Start the authority discovery service, if enabled.
+    // It only needs to be kept alive in order to function.
+    let _authority_discovery_service = if cli_options.authority_discovery {
+        Some(authority_discovery_service::AuthorityDiscoveryService::new(
+            authority_discovery_service::Config {
+                tasks_executor: &mut |task| threads_pool.spawn_ok(task),
+                database: database.clone(),
+                network_service: (network_service.clone(), 0),
+                block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+                keystore,
+                local_peer_id: local_peer_id.clone(),
+                listen_addresses: cli_options.listen_addr.clone(),
+            },
+        ))
+    } else {
+        None
+    };
+
     // Start the JSON-RPC service.
     // It only needs to be kept alive in order to function.
     //
@@ -480,6 +523,16 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
         let result = json_rpc_service::JsonRpcService::new(json_rpc_service::Config {
             tasks_executor: { &mut move |task| threads_pool.spawn_ok(task) },
             bind_address,
+            database,
+            consensus_service: consensus_service.clone(),
+            network_service: (network_service.clone(), 0),
+            block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+            chain_name: chain_spec.name().to_owned(),
+            chain_type: chain_spec.chain_type().to_owned(),
+            chain_properties_json: chain_spec.properties().to_owned(),
+            local_peer_id: local_peer_id.clone(),
+            listen_addresses: cli_options.listen_addr,
+            max_pending_requests_per_client: cli_options.json_rpc_max_pending_requests,
         })
         .await;
 
@@ -660,6 +713,37 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
     }
 }
 
+/// Loads the chain specification designated by the CLI options.
+///
+/// # Panic
+///
+/// Panics if the chain specification can't be loaded. This function is expected to be called
+/// from the `main` function.
+///
+pub fn load_chain_spec(chain: &cli::CliChain) -> chain_spec::ChainSpec {
+    let json: Cow<[u8]> = match chain {
+        cli::CliChain::Polkadot => (&include_bytes!("../../polkadot.json")[..]).into(),
+        cli::CliChain::Kusama => (&include_bytes!("../../kusama.json")[..]).into(),
+        cli::CliChain::Westend => (&include_bytes!("../../westend.json")[..]).into(),
+        cli::CliChain::Custom(path) => fs::read(path).expect("Failed to read chain specs").into(),
+    };
+
+    smoldot::chain_spec::ChainSpec::from_json_bytes(&json).expect("Failed to decode chain specs")
+}
+
+/// Returns the directory where everything is stored on the disk, such as the database, secret
+/// keys, etc. Returns `None` if the $HOME directory couldn't be determined.
+pub fn base_storage_directory() -> Option<PathBuf> {
+    directories::ProjectDirs::from("io", "paritytech", "smoldot").map(|d| d.data_dir().to_owned())
+}
+
+/// Returns the directory containing the database of the given chain.
+pub fn database_path(base_storage_directory: &Path, chain_spec: &chain_spec::ChainSpec) -> PathBuf {
+    base_storage_directory
+        .join(chain_spec.id())
+        .join("database")
+}
+
 /// Opens the database from the file system, or create a new database if none is found.
 ///
 /// If `db_path` is `None`, open the database in memory instead.
@@ -676,12 +760,14 @@ async fn open_database(
     chain_spec: &chain_spec::ChainSpec,
     genesis_chain_information: chain::chain_information::ChainInformationRef<'_>,
     db_path: Option<PathBuf>,
+    pruning_mode: full_sqlite::PruningMode,
     show_progress: bool,
 ) -> (full_sqlite::SqliteFullDatabase, bool) {
     // The `unwrap()` here can panic for example in case of access denied.
     match background_open_database(
         db_path.clone(),
         chain_spec.block_number_bytes().into(),
+        Some(pruning_mode),
         show_progress,
     )
     .await
@@ -689,12 +775,18 @@ async fn open_database(
     {
         // Database already exists and contains data.
         full_sqlite::DatabaseOpen::Open(database) => {
-            if database.block_hash_by_number(0).unwrap().next().unwrap()
-                != genesis_chain_information
-                    .finalized_block_header
-                    .hash(chain_spec.block_number_bytes().into())
-            {
-                panic!("Mismatch between database and chain specification. Shutting down node.");
+            // The genesis block isn't in the database if the database has been initialized
+            // from a snapshot.
+            if let Some(genesis_hash) = database.block_hash_by_number(0).unwrap().next() {
+                if genesis_hash
+                    != genesis_chain_information
+                        .finalized_block_header
+                        .hash(chain_spec.block_number_bytes().into())
+                {
+                    panic!(
+                        "Mismatch between database and chain specification. Shutting down node."
+                    );
+                }
             }
 
             (database, true)
@@ -729,8 +821,9 @@ async fn open_database(
 async fn background_open_database(
     path: Option<PathBuf>,
     block_number_bytes: usize,
+    pruning_mode: Option<full_sqlite::PruningMode>,
     show_progress: bool,
-) -> Result<full_sqlite::DatabaseOpen, full_sqlite::InternalError> {
+) -> Result<full_sqlite::DatabaseOpen, full_sqlite::AccessError> {
     let (tx, rx) = oneshot::channel();
     let mut rx = rx.fuse();
 
@@ -739,6 +832,7 @@ async fn background_open_database(
         move || {
             let result = full_sqlite::open(full_sqlite::Config {
                 block_number_bytes,
+                pruning_mode,
                 ty: if let Some(path) = &path {
                     full_sqlite::ConfigTy::Disk(path)
                 } else {
@@ -753,6 +847,7 @@ async fn background_open_database(
     if thread_spawn_result.is_err() {
         return full_sqlite::open(full_sqlite::Config {
             block_number_bytes,
+            pruning_mode,
             ty: if let Some(path) = &path {
                 full_sqlite::ConfigTy::Disk(path)
             } else {
@@ -0,0 +1,481 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Background service that periodically publishes the addresses of the local node in the
+//! Kademlia DHT under each of the authority discovery keys found in the keystore, and resolves
+//! the addresses of the current authorities of the chain.
+//!
+//! The list of current authorities is obtained by calling the runtime of the finalized block.
+//! The addresses that are found are reported through logs.
+
+use crate::run::{database_thread, network_service};
+
+use core::time::Duration;
+use futures::prelude::*;
+use smoldot::{
+    database::full_sqlite,
+    executor::{self, host, read_only_runtime_host},
+    header,
+    identity::keystore,
+    libp2p::{
+        multiaddr::{Multiaddr, ProtocolRef},
+        peer_id::PeerId,
+    },
+    network::protocol,
+};
+use std::{borrow::Cow, iter, sync::Arc};
+
+/// Configuration for a [`AuthorityDiscoveryService`].
+pub struct Config<'a> {
+    /// Closure that spawns background tasks.
+    pub tasks_executor: &'a mut dyn FnMut(future::BoxFuture<'static, ()>),
+
+    /// Database to read the storage of the finalized block from.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Access to the network, and index of the chain to use.
+    pub network_service: (Arc<network_service::NetworkService>, usize),
+
+    /// Number of bytes of the block number in the headers of the chain.
+    pub block_number_bytes: usize,
+
+    /// Keystore containing the authority discovery keys under which the addresses of the local
+    /// node are published.
+    pub keystore: Arc<keystore::Keystore>,
+
+    /// Identity of the local node on the peer-to-peer network.
+    pub local_peer_id: PeerId,
+
+    /// Addresses the peer-to-peer networking is listening on.
+    pub listen_addresses: Vec<Multiaddr>,
+}
+
+/// Running authority discovery service. The background task stops when this object is dropped.
+pub struct AuthorityDiscoveryService {
+    /// Aborts the background task when triggered.
+    background_task_abort: future::AbortHandle,
+}
+
+impl AuthorityDiscoveryService {
+    /// Initializes a new [`AuthorityDiscoveryService`].
+    pub fn new(config: Config<'_>) -> Self {
+        // The published addresses must contain the identity of the local node, as otherwise
+        // other nodes couldn't connect to them.
+        let published_addresses = config
+            .listen_addresses
+            .into_iter()
+            .map(|mut addr| {
+                addr.push(ProtocolRef::P2p(Cow::Borrowed(
+                    config.local_peer_id.as_bytes(),
+                )));
+                addr
+            })
+            .collect();
+
+        let mut background = Background {
+            database: config.database,
+            network_service: config.network_service.0,
+            network_chain_index: config.network_service.1,
+            block_number_bytes: config.block_number_bytes,
+            keystore: config.keystore,
+            published_addresses,
+            runtime_cache: None,
+        };
+
+        let (background_task_abort, abort_registration) = future::AbortHandle::new_pair();
+
+        (config.tasks_executor)(Box::pin(
+            future::Abortable::new(
+                async move {
+                    // Leave some time for the networking to connect to other nodes.
+                    futures_timer::Delay::new(Duration::from_secs(30)).await;
+
+                    loop {
+                        background.publish().await;
+                        background.resolve().await;
+
+                        // Records in the DHT expire after a while, so they must be published
+                        // again regularly. The authorities also change over time.
+                        futures_timer::Delay::new(Duration::from_secs(10 * 60)).await;
+                    }
+                },
+                abort_registration,
+            )
+            .map(|_| ()),
+        ));
+
+        AuthorityDiscoveryService {
+            background_task_abort,
+        }
+    }
+}
+
+impl Drop for AuthorityDiscoveryService {
+    fn drop(&mut self) {
+        self.background_task_abort.abort();
+    }
+}
+
+struct Background {
+    /// See [`Config::database`].
+    database: Arc<database_thread::DatabaseThread>,
+
+    /// See [`Config::network_service`].
+    network_service: Arc<network_service::NetworkService>,
+
+    /// See [`Config::network_service`].
+    network_chain_index: usize,
+
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+
+    /// See [`Config::keystore`].
+    keystore: Arc<keystore::Keystore>,
+
+    /// Addresses to publish, built from [`Config::listen_addresses`].
+    published_addresses: Vec<Multiaddr>,
+
+    /// Runtime that has been used in the most recent call, if any.
+    ///
+    /// Compiling a runtime is expensive, and the runtime rarely changes.
+    runtime_cache: Option<CachedRuntime>,
+}
+
+/// See [`Background::runtime_cache`].
+struct CachedRuntime {
+    /// Value of the `:code` storage item the runtime has been compiled from.
+    code: Vec<u8>,
+    /// Value of the `:heappages` storage item the runtime has been compiled with.
+    heap_pages: Option<Vec<u8>>,
+    /// Compiled runtime.
+    runtime: host::HostVmPrototype,
+}
+
+impl Background {
+    /// Publishes the addresses of the local node under each authority discovery key of the
+    /// keystore. Errors are logged.
+    async fn publish(&mut self) {
+        let record = protocol::build_authority_record(self.published_addresses.iter());
+
+        let public_keys = self
+            .keystore
+            .keys()
+            .await
+            .filter(|(namespace, _)| *namespace == keystore::KeyNamespace::AuthorityDiscovery)
+            .map(|(_, public_key)| public_key)
+            .collect::<Vec<_>>();
+
+        for public_key in public_keys {
+            let signature = match self
+                .keystore
+                .sign(
+                    keystore::KeyNamespace::AuthorityDiscovery,
+                    &public_key,
+                    &record,
+                )
+                .await
+            {
+                Ok(signature) => signature,
+                Err(error) => {
+                    tracing::warn!(
+                        authority = %hex::encode(public_key), %error,
+                        "authority-discovery-sign-error"
+                    );
+                    continue;
+                }
+            };
+
+            let result = self
+                .network_service
+                .clone()
+                .kademlia_put_value(
+                    self.network_chain_index,
+                    protocol::authority_discovery_dht_key(&public_key).to_vec(),
+                    protocol::build_signed_authority_record(&record, &signature),
+                )
+                .await;
+
+            match result {
+                Ok(num_peers) => {
+                    tracing::debug!(
+                        authority = %hex::encode(public_key), %num_peers,
+                        "authority-discovery-published"
+                    );
+                }
+                Err(error) => {
+                    tracing::warn!(
+                        authority = %hex::encode(public_key), %error,
+                        "authority-discovery-publish-error"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Looks up the addresses of each of the current authorities and logs them. Errors are
+    /// logged.
+    async fn resolve(&mut self) {
+        let authorities = match self.authorities().await {
+            Ok(authorities) => authorities,
+            Err(error) => {
+                tracing::warn!(%error, "authority-discovery-authorities-error");
+                return;
+            }
+        };
+
+        for authority in authorities {
+            let result = self
+                .network_service
+                .clone()
+                .kademlia_get_value(
+                    self.network_chain_index,
+                    protocol::authority_discovery_dht_key(&authority).to_vec(),
+                )
+                .await;
+
+            let values = match result {
+                Ok(values) => values,
+                Err(error) => {
+                    tracing::debug!(
+                        authority = %hex::encode(authority), %error,
+                        "authority-discovery-lookup-error"
+                    );
+                    continue;
+                }
+            };
+
+            // Records that haven't been signed by the authority are ignored. Anyone can store
+            // anything in the DHT.
+            let mut addresses = Vec::new();
+            for value in values {
+                match protocol::decode_signed_authority_record(&value, &authority) {
+                    Ok(record) => {
+                        for address in record.addresses {
+                            if !addresses.contains(&address) {
+                                addresses.push(address);
+                            }
+                        }
+                    }
+                    Err(error) => {
+                        tracing::debug!(
+                            authority = %hex::encode(authority), %error,
+                            "authority-discovery-bad-record"
+                        );
+                    }
+                }
+            }
+
+            if addresses.is_empty() {
+                tracing::debug!(
+                    authority = %hex::encode(authority),
+                    "authority-discovery-not-found"
+                );
+            } else {
+                tracing::info!(
+                    authority = %hex::encode(authority),
+                    addresses = %addresses.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "),
+                    "authority-discovery-resolved"
+                );
+            }
+        }
+    }
+
+    /// Calls the runtime of the finalized block in order to obtain the authority discovery
+    /// public keys of the current authorities.
+    async fn authorities(&mut self) -> Result<Vec<[u8; 32]>, AuthoritiesError> {
+        let (block_hash, state_root) = {
+            let block_number_bytes = self.block_number_bytes;
+            self.database
+                .with_database(move |database| {
+                    let block_hash = database
+                        .finalized_block_hash()
+                        .map_err(|err| AuthoritiesError::Storage(err.into()))?;
+                    let header = database
+                        .block_scale_encoded_header(&block_hash)
+                        .map_err(|err| AuthoritiesError::Storage(err.into()))?
+                        .ok_or(AuthoritiesError::Storage(
+                            full_sqlite::StorageAccessError::UnknownBlock,
+                        ))?;
+                    let state_root = *header::decode(&header, block_number_bytes)
+                        .map_err(AuthoritiesError::InvalidHeader)?
+                        .state_root;
+                    Ok::<_, AuthoritiesError>((block_hash, state_root))
+                })
+                .await?
+        };
+
+        let CachedRuntime {
+            code,
+            heap_pages,
+            runtime,
+        } = self.runtime(&block_hash).await?;
+
+        let mut call = match read_only_runtime_host::run(read_only_runtime_host::Config {
+            virtual_machine: runtime,
+            function_to_call: protocol::AUTHORITY_DISCOVERY_AUTHORITIES_FUNCTION,
+            parameter: iter::empty::<&[u8]>(),
+        }) {
+            Ok(call) => call,
+            Err((error, runtime)) => {
+                self.runtime_cache = Some(CachedRuntime {
+                    code,
+                    heap_pages,
+                    runtime,
+                });
+                return Err(AuthoritiesError::StartError(error));
+            }
+        };
+
+        let (result, runtime) = loop {
+            match call {
+                read_only_runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                    let result = protocol::decode_authority_discovery_authorities(
+                        success.virtual_machine.value().as_ref(),
+                    )
+                    .map_err(AuthoritiesError::Decode);
+                    break (result, success.virtual_machine.into_prototype());
+                }
+                read_only_runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                    break (
+                        Err(AuthoritiesError::Execution(error.detail)),
+                        error.prototype,
+                    );
+                }
+                read_only_runtime_host::RuntimeHostVm::StorageGet(get) => {
+                    let key = get.key().as_ref().to_vec();
+                    let value = self
+                        .database
+                        .with_database(move |database| {
+                            database.block_storage_top_trie_get(&block_hash, &key)
+                        })
+                        .await;
+                    match value {
+                        Ok(value) => call = get.inject_value(value.as_ref().map(iter::once)),
+                        Err(error) => {
+                            break (
+                                Err(AuthoritiesError::Storage(error)),
+                                read_only_runtime_host::RuntimeHostVm::StorageGet(get)
+                                    .into_prototype(),
+                            )
+                        }
+                    }
+                }
+                read_only_runtime_host::RuntimeHostVm::NextKey(next_key) => {
+                    let key = next_key.key().as_ref().to_vec();
+                    let next = self
+                        .database
+                        .with_database(move |database| {
+                            database.block_storage_top_trie_next_key(&block_hash, &key)
+                        })
+                        .await;
+                    match next {
+                        Ok(next) => call = next_key.inject_key(next),
+                        Err(error) => {
+                            break (
+                                Err(AuthoritiesError::Storage(error)),
+                                read_only_runtime_host::RuntimeHostVm::NextKey(next_key)
+                                    .into_prototype(),
+                            )
+                        }
+                    }
+                }
+                read_only_runtime_host::RuntimeHostVm::StorageRoot(storage_root) => {
+                    call = storage_root.resume(&state_root);
+                }
+                read_only_runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                    call = sig.verify_and_resume();
+                }
+            }
+        };
+
+        self.runtime_cache = Some(CachedRuntime {
+            code,
+            heap_pages,
+            runtime,
+        });
+
+        result
+    }
+
+    /// Returns the runtime of the given block, either from the cache or by compiling it.
+    async fn runtime(&mut self, block_hash: &[u8; 32]) -> Result<CachedRuntime, AuthoritiesError> {
+        let (code, heap_pages) = {
+            let block_hash = *block_hash;
+            self.database
+                .with_database(move |database| {
+                    let code = database.block_storage_top_trie_get(&block_hash, b":code")?;
+                    let heap_pages =
+                        database.block_storage_top_trie_get(&block_hash, b":heappages")?;
+                    Ok::<_, full_sqlite::StorageAccessError>((code, heap_pages))
+                })
+                .await
+                .map_err(AuthoritiesError::Storage)?
+        };
+
+        let code = code.ok_or(AuthoritiesError::NoCode)?;
+
+        if let Some(cached) = self.runtime_cache.take() {
+            if cached.code == code && cached.heap_pages == heap_pages {
+                return Ok(cached);
+            }
+        }
+
+        let runtime = host::HostVmPrototype::new(host::Config {
+            module: &code,
+            heap_pages: executor::storage_heap_pages_to_value(heap_pages.as_deref())
+                .map_err(AuthoritiesError::InvalidHeapPages)?,
+            exec_hint: executor::vm::ExecHint::CompileAheadOfTime,
+            allow_unresolved_imports: true,
+        })
+        .map_err(AuthoritiesError::InvalidRuntime)?;
+
+        Ok(CachedRuntime {
+            code,
+            heap_pages,
+            runtime,
+        })
+    }
+}
+
+/// Error potentially returned by [`Background::authorities`].
+#[derive(Debug, derive_more::Display)]
+enum AuthoritiesError {
+    /// Error while accessing the storage of the finalized block.
+    #[display(fmt = "{}", _0)]
+    Storage(full_sqlite::StorageAccessError),
+    /// Error while decoding the header of the finalized block.
+    #[display(fmt = "Invalid block header: {}", _0)]
+    InvalidHeader(header::Error),
+    /// The storage of the block doesn't contain any runtime.
+    #[display(fmt = "No runtime found in the storage of the block")]
+    NoCode,
+    /// Invalid value for the `:heappages` storage item.
+    #[display(fmt = "Invalid heap pages: {}", _0)]
+    InvalidHeapPages(executor::InvalidHeapPagesError),
+    /// Failed to compile the runtime.
+    #[display(fmt = "Invalid runtime: {}", _0)]
+    InvalidRuntime(host::NewErr),
+    /// Failed to start the runtime call.
+    #[display(fmt = "Failed to start runtime call: {}", _0)]
+    StartError(host::StartErr),
+    /// Error during the runtime call.
+    #[display(fmt = "Error during runtime call: {}", _0)]
+    Execution(read_only_runtime_host::ErrorDetail),
+    /// Failed to decode the output of the runtime call.
+    #[display(fmt = "{}", _0)]
+    Decode(protocol::DecodeAuthorityDiscoveryAuthoritiesError),
+}
@@ -27,25 +27,35 @@
 use crate::run::{database_thread, jaeger_service, network_service};
 
 use core::{num::NonZeroU32, ops};
-use futures::{lock::Mutex, prelude::*};
+use futures::{
+    channel::{mpsc, oneshot},
+    lock::Mutex,
+    prelude::*,
+};
 use hashbrown::HashSet;
 use smoldot::{
     author,
     chain::chain_information,
     database::full_sqlite,
-    executor, header,
+    executor,
+    finality::grandpa,
+    header,
     identity::keystore,
     informant::HashDisplay,
     libp2p,
-    network::{self, protocol::BlockData},
+    network::{
+        self,
+        protocol::{self, BlockData},
+    },
     sync::all,
+    transactions::{pool, validate},
 };
 use std::{
     collections::BTreeMap,
-    iter,
+    iter, mem,
     num::NonZeroU64,
     sync::Arc,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tracing::Instrument as _;
 
@@ -66,6 +76,15 @@ pub struct Config<'a> {
     /// >           to compare against a known genesis hash and print a warning.
     pub genesis_block_hash: [u8; 32],
 
+    /// List of hashes of blocks that are known to be bad and must never be synchronized.
+    pub banned_blocks: Vec<[u8; 32]>,
+
+    /// If `true`, the chain is first warp synced to a recent finalized block whose storage is
+    /// then downloaded from the network, instead of executing all the blocks since the genesis.
+    ///
+    /// Ignored if the finalized block in the database isn't the genesis block.
+    pub state_sync: bool,
+
     /// Stores of key to use for all block-production-related purposes.
     pub keystore: Arc<keystore::Keystore>,
 
@@ -110,12 +129,96 @@ pub struct SyncState {
     pub best_block_hash: [u8; 32],
     pub finalized_block_number: u64,
     pub finalized_block_hash: [u8; 32],
+    /// `true` if the best block is believed to be near the head of the chain.
+    pub is_near_head_of_chain: bool,
 }
 
 /// Background task that verifies blocks and emits requests.
 pub struct ConsensusService {
     /// State kept up-to-date with the background task.
     sync_state: Arc<Mutex<SyncState>>,
+
+    /// Channel to send messages to the background task.
+    to_background_tx: Mutex<mpsc::Sender<ToBackground>>,
+}
+
+/// Return value of [`ConsensusService::subscribe_all`].
+pub struct SubscribeAll {
+    /// SCALE-encoded header of the finalized block at the time of the subscription.
+    pub finalized_block_scale_encoded_header: Vec<u8>,
+
+    /// List of all known non-finalized blocks at the time of subscription.
+    ///
+    /// Only one element in this list has [`BlockNotification::is_new_best`] equal to true.
+    ///
+    /// The blocks are guaranteed to be ordered so that parents are always found before their
+    /// children.
+    pub non_finalized_blocks_ancestry_order: Vec<BlockNotification>,
+
+    /// Channel onto which new blocks and finality updates are sent.
+    ///
+    /// The channel is closed if the subscriber doesn't process the notifications quickly enough.
+    pub new_blocks: mpsc::Receiver<Notification>,
+}
+
+/// Notification about a new block or a new finalized block.
+///
+/// See [`ConsensusService::subscribe_all`].
+#[derive(Debug, Clone)]
+pub enum Notification {
+    /// A non-finalized block has been finalized. Its content has been written to the database
+    /// before this notification is sent.
+    Finalized {
+        /// Hash of the block that has been finalized.
+        ///
+        /// A block with this hash is guaranteed to have earlier been reported in a
+        /// [`BlockNotification`], either in [`SubscribeAll::non_finalized_blocks_ancestry_order`]
+        /// or in a [`Notification::Block`].
+        hash: [u8; 32],
+
+        /// SCALE-encoded header of the block that has been finalized.
+        scale_encoded_header: Vec<u8>,
+
+        /// Hash of the best block after the finalization.
+        best_block_hash: [u8; 32],
+    },
+
+    /// A new block has been added to the list of unfinalized blocks.
+    Block(BlockNotification),
+}
+
+/// Notification about a new block.
+///
+/// See [`ConsensusService::subscribe_all`].
+#[derive(Debug, Clone)]
+pub struct BlockNotification {
+    /// True if this block is considered as the best block of the chain.
+    pub is_new_best: bool,
+
+    /// SCALE-encoded header of the block.
+    pub scale_encoded_header: Vec<u8>,
+
+    /// Hash of the header of the block.
+    pub block_hash: [u8; 32],
+}
+
+/// Message sent from the [`ConsensusService`] to the background task.
+enum ToBackground {
+    /// See [`ConsensusService::subscribe_all`].
+    SubscribeAll {
+        buffer_size: usize,
+        result_tx: oneshot::Sender<SubscribeAll>,
+    },
+    /// See [`ConsensusService::submit_transaction`].
+    SubmitTransaction {
+        scale_encoded_transaction: Vec<u8>,
+        source: validate::TransactionSource,
+    },
+    /// See [`ConsensusService::best_block_storage_get`].
+    BestBlockStorageGet {
+        keys: Vec<Vec<u8>>,
+        result_tx: oneshot::Sender<Option<([u8; 32], Vec<Option<Vec<u8>>>)>>,
+    },
 }
 
 impl ConsensusService {
@@ -129,8 +232,9 @@ impl ConsensusService {
             best_block_hash,
             best_block_number,
             finalized_block_storage,
+            finalized_block_child_tries,
             finalized_chain_information,
-        ): (_, _, _, _, BTreeMap<Vec<u8>, Vec<u8>>, _) = config
+        ): (_, _, _, _, BTreeMap<Vec<u8>, Vec<u8>>, _, _) = config
             .database
             .with_database({
                 let block_number_bytes = config.block_number_bytes;
@@ -158,6 +262,17 @@ impl ConsensusService {
                     let finalized_block_storage = database
                         .finalized_block_storage_top_trie(&finalized_block_hash)
                         .unwrap();
+                    let finalized_block_child_tries = database
+                        .finalized_block_storage_child_tries::<Vec<_>>(&finalized_block_hash)
+                        .unwrap()
+                        .into_iter()
+                        .fold(
+                            BTreeMap::<_, BTreeMap<_, _>>::new(),
+                            |mut tries, (child_trie, key, value)| {
+                                tries.entry(child_trie).or_default().insert(key, value);
+                                tries
+                            },
+                        );
                     let finalized_chain_information = database
                         .to_chain_information(&finalized_block_hash)
                         .unwrap();
@@ -167,6 +282,7 @@ impl ConsensusService {
                         best_block_hash,
                         best_block_number,
                         finalized_block_storage,
+                        finalized_block_child_tries,
                         finalized_chain_information,
                     )
                 }
@@ -197,8 +313,11 @@ impl ConsensusService {
             best_block_hash,
             finalized_block_number,
             finalized_block_hash,
+            is_near_head_of_chain: false,
         }));
 
+        let (to_background_tx, from_foreground) = mpsc::channel(4);
+
         // Spawn the background task that synchronizes blocks and updates the database.
         (config.tasks_executor)({
             let mut sync = all::AllSync::new(all::Config {
@@ -220,7 +339,9 @@ impl ConsensusService {
                     // the chain and the machine of the user.
                     NonZeroU32::new(2000).unwrap()
                 },
+                banned_blocks: config.banned_blocks,
                 full: Some(all::ConfigFull {
+                    state_sync: config.state_sync && finalized_block_number == 0,
                     finalized_runtime: {
                         // Builds the runtime of the finalized block.
                         // Assumed to always be valid, otherwise the block wouldn't have been saved in the
@@ -252,15 +373,23 @@ impl ConsensusService {
                 block_authoring: None,
                 authored_block: None,
                 slot_duration_author_ratio: config.slot_duration_author_ratio,
+                babe_slot_duration: None,
                 keystore: config.keystore,
+                transactions_pool: new_transactions_pool(finalized_block_number),
+                transactions_pool_best_chain: Vec::new(),
+                grandpa_voter: None,
                 finalized_block_storage,
+                finalized_block_child_tries,
                 sync_state: sync_state.clone(),
+                from_foreground,
+                blocks_notifications: Vec::new(),
                 network_service: config.network_service.0,
                 network_chain_index: config.network_service.1,
                 from_network_service: config.network_events_receiver,
                 database: config.database,
                 peers_source_id_map: Default::default(),
                 block_requests_finished: stream::FuturesUnordered::new(),
+                state_sync_requests_finished: stream::FuturesUnordered::new(),
                 jaeger_service: config.jaeger_service,
             };
 
@@ -269,7 +398,10 @@ impl ConsensusService {
             ))
         });
 
-        Arc::new(ConsensusService { sync_state })
+        Arc::new(ConsensusService {
+            sync_state,
+            to_background_tx: Mutex::new(to_background_tx),
+        })
     }
 
     /// Returns a summary of the state of the service.
@@ -280,6 +412,67 @@ impl ConsensusService {
     pub async fn sync_state(&self) -> SyncState {
         self.sync_state.lock().await.clone()
     }
+
+    /// Subscribes to the state of the chain: the current state and the new blocks.
+    ///
+    /// Only up to `buffer_size` block notifications are buffered in the channel. If the channel
+    /// is full when a new notification is attempted to be pushed, the channel gets closed.
+    pub async fn subscribe_all(&self, buffer_size: usize) -> SubscribeAll {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::SubscribeAll {
+                buffer_size,
+                result_tx,
+            })
+            .await;
+        result_rx.await.unwrap()
+    }
+
+    /// Adds a SCALE-encoded transaction to the transactions pool of the service.
+    ///
+    /// The transaction is later validated against the best block. If it is valid, it is
+    /// announced to the networking peers and included in the blocks that are authored locally.
+    /// If it is invalid, it is silently discarded.
+    pub async fn submit_transaction(
+        &self,
+        scale_encoded_transaction: Vec<u8>,
+        source: validate::TransactionSource,
+    ) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::SubmitTransaction {
+                scale_encoded_transaction,
+                source,
+            })
+            .await;
+    }
+
+    /// Returns the hash of the current best block and the values of the given keys in its
+    /// storage.
+    ///
+    /// Contrary to the database, which only contains the storage of the finalized blocks, this
+    /// function has access to the storage of the non-finalized blocks.
+    ///
+    /// Returns `None` if the storage of the best block isn't available, for example while the
+    /// service is warp syncing.
+    pub async fn best_block_storage_get(
+        &self,
+        keys: Vec<Vec<u8>>,
+    ) -> Option<([u8; 32], Vec<Option<Vec<u8>>>)> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::BestBlockStorageGet { keys, result_tx })
+            .await;
+        result_rx.await.unwrap()
+    }
 }
 
 struct SyncBackground {
@@ -320,6 +513,10 @@ struct SyncBackground {
     /// See [`Config::slot_duration_author_ratio`].
     slot_duration_author_ratio: u16,
 
+    /// Duration, in milliseconds, of a Babe slot. Obtained by calling the `BabeApi_configuration`
+    /// runtime function the first time a Babe block is authored, as it never changes afterwards.
+    babe_slot_duration: Option<NonZeroU64>,
+
     /// After a block has been authored, it is inserted here while waiting for the `sync` to
     /// import it. Contains the block height, the block hash, the SCALE-encoded block header, and
     /// the list of SCALE-encoded extrinsics of the block.
@@ -328,6 +525,18 @@ struct SyncBackground {
     /// See [`Config::keystore`].
     keystore: Arc<keystore::Keystore>,
 
+    /// Transactions waiting to be included in a block, and transactions included in the
+    /// non-finalized blocks of [`SyncBackground::transactions_pool_best_chain`].
+    transactions_pool: pool::Pool<PoolTransaction>,
+
+    /// Hashes of the non-finalized blocks of the best chain, as tracked by
+    /// [`SyncBackground::transactions_pool`], ordered by increasing height.
+    transactions_pool_best_chain: Vec<[u8; 32]>,
+
+    /// GrandPa voter, if the keystore contains the key of one of the authorities of the current
+    /// GrandPa authorities set. Updated by [`SyncBackground::update_grandpa_voter`].
+    grandpa_voter: Option<grandpa::voter::Voter<Instant>>,
+
     /// Holds, in parallel of the database, the storage of the latest finalized block.
     /// At the time of writing, this state is stable around `~3MiB` for Polkadot, meaning that it is
     /// completely acceptable to hold it entirely in memory.
@@ -336,8 +545,19 @@ struct SyncBackground {
     /// parallel of this verification.
     finalized_block_storage: BTreeMap<Vec<u8>, Vec<u8>>,
 
+    /// Same as [`SyncBackground::finalized_block_storage`], but for the default child tries of
+    /// the latest finalized block. Keys are the identifiers of the child tries, without the
+    /// `:child_storage:default:` prefix. Child tries are never empty.
+    finalized_block_child_tries: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Vec<u8>>>,
+
     sync_state: Arc<Mutex<SyncState>>,
 
+    /// Receiver for messages sent by the [`ConsensusService`].
+    from_foreground: mpsc::Receiver<ToBackground>,
+
+    /// List of senders to report events to when they happen.
+    blocks_notifications: Vec<mpsc::Sender<Notification>>,
+
     /// Service managing the connections to the networking peers.
     network_service: Arc<network_service::NetworkService>,
 
@@ -373,6 +593,21 @@ struct SyncBackground {
         >,
     >,
 
+    /// Requests other than block requests, used during the state sync, that have been emitted
+    /// on the networking service and that are still in progress. Each entry in this field also
+    /// has an entry in [`SyncBackground::sync`]. The outcome is `None` if the request has been
+    /// aborted.
+    state_sync_requests_finished: stream::FuturesUnordered<
+        future::BoxFuture<
+            'static,
+            (
+                all::RequestId,
+                all::SourceId,
+                Option<StateSyncRequestResult>,
+            ),
+        >,
+    >,
+
     /// See [`Config::database`].
     database: Arc<database_thread::DatabaseThread>,
 
@@ -380,6 +615,21 @@ struct SyncBackground {
     jaeger_service: Arc<jaeger_service::JaegerService>,
 }
 
+/// Outcome of a request found in [`SyncBackground::state_sync_requests_finished`].
+enum StateSyncRequestResult {
+    GrandpaWarpSync(
+        Result<
+            network::service::EncodedGrandpaWarpSyncResponse,
+            network_service::GrandpaWarpSyncRequestError,
+        >,
+    ),
+    StorageProof(
+        Result<network::service::EncodedMerkleProof, network_service::StorageProofRequestError>,
+    ),
+    CallProof(Result<network::service::EncodedMerkleProof, network_service::CallProofRequestError>),
+    State(Result<network::service::EncodedStateResponse, network_service::StateRequestError>),
+}
+
 /// Information about a source in the sync state machine.
 #[derive(Debug, Clone)]
 struct NetworkSourceInfo {
@@ -390,23 +640,80 @@ struct NetworkSourceInfo {
     is_disconnected: bool,
 }
 
+/// User data of the transactions of [`SyncBackground::transactions_pool`].
+#[derive(Debug)]
+struct PoolTransaction {
+    /// Where the transaction comes from. Passed to the runtime when validating the transaction.
+    source: validate::TransactionSource,
+}
+
+/// Builds an empty [`SyncBackground::transactions_pool`].
+fn new_transactions_pool(finalized_block_height: u64) -> pool::Pool<PoolTransaction> {
+    pool::Pool::new(pool::Config {
+        capacity: 1024,
+        finalized_block_height,
+        // These limits are the same as the default ones of Substrate.
+        max_pending_transactions: 8192,
+        max_pending_bytes: 20 * 1024 * 1024,
+        // Transactions received from the network are counted per peer, which prevents a single
+        // peer from filling the pool.
+        max_pending_transactions_per_sender: 1024,
+    })
+}
+
+/// Error while obtaining the Babe slot duration. See
+/// [`SyncBackground::babe_configuration_slot_duration`].
+#[derive(Debug, derive_more::Display)]
+enum BabeConfigurationError {
+    /// Error while starting the runtime call.
+    #[display(fmt = "{}", _0)]
+    StartError(executor::host::StartErr),
+    /// Error while executing the runtime call.
+    #[display(fmt = "{}", _0)]
+    Execution(executor::read_only_runtime_host::ErrorDetail),
+    /// The runtime call has requested the key following a given one in the storage, which isn't
+    /// supported.
+    NextKeyUnsupported,
+    /// Failed to decode the output of the runtime call.
+    OutputDecode,
+}
+
 impl SyncBackground {
     #[tracing::instrument(level = "trace", skip(self))]
     async fn run(mut self) {
         loop {
             self.start_network_requests().await;
             self = self.process_blocks().await;
+            self.validate_transactions().await;
 
             // Update the current best block, used for CLI-related purposes.
             {
                 let mut lock = self.sync_state.lock().await;
                 lock.best_block_hash = self.sync.best_block_hash();
                 lock.best_block_number = self.sync.best_block_number();
+                lock.is_near_head_of_chain = self.sync.is_near_head_of_chain_heuristic();
             }
 
+            // Update the GrandPa voter and perform everything it has to do. This produces a
+            // future that is ready when the voter must be driven again.
+            let mut grandpa_ready_future = {
+                self.update_grandpa_voter().await;
+                match self.process_grandpa_voter().await {
+                    Some(wake_up) => {
+                        let delay = wake_up.saturating_duration_since(Instant::now());
+                        future::Either::Left(futures_timer::Delay::new(delay).fuse())
+                    }
+                    None => future::Either::Right(future::pending::<()>()),
+                }
+            };
+
             // Creating the block authoring state and prepare a future that is ready when something
             // related to the block authoring is ready.
-            let mut authoring_ready_future = {
+            let mut authoring_ready_future = if !matches!(self.sync.status(), all::Status::Sync) {
+                // Blocks can't be authored while the state sync is in progress, as the chain
+                // isn't known yet.
+                future::Either::Left(future::Either::Right(future::pending::<()>()))
+            } else {
                 // TODO: overhead to call best_block_consensus() multiple times
                 let local_authorities = {
                     let namespace_filter = match self.sync.best_block_consensus() {
@@ -434,6 +741,18 @@ impl SyncBackground {
                         .collect::<Vec<_>>() // TODO: collect overhead :-/
                 };
 
+                // Claiming a Babe slot requires generating VRF outputs using the keystore, which
+                // is done before creating the builder.
+                let babe_slot_claim = if self.block_authoring.is_none()
+                    && matches!(
+                        self.sync.best_block_consensus(),
+                        chain_information::ChainInformationConsensusRef::Babe { .. }
+                    ) {
+                    self.babe_slot_claim(&local_authorities).await
+                } else {
+                    None
+                };
+
                 let block_authoring =
                     match (&mut self.block_authoring, self.sync.best_block_consensus()) {
                         (Some(ba), _) => Some(ba),
@@ -458,9 +777,28 @@ impl SyncBackground {
                                 local_authorities,
                             )),
                         ),
-                        (None, chain_information::ChainInformationConsensusRef::Babe { .. }) => {
-                            None // TODO: the block authoring doesn't support Babe at the moment
-                        }
+                        (
+                            block_authoring @ None,
+                            chain_information::ChainInformationConsensusRef::Babe { .. },
+                        ) => Some(
+                            block_authoring.insert(match babe_slot_claim {
+                                Some(slot_claim) => (
+                                    author::build::Builder::new(author::build::Config::<
+                                        '_,
+                                        iter::Empty<_>,
+                                    > {
+                                        consensus: author::build::ConfigConsensus::Babe {
+                                            now_from_unix_epoch: SystemTime::now()
+                                                .duration_since(SystemTime::UNIX_EPOCH)
+                                                .unwrap(),
+                                            slot_claim,
+                                        },
+                                    }),
+                                    local_authorities,
+                                ),
+                                None => (author::build::Builder::Idle, Vec::new()),
+                            }),
+                        ),
                         (None, _) => todo!(),
                     };
 
@@ -511,6 +849,10 @@ impl SyncBackground {
                     }
                 },
 
+                () = grandpa_ready_future => {
+                    // The GrandPa voter is driven at the beginning of the loop.
+                },
+
                 network_event = self.from_network_service.next().fuse() => {
                     // We expect the network events channel to never shut down.
                     let network_event = network_event.unwrap();
@@ -573,6 +915,28 @@ impl SyncBackground {
                                 all::BlockAnnounceOutcome::Discarded => {},
                                 all::BlockAnnounceOutcome::StoredForLater {} => {},
                                 all::BlockAnnounceOutcome::InvalidHeader(_) => unreachable!(),
+                                all::BlockAnnounceOutcome::BannedBlock => {
+                                    tracing::warn!(%peer_id, "banned-block-announce");
+                                    self.network_service
+                                        .report_misbehaviour(&peer_id, network::service::Misbehaviour::BadBlock)
+                                        .await;
+                                },
+                            }
+                        },
+                        network_service::Event::GrandpaNotification { chain_index, peer_id, notification }
+                            if chain_index == self.network_chain_index =>
+                        {
+                            self.inject_grandpa_notification(peer_id, notification).await;
+                        },
+                        network_service::Event::Transactions { chain_index, peer_id, transactions }
+                            if chain_index == self.network_chain_index =>
+                        {
+                            for transaction in transactions.decode() {
+                                self.add_transaction_to_pool(
+                                    transaction.to_vec(),
+                                    Some(peer_id.as_bytes()),
+                                    validate::TransactionSource::External
+                                );
                             }
                         },
                         _ => {
@@ -581,6 +945,55 @@ impl SyncBackground {
                     }
                 },
 
+                message = self.from_foreground.select_next_some() => {
+                    match message {
+                        ToBackground::SubscribeAll { buffer_size, result_tx } => {
+                            let (tx, new_blocks) = mpsc::channel(buffer_size.saturating_sub(1));
+
+                            let block_number_bytes = self.sync.block_number_bytes();
+                            let best_block_hash = self.sync.best_block_hash();
+                            let non_finalized_blocks_ancestry_order = self
+                                .sync
+                                .non_finalized_blocks_ancestry_order()
+                                .map(|h| {
+                                    let block_hash = h.hash(block_number_bytes);
+                                    BlockNotification {
+                                        is_new_best: block_hash == best_block_hash,
+                                        scale_encoded_header: h.scale_encoding_vec(block_number_bytes),
+                                        block_hash,
+                                    }
+                                })
+                                .collect();
+
+                            let _ = result_tx.send(SubscribeAll {
+                                finalized_block_scale_encoded_header: self
+                                    .sync
+                                    .finalized_block_header()
+                                    .scale_encoding_vec(block_number_bytes),
+                                non_finalized_blocks_ancestry_order,
+                                new_blocks,
+                            });
+
+                            self.blocks_notifications.push(tx);
+                        }
+                        ToBackground::SubmitTransaction { scale_encoded_transaction, source } => {
+                            self.add_transaction_to_pool(scale_encoded_transaction, None, source);
+                        }
+                        ToBackground::BestBlockStorageGet { keys, result_tx } => {
+                            let result = if self.sync.best_block_storage().is_some() {
+                                let values = keys
+                                    .iter()
+                                    .map(|key| self.best_block_storage_get(None, key))
+                                    .collect();
+                                Some((self.sync.best_block_hash(), values))
+                            } else {
+                                None
+                            };
+                            let _ = result_tx.send(result);
+                        }
+                    }
+                },
+
                 (request_id, source_id, result) = self.block_requests_finished.select_next_some() => {
                     // `result` is an error if the block request got cancelled by the sync state
                     // machine.
@@ -602,21 +1015,336 @@ impl SyncBackground {
                             }
                         }
 
-                        // If the source was actually disconnected and has no other request in
-                        // progress, we clean it up.
-                        if self.sync[source_id].as_ref().map_or(false, |info| info.is_disconnected)
-                            && self.sync.source_num_ongoing_requests(source_id) == 0
-                        {
-                            let (info, mut _requests) = self.sync.remove_source(source_id);
-                            debug_assert!(_requests.next().is_none());
-                            self.peers_source_id_map.remove(&info.unwrap().peer_id).unwrap();
+                        self.remove_source_if_disconnected(source_id);
+                    }
+                },
+
+                (request_id, source_id, result) = self.state_sync_requests_finished.select_next_some() => {
+                    // `result` is `None` if the request got cancelled by the sync state machine.
+                    let result = match result {
+                        Some(r) => r,
+                        None => continue,
+                    };
+
+                    match result {
+                        StateSyncRequestResult::GrandpaWarpSync(Ok(response)) => {
+                            let decoded = response.decode();
+                            let fragments = decoded.fragments
+                                .into_iter()
+                                .map(|f| all::WarpSyncFragment {
+                                    scale_encoded_header: f.scale_encoded_header.to_vec(),
+                                    scale_encoded_justification: f.scale_encoded_justification.to_vec(),
+                                })
+                                .collect();
+                            self.sync.grandpa_warp_sync_response_ok(request_id, fragments, decoded.is_finished);
+                        }
+                        StateSyncRequestResult::GrandpaWarpSync(Err(_)) => {
+                            self.sync.grandpa_warp_sync_response_err(request_id);
+                        }
+                        StateSyncRequestResult::StorageProof(result) => {
+                            let result = result.map(|proof| proof.decode().to_vec()).map_err(|_| ());
+                            self.sync.storage_get_response(request_id, result);
+                        }
+                        StateSyncRequestResult::CallProof(result) => {
+                            let result = result.map(|proof| proof.decode().to_vec()).map_err(|_| ());
+                            self.sync.call_proof_response(request_id, result);
+                        }
+                        StateSyncRequestResult::State(result) => {
+                            let result = result.map(|response| response.merkle_proof()).map_err(|_| ());
+                            self.sync.state_response(request_id, result);
                         }
                     }
+
+                    self.remove_source_if_disconnected(source_id);
                 },
             }
         }
     }
 
+    /// If the given source was actually disconnected and has no other request in progress,
+    /// cleans it up.
+    fn remove_source_if_disconnected(&mut self, source_id: all::SourceId) {
+        if matches!(&self.sync[source_id], Some(info) if info.is_disconnected)
+            && self.sync.source_num_ongoing_requests(source_id) == 0
+        {
+            let (info, mut _requests) = self.sync.remove_source(source_id);
+            debug_assert!(_requests.next().is_none());
+            self.peers_source_id_map
+                .remove(&info.unwrap().peer_id)
+                .unwrap();
+        }
+    }
+
+    /// Creates or destroys the GrandPa voter depending on the authorities set of the finalized
+    /// block and on the content of the keystore, then informs the voter of the state of the
+    /// chain.
+    async fn update_grandpa_voter(&mut self) {
+        let block_number_bytes = self.sync.block_number_bytes();
+
+        let chain_information = self.sync.as_chain_information();
+        let chain_information = chain_information.as_ref();
+        let (set_id, authorities, voting_limit) = match chain_information.finality {
+            chain_information::ChainInformationFinalityRef::Grandpa {
+                after_finalized_block_authorities_set_id,
+                finalized_triggered_authorities,
+                finalized_scheduled_change,
+            } => (
+                after_finalized_block_authorities_set_id,
+                finalized_triggered_authorities,
+                finalized_scheduled_change.map(|(n, _)| n),
+            ),
+            chain_information::ChainInformationFinalityRef::Outsourced => {
+                self.grandpa_voter = None;
+                return;
+            }
+        };
+
+        // A new voter must be created every time the authorities set changes. The voter is
+        // only created once the node is near the head of the chain, as it would otherwise vote
+        // on blocks that are very old.
+        if !matches!(&self.grandpa_voter, Some(voter) if voter.set_id() == set_id) {
+            self.grandpa_voter = None;
+
+            if !self.sync.is_near_head_of_chain_heuristic() {
+                return;
+            }
+
+            // Calling `keys()` on the keystore is racy, but that's considered acceptable and
+            // part of the design of the node.
+            let local_authority_public_key = self
+                .keystore
+                .keys()
+                .await
+                .filter(|(namespace, _)| *namespace == keystore::KeyNamespace::Grandpa)
+                .map(|(_, key)| key)
+                .find(|key| authorities.iter().any(|a| a.public_key == *key));
+
+            if let Some(local_authority_public_key) = local_authority_public_key {
+                // The state of the previous voter, if any, is restored in order to not cast
+                // votes that conflict with the ones cast before the node has been restarted.
+                let persisted_state = self
+                    .database
+                    .with_database(|database| database.grandpa_voter_state())
+                    .await
+                    .unwrap()
+                    .and_then(
+                        |state| match grandpa::voter::PersistedState::decode(&state) {
+                            Ok(state) => Some(state),
+                            Err(error) => {
+                                tracing::warn!(%error, "grandpa-voter-state-corrupted");
+                                None
+                            }
+                        },
+                    );
+
+                let voter = grandpa::voter::Voter::new(grandpa::voter::Config {
+                    block_number_bytes,
+                    set_id,
+                    authorities,
+                    local_authority_public_key,
+                    finalized_block_hash: chain_information
+                        .finalized_block_header
+                        .hash(block_number_bytes),
+                    finalized_block_number: chain_information.finalized_block_header.number,
+                    voting_limit,
+                    gossip_duration: Duration::from_secs(1),
+                    now: Instant::now(),
+                    persisted_state,
+                })
+                .unwrap();
+
+                tracing::info!(
+                    %set_id,
+                    public_key = %HashDisplay(&local_authority_public_key),
+                    "grandpa-voter-started"
+                );
+
+                self.grandpa_voter = Some(voter);
+            }
+        }
+
+        let voter = match &mut self.grandpa_voter {
+            Some(v) => v,
+            None => return,
+        };
+
+        for header in self.sync.non_finalized_blocks_ancestry_order() {
+            voter.insert_block(
+                header.hash(block_number_bytes),
+                header.number,
+                *header.parent_hash,
+            );
+        }
+        voter.set_finalized_block(&self.sync.finalized_block_header().hash(block_number_bytes));
+        voter.set_best_block(&self.sync.best_block_hash());
+    }
+
+    /// Performs the actions requested by the GrandPa voter until it is idle. Returns the moment
+    /// when the voter must be driven again, or `None` if there is no voter.
+    async fn process_grandpa_voter(&mut self) -> Option<Instant> {
+        let voter = self.grandpa_voter.as_mut()?;
+        let block_number_bytes = self.sync.block_number_bytes();
+        let mut justification_queued = false;
+
+        loop {
+            match voter.next_action(&Instant::now()) {
+                grandpa::voter::Action::Idle { wake_up } => {
+                    // If a justification has been queued, the sync state machine must process
+                    // it as soon as possible.
+                    return Some(if justification_queued {
+                        Instant::now()
+                    } else {
+                        wake_up
+                    });
+                }
+                grandpa::voter::Action::SignVote(vote) => {
+                    let sign_result = self
+                        .keystore
+                        .sign(
+                            keystore::KeyNamespace::Grandpa,
+                            vote.authority_public_key(),
+                            &vote.payload(),
+                        )
+                        .await;
+
+                    match sign_result {
+                        Ok(signature) => voter.inject_vote_signature(vote, signature),
+                        Err(error) => {
+                            // Because the keystore is subject to race conditions, it is possible
+                            // for this situation to happen if the key has been removed from the
+                            // keystore. The vote is simply not cast.
+                            tracing::warn!(%error, "grandpa-signing-error");
+                        }
+                    }
+                }
+                grandpa::voter::Action::PersistState(state) => {
+                    // The state must be written to the database before the votes it covers are
+                    // broadcast.
+                    let state = state.scale_encoding_vec();
+                    self.database
+                        .with_database(move |database| database.set_grandpa_voter_state(&state))
+                        .await
+                        .unwrap();
+                }
+                grandpa::voter::Action::Broadcast(notification) => {
+                    let notification =
+                        protocol::decode_grandpa_notification(&notification, block_number_bytes)
+                            .unwrap();
+                    self.network_service
+                        .broadcast_grandpa_notification(self.network_chain_index, notification)
+                        .await;
+                }
+                grandpa::voter::Action::LocalStateUpdate {
+                    round_number,
+                    set_id,
+                    commit_finalized_height,
+                } => {
+                    self.network_service
+                        .set_local_grandpa_state(
+                            self.network_chain_index,
+                            network::service::GrandpaState {
+                                round_number,
+                                set_id,
+                                commit_finalized_height,
+                            },
+                        )
+                        .await;
+                }
+                grandpa::voter::Action::Finalized(commit) => {
+                    tracing::debug!(
+                        round = commit.round_number,
+                        hash = %HashDisplay(&commit.target_hash),
+                        number = commit.target_number,
+                        "grandpa-voter-finalized"
+                    );
+
+                    let votes_ancestries = self
+                        .sync
+                        .non_finalized_blocks_ancestry_order()
+                        .filter(|h| {
+                            commit
+                                .votes_ancestries
+                                .contains(&h.hash(block_number_bytes))
+                        })
+                        .map(|h| h.scale_encoding_vec(block_number_bytes))
+                        .collect::<Vec<_>>();
+                    let justification = commit.scale_encoded_justification(votes_ancestries.iter());
+
+                    // The justification is injected in the sync state machine the same way as
+                    // if it had been received from the network, and is verified by it.
+                    match self
+                        .sync
+                        .grandpa_justification(self.block_author_sync_source, justification)
+                    {
+                        all::GrandpaJustificationOutcome::Queued => justification_queued = true,
+                        all::GrandpaJustificationOutcome::Discarded => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Injects in the GrandPa voter a GrandPa notification received from the network.
+    async fn inject_grandpa_notification(
+        &mut self,
+        peer_id: libp2p::PeerId,
+        notification: network::service::EncodedGrandpaNotification,
+    ) {
+        let voter = match &mut self.grandpa_voter {
+            Some(v) => v,
+            None => return,
+        };
+
+        match notification.decode() {
+            protocol::GrandpaNotificationRef::Vote(vote) => {
+                if let Err(error) = voter.inject_vote(&vote) {
+                    tracing::debug!(%peer_id, %error, "grandpa-vote-rejected");
+                }
+            }
+            protocol::GrandpaNotificationRef::Neighbor(packet) => {
+                if let Some(request) = voter.inject_neighbor_packet(&packet) {
+                    let _ = self
+                        .network_service
+                        .send_grandpa_notification(
+                            &peer_id,
+                            self.network_chain_index,
+                            protocol::GrandpaNotificationRef::CatchUpRequest(request),
+                        )
+                        .await;
+                }
+            }
+            protocol::GrandpaNotificationRef::CatchUpRequest(request) => {
+                if let Some(response) = voter.catch_up_response(&request) {
+                    let response = protocol::decode_grandpa_notification(
+                        &response,
+                        self.sync.block_number_bytes(),
+                    )
+                    .unwrap();
+                    let _ = self
+                        .network_service
+                        .send_grandpa_notification(&peer_id, self.network_chain_index, response)
+                        .await;
+                }
+            }
+            protocol::GrandpaNotificationRef::CatchUp(catch_up) => {
+                if let Err(error) = voter.inject_catch_up(&Instant::now(), &catch_up) {
+                    tracing::debug!(%peer_id, %error, "grandpa-catch-up-rejected");
+                }
+            }
+            protocol::GrandpaNotificationRef::Commit(_) => {
+                // Commit messages are reported through a different event.
+            }
+        }
+    }
+
+    /// Sends the given notification to all the subscribers of [`ConsensusService::subscribe_all`].
+    ///
+    /// Subscribers whose channel is full or closed are removed.
+    fn notify_subscribers(&mut self, notification: Notification) {
+        self.blocks_notifications
+            .retain_mut(|tx| tx.try_send(notification.clone()).is_ok());
+    }
+
     /// Authors a block, then imports it and gossips it out.
     ///
     /// # Panic
@@ -656,7 +1384,6 @@ impl SyncBackground {
         // Most parts of the block authorship can't be accelerated, in particular the
         // initialization and the signing at the end. This end of authoring threshold is only
         // checked when deciding whether to continue including more transactions in the block.
-        // TODO: use this
         // TODO: Substrate nodes increase the time available for authoring if it detects that slots have been skipped, in order to account for the possibility that the initialization of a block or the inclusion of an extrinsic takes too long
         let authoring_end = {
             let start = authoring_start.slot_start_from_unix_epoch();
@@ -669,6 +1396,19 @@ impl SyncBackground {
                     / u32::from(u16::max_value())
         };
 
+        // Transactions of the pool to try include in the block, in order.
+        let mut transactions_to_include = self
+            .transactions_pool
+            .inclusion_order()
+            .map(|tx_id| {
+                self.transactions_pool
+                    .scale_encoding(tx_id)
+                    .unwrap()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>()
+            .into_iter();
+
         // Actual block production now happening.
         let block = {
             // Start the block authoring process.
@@ -684,7 +1424,7 @@ impl SyncBackground {
                         .duration_since(SystemTime::UNIX_EPOCH)
                         .unwrap(),
                     parent_runtime,
-                    block_body_capacity: 0, // TODO: could be set to the size of the tx pool
+                    block_body_capacity: transactions_to_include.len(),
                     top_trie_root_calculation_cache: None, // TODO: pretty important for performances
                 })
             };
@@ -703,10 +1443,15 @@ impl SyncBackground {
                         let span = tracing::debug_span!("block-authoring-signing");
                         let _enter = span.enter();
 
-                        // TODO: correct key namespace
+                        let key_namespace = match self.sync.best_block_consensus() {
+                            chain_information::ChainInformationConsensusRef::Babe { .. } => {
+                                keystore::KeyNamespace::Babe
+                            }
+                            _ => keystore::KeyNamespace::Aura,
+                        };
                         let data_to_sign = seal.to_sign();
                         let sign_future = self.keystore.sign(
-                            keystore::KeyNamespace::Aura,
+                            key_namespace,
                             &local_authorities[seal.authority_index()],
                             &data_to_sign,
                         );
@@ -743,8 +1488,14 @@ impl SyncBackground {
                     // Part of the block production consists in adding transactions to the block.
                     // These transactions are extracted from the transactions pool.
                     author::build::BuilderAuthoring::ApplyExtrinsic(apply) => {
-                        // TODO: actually implement including transactions in the blocks
-                        block_authoring = apply.finish();
+                        // No new transaction is added after the end of the authoring has been
+                        // reached.
+                        block_authoring = match transactions_to_include.next() {
+                            Some(transaction) if SystemTime::now() < authoring_end => {
+                                apply.add_extrinsic(transaction)
+                            }
+                            _ => apply.finish(),
+                        };
                         continue;
                     }
                     author::build::BuilderAuthoring::ApplyExtrinsicResult { result, resume } => {
@@ -753,52 +1504,41 @@ impl SyncBackground {
                             tracing::warn!(%error, "block-author-transaction-inclusion-error");
                         }
 
-                        // TODO: actually implement including transactions in the blocks
-                        block_authoring = resume.finish();
+                        block_authoring = author::build::BuilderAuthoring::ApplyExtrinsic(resume);
                         continue;
                     }
 
                     // Access to the best block storage.
                     author::build::BuilderAuthoring::StorageGet(get) => {
-                        // Access the storage of the best block. Can return `̀None` if not syncing
-                        // in full mode, in which case we shouldn't have reached this code.
-                        let best_block_storage_access = self.sync.best_block_storage().unwrap();
-
                         let value = {
-                            let key = get.key();
-                            best_block_storage_access.get(key.as_ref(), || {
-                                self.finalized_block_storage
-                                    .get(key.as_ref())
-                                    .map(|v| &v[..])
-                            })
+                            let child_trie = get.child_trie();
+                            self.best_block_storage_get(
+                                child_trie.as_ref().map(|c| c.as_ref()),
+                                get.key().as_ref(),
+                            )
                         };
-                        block_authoring = get.inject_value(value.map(iter::once));
+                        block_authoring = get.inject_value(value.as_ref().map(iter::once));
                         continue;
                     }
-                    author::build::BuilderAuthoring::NextKey(_) => {
-                        todo!() // TODO: implement
+                    author::build::BuilderAuthoring::NextKey(next_key) => {
+                        let key = {
+                            let child_trie = next_key.child_trie();
+                            self.best_block_storage_next_key(
+                                child_trie.as_ref().map(|c| c.as_ref()),
+                                next_key.key().as_ref(),
+                            )
+                        };
+                        block_authoring = next_key.inject_key(key);
+                        continue;
                     }
                     author::build::BuilderAuthoring::PrefixKeys(prefix_key) => {
-                        // Access the storage of the best block. Can return `̀None` if not syncing
-                        // in full mode, in which case we shouldn't have reached this code.
-                        let best_block_storage_access = self.sync.best_block_storage().unwrap();
-
-                        let keys = best_block_storage_access
-                            .prefix_keys_ordered(
+                        let keys = {
+                            let child_trie = prefix_key.child_trie();
+                            self.best_block_storage_prefix_keys(
+                                child_trie.as_ref().map(|c| c.as_ref()),
                                 prefix_key.prefix().as_ref(),
-                                self.finalized_block_storage
-                                    .range::<[u8], _>((
-                                        ops::Bound::Included(prefix_key.prefix().as_ref()),
-                                        ops::Bound::Unbounded,
-                                    ))
-                                    .take_while(|(k, _)| {
-                                        k.starts_with(prefix_key.prefix().as_ref())
-                                    })
-                                    .map(|(k, _)| &k[..]),
                             )
-                            .map(|k| k.as_ref().to_vec()) // TODO: overhead
-                            .collect::<Vec<_>>();
-
+                        };
                         block_authoring = prefix_key.inject_keys_ordered(keys.into_iter());
                         continue;
                     }
@@ -848,7 +1588,8 @@ impl SyncBackground {
             all::BlockAnnounceOutcome::TooOld { .. }
             | all::BlockAnnounceOutcome::AlreadyInChain
             | all::BlockAnnounceOutcome::NotFinalizedChain
-            | all::BlockAnnounceOutcome::InvalidHeader(_) => unreachable!(),
+            | all::BlockAnnounceOutcome::InvalidHeader(_)
+            | all::BlockAnnounceOutcome::BannedBlock => unreachable!(),
         }
 
         debug_assert!(self.authored_block.is_none());
@@ -860,6 +1601,525 @@ impl SyncBackground {
         ));
     }
 
+    /// Returns the storage value of the given key in the storage of the current best block, or
+    /// in the given default child trie of the current best block.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`SyncBackground::sync`] isn't syncing in full mode.
+    ///
+    fn best_block_storage_get(&self, child_trie: Option<&[u8]>, key: &[u8]) -> Option<Vec<u8>> {
+        // Access the storage of the best block. Can return `̀None` if not syncing in full mode, in
+        // which case we shouldn't have reached this code.
+        let best_block_storage_access = self.sync.best_block_storage().unwrap();
+
+        let value = if let Some(child_trie) = child_trie {
+            best_block_storage_access.child_trie_get(child_trie, key, || {
+                self.finalized_block_child_tries
+                    .get(child_trie)
+                    .and_then(|trie| trie.get(key))
+                    .map(|v| &v[..])
+            })
+        } else {
+            best_block_storage_access.get(key, || {
+                self.finalized_block_storage.get(key).map(|v| &v[..])
+            })
+        };
+
+        value.map(|v| v.to_vec()) // TODO: overhead
+    }
+
+    /// Returns the key that immediately follows the given key in the storage of the current best
+    /// block, or in the given default child trie of the current best block.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`SyncBackground::sync`] isn't syncing in full mode.
+    ///
+    fn best_block_storage_next_key(
+        &self,
+        child_trie: Option<&[u8]>,
+        key: &[u8],
+    ) -> Option<Vec<u8>> {
+        // Access the storage of the best block. Can return `̀None` if not syncing in full mode, in
+        // which case we shouldn't have reached this code.
+        let best_block_storage_access = self.sync.best_block_storage().unwrap();
+
+        let empty = BTreeMap::new();
+        let finalized_storage = match child_trie {
+            Some(child_trie) => self
+                .finalized_block_child_tries
+                .get(child_trie)
+                .unwrap_or(&empty),
+            None => &self.finalized_block_storage,
+        };
+        let in_finalized = |key: &[u8]| {
+            finalized_storage
+                .range::<[u8], _>((ops::Bound::Excluded(key), ops::Bound::Unbounded))
+                .next()
+                .map(|(k, _)| &k[..])
+        };
+
+        let next_key = if let Some(child_trie) = child_trie {
+            best_block_storage_access.child_trie_next_key(child_trie, key, in_finalized)
+        } else {
+            best_block_storage_access.next_key(key, in_finalized)
+        };
+
+        next_key.map(|k| k.to_vec()) // TODO: overhead
+    }
+
+    /// Returns the list of keys that start with the given prefix in the storage of the current
+    /// best block, or in the given default child trie of the current best block.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`SyncBackground::sync`] isn't syncing in full mode.
+    ///
+    fn best_block_storage_prefix_keys(
+        &self,
+        child_trie: Option<&[u8]>,
+        prefix: &[u8],
+    ) -> Vec<Vec<u8>> {
+        // Access the storage of the best block. Can return `̀None` if not syncing in full mode, in
+        // which case we shouldn't have reached this code.
+        let best_block_storage_access = self.sync.best_block_storage().unwrap();
+
+        if let Some(child_trie) = child_trie {
+            let empty = BTreeMap::new();
+            best_block_storage_access
+                .child_trie_prefix_keys_ordered(
+                    child_trie,
+                    prefix,
+                    self.finalized_block_child_tries
+                        .get(child_trie)
+                        .unwrap_or(&empty)
+                        .range::<[u8], _>((ops::Bound::Included(prefix), ops::Bound::Unbounded))
+                        .take_while(|(k, _)| k.starts_with(prefix))
+                        .map(|(k, _)| &k[..]),
+                )
+                .map(|k| k.as_ref().to_vec()) // TODO: overhead
+                .collect::<Vec<_>>()
+        } else {
+            best_block_storage_access
+                .prefix_keys_ordered(
+                    prefix,
+                    self.finalized_block_storage
+                        .range::<[u8], _>((ops::Bound::Included(prefix), ops::Bound::Unbounded))
+                        .take_while(|(k, _)| k.starts_with(prefix))
+                        .map(|(k, _)| &k[..]),
+                )
+                .map(|k| k.as_ref().to_vec()) // TODO: overhead
+                .collect::<Vec<_>>()
+        }
+    }
+
+    /// Adds a transaction to the [`SyncBackground::transactions_pool`], unless the pool already
+    /// contains an identical transaction or is full.
+    ///
+    /// `sender` is the identity of the networking peer the transaction comes from, if any.
+    ///
+    /// The transaction is validated the next time [`SyncBackground::validate_transactions`] is
+    /// called.
+    fn add_transaction_to_pool(
+        &mut self,
+        scale_encoded_transaction: Vec<u8>,
+        sender: Option<&[u8]>,
+        source: validate::TransactionSource,
+    ) {
+        if self
+            .transactions_pool
+            .find(&scale_encoded_transaction)
+            .next()
+            .is_some()
+        {
+            return;
+        }
+
+        if let Err(error) = self.transactions_pool.add_unvalidated(
+            scale_encoded_transaction,
+            sender,
+            PoolTransaction { source },
+        ) {
+            tracing::debug!(%error, "transaction-pool-add-error");
+        }
+    }
+
+    /// Validates against the current best block the transactions of the
+    /// [`SyncBackground::transactions_pool`] that aren't included in any block and haven't been
+    /// validated yet, then announces the valid transactions to the networking peers.
+    ///
+    /// Invalid transactions are removed from the pool.
+    async fn validate_transactions(&mut self) {
+        let best_block_height = self.transactions_pool.best_block_height();
+        debug_assert_eq!(best_block_height, self.sync.best_block_number());
+
+        let to_validate = self
+            .transactions_pool
+            .unvalidated_transactions()
+            .filter(|(tx_id, _, _)| {
+                self.transactions_pool
+                    .included_block_height(*tx_id)
+                    .is_none()
+            })
+            .map(|(tx_id, tx, _)| (tx_id, tx.source))
+            .collect::<Vec<_>>();
+        if to_validate.is_empty() {
+            return;
+        }
+
+        let best_block_scale_encoded_header = self
+            .sync
+            .best_block_header()
+            .scale_encoding_vec(self.sync.block_number_bytes());
+
+        // The same runtime is re-used for all the transactions.
+        let mut runtime = self.sync.best_block_storage().unwrap().runtime().clone(); // TODO: overhead here with cloning, but solving it requires very tricky API changes in syncing code
+
+        for (tx_id, source) in to_validate {
+            let scale_encoded_transaction = self
+                .transactions_pool
+                .scale_encoding(tx_id)
+                .unwrap()
+                .to_vec(); // TODO: copy :-/
+
+            let span = tracing::debug_span!(
+                "transaction-validation",
+                best_block_hash = %HashDisplay(&self.sync.best_block_hash()),
+                best_block_height,
+                outcome = tracing::field::Empty,
+                error = tracing::field::Empty,
+            );
+            let _enter = span.enter();
+
+            let (result, runtime_back) = self.validate_transaction(
+                runtime,
+                &best_block_scale_encoded_header,
+                &scale_encoded_transaction,
+                source,
+            );
+            runtime = runtime_back;
+
+            match result {
+                Ok(Ok(valid)) => {
+                    span.record("outcome", "valid");
+                    let propagate = valid.propagate;
+                    self.transactions_pool.set_validation_result(
+                        tx_id,
+                        best_block_height,
+                        Ok(valid),
+                    );
+
+                    if propagate {
+                        self.network_service
+                            .announce_transaction(
+                                self.network_chain_index,
+                                &scale_encoded_transaction,
+                            )
+                            .await;
+                    }
+                }
+                Ok(Err(error)) => {
+                    span.record("outcome", "invalid");
+                    span.record("error", tracing::field::display(error));
+                    self.transactions_pool.remove(tx_id);
+                }
+                Err(error) => {
+                    tracing::warn!(%error, "transaction-validation-error");
+                    span.record("outcome", "failure");
+                    span.record("error", tracing::field::display(error));
+                    self.transactions_pool.remove(tx_id);
+                }
+            }
+        }
+    }
+
+    /// Validates the given transaction against the current best block using the given runtime,
+    /// which must be the runtime of the current best block.
+    ///
+    /// Returns the outcome of the validation and the runtime.
+    fn validate_transaction(
+        &self,
+        runtime: executor::host::HostVmPrototype,
+        best_block_scale_encoded_header: &[u8],
+        scale_encoded_transaction: &[u8],
+        source: validate::TransactionSource,
+    ) -> (
+        Result<
+            Result<validate::ValidTransaction, validate::TransactionValidityError>,
+            validate::Error,
+        >,
+        executor::host::HostVmPrototype,
+    ) {
+        let mut query = validate::validate_transaction(validate::Config {
+            runtime,
+            scale_encoded_header: best_block_scale_encoded_header,
+            block_number_bytes: self.sync.block_number_bytes(),
+            scale_encoded_transaction: iter::once(scale_encoded_transaction),
+            source,
+        });
+
+        loop {
+            match query {
+                validate::Query::Finished {
+                    result,
+                    virtual_machine,
+                } => return (result, virtual_machine),
+                validate::Query::StorageGet(get) => {
+                    let value = {
+                        let child_trie = get.child_trie();
+                        self.best_block_storage_get(
+                            child_trie.as_ref().map(|c| c.as_ref()),
+                            get.key().as_ref(),
+                        )
+                    };
+                    query = get.inject_value(value.as_ref().map(iter::once));
+                }
+                validate::Query::NextKey(next_key) => {
+                    let key = {
+                        let child_trie = next_key.child_trie();
+                        self.best_block_storage_next_key(
+                            child_trie.as_ref().map(|c| c.as_ref()),
+                            next_key.key().as_ref(),
+                        )
+                    };
+                    query = next_key.inject_key(key);
+                }
+                validate::Query::PrefixKeys(prefix_keys) => {
+                    let keys = {
+                        let child_trie = prefix_keys.child_trie();
+                        self.best_block_storage_prefix_keys(
+                            child_trie.as_ref().map(|c| c.as_ref()),
+                            prefix_keys.prefix().as_ref(),
+                        )
+                    };
+                    query = prefix_keys.inject_keys_ordered(keys.into_iter());
+                }
+            }
+        }
+    }
+
+    /// Updates the [`SyncBackground::transactions_pool`] so that the chain of blocks it tracks
+    /// matches the best chain of [`SyncBackground::sync`].
+    ///
+    /// `new_best_block_body` must contain the list of SCALE-encoded extrinsics of the current
+    /// best block if this block has just been verified. The transactions found in this body are
+    /// marked as included. The bodies of the other blocks of the best chain aren't known anymore,
+    /// and these blocks are added to the pool as if they were empty.
+    fn update_transactions_pool(mut self, new_best_block_body: Option<Vec<Vec<u8>>>) -> Self {
+        let block_number_bytes = self.sync.block_number_bytes();
+        let best_block_hash = self.sync.best_block_hash();
+        let finalized_block_hash = self.sync.finalized_block_header().hash(block_number_bytes);
+
+        let pool_best_block_hash = self
+            .transactions_pool_best_chain
+            .last()
+            .copied()
+            .unwrap_or(finalized_block_hash);
+        if pool_best_block_hash == best_block_hash {
+            return self;
+        }
+
+        // Determine the number of blocks that the pool and the sync have in common, and the
+        // list of blocks to add to the pool.
+        let (num_common_blocks, blocks_to_append) =
+            if *self.sync.best_block_header().parent_hash == pool_best_block_hash {
+                // Fast path for the most common situation.
+                (
+                    self.transactions_pool_best_chain.len(),
+                    vec![best_block_hash],
+                )
+            } else {
+                let parents = self
+                    .sync
+                    .non_finalized_blocks_unordered()
+                    .map(|header| (header.hash(block_number_bytes), *header.parent_hash))
+                    .collect::<hashbrown::HashMap<_, _, fnv::FnvBuildHasher>>();
+
+                let mut best_chain = Vec::new();
+                let mut iter = best_block_hash;
+                while iter != finalized_block_hash {
+                    best_chain.push(iter);
+                    iter = *parents.get(&iter).unwrap();
+                }
+                best_chain.reverse();
+
+                let num_common_blocks = best_chain
+                    .iter()
+                    .zip(self.transactions_pool_best_chain.iter())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                best_chain.drain(..num_common_blocks);
+                (num_common_blocks, best_chain)
+            };
+
+        // The transactions found in the retracted blocks go back to being non-included.
+        let num_to_retract =
+            u64::try_from(self.transactions_pool_best_chain.len() - num_common_blocks).unwrap();
+        let _ = self.transactions_pool.retract_blocks(num_to_retract);
+        self.transactions_pool_best_chain
+            .truncate(num_common_blocks);
+
+        for block_hash in blocks_to_append {
+            let mut append = self.transactions_pool.append_block();
+            if block_hash == best_block_hash {
+                for extrinsic in new_best_block_body.iter().flatten() {
+                    match append.block_transaction(extrinsic) {
+                        pool::AppendBlockTransaction::Unknown(vacant) => {
+                            vacant.insert(PoolTransaction {
+                                source: validate::TransactionSource::InBlock,
+                            });
+                        }
+                        pool::AppendBlockTransaction::NonIncludedUpdated { .. } => {}
+                    }
+                }
+            }
+            self.transactions_pool = append.finish();
+            self.transactions_pool_best_chain.push(block_hash);
+        }
+
+        // Transactions whose longevity has expired are removed from the pool.
+        let _ = self.transactions_pool.remove_expired();
+
+        self
+    }
+
+    /// Searches for the earliest Babe slot that one of the given local authorities can claim on
+    /// top of the current best block.
+    ///
+    /// Returns `None` if no slot can be claimed, or in case of error.
+    async fn babe_slot_claim(
+        &mut self,
+        local_authorities: &[[u8; 32]],
+    ) -> Option<author::babe::SlotClaim> {
+        let slot_duration = match self.babe_slot_duration {
+            Some(slot_duration) => slot_duration,
+            None => match self.babe_configuration_slot_duration() {
+                Ok(slot_duration) => *self.babe_slot_duration.insert(slot_duration),
+                Err(error) => {
+                    tracing::warn!(%error, "babe-configuration-error");
+                    return None;
+                }
+            },
+        };
+
+        let mut search = {
+            let (slots_per_epoch, parent_block_epoch, parent_block_next_epoch) =
+                match self.sync.best_block_consensus() {
+                    chain_information::ChainInformationConsensusRef::Babe {
+                        slots_per_epoch,
+                        finalized_block_epoch_information,
+                        finalized_next_epoch_transition,
+                    } => (
+                        slots_per_epoch,
+                        finalized_block_epoch_information,
+                        finalized_next_epoch_transition,
+                    ),
+                    _ => unreachable!(),
+                };
+
+            let best_block_header = self.sync.best_block_header();
+            let parent_block_slot_number = if best_block_header.number != 0 {
+                Some(best_block_header.digest.babe_pre_runtime()?.slot_number())
+            } else {
+                None
+            };
+
+            author::babe::next_slot_claim(author::babe::Config {
+                now_from_unix_epoch: SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap(),
+                slot_duration,
+                slots_per_epoch,
+                parent_block_slot_number,
+                parent_block_epoch,
+                parent_block_next_epoch,
+                local_authorities: local_authorities.iter(),
+            })
+        };
+
+        loop {
+            match search {
+                author::babe::SlotClaimSearch::Finished(claim) => break claim,
+                author::babe::SlotClaimSearch::VrfSign(vrf_sign) => {
+                    let signature = self
+                        .keystore
+                        .sign_sr25519_vrf(
+                            keystore::KeyNamespace::Babe,
+                            vrf_sign.public_key(),
+                            vrf_sign.transcript_label(),
+                            vrf_sign.transcript_items(),
+                        )
+                        .await;
+
+                    match signature {
+                        Ok(signature) => {
+                            search = vrf_sign.inject_vrf_output(signature.output, signature.proof)
+                        }
+                        Err(error) => {
+                            // Because the keystore is subject to race conditions, it is possible
+                            // for this situation to happen if the key has been removed from the
+                            // keystore in parallel of the slot claim search.
+                            tracing::warn!(%error, "vrf-signing-error");
+                            break None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Calls the `BabeApi_configuration` runtime function on the current best block and returns
+    /// the Babe slot duration found in its output.
+    fn babe_configuration_slot_duration(&self) -> Result<NonZeroU64, BabeConfigurationError> {
+        // Access the storage of the best block. Can return `̀None` if not syncing in full mode,
+        // in which case we shouldn't have reached this code.
+        let best_block_storage_access = self.sync.best_block_storage().unwrap();
+
+        let mut call =
+            executor::read_only_runtime_host::run(executor::read_only_runtime_host::Config {
+                virtual_machine: best_block_storage_access.runtime().clone(), // TODO: overhead here with cloning
+                function_to_call: "BabeApi_configuration",
+                parameter: iter::empty::<&[u8]>(),
+            })
+            .map_err(|(error, _)| BabeConfigurationError::StartError(error))?;
+
+        let output = loop {
+            match call {
+                executor::read_only_runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                    break success.virtual_machine.value().as_ref().to_vec()
+                }
+                executor::read_only_runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                    return Err(BabeConfigurationError::Execution(error.detail))
+                }
+                executor::read_only_runtime_host::RuntimeHostVm::StorageGet(get) => {
+                    let value = best_block_storage_access.get(get.key().as_ref(), || {
+                        self.finalized_block_storage
+                            .get(get.key().as_ref())
+                            .map(|v| &v[..])
+                    });
+                    call = get.inject_value(value.map(iter::once));
+                }
+                executor::read_only_runtime_host::RuntimeHostVm::NextKey(_) => {
+                    return Err(BabeConfigurationError::NextKeyUnsupported)
+                }
+                executor::read_only_runtime_host::RuntimeHostVm::StorageRoot(storage_root) => {
+                    call = storage_root.resume(self.sync.best_block_header().state_root);
+                }
+                executor::read_only_runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                    call = sig.verify_and_resume();
+                }
+            }
+        };
+
+        // The slot duration is the first field of the output, encoded as a little endian `u64`.
+        output
+            .get(..8)
+            .map(|bytes| u64::from_le_bytes(<[u8; 8]>::try_from(bytes).unwrap()))
+            .and_then(NonZeroU64::new)
+            .ok_or(BabeConfigurationError::OutputDecode)
+    }
+
     /// Starts all the new network requests that should be started.
     // TODO: handle obsolete requests
     async fn start_network_requests(&mut self) {
@@ -996,11 +2256,116 @@ impl SyncBackground {
                     self.block_requests_finished
                         .push(request.map(move |r| (request_id, source_id, r)).boxed());
                 }
-                all::DesiredRequest::GrandpaWarpSync { .. }
-                | all::DesiredRequest::StorageGetMerkleProof { .. }
-                | all::DesiredRequest::RuntimeCallMerkleProof { .. } => {
-                    // Not used in "full" mode.
-                    unreachable!()
+                all::DesiredRequest::GrandpaWarpSync {
+                    sync_start_block_hash,
+                } => {
+                    let peer_id = self.sync[source_id].clone().unwrap().peer_id;
+
+                    let request = self
+                        .network_service
+                        .clone()
+                        .grandpa_warp_sync_request(
+                            peer_id,
+                            self.network_chain_index,
+                            sync_start_block_hash,
+                        )
+                        .map(StateSyncRequestResult::GrandpaWarpSync);
+
+                    let (request, abort) = future::abortable(request);
+                    let request_id = self.sync.add_request(source_id, request_info.into(), abort);
+
+                    self.state_sync_requests_finished.push(
+                        request
+                            .map(move |r| (request_id, source_id, r.ok()))
+                            .boxed(),
+                    );
+                }
+
+                all::DesiredRequest::StorageGetMerkleProof {
+                    block_hash,
+                    ref keys,
+                    ..
+                } => {
+                    let peer_id = self.sync[source_id].clone().unwrap().peer_id;
+
+                    let request = self
+                        .network_service
+                        .clone()
+                        .storage_proof_request(
+                            peer_id,
+                            self.network_chain_index,
+                            block_hash,
+                            keys.clone(),
+                        )
+                        .map(StateSyncRequestResult::StorageProof);
+
+                    let (request, abort) = future::abortable(request);
+                    let request_id = self.sync.add_request(source_id, request_info.into(), abort);
+
+                    self.state_sync_requests_finished.push(
+                        request
+                            .map(move |r| (request_id, source_id, r.ok()))
+                            .boxed(),
+                    );
+                }
+
+                all::DesiredRequest::RuntimeCallMerkleProof {
+                    block_hash,
+                    ref function_name,
+                    ref parameter_vectored,
+                } => {
+                    let peer_id = self.sync[source_id].clone().unwrap().peer_id;
+
+                    let request = self
+                        .network_service
+                        .clone()
+                        .call_proof_request(
+                            peer_id,
+                            self.network_chain_index,
+                            block_hash,
+                            function_name.to_string(),
+                            parameter_vectored.to_vec(),
+                        )
+                        .map(StateSyncRequestResult::CallProof);
+
+                    let (request, abort) = future::abortable(request);
+                    let request_id = self.sync.add_request(source_id, request_info.into(), abort);
+
+                    self.state_sync_requests_finished.push(
+                        request
+                            .map(move |r| (request_id, source_id, r.ok()))
+                            .boxed(),
+                    );
+                }
+
+                all::DesiredRequest::StateRequest {
+                    block_hash,
+                    ref child_trie,
+                    ref start_key,
+                    ..
+                } => {
+                    let peer_id = self.sync[source_id].clone().unwrap().peer_id;
+
+                    let request = self
+                        .network_service
+                        .clone()
+                        .state_request(
+                            peer_id,
+                            self.network_chain_index,
+                            block_hash,
+                            child_trie.clone(),
+                            start_key.clone(),
+                        )
+                        .map(StateSyncRequestResult::State);
+
+                    let (request, abort) = future::abortable(request);
+                    let request_id = self.sync.add_request(source_id, request_info.into(), abort);
+
+                    self.state_sync_requests_finished.push(
+                        request
+                            .map(move |r| (request_id, source_id, r.ok()))
+                            .boxed(),
+                    );
                 }
             }
         }
@@ -1022,13 +2387,103 @@ impl SyncBackground {
                     self.sync = idle;
                     break;
                 }
-                all::ProcessOne::VerifyWarpSyncFragment(_)
-                | all::ProcessOne::WarpSyncError { .. }
-                | all::ProcessOne::WarpSyncFinished { .. } => unreachable!(),
+                all::ProcessOne::VerifyWarpSyncFragment(verify) => {
+                    let (sync_out, result) = verify.perform(rand::random());
+                    self.sync = sync_out;
+                    if let Err(error) = result {
+                        tracing::warn!(%error, "failed-warp-sync-fragment-verification");
+                    }
+                }
+                all::ProcessOne::WarpSyncError { sync, error } => {
+                    self.sync = sync;
+                    tracing::warn!(%error, "warp-sync-error");
+                }
+                all::ProcessOne::WarpSyncFinished {
+                    sync,
+                    finalized_storage,
+                    finalized_storage_child_tries,
+                    ..
+                } => {
+                    self.sync = sync;
+
+                    // The storage is always downloaded when warp syncing in full mode.
+                    let finalized_storage = finalized_storage.unwrap();
+
+                    let finalized_block_hash = self
+                        .sync
+                        .finalized_block_header()
+                        .hash(self.sync.block_number_bytes());
+                    let finalized_block_number = self.sync.finalized_block_header().number;
+                    tracing::info!(
+                        finalized_block_hash = %HashDisplay(&finalized_block_hash),
+                        finalized_block_number, num_storage_entries = finalized_storage.len(),
+                        num_child_tries = finalized_storage_child_tries.len(),
+                        "state-sync-finished"
+                    );
+
+                    // The database is entirely reset to the new finalized block. Its body isn't
+                    // known, and is thus stored as empty.
+                    // The storage is moved to the database thread and given back afterwards in
+                    // order to avoid cloning it.
+                    let chain_information = chain_information::ValidChainInformation::from(
+                        self.sync.as_chain_information(),
+                    );
+                    (
+                        self.finalized_block_storage,
+                        self.finalized_block_child_tries,
+                    ) = self
+                        .database
+                        .with_database(move |database| {
+                            database
+                                .reset(
+                                    chain_information.as_ref(),
+                                    iter::empty(),
+                                    None,
+                                    finalized_storage.iter().map(|(k, v)| (&k[..], &v[..])),
+                                    finalized_storage_child_tries.iter().flat_map(
+                                        |(child_trie, entries)| {
+                                            entries
+                                                .iter()
+                                                .map(|(k, v)| (&child_trie[..], &k[..], &v[..]))
+                                        },
+                                    ),
+                                )
+                                .unwrap();
+                            (finalized_storage, finalized_storage_child_tries)
+                        })
+                        .await;
+
+                    // Everything that was tracked about the chain prior to the state sync is
+                    // now obsolete.
+                    self.transactions_pool = new_transactions_pool(finalized_block_number);
+                    self.transactions_pool_best_chain.clear();
+                    self.block_authoring = None;
+                    self.grandpa_voter = None;
+                    // Subscribers subscribe again when their channel is closed, and thus
+                    // receive the new finalized block.
+                    self.blocks_notifications.clear();
+
+                    let fut = self.network_service.set_local_best_block(
+                        self.network_chain_index,
+                        self.sync.best_block_hash(),
+                        self.sync.best_block_number(),
+                    );
+                    fut.await;
+
+                    let mut lock = self.sync_state.lock().await;
+                    lock.best_block_hash = self.sync.best_block_hash();
+                    lock.best_block_number = self.sync.best_block_number();
+                    lock.finalized_block_hash = finalized_block_hash;
+                    lock.finalized_block_number = finalized_block_number;
+                }
                 all::ProcessOne::VerifyBodyHeader(verify) => {
                     let hash_to_verify = verify.hash();
                     let height_to_verify = verify.height();
                     let scale_encoded_header_to_verify = verify.scale_encoded_header().to_owned(); // TODO: copy :-/
+                    let mut scale_encoded_extrinsics_to_verify = verify
+                        .scale_encoded_extrinsics()
+                        .map(|extrinsic| extrinsic.as_ref().to_vec())
+                        .collect::<Vec<_>>(); // TODO: copy :-/
 
                     let span = tracing::debug_span!(
                         "block-verification",
@@ -1093,6 +2548,21 @@ impl SyncBackground {
 
                                 self.sync = sync_out;
 
+                                // Update the transactions pool with the transactions of the
+                                // new best block.
+                                if is_new_best {
+                                    self = self.update_transactions_pool(Some(mem::take(
+                                        &mut scale_encoded_extrinsics_to_verify,
+                                    )));
+                                }
+
+                                // Notify the subscribers.
+                                self.notify_subscribers(Notification::Block(BlockNotification {
+                                    is_new_best,
+                                    scale_encoded_header: scale_encoded_header_to_verify.clone(),
+                                    block_hash: hash_to_verify,
+                                }));
+
                                 // Announce the newly-verified block to all the sources that might
                                 // not be aware of it. We can never be guaranteed that a certain
                                 // source does *not* know about a block, however it is not a big
@@ -1152,17 +2622,28 @@ impl SyncBackground {
                             }
 
                             all::BlockVerification::FinalizedStorageGet(req) => {
-                                let value = self
-                                    .finalized_block_storage
-                                    .get(req.key().as_ref())
-                                    .map(|v| &v[..]);
+                                let value = match req.child_trie() {
+                                    Some(child_trie) => self
+                                        .finalized_block_child_tries
+                                        .get(child_trie.as_ref())
+                                        .and_then(|trie| trie.get(req.key().as_ref())),
+                                    None => self.finalized_block_storage.get(req.key().as_ref()),
+                                }
+                                .map(|v| &v[..]);
                                 verify = req.inject_value(value);
                             }
                             all::BlockVerification::FinalizedStorageNextKey(req) => {
                                 // TODO: to_vec() :-/ range() immediately calculates the range of keys so there's no borrowing issue, but the take_while needs to keep req borrowed, which isn't possible
                                 let req_key = req.key().as_ref().to_vec();
-                                let next_key = self
-                                    .finalized_block_storage
+                                let empty = BTreeMap::new();
+                                let storage = match req.child_trie() {
+                                    Some(child_trie) => self
+                                        .finalized_block_child_tries
+                                        .get(child_trie.as_ref())
+                                        .unwrap_or(&empty),
+                                    None => &self.finalized_block_storage,
+                                };
+                                let next_key = storage
                                     .range::<[u8], _>((
                                         ops::Bound::Included(req.key().as_ref()),
                                         ops::Bound::Unbounded,
@@ -1174,8 +2655,15 @@ impl SyncBackground {
                             all::BlockVerification::FinalizedStoragePrefixKeys(req) => {
                                 // TODO: to_vec() :-/ range() immediately calculates the range of keys so there's no borrowing issue, but the take_while needs to keep req borrowed, which isn't possible
                                 let prefix = req.prefix().as_ref().to_vec();
-                                let keys = self
-                                    .finalized_block_storage
+                                let empty = BTreeMap::new();
+                                let storage = match req.child_trie() {
+                                    Some(child_trie) => self
+                                        .finalized_block_child_tries
+                                        .get(child_trie.as_ref())
+                                        .unwrap_or(&empty),
+                                    None => &self.finalized_block_storage,
+                                };
+                                let keys = storage
                                     .range::<[u8], _>((
                                         ops::Bound::Included(req.prefix().as_ref()),
                                         ops::Bound::Unbounded,
@@ -1210,6 +2698,27 @@ impl SyncBackground {
                             span.record("outcome", &"success");
                             self.sync = sync_out;
 
+                            // Transactions included in the newly-finalized blocks are removed
+                            // from the pool, and the newly-finalized blocks are no longer
+                            // tracked by it.
+                            {
+                                let finalized_block_height =
+                                    self.sync.finalized_block_header().number;
+                                let _ = self
+                                    .transactions_pool
+                                    .remove_included(finalized_block_height);
+                                let pool_base_height = self.transactions_pool.best_block_height()
+                                    - u64::try_from(self.transactions_pool_best_chain.len())
+                                        .unwrap();
+                                let num_finalized = usize::try_from(
+                                    finalized_block_height.saturating_sub(pool_base_height),
+                                )
+                                .unwrap()
+                                .min(self.transactions_pool_best_chain.len());
+                                self.transactions_pool_best_chain.drain(..num_finalized);
+                            }
+                            self = self.update_transactions_pool(None);
+
                             if updates_best_block {
                                 let fut = self.network_service.set_local_best_block(
                                     self.network_chain_index,
@@ -1253,16 +2762,49 @@ impl SyncBackground {
                                         // assert!(_was_there.is_some());
                                     }
                                 }
+
+                                for (child_trie, changes) in
+                                    &block.full.as_ref().unwrap().storage_child_tries_changes
+                                {
+                                    let trie = self
+                                        .finalized_block_child_tries
+                                        .entry(child_trie.clone())
+                                        .or_default();
+                                    for (key, value) in changes.diff_iter_unordered() {
+                                        if let Some(value) = value {
+                                            trie.insert(key.to_owned(), value.to_owned());
+                                        } else {
+                                            trie.remove(key);
+                                        }
+                                    }
+                                    if trie.is_empty() {
+                                        self.finalized_block_child_tries.remove(child_trie);
+                                    }
+                                }
                             }
 
                             let new_finalized_hash = finalized_blocks
                                 .last()
                                 .map(|lf| lf.header.hash(self.sync.block_number_bytes()))
                                 .unwrap();
+                            let new_finalized_scale_encoded_header = finalized_blocks
+                                .last()
+                                .unwrap()
+                                .header
+                                .scale_encoding_vec(self.sync.block_number_bytes());
                             let block_number_bytes = self.sync.block_number_bytes();
                             database_blocks(&self.database, finalized_blocks, block_number_bytes)
                                 .await;
                             database_set_finalized(&self.database, new_finalized_hash).await;
+
+                            // The subscribers are notified only after the finalized blocks have
+                            // been queued for insertion in the database, so that they can access
+                            // their storage.
+                            self.notify_subscribers(Notification::Finalized {
+                                hash: new_finalized_hash,
+                                scale_encoded_header: new_finalized_scale_encoded_header,
+                                best_block_hash: self.sync.best_block_hash(),
+                            });
                             continue;
                         }
                         (sync_out, all::FinalityProofVerifyOutcome::GrandpaCommitPending) => {
@@ -1355,6 +2897,17 @@ async fn database_blocks(
                         .unwrap()
                         .storage_top_trie_changes
                         .diff_iter_unordered(),
+                    block
+                        .full
+                        .as_ref()
+                        .unwrap()
+                        .storage_child_tries_changes
+                        .iter()
+                        .flat_map(|(child_trie, changes)| {
+                            changes
+                                .diff_iter_unordered()
+                                .map(move |(key, value)| (&child_trie[..], key, value))
+                        }),
                 );
 
                 match result {
@@ -15,9 +15,24 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use futures::{channel::oneshot, prelude::*};
-use smoldot::json_rpc::{self, methods, websocket_server};
-use std::{io, net::SocketAddr};
+use crate::run::{consensus_service, database_thread, network_service};
+
+use core::num::NonZeroU32;
+use futures::{
+    channel::{mpsc, oneshot},
+    lock::Mutex,
+    prelude::*,
+    stream::FuturesUnordered,
+};
+use smoldot::{
+    database::full_sqlite,
+    executor::{self, host, read_only_runtime_host},
+    header,
+    informant::HashDisplay,
+    json_rpc::{self, methods, requests_subscriptions, websocket_server},
+    libp2p::{multiaddr::Multiaddr, peer_id::PeerId},
+};
+use std::{collections::HashMap, io, iter, net::SocketAddr, sync::Arc};
 use tracing::Instrument as _;
 
 /// Configuration for a [`JsonRpcService`].
@@ -27,6 +42,41 @@ pub struct Config<'a> {
 
     /// Where to bind the WebSocket server.
     pub bind_address: SocketAddr,
+
+    /// Database to read the blocks and storage of the chain from.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Service that synchronizes the chain. Used to report the health of the node and to feed
+    /// the subscriptions.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Access to the network, and index of the chain within the network service.
+    pub network_service: (Arc<network_service::NetworkService>, usize),
+
+    /// Number of bytes of the block number in the headers of the chain.
+    pub block_number_bytes: usize,
+
+    /// Name of the chain, as found in the chain specification.
+    pub chain_name: String,
+
+    /// Type of the chain, as found in the chain specification.
+    pub chain_type: String,
+
+    /// JSON-encoded properties of the chain, as found in the chain specification.
+    pub chain_properties_json: String,
+
+    /// Identity of the local node on the peer-to-peer network.
+    pub local_peer_id: PeerId,
+
+    /// Addresses the peer-to-peer networking is listening on.
+    pub listen_addresses: Vec<Multiaddr>,
+
+    /// Maximum number of JSON-RPC requests of each client that can be waiting to be answered.
+    /// Any additional request will be immediately rejected.
+    ///
+    /// This parameter is necessary in order to prevent clients from using up too much memory
+    /// within the node.
+    pub max_pending_requests_per_client: NonZeroU32,
 }
 
 /// Running JSON-RPC service. Holds a server open for as long as it is alive.
@@ -60,9 +110,58 @@ impl JsonRpcService {
 
         let (_server_keep_alive, client_still_alive) = oneshot::channel();
 
+        let requests_subscriptions = Arc::new(requests_subscriptions::RequestsSubscriptions::new(
+            requests_subscriptions::Config {
+                max_clients: 64,
+                max_requests_per_client: config.max_pending_requests_per_client,
+                max_subscriptions_per_client: 1024,
+            },
+        ));
+
+        let (new_child_tasks_tx, new_child_tasks_rx) = mpsc::unbounded();
+
+        let requests_handler = Arc::new(RequestsHandler {
+            requests_subscriptions: requests_subscriptions.clone(),
+            new_child_tasks_tx,
+            database: config.database,
+            consensus_service: config.consensus_service,
+            network_service: config.network_service,
+            block_number_bytes: config.block_number_bytes,
+            chain_name: config.chain_name,
+            chain_type: config.chain_type,
+            chain_properties_json: config.chain_properties_json,
+            local_peer_id: config.local_peer_id.to_base58(),
+            listen_addresses: config
+                .listen_addresses
+                .iter()
+                .map(|addr| addr.to_string())
+                .collect(),
+            runtime_cache: Mutex::new(None),
+            subscriptions: Mutex::new(HashMap::new()),
+        });
+
+        // A fixed number of tasks are dedicated to pulling requests from the
+        // `requests_subscriptions` and answering them.
+        let tasks = (0..16)
+            .map(|_| {
+                let requests_handler = requests_handler.clone();
+                async move {
+                    loop {
+                        requests_handler.handle_request().await;
+                    }
+                }
+                .boxed()
+            })
+            .collect();
+
         let background = JsonRpcBackground {
             server,
             client_still_alive: client_still_alive.fuse(),
+            requests_subscriptions,
+            responses: FuturesUnordered::new(),
+            requests_handler,
+            tasks,
+            new_child_tasks_rx,
         };
 
         (config.tasks_executor)(
@@ -90,10 +189,54 @@ pub enum InitError {
 
 struct JsonRpcBackground {
     /// State machine of the WebSocket server. Holds the TCP socket.
-    server: websocket_server::WsServer<SocketAddr>,
+    server: websocket_server::WsServer<Connection>,
 
     /// As long as this channel is pending, the frontend of the JSON-RPC server is still alive.
     client_still_alive: future::Fuse<oneshot::Receiver<()>>,
+
+    /// State machine holding all the clients, requests, and subscriptions.
+    ///
+    /// Only requests that are valid JSON-RPC calls are inserted into the state machine.
+    requests_subscriptions: Arc<requests_subscriptions::RequestsSubscriptions>,
+
+    /// For each connection, a future that yields the next response or notification to send to
+    /// this connection. The future is aborted when the connection is closed.
+    responses: FuturesUnordered<
+        future::Abortable<
+            future::BoxFuture<
+                'static,
+                (
+                    websocket_server::ConnectionId,
+                    requests_subscriptions::ClientId,
+                    String,
+                ),
+            >,
+        >,
+    >,
+
+    /// State shared between all the requests being answered.
+    requests_handler: Arc<RequestsHandler>,
+
+    /// Tasks that answer requests and tasks dedicated to subscriptions. These tasks never end
+    /// except when aborted.
+    tasks: FuturesUnordered<future::BoxFuture<'static, ()>>,
+
+    /// Whenever a task is received on this channel, it is pushed to
+    /// [`JsonRpcBackground::tasks`].
+    new_child_tasks_rx: mpsc::UnboundedReceiver<future::BoxFuture<'static, ()>>,
+}
+
+/// User data associated to each connection of the WebSocket server.
+struct Connection {
+    /// Address of the remote, as provided by the operating system.
+    address: SocketAddr,
+
+    /// Identifier of the connection within [`JsonRpcBackground::requests_subscriptions`].
+    client_id: requests_subscriptions::ClientId,
+
+    /// Aborts the future within [`JsonRpcBackground::responses`] corresponding to this
+    /// connection.
+    responses_abort: future::AbortHandle,
 }
 
 impl JsonRpcBackground {
@@ -101,50 +244,1660 @@ impl JsonRpcBackground {
         loop {
             let event = futures::select! {
                 _ = &mut self.client_still_alive => return,
+                () = self.tasks.select_next_some() => continue,
+                task = self.new_child_tasks_rx.select_next_some() => {
+                    self.tasks.push(task);
+                    continue;
+                },
+                response = self.responses.select_next_some() => {
+                    // Responses whose future has been aborted are simply ignored.
+                    if let Ok((connection_id, client_id, response)) = response {
+                        self.server.queue_send(connection_id, response);
+                        self.push_next_response(connection_id, client_id);
+                    }
+                    continue;
+                },
                 event = self.server.next_event().fuse() => event,
             };
 
-            let (connection_id, message) = match event {
+            let (connection_id, client_id, message) = match event {
                 websocket_server::Event::ConnectionOpen { address, .. } => {
+                    let client_id = match self.requests_subscriptions.add_client().await {
+                        Ok(c) => c,
+                        Err(requests_subscriptions::AddClientError::LimitReached) => {
+                            tracing::debug!(%address, "incoming-connection-rejected");
+                            self.server.reject();
+                            continue;
+                        }
+                    };
+
                     tracing::debug!(%address, "incoming-connection");
-                    self.server.accept(address);
+                    let (responses_abort, _) = future::AbortHandle::new_pair();
+                    let connection_id = self.server.accept(Connection {
+                        address,
+                        client_id: client_id.clone(),
+                        responses_abort,
+                    });
+                    self.push_next_response(connection_id, client_id);
                     continue;
                 }
-                websocket_server::Event::ConnectionError {
-                    user_data: address, ..
-                } => {
-                    tracing::debug!(%address, "connection-closed");
+                websocket_server::Event::ConnectionError { user_data, .. } => {
+                    tracing::debug!(address = %user_data.address, "connection-closed");
+                    self.remove_connection(user_data).await;
                     continue;
                 }
                 websocket_server::Event::TextFrame {
                     connection_id,
                     message,
-                    ..
-                } => (connection_id, message),
+                    user_data,
+                } => (connection_id, user_data.client_id.clone(), message),
             };
 
-            let (request_id, _method) = match methods::parse_json_call(&message) {
-                Ok(v) => v,
+            match methods::parse_json_call(&message) {
+                Ok((request_id, method)) => {
+                    tracing::debug!(%request_id, ?method, "request");
+                }
                 Err(error) => {
                     tracing::debug!(%error, %message, "bad-request");
-                    self.server.close(connection_id);
+                    let user_data = self.server.close(connection_id);
+                    self.remove_connection(user_data).await;
                     continue;
                 }
-            };
+            }
+
+            if let Err(error) = self
+                .requests_subscriptions
+                .try_queue_client_request(&client_id, message)
+            {
+                // The request has been successfully parsed above.
+                let (request_id, _) = methods::parse_json_call(&error.request).unwrap();
+                self.server.queue_send(
+                    connection_id,
+                    json_rpc::parse::build_error_response(
+                        request_id,
+                        json_rpc::parse::ErrorResponse::ServerError(-32000, "Too busy"),
+                        None,
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Pushes to [`JsonRpcBackground::responses`] a future that yields the next response to send
+    /// to the given connection.
+    fn push_next_response(
+        &mut self,
+        connection_id: websocket_server::ConnectionId,
+        client_id: requests_subscriptions::ClientId,
+    ) {
+        let (abort_handle, abort_registration) = future::AbortHandle::new_pair();
+        self.server[connection_id].responses_abort = abort_handle;
+
+        let requests_subscriptions = self.requests_subscriptions.clone();
+        self.responses.push(future::Abortable::new(
+            async move {
+                let response = requests_subscriptions.next_response(&client_id).await;
+                (connection_id, client_id, response)
+            }
+            .boxed(),
+            abort_registration,
+        ));
+    }
+
+    /// Cleans up the state associated to a connection that has been closed.
+    async fn remove_connection(&mut self, connection: Connection) {
+        // `next_response` never returns if the client has been removed, and must thus be
+        // aborted.
+        connection.responses_abort.abort();
+
+        if let Some((_, subscriptions)) = self
+            .requests_subscriptions
+            .remove_client(&connection.client_id)
+            .await
+        {
+            self.requests_handler
+                .abort_subscriptions(&subscriptions)
+                .await;
+        }
+    }
+}
+
+/// State necessary to answer JSON-RPC requests.
+struct RequestsHandler {
+    /// See [`JsonRpcBackground::requests_subscriptions`].
+    requests_subscriptions: Arc<requests_subscriptions::RequestsSubscriptions>,
+
+    /// Whenever a task is sent on this channel, it is run to completion in the background.
+    new_child_tasks_tx: mpsc::UnboundedSender<future::BoxFuture<'static, ()>>,
+
+    /// See [`Config::database`].
+    database: Arc<database_thread::DatabaseThread>,
+
+    /// See [`Config::consensus_service`].
+    consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// See [`Config::network_service`].
+    network_service: (Arc<network_service::NetworkService>, usize),
+
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+
+    /// See [`Config::chain_name`].
+    chain_name: String,
+
+    /// See [`Config::chain_type`].
+    chain_type: String,
+
+    /// See [`Config::chain_properties_json`].
+    chain_properties_json: String,
+
+    /// [`Config::local_peer_id`] encoded in base58.
+    local_peer_id: String,
+
+    /// [`Config::listen_addresses`] turned into strings.
+    listen_addresses: Vec<String>,
+
+    /// Runtime that has been used in the most recent runtime call, if any.
+    ///
+    /// Compiling a runtime is expensive, and consecutive calls are very likely to target the
+    /// same runtime. The runtime is extracted from the cache for the duration of a call, meaning
+    /// that parallel calls compile their own runtime.
+    runtime_cache: Mutex<Option<CachedRuntime>>,
+
+    /// For each active subscription (the key), an abort handle and the id of the subscription in
+    /// the state machine. The abort handle is linked to the task dedicated to handling that
+    /// subscription.
+    ///
+    /// Since the server is shared between multiple clients, the JSON-RPC subscription
+    /// identifiers are randomly generated in order to prevent clients from guessing the
+    /// identifiers of the subscriptions of other clients.
+    subscriptions: Mutex<
+        HashMap<
+            (String, SubscriptionTy),
+            (future::AbortHandle, requests_subscriptions::SubscriptionId),
+        >,
+    >,
+}
+
+/// Maximum number of keys that `state_getKeys` and `state_getKeysPaged` return. Matches the
+/// limit enforced by Substrate.
+const MAX_STORAGE_KEYS: usize = 1000;
 
-            tracing::debug!(%request_id, method = ?_method, "request");
+/// List of the JSON-RPC methods that [`RequestsHandler`] answers, as reported by `rpc_methods`.
+///
+/// Must be kept in sync with [`RequestsHandler::handle_request`] and [`RequestsHandler::answer`].
+/// Methods that aren't in this list are answered with an error.
+const SUPPORTED_METHODS: &[&str] = &[
+    "chain_getBlock",
+    "chain_getBlockHash",
+    "chain_getFinalizedHead",
+    "chain_getHeader",
+    "chain_subscribeAllHeads",
+    "chain_subscribeFinalizedHeads",
+    "chain_subscribeNewHeads",
+    "chain_unsubscribeAllHeads",
+    "chain_unsubscribeFinalizedHeads",
+    "chain_unsubscribeNewHeads",
+    "rpc_methods",
+    "state_call",
+    "state_getKeys",
+    "state_getKeysPaged",
+    "state_getMetadata",
+    "state_getRuntimeVersion",
+    "state_getStorage",
+    "state_queryStorageAt",
+    "state_subscribeRuntimeVersion",
+    "state_subscribeStorage",
+    "state_unsubscribeRuntimeVersion",
+    "state_unsubscribeStorage",
+    "system_chain",
+    "system_chainType",
+    "system_health",
+    "system_localListenAddresses",
+    "system_localPeerId",
+    "system_name",
+    "system_nodeRoles",
+    "system_properties",
+    "system_version",
+];
 
-            self.server.queue_send(
-                connection_id,
-                json_rpc::parse::build_error_response(
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum SubscriptionTy {
+    AllHeads,
+    NewHeads,
+    FinalizedHeads,
+    RuntimeVersion,
+    Storage,
+}
+
+/// See [`RequestsHandler::runtime_cache`].
+struct CachedRuntime {
+    /// Value of the `:code` storage item the runtime has been compiled from.
+    code: Vec<u8>,
+    /// Value of the `:heappages` storage item the runtime has been compiled with.
+    heap_pages: Option<Vec<u8>>,
+    /// Compiled runtime.
+    runtime: host::HostVmPrototype,
+}
+
+impl RequestsHandler {
+    /// Pulls one request from [`RequestsHandler::requests_subscriptions`], and processes it.
+    async fn handle_request(self: &Arc<Self>) {
+        let (json_rpc_request, state_machine_request_id) =
+            self.requests_subscriptions.next_request().await;
+
+        // Requests are validated before being inserted in the state machine.
+        let (request_id, call) = methods::parse_json_call(&json_rpc_request).unwrap();
+
+        match call {
+            methods::MethodCall::chain_subscribeAllHeads {} => {
+                self.chain_subscribe_heads(
                     request_id,
-                    json_rpc::parse::ErrorResponse::ServerError(
-                        -32000,
-                        "Not implemented in smoldot yet",
-                    ),
-                    None,
+                    &state_machine_request_id,
+                    SubscriptionTy::AllHeads,
+                )
+                .await;
+            }
+            methods::MethodCall::chain_subscribeFinalizedHeads {} => {
+                self.chain_subscribe_heads(
+                    request_id,
+                    &state_machine_request_id,
+                    SubscriptionTy::FinalizedHeads,
+                )
+                .await;
+            }
+            methods::MethodCall::chain_subscribeNewHeads {} => {
+                self.chain_subscribe_heads(
+                    request_id,
+                    &state_machine_request_id,
+                    SubscriptionTy::NewHeads,
+                )
+                .await;
+            }
+            methods::MethodCall::state_subscribeRuntimeVersion {} => {
+                self.state_subscribe_runtime_version(request_id, &state_machine_request_id)
+                    .await;
+            }
+            methods::MethodCall::state_subscribeStorage { list } => {
+                self.state_subscribe_storage(request_id, &state_machine_request_id, list)
+                    .await;
+            }
+            call => {
+                let response = self.answer(request_id, call).await;
+                self.requests_subscriptions
+                    .respond(&state_machine_request_id, response)
+                    .await;
+            }
+        }
+    }
+
+    /// Analyzes the given JSON-RPC call and returns the JSON-encoded response.
+    async fn answer(&self, request_id: &str, method: methods::MethodCall<'_>) -> String {
+        match method {
+            methods::MethodCall::chain_getBlock { hash } => {
+                self.chain_get_block(request_id, hash).await
+            }
+            methods::MethodCall::chain_getBlockHash { height } => {
+                self.chain_get_block_hash(request_id, height).await
+            }
+            methods::MethodCall::chain_getFinalizedHead {} => {
+                self.chain_get_finalized_head(request_id).await
+            }
+            methods::MethodCall::chain_getHeader { hash } => {
+                self.chain_get_header(request_id, hash).await
+            }
+            methods::MethodCall::chain_unsubscribeAllHeads { subscription } => {
+                let success = self
+                    .unsubscribe(&subscription, SubscriptionTy::AllHeads)
+                    .await;
+                methods::Response::chain_unsubscribeAllHeads(success).to_json_response(request_id)
+            }
+            methods::MethodCall::chain_unsubscribeFinalizedHeads { subscription } => {
+                let success = self
+                    .unsubscribe(&subscription, SubscriptionTy::FinalizedHeads)
+                    .await;
+                methods::Response::chain_unsubscribeFinalizedHeads(success)
+                    .to_json_response(request_id)
+            }
+            methods::MethodCall::chain_unsubscribeNewHeads { subscription } => {
+                let success = self
+                    .unsubscribe(&subscription, SubscriptionTy::NewHeads)
+                    .await;
+                methods::Response::chain_unsubscribeNewHeads(success).to_json_response(request_id)
+            }
+            methods::MethodCall::rpc_methods {} => {
+                methods::Response::rpc_methods(methods::RpcMethods {
+                    methods: SUPPORTED_METHODS.iter().map(|n| (*n).into()).collect(),
+                })
+                .to_json_response(request_id)
+            }
+            methods::MethodCall::state_call {
+                name,
+                parameters,
+                hash,
+            } => self.state_call(request_id, &name, parameters, hash).await,
+            methods::MethodCall::state_getKeys { prefix, hash } => {
+                self.state_get_keys(request_id, prefix, hash).await
+            }
+            methods::MethodCall::state_getKeysPaged {
+                prefix,
+                count,
+                start_key,
+                hash,
+            } => {
+                self.state_get_keys_paged(request_id, prefix, count, start_key, hash)
+                    .await
+            }
+            methods::MethodCall::state_getMetadata { hash } => {
+                self.state_get_metadata(request_id, hash).await
+            }
+            methods::MethodCall::state_getRuntimeVersion { at } => {
+                self.state_get_runtime_version(request_id, at).await
+            }
+            methods::MethodCall::state_getStorage { key, hash } => {
+                self.state_get_storage(request_id, key, hash).await
+            }
+            methods::MethodCall::state_queryStorageAt { keys, at } => {
+                self.state_query_storage_at(request_id, keys, at).await
+            }
+            methods::MethodCall::state_unsubscribeRuntimeVersion { subscription } => {
+                let success = self
+                    .unsubscribe(&subscription, SubscriptionTy::RuntimeVersion)
+                    .await;
+                methods::Response::state_unsubscribeRuntimeVersion(success)
+                    .to_json_response(request_id)
+            }
+            methods::MethodCall::state_unsubscribeStorage { subscription } => {
+                let success = self
+                    .unsubscribe(&subscription, SubscriptionTy::Storage)
+                    .await;
+                methods::Response::state_unsubscribeStorage(success).to_json_response(request_id)
+            }
+            methods::MethodCall::system_chain {} => {
+                methods::Response::system_chain((&self.chain_name).into())
+                    .to_json_response(request_id)
+            }
+            methods::MethodCall::system_chainType {} => {
+                methods::Response::system_chainType((&self.chain_type).into())
+                    .to_json_response(request_id)
+            }
+            methods::MethodCall::system_health {} => self.system_health(request_id).await,
+            methods::MethodCall::system_localListenAddresses {} => {
+                methods::Response::system_localListenAddresses(self.listen_addresses.clone())
+                    .to_json_response(request_id)
+            }
+            methods::MethodCall::system_localPeerId {} => {
+                methods::Response::system_localPeerId((&self.local_peer_id).into())
+                    .to_json_response(request_id)
+            }
+            methods::MethodCall::system_name {} => {
+                methods::Response::system_name(env!("CARGO_PKG_NAME").into())
+                    .to_json_response(request_id)
+            }
+            methods::MethodCall::system_nodeRoles {} => {
+                methods::Response::system_nodeRoles((&[methods::NodeRole::Full][..]).into())
+                    .to_json_response(request_id)
+            }
+            methods::MethodCall::system_properties {} => {
+                match serde_json::from_str(&self.chain_properties_json) {
+                    Ok(properties) => methods::Response::system_properties(properties)
+                        .to_json_response(request_id),
+                    Err(error) => internal_error_response(request_id, &error),
+                }
+            }
+            methods::MethodCall::system_version {} => {
+                methods::Response::system_version(env!("CARGO_PKG_VERSION").into())
+                    .to_json_response(request_id)
+            }
+            _ => json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::ServerError(
+                    -32000,
+                    "Not implemented in smoldot yet",
                 ),
-            );
+                None,
+            ),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::chain_getBlock`].
+    async fn chain_get_block(
+        &self,
+        request_id: &str,
+        hash: Option<methods::HashHexString>,
+    ) -> String {
+        let block_number_bytes = self.block_number_bytes;
+        let result = self
+            .database
+            .with_database(move |database| {
+                let hash = match hash {
+                    Some(h) => h.0,
+                    None => database.best_block_hash()?,
+                };
+                let header = match database.block_scale_encoded_header(&hash)? {
+                    Some(h) => h,
+                    None => return Ok(None),
+                };
+                let extrinsics = match database.block_extrinsics(&hash)? {
+                    Some(e) => e.collect::<Vec<_>>(),
+                    None => return Ok(None),
+                };
+                Ok::<_, full_sqlite::AccessError>(Some((header, extrinsics)))
+            })
+            .await;
+
+        match result {
+            Ok(Some((header, extrinsics))) => {
+                let header =
+                    match methods::Header::from_scale_encoded_header(&header, block_number_bytes) {
+                        Ok(h) => h,
+                        Err(error) => return internal_error_response(request_id, &error),
+                    };
+
+                methods::Response::chain_getBlock(methods::Block {
+                    extrinsics: extrinsics.into_iter().map(methods::HexString).collect(),
+                    header,
+                    // Justifications aren't stored in the database.
+                    justifications: None,
+                })
+                .to_json_response(request_id)
+            }
+            Ok(None) => json_rpc::parse::build_success_response(request_id, "null"),
+            Err(error) => internal_error_response(request_id, &error),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::chain_getBlockHash`].
+    async fn chain_get_block_hash(&self, request_id: &str, height: Option<u64>) -> String {
+        let block_number_bytes = self.block_number_bytes;
+        let result = self
+            .database
+            .with_database(move |database| {
+                let best_hash = database.best_block_hash()?;
+                let height = match height {
+                    Some(h) => h,
+                    None => return Ok(Some(best_hash)),
+                };
+
+                let mut candidates = database.block_hash_by_number(height)?;
+                if candidates.len() <= 1 {
+                    return Ok(candidates.next());
+                }
+
+                // If there exists multiple blocks with this number, find the one that is an
+                // ancestor of the best block by walking the chain backwards.
+                let mut current = best_hash;
+                loop {
+                    let header = match database.block_scale_encoded_header(&current)? {
+                        Some(h) => h,
+                        None => return Ok(None),
+                    };
+                    let decoded = match header::decode(&header, block_number_bytes) {
+                        Ok(h) => h,
+                        Err(_) => return Ok(None),
+                    };
+                    if decoded.number == height {
+                        return Ok(Some(current));
+                    }
+                    if decoded.number < height {
+                        return Ok(None);
+                    }
+                    current = *decoded.parent_hash;
+                }
+            })
+            .await;
+
+        match result {
+            Ok(Some(hash)) => methods::Response::chain_getBlockHash(methods::HashHexString(hash))
+                .to_json_response(request_id),
+            Ok(None) => json_rpc::parse::build_success_response(request_id, "null"),
+            Err::<_, full_sqlite::AccessError>(error) => {
+                internal_error_response(request_id, &error)
+            }
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::chain_getFinalizedHead`].
+    async fn chain_get_finalized_head(&self, request_id: &str) -> String {
+        match self
+            .database
+            .with_database(|database| database.finalized_block_hash())
+            .await
+        {
+            Ok(hash) => methods::Response::chain_getFinalizedHead(methods::HashHexString(hash))
+                .to_json_response(request_id),
+            Err(error) => internal_error_response(request_id, &error),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::chain_getHeader`].
+    async fn chain_get_header(
+        &self,
+        request_id: &str,
+        hash: Option<methods::HashHexString>,
+    ) -> String {
+        let result = self
+            .database
+            .with_database(move |database| {
+                let hash = match hash {
+                    Some(h) => h.0,
+                    None => database.best_block_hash()?,
+                };
+                database.block_scale_encoded_header(&hash)
+            })
+            .await;
+
+        match result {
+            Ok(Some(header)) => {
+                match methods::Header::from_scale_encoded_header(&header, self.block_number_bytes) {
+                    Ok(decoded) => {
+                        methods::Response::chain_getHeader(decoded).to_json_response(request_id)
+                    }
+                    Err(error) => internal_error_response(request_id, &error),
+                }
+            }
+            Ok(None) => json_rpc::parse::build_success_response(request_id, "null"),
+            Err(error) => internal_error_response(request_id, &error),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_call`].
+    async fn state_call(
+        &self,
+        request_id: &str,
+        function_to_call: &str,
+        parameters: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) -> String {
+        let block_hash = match self.block_hash_or_best(hash).await {
+            Ok(h) => h,
+            Err(error) => return internal_error_response(request_id, &error),
+        };
+
+        match self
+            .runtime_call(&block_hash, function_to_call, &parameters.0)
+            .await
+        {
+            Ok(output) => methods::Response::state_call(methods::HexString(output))
+                .to_json_response(request_id),
+            Err(error) => internal_error_response(request_id, &error),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_getKeys`].
+    async fn state_get_keys(
+        &self,
+        request_id: &str,
+        prefix: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) -> String {
+        let block_hash = match self.block_hash_or_best(hash).await {
+            Ok(h) => h,
+            Err(error) => return internal_error_response(request_id, &error),
+        };
+
+        // This method doesn't have any pagination. In order to not enumerate a potentially very
+        // large number of keys, one more key than the limit is fetched in order to detect when
+        // the limit is exceeded.
+        let result = self
+            .database
+            .with_database(move |database| {
+                storage_keys_paged(database, &block_hash, &prefix.0, None, MAX_STORAGE_KEYS + 1)
+            })
+            .await;
+
+        match result {
+            Ok(keys) if keys.len() > MAX_STORAGE_KEYS => json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::ServerError(
+                    -32000,
+                    "Too many keys with this prefix; use state_getKeysPaged instead",
+                ),
+                None,
+            ),
+            Ok(keys) => {
+                methods::Response::state_getKeys(keys.into_iter().map(methods::HexString).collect())
+                    .to_json_response(request_id)
+            }
+            Err(error) => internal_error_response(request_id, &error),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_getKeysPaged`].
+    async fn state_get_keys_paged(
+        &self,
+        request_id: &str,
+        prefix: Option<methods::HexString>,
+        count: u32,
+        start_key: Option<methods::HexString>,
+        hash: Option<methods::HashHexString>,
+    ) -> String {
+        let count = match usize::try_from(count) {
+            Ok(count) if count <= MAX_STORAGE_KEYS => count,
+            _ => {
+                return json_rpc::parse::build_error_response(
+                    request_id,
+                    json_rpc::parse::ErrorResponse::InvalidParams,
+                    None,
+                )
+            }
+        };
+
+        let block_hash = match self.block_hash_or_best(hash).await {
+            Ok(h) => h,
+            Err(error) => return internal_error_response(request_id, &error),
+        };
+
+        let prefix = prefix.map_or(Vec::new(), |p| p.0);
+
+        let result = self
+            .database
+            .with_database(move |database| {
+                storage_keys_paged(
+                    database,
+                    &block_hash,
+                    &prefix,
+                    start_key.map(|k| k.0),
+                    count,
+                )
+            })
+            .await;
+
+        match result {
+            Ok(keys) => methods::Response::state_getKeysPaged(
+                keys.into_iter().map(methods::HexString).collect(),
+            )
+            .to_json_response(request_id),
+            Err(error) => internal_error_response(request_id, &error),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_getMetadata`].
+    async fn state_get_metadata(
+        &self,
+        request_id: &str,
+        hash: Option<methods::HashHexString>,
+    ) -> String {
+        let block_hash = match self.block_hash_or_best(hash).await {
+            Ok(h) => h,
+            Err(error) => return internal_error_response(request_id, &error),
+        };
+
+        match self
+            .runtime_call(&block_hash, "Metadata_metadata", &[])
+            .await
+        {
+            Ok(output) => match smoldot::remove_metadata_length_prefix(&output) {
+                Ok(metadata) => {
+                    methods::Response::state_getMetadata(methods::HexString(metadata.to_vec()))
+                        .to_json_response(request_id)
+                }
+                Err(()) => internal_error_response(request_id, &"Invalid metadata format"),
+            },
+            Err(error) => internal_error_response(request_id, &error),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_getRuntimeVersion`].
+    async fn state_get_runtime_version(
+        &self,
+        request_id: &str,
+        at: Option<methods::HashHexString>,
+    ) -> String {
+        let block_hash = match self.block_hash_or_best(at).await {
+            Ok(h) => h,
+            Err(error) => return internal_error_response(request_id, &error),
+        };
+
+        let runtime = match self.runtime(&block_hash).await {
+            Ok(r) => r,
+            Err(error) => return internal_error_response(request_id, &error),
+        };
+
+        let runtime_spec = runtime.runtime.runtime_version().decode();
+        let response = methods::Response::state_getRuntimeVersion(methods::RuntimeVersion {
+            spec_name: runtime_spec.spec_name.into(),
+            impl_name: runtime_spec.impl_name.into(),
+            authoring_version: u64::from(runtime_spec.authoring_version),
+            spec_version: u64::from(runtime_spec.spec_version),
+            impl_version: u64::from(runtime_spec.impl_version),
+            transaction_version: runtime_spec.transaction_version.map(u64::from),
+            state_version: runtime_spec.state_version.map(u64::from),
+            apis: runtime_spec
+                .apis
+                .map(|api| (methods::HexString(api.name_hash.to_vec()), api.version))
+                .collect(),
+        })
+        .to_json_response(request_id);
+
+        *self.runtime_cache.lock().await = Some(runtime);
+        response
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_getStorage`].
+    async fn state_get_storage(
+        &self,
+        request_id: &str,
+        key: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) -> String {
+        let block_hash = match self.block_hash_or_best(hash).await {
+            Ok(h) => h,
+            Err(error) => return internal_error_response(request_id, &error),
+        };
+
+        match self
+            .database
+            .with_database(move |database| database.block_storage_top_trie_get(&block_hash, &key.0))
+            .await
+        {
+            Ok(Some(value)) => methods::Response::state_getStorage(methods::HexString(value))
+                .to_json_response(request_id),
+            Ok(None) => json_rpc::parse::build_success_response(request_id, "null"),
+            Err(error) => internal_error_response(request_id, &error),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_queryStorageAt`].
+    async fn state_query_storage_at(
+        &self,
+        request_id: &str,
+        keys: Vec<methods::HexString>,
+        at: Option<methods::HashHexString>,
+    ) -> String {
+        let block_hash = match self.block_hash_or_best(at).await {
+            Ok(h) => h,
+            Err(error) => return internal_error_response(request_id, &error),
+        };
+
+        let result = self
+            .database
+            .with_database(move |database| {
+                let mut changes = Vec::with_capacity(keys.len());
+                for key in keys {
+                    let value = database.block_storage_top_trie_get(&block_hash, &key.0)?;
+                    changes.push((key, value.map(methods::HexString)));
+                }
+                Ok::<_, full_sqlite::StorageAccessError>(changes)
+            })
+            .await;
+
+        match result {
+            Ok(changes) => {
+                methods::Response::state_queryStorageAt(vec![methods::StorageChangeSet {
+                    block: methods::HashHexString(block_hash),
+                    changes,
+                }])
+                .to_json_response(request_id)
+            }
+            Err(error) => internal_error_response(request_id, &error),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::system_health`].
+    async fn system_health(&self, request_id: &str) -> String {
+        let sync_state = self.consensus_service.sync_state().await;
+        let (network_service, chain_index) = &self.network_service;
+
+        methods::Response::system_health(methods::SystemHealth {
+            is_syncing: !sync_state.is_near_head_of_chain,
+            peers: u64::try_from(network_service.num_peers(*chain_index).await)
+                .unwrap_or(u64::max_value()),
+            // The full node always tries to connect to other nodes.
+            should_have_peers: true,
+        })
+        .to_json_response(request_id)
+    }
+
+    /// Returns the hash passed as parameter, or the hash of the current best block of the
+    /// database if `None`.
+    async fn block_hash_or_best(
+        &self,
+        hash: Option<methods::HashHexString>,
+    ) -> Result<[u8; 32], full_sqlite::AccessError> {
+        match hash {
+            Some(h) => Ok(h.0),
+            None => {
+                self.database
+                    .with_database(|database| database.best_block_hash())
+                    .await
+            }
+        }
+    }
+
+    /// Returns the runtime of the given block, either by extracting it from
+    /// [`RequestsHandler::runtime_cache`] or by compiling it.
+    ///
+    /// The caller is encouraged to put the runtime back in the cache after it's done using it.
+    async fn runtime(&self, block_hash: &[u8; 32]) -> Result<CachedRuntime, RuntimeCallError> {
+        let (code, heap_pages) = {
+            let block_hash = *block_hash;
+            self.database
+                .with_database(move |database| {
+                    let code = database.block_storage_top_trie_get(&block_hash, b":code")?;
+                    let heap_pages =
+                        database.block_storage_top_trie_get(&block_hash, b":heappages")?;
+                    Ok::<_, full_sqlite::StorageAccessError>((code, heap_pages))
+                })
+                .await
+                .map_err(RuntimeCallError::Storage)?
+        };
+
+        self.runtime_from_code(code, heap_pages).await
+    }
+
+    /// Returns the runtime corresponding to the given `:code` and `:heappages`, either by
+    /// extracting it from [`RequestsHandler::runtime_cache`] or by compiling it.
+    ///
+    /// The caller is encouraged to put the runtime back in the cache after it's done using it.
+    async fn runtime_from_code(
+        &self,
+        code: Option<Vec<u8>>,
+        heap_pages: Option<Vec<u8>>,
+    ) -> Result<CachedRuntime, RuntimeCallError> {
+        let code = code.ok_or(RuntimeCallError::NoCode)?;
+
+        if let Some(cached) = self.runtime_cache.lock().await.take() {
+            if cached.code == code && cached.heap_pages == heap_pages {
+                return Ok(cached);
+            }
         }
+
+        let runtime = host::HostVmPrototype::new(host::Config {
+            module: &code,
+            heap_pages: executor::storage_heap_pages_to_value(heap_pages.as_deref())
+                .map_err(RuntimeCallError::InvalidHeapPages)?,
+            exec_hint: executor::vm::ExecHint::CompileAheadOfTime,
+            allow_unresolved_imports: true,
+        })
+        .map_err(RuntimeCallError::InvalidRuntime)?;
+
+        Ok(CachedRuntime {
+            code,
+            heap_pages,
+            runtime,
+        })
     }
+
+    /// Performs a runtime call against the storage of the given block and returns the output of
+    /// the call.
+    async fn runtime_call(
+        &self,
+        block_hash: &[u8; 32],
+        function_to_call: &str,
+        parameter: &[u8],
+    ) -> Result<Vec<u8>, RuntimeCallError> {
+        let block_number_bytes = self.block_number_bytes;
+        let state_root = {
+            let block_hash = *block_hash;
+            let header = self
+                .database
+                .with_database(move |database| database.block_scale_encoded_header(&block_hash))
+                .await
+                .map_err(|err| RuntimeCallError::Storage(err.into()))?
+                .ok_or(RuntimeCallError::UnknownBlock)?;
+            *header::decode(&header, block_number_bytes)
+                .map_err(RuntimeCallError::InvalidHeader)?
+                .state_root
+        };
+
+        let CachedRuntime {
+            code,
+            heap_pages,
+            runtime,
+        } = self.runtime(block_hash).await?;
+
+        let mut call = match read_only_runtime_host::run(read_only_runtime_host::Config {
+            virtual_machine: runtime,
+            function_to_call,
+            parameter: iter::once(parameter),
+        }) {
+            Ok(call) => call,
+            Err((error, runtime)) => {
+                *self.runtime_cache.lock().await = Some(CachedRuntime {
+                    code,
+                    heap_pages,
+                    runtime,
+                });
+                return Err(RuntimeCallError::StartError(error));
+            }
+        };
+
+        loop {
+            match call {
+                read_only_runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                    let output = success.virtual_machine.value().as_ref().to_vec();
+                    *self.runtime_cache.lock().await = Some(CachedRuntime {
+                        code,
+                        heap_pages,
+                        runtime: success.virtual_machine.into_prototype(),
+                    });
+                    return Ok(output);
+                }
+                read_only_runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                    *self.runtime_cache.lock().await = Some(CachedRuntime {
+                        code,
+                        heap_pages,
+                        runtime: error.prototype,
+                    });
+                    return Err(RuntimeCallError::Execution(error.detail));
+                }
+                read_only_runtime_host::RuntimeHostVm::StorageGet(get) => {
+                    let key = get.key().as_ref().to_vec();
+                    let block_hash = *block_hash;
+                    let value = self
+                        .database
+                        .with_database(move |database| {
+                            database.block_storage_top_trie_get(&block_hash, &key)
+                        })
+                        .await;
+                    match value {
+                        Ok(value) => call = get.inject_value(value.as_ref().map(iter::once)),
+                        Err(error) => return Err(RuntimeCallError::Storage(error)),
+                    }
+                }
+                read_only_runtime_host::RuntimeHostVm::NextKey(next_key) => {
+                    let key = next_key.key().as_ref().to_vec();
+                    let block_hash = *block_hash;
+                    let next = self
+                        .database
+                        .with_database(move |database| {
+                            database.block_storage_top_trie_next_key(&block_hash, &key)
+                        })
+                        .await;
+                    match next {
+                        Ok(next) => call = next_key.inject_key(next),
+                        Err(error) => return Err(RuntimeCallError::Storage(error)),
+                    }
+                }
+                read_only_runtime_host::RuntimeHostVm::StorageRoot(storage_root) => {
+                    call = storage_root.resume(&state_root);
+                }
+                read_only_runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                    call = sig.verify_and_resume();
+                }
+            }
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::chain_subscribeAllHeads`],
+    /// [`methods::MethodCall::chain_subscribeFinalizedHeads`], or
+    /// [`methods::MethodCall::chain_subscribeNewHeads`], depending on `ty`.
+    async fn chain_subscribe_heads(
+        self: &Arc<Self>,
+        request_id: &str,
+        state_machine_request_id: &requests_subscriptions::RequestId,
+        ty: SubscriptionTy,
+    ) {
+        // `chain_subscribeAllHeads` must report all blocks, while the two other subscriptions
+        // only need to report the latest value.
+        let messages_capacity = if ty == SubscriptionTy::AllHeads { 8 } else { 1 };
+
+        let (subscription_id, state_machine_subscription, abort_registration) = match self
+            .start_subscription(request_id, state_machine_request_id, ty, messages_capacity)
+            .await
+        {
+            Some(v) => v,
+            None => return,
+        };
+
+        let response = match ty {
+            SubscriptionTy::AllHeads => {
+                methods::Response::chain_subscribeAllHeads((&subscription_id).into())
+            }
+            SubscriptionTy::FinalizedHeads => {
+                methods::Response::chain_subscribeFinalizedHeads((&subscription_id).into())
+            }
+            SubscriptionTy::NewHeads => {
+                methods::Response::chain_subscribeNewHeads((&subscription_id).into())
+            }
+            _ => unreachable!(),
+        };
+        self.requests_subscriptions
+            .respond(
+                state_machine_request_id,
+                response.to_json_response(request_id),
+            )
+            .await;
+
+        // Spawn a separate task for the subscription.
+        let task = {
+            let me = self.clone();
+            async move {
+                // The subscription to the consensus service is closed if the notifications aren't
+                // processed quickly enough, in which case we simply subscribe again.
+                loop {
+                    let subscribe_all = me.consensus_service.subscribe_all(32).await;
+
+                    let finalized_block_hash = header::hash_from_scale_encoded_header(
+                        &subscribe_all.finalized_block_scale_encoded_header,
+                    );
+
+                    // Headers of the blocks that descend from the finalized block. Necessary in
+                    // order to report the new best block when it is updated by a finalization.
+                    let mut non_finalized_headers = subscribe_all
+                        .non_finalized_blocks_ancestry_order
+                        .iter()
+                        .map(|block| (block.block_hash, block.scale_encoded_header.clone()))
+                        .collect::<HashMap<_, _>>();
+
+                    let mut current_best = subscribe_all
+                        .non_finalized_blocks_ancestry_order
+                        .iter()
+                        .find(|block| block.is_new_best)
+                        .map_or(finalized_block_hash, |block| block.block_hash);
+
+                    match ty {
+                        SubscriptionTy::FinalizedHeads => {
+                            me.notify_header(
+                                &state_machine_subscription,
+                                &subscription_id,
+                                ty,
+                                &subscribe_all.finalized_block_scale_encoded_header,
+                            )
+                            .await;
+                        }
+                        SubscriptionTy::NewHeads => {
+                            let best_header = non_finalized_headers
+                                .get(&current_best)
+                                .unwrap_or(&subscribe_all.finalized_block_scale_encoded_header);
+                            me.notify_header(
+                                &state_machine_subscription,
+                                &subscription_id,
+                                ty,
+                                best_header,
+                            )
+                            .await;
+                        }
+                        _ => {}
+                    }
+
+                    let mut new_blocks = subscribe_all.new_blocks;
+                    while let Some(notification) = new_blocks.next().await {
+                        match notification {
+                            consensus_service::Notification::Block(block) => {
+                                if ty == SubscriptionTy::AllHeads
+                                    || (ty == SubscriptionTy::NewHeads && block.is_new_best)
+                                {
+                                    me.notify_header(
+                                        &state_machine_subscription,
+                                        &subscription_id,
+                                        ty,
+                                        &block.scale_encoded_header,
+                                    )
+                                    .await;
+                                }
+
+                                if block.is_new_best {
+                                    current_best = block.block_hash;
+                                }
+
+                                non_finalized_headers
+                                    .insert(block.block_hash, block.scale_encoded_header);
+                            }
+                            consensus_service::Notification::Finalized {
+                                hash,
+                                scale_encoded_header,
+                                best_block_hash,
+                            } => {
+                                if ty == SubscriptionTy::FinalizedHeads {
+                                    me.notify_header(
+                                        &state_machine_subscription,
+                                        &subscription_id,
+                                        ty,
+                                        &scale_encoded_header,
+                                    )
+                                    .await;
+                                }
+
+                                if best_block_hash != current_best {
+                                    current_best = best_block_hash;
+
+                                    if ty == SubscriptionTy::NewHeads {
+                                        let best_header = if best_block_hash == hash {
+                                            Some(&scale_encoded_header)
+                                        } else {
+                                            non_finalized_headers.get(&best_block_hash)
+                                        };
+
+                                        if let Some(best_header) = best_header {
+                                            me.notify_header(
+                                                &state_machine_subscription,
+                                                &subscription_id,
+                                                ty,
+                                                best_header,
+                                            )
+                                            .await;
+                                        }
+                                    }
+                                }
+
+                                // Discard the headers of the blocks that no longer descend from
+                                // the finalized block.
+                                if let Ok(finalized) =
+                                    header::decode(&scale_encoded_header, me.block_number_bytes)
+                                {
+                                    let finalized_number = finalized.number;
+                                    non_finalized_headers.retain(|_, header| {
+                                        header::decode(header, me.block_number_bytes)
+                                            .map_or(false, |h| h.number > finalized_number)
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        self.new_child_tasks_tx
+            .unbounded_send(Box::pin(
+                future::Abortable::new(task, abort_registration).map(|_| ()),
+            ))
+            .unwrap();
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_subscribeRuntimeVersion`].
+    ///
+    /// The runtime version is reported against the best block.
+    async fn state_subscribe_runtime_version(
+        self: &Arc<Self>,
+        request_id: &str,
+        state_machine_request_id: &requests_subscriptions::RequestId,
+    ) {
+        let (subscription_id, state_machine_subscription, abort_registration) = match self
+            .start_subscription(
+                request_id,
+                state_machine_request_id,
+                SubscriptionTy::RuntimeVersion,
+                1,
+            )
+            .await
+        {
+            Some(v) => v,
+            None => return,
+        };
+
+        self.requests_subscriptions
+            .respond(
+                state_machine_request_id,
+                methods::Response::state_subscribeRuntimeVersion((&subscription_id).into())
+                    .to_json_response(request_id),
+            )
+            .await;
+
+        // Spawn a separate task for the subscription.
+        let task = {
+            let me = self.clone();
+            async move {
+                // Runtime version that has last been reported to the client. Contains `None` if
+                // an error has been reported, and is `None` if nothing has been reported yet.
+                let mut previous_runtime_version: Option<Option<Vec<u8>>> = None;
+
+                loop {
+                    let subscribe_all = me.consensus_service.subscribe_all(32).await;
+                    let mut current_best = subscribe_all_best_block(&subscribe_all);
+                    let mut new_blocks = subscribe_all.new_blocks;
+
+                    loop {
+                        let runtime = match me
+                            .consensus_service
+                            .best_block_storage_get(vec![b":code".to_vec(), b":heappages".to_vec()])
+                            .await
+                        {
+                            Some((_, mut values)) => {
+                                let heap_pages = values.pop().unwrap();
+                                let code = values.pop().unwrap();
+                                Some(me.runtime_from_code(code, heap_pages).await)
+                            }
+                            None => None,
+                        };
+
+                        let notification = match runtime {
+                            None => None,
+                            Some(Ok(runtime)) => {
+                                let runtime_version =
+                                    runtime.runtime.runtime_version().as_ref().to_vec();
+                                let notification = if previous_runtime_version.as_ref()
+                                    != Some(&Some(runtime_version.clone()))
+                                {
+                                    let runtime_spec = runtime.runtime.runtime_version().decode();
+                                    Some(
+                                        methods::ServerToClient::state_runtimeVersion {
+                                            subscription: (&subscription_id).into(),
+                                            result: Some(methods::RuntimeVersion {
+                                                spec_name: runtime_spec.spec_name.into(),
+                                                impl_name: runtime_spec.impl_name.into(),
+                                                authoring_version: u64::from(
+                                                    runtime_spec.authoring_version,
+                                                ),
+                                                spec_version: u64::from(runtime_spec.spec_version),
+                                                impl_version: u64::from(runtime_spec.impl_version),
+                                                transaction_version: runtime_spec
+                                                    .transaction_version
+                                                    .map(u64::from),
+                                                state_version: runtime_spec
+                                                    .state_version
+                                                    .map(u64::from),
+                                                apis: runtime_spec
+                                                    .apis
+                                                    .map(|api| {
+                                                        (
+                                                            methods::HexString(
+                                                                api.name_hash.to_vec(),
+                                                            ),
+                                                            api.version,
+                                                        )
+                                                    })
+                                                    .collect(),
+                                            }),
+                                        }
+                                        .to_json_call_object_parameters(None),
+                                    )
+                                } else {
+                                    None
+                                };
+
+                                previous_runtime_version = Some(Some(runtime_version));
+                                *me.runtime_cache.lock().await = Some(runtime);
+                                notification
+                            }
+                            Some(Err(error)) => {
+                                tracing::warn!(%error, "runtime-version-subscription-error");
+                                if previous_runtime_version != Some(None) {
+                                    previous_runtime_version = Some(None);
+                                    Some(
+                                        methods::ServerToClient::state_runtimeVersion {
+                                            subscription: (&subscription_id).into(),
+                                            result: None,
+                                        }
+                                        .to_json_call_object_parameters(None),
+                                    )
+                                } else {
+                                    None
+                                }
+                            }
+                        };
+
+                        if let Some(notification) = notification {
+                            me.requests_subscriptions
+                                .set_queued_notification(
+                                    &state_machine_subscription,
+                                    0,
+                                    notification,
+                                )
+                                .await;
+                        }
+
+                        if next_best_block(&mut new_blocks, &mut current_best)
+                            .await
+                            .is_none()
+                        {
+                            break;
+                        }
+                    }
+                }
+            }
+        };
+
+        self.new_child_tasks_tx
+            .unbounded_send(Box::pin(
+                future::Abortable::new(task, abort_registration).map(|_| ()),
+            ))
+            .unwrap();
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_subscribeStorage`].
+    ///
+    /// The changes are reported against the best block.
+    async fn state_subscribe_storage(
+        self: &Arc<Self>,
+        request_id: &str,
+        state_machine_request_id: &requests_subscriptions::RequestId,
+        list: Vec<methods::HexString>,
+    ) {
+        let (subscription_id, state_machine_subscription, abort_registration) = match self
+            .start_subscription(
+                request_id,
+                state_machine_request_id,
+                SubscriptionTy::Storage,
+                16,
+            )
+            .await
+        {
+            Some(v) => v,
+            None => return,
+        };
+
+        self.requests_subscriptions
+            .respond(
+                state_machine_request_id,
+                methods::Response::state_subscribeStorage((&subscription_id).into())
+                    .to_json_response(request_id),
+            )
+            .await;
+
+        // Spawn a separate task for the subscription.
+        let task = {
+            let me = self.clone();
+            async move {
+                // For each key in `list`, the value that has last been reported to the client, or
+                // `None` if nothing has been reported yet.
+                let mut known_values = vec![None; list.len()];
+
+                loop {
+                    let subscribe_all = me.consensus_service.subscribe_all(32).await;
+                    let mut current_best = subscribe_all_best_block(&subscribe_all);
+                    let mut new_blocks = subscribe_all.new_blocks;
+
+                    loop {
+                        let values = me
+                            .consensus_service
+                            .best_block_storage_get(list.iter().map(|key| key.0.clone()).collect())
+                            .await;
+
+                        if let Some((hash, values)) = values {
+                            let mut changes = Vec::new();
+                            for ((key, known_value), value) in
+                                list.iter().zip(known_values.iter_mut()).zip(values)
+                            {
+                                if known_value.as_ref() != Some(&value) {
+                                    *known_value = Some(value.clone());
+                                    changes.push((key.clone(), value.map(methods::HexString)));
+                                }
+                            }
+
+                            if !changes.is_empty() {
+                                me.requests_subscriptions
+                                    .push_notification(
+                                        &state_machine_subscription,
+                                        methods::ServerToClient::state_storage {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::StorageChangeSet {
+                                                block: methods::HashHexString(hash),
+                                                changes,
+                                            },
+                                        }
+                                        .to_json_call_object_parameters(None),
+                                    )
+                                    .await;
+                            }
+                        }
+
+                        if next_best_block(&mut new_blocks, &mut current_best)
+                            .await
+                            .is_none()
+                        {
+                            break;
+                        }
+                    }
+                }
+            }
+        };
+
+        self.new_child_tasks_tx
+            .unbounded_send(Box::pin(
+                future::Abortable::new(task, abort_registration).map(|_| ()),
+            ))
+            .unwrap();
+    }
+
+    /// Starts a new subscription in [`RequestsHandler::requests_subscriptions`] and inserts it in
+    /// [`RequestsHandler::subscriptions`].
+    ///
+    /// Returns the JSON-RPC identifier of the subscription, its identifier within the state
+    /// machine, and the registration to use to make the subscription task abortable.
+    ///
+    /// Returns `None` if the client has reached its limit of subscriptions, in which case an
+    /// error has already been sent back to the client.
+    async fn start_subscription(
+        &self,
+        request_id: &str,
+        state_machine_request_id: &requests_subscriptions::RequestId,
+        ty: SubscriptionTy,
+        messages_capacity: usize,
+    ) -> Option<(
+        String,
+        requests_subscriptions::SubscriptionId,
+        future::AbortRegistration,
+    )> {
+        let state_machine_subscription = match self
+            .requests_subscriptions
+            .start_subscription(state_machine_request_id, messages_capacity)
+            .await
+        {
+            Ok(v) => v,
+            Err(requests_subscriptions::StartSubscriptionError::LimitReached) => {
+                self.requests_subscriptions
+                    .respond(
+                        state_machine_request_id,
+                        json_rpc::parse::build_error_response(
+                            request_id,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                "Too many active subscriptions",
+                            ),
+                            None,
+                        ),
+                    )
+                    .await;
+                return None;
+            }
+        };
+
+        let subscription_id = hex::encode(rand::random::<[u8; 16]>());
+
+        let (abort_handle, abort_registration) = future::AbortHandle::new_pair();
+        self.subscriptions.lock().await.insert(
+            (subscription_id.clone(), ty),
+            (abort_handle, state_machine_subscription.clone()),
+        );
+
+        Some((
+            subscription_id,
+            state_machine_subscription,
+            abort_registration,
+        ))
+    }
+
+    /// Stops the given subscription. Returns `false` if it doesn't exist.
+    async fn unsubscribe(&self, subscription: &str, ty: SubscriptionTy) -> bool {
+        let removed = self
+            .subscriptions
+            .lock()
+            .await
+            .remove(&(subscription.to_owned(), ty));
+
+        if let Some((abort_handle, state_machine_subscription)) = removed {
+            abort_handle.abort();
+            self.requests_subscriptions
+                .stop_subscription(&state_machine_subscription)
+                .await;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Aborts the tasks of the given subscriptions, whose client has been removed.
+    async fn abort_subscriptions(&self, list: &[requests_subscriptions::SubscriptionId]) {
+        self.subscriptions
+            .lock()
+            .await
+            .retain(|_, (abort_handle, state_machine_subscription)| {
+                if list.contains(state_machine_subscription) {
+                    abort_handle.abort();
+                    false
+                } else {
+                    true
+                }
+            });
+    }
+
+    /// Sends a `chain_newHead` or `chain_finalizedHead` notification, depending on `ty`, to the
+    /// given subscription.
+    async fn notify_header(
+        &self,
+        state_machine_subscription: &requests_subscriptions::SubscriptionId,
+        subscription_id: &str,
+        ty: SubscriptionTy,
+        scale_encoded_header: &[u8],
+    ) {
+        let header = match methods::Header::from_scale_encoded_header(
+            scale_encoded_header,
+            self.block_number_bytes,
+        ) {
+            Ok(h) => h,
+            Err(error) => {
+                tracing::warn!(
+                    hash = %HashDisplay(&header::hash_from_scale_encoded_header(scale_encoded_header)),
+                    %error, "subscription-skipped-undecodable-header"
+                );
+                return;
+            }
+        };
+
+        let notification = if ty == SubscriptionTy::FinalizedHeads {
+            methods::ServerToClient::chain_finalizedHead {
+                subscription: subscription_id.into(),
+                result: header,
+            }
+        } else {
+            methods::ServerToClient::chain_newHead {
+                subscription: subscription_id.into(),
+                result: header,
+            }
+        }
+        .to_json_call_object_parameters(None);
+
+        if ty == SubscriptionTy::AllHeads {
+            // This function call will fail if the queue of notifications to the user has too
+            // many elements in it. This JSON-RPC function unfortunately doesn't provide any
+            // mechanism to deal with this situation, and we handle it by simply not sending the
+            // notification.
+            let _ = self
+                .requests_subscriptions
+                .try_push_notification(state_machine_subscription, notification)
+                .await;
+        } else {
+            self.requests_subscriptions
+                .set_queued_notification(state_machine_subscription, 0, notification)
+                .await;
+        }
+    }
+}
+
+/// Error potentially returned by [`RequestsHandler::runtime_call`].
+#[derive(Debug, derive_more::Display)]
+enum RuntimeCallError {
+    /// Block isn't in the database.
+    UnknownBlock,
+    /// Error while decoding the header of the block.
+    #[display(fmt = "Invalid block header: {}", _0)]
+    InvalidHeader(header::Error),
+    /// Error while accessing the storage of the block.
+    #[display(fmt = "{}", _0)]
+    Storage(full_sqlite::StorageAccessError),
+    /// The storage of the block doesn't contain any runtime.
+    #[display(fmt = "No runtime found in the storage of the block")]
+    NoCode,
+    /// Invalid value for the `:heappages` storage item.
+    #[display(fmt = "Invalid heap pages: {}", _0)]
+    InvalidHeapPages(executor::InvalidHeapPagesError),
+    /// Failed to compile the runtime.
+    #[display(fmt = "Invalid runtime: {}", _0)]
+    InvalidRuntime(host::NewErr),
+    /// Failed to start the runtime call.
+    #[display(fmt = "Failed to start runtime call: {}", _0)]
+    StartError(host::StartErr),
+    /// Error during the runtime call.
+    #[display(fmt = "Error during runtime call: {}", _0)]
+    Execution(read_only_runtime_host::ErrorDetail),
+}
+
+/// Returns the hash of the best block at the time when the given subscription has been started.
+fn subscribe_all_best_block(subscribe_all: &consensus_service::SubscribeAll) -> [u8; 32] {
+    subscribe_all
+        .non_finalized_blocks_ancestry_order
+        .iter()
+        .find(|block| block.is_new_best)
+        .map_or_else(
+            || {
+                header::hash_from_scale_encoded_header(
+                    &subscribe_all.finalized_block_scale_encoded_header,
+                )
+            },
+            |block| block.block_hash,
+        )
+}
+
+/// Waits until the best block is different from `current_best`, then updates `current_best` and
+/// returns its new value. Returns `None` if the channel has been closed.
+async fn next_best_block(
+    new_blocks: &mut mpsc::Receiver<consensus_service::Notification>,
+    current_best: &mut [u8; 32],
+) -> Option<[u8; 32]> {
+    loop {
+        let new_best = match new_blocks.next().await? {
+            consensus_service::Notification::Block(block) if block.is_new_best => block.block_hash,
+            consensus_service::Notification::Block(_) => continue,
+            consensus_service::Notification::Finalized {
+                best_block_hash, ..
+            } => best_block_hash,
+        };
+
+        if new_best != *current_best {
+            *current_best = new_best;
+            return Some(new_best);
+        }
+    }
+}
+
+/// Returns at most `count` keys of the storage of the given block that start with `prefix`, in
+/// increasing order. If `start_key` is `Some`, only the keys strictly superior to it are returned.
+fn storage_keys_paged(
+    database: &full_sqlite::SqliteFullDatabase,
+    block_hash: &[u8; 32],
+    prefix: &[u8],
+    start_key: Option<Vec<u8>>,
+    count: usize,
+) -> Result<Vec<Vec<u8>>, full_sqlite::StorageAccessError> {
+    let mut out = Vec::new();
+
+    // Keys strictly superior to `start_key` are returned. If `start_key` is before `prefix`, the
+    // iteration starts at `prefix` itself instead.
+    let mut cursor = match start_key {
+        Some(k) if *k >= *prefix => k,
+        _ => {
+            if count != 0
+                && database
+                    .block_storage_top_trie_get(block_hash, prefix)?
+                    .is_some()
+            {
+                out.push(prefix.to_vec());
+            }
+            prefix.to_vec()
+        }
+    };
+
+    while out.len() < count {
+        match database.block_storage_top_trie_next_key(block_hash, &cursor)? {
+            Some(key) if key.starts_with(prefix) => {
+                out.push(key.clone());
+                cursor = key;
+            }
+            _ => break,
+        }
+    }
+
+    Ok(out)
+}
+
+/// Builds a JSON-RPC error response containing the given error message.
+fn internal_error_response(request_id: &str, error: &impl ToString) -> String {
+    json_rpc::parse::build_error_response(
+        request_id,
+        json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+        None,
+    )
 }
@@ -38,6 +38,7 @@ use futures::{
 use hashbrown::HashMap;
 use smoldot::{
     database::full_sqlite,
+    executor::{self, host, read_only_runtime_host},
     header,
     informant::HashDisplay,
     libp2p::{
@@ -47,8 +48,10 @@ use smoldot::{
         peers,
     },
     network::{protocol, service},
+    trie,
 };
 use std::{
+    collections::VecDeque,
     io, iter,
     net::{IpAddr, SocketAddr},
     num::NonZeroUsize,
@@ -132,6 +135,16 @@ pub enum Event {
         header: header::Header,
         is_best: bool,
     },
+    GrandpaNotification {
+        chain_index: usize,
+        peer_id: PeerId,
+        notification: service::EncodedGrandpaNotification,
+    },
+    Transactions {
+        chain_index: usize,
+        peer_id: PeerId,
+        transactions: service::EncodedTransactions,
+    },
 }
 
 pub struct NetworkService {
@@ -157,6 +170,14 @@ struct Inner {
     /// Databases to use to read blocks from when answering requests.
     databases: Vec<Arc<database_thread::DatabaseThread>>,
 
+    /// Version of the trie entries of each runtime, used when answering incoming storage proof
+    /// and state requests.
+    runtime_trie_versions: Arc<RuntimeTrieVersions>,
+
+    /// Merkle values of the large subtrees of the storage of the blocks whose storage has
+    /// recently been requested through state requests.
+    state_request_subtrees: Arc<StateRequestSubtrees>,
+
     /// Identity of the local node.
     local_peer_id: PeerId,
 
@@ -164,6 +185,17 @@ struct Inner {
     jaeger_service: Arc<jaeger_service::JaegerService>,
 }
 
+/// Maximum number of incoming storage proof, call proof, and state requests that are answered at
+/// the same time. Requests above this limit are refused.
+const MAX_IN_PROOF_REQUESTS: usize = 16;
+
+/// Maximum number of incoming storage proof, call proof, and state requests from the same peer
+/// that are answered at the same time. Requests above this limit are refused.
+const MAX_IN_PROOF_REQUESTS_PER_PEER: usize = 2;
+
+/// Sending side of the channel used to report the outcome of a Kademlia get value operation.
+type KademliaGetValueSender = oneshot::Sender<Result<Vec<Vec<u8>>, service::KademliaValueError>>;
+
 struct Guarded {
     /// Data structure holding the entire state of the networking.
     network: service::ChainNetwork<Instant>,
@@ -176,6 +208,18 @@ struct Guarded {
 
     conn_tasks_tx: mpsc::Sender<Pin<Box<dyn Future<Output = ()> + Send>>>,
 
+    /// List of incoming storage proof, call proof, and state requests that are being answered,
+    /// with the peer that has sent them.
+    ///
+    /// Answering these requests requires going through the storage of a block, which can take a
+    /// long time. They are thus answered in the background, and their number is capped to
+    /// [`MAX_IN_PROOF_REQUESTS`] and [`MAX_IN_PROOF_REQUESTS_PER_PEER`].
+    in_proof_requests: HashMap<service::InRequestId, PeerId, fnv::FnvBuildHasher>,
+
+    /// Channel used to spawn the tasks that answer the requests of
+    /// [`Guarded::in_proof_requests`].
+    in_proof_requests_tasks_tx: mpsc::UnboundedSender<Pin<Box<dyn Future<Output = ()> + Send>>>,
+
     active_connections: HashMap<
         service::ConnectionId,
         mpsc::Sender<service::CoordinatorToConnection<Instant>>,
@@ -201,9 +245,50 @@ struct Guarded {
         fnv::FnvBuildHasher,
     >,
 
+    /// List of all GrandPa warp sync requests that have been started but not finished yet.
+    grandpa_warp_sync_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<
+            Result<service::EncodedGrandpaWarpSyncResponse, service::GrandpaWarpSyncRequestError>,
+        >,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all storage proof requests that have been started but not finished yet.
+    storage_proof_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<Result<service::EncodedMerkleProof, service::StorageProofRequestError>>,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all call proof requests that have been started but not finished yet.
+    call_proof_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<Result<service::EncodedMerkleProof, service::CallProofRequestError>>,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all state requests that have been started but not finished yet.
+    state_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<Result<service::EncodedStateResponse, service::StateRequestError>>,
+        fnv::FnvBuildHasher,
+    >,
+
     /// List of Kademlia discovery operations that have been started but not finished yet.
     kademlia_discovery_operations:
         HashMap<service::KademliaOperationId, usize, fnv::FnvBuildHasher>,
+
+    /// List of Kademlia get value operations that have been started but not finished yet.
+    kademlia_get_value_operations:
+        HashMap<service::KademliaOperationId, KademliaGetValueSender, fnv::FnvBuildHasher>,
+
+    /// List of Kademlia put value operations that have been started but not finished yet.
+    kademlia_put_value_operations: HashMap<
+        service::KademliaOperationId,
+        oneshot::Sender<Result<NonZeroUsize, service::KademliaValueError>>,
+        fnv::FnvBuildHasher,
+    >,
 }
 
 impl NetworkService {
@@ -238,6 +323,9 @@ impl NetworkService {
                     None
                 },
                 allow_inbound_block_requests: true,
+                allow_inbound_light_requests: true,
+                allow_inbound_state_requests: true,
+                allow_inbound_kademlia_requests: true,
             });
 
             databases.push(chain.database.clone());
@@ -250,7 +338,12 @@ impl NetworkService {
             peers_capacity: 100,       // TODO: ?
             noise_key: config.noise_key,
             handshake_timeout: Duration::from_secs(8),
+            multiplexing_protocols: vec![
+                service::MultiplexingProtocol::Yamux,
+                service::MultiplexingProtocol::Mplex,
+            ],
             max_addresses_per_peer: NonZeroUsize::new(5).unwrap(),
+            reputation: Default::default(),
             randomness_seed: rand::random(),
         });
 
@@ -268,6 +361,9 @@ impl NetworkService {
                 .unwrap_or(4),
         );
 
+        // A channel is used to communicate new tasks dedicated to answering incoming requests.
+        let (in_proof_requests_tasks_tx, mut in_proof_requests_tasks_rx) = mpsc::unbounded();
+
         // Initialize the inner network service.
         let inner = {
             let (messages_from_connections_tx, messages_from_connections_rx) = mpsc::channel(64);
@@ -279,11 +375,22 @@ impl NetworkService {
                 .into_peer_id(),
                 wake_up_main_background_task: event_listener::Event::new(),
                 databases,
+                runtime_trie_versions: Arc::new(std::sync::Mutex::new(
+                    hashbrown::HashMap::with_capacity_and_hasher(8, Default::default()),
+                )),
+                state_request_subtrees: Arc::new(std::sync::Mutex::new(VecDeque::with_capacity(
+                    STATE_REQUEST_SUBTREES_CACHE_LEN,
+                ))),
                 guarded: Mutex::new(Guarded {
                     num_pending_out_attempts: 0,
                     messages_from_connections_tx,
                     messages_from_connections_rx,
                     conn_tasks_tx: conn_tasks_tx.clone(),
+                    in_proof_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        MAX_IN_PROOF_REQUESTS,
+                        Default::default(),
+                    ),
+                    in_proof_requests_tasks_tx,
                     network,
                     slots_assign_backoff: hashbrown::HashMap::with_capacity_and_hasher(
                         50, // TODO: ?
@@ -297,10 +404,34 @@ impl NetworkService {
                         50, // TODO: ?
                         Default::default(),
                     ),
+                    grandpa_warp_sync_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        4,
+                        Default::default(),
+                    ),
+                    storage_proof_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        4,
+                        Default::default(),
+                    ),
+                    call_proof_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        4,
+                        Default::default(),
+                    ),
+                    state_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        4,
+                        Default::default(),
+                    ),
                     kademlia_discovery_operations: hashbrown::HashMap::with_capacity_and_hasher(
                         4,
                         Default::default(),
                     ),
+                    kademlia_get_value_operations: hashbrown::HashMap::with_capacity_and_hasher(
+                        4,
+                        Default::default(),
+                    ),
+                    kademlia_put_value_operations: hashbrown::HashMap::with_capacity_and_hasher(
+                        4,
+                        Default::default(),
+                    ),
                 }),
                 jaeger_service: config.jaeger_service,
             })
@@ -484,6 +615,28 @@ impl NetworkService {
             abortable.map(|_| ())
         }));
 
+        // Spawn task dedicated to answering the incoming requests that require going through the
+        // storage of a block.
+        (config.tasks_executor)(Box::pin({
+            let future = async move {
+                let mut requests = stream::FuturesUnordered::new();
+                loop {
+                    futures::select! {
+                        new_request = in_proof_requests_tasks_rx.select_next_some() => {
+                            requests.push(new_request);
+                        },
+                        () = requests.select_next_some() => {},
+                    }
+                }
+            };
+
+            let (abortable, abort_handle) = future::abortable(
+                future.instrument(tracing::trace_span!(parent: None, "in-requests-executor")),
+            );
+            abort_handles.push(abort_handle);
+            abortable.map(|_| ())
+        }));
+
         // Build the final network service.
         let network_service = Arc::new(NetworkService {
             inner,
@@ -529,6 +682,20 @@ impl NetworkService {
             .num_peers(chain_index)
     }
 
+    /// Reports that the given peer has misbehaved.
+    ///
+    /// See [`service::ChainNetwork::report_misbehaviour`].
+    pub async fn report_misbehaviour(&self, peer_id: &PeerId, misbehaviour: service::Misbehaviour) {
+        let mut guarded = self.inner.guarded.lock().await;
+        if guarded
+            .network
+            .report_misbehaviour(&Instant::now(), peer_id, misbehaviour)
+        {
+            tracing::debug!(%peer_id, ?misbehaviour, "peer-banned");
+        }
+        self.inner.wake_up_main_background_task.notify(1);
+    }
+
     pub async fn set_local_best_block(
         &self,
         chain_index: usize,
@@ -570,6 +737,117 @@ impl NetworkService {
         result
     }
 
+    /// Updates the state of the local node with regards to GrandPa rounds, and sends a neighbor
+    /// packet to all the peers we have a GrandPa substream with.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range, or if the chain has GrandPa disabled.
+    ///
+    pub async fn set_local_grandpa_state(
+        &self,
+        chain_index: usize,
+        grandpa_state: service::GrandpaState,
+    ) {
+        self.inner
+            .guarded
+            .lock()
+            .await
+            .network
+            .set_local_grandpa_state(chain_index, grandpa_state);
+        self.inner.wake_up_main_background_task.notify(1);
+    }
+
+    /// Sends the given SCALE-encoded GrandPa notification to the given peer.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range, or if the chain has GrandPa disabled.
+    ///
+    pub async fn send_grandpa_notification(
+        &self,
+        target: &PeerId,
+        chain_index: usize,
+        notification: protocol::GrandpaNotificationRef<'_>,
+    ) -> Result<(), QueueNotificationError> {
+        let mut guarded = self.inner.guarded.lock().await;
+
+        // The call to `send_grandpa_notification` below panics if we have no active connection.
+        if !guarded
+            .network
+            .can_send_grandpa_notifications(target, chain_index)
+        {
+            return Err(QueueNotificationError::NoConnection);
+        }
+
+        let result = guarded
+            .network
+            .send_grandpa_notification(target, chain_index, notification)
+            .map_err(QueueNotificationError::Queue);
+
+        self.inner.wake_up_main_background_task.notify(1);
+        result
+    }
+
+    /// Sends the given SCALE-encoded GrandPa notification to all the peers we have a GrandPa
+    /// substream with.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range, or if the chain has GrandPa disabled.
+    ///
+    pub async fn broadcast_grandpa_notification(
+        &self,
+        chain_index: usize,
+        notification: protocol::GrandpaNotificationRef<'_>,
+    ) {
+        self.inner
+            .guarded
+            .lock()
+            .await
+            .network
+            .broadcast_grandpa_notification(chain_index, notification);
+        self.inner.wake_up_main_background_task.notify(1);
+    }
+
+    /// Sends the given SCALE-encoded transaction to all the peers we have a transactions
+    /// substream with.
+    ///
+    /// Returns the list of peers that the transaction has been sent to.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range.
+    ///
+    pub async fn announce_transaction(
+        &self,
+        chain_index: usize,
+        transaction: &[u8],
+    ) -> Vec<PeerId> {
+        let mut sent_peers = Vec::new();
+        let mut guarded = self.inner.guarded.lock().await;
+
+        // TODO: keep track of which peer knows about which transaction, and don't send it again
+        // TODO: collecting in a Vec :-/
+        for peer in guarded
+            .network
+            .opened_transactions_substream(chain_index)
+            .cloned()
+            .collect::<Vec<_>>()
+        {
+            if guarded
+                .network
+                .announce_transaction(&peer, chain_index, transaction)
+                .is_ok()
+            {
+                sent_peers.push(peer);
+            }
+        }
+
+        self.inner.wake_up_main_background_task.notify(1);
+        sent_peers
+    }
+
     /// Sends a blocks request to the given peer.
     // TODO: more docs
     // TODO: proper error type
@@ -673,85 +951,499 @@ impl NetworkService {
 
         result
     }
-}
 
-impl Drop for NetworkService {
-    fn drop(&mut self) {
-        for handle in &mut self.abort_handles {
-            handle.abort();
-        }
-    }
-}
+    /// Sends a GrandPa warp sync request to the given peer.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn grandpa_warp_sync_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_index: usize,
+        begin_hash: [u8; 32],
+    ) -> Result<service::EncodedGrandpaWarpSyncResponse, GrandpaWarpSyncRequestError> {
+        tracing::debug!(
+            peer_id = %target, %chain_index, begin_hash = %HashDisplay(&begin_hash),
+            "grandpa-warp-sync-request-start"
+        );
 
-/// Error when initializing the network service.
-#[derive(Debug, derive_more::Display)]
-pub enum InitError {
-    /// I/O error when initializing a listener.
-    #[display(fmt = "I/O error when creating listener for {}: {}", _0, _1)]
-    ListenerIo(Multiaddr, io::Error),
-    /// A listening address passed through the configuration isn't valid.
-    #[display(
-        fmt = "A listening address passed through the configuration isn't valid: {}",
-        _0
-    )]
-    BadListenMultiaddr(Multiaddr),
-}
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
 
-/// Error returned by [`NetworkService::blocks_request`].
-#[derive(Debug, derive_more::Display)]
-pub enum BlocksRequestError {
-    /// No established connection with the target.
-    NoConnection,
-    /// Error during the request.
-    #[display(fmt = "{}", _0)]
-    Request(service::BlocksRequestError),
-}
+            // The call to `start_grandpa_warp_sync_request` below panics if we have no active
+            // connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(GrandpaWarpSyncRequestError::NoConnection);
+            }
 
-/// Error returned by [`NetworkService::send_block_announce`].
-#[derive(Debug, derive_more::Display)]
-pub enum QueueNotificationError {
-    /// No established connection with the target.
-    NoConnection,
-    /// Error during the queuing.
-    #[display(fmt = "{}", _0)]
-    Queue(peers::QueueNotificationError),
-}
+            let (tx, rx) = oneshot::channel();
 
-async fn background_task(inner: Arc<Inner>, mut event_senders: Vec<mpsc::Sender<Event>>) {
-    loop {
-        // In order to guarantee that waking up `wake_up_background` will run an entirely
-        // loop of `update_round`, we grab the listener at the start. If `wake_up_background`
-        // is notified while `update_round` is running, the `notified.await` below will be
-        // instantaneous.
-        let notified = inner.wake_up_main_background_task.listen();
-        update_round(&inner, &mut event_senders).await;
-        notified.await;
-    }
-}
+            // The timeout needs to be long enough to potentially download the maximum response
+            // size of 16 MiB.
+            let request_id = guarded.network.start_grandpa_warp_sync_request(
+                Instant::now(),
+                &target,
+                chain_index,
+                begin_hash,
+                Duration::from_secs(24),
+            );
 
-async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Event>]) {
-    let mut guarded = inner.guarded.lock().await;
+            guarded.grandpa_warp_sync_requests.insert(request_id, tx);
 
-    // Inject in the coordinator the messages that the connections have generated.
-    loop {
-        let (connection_id, message) =
-            match guarded.messages_from_connections_rx.next().now_or_never() {
-                Some(Some(v)) => v,
-                _ => break,
-            };
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
 
-        guarded
-            .network
-            .inject_connection_message(connection_id, message);
+        let result = rx
+            .await
+            .unwrap()
+            .map_err(GrandpaWarpSyncRequestError::Request);
+
+        match &result {
+            Ok(response) => {
+                let decoded = response.decode();
+                tracing::debug!(
+                    peer_id = %target, %chain_index,
+                    outcome = "success",
+                    num_fragments = decoded.fragments.len(),
+                    is_finished = decoded.is_finished,
+                    "grandpa-warp-sync-request-ended"
+                );
+            }
+            Err(err) => {
+                tracing::debug!(
+                    peer_id = %target, %chain_index,
+                    outcome = "failure",
+                    error = %err,
+                    "grandpa-warp-sync-request-ended"
+                );
+            }
+        }
+
+        result
     }
 
-    // Process events generated by the state machine.
-    'events_loop: loop {
-        let event = loop {
-            let inner_event = match guarded.network.next_event(Instant::now()) {
-                Some(ev) => ev,
-                None => break 'events_loop,
-            };
+    /// Sends a storage proof request to the given peer.
+    #[tracing::instrument(level = "trace", skip(self, keys))]
+    pub async fn storage_proof_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_index: usize,
+        block_hash: [u8; 32],
+        keys: Vec<Vec<u8>>,
+    ) -> Result<service::EncodedMerkleProof, StorageProofRequestError> {
+        tracing::debug!(
+            peer_id = %target, %chain_index, block_hash = %HashDisplay(&block_hash),
+            num_keys = keys.len(),
+            "storage-proof-request-start"
+        );
+
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+
+            // The call to `start_storage_proof_request` below panics if we have no active
+            // connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(StorageProofRequestError::NoConnection);
+            }
+
+            let (tx, rx) = oneshot::channel();
+
+            let request_id = guarded.network.start_storage_proof_request(
+                Instant::now(),
+                &target,
+                chain_index,
+                protocol::StorageProofRequestConfig {
+                    block_hash,
+                    keys: keys.into_iter(),
+                    child_trie: None,
+                },
+                Duration::from_secs(16),
+            );
+
+            guarded.storage_proof_requests.insert(request_id, tx);
+
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx.await.unwrap().map_err(StorageProofRequestError::Request);
+
+        match &result {
+            Ok(proof) => {
+                tracing::debug!(
+                    peer_id = %target, %chain_index,
+                    outcome = "success",
+                    proof_size = proof.decode().len(),
+                    "storage-proof-request-ended"
+                );
+            }
+            Err(err) => {
+                tracing::debug!(
+                    peer_id = %target, %chain_index,
+                    outcome = "failure",
+                    error = %err,
+                    "storage-proof-request-ended"
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Sends a call proof request to the given peer.
+    #[tracing::instrument(level = "trace", skip(self, parameter_vectored))]
+    pub async fn call_proof_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_index: usize,
+        block_hash: [u8; 32],
+        function_name: String,
+        parameter_vectored: Vec<u8>,
+    ) -> Result<service::EncodedMerkleProof, CallProofRequestError> {
+        tracing::debug!(
+            peer_id = %target, %chain_index, block_hash = %HashDisplay(&block_hash),
+            %function_name,
+            "call-proof-request-start"
+        );
+
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+
+            // The call to `start_call_proof_request` below panics if we have no active
+            // connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(CallProofRequestError::NoConnection);
+            }
+
+            let (tx, rx) = oneshot::channel();
+
+            let request_id = guarded.network.start_call_proof_request(
+                Instant::now(),
+                &target,
+                chain_index,
+                protocol::CallProofRequestConfig {
+                    block_hash,
+                    method: &function_name,
+                    parameter_vectored: iter::once(parameter_vectored),
+                },
+                Duration::from_secs(16),
+            );
+
+            guarded.call_proof_requests.insert(request_id, tx);
+
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx.await.unwrap().map_err(CallProofRequestError::Request);
+
+        match &result {
+            Ok(proof) => {
+                tracing::debug!(
+                    peer_id = %target, %chain_index,
+                    outcome = "success",
+                    proof_size = proof.decode().len(),
+                    "call-proof-request-ended"
+                );
+            }
+            Err(err) => {
+                tracing::debug!(
+                    peer_id = %target, %chain_index,
+                    outcome = "failure",
+                    error = %err,
+                    "call-proof-request-ended"
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Sends a state request to the given peer, asking for the entries of the storage of the
+    /// given block starting at the given key.
+    ///
+    /// If `child_trie` is `Some`, the request starts at the given key within this default child
+    /// trie (whose identifier doesn't include the `:child_storage:default:` prefix) rather than
+    /// within the main trie.
+    #[tracing::instrument(level = "trace", skip(self, child_trie, start_key))]
+    pub async fn state_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_index: usize,
+        block_hash: [u8; 32],
+        child_trie: Option<Vec<u8>>,
+        start_key: Vec<u8>,
+    ) -> Result<service::EncodedStateResponse, StateRequestError> {
+        tracing::debug!(
+            peer_id = %target, %chain_index, block_hash = %HashDisplay(&block_hash),
+            child_trie = %child_trie.as_ref().map(hex::encode).unwrap_or_default(),
+            start_key = %hex::encode(&start_key),
+            "state-request-start"
+        );
+
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+
+            // The call to `start_state_request` below panics if we have no active connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(StateRequestError::NoConnection);
+            }
+
+            let (tx, rx) = oneshot::channel();
+
+            let request_id = guarded.network.start_state_request(
+                Instant::now(),
+                &target,
+                chain_index,
+                &block_hash,
+                match &child_trie {
+                    Some(child_trie) => protocol::StateRequestStart::ChildTrieDefault {
+                        child_trie,
+                        key: &start_key,
+                    },
+                    None => protocol::StateRequestStart::MainTrie(&start_key),
+                },
+                Duration::from_secs(16),
+            );
+
+            guarded.state_requests.insert(request_id, tx);
+
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx.await.unwrap().map_err(StateRequestError::Request);
+
+        match &result {
+            Ok(response) => {
+                tracing::debug!(
+                    peer_id = %target, %chain_index,
+                    outcome = "success",
+                    num_proof_entries = response.decode().len(),
+                    "state-request-ended"
+                );
+            }
+            Err(err) => {
+                tracing::debug!(
+                    peer_id = %target, %chain_index,
+                    outcome = "failure",
+                    error = %err,
+                    "state-request-ended"
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Looks for the record stored in the Kademlia DHT of the given chain under the given key.
+    ///
+    /// Returns the distinct values of the records that have been found, which can be empty.
+    #[tracing::instrument(level = "trace", skip(self, key))]
+    pub async fn kademlia_get_value(
+        self: Arc<Self>,
+        chain_index: usize,
+        key: Vec<u8>,
+    ) -> Result<Vec<Vec<u8>>, service::KademliaValueError> {
+        tracing::debug!(%chain_index, key = %hex::encode(&key), "kademlia-get-value-start");
+
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+            let (tx, rx) = oneshot::channel();
+            let operation_id =
+                guarded
+                    .network
+                    .start_kademlia_get_value(Instant::now(), chain_index, key);
+            guarded
+                .kademlia_get_value_operations
+                .insert(operation_id, tx);
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx.await.unwrap();
+
+        match &result {
+            Ok(values) => {
+                tracing::debug!(
+                    %chain_index, outcome = "success", num_values = values.len(),
+                    "kademlia-get-value-ended"
+                );
+            }
+            Err(err) => {
+                tracing::debug!(
+                    %chain_index, outcome = "failure", error = %err,
+                    "kademlia-get-value-ended"
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Stores a record in the Kademlia DHT of the given chain.
+    ///
+    /// Returns the number of peers that have accepted to store the record.
+    #[tracing::instrument(level = "trace", skip(self, key, value))]
+    pub async fn kademlia_put_value(
+        self: Arc<Self>,
+        chain_index: usize,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> Result<NonZeroUsize, service::KademliaValueError> {
+        tracing::debug!(%chain_index, key = %hex::encode(&key), "kademlia-put-value-start");
+
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+            let (tx, rx) = oneshot::channel();
+            let operation_id =
+                guarded
+                    .network
+                    .start_kademlia_put_value(Instant::now(), chain_index, key, value);
+            guarded
+                .kademlia_put_value_operations
+                .insert(operation_id, tx);
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx.await.unwrap();
+
+        match &result {
+            Ok(num_peers) => {
+                tracing::debug!(
+                    %chain_index, outcome = "success", %num_peers,
+                    "kademlia-put-value-ended"
+                );
+            }
+            Err(err) => {
+                tracing::debug!(
+                    %chain_index, outcome = "failure", error = %err,
+                    "kademlia-put-value-ended"
+                );
+            }
+        }
+
+        result
+    }
+}
+
+impl Drop for NetworkService {
+    fn drop(&mut self) {
+        for handle in &mut self.abort_handles {
+            handle.abort();
+        }
+    }
+}
+
+/// Error when initializing the network service.
+#[derive(Debug, derive_more::Display)]
+pub enum InitError {
+    /// I/O error when initializing a listener.
+    #[display(fmt = "I/O error when creating listener for {}: {}", _0, _1)]
+    ListenerIo(Multiaddr, io::Error),
+    /// A listening address passed through the configuration isn't valid.
+    #[display(
+        fmt = "A listening address passed through the configuration isn't valid: {}",
+        _0
+    )]
+    BadListenMultiaddr(Multiaddr),
+}
+
+/// Error returned by [`NetworkService::blocks_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum BlocksRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the request.
+    #[display(fmt = "{}", _0)]
+    Request(service::BlocksRequestError),
+}
+
+/// Error returned by [`NetworkService::grandpa_warp_sync_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum GrandpaWarpSyncRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the request.
+    #[display(fmt = "{}", _0)]
+    Request(service::GrandpaWarpSyncRequestError),
+}
+
+/// Error returned by [`NetworkService::storage_proof_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum StorageProofRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the request.
+    #[display(fmt = "{}", _0)]
+    Request(service::StorageProofRequestError),
+}
+
+/// Error returned by [`NetworkService::call_proof_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum CallProofRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the request.
+    #[display(fmt = "{}", _0)]
+    Request(service::CallProofRequestError),
+}
+
+/// Error returned by [`NetworkService::state_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum StateRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the request.
+    #[display(fmt = "{}", _0)]
+    Request(service::StateRequestError),
+}
+
+/// Error returned by [`NetworkService::send_block_announce`].
+#[derive(Debug, derive_more::Display)]
+pub enum QueueNotificationError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the queuing.
+    #[display(fmt = "{}", _0)]
+    Queue(peers::QueueNotificationError),
+}
+
+async fn background_task(inner: Arc<Inner>, mut event_senders: Vec<mpsc::Sender<Event>>) {
+    loop {
+        // In order to guarantee that waking up `wake_up_background` will run an entirely
+        // loop of `update_round`, we grab the listener at the start. If `wake_up_background`
+        // is notified while `update_round` is running, the `notified.await` below will be
+        // instantaneous.
+        let notified = inner.wake_up_main_background_task.listen();
+        update_round(&inner, &mut event_senders).await;
+        notified.await;
+    }
+}
+
+async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Event>]) {
+    let mut guarded = inner.guarded.lock().await;
+
+    // Inject in the coordinator the messages that the connections have generated.
+    loop {
+        let (connection_id, message) =
+            match guarded.messages_from_connections_rx.next().now_or_never() {
+                Some(Some(v)) => v,
+                _ => break,
+            };
+
+        guarded
+            .network
+            .inject_connection_message(connection_id, message);
+    }
+
+    // Process events generated by the state machine.
+    'events_loop: loop {
+        let event = loop {
+            let inner_event = match guarded.network.next_event(Instant::now()) {
+                Some(ev) => ev,
+                None => break 'events_loop,
+            };
 
             match inner_event {
                 service::Event::Connected(peer_id) => {
@@ -883,13 +1575,55 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                         .unwrap()
                         .send(response);
                 }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::GrandpaWarpSync(response),
+                } => {
+                    let _ = guarded
+                        .grandpa_warp_sync_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::StorageProof(response),
+                } => {
+                    let _ = guarded
+                        .storage_proof_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::CallProof(response),
+                } => {
+                    let _ = guarded
+                        .call_proof_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::State(response),
+                } => {
+                    let _ = guarded
+                        .state_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
+                }
                 service::Event::RequestResult { .. } => {
                     // We never start a request of any other kind.
                     unreachable!()
                 }
-                service::Event::RequestInCancel { .. } => {
-                    // Requests are answered immediately, and thus cancelling events can't happen.
-                    unreachable!()
+                service::Event::RequestInCancel { request_id } => {
+                    // Only the requests answered in the background can be cancelled, as the
+                    // other requests are answered immediately.
+                    let _was_in = guarded.in_proof_requests.remove(&request_id);
+                    debug_assert!(_was_in.is_some());
                 }
                 service::Event::KademliaDiscoveryResult {
                     operation_id,
@@ -916,6 +1650,26 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                         }
                     }
                 }
+                service::Event::KademliaGetValueResult {
+                    operation_id,
+                    result,
+                } => {
+                    let _ = guarded
+                        .kademlia_get_value_operations
+                        .remove(&operation_id)
+                        .unwrap()
+                        .send(result);
+                }
+                service::Event::KademliaPutValueResult {
+                    operation_id,
+                    result,
+                } => {
+                    let _ = guarded
+                        .kademlia_put_value_operations
+                        .remove(&operation_id)
+                        .unwrap()
+                        .send(result);
+                }
                 service::Event::IdentifyRequestIn {
                     peer_id,
                     request_id,
@@ -961,6 +1715,138 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                         },
                     );
                 }
+                service::Event::StorageProofRequestIn {
+                    peer_id,
+                    chain_index,
+                    config,
+                    request_id,
+                } => {
+                    tracing::debug!(%peer_id, "incoming-storage-proof-request");
+                    let config = config.decode();
+
+                    // The database doesn't store child tries.
+                    if config.child_trie.is_some() {
+                        guarded.network.respond_storage_proof(request_id, None);
+                        continue;
+                    }
+
+                    let response = storage_proof_request_response(
+                        inner.databases[chain_index].clone(),
+                        inner.runtime_trie_versions.clone(),
+                        guarded.network.block_number_bytes(chain_index),
+                        config.block_hash,
+                        config.keys.map(|key| key.to_vec()).collect(),
+                    );
+                    spawn_in_proof_request(
+                        inner,
+                        &mut guarded,
+                        peer_id,
+                        request_id,
+                        async move {
+                            response
+                                .await
+                                .map_err(|error| {
+                                    tracing::warn!(%error, "incoming-storage-proof-request-error");
+                                })
+                                .ok()
+                        },
+                        service::ChainNetwork::respond_storage_proof,
+                    );
+                }
+                service::Event::CallProofRequestIn {
+                    peer_id,
+                    chain_index,
+                    config,
+                    request_id,
+                } => {
+                    tracing::debug!(%peer_id, "incoming-call-proof-request");
+                    let config = config.decode();
+
+                    let response = call_proof_request_response(
+                        inner.databases[chain_index].clone(),
+                        guarded.network.block_number_bytes(chain_index),
+                        config.block_hash,
+                        config.method.to_owned(),
+                        config.parameter_vectored.fold(Vec::new(), |mut a, b| {
+                            a.extend_from_slice(b);
+                            a
+                        }),
+                    );
+                    spawn_in_proof_request(
+                        inner,
+                        &mut guarded,
+                        peer_id,
+                        request_id,
+                        async move {
+                            response
+                                .await
+                                .map_err(|error| {
+                                    tracing::warn!(%error, "incoming-call-proof-request-error");
+                                })
+                                .ok()
+                        },
+                        service::ChainNetwork::respond_call_proof,
+                    );
+                }
+                service::Event::StateRequestIn {
+                    peer_id,
+                    chain_index,
+                    config,
+                    request_id,
+                } => {
+                    tracing::debug!(%peer_id, "incoming-state-request");
+                    let config = config.decode();
+
+                    // The database doesn't store child tries.
+                    let protocol::StateRequestStart::MainTrie(start_key) = config.start_key else {
+                        guarded.network.respond_state(request_id, None);
+                        continue;
+                    };
+
+                    let response = state_request_response(
+                        inner.databases[chain_index].clone(),
+                        inner.runtime_trie_versions.clone(),
+                        inner.state_request_subtrees.clone(),
+                        guarded.network.block_number_bytes(chain_index),
+                        *config.block_hash,
+                        start_key.to_vec(),
+                    );
+                    spawn_in_proof_request(
+                        inner,
+                        &mut guarded,
+                        peer_id,
+                        request_id,
+                        async move {
+                            response
+                                .await
+                                .map_err(|error| {
+                                    tracing::warn!(%error, "incoming-state-request-error");
+                                })
+                                .ok()
+                        },
+                        service::ChainNetwork::respond_state,
+                    );
+                }
+                service::Event::KademliaFindNodeRequestIn {
+                    peer_id,
+                    chain_index,
+                    target,
+                    request_id,
+                } => {
+                    tracing::debug!(%peer_id, "incoming-kademlia-find-node-request");
+                    // As done by other implementations, the requester is excluded from the
+                    // response, and the response contains at most 20 peers.
+                    let closer_peers = guarded
+                        .network
+                        .kademlia_closest_peers(chain_index, &target)
+                        .filter(|(p, _)| **p != peer_id)
+                        .take(20)
+                        .map(|(p, addrs)| (p.clone(), addrs.cloned().collect()))
+                        .collect::<Vec<_>>();
+                    guarded
+                        .network
+                        .respond_kademlia_find_node(request_id, &closer_peers);
+                }
                 service::Event::GrandpaCommitMessage {
                     chain_index,
                     peer_id,
@@ -972,6 +1858,30 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                         "grandpa-commit-message"
                     );
                 }
+                service::Event::GrandpaNotification {
+                    chain_index,
+                    peer_id,
+                    notification,
+                } => {
+                    tracing::debug!(%chain_index, %peer_id, "grandpa-notification");
+                    break Event::GrandpaNotification {
+                        chain_index,
+                        peer_id,
+                        notification,
+                    };
+                }
+                service::Event::Transactions {
+                    chain_index,
+                    peer_id,
+                    transactions,
+                } => {
+                    tracing::debug!(%chain_index, %peer_id, "transactions");
+                    break Event::Transactions {
+                        chain_index,
+                        peer_id,
+                        transactions,
+                    };
+                }
                 service::Event::ProtocolError { peer_id, error } => {
                     tracing::warn!(
                         %peer_id,
@@ -979,8 +1889,10 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                         "protocol-error"
                     );
 
-                    for chain_index in 0..guarded.network.num_chains() {
-                        guarded.unassign_slot_and_ban(chain_index, peer_id.clone());
+                    // The reputation of the peer has been decreased by the network service,
+                    // which might have banned it.
+                    if guarded.network.is_banned(&Instant::now(), &peer_id) {
+                        tracing::debug!(%peer_id, "peer-banned");
                     }
                     inner.wake_up_main_background_task.notify(1);
                 }
@@ -1025,7 +1937,7 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
         loop {
             let peer_to_assign = guarded
                 .network
-                .slots_to_assign(chain_index)
+                .slots_to_assign(chain_index, &now)
                 .filter(|peer_id| {
                     !guarded
                         .slots_assign_backoff
@@ -1034,7 +1946,9 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                 .next()
                 .cloned();
 
-            let Some(peer_to_assign) = peer_to_assign else { break };
+            let Some(peer_to_assign) = peer_to_assign else {
+                break;
+            };
             tracing::debug!(peer_id = %peer_to_assign, %chain_index, "slot-assigned");
             guarded.network.assign_out_slot(chain_index, peer_to_assign);
         }
@@ -1183,3 +2097,369 @@ async fn blocks_request_response(
         })
         .await
 }
+
+/// Starts answering an incoming storage proof, call proof, or state request in the background.
+///
+/// `response` is the future that builds the response, and `respond` the method of the
+/// [`service::ChainNetwork`] to call with the response. The request is immediately refused if
+/// too many requests are already being answered.
+fn spawn_in_proof_request(
+    inner: &Arc<Inner>,
+    guarded: &mut Guarded,
+    peer_id: PeerId,
+    request_id: service::InRequestId,
+    response: impl Future<Output = Option<Vec<u8>>> + Send + 'static,
+    respond: fn(&mut service::ChainNetwork<Instant>, service::InRequestId, Option<Vec<u8>>),
+) {
+    let num_from_peer = guarded
+        .in_proof_requests
+        .values()
+        .filter(|p| **p == peer_id)
+        .count();
+    if guarded.in_proof_requests.len() >= MAX_IN_PROOF_REQUESTS
+        || num_from_peer >= MAX_IN_PROOF_REQUESTS_PER_PEER
+    {
+        tracing::debug!(%peer_id, "incoming-proof-request-refused");
+        respond(&mut guarded.network, request_id, None);
+        return;
+    }
+
+    guarded.in_proof_requests.insert(request_id, peer_id);
+
+    let inner = inner.clone();
+    let _ = guarded
+        .in_proof_requests_tasks_tx
+        .unbounded_send(Box::pin(async move {
+            let response = response.await;
+
+            // The request might have been cancelled in the meanwhile, in which case it has been
+            // removed from `in_proof_requests`.
+            let mut guarded = inner.guarded.lock().await;
+            if guarded.in_proof_requests.remove(&request_id).is_some() {
+                respond(&mut guarded.network, request_id, response);
+                inner.wake_up_main_background_task.notify(1);
+            }
+        }));
+}
+
+/// Builds a Merkle proof of the given keys of the storage of the given block.
+async fn storage_proof_request_response(
+    database: Arc<database_thread::DatabaseThread>,
+    runtime_trie_versions: Arc<RuntimeTrieVersions>,
+    block_number_bytes: usize,
+    block_hash: [u8; 32],
+    keys: Vec<Vec<u8>>,
+) -> Result<Vec<u8>, IncomingProofRequestError> {
+    database
+        .with_database(move |database| {
+            let state_root = block_state_root(database, block_number_bytes, &block_hash)?;
+            let trie_version = block_trie_version(database, &runtime_trie_versions, &block_hash)?;
+            build_storage_proof(database, &block_hash, &state_root, trie_version, keys)
+        })
+        .await
+}
+
+/// Performs the given runtime call against the storage of the given block, and builds a Merkle
+/// proof of all the storage items accessed during the call.
+async fn call_proof_request_response(
+    database: Arc<database_thread::DatabaseThread>,
+    block_number_bytes: usize,
+    block_hash: [u8; 32],
+    method: String,
+    parameter: Vec<u8>,
+) -> Result<Vec<u8>, IncomingProofRequestError> {
+    database
+        .with_database(move |database| {
+            let state_root = block_state_root(database, block_number_bytes, &block_hash)?;
+
+            // The runtime code and heap pages are always part of the proof, as the remote needs
+            // them in order to perform the call.
+            let mut accessed_keys = vec![b":code".to_vec(), b":heappages".to_vec()];
+
+            let runtime = block_runtime(database, &block_hash)?;
+            let trie_version = runtime_trie_version(&runtime)?;
+
+            let mut call = read_only_runtime_host::run(read_only_runtime_host::Config {
+                virtual_machine: runtime,
+                function_to_call: &method,
+                parameter: iter::once(&parameter),
+            })
+            .map_err(|(error, _)| IncomingProofRequestError::StartError(error))?;
+
+            loop {
+                match call {
+                    read_only_runtime_host::RuntimeHostVm::Finished(Ok(_)) => break,
+                    read_only_runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                        return Err(IncomingProofRequestError::Execution(error.detail))
+                    }
+                    read_only_runtime_host::RuntimeHostVm::StorageGet(get) => {
+                        let key = get.key().as_ref().to_vec();
+                        let value = database
+                            .block_storage_top_trie_get(&block_hash, &key)
+                            .map_err(IncomingProofRequestError::Storage)?;
+                        call = get.inject_value(value.as_ref().map(iter::once));
+                        accessed_keys.push(key);
+                    }
+                    read_only_runtime_host::RuntimeHostVm::NextKey(next_key) => {
+                        let key = next_key.key().as_ref().to_vec();
+                        let next = database
+                            .block_storage_top_trie_next_key(&block_hash, &key)
+                            .map_err(IncomingProofRequestError::Storage)?;
+                        accessed_keys.push(key);
+                        accessed_keys.extend(next.clone());
+                        call = next_key.inject_key(next);
+                    }
+                    read_only_runtime_host::RuntimeHostVm::StorageRoot(storage_root) => {
+                        call = storage_root.resume(&state_root);
+                    }
+                    read_only_runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                        call = sig.verify_and_resume();
+                    }
+                }
+            }
+
+            build_storage_proof(
+                database,
+                &block_hash,
+                &state_root,
+                trie_version,
+                accessed_keys,
+            )
+        })
+        .await
+}
+
+/// Builds a Merkle proof of the storage entries of the given block starting at the given key,
+/// for a total size of at most 2MiB.
+///
+/// Calculating the proof requires the Merkle values of all the trie nodes of the storage. The
+/// storage is read entirely the first time a block is requested, and the Merkle values of its
+/// large subtrees are kept in cache. Afterwards, only the entries close to the requested ones
+/// are read.
+async fn state_request_response(
+    database: Arc<database_thread::DatabaseThread>,
+    runtime_trie_versions: Arc<RuntimeTrieVersions>,
+    state_request_subtrees: Arc<StateRequestSubtrees>,
+    block_number_bytes: usize,
+    block_hash: [u8; 32],
+    start_key: Vec<u8>,
+) -> Result<Vec<u8>, IncomingProofRequestError> {
+    database
+        .with_database(move |database| {
+            let state_root = block_state_root(database, block_number_bytes, &block_hash)?;
+
+            let cached = state_request_subtrees
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(hash, _)| *hash == block_hash)
+                .map(|(_, subtrees)| subtrees.clone());
+            let subtrees = match cached {
+                Some(subtrees) => subtrees,
+                None => {
+                    let trie_version =
+                        block_trie_version(database, &runtime_trie_versions, &block_hash)?;
+                    let mut builder = trie::proof_encode::SubtreesMerkleValuesBuilder::new(
+                        trie_version,
+                        NonZeroUsize::new(STATE_REQUEST_SUBTREES_MIN_ENTRIES).unwrap(),
+                    );
+                    database
+                        .block_storage_top_trie_for_each(&block_hash, &[], |key, value| {
+                            builder.push(key, value);
+                            true
+                        })
+                        .map_err(IncomingProofRequestError::Storage)?;
+                    let subtrees = Arc::new(builder.build());
+                    if *subtrees.trie_root_hash() != state_root {
+                        return Err(IncomingProofRequestError::StateRootMismatch);
+                    }
+
+                    let mut cache = state_request_subtrees.lock().unwrap();
+                    if cache.len() >= STATE_REQUEST_SUBTREES_CACHE_LEN {
+                        cache.pop_front();
+                    }
+                    cache.push_back((block_hash, subtrees.clone()));
+                    subtrees
+                }
+            };
+
+            let range_proof = trie::proof_encode::build_range_proof(
+                &subtrees,
+                &start_key,
+                2 * 1024 * 1024,
+                |start, callback| {
+                    database.block_storage_top_trie_for_each(&block_hash, start, callback)
+                },
+            )
+            .map_err(IncomingProofRequestError::Storage)?;
+
+            if range_proof.trie_root_hash != state_root {
+                return Err(IncomingProofRequestError::StateRootMismatch);
+            }
+
+            Ok(range_proof.proof)
+        })
+        .await
+}
+
+/// Cache of the Merkle values of the large subtrees of the storage of blocks, used when
+/// answering state requests. Contains at most [`STATE_REQUEST_SUBTREES_CACHE_LEN`] blocks, from
+/// the least recently to the most recently inserted.
+type StateRequestSubtrees =
+    std::sync::Mutex<VecDeque<([u8; 32], Arc<trie::proof_encode::SubtreesMerkleValues>)>>;
+
+/// Maximum number of blocks in [`StateRequestSubtrees`].
+const STATE_REQUEST_SUBTREES_CACHE_LEN: usize = 4;
+
+/// Minimum number of entries in a subtree for its Merkle value to be in
+/// [`StateRequestSubtrees`]. The entries of the smaller subtrees next to the requested range are
+/// read from the database when answering a state request.
+const STATE_REQUEST_SUBTREES_MIN_ENTRIES: usize = 256;
+
+/// Cache of the version of the trie entries used by runtimes, indexed by the hash of the
+/// runtime code. Determining this version requires compiling the runtime.
+type RuntimeTrieVersions =
+    std::sync::Mutex<HashMap<[u8; 32], trie::TrieEntryVersion, fnv::FnvBuildHasher>>;
+
+/// Returns the state trie root hash found in the header of the given block.
+fn block_state_root(
+    database: &full_sqlite::SqliteFullDatabase,
+    block_number_bytes: usize,
+    block_hash: &[u8; 32],
+) -> Result<[u8; 32], IncomingProofRequestError> {
+    let header = database
+        .block_scale_encoded_header(block_hash)
+        .map_err(|err| IncomingProofRequestError::Storage(err.into()))?
+        .ok_or(IncomingProofRequestError::UnknownBlock)?;
+    Ok(*header::decode(&header, block_number_bytes)
+        .map_err(IncomingProofRequestError::InvalidHeader)?
+        .state_root)
+}
+
+/// Compiles the runtime found in the storage of the given block.
+fn block_runtime(
+    database: &full_sqlite::SqliteFullDatabase,
+    block_hash: &[u8; 32],
+) -> Result<host::HostVmPrototype, IncomingProofRequestError> {
+    let code = database
+        .block_storage_top_trie_get(block_hash, b":code")
+        .map_err(IncomingProofRequestError::Storage)?
+        .ok_or(IncomingProofRequestError::NoCode)?;
+    let heap_pages = database
+        .block_storage_top_trie_get(block_hash, b":heappages")
+        .map_err(IncomingProofRequestError::Storage)?;
+
+    host::HostVmPrototype::new(host::Config {
+        module: &code,
+        heap_pages: executor::storage_heap_pages_to_value(heap_pages.as_deref())
+            .map_err(IncomingProofRequestError::InvalidHeapPages)?,
+        exec_hint: executor::vm::ExecHint::Oneshot,
+        allow_unresolved_imports: true,
+    })
+    .map_err(IncomingProofRequestError::InvalidRuntime)
+}
+
+/// Returns the version of the trie entries used by the given runtime.
+fn runtime_trie_version(
+    runtime: &host::HostVmPrototype,
+) -> Result<trie::TrieEntryVersion, IncomingProofRequestError> {
+    match runtime.runtime_version().decode().state_version {
+        Some(0) | None => Ok(trie::TrieEntryVersion::V0),
+        Some(1) => Ok(trie::TrieEntryVersion::V1),
+        Some(_) => Err(IncomingProofRequestError::UnknownStateVersion),
+    }
+}
+
+/// Returns the version of the trie entries of the storage of the given block, which depends on
+/// the runtime of the block.
+fn block_trie_version(
+    database: &full_sqlite::SqliteFullDatabase,
+    runtime_trie_versions: &RuntimeTrieVersions,
+    block_hash: &[u8; 32],
+) -> Result<trie::TrieEntryVersion, IncomingProofRequestError> {
+    let code_hash = {
+        let code = database
+            .block_storage_top_trie_get(block_hash, b":code")
+            .map_err(IncomingProofRequestError::Storage)?
+            .ok_or(IncomingProofRequestError::NoCode)?;
+        <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], &code).as_bytes()).unwrap()
+    };
+
+    if let Some(version) = runtime_trie_versions.lock().unwrap().get(&code_hash) {
+        return Ok(*version);
+    }
+
+    let version = runtime_trie_version(&block_runtime(database, block_hash)?)?;
+
+    // Runtimes are rarely upgraded, and the cache is simply cleared if it becomes too large.
+    let mut runtime_trie_versions = runtime_trie_versions.lock().unwrap();
+    if runtime_trie_versions.len() >= 8 {
+        runtime_trie_versions.clear();
+    }
+    runtime_trie_versions.insert(code_hash, version);
+
+    Ok(version)
+}
+
+/// Builds a Merkle proof of the given keys out of the storage of the given block.
+///
+/// The database doesn't store the Merkle values of the trie nodes. All the entries of the
+/// storage are thus read one by one in order to calculate them, which takes a time proportional
+/// to the size of the storage but doesn't load the storage in memory.
+fn build_storage_proof(
+    database: &full_sqlite::SqliteFullDatabase,
+    block_hash: &[u8; 32],
+    state_root: &[u8; 32],
+    trie_version: trie::TrieEntryVersion,
+    keys: Vec<Vec<u8>>,
+) -> Result<Vec<u8>, IncomingProofRequestError> {
+    let mut builder = trie::proof_encode::EntriesProofBuilder::new(trie_version, keys);
+    database
+        .block_storage_top_trie_for_each(block_hash, &[], |key, value| {
+            builder.push(key, value);
+            true
+        })
+        .map_err(IncomingProofRequestError::Storage)?;
+
+    let (trie_root_hash, proof) = builder.build_to_vec();
+    if trie_root_hash != *state_root {
+        return Err(IncomingProofRequestError::StateRootMismatch);
+    }
+
+    Ok(proof)
+}
+
+/// Error potentially returned when answering an incoming storage proof, call proof, or state
+/// request.
+#[derive(Debug, derive_more::Display)]
+enum IncomingProofRequestError {
+    /// Block isn't in the database.
+    UnknownBlock,
+    /// Error while decoding the header of the block.
+    #[display(fmt = "Invalid block header: {}", _0)]
+    InvalidHeader(header::Error),
+    /// Error while accessing the storage of the block.
+    #[display(fmt = "{}", _0)]
+    Storage(full_sqlite::StorageAccessError),
+    /// The trie root hash of the storage of the block doesn't match its header.
+    #[display(fmt = "Storage of the block doesn't match its state trie root hash")]
+    StateRootMismatch,
+    /// The storage of the block doesn't contain any runtime.
+    #[display(fmt = "No runtime found in the storage of the block")]
+    NoCode,
+    /// Invalid value for the `:heappages` storage item.
+    #[display(fmt = "Invalid heap pages: {}", _0)]
+    InvalidHeapPages(executor::InvalidHeapPagesError),
+    /// Failed to compile the runtime.
+    #[display(fmt = "Invalid runtime: {}", _0)]
+    InvalidRuntime(host::NewErr),
+    /// The runtime reports a state version that isn't supported.
+    #[display(fmt = "Unknown state version reported by the runtime")]
+    UnknownStateVersion,
+    /// Failed to start the runtime call.
+    #[display(fmt = "Failed to start runtime call: {}", _0)]
+    StartError(host::StartErr),
+    /// Error during the runtime call.
+    #[display(fmt = "Error during runtime call: {}", _0)]
+    Execution(read_only_runtime_host::ErrorDetail),
+}
@@ -0,0 +1,549 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Background service that runs the off-chain worker of each block after it has been imported.
+//!
+//! Because the database only contains the storage of finalized blocks, the off-chain worker is
+//! run against each newly-finalized block. If the service can't keep up with the finalized
+//! blocks, some blocks are skipped.
+
+use crate::run::{consensus_service, database_thread};
+
+use core::time::Duration;
+use futures::prelude::*;
+use smoldot::{
+    database::full_sqlite,
+    executor::{self, host, offchain_worker},
+    identity::keystore,
+    informant::HashDisplay,
+    libp2p::{multiaddr::Multiaddr, peer_id::PeerId},
+    transactions::validate,
+};
+use std::{
+    io, iter,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+mod http;
+
+/// Configuration for a [`OffchainWorkerService`].
+pub struct Config<'a> {
+    /// Closure that spawns background tasks.
+    pub tasks_executor: &'a mut dyn FnMut(future::BoxFuture<'static, ()>),
+
+    /// Database to read the storage of the chain from, and where both kinds of off-chain
+    /// storage are located.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Service that synchronizes the chain. Used to be notified of new blocks.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Number of bytes of the block number in the headers of the chain.
+    pub block_number_bytes: usize,
+
+    /// Value reported to the off-chain workers when they ask whether the node is a validator.
+    pub is_validator: bool,
+
+    /// Keystore in which the off-chain workers can generate keys and list them.
+    pub keystore: Arc<keystore::Keystore>,
+
+    /// Identity of the local node on the peer-to-peer network.
+    pub local_peer_id: PeerId,
+
+    /// Addresses the peer-to-peer networking is listening on.
+    pub listen_addresses: Vec<Multiaddr>,
+}
+
+/// Running off-chain workers service. The background task stops when this object is dropped.
+pub struct OffchainWorkerService {
+    /// Aborts the background task when triggered.
+    background_task_abort: future::AbortHandle,
+}
+
+impl OffchainWorkerService {
+    /// Initializes a new [`OffchainWorkerService`].
+    pub fn new(config: Config<'_>) -> Self {
+        let mut background = Background {
+            database: config.database,
+            block_number_bytes: config.block_number_bytes,
+            is_validator: config.is_validator,
+            keystore: config.keystore,
+            local_peer_id: config.local_peer_id,
+            listen_addresses: config.listen_addresses,
+            runtime_cache: None,
+            consensus_service: config.consensus_service.clone(),
+        };
+
+        let consensus_service = config.consensus_service;
+        let (background_task_abort, abort_registration) = future::AbortHandle::new_pair();
+
+        (config.tasks_executor)(Box::pin(
+            future::Abortable::new(
+                async move {
+                    // The subscription to the consensus service is closed if the notifications
+                    // aren't processed quickly enough, in which case we simply subscribe again.
+                    loop {
+                        let subscribe_all = consensus_service.subscribe_all(16).await;
+                        let mut new_blocks = subscribe_all.new_blocks;
+                        while let Some(notification) = new_blocks.next().await {
+                            if let consensus_service::Notification::Finalized {
+                                hash,
+                                scale_encoded_header,
+                                ..
+                            } = notification
+                            {
+                                background.run_block(&hash, &scale_encoded_header).await;
+                            }
+                        }
+                    }
+                },
+                abort_registration,
+            )
+            .map(|_| ()),
+        ));
+
+        OffchainWorkerService {
+            background_task_abort,
+        }
+    }
+}
+
+impl Drop for OffchainWorkerService {
+    fn drop(&mut self) {
+        self.background_task_abort.abort();
+    }
+}
+
+struct Background {
+    /// See [`Config::database`].
+    database: Arc<database_thread::DatabaseThread>,
+
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+
+    /// See [`Config::is_validator`].
+    is_validator: bool,
+
+    /// See [`Config::keystore`].
+    keystore: Arc<keystore::Keystore>,
+
+    /// See [`Config::local_peer_id`].
+    local_peer_id: PeerId,
+
+    /// See [`Config::listen_addresses`].
+    listen_addresses: Vec<Multiaddr>,
+
+    /// Runtime that has been used in the most recent execution, if any.
+    ///
+    /// Compiling a runtime is expensive, and consecutive blocks are very likely to use the same
+    /// runtime.
+    runtime_cache: Option<CachedRuntime>,
+
+    /// See [`Config::consensus_service`]. Transactions submitted by the off-chain worker are
+    /// added to its transactions pool.
+    consensus_service: Arc<consensus_service::ConsensusService>,
+}
+
+/// See [`Background::runtime_cache`].
+struct CachedRuntime {
+    /// Value of the `:code` storage item the runtime has been compiled from.
+    code: Vec<u8>,
+    /// Value of the `:heappages` storage item the runtime has been compiled with.
+    heap_pages: Option<Vec<u8>>,
+    /// Compiled runtime.
+    runtime: host::HostVmPrototype,
+}
+
+impl Background {
+    /// Runs the off-chain worker of the given block until it finishes. Errors are logged.
+    async fn run_block(&mut self, block_hash: &[u8; 32], scale_encoded_header: &[u8]) {
+        let CachedRuntime {
+            code,
+            heap_pages,
+            runtime,
+        } = match self.runtime(block_hash).await {
+            Ok(runtime) => runtime,
+            Err(error) => {
+                tracing::warn!(
+                    block = %HashDisplay(block_hash), %error,
+                    "offchain-worker-runtime-error"
+                );
+                return;
+            }
+        };
+
+        let mut http_requests = http::HttpRequests::new();
+
+        let mut worker = offchain_worker::run(offchain_worker::Config {
+            runtime,
+            scale_encoded_header,
+            block_number_bytes: self.block_number_bytes,
+        });
+
+        let result = loop {
+            match worker {
+                offchain_worker::OffchainWorker::Finished {
+                    result,
+                    virtual_machine,
+                } => {
+                    self.runtime_cache = Some(CachedRuntime {
+                        code,
+                        heap_pages,
+                        runtime: virtual_machine,
+                    });
+                    break result.map_err(RunError::Execution);
+                }
+                offchain_worker::OffchainWorker::StorageGet(get) => {
+                    // TODO: child tries aren't stored in the database
+                    if get.child_trie().is_some() {
+                        worker = get.inject_value(None::<iter::Empty<Vec<u8>>>);
+                        continue;
+                    }
+
+                    let key = get.key().as_ref().to_vec();
+                    let block_hash = *block_hash;
+                    let value = self
+                        .database
+                        .with_database(move |database| {
+                            database.block_storage_top_trie_get(&block_hash, &key)
+                        })
+                        .await;
+                    match value {
+                        Ok(value) => worker = get.inject_value(value.as_ref().map(iter::once)),
+                        Err(error) => break Err(RunError::Storage(error)),
+                    }
+                }
+                offchain_worker::OffchainWorker::NextKey(next_key) => {
+                    // TODO: child tries aren't stored in the database
+                    if next_key.child_trie().is_some() {
+                        worker = next_key.inject_key(None::<Vec<u8>>);
+                        continue;
+                    }
+
+                    let key = next_key.key().as_ref().to_vec();
+                    let block_hash = *block_hash;
+                    let next = self
+                        .database
+                        .with_database(move |database| {
+                            database.block_storage_top_trie_next_key(&block_hash, &key)
+                        })
+                        .await;
+                    match next {
+                        Ok(next) => worker = next_key.inject_key(next),
+                        Err(error) => break Err(RunError::Storage(error)),
+                    }
+                }
+                offchain_worker::OffchainWorker::PrefixKeys(prefix_keys) => {
+                    // TODO: child tries aren't stored in the database
+                    if prefix_keys.child_trie().is_some() {
+                        worker = prefix_keys.inject_keys_ordered(iter::empty::<Vec<u8>>());
+                        continue;
+                    }
+
+                    let prefix = prefix_keys.prefix().as_ref().to_vec();
+                    let block_hash = *block_hash;
+                    let keys = self
+                        .database
+                        .with_database(move |database| {
+                            database.block_storage_top_trie_keys(&block_hash, &prefix)
+                        })
+                        .await;
+                    match keys {
+                        Ok(keys) => worker = prefix_keys.inject_keys_ordered(keys.into_iter()),
+                        Err(error) => break Err(RunError::Storage(error)),
+                    }
+                }
+                offchain_worker::OffchainWorker::IsValidator(req) => {
+                    worker = req.resume(self.is_validator);
+                }
+                offchain_worker::OffchainWorker::SubmitTransaction(req) => {
+                    let transaction = req.transaction().as_ref().to_vec();
+                    tracing::debug!(
+                        transaction = %hex::encode(&transaction),
+                        "offchain-worker-transaction-submitted"
+                    );
+                    self.consensus_service
+                        .submit_transaction(transaction, validate::TransactionSource::Local)
+                        .await;
+                    worker = req.resume(true);
+                }
+                offchain_worker::OffchainWorker::EcdsaGenerate(req) => {
+                    let namespace =
+                        match keystore::KeyNamespace::from_key_type_id(req.key_type_id()) {
+                            Some(ns) => ns,
+                            None => break Err(RunError::UnknownKeyType(*req.key_type_id())),
+                        };
+
+                    // Similar to Substrate, keys derived from a seed phrase are only kept in
+                    // memory, while randomly-generated keys are saved on disk.
+                    let public_key = match req.seed() {
+                        Some(seed) => self
+                            .keystore
+                            .insert_ecdsa_from_seed_phrase(namespace, seed)
+                            .await
+                            .map_err(RunError::EcdsaSeedPhrase),
+                        None => self
+                            .keystore
+                            .generate_ecdsa(namespace, true)
+                            .await
+                            .map_err(RunError::KeySave),
+                    };
+
+                    match public_key {
+                        Ok(public_key) => worker = req.resume(&public_key),
+                        Err(error) => break Err(error),
+                    }
+                }
+                offchain_worker::OffchainWorker::EcdsaPublicKeys(req) => {
+                    let namespace = keystore::KeyNamespace::from_key_type_id(req.key_type_id());
+                    let public_keys = self
+                        .keystore
+                        .keys_ecdsa()
+                        .await
+                        .filter(|(ns, _)| Some(*ns) == namespace)
+                        .map(|(_, public_key)| public_key)
+                        .collect::<Vec<_>>();
+                    worker = req.resume(public_keys.into_iter());
+                }
+                offchain_worker::OffchainWorker::NetworkState(req) => {
+                    worker = req.resume(Ok((
+                        self.local_peer_id.as_bytes(),
+                        self.listen_addresses.iter(),
+                    )));
+                }
+                offchain_worker::OffchainWorker::Timestamp(req) => {
+                    worker = req.resume(unix_time_ms());
+                }
+                offchain_worker::OffchainWorker::SleepUntil(req) => {
+                    let now = unix_time_ms();
+                    if req.deadline() > now {
+                        futures_timer::Delay::new(Duration::from_millis(req.deadline() - now))
+                            .await;
+                    }
+                    worker = req.resume();
+                }
+                offchain_worker::OffchainWorker::RandomSeed(req) => {
+                    worker = req.resume(&rand::random());
+                }
+                offchain_worker::OffchainWorker::LocalStorageGet(req) => {
+                    let key = database_key(req.kind(), req.key().as_ref());
+                    let value = self
+                        .database
+                        .with_database(move |database| database.offchain_storage_get(&key))
+                        .await;
+                    match value {
+                        Ok(value) => worker = req.resume(value.as_deref()),
+                        Err(error) => {
+                            break Err(RunError::Storage(full_sqlite::StorageAccessError::Access(
+                                error,
+                            )))
+                        }
+                    }
+                }
+                offchain_worker::OffchainWorker::LocalStorageSet(req) => {
+                    let key = database_key(req.kind(), req.key().as_ref());
+                    let value = req.value().map(|v| v.as_ref().to_vec());
+                    let result = self
+                        .database
+                        .with_database(move |database| {
+                            database.offchain_storage_set(&key, value.as_deref())
+                        })
+                        .await;
+                    match result {
+                        Ok(()) => worker = req.resume(),
+                        Err(error) => {
+                            break Err(RunError::Storage(full_sqlite::StorageAccessError::Access(
+                                error,
+                            )))
+                        }
+                    }
+                }
+                offchain_worker::OffchainWorker::LocalStorageCompareAndSet(req) => {
+                    let key = database_key(req.kind(), req.key().as_ref());
+                    let old_value = req.old_value().map(|v| v.to_vec());
+                    let new_value = req.new_value().as_ref().to_vec();
+                    let result = self
+                        .database
+                        .with_database(move |database| {
+                            database.offchain_storage_compare_and_set(
+                                &key,
+                                old_value.as_deref(),
+                                &new_value,
+                            )
+                        })
+                        .await;
+                    match result {
+                        Ok(replaced) => worker = req.resume(replaced),
+                        Err(error) => {
+                            break Err(RunError::Storage(full_sqlite::StorageAccessError::Access(
+                                error,
+                            )))
+                        }
+                    }
+                }
+                offchain_worker::OffchainWorker::HttpRequestStart(req) => {
+                    let result = http_requests.start(req.method().as_ref(), req.uri().as_ref());
+                    worker = req.resume(result);
+                }
+                offchain_worker::OffchainWorker::HttpRequestAddHeader(req) => {
+                    let result = http_requests.add_header(
+                        req.request_id(),
+                        req.name().as_ref(),
+                        req.value().as_ref(),
+                    );
+                    worker = req.resume(result);
+                }
+                offchain_worker::OffchainWorker::HttpRequestWriteBody(req) => {
+                    let result = http_requests.write_body(req.request_id(), req.chunk().as_ref());
+                    worker = req.resume(result);
+                }
+                offchain_worker::OffchainWorker::HttpResponseWait(req) => {
+                    let statuses = http_requests
+                        .response_wait(req.request_ids(), req.deadline())
+                        .await;
+                    worker = req.resume(statuses.into_iter());
+                }
+                offchain_worker::OffchainWorker::HttpResponseHeaders(req) => {
+                    let headers = http_requests.response_headers(req.request_id()).to_vec();
+                    worker = req.resume(headers.into_iter());
+                }
+                offchain_worker::OffchainWorker::HttpResponseReadBody(req) => {
+                    let result = http_requests
+                        .read_body(
+                            req.request_id(),
+                            usize::try_from(req.max_size()).unwrap_or(usize::MAX),
+                            req.deadline(),
+                        )
+                        .await;
+                    worker = req.resume(result.as_deref().map_err(|err| *err));
+                }
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                tracing::debug!(block = %HashDisplay(block_hash), "offchain-worker-finished");
+            }
+            Err(error) => {
+                tracing::warn!(
+                    block = %HashDisplay(block_hash), %error,
+                    "offchain-worker-error"
+                );
+            }
+        }
+    }
+
+    /// Returns the runtime of the given block, either by extracting it from
+    /// [`Background::runtime_cache`] or by compiling it.
+    async fn runtime(&mut self, block_hash: &[u8; 32]) -> Result<CachedRuntime, RunError> {
+        let (code, heap_pages) = {
+            let block_hash = *block_hash;
+            self.database
+                .with_database(move |database| {
+                    let code = database.block_storage_top_trie_get(&block_hash, b":code")?;
+                    let heap_pages =
+                        database.block_storage_top_trie_get(&block_hash, b":heappages")?;
+                    Ok::<_, full_sqlite::StorageAccessError>((code, heap_pages))
+                })
+                .await
+                .map_err(RunError::Storage)?
+        };
+
+        let code = code.ok_or(RunError::NoCode)?;
+
+        if let Some(cached) = self.runtime_cache.take() {
+            if cached.code == code && cached.heap_pages == heap_pages {
+                return Ok(cached);
+            }
+        }
+
+        let runtime = host::HostVmPrototype::new(host::Config {
+            module: &code,
+            heap_pages: executor::storage_heap_pages_to_value(heap_pages.as_deref())
+                .map_err(RunError::InvalidHeapPages)?,
+            exec_hint: executor::vm::ExecHint::CompileAheadOfTime,
+            allow_unresolved_imports: true,
+        })
+        .map_err(RunError::InvalidRuntime)?;
+
+        Ok(CachedRuntime {
+            code,
+            heap_pages,
+            runtime,
+        })
+    }
+}
+
+/// Error potentially returned by [`Background::run_block`].
+#[derive(Debug, derive_more::Display)]
+enum RunError {
+    /// Error while accessing the storage of the block or the off-chain storage.
+    #[display(fmt = "{}", _0)]
+    Storage(full_sqlite::StorageAccessError),
+    /// The storage of the block doesn't contain any runtime.
+    #[display(fmt = "No runtime found in the storage of the block")]
+    NoCode,
+    /// Invalid value for the `:heappages` storage item.
+    #[display(fmt = "Invalid heap pages: {}", _0)]
+    InvalidHeapPages(executor::InvalidHeapPagesError),
+    /// Failed to compile the runtime.
+    #[display(fmt = "Invalid runtime: {}", _0)]
+    InvalidRuntime(host::NewErr),
+    /// Error during the off-chain worker execution.
+    #[display(fmt = "Error during off-chain worker execution: {}", _0)]
+    Execution(offchain_worker::Error),
+    /// The off-chain worker has requested a key of a type unknown to the keystore.
+    #[display(fmt = "Unknown key type: {:?}", _0)]
+    UnknownKeyType([u8; 4]),
+    /// Invalid seed phrase passed by the off-chain worker when generating a key.
+    #[display(fmt = "Invalid seed phrase: {}", _0)]
+    EcdsaSeedPhrase(keystore::EcdsaSeedPhraseError),
+    /// Failed to save a newly-generated key on disk.
+    #[display(fmt = "Failed to save generated key: {}", _0)]
+    KeySave(io::Error),
+}
+
+/// Prefix of the keys of the off-chain storage of kind [`offchain_worker::StorageKind::Persistent`]
+/// in the database.
+///
+/// Both kinds of off-chain storage are saved in the same database table. Their keys are prefixed
+/// differently in order to not conflict with each other.
+const PERSISTENT_STORAGE_PREFIX: &[u8] = b"persistent:";
+
+/// Same as [`PERSISTENT_STORAGE_PREFIX`], but for [`offchain_worker::StorageKind::Local`].
+const LOCAL_STORAGE_PREFIX: &[u8] = b"local:";
+
+/// Returns the key in the database of the given key of the off-chain storage.
+fn database_key(kind: offchain_worker::StorageKind, key: &[u8]) -> Vec<u8> {
+    let prefix = match kind {
+        offchain_worker::StorageKind::Persistent => PERSISTENT_STORAGE_PREFIX,
+        offchain_worker::StorageKind::Local => LOCAL_STORAGE_PREFIX,
+    };
+
+    let mut out = Vec::with_capacity(prefix.len() + key.len());
+    out.extend_from_slice(prefix);
+    out.extend_from_slice(key);
+    out
+}
+
+/// Returns the current UNIX timestamp in milliseconds.
+fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+}
@@ -0,0 +1,423 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Minimal HTTP/1.1 client used to answer the HTTP requests of the off-chain workers.
+//!
+//! Only plain `http://` URLs are supported. Each request opens a new TCP connection, which is
+//! closed by the server once the response has been sent. Request and response bodies are
+//! entirely buffered in memory.
+
+use core::time::Duration;
+use futures::{future, prelude::*};
+use hashbrown::HashMap;
+use smoldot::executor::offchain_worker::{HttpError, HttpRequestStatus};
+use std::{
+    io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Maximum number of requests that can exist simultaneously.
+const MAX_REQUESTS: usize = 64;
+
+/// Maximum size, in bytes, of a response, including its headers.
+const MAX_RESPONSE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Collection of HTTP requests started by an off-chain worker.
+///
+/// Requests are identified by a `u16` that is passed back to the off-chain worker.
+pub struct HttpRequests {
+    /// List of requests, indexed by their identifier.
+    requests: HashMap<u16, Request, fnv::FnvBuildHasher>,
+
+    /// Identifier to try to assign to the next request.
+    next_request_id: u16,
+}
+
+enum Request {
+    /// Request has been started, but hasn't been sent yet.
+    Building {
+        method: String,
+        host: String,
+        port: u16,
+        path: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    },
+    /// Request has been sent, and the response is being waited for.
+    InProgress(future::BoxFuture<'static, Result<Response, io::Error>>),
+    /// Response has been received.
+    Finished(Response),
+    /// Request has failed.
+    Failed,
+}
+
+struct Response {
+    status_code: u16,
+    headers: Vec<(Vec<u8>, Vec<u8>)>,
+    body: Vec<u8>,
+    /// Number of bytes of [`Response::body`] that have already been read.
+    body_read_cursor: usize,
+}
+
+impl HttpRequests {
+    /// Creates a new empty collection of requests.
+    pub fn new() -> Self {
+        HttpRequests {
+            requests: HashMap::with_capacity_and_hasher(4, Default::default()),
+            next_request_id: 0,
+        }
+    }
+
+    /// Starts building a new request. Returns an error if the URI isn't supported or if there
+    /// are too many simultaneous requests.
+    pub fn start(&mut self, method: &str, uri: &str) -> Result<u16, ()> {
+        if self.requests.len() >= MAX_REQUESTS {
+            return Err(());
+        }
+
+        let (host, port, path) = parse_uri(uri).ok_or(())?;
+
+        let request_id = loop {
+            let id = self.next_request_id;
+            self.next_request_id = self.next_request_id.wrapping_add(1);
+            if !self.requests.contains_key(&id) {
+                break id;
+            }
+        };
+
+        self.requests.insert(
+            request_id,
+            Request::Building {
+                method: method.to_owned(),
+                host,
+                port,
+                path,
+                headers: Vec::new(),
+                body: Vec::new(),
+            },
+        );
+
+        Ok(request_id)
+    }
+
+    /// Adds a header to a request that hasn't been sent yet.
+    pub fn add_header(&mut self, request_id: u16, name: &str, value: &str) -> Result<(), ()> {
+        match self.requests.get_mut(&request_id) {
+            Some(Request::Building { headers, .. }) => {
+                headers.push((name.to_owned(), value.to_owned()));
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+
+    /// Appends a chunk to the body of a request that hasn't been sent yet. An empty chunk
+    /// indicates that the body is complete, in which case the request is sent.
+    pub fn write_body(&mut self, request_id: u16, chunk: &[u8]) -> Result<(), HttpError> {
+        match self.requests.get_mut(&request_id) {
+            Some(Request::Building { body, .. }) if !chunk.is_empty() => {
+                body.extend_from_slice(chunk);
+                Ok(())
+            }
+            Some(Request::Building { .. }) => {
+                self.send(request_id);
+                Ok(())
+            }
+            Some(Request::Failed) => {
+                self.requests.remove(&request_id);
+                Err(HttpError::IoError)
+            }
+            Some(Request::InProgress(_) | Request::Finished(_)) | None => Err(HttpError::Invalid),
+        }
+    }
+
+    /// Waits for the responses of the given requests, sending them first if necessary, and
+    /// returns their status in the same order as `request_ids`.
+    ///
+    /// `deadline` is a UNIX timestamp in milliseconds after which waiting stops.
+    pub async fn response_wait(
+        &mut self,
+        request_ids: &[u16],
+        deadline: Option<u64>,
+    ) -> Vec<HttpRequestStatus> {
+        for request_id in request_ids {
+            if matches!(
+                self.requests.get(request_id),
+                Some(Request::Building { .. })
+            ) {
+                self.send(*request_id);
+            }
+        }
+
+        let timeout = deadline.map(duration_until);
+
+        // Wait for all the requests in parallel.
+        let results = future::join_all(self.requests.iter_mut().filter_map(|(id, request)| {
+            let request_id = *id;
+            match request {
+                Request::InProgress(response) if request_ids.contains(&request_id) => {
+                    Some(async move {
+                        let result = match timeout {
+                            Some(timeout) => {
+                                match future::select(response, futures_timer::Delay::new(timeout))
+                                    .await
+                                {
+                                    future::Either::Left((result, _)) => Some(result),
+                                    future::Either::Right(_) => None,
+                                }
+                            }
+                            None => Some(response.await),
+                        };
+                        (request_id, result)
+                    })
+                }
+                _ => None,
+            }
+        }))
+        .await;
+
+        for (request_id, result) in results {
+            match result {
+                Some(Ok(response)) => {
+                    self.requests
+                        .insert(request_id, Request::Finished(response));
+                }
+                Some(Err(error)) => {
+                    tracing::debug!(%request_id, %error, "offchain-http-request-failed");
+                    self.requests.insert(request_id, Request::Failed);
+                }
+                None => {}
+            }
+        }
+
+        request_ids
+            .iter()
+            .map(|request_id| match self.requests.get(request_id) {
+                Some(Request::Finished(response)) => {
+                    HttpRequestStatus::Finished(response.status_code)
+                }
+                Some(Request::InProgress(_)) => HttpRequestStatus::DeadlineReached,
+                Some(Request::Failed) => HttpRequestStatus::IoError,
+                Some(Request::Building { .. }) => unreachable!(),
+                None => HttpRequestStatus::Invalid,
+            })
+            .collect()
+    }
+
+    /// Returns the headers of the response of the given request. Returns an empty list if the
+    /// response hasn't been received yet.
+    pub fn response_headers(&self, request_id: u16) -> &[(Vec<u8>, Vec<u8>)] {
+        match self.requests.get(&request_id) {
+            Some(Request::Finished(response)) => &response.headers,
+            _ => &[],
+        }
+    }
+
+    /// Reads a chunk of at most `max_size` bytes of the body of the response of the given
+    /// request, waiting for the response if necessary. An empty chunk indicates that the end of
+    /// the body has been reached, in which case the request is removed.
+    ///
+    /// `deadline` is a UNIX timestamp in milliseconds after which waiting stops.
+    pub async fn read_body(
+        &mut self,
+        request_id: u16,
+        max_size: usize,
+        deadline: Option<u64>,
+    ) -> Result<Vec<u8>, HttpError> {
+        match self.requests.get(&request_id) {
+            Some(Request::Building { .. } | Request::InProgress(_)) => {
+                let status = self.response_wait(&[request_id], deadline).await;
+                if matches!(status[..], [HttpRequestStatus::DeadlineReached]) {
+                    return Err(HttpError::DeadlineReached);
+                }
+            }
+            Some(Request::Finished(_) | Request::Failed) => {}
+            None => return Err(HttpError::Invalid),
+        }
+
+        match self.requests.get_mut(&request_id) {
+            Some(Request::Finished(response)) => {
+                let remaining = &response.body[response.body_read_cursor..];
+                let chunk = remaining[..remaining.len().min(max_size)].to_vec();
+                response.body_read_cursor += chunk.len();
+                if chunk.is_empty() {
+                    self.requests.remove(&request_id);
+                }
+                Ok(chunk)
+            }
+            Some(Request::Failed) => {
+                self.requests.remove(&request_id);
+                Err(HttpError::IoError)
+            }
+            _ => Err(HttpError::Invalid),
+        }
+    }
+
+    /// Turns a [`Request::Building`] into a [`Request::InProgress`].
+    fn send(&mut self, request_id: u16) {
+        let request = self.requests.get_mut(&request_id).unwrap();
+        let (method, host, port, path, headers, body) = match request {
+            Request::Building {
+                method,
+                host,
+                port,
+                path,
+                headers,
+                body,
+            } => (
+                method.clone(),
+                host.clone(),
+                *port,
+                path.clone(),
+                headers.clone(),
+                body.clone(),
+            ),
+            _ => unreachable!(),
+        };
+
+        *request = Request::InProgress(Box::pin(perform_request(
+            method, host, port, path, headers, body,
+        )));
+    }
+}
+
+/// Sends the request over a new TCP connection and reads the response.
+async fn perform_request(
+    method: String,
+    host: String,
+    port: u16,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+) -> Result<Response, io::Error> {
+    let mut stream = async_std::net::TcpStream::connect((&host[..], port)).await?;
+
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", method, path, host);
+    for (name, value) in &headers {
+        // These headers are always set below.
+        if name.eq_ignore_ascii_case("connection") || name.eq_ignore_ascii_case("content-length") {
+            continue;
+        }
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    ));
+
+    stream.write_all(request.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.flush().await?;
+
+    // Because of the `Connection: close` header, the server closes the connection after the
+    // response has been sent.
+    let mut response = Vec::new();
+    stream
+        .take(MAX_RESPONSE_SIZE)
+        .read_to_end(&mut response)
+        .await?;
+
+    let mut headers_buffer = [httparse::EMPTY_HEADER; 64];
+    let mut parsed = httparse::Response::new(&mut headers_buffer);
+    let headers_len = match parsed.parse(&response) {
+        Ok(httparse::Status::Complete(len)) => len,
+        Ok(httparse::Status::Partial) => {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "incomplete response",
+            ))
+        }
+        Err(error) => return Err(io::Error::new(io::ErrorKind::InvalidData, error)),
+    };
+
+    let status_code = parsed.code.unwrap_or(0);
+    let headers = parsed
+        .headers
+        .iter()
+        .map(|header| (header.name.as_bytes().to_vec(), header.value.to_vec()))
+        .collect::<Vec<_>>();
+
+    let is_chunked = headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case(b"transfer-encoding") && value.eq_ignore_ascii_case(b"chunked")
+    });
+
+    let body = if is_chunked {
+        decode_chunked(&response[headers_len..])
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid chunked encoding"))?
+    } else {
+        response[headers_len..].to_vec()
+    };
+
+    Ok(Response {
+        status_code,
+        headers,
+        body,
+        body_read_cursor: 0,
+    })
+}
+
+/// Parses an `http://` URI into a host, a port, and a path.
+fn parse_uri(uri: &str) -> Option<(String, u16, String)> {
+    let rest = uri.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, "/"),
+    };
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, 80),
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+
+    Some((host.to_owned(), port, path.to_owned()))
+}
+
+/// Decodes a body using the `chunked` transfer encoding. Returns `None` if the encoding is
+/// invalid.
+fn decode_chunked(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+
+    loop {
+        let line_end = data.windows(2).position(|w| w == b"\r\n")?;
+        let size_str = core::str::from_utf8(&data[..line_end]).ok()?;
+        let size_str = size_str.split(';').next()?.trim();
+        let size = usize::from_str_radix(size_str, 16).ok()?;
+        data = &data[line_end + 2..];
+
+        if size == 0 {
+            return Some(out);
+        }
+
+        if data.len() < size + 2 {
+            return None;
+        }
+        out.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
+
+/// Returns the duration between now and the given UNIX timestamp in milliseconds.
+fn duration_until(timestamp_ms: u64) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::new(0, 0));
+    Duration::from_millis(timestamp_ms).saturating_sub(now)
+}
@@ -0,0 +1,113 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Implementation of the `export-state` and `import-state` CLI commands.
+
+use crate::{cli, run};
+
+use smoldot::{chain_spec, database::full_sqlite, informant::HashDisplay};
+use std::{fs, io};
+
+/// Writes the finalized block of the database of the chain to the file indicated in the CLI
+/// options.
+///
+/// # Panic
+///
+/// Panics in case of error. This function is expected to be called from the `main` function.
+///
+pub fn export_state(cli_options: cli::CliOptionsExportState) {
+    let chain_spec = run::load_chain_spec(&cli_options.chain);
+    let base_storage_directory =
+        run::base_storage_directory().expect("Failed to fetch $HOME directory");
+
+    let database = match full_sqlite::open(full_sqlite::Config {
+        ty: full_sqlite::ConfigTy::Disk(&run::database_path(&base_storage_directory, &chain_spec)),
+        block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+        // The pruning mode of the database is left untouched.
+        pruning_mode: None,
+    })
+    .expect("Failed to open database")
+    {
+        full_sqlite::DatabaseOpen::Open(database) => database,
+        full_sqlite::DatabaseOpen::Empty(_) => panic!("The database of this chain is empty"),
+    };
+
+    let finalized_block_hash = database.finalized_block_hash().unwrap();
+
+    let file = fs::File::create(&cli_options.output).expect("Failed to create output file");
+    database
+        .export_finalized_state(
+            &finalized_block_hash,
+            &genesis_block_hash(&chain_spec),
+            io::BufWriter::new(file),
+        )
+        .unwrap_or_else(|err| panic!("Failed to export state: {}", err));
+
+    println!(
+        "Exported state of block {} to {}",
+        HashDisplay(&finalized_block_hash),
+        cli_options.output.display()
+    );
+}
+
+/// Initializes the database of the chain from the file indicated in the CLI options.
+///
+/// # Panic
+///
+/// Panics in case of error, including if the database of the chain already contains data. This
+/// function is expected to be called from the `main` function.
+///
+pub fn import_state(cli_options: cli::CliOptionsImportState) {
+    let chain_spec = run::load_chain_spec(&cli_options.chain);
+    let base_storage_directory =
+        run::base_storage_directory().expect("Failed to fetch $HOME directory");
+
+    let empty = match full_sqlite::open(full_sqlite::Config {
+        ty: full_sqlite::ConfigTy::Disk(&run::database_path(&base_storage_directory, &chain_spec)),
+        block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+        pruning_mode: Some(cli_options.pruning.0),
+    })
+    .expect("Failed to open database")
+    {
+        full_sqlite::DatabaseOpen::Open(_) => {
+            panic!("The database of this chain already exists and isn't empty")
+        }
+        full_sqlite::DatabaseOpen::Empty(empty) => empty,
+    };
+
+    let file = fs::File::open(&cli_options.input).expect("Failed to open input file");
+    let database = empty
+        .import_finalized_state(&genesis_block_hash(&chain_spec), io::BufReader::new(file))
+        .unwrap_or_else(|err| panic!("Failed to import state: {}", err));
+
+    let finalized_block_hash = database.finalized_block_hash().unwrap();
+    println!(
+        "Imported state of block {}",
+        HashDisplay(&finalized_block_hash)
+    );
+}
+
+/// Returns the hash of the genesis block of the given chain.
+fn genesis_block_hash(chain_spec: &chain_spec::ChainSpec) -> [u8; 32] {
+    chain_spec
+        .as_chain_information()
+        .expect("Failed to build the genesis block from the chain specification")
+        .0
+        .as_ref()
+        .finalized_block_header
+        .hash(chain_spec.block_number_bytes().into())
+}
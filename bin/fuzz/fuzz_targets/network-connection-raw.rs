@@ -40,6 +40,10 @@ libfuzzer_sys::fuzz_target!(|data: &[u8]| {
             // This timeout doesn't matter as we pass dummy time values.
             handshake_timeout: Duration::from_secs(5),
             ping_protocol: "ping".into(),
+            multiplexing_protocols: vec![
+                smoldot::libp2p::collection::MultiplexingProtocol::Yamux,
+                smoldot::libp2p::collection::MultiplexingProtocol::Mplex,
+            ],
             noise_key: smoldot::libp2p::connection::NoiseKey::new(&[0; 32]),
         });
 
@@ -36,11 +36,11 @@ use alloc::{
 use core::cmp;
 use smoldot::{
     chain,
-    database::finalized_serialize,
+    database::{finalized_serialize, transactions_serialize},
     libp2p::{multiaddr, PeerId},
 };
 
-use crate::{network_service, platform, sync_service};
+use crate::{network_service, platform, sync_service, transactions_service};
 
 /// A decoded database.
 pub struct DatabaseContent {
@@ -51,6 +51,9 @@ pub struct DatabaseContent {
     /// List of nodes that were known to be part of the peer-to-peer network when the database
     /// was encoded.
     pub known_nodes: Vec<(PeerId, Vec<multiaddr::Multiaddr>)>,
+    /// List of transactions that were pending in the transactions service when the database
+    /// was encoded.
+    pub pending_transactions: Vec<transactions_serialize::PendingTransaction>,
 }
 
 /// Serializes the finalized state of the chain, using the given services.
@@ -60,6 +63,7 @@ pub struct DatabaseContent {
 pub async fn encode_database<TPlat: platform::Platform>(
     network_service: &network_service::NetworkService<TPlat>,
     sync_service: &sync_service::SyncService<TPlat>,
+    transactions_service: &transactions_service::TransactionsService<TPlat>,
     genesis_block_hash: &[u8; 32],
     max_size: usize,
 ) -> String {
@@ -93,17 +97,27 @@ pub async fn encode_database<TPlat: platform::Platform>(
                 )
             })
             .collect(),
+        transactions: None,
     };
 
+    let mut pending_transactions = transactions_service.serialize_pending_transactions().await;
+
     // Cap the database length to the maximum size.
     loop {
+        database_draft.transactions = if pending_transactions.is_empty() {
+            None
+        } else {
+            let encoded = transactions_serialize::encode_transactions(&pending_transactions);
+            Some(serde_json::from_str(&encoded).unwrap())
+        };
+
         let serialized = serde_json::to_string(&database_draft).unwrap();
         if serialized.len() <= max_size {
             // Success!
             return serialized;
         }
 
-        if database_draft.nodes.is_empty() {
+        if database_draft.nodes.is_empty() && pending_transactions.is_empty() {
             // Can't shrink the database anymore. Return the string `"<too-large>"` which will
             // fail to decode but will indicate what is wrong.
             let dummy_message = "<too-large>";
@@ -116,6 +130,14 @@ pub async fn encode_database<TPlat: platform::Platform>(
 
         // Try to reduce the size of the database.
 
+        // Remove half of the pending transactions, starting from the end of the list.
+        // Transactions are removed before nodes, as losing them is less problematic than losing
+        // the nodes of the peer-to-peer network.
+        if !pending_transactions.is_empty() {
+            pending_transactions.truncate(pending_transactions.len() / 2);
+            continue;
+        }
+
         // Remove half of the nodes.
         // Which nodes are removed doesn't really matter.
         let mut nodes_to_remove = cmp::max(1, database_draft.nodes.len() / 2);
@@ -165,10 +187,20 @@ pub fn decode_database(encoded: &str, block_number_bytes: usize) -> Result<Datab
         })
         .collect::<Vec<_>>();
 
+    // Similarly, failing to decode the list of transactions simply leads to this list being
+    // ignored, as the transactions are not essential for the chain to function.
+    let pending_transactions = decoded
+        .transactions
+        .and_then(|transactions| {
+            transactions_serialize::decode_transactions(transactions.get()).ok()
+        })
+        .unwrap_or_default();
+
     Ok(DatabaseContent {
         genesis_block_hash,
         chain_information,
         known_nodes,
+        pending_transactions,
     })
 }
 
@@ -179,4 +211,8 @@ struct SerdeDatabase {
     genesis_hash: String,
     chain: Box<serde_json::value::RawValue>,
     nodes: hashbrown::HashMap<String, Vec<String>, fnv::FnvBuildHasher>,
+    /// Output of [`transactions_serialize::encode_transactions`]. Absent if no transaction was
+    /// pending.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transactions: Option<Box<serde_json::value::RawValue>>,
 }
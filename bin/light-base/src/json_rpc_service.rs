@@ -910,6 +910,62 @@ impl<TPlat: Platform> Background<TPlat> {
                 )
                 .await;
             }
+            methods::MethodCall::childstate_getKeys {
+                child_storage_key,
+                prefix,
+                hash,
+            } => {
+                self.childstate_get_keys(
+                    request_id,
+                    &state_machine_request_id,
+                    child_storage_key,
+                    prefix,
+                    hash,
+                )
+                .await;
+            }
+            methods::MethodCall::childstate_getStorage {
+                child_storage_key,
+                key,
+                hash,
+            } => {
+                self.childstate_get_storage(
+                    request_id,
+                    &state_machine_request_id,
+                    child_storage_key,
+                    key,
+                    hash,
+                )
+                .await;
+            }
+            methods::MethodCall::childstate_getStorageHash {
+                child_storage_key,
+                key,
+                hash,
+            } => {
+                self.childstate_get_storage_hash(
+                    request_id,
+                    &state_machine_request_id,
+                    child_storage_key,
+                    key,
+                    hash,
+                )
+                .await;
+            }
+            methods::MethodCall::childstate_getStorageSize {
+                child_storage_key,
+                key,
+                hash,
+            } => {
+                self.childstate_get_storage_size(
+                    request_id,
+                    &state_machine_request_id,
+                    child_storage_key,
+                    key,
+                    hash,
+                )
+                .await;
+            }
             methods::MethodCall::payment_queryInfo { extrinsic, hash } => {
                 self.payment_query_info(
                     request_id,
@@ -1215,10 +1271,6 @@ impl<TPlat: Platform> Background<TPlat> {
             | methods::MethodCall::author_removeExtrinsic { .. }
             | methods::MethodCall::author_rotateKeys { .. }
             | methods::MethodCall::babe_epochAuthorship { .. }
-            | methods::MethodCall::childstate_getKeys { .. }
-            | methods::MethodCall::childstate_getStorage { .. }
-            | methods::MethodCall::childstate_getStorageHash { .. }
-            | methods::MethodCall::childstate_getStorageSize { .. }
             | methods::MethodCall::grandpa_roundState { .. }
             | methods::MethodCall::offchain_localStorageGet { .. }
             | methods::MethodCall::offchain_localStorageSet { .. }
@@ -1427,6 +1479,43 @@ impl<TPlat: Platform> Background<TPlat> {
         Ok(result)
     }
 
+    /// Similar to [`Background::storage_query`], but queries the keys of the given default child
+    /// trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    async fn child_storage_query(
+        &self,
+        child_trie: &[u8],
+        keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        hash: &[u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
+        let (state_trie_root_hash, block_number) = self
+            .state_trie_root_hash(hash)
+            .await
+            .map_err(StorageQueryError::FindStorageRootHashError)?;
+
+        let result = self
+            .sync_service
+            .clone()
+            .child_storage_query(
+                sync_service::StorageQueryBlock {
+                    block_number,
+                    block_hash: hash,
+                    storage_trie_root: &state_trie_root_hash,
+                },
+                child_trie,
+                keys,
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+            )
+            .await
+            .map_err(StorageQueryError::StorageRetrieval)?;
+
+        Ok(result)
+    }
+
     /// Obtain a lock to the runtime of the given block against the runtime service.
     // TODO: return better error?
     async fn runtime_lock<'a>(
@@ -1583,7 +1672,7 @@ impl<TPlat: Platform> Background<TPlat> {
         // then performing the actual call. The first step is the longest and most difficult.
         let precall = self.runtime_lock(block_hash).await?;
 
-        let (runtime_call_lock, virtual_machine) = precall
+        let (mut runtime_call_lock, virtual_machine) = precall
             .start(
                 function_to_call,
                 call_parameters.clone(),
@@ -1653,11 +1742,17 @@ impl<TPlat: Platform> Background<TPlat> {
                     runtime_call = get.inject_value(storage_value.map(iter::once));
                 }
                 read_only_runtime_host::RuntimeHostVm::NextKey(nk) => {
-                    // TODO:
-                    runtime_call_lock.unlock(
-                        read_only_runtime_host::RuntimeHostVm::NextKey(nk).into_prototype(),
-                    );
-                    break Err(RuntimeCallError::NextKeyForbidden);
+                    let key = nk.key().as_ref().to_owned();
+                    let next_key = match runtime_call_lock.storage_next_key(&key, false).await {
+                        Ok(k) => k,
+                        Err(err) => {
+                            runtime_call_lock.unlock(
+                                read_only_runtime_host::RuntimeHostVm::NextKey(nk).into_prototype(),
+                            );
+                            break Err(RuntimeCallError::Call(err));
+                        }
+                    };
+                    runtime_call = nk.inject_key(next_key);
                 }
                 read_only_runtime_host::RuntimeHostVm::StorageRoot(storage_root) => {
                     runtime_call = storage_root.resume(runtime_call_lock.block_storage_root());
@@ -1689,7 +1784,6 @@ enum RuntimeCallError {
     Call(runtime_service::RuntimeCallError),
     StartError(host::StartErr),
     ReadOnlyRuntime(read_only_runtime_host::ErrorDetail),
-    NextKeyForbidden,
     /// Required runtime API isn't supported by the runtime.
     ApiNotFound,
     /// Version requirement of runtime API isn't supported.
@@ -176,7 +176,7 @@ impl<TPlat: Platform> Background<TPlat> {
                 };
 
                 let final_notif = match pre_runtime_call {
-                    Some(Ok((runtime_call_lock, virtual_machine))) => {
+                    Some(Ok((mut runtime_call_lock, virtual_machine))) => {
                         match runtime_host::run(runtime_host::Config {
                             virtual_machine,
                             function_to_call: &function_to_call,
@@ -184,6 +184,7 @@ impl<TPlat: Platform> Background<TPlat> {
                             top_trie_root_calculation_cache: None,
                             offchain_storage_changes: Default::default(),
                             storage_top_trie_changes: Default::default(),
+                            storage_child_tries_changes: Default::default(),
                         }) {
                             Err((error, prototype)) => {
                                 runtime_call_lock.unlock(prototype);
@@ -221,6 +222,22 @@ impl<TPlat: Platform> Background<TPlat> {
                                                 }
                                                 .to_json_call_object_parameters(None);
                                         }
+                                        runtime_host::RuntimeHostVm::StorageGet(get)
+                                            if get.child_trie().is_some() =>
+                                        {
+                                            // TODO: implement somehow
+                                            runtime_call_lock.unlock(
+                                                runtime_host::RuntimeHostVm::StorageGet(get)
+                                                    .into_prototype(),
+                                            );
+                                            break methods::ServerToClient::chainHead_unstable_callEvent {
+                                                    subscription: (&subscription_id).into(),
+                                                    result: methods::ChainHeadCallEvent::Inaccessible {
+                                                        error: "getting child trie storage not implemented".into(),
+                                                    },
+                                                }
+                                                .to_json_call_object_parameters(None);
+                                        }
                                         runtime_host::RuntimeHostVm::StorageGet(get) => {
                                             // TODO: what if the remote lied to us?
                                             let storage_value =
@@ -246,7 +263,9 @@ impl<TPlat: Platform> Background<TPlat> {
                                             runtime_call =
                                                 get.inject_value(storage_value.map(iter::once));
                                         }
-                                        runtime_host::RuntimeHostVm::NextKey(nk) => {
+                                        runtime_host::RuntimeHostVm::NextKey(nk)
+                                            if nk.child_trie().is_some() =>
+                                        {
                                             // TODO: implement somehow
                                             runtime_call_lock.unlock(
                                                 runtime_host::RuntimeHostVm::NextKey(nk)
@@ -255,28 +274,84 @@ impl<TPlat: Platform> Background<TPlat> {
                                             break methods::ServerToClient::chainHead_unstable_callEvent {
                                                     subscription: (&subscription_id).into(),
                                                     result: methods::ChainHeadCallEvent::Inaccessible {
-                                                        error: "getting next key not implemented".into(),
+                                                        error: "getting child trie storage not implemented".into(),
                                                     },
                                                 }
                                                 .to_json_call_object_parameters(None);
                                         }
-                                        runtime_host::RuntimeHostVm::PrefixKeys(nk) => {
+                                        runtime_host::RuntimeHostVm::NextKey(nk) => {
+                                            let key = nk.key().as_ref().to_owned();
+                                            let next_key = runtime_call_lock
+                                                .storage_next_key(&key, false)
+                                                .await;
+                                            let next_key = match next_key {
+                                                Ok(k) => k,
+                                                Err(error) => {
+                                                    runtime_call_lock.unlock(
+                                                        runtime_host::RuntimeHostVm::NextKey(nk)
+                                                            .into_prototype(),
+                                                    );
+                                                    break methods::ServerToClient::chainHead_unstable_callEvent {
+                                                            subscription: (&subscription_id).into(),
+                                                            result: methods::ChainHeadCallEvent::Inaccessible {
+                                                                error: error.to_string().into(),
+                                                            },
+                                                        }
+                                                        .to_json_call_object_parameters(None);
+                                                }
+                                            };
+                                            runtime_call = nk.inject_key(next_key);
+                                        }
+                                        runtime_host::RuntimeHostVm::PrefixKeys(prefix)
+                                            if prefix.child_trie().is_some() =>
+                                        {
                                             // TODO: implement somehow
                                             runtime_call_lock.unlock(
-                                                runtime_host::RuntimeHostVm::PrefixKeys(nk)
+                                                runtime_host::RuntimeHostVm::PrefixKeys(prefix)
                                                     .into_prototype(),
                                             );
                                             break methods::ServerToClient::chainHead_unstable_callEvent {
                                                     subscription: (&subscription_id).into(),
                                                     result: methods::ChainHeadCallEvent::Inaccessible {
-                                                        error: "getting prefix keys not implemented".into(),
+                                                        error: "getting child trie storage not implemented".into(),
                                                     },
                                                 }
                                                 .to_json_call_object_parameters(None);
                                         }
+                                        runtime_host::RuntimeHostVm::PrefixKeys(prefix) => {
+                                            let prefix_bytes = prefix.prefix().as_ref().to_owned();
+                                            let keys = runtime_call_lock
+                                                .storage_prefix_keys(&prefix_bytes)
+                                                .await;
+                                            let keys = match keys {
+                                                Ok(k) => k,
+                                                Err(error) => {
+                                                    runtime_call_lock.unlock(
+                                                        runtime_host::RuntimeHostVm::PrefixKeys(
+                                                            prefix,
+                                                        )
+                                                        .into_prototype(),
+                                                    );
+                                                    break methods::ServerToClient::chainHead_unstable_callEvent {
+                                                            subscription: (&subscription_id).into(),
+                                                            result: methods::ChainHeadCallEvent::Inaccessible {
+                                                                error: error.to_string().into(),
+                                                            },
+                                                        }
+                                                        .to_json_call_object_parameters(None);
+                                                }
+                                            };
+                                            runtime_call =
+                                                prefix.inject_keys_ordered(keys.into_iter());
+                                        }
                                         runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
                                             runtime_call = sig.verify_and_resume();
                                         }
+                                        runtime_host::RuntimeHostVm::Offchain(ctx) => {
+                                            runtime_call = runtime_host::RuntimeHostVm::Finished(
+                                                Err(ctx.forbid()),
+                                            );
+                                        }
                                     }
                                 }
                             }
@@ -1452,6 +1527,7 @@ impl<TPlat: Platform> Background<TPlat> {
         let response = crate::database::encode_database(
             &self.network_service.0,
             &self.sync_service,
+            &self.transactions_service,
             &self.genesis_block_hash,
             usize::try_from(max_size_bytes.unwrap_or(u64::max_value()))
                 .unwrap_or(usize::max_value()),
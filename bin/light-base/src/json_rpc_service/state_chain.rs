@@ -19,7 +19,7 @@
 
 use super::{Background, Platform, SubscriptionTy};
 
-use crate::runtime_service;
+use crate::{runtime_service, sync_service};
 
 use alloc::{
     borrow::ToOwned as _,
@@ -842,6 +842,214 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Handles a call to [`methods::MethodCall::childstate_getKeys`].
+    pub(super) async fn childstate_get_keys(
+        self: &Arc<Self>,
+        request_id: &str,
+        state_machine_request_id: &requests_subscriptions::RequestId,
+        child_storage_key: methods::HexString,
+        prefix: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) {
+        let child_trie = match child_trie_from_storage_key(&child_storage_key.0) {
+            Some(child_trie) => child_trie,
+            None => {
+                self.requests_subscriptions
+                    .respond(
+                        state_machine_request_id,
+                        invalid_child_storage_key_response(request_id),
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        // `hash` equal to `None` means "best block".
+        let hash = match hash {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
+                &sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        // Obtain the state trie root and height of the requested block.
+        // This is necessary to perform network storage queries.
+        let (state_root, block_number) = match self.state_trie_root_hash(&hash).await {
+            Ok(v) => v,
+            Err(err) => {
+                self.requests_subscriptions
+                    .respond(
+                        state_machine_request_id,
+                        json_rpc::parse::build_error_response(
+                            request_id,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                &format!("Failed to fetch block information: {}", err),
+                            ),
+                            None,
+                        ),
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        let outcome = self
+            .sync_service
+            .clone()
+            .child_storage_prefix_keys_query(
+                sync_service::StorageQueryBlock {
+                    block_number,
+                    block_hash: &hash,
+                    storage_trie_root: &state_root,
+                },
+                child_trie,
+                &prefix.0,
+                3,
+                Duration::from_secs(12),
+                NonZeroU32::new(1).unwrap(),
+            )
+            .await;
+
+        let response = match outcome {
+            Ok(keys) => {
+                let out = keys.into_iter().map(methods::HexString).collect::<Vec<_>>();
+                methods::Response::childstate_getKeys(out).to_json_response(request_id)
+            }
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(state_machine_request_id, response)
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::childstate_getStorage`].
+    pub(super) async fn childstate_get_storage(
+        self: &Arc<Self>,
+        request_id: &str,
+        state_machine_request_id: &requests_subscriptions::RequestId,
+        child_storage_key: methods::HexString,
+        key: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) {
+        let response = match self
+            .child_storage_value(request_id, &child_storage_key.0, &key.0, hash)
+            .await
+        {
+            Ok(Some(value)) => methods::Response::childstate_getStorage(methods::HexString(value))
+                .to_json_response(request_id),
+            Ok(None) => json_rpc::parse::build_success_response(request_id, "null"),
+            Err(response) => response,
+        };
+
+        self.requests_subscriptions
+            .respond(state_machine_request_id, response)
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::childstate_getStorageHash`].
+    pub(super) async fn childstate_get_storage_hash(
+        self: &Arc<Self>,
+        request_id: &str,
+        state_machine_request_id: &requests_subscriptions::RequestId,
+        child_storage_key: methods::HexString,
+        key: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) {
+        let response = match self
+            .child_storage_value(request_id, &child_storage_key.0, &key.0, hash)
+            .await
+        {
+            Ok(Some(value)) => {
+                let hash =
+                    <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], &value).as_bytes())
+                        .unwrap();
+                methods::Response::childstate_getStorageHash(methods::HashHexString(hash))
+                    .to_json_response(request_id)
+            }
+            Ok(None) => json_rpc::parse::build_success_response(request_id, "null"),
+            Err(response) => response,
+        };
+
+        self.requests_subscriptions
+            .respond(state_machine_request_id, response)
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::childstate_getStorageSize`].
+    pub(super) async fn childstate_get_storage_size(
+        self: &Arc<Self>,
+        request_id: &str,
+        state_machine_request_id: &requests_subscriptions::RequestId,
+        child_storage_key: methods::HexString,
+        key: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) {
+        let response = match self
+            .child_storage_value(request_id, &child_storage_key.0, &key.0, hash)
+            .await
+        {
+            Ok(Some(value)) => {
+                methods::Response::childstate_getStorageSize(u64::try_from(value.len()).unwrap())
+                    .to_json_response(request_id)
+            }
+            Ok(None) => json_rpc::parse::build_success_response(request_id, "null"),
+            Err(response) => response,
+        };
+
+        self.requests_subscriptions
+            .respond(state_machine_request_id, response)
+            .await;
+    }
+
+    /// Common code for the `childstate_getStorage*` functions. Fetches the storage value of the
+    /// given key in the given child trie.
+    ///
+    /// On error, returns the JSON-RPC response to send back.
+    async fn child_storage_value(
+        self: &Arc<Self>,
+        request_id: &str,
+        child_storage_key: &[u8],
+        key: &[u8],
+        hash: Option<methods::HashHexString>,
+    ) -> Result<Option<Vec<u8>>, String> {
+        let child_trie = match child_trie_from_storage_key(child_storage_key) {
+            Some(child_trie) => child_trie,
+            None => return Err(invalid_child_storage_key_response(request_id)),
+        };
+
+        // `hash` equal to `None` means "best block".
+        let hash = match hash {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
+                &sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        self.child_storage_query(
+            child_trie,
+            iter::once(key),
+            &hash,
+            3,
+            Duration::from_secs(12),
+            NonZeroU32::new(1).unwrap(),
+        )
+        .await
+        .map(|mut values| values.pop().unwrap())
+        .map_err(|error| {
+            json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            )
+        })
+    }
+
     /// Handles a call to [`methods::MethodCall::payment_queryInfo`].
     pub(super) async fn payment_query_info(
         self: &Arc<Self>,
@@ -1696,3 +1904,21 @@ impl<TPlat: Platform> Background<TPlat> {
             .unwrap();
     }
 }
+
+/// Extracts the identifier of a default child trie from the key where its root is found in the
+/// main trie, as passed to the `childstate_*` JSON-RPC functions.
+fn child_trie_from_storage_key(child_storage_key: &[u8]) -> Option<&[u8]> {
+    child_storage_key.strip_prefix(b":child_storage:default:")
+}
+
+/// Builds the response to send back if [`child_trie_from_storage_key`] returns `None`.
+fn invalid_child_storage_key_response(request_id: &str) -> String {
+    json_rpc::parse::build_error_response(
+        request_id,
+        json_rpc::parse::ErrorResponse::InvalidParams,
+        Some(
+            &serde_json::to_string("child storage key must start with `:child_storage:default:`")
+                .unwrap(),
+        ),
+    )
+}
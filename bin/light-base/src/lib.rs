@@ -86,7 +86,9 @@ use futures::{channel::oneshot, prelude::*};
 use hashbrown::{hash_map::Entry, HashMap};
 use itertools::Itertools as _;
 use smoldot::{
-    chain, chain_spec, header,
+    chain, chain_spec,
+    database::transactions_serialize,
+    header,
     informant::HashDisplay,
     libp2p::{connection, multiaddr, peer_id},
 };
@@ -136,6 +138,9 @@ pub struct AddChainConfig<'a, TChain, TRelays> {
     ///
     /// Pass an empty string if no database content exists or is known.
     ///
+    /// The database content also contains the transactions that were pending at the time when it
+    /// was retrieved. These transactions are submitted again when the chain is added.
+    ///
     /// No error is generated if this data is invalid and/or can't be decoded. The implementation
     /// reserves the right to break the format of this data at any point.
     pub database_content: &'a str,
@@ -352,7 +357,7 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
         // known as a checkpoint) is present in the chain spec, it is possible to start syncing at
         // the finalized block it describes.
         // TODO: clean up that block
-        let (chain_information, genesis_block_header, checkpoint_nodes) = {
+        let (chain_information, genesis_block_header, checkpoint_nodes, database_transactions) = {
             match (
                 chain_spec.as_chain_information().map(|(ci, _)| ci), // TODO: don't just throw away the runtime
                 chain_spec.light_sync_state().map(|s| {
@@ -389,6 +394,7 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                         database_content.chain_information,
                         genesis_header.into(),
                         database_content.known_nodes,
+                        database_content.pending_transactions,
                     )
                 }
 
@@ -424,10 +430,16 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                             database_content.chain_information,
                             genesis_header,
                             database_content.known_nodes,
+                            database_content.pending_transactions,
                         )
                     } else if let Some(Ok(checkpoint)) = checkpoint {
                         // Database is incorrect.
-                        (checkpoint, genesis_header, database_content.known_nodes)
+                        (
+                            checkpoint,
+                            genesis_header,
+                            database_content.known_nodes,
+                            Vec::new(),
+                        )
                     } else {
                         // TODO: we can in theory support chain specs that have neither a checkpoint nor the genesis storage, but it's complicated
                         return Err(
@@ -457,7 +469,7 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                         digest: header::DigestRef::empty().into(),
                     };
 
-                    (checkpoint, genesis_header, Default::default())
+                    (checkpoint, genesis_header, Default::default(), Vec::new())
                 }
 
                 (Err(err), _, _) => {
@@ -476,13 +488,18 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
 
                 (Ok(genesis_ci), Some(Ok(checkpoint)), _) => {
                     let genesis_header = genesis_ci.as_ref().finalized_block_header.clone();
-                    (checkpoint, genesis_header.into(), Default::default())
+                    (
+                        checkpoint,
+                        genesis_header.into(),
+                        Default::default(),
+                        Vec::new(),
+                    )
                 }
 
                 (Ok(genesis_ci), None, _) => {
                     let genesis_header =
                         header::Header::from(genesis_ci.as_ref().finalized_block_header.clone());
-                    (genesis_ci, genesis_header, Default::default())
+                    (genesis_ci, genesis_header, Default::default(), Vec::new())
                 }
             }
         };
@@ -682,7 +699,10 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                             .as_ref()
                             .finalized_block_header
                             .hash(chain_spec.block_number_bytes().into());
-                        let has_bad_blocks = chain_spec.bad_blocks_hashes().count() != 0;
+                        let starting_block_parent_hash = *chain_information
+                            .as_ref()
+                            .finalized_block_header
+                            .parent_hash;
 
                         let running_chain = start_services(
                             log_name.clone(),
@@ -731,20 +751,42 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                             );
                         }
 
-                        // TODO: remove after https://github.com/paritytech/smoldot/issues/2584
-                        if has_bad_blocks {
-                            log::warn!(
-                                target: "smoldot",
-                                "Chain specification of {} contains a list of bad blocks. Bad \
-                                blocks are not implemented in the light client. An appropriate \
-                                way to silence this warning is to remove the bad blocks from the \
-                                chain specification, which can safely be done:\n\
-                                - For relay chains: if the chain specification contains a \
-                                checkpoint and that the bad blocks have a block number inferior \
-                                to this checkpoint.\n\
-                                - For parachains: if the bad blocks have a block number inferior \
-                                to the current parachain finalized block.", log_name
-                            );
+                        // Submit again the transactions that were pending when the database was
+                        // encoded. Transactions known to be invalid are discarded, as well as
+                        // transactions included in the finalized block we start from or in its
+                        // parent. Transactions included in another block are only tracked
+                        // again once the fate of this block is known, in order to not gossip
+                        // them again. The other transactions will be validated again, and
+                        // transactions that have already been finalized in the meanwhile will
+                        // be found invalid.
+                        for transaction in database_transactions {
+                            if matches!(
+                                transaction.validation,
+                                transactions_serialize::ValidationStatus::Invalid
+                            ) {
+                                continue;
+                            }
+
+                            match transaction.included_block {
+                                Some((block_hash, _))
+                                    if block_hash == starting_block_hash
+                                        || block_hash == starting_block_parent_hash => {}
+                                Some((block_hash, _)) => {
+                                    running_chain
+                                        .transactions_service
+                                        .submit_included_transaction(
+                                            transaction.scale_encoded,
+                                            block_hash,
+                                        )
+                                        .await
+                                }
+                                None => {
+                                    running_chain
+                                        .transactions_service
+                                        .submit_transaction(transaction.scale_encoded)
+                                        .await
+                                }
+                            }
                         }
 
                         running_chain
@@ -1040,6 +1082,7 @@ async fn start_services<TPlat: platform::Platform>(
                 log_name: log_name.clone(),
                 chain_information: chain_information.clone(),
                 block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+                banned_blocks: chain_spec.bad_blocks_hashes().cloned().collect(),
                 tasks_executor: Box::new({
                     let spawn_new_task = spawn_new_task.clone();
                     move |name, fut| spawn_new_task(name, fut)
@@ -1082,6 +1125,7 @@ async fn start_services<TPlat: platform::Platform>(
                 log_name: log_name.clone(),
                 chain_information: chain_information.clone(),
                 block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+                banned_blocks: chain_spec.bad_blocks_hashes().cloned().collect(),
                 tasks_executor: Box::new({
                     let spawn_new_task = spawn_new_task.clone();
                     move |name, fut| spawn_new_task(name, fut)
@@ -234,6 +234,9 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                 genesis_hash: chain.genesis_block_hash,
                 role: protocol::Role::Light,
                 allow_inbound_block_requests: false,
+                allow_inbound_light_requests: false,
+                allow_inbound_state_requests: false,
+                allow_inbound_kademlia_requests: false,
             });
 
             log_chain_names.push(chain.log_name);
@@ -252,8 +255,13 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                     connections_capacity: 32,
                     peers_capacity: 8,
                     max_addresses_per_peer: NonZeroUsize::new(5).unwrap(),
+                    reputation: Default::default(),
                     noise_key: config.noise_key,
                     handshake_timeout: Duration::from_secs(8),
+                    multiplexing_protocols: vec![
+                        service::MultiplexingProtocol::Yamux,
+                        service::MultiplexingProtocol::Mplex,
+                    ],
                     randomness_seed: rand::random(),
                 }),
                 slots_assign_backoff: HashMap::with_capacity_and_hasher(32, Default::default()),
@@ -787,6 +795,35 @@ impl<TPlat: Platform> NetworkService<TPlat> {
         result
     }
 
+    /// Removes the slot of the given peer on the given chain, and prevents it from being
+    /// assigned a slot again for some time.
+    ///
+    /// Must be used on peers that have been found misbehaving. The misbehaviour is also reported
+    /// to the networking state machine, which decreases the reputation of the peer accordingly.
+    /// See [`service::ChainNetwork::report_misbehaviour`].
+    pub async fn ban_and_disconnect(
+        &self,
+        peer_id: PeerId,
+        chain_index: usize,
+        misbehaviour: service::Misbehaviour,
+    ) {
+        let mut guarded = self.shared.guarded.lock().await;
+
+        guarded
+            .network
+            .report_misbehaviour(&TPlat::now(), &peer_id, misbehaviour);
+
+        log::debug!(
+            target: "network",
+            "Connection({}, {}) => Banned",
+            peer_id,
+            &self.shared.log_chain_names[chain_index],
+        );
+
+        guarded.unassign_slot_and_ban(chain_index, peer_id);
+        self.shared.wake_up_main_background_task.notify(1);
+    }
+
     /// See [`service::ChainNetwork::discover`].
     ///
     /// The `important_nodes` parameter indicates whether these nodes are considered note-worthy
@@ -1156,6 +1193,11 @@ async fn update_round<TPlat: Platform>(
                     // We never start any other kind of requests.
                     unreachable!()
                 }
+                service::Event::KademliaGetValueResult { .. }
+                | service::Event::KademliaPutValueResult { .. } => {
+                    // We never start any Kademlia get value or put value operation.
+                    unreachable!()
+                }
                 service::Event::KademliaDiscoveryResult {
                     operation_id,
                     result,
@@ -1232,7 +1274,11 @@ async fn update_round<TPlat: Platform>(
                     );
                     guarded.network.respond_identify(request_id, "smoldot");
                 }
-                service::Event::BlocksRequestIn { .. } => unreachable!(),
+                service::Event::BlocksRequestIn { .. }
+                | service::Event::StorageProofRequestIn { .. }
+                | service::Event::CallProofRequestIn { .. }
+                | service::Event::StateRequestIn { .. }
+                | service::Event::KademliaFindNodeRequestIn { .. } => unreachable!(),
                 service::Event::RequestInCancel { .. } => {
                     // All incoming requests are immediately answered.
                     unreachable!()
@@ -1254,8 +1300,13 @@ async fn update_round<TPlat: Platform>(
                         message,
                     };
                 }
+                service::Event::GrandpaNotification { .. } => {
+                    // The light client doesn't participate in GrandPa rounds.
+                }
+                service::Event::Transactions { .. } => {
+                    // The light client doesn't relay transactions sent by other peers.
+                }
                 service::Event::ProtocolError { peer_id, error } => {
-                    // TODO: handle properly?
                     log::warn!(
                         target: "network",
                         "Connection({}) => ProtocolError(error={:?})",
@@ -1263,8 +1314,10 @@ async fn update_round<TPlat: Platform>(
                         error,
                     );
 
-                    for chain_index in 0..guarded.network.num_chains() {
-                        guarded.unassign_slot_and_ban(chain_index, peer_id.clone());
+                    // The reputation of the peer has been decreased by the network service,
+                    // which might have banned it.
+                    if guarded.network.is_banned(&TPlat::now(), &peer_id) {
+                        log::debug!(target: "network", "Connection({}) => Banned", peer_id);
                     }
                     shared.wake_up_main_background_task.notify(1);
                 }
@@ -1308,7 +1361,7 @@ async fn update_round<TPlat: Platform>(
         loop {
             let peer_id = guarded
                 .network
-                .slots_to_assign(chain_index)
+                .slots_to_assign(chain_index, &now)
                 .filter(|peer_id| {
                     !guarded
                         .slots_assign_backoff
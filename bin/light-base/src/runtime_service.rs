@@ -67,7 +67,7 @@ use alloc::{
     vec::Vec,
 };
 use core::{
-    iter, mem,
+    cmp, iter, mem,
     num::{NonZeroU32, NonZeroUsize},
     pin::Pin,
     time::Duration,
@@ -779,7 +779,8 @@ impl<'a, TPlat: Platform> RuntimeLock<'a, TPlat> {
         total_attempts: u32,
         timeout_per_request: Duration,
         max_parallel: NonZeroU32,
-    ) -> Result<(RuntimeCallLock<'a>, executor::host::HostVmPrototype), RuntimeCallError> {
+    ) -> Result<(RuntimeCallLock<'a, TPlat>, executor::host::HostVmPrototype), RuntimeCallError>
+    {
         // TODO: DRY :-/ this whole thing is messy
 
         // Perform the call proof request.
@@ -824,9 +825,19 @@ impl<'a, TPlat: Platform> RuntimeLock<'a, TPlat> {
         };
 
         let lock = RuntimeCallLock {
+            service: self.service,
             guarded,
+            block_number: self.block_number,
+            block_hash: self.hash,
             block_state_root_hash: self.block_state_root_hash,
             call_proof,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+            downloaded_prefix_keys: hashbrown::HashMap::with_capacity_and_hasher(
+                0,
+                Default::default(),
+            ),
         };
 
         Ok((lock, virtual_machine))
@@ -835,13 +846,26 @@ impl<'a, TPlat: Platform> RuntimeLock<'a, TPlat> {
 
 /// See [`RuntimeService::pinned_block_runtime_lock`].
 #[must_use]
-pub struct RuntimeCallLock<'a> {
+pub struct RuntimeCallLock<'a, TPlat: Platform> {
+    service: &'a RuntimeService<TPlat>,
     guarded: MutexGuard<'a, Option<executor::host::HostVmPrototype>>,
+    block_number: u64,
+    block_hash: [u8; 32],
     block_state_root_hash: [u8; 32],
     call_proof: Result<trie::proof_decode::DecodedTrieProof<Vec<u8>>, RuntimeCallError>,
+
+    /// Parameters passed to [`RuntimeLock::start`]. Used when storage keys that are missing from
+    /// the call proof need to be downloaded from the network.
+    total_attempts: u32,
+    timeout_per_request: Duration,
+    max_parallel: NonZeroU32,
+
+    /// Keys of the storage downloaded from the network, indexed by the prefix that was requested.
+    /// Each list is ordered lexicographically.
+    downloaded_prefix_keys: hashbrown::HashMap<Vec<u8>, Vec<Vec<u8>>, fnv::FnvBuildHasher>,
 }
 
-impl<'a> RuntimeCallLock<'a> {
+impl<'a, TPlat: Platform> RuntimeCallLock<'a, TPlat> {
     /// Returns the storage root of the block the call is being made against.
     pub fn block_storage_root(&self) -> &[u8; 32] {
         &self.block_state_root_hash
@@ -916,6 +940,202 @@ impl<'a> RuntimeCallLock<'a> {
         Ok(output.into_iter())
     }
 
+    /// Returns the key of the storage that immediately follows the given key.
+    ///
+    /// The call proof is used in priority. If it doesn't contain enough information, the keys
+    /// of the parts of the trie that are missing from the proof are downloaded from the network.
+    pub async fn storage_next_key(
+        &mut self,
+        key: &[u8],
+        or_equal: bool,
+    ) -> Result<Option<Vec<u8>>, RuntimeCallError> {
+        self.next_key_inner(
+            trie::bytes_to_nibbles(key.iter().copied()).collect(),
+            or_equal,
+        )
+        .await
+    }
+
+    /// Returns the list of keys of the storage that start with the given prefix.
+    ///
+    /// Similar to [`RuntimeCallLock::storage_next_key`], the call proof is used in priority and
+    /// the keys missing from the proof are downloaded from the network.
+    ///
+    /// The keys returned are ordered lexicographically.
+    pub async fn storage_prefix_keys(
+        &mut self,
+        prefix: &[u8],
+    ) -> Result<Vec<Vec<u8>>, RuntimeCallError> {
+        let mut output = Vec::new();
+
+        let mut next = self
+            .next_key_inner(
+                trie::bytes_to_nibbles(prefix.iter().copied()).collect(),
+                true,
+            )
+            .await?;
+
+        while let Some(key) = next {
+            if !key.starts_with(prefix) {
+                break;
+            }
+
+            let key_nibbles = trie::bytes_to_nibbles(key.iter().copied()).collect();
+            output.push(key);
+            next = self.next_key_inner(key_nibbles, false).await?;
+        }
+
+        Ok(output)
+    }
+
+    async fn next_key_inner(
+        &mut self,
+        mut key: Vec<trie::Nibble>,
+        mut or_equal: bool,
+    ) -> Result<Option<Vec<u8>>, RuntimeCallError> {
+        // Key whose storage proof has been downloaded at the previous iteration, if any.
+        let mut previous_missing_key = None::<Vec<u8>>;
+
+        loop {
+            let mut missing_node_prefix = {
+                let call_proof = match &self.call_proof {
+                    Ok(p) => p,
+                    Err(err) => return Err(err.clone()),
+                };
+
+                match call_proof.next_key(&key, or_equal) {
+                    Ok(next) => {
+                        return Ok(next.map(|next| {
+                            trie::nibbles_to_bytes_suffix_extend(next.iter().copied())
+                                .collect::<Vec<_>>()
+                        }))
+                    }
+                    Err(err) => err.missing_node_prefix,
+                }
+            };
+
+            // If the missing node is close to the root of the trie, downloading all the keys
+            // that start with its prefix would download a large fraction of the storage.
+            // Instead, a storage proof of a key that starts with the prefix of the missing node
+            // is downloaded and added to the call proof, as such a proof is guaranteed to
+            // contain the missing node.
+            if missing_node_prefix.len() < 2 {
+                let missing_key =
+                    trie::nibbles_to_bytes_suffix_extend(missing_node_prefix.iter().copied())
+                        .collect::<Vec<_>>();
+
+                // The storage proof is verified to contain the requested key, and thus the
+                // missing node, when downloaded. Downloading it a second time would indicate
+                // a bug.
+                if previous_missing_key.as_ref() == Some(&missing_key) {
+                    return Err(RuntimeCallError::MissingProofEntry);
+                }
+
+                let storage_proof = self
+                    .service
+                    .sync_service
+                    .clone()
+                    .storage_proof_query(
+                        sync_service::StorageQueryBlock {
+                            block_number: self.block_number,
+                            block_hash: &self.block_hash,
+                            storage_trie_root: &self.block_state_root_hash,
+                        },
+                        iter::once(&missing_key),
+                        self.total_attempts,
+                        self.timeout_per_request,
+                        self.max_parallel,
+                    )
+                    .await
+                    .map_err(RuntimeCallError::StorageQuery)?;
+
+                let merged = match &self.call_proof {
+                    Ok(p) => trie::proof_encode::merge_proofs(p.encoded_proof(), &storage_proof)
+                        .ok_or(RuntimeCallError::StorageRetrieval(
+                            proof_decode::Error::InvalidFormat,
+                        ))?,
+                    Err(err) => return Err(err.clone()),
+                };
+                self.call_proof = proof_decode::decode_and_verify_proof(proof_decode::Config {
+                    proof: merged,
+                    trie_root_hash: &self.block_state_root_hash,
+                })
+                .map_err(RuntimeCallError::StorageRetrieval);
+
+                previous_missing_key = Some(missing_key);
+                continue;
+            }
+
+            // All the keys found in the part of the trie missing from the proof start with
+            // `missing_node_prefix`. Download from the network the keys that start with the
+            // bytes of this prefix.
+            let byte_prefix = trie::nibbles_to_bytes_suffix_extend(
+                missing_node_prefix[..missing_node_prefix.len() - missing_node_prefix.len() % 2]
+                    .iter()
+                    .copied(),
+            )
+            .collect::<Vec<_>>();
+
+            if !self.downloaded_prefix_keys.contains_key(&byte_prefix) {
+                let mut keys = self
+                    .service
+                    .sync_service
+                    .clone()
+                    .storage_prefix_keys_query(
+                        self.block_number,
+                        &self.block_hash,
+                        &byte_prefix,
+                        &self.block_state_root_hash,
+                        self.total_attempts,
+                        self.timeout_per_request,
+                        self.max_parallel,
+                    )
+                    .await
+                    .map_err(RuntimeCallError::StorageQuery)?;
+                keys.sort_unstable();
+                self.downloaded_prefix_keys
+                    .insert(byte_prefix.clone(), keys);
+            }
+
+            // The downloaded keys are ordered, and the nibbles of the keys are in the same order
+            // as their bytes. The first key that starts with `missing_node_prefix` and that is
+            // after `key` is found through a binary search.
+            let downloaded_keys = &self.downloaded_prefix_keys[&byte_prefix];
+            let candidate_index = downloaded_keys.partition_point(|candidate| {
+                let candidate = trie::bytes_to_nibbles(candidate.iter().copied());
+                candidate.clone().lt(missing_node_prefix.iter().copied())
+                    || match candidate.cmp(key.iter().copied()) {
+                        cmp::Ordering::Less => true,
+                        cmp::Ordering::Equal => !or_equal,
+                        cmp::Ordering::Greater => false,
+                    }
+            });
+            if let Some(next) = downloaded_keys.get(candidate_index) {
+                if trie::bytes_to_nibbles(next.iter().copied())
+                    .take(missing_node_prefix.len())
+                    .eq(missing_node_prefix.iter().copied())
+                {
+                    return Ok(Some(next.clone()));
+                }
+            }
+
+            // No key in the missing part of the trie. Continue the search right after it.
+            loop {
+                match missing_node_prefix.pop().map(u8::from) {
+                    Some(15) => continue,
+                    Some(nibble) => {
+                        missing_node_prefix.push(trie::Nibble::try_from(nibble + 1).unwrap());
+                        break;
+                    }
+                    None => return Ok(None),
+                }
+            }
+
+            key = missing_node_prefix;
+            or_equal = true;
+        }
+    }
+
     /// End the runtime call.
     ///
     /// This method **must** be called.
@@ -925,7 +1145,7 @@ impl<'a> RuntimeCallLock<'a> {
     }
 }
 
-impl<'a> Drop for RuntimeCallLock<'a> {
+impl<'a, TPlat: Platform> Drop for RuntimeCallLock<'a, TPlat> {
     fn drop(&mut self) {
         if self.guarded.is_none() {
             // The [`RuntimeCallLock`] has been destroyed without being properly unlocked.
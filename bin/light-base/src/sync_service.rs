@@ -60,6 +60,13 @@ pub struct Config<TPlat: Platform> {
     /// Number of bytes of the block number in the networking protocol.
     pub block_number_bytes: usize,
 
+    /// List of hashes of blocks that are known to be bad and must never be synchronized. Peers
+    /// that announce these blocks or their descendants are banned.
+    ///
+    /// Ignored if [`Config::parachain`] is `Some`, as the blocks of parachains are obtained from
+    /// the relay chain.
+    pub banned_blocks: Vec<[u8; 32]>,
+
     /// Closure that spawns background tasks.
     pub tasks_executor: Box<dyn FnMut(String, future::BoxFuture<'static, ()>) + Send>,
 
@@ -138,6 +145,7 @@ impl<TPlat: Platform> SyncService<TPlat> {
                     log_target,
                     config.chain_information,
                     config.block_number_bytes,
+                    config.banned_blocks,
                     from_foreground,
                     config.network_service.0.clone(),
                     config.network_service.1,
@@ -401,15 +409,91 @@ impl<TPlat: Platform> SyncService<TPlat> {
         requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
         total_attempts: u32,
         timeout_per_request: Duration,
-        _max_parallel: NonZeroU32,
+        max_parallel: NonZeroU32,
     ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
+        self.storage_query_inner(
+            StorageQueryBlock {
+                block_number,
+                block_hash,
+                storage_trie_root,
+            },
+            None,
+            requested_keys,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+        .map(|(values, _)| values)
+    }
+
+    /// Similar to [`SyncService::storage_query`], but queries the storage of the given default
+    /// child trie instead of the main trie.
+    ///
+    /// `child_trie` is the identifier of the child trie, without the `:child_storage:default:`
+    /// prefix. The root of the child trie is found through the proofs.
+    pub async fn child_storage_query(
+        self: Arc<Self>,
+        block: StorageQueryBlock<'_>,
+        child_trie: &[u8],
+        requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
+        self.storage_query_inner(
+            block,
+            Some(child_trie),
+            requested_keys,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+        .map(|(values, _)| values)
+    }
+
+    /// Similar to [`SyncService::storage_query`], but returns the Merkle proof sent by the
+    /// remote instead of the storage values.
+    ///
+    /// The proof is guaranteed to be valid and to contain the storage values of all the
+    /// `requested_keys`.
+    pub async fn storage_proof_query(
+        self: Arc<Self>,
+        block: StorageQueryBlock<'_>,
+        requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<u8>, StorageQueryError> {
+        self.storage_query_inner(
+            block,
+            None,
+            requested_keys,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+        .map(|(_, proof)| proof.decode().to_owned())
+    }
+
+    async fn storage_query_inner(
+        self: Arc<Self>,
+        block: StorageQueryBlock<'_>,
+        child_trie: Option<&[u8]>,
+        requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        _max_parallel: NonZeroU32,
+    ) -> Result<(Vec<Option<Vec<u8>>>, service::EncodedMerkleProof), StorageQueryError> {
         let mut outcome_errors =
             Vec::with_capacity(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
 
         // TODO: better peers selection ; don't just take the first
         // TODO: handle max_parallel
         for target in self
-            .peers_assumed_know_blocks(block_number, block_hash)
+            .peers_assumed_know_blocks(block.block_number, block.block_hash)
             .await
             .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
         {
@@ -420,32 +504,41 @@ impl<TPlat: Platform> SyncService<TPlat> {
                     self.network_chain_index,
                     target,
                     protocol::StorageProofRequestConfig {
-                        block_hash: *block_hash,
+                        block_hash: *block.block_hash,
                         keys: requested_keys.clone(),
+                        child_trie: child_trie.map(|child_trie| child_trie.to_vec()),
                     },
                     timeout_per_request,
                 )
                 .await
                 .map_err(StorageQueryErrorDetail::Network)
                 .and_then(|outcome| {
-                    let decoded = outcome.decode();
-                    let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
-                        proof: decoded,
-                        trie_root_hash: &storage_trie_root,
-                    })
-                    .map_err(StorageQueryErrorDetail::ProofVerification)?;
-
-                    let mut result = Vec::with_capacity(requested_keys.clone().count());
-                    for key in requested_keys.clone() {
-                        result.push(
-                            decoded
-                                .storage_value(key.as_ref())
-                                .ok_or(StorageQueryErrorDetail::MissingProofEntry)?
-                                .map(|v| v.to_owned()),
-                        );
-                    }
-                    debug_assert_eq!(result.len(), result.capacity());
-                    Ok(result)
+                    let result = {
+                        let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+                            proof: outcome.decode(),
+                            trie_root_hash: block.storage_trie_root,
+                        })
+                        .map_err(StorageQueryErrorDetail::ProofVerification)?;
+
+                        let mut result = Vec::with_capacity(requested_keys.clone().count());
+                        for key in requested_keys.clone() {
+                            let value = match child_trie {
+                                Some(child_trie) => {
+                                    decoded.child_trie_storage_value(child_trie, key.as_ref())
+                                }
+                                None => decoded.storage_value(key.as_ref()),
+                            };
+                            result.push(
+                                value
+                                    .ok_or(StorageQueryErrorDetail::MissingProofEntry)?
+                                    .map(|v| v.to_owned()),
+                            );
+                        }
+                        debug_assert_eq!(result.len(), result.capacity());
+                        result
+                    };
+
+                    Ok((result, outcome))
                 });
 
             match result {
@@ -469,11 +562,61 @@ impl<TPlat: Platform> SyncService<TPlat> {
         storage_trie_root: &[u8; 32],
         total_attempts: u32,
         timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
+        self.storage_prefix_keys_query_inner(
+            StorageQueryBlock {
+                block_number,
+                block_hash,
+                storage_trie_root,
+            },
+            prefix,
+            None,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+    }
+
+    /// Similar to [`SyncService::storage_prefix_keys_query`], but queries the keys of the given
+    /// default child trie instead of the main trie.
+    ///
+    /// `child_trie` is the identifier of the child trie, without the `:child_storage:default:`
+    /// prefix. The root of the child trie is found through the proofs.
+    pub async fn child_storage_prefix_keys_query(
+        self: Arc<Self>,
+        block: StorageQueryBlock<'_>,
+        child_trie: &[u8],
+        prefix: &[u8],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
+        self.storage_prefix_keys_query_inner(
+            block,
+            prefix,
+            Some(child_trie),
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+    }
+
+    async fn storage_prefix_keys_query_inner(
+        self: Arc<Self>,
+        block: StorageQueryBlock<'_>,
+        prefix: &[u8],
+        child_trie: Option<&[u8]>,
+        total_attempts: u32,
+        timeout_per_request: Duration,
         _max_parallel: NonZeroU32,
     ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
         let mut prefix_scan = prefix_proof::prefix_scan(prefix_proof::Config {
             prefix,
-            trie_root_hash: *storage_trie_root,
+            trie_root_hash: *block.storage_trie_root,
+            child_trie,
         });
 
         'main_scan: loop {
@@ -484,7 +627,7 @@ impl<TPlat: Platform> SyncService<TPlat> {
             // TODO: handle max_parallel
             // TODO: is the number of keys is large, split into multiple requests
             for target in self
-                .peers_assumed_know_blocks(block_number, block_hash)
+                .peers_assumed_know_blocks(block.block_number, block.block_hash)
                 .await
                 .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
             {
@@ -495,10 +638,11 @@ impl<TPlat: Platform> SyncService<TPlat> {
                         self.network_chain_index,
                         target,
                         protocol::StorageProofRequestConfig {
-                            block_hash: *block_hash,
+                            block_hash: *block.block_hash,
                             keys: prefix_scan.requested_keys().map(|nibbles| {
                                 trie::nibbles_to_bytes_suffix_extend(nibbles).collect::<Vec<_>>()
                             }),
+                            child_trie: child_trie.map(|child_trie| child_trie.to_vec()),
                         },
                         timeout_per_request,
                     )
@@ -597,6 +741,18 @@ impl<TPlat: Platform> SyncService<TPlat> {
     }
 }
 
+/// Block whose storage is queried. See [`SyncService::child_storage_query`],
+/// [`SyncService::storage_proof_query`] and [`SyncService::child_storage_prefix_keys_query`].
+#[derive(Debug, Copy, Clone)]
+pub struct StorageQueryBlock<'a> {
+    /// Height of the block.
+    pub block_number: u64,
+    /// Hash of the block.
+    pub block_hash: &'a [u8; 32],
+    /// Merkle value of the root node of the main trie of the storage of the block.
+    pub storage_trie_root: &'a [u8; 32],
+}
+
 /// Error that can happen when calling [`SyncService::storage_query`].
 #[derive(Debug, Clone)]
 pub struct StorageQueryError {
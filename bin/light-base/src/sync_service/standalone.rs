@@ -22,6 +22,7 @@ use alloc::{borrow::ToOwned as _, string::String, sync::Arc, vec::Vec};
 use core::{
     iter,
     marker::PhantomData,
+    mem,
     num::{NonZeroU32, NonZeroU64},
     time::Duration,
 };
@@ -40,6 +41,7 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
     log_target: String,
     chain_information: chain::chain_information::ValidChainInformation,
     block_number_bytes: usize,
+    banned_blocks: Vec<[u8; 32]>,
     mut from_foreground: mpsc::Receiver<ToBackground>,
     network_service: Arc<network_service::NetworkService<TPlat>>,
     network_chain_index: usize,
@@ -73,6 +75,7 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
                 // is 5k.
                 NonZeroU32::new(5000).unwrap()
             },
+            banned_blocks,
             full: None,
         }),
         network_up_to_date_best: true,
@@ -91,6 +94,7 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
         network_service,
         network_chain_index,
         peers_source_id_map: HashMap::with_capacity_and_hasher(0, Default::default()),
+        peers_to_ban: Vec::new(),
         platform: PhantomData,
     };
 
@@ -189,6 +193,17 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
             task.network_up_to_date_finalized = true;
         }
 
+        // Processing the queue or the network events might have detected misbehaving peers.
+        for peer_id in mem::take(&mut task.peers_to_ban) {
+            task.network_service
+                .ban_and_disconnect(
+                    peer_id,
+                    network_chain_index,
+                    network::service::Misbehaviour::BadBlock,
+                )
+                .await;
+        }
+
         // Now waiting for some event to happen: a network event, a request from the frontend
         // of the sync service, or a request being finished.
         let response_outcome = futures::select! {
@@ -329,6 +344,10 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
                             peer_id
                         );
                     },
+                    all::Status::WarpSyncStorage { .. } => {
+                        // The light client never enables the download of the storage.
+                        unreachable!()
+                    }
                 };
 
                 task.warp_sync_taking_long_time_warning =
@@ -386,6 +405,10 @@ struct Task<TPlat: Platform> {
     /// after the networking has been notified of this change.
     network_up_to_date_finalized: bool,
 
+    /// List of peers that have been found misbehaving, for example by announcing a banned block,
+    /// and that must be banned from the networking.
+    peers_to_ban: Vec<libp2p::PeerId>,
+
     /// All event subscribers that are interested in events about the chain.
     all_notifications: Vec<mpsc::Sender<Notification>>,
 
@@ -562,6 +585,7 @@ impl<TPlat: Platform> Task<TPlat> {
                     network::protocol::StorageProofRequestConfig {
                         block_hash,
                         keys: keys.clone().into_iter(),
+                        child_trie: None,
                     },
                     Duration::from_secs(16),
                 );
@@ -622,6 +646,11 @@ impl<TPlat: Platform> Task<TPlat> {
                 self.pending_call_proof_requests
                     .push(async move { (request_id, call_proof_request.await) }.boxed());
             }
+
+            all::DesiredRequest::StateRequest { .. } => {
+                // The light client never enables the download of the storage.
+                unreachable!()
+            }
         }
 
         true
@@ -656,6 +685,7 @@ impl<TPlat: Platform> Task<TPlat> {
                 finalized_block_runtime,
                 finalized_storage_code,
                 finalized_storage_heap_pages,
+                ..
             } => {
                 self.sync = sync;
 
@@ -836,6 +866,18 @@ impl<TPlat: Platform> Task<TPlat> {
                     all::HeaderVerifyOutcome::Error { sync, error, .. } => {
                         self.sync = sync;
 
+                        // The sources that know about a banned block are on a bad fork and are
+                        // banned as well.
+                        if matches!(error, all::HeaderVerifyError::BannedBlock) {
+                            for source_id in self
+                                .sync
+                                .knows_non_finalized_block(verified_height, &verified_hash)
+                                .collect::<Vec<_>>()
+                            {
+                                self.peers_to_ban.push(self.sync[source_id].0.clone());
+                            }
+                        }
+
                         // TODO: print which peer sent the header
                         log::debug!(
                             target: &self.log_target,
@@ -1172,6 +1214,20 @@ impl<TPlat: Platform> Task<TPlat> {
                     all::BlockAnnounceOutcome::InvalidHeader(_) => {
                         // Log messages are already printed above.
                     }
+                    all::BlockAnnounceOutcome::BannedBlock => {
+                        log::debug!(
+                            target: &self.log_target,
+                            "Sync => BannedBlock"
+                        );
+
+                        log::warn!(
+                            target: &self.log_target,
+                            "Block announce from {} is a banned block or one of its descendants",
+                            peer_id
+                        );
+
+                        self.peers_to_ban.push(peer_id);
+                    }
                 }
             }
 
@@ -80,12 +80,19 @@ use alloc::{
 use core::{
     cmp, iter,
     marker::PhantomData,
+    mem,
     num::{NonZeroU32, NonZeroUsize},
     time::Duration,
 };
-use futures::{channel::mpsc, lock::Mutex, prelude::*, stream::FuturesUnordered};
+use futures::{
+    channel::{mpsc, oneshot},
+    lock::Mutex,
+    prelude::*,
+    stream::FuturesUnordered,
+};
 use itertools::Itertools as _;
 use smoldot::{
+    database::transactions_serialize,
     header,
     informant::HashDisplay,
     libp2p::peer_id::PeerId,
@@ -214,6 +221,53 @@ impl<TPlat: Platform> TransactionsService<TPlat> {
             .await
             .unwrap();
     }
+
+    /// Similar to [`TransactionsService::submit_transaction`], but for a transaction that is
+    /// known to be included in the block with the given hash, typically because it was
+    /// restored from [`TransactionsService::serialize_pending_transactions`].
+    ///
+    /// The transaction isn't validated or gossiped until this block is either reported by the
+    /// runtime service, in which case the transaction is tracked normally, or is finalized, in
+    /// which case the transaction is discarded. If the block is still unknown once the chain
+    /// is near its head, the block is assumed to have been discarded and the transaction is
+    /// tracked normally.
+    pub async fn submit_included_transaction(
+        &self,
+        transaction_bytes: Vec<u8>,
+        included_block_hash: [u8; 32],
+    ) {
+        self.to_background
+            .lock()
+            .await
+            .send(ToBackground::SubmitIncludedTransaction {
+                transaction_bytes,
+                included_block_hash,
+            })
+            .await
+            .unwrap();
+    }
+
+    /// Returns the list of transactions currently pending in the service, in a format that can
+    /// be passed to [`smoldot::database::transactions_serialize::encode_transactions`].
+    ///
+    /// The transactions returned by this function can later be passed back to
+    /// [`TransactionsService::submit_transaction`], or to
+    /// [`TransactionsService::submit_included_transaction`] if they were included in a block,
+    /// for example after a restart.
+    pub async fn serialize_pending_transactions(
+        &self,
+    ) -> Vec<transactions_serialize::PendingTransaction> {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .lock()
+            .await
+            .send(ToBackground::SerializePendingTransactions { send_back })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
 }
 
 /// Update on the state of a transaction in the service.
@@ -274,9 +328,9 @@ pub enum ValidateTransactionError {
     /// Error during the validation runtime call.
     #[display(fmt = "{}", _0)]
     Validation(validate::Error),
-    /// Tried to access the next key of a storage key. This isn't possible through a call request
+    /// Tried to access the storage of a child trie. This isn't possible through a call request
     /// at the moment.
-    NextKeyForbidden,
+    ChildTrieForbidden,
 }
 
 #[derive(Debug, Clone)]
@@ -297,6 +351,13 @@ enum ToBackground {
         transaction_bytes: Vec<u8>,
         updates_report: Option<mpsc::Sender<TransactionStatus>>,
     },
+    SubmitIncludedTransaction {
+        transaction_bytes: Vec<u8>,
+        included_block_hash: [u8; 32],
+    },
+    SerializePendingTransactions {
+        send_back: oneshot::Sender<Vec<transactions_serialize::PendingTransaction>>,
+    },
 }
 
 /// Background task running in parallel of the front service.
@@ -327,6 +388,7 @@ async fn background_task<TPlat: Platform>(
         block_downloads: FuturesUnordered::new(),
         validations_in_progress: FuturesUnordered::new(),
         next_reannounce: FuturesUnordered::new(),
+        included_transactions_on_hold: Vec::new(),
         max_concurrent_downloads,
         max_pending_transactions,
     };
@@ -375,8 +437,14 @@ async fn background_task<TPlat: Platform>(
             finalized_block_hash: initial_finalized_block_hash,
         });
 
+        // Transactions on hold whose block is the finalized block are discarded.
+        worker
+            .included_transactions_on_hold
+            .retain(|(_, block_hash)| *block_hash != initial_finalized_block_hash);
+
         for block in subscribe_all.non_finalized_blocks_ancestry_order {
             let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+            worker.release_included_transactions_on_hold(&log_target, |b| *b == hash);
             worker.pending_transactions.add_block(
                 hash,
                 &block.parent_hash,
@@ -624,6 +692,7 @@ async fn background_task<TPlat: Platform>(
                     match notification {
                         Some(runtime_service::Notification::Block(new_block)) => {
                             let hash = header::hash_from_scale_encoded_header(&new_block.scale_encoded_header);
+                            worker.release_included_transactions_on_hold(&log_target, |b| *b == hash);
                             worker.pending_transactions.add_block(
                                 header::hash_from_scale_encoded_header(&new_block.scale_encoded_header),
                                 &new_block.parent_hash,
@@ -650,6 +719,15 @@ async fn background_task<TPlat: Platform>(
                                 // Note that we could in principle interrupt any on-going
                                 // download of that block, but it is not worth the effort.
                             }
+
+                            // If the blocks of the transactions on hold still haven't been
+                            // reported once we are near the head of the chain, they have most
+                            // likely been discarded, and the transactions are tracked normally.
+                            if !worker.included_transactions_on_hold.is_empty()
+                                && worker.sync_service.is_near_head_of_chain_heuristic().await
+                            {
+                                worker.release_included_transactions_on_hold(&log_target, |_| true);
+                            }
                         },
                         Some(runtime_service::Notification::BestBlockChanged { hash }) => {
                             worker.set_best_block(&log_target, &hash);
@@ -931,6 +1009,23 @@ async fn background_task<TPlat: Platform>(
                                     validation_in_progress: None,
                                 });
                         }
+                        ToBackground::SubmitIncludedTransaction {
+                            transaction_bytes,
+                            included_block_hash,
+                        } => {
+                            worker.included_transactions_on_hold.push((transaction_bytes, included_block_hash));
+
+                            // If the block is already known, there's no point in waiting.
+                            // Note that the inclusion will not be detected if the body of this
+                            // block has already been downloaded, but this is unlikely to happen
+                            // as transactions are restored right after the service has started.
+                            if worker.pending_transactions.has_block(&included_block_hash) {
+                                worker.release_included_transactions_on_hold(&log_target, |b| *b == included_block_hash);
+                            }
+                        }
+                        ToBackground::SerializePendingTransactions { send_back } => {
+                            let _ = send_back.send(worker.serialize_pending_transactions());
+                        }
                     }
                 }
             }
@@ -991,12 +1086,97 @@ struct Worker<TPlat: Platform> {
     /// [`PendingTransaction::when_reannounce`] should be checked.
     next_reannounce: FuturesUnordered<future::BoxFuture<'static, light_pool::TransactionId>>,
 
+    /// List of transactions submitted with
+    /// [`TransactionsService::submit_included_transaction`] and that haven't been inserted in
+    /// [`Worker::pending_transactions`] yet, with the hash of the block they are included in.
+    ///
+    /// A transaction is moved to [`Worker::pending_transactions`] right before its block is
+    /// added to it, so that the inclusion is detected once the block body has been downloaded.
+    included_transactions_on_hold: Vec<(Vec<u8>, [u8; 32])>,
+
     /// See [`Config::max_concurrent_downloads`]. Maximum number of elements in
     /// [`Worker::block_downloads`].
     max_concurrent_downloads: usize,
 }
 
 impl<TPlat: Platform> Worker<TPlat> {
+    /// Builds the list of transactions returned by
+    /// [`TransactionsService::serialize_pending_transactions`].
+    fn serialize_pending_transactions(&self) -> Vec<transactions_serialize::PendingTransaction> {
+        let invalid_transactions = self
+            .pending_transactions
+            .invalid_transactions_best_block()
+            .map(|(tx_id, _, _)| tx_id)
+            .collect::<hashbrown::HashSet<_, fnv::FnvBuildHasher>>();
+
+        self.pending_transactions
+            .transactions_iter()
+            .map(|(tx_id, _)| transactions_serialize::PendingTransaction {
+                scale_encoded: self
+                    .pending_transactions
+                    .scale_encoding(tx_id)
+                    .unwrap()
+                    .to_vec(),
+                validation: if self.pending_transactions.is_valid_against_best_block(tx_id) {
+                    transactions_serialize::ValidationStatus::Valid
+                } else if invalid_transactions.contains(&tx_id) {
+                    transactions_serialize::ValidationStatus::Invalid
+                } else {
+                    transactions_serialize::ValidationStatus::NotValidated
+                },
+                // We assume that there's no more than 2<<32 transactions per block.
+                included_block: self
+                    .pending_transactions
+                    .best_chain_inclusion(tx_id)
+                    .map(|(hash, index)| (*hash, u32::try_from(index).unwrap())),
+            })
+            .collect()
+    }
+
+    /// Moves to [`Worker::pending_transactions`] the transactions of
+    /// [`Worker::included_transactions_on_hold`] whose block hash matches the given filter.
+    ///
+    /// Transactions that are already in the pool, or that don't fit in it, are discarded.
+    fn release_included_transactions_on_hold(
+        &mut self,
+        log_target: &str,
+        mut filter: impl FnMut(&[u8; 32]) -> bool,
+    ) {
+        let (to_release, on_hold) = mem::take(&mut self.included_transactions_on_hold)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(_, block_hash)| filter(block_hash));
+        self.included_transactions_on_hold = on_hold;
+
+        for (transaction_bytes, block_hash) in to_release {
+            log::debug!(
+                target: log_target,
+                "ReleasedFromHold(tx={}, block={})",
+                HashDisplay(&blake2_hash(&transaction_bytes)),
+                HashDisplay(&block_hash)
+            );
+
+            if self
+                .pending_transactions
+                .find_transaction(&transaction_bytes)
+                .next()
+                .is_some()
+                || self.pending_transactions.num_transactions() >= self.max_pending_transactions
+            {
+                continue;
+            }
+
+            self.pending_transactions.add_unvalidated(
+                transaction_bytes,
+                PendingTransaction {
+                    when_reannounce: TPlat::now(),
+                    status_update: Vec::new(),
+                    latest_status: None,
+                    validation_in_progress: None,
+                },
+            );
+        }
+    }
+
     /// Update the best block. Must have been previously inserted with
     /// [`light_pool::LightPool::add_block`].
     fn set_best_block(&mut self, log_target: &str, new_best_block_hash: &[u8; 32]) {
@@ -1142,7 +1322,7 @@ async fn validate_transaction<TPlat: Platform>(
     );
 
     let block_hash = *runtime_lock.block_hash();
-    let (runtime_call_lock, runtime) = runtime_lock
+    let (mut runtime_call_lock, runtime) = runtime_lock
         .start(
             validate::VALIDATION_FUNCTION_NAME,
             // TODO: don't hardcode v3 but determine parameters dynamically from the runtime
@@ -1195,6 +1375,13 @@ async fn validate_transaction<TPlat: Platform>(
                     InvalidOrError::ValidateError(ValidateTransactionError::Validation(error)),
                 ));
             }
+            validate::Query::StorageGet(get) if get.child_trie().is_some() => {
+                // TODO:
+                runtime_call_lock.unlock(validate::Query::StorageGet(get).into_prototype());
+                break Err(ValidationError::InvalidOrError(
+                    InvalidOrError::ValidateError(ValidateTransactionError::ChildTrieForbidden),
+                ));
+            }
             validate::Query::StorageGet(get) => {
                 let storage_value = runtime_call_lock.storage_entry(get.key().as_ref());
                 let storage_value = match storage_value {
@@ -1208,11 +1395,31 @@ async fn validate_transaction<TPlat: Platform>(
                 };
                 validation_in_progress = get.inject_value(storage_value.map(iter::once));
             }
-            validate::Query::NextKey(nk) => {
+            validate::Query::NextKey(nk) if nk.child_trie().is_some() => {
                 // TODO:
                 runtime_call_lock.unlock(validate::Query::NextKey(nk).into_prototype());
                 break Err(ValidationError::InvalidOrError(
-                    InvalidOrError::ValidateError(ValidateTransactionError::NextKeyForbidden),
+                    InvalidOrError::ValidateError(ValidateTransactionError::ChildTrieForbidden),
+                ));
+            }
+            validate::Query::NextKey(nk) => {
+                let key = nk.key().as_ref().to_owned();
+                let next_key = match runtime_call_lock.storage_next_key(&key, false).await {
+                    Ok(k) => k,
+                    Err(err) => {
+                        runtime_call_lock.unlock(validate::Query::NextKey(nk).into_prototype());
+                        return Err(ValidationError::InvalidOrError(
+                            InvalidOrError::ValidateError(ValidateTransactionError::Call(err)),
+                        ));
+                    }
+                };
+                validation_in_progress = nk.inject_key(next_key);
+            }
+            validate::Query::PrefixKeys(prefix) if prefix.child_trie().is_some() => {
+                // TODO:
+                runtime_call_lock.unlock(validate::Query::PrefixKeys(prefix).into_prototype());
+                break Err(ValidationError::InvalidOrError(
+                    InvalidOrError::ValidateError(ValidateTransactionError::ChildTrieForbidden),
                 ));
             }
             validate::Query::PrefixKeys(prefix) => {
@@ -0,0 +1,828 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Search for a Babe slot that one of the local authorities is allowed to claim.
+//!
+//! See the [`crate::verify::babe`] module for an overview of primary and secondary slot claims.
+//!
+//! Contrary to Aura, determining whether a slot can be claimed requires generating a VRF output
+//! using the private key of the authority. The search is consequently implemented as a state
+//! machine: [`next_slot_claim`] starts the search, and each [`SlotClaimSearch::VrfSign`] must be
+//! answered with a VRF output and proof generated using the corresponding private key, until a
+//! [`SlotClaimSearch::Finished`] is returned.
+
+use crate::{chain::chain_information, header, verify::babe as verify_babe};
+
+use alloc::vec::Vec;
+use core::{cmp, num::NonZeroU64, time::Duration};
+
+/// Configuration for [`next_slot_claim`].
+pub struct Config<'a, TLocAuth> {
+    /// Time elapsed since [the Unix Epoch](https://en.wikipedia.org/wiki/Unix_time) (i.e.
+    /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
+    pub now_from_unix_epoch: Duration,
+
+    /// Duration, in milliseconds, of a Babe slot.
+    ///
+    /// Can be found by calling the `BabeApi_configuration` runtime function.
+    pub slot_duration: NonZeroU64,
+
+    /// Number of slots per epoch in the Babe configuration.
+    pub slots_per_epoch: NonZeroU64,
+
+    /// Slot number of the block that the block to author will be built upon. Must be `None` if
+    /// and only if this parent block is the genesis block.
+    pub parent_block_slot_number: Option<u64>,
+
+    /// Epoch the parent block belongs to. Must be `None` if and only if the parent block is the
+    /// genesis block.
+    pub parent_block_epoch: Option<chain_information::BabeEpochInformationRef<'a>>,
+
+    /// Epoch that follows the epoch the parent block belongs to.
+    pub parent_block_next_epoch: chain_information::BabeEpochInformationRef<'a>,
+
+    /// Iterator to the list of Sr25519 public keys available locally.
+    ///
+    /// Must implement `Iterator<Item = &[u8; 32]>`.
+    pub local_authorities: TLocAuth,
+}
+
+/// Starts searching for the earliest slot one of the authorities in
+/// [`Config::local_authorities`] is allowed to claim.
+///
+/// The search starts at the current slot, or at the slot following the one of the parent block
+/// if the latter is in the future, and stops at the end of the epoch this first slot belongs to.
+/// [`SlotClaimSearch::Finished`] contains `None` if no slot can be claimed within this range, in
+/// which case the search should be attempted again later.
+///
+/// Keep in mind that a VRF output is generated for each slot and each local authority that
+/// belongs to the list of authorities of the epoch until a claim is found.
+pub fn next_slot_claim<'a>(
+    config: Config<'a, impl Iterator<Item = &'a [u8; 32]>>,
+) -> SlotClaimSearch {
+    // Note that this calculation can overflow in the very distant future. This is considered
+    // acceptable.
+    let current_slot = u64::try_from(
+        config.now_from_unix_epoch.as_millis() / u128::from(config.slot_duration.get()),
+    )
+    .unwrap();
+
+    // Slot numbers must be strictly increasing between a parent and its child.
+    let first_slot = match config.parent_block_slot_number {
+        Some(parent_slot) => cmp::max(current_slot, parent_slot.saturating_add(1)),
+        None => current_slot,
+    };
+
+    // Determine the epoch the new block belongs to, and the slot at which this epoch ends.
+    let (epoch, end_slot) = match (
+        &config.parent_block_epoch,
+        config.parent_block_next_epoch.start_slot_number,
+    ) {
+        (Some(parent_epoch), Some(next_epoch_start)) if first_slot < next_epoch_start => {
+            (parent_epoch, next_epoch_start)
+        }
+        (Some(_), Some(next_epoch_start)) => (
+            &config.parent_block_next_epoch,
+            next_epoch_start.saturating_add(config.slots_per_epoch.get()),
+        ),
+        _ => {
+            // The parent block is the genesis block. Epoch #0 starts at the slot of block #1.
+            debug_assert!(config.parent_block_epoch.is_none());
+            debug_assert_eq!(config.parent_block_next_epoch.epoch_index, 0);
+            (
+                &config.parent_block_next_epoch,
+                first_slot.saturating_add(config.slots_per_epoch.get()),
+            )
+        }
+    };
+
+    // If the epoch that follows the one of the parent has entirely been skipped, the information
+    // about the epoch of the new block isn't known.
+    // TODO: Substrate re-uses the information of the skipped epoch in that situation; not supported
+    if first_slot >= end_slot {
+        return SlotClaimSearch::Finished(None);
+    }
+
+    let candidates = config
+        .local_authorities
+        .enumerate()
+        .filter_map(|(local_authorities_index, local_pub_key)| {
+            // TODO: O(n) complexity
+            let (authority_index, authority) = epoch
+                .authorities
+                .clone()
+                .enumerate()
+                .find(|(_, a)| a.public_key == local_pub_key)?;
+
+            // An authority with a weight of 0 can never claim a primary slot.
+            let primary_threshold = if authority.weight != 0 {
+                verify_babe::calculate_primary_threshold(
+                    epoch.c,
+                    epoch.authorities.clone().map(|a| a.weight),
+                    authority.weight,
+                )
+            } else {
+                0
+            };
+
+            Some(Candidate {
+                local_authorities_index,
+                authority_index: u32::try_from(authority_index).ok()?,
+                public_key: *local_pub_key,
+                primary_threshold,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if candidates.is_empty() {
+        return SlotClaimSearch::Finished(None);
+    }
+
+    let mut inner = Inner {
+        slot_duration: config.slot_duration,
+        epoch_index: epoch.epoch_index,
+        randomness: *epoch.randomness,
+        allowed_slots: epoch.allowed_slots,
+        num_authorities: epoch.authorities.len(),
+        candidates,
+        slot_number: first_slot,
+        end_slot,
+        next_candidate: 0,
+        secondary_candidate: None,
+        secondary_vrf: None,
+    };
+
+    inner.start_slot();
+    inner.run()
+}
+
+/// Current state of the search for a slot claim.
+#[must_use]
+#[derive(Debug)]
+pub enum SlotClaimSearch {
+    /// Search is over. Contains `None` if none of the local authorities can claim a slot before
+    /// the end of the epoch.
+    Finished(Option<SlotClaim>),
+
+    /// A VRF output must be generated in order to continue the search.
+    VrfSign(VrfSign),
+}
+
+/// A VRF output must be generated in order to continue the search.
+#[must_use]
+#[derive(Debug)]
+pub struct VrfSign {
+    inner: Inner,
+}
+
+impl VrfSign {
+    /// Returns the index within [`Config::local_authorities`] of the authority whose private key
+    /// must be used to generate the VRF output.
+    pub fn local_authorities_index(&self) -> usize {
+        self.inner.candidates[self.inner.next_candidate].local_authorities_index
+    }
+
+    /// Returns the Sr25519 public key of the authority whose private key must be used to generate
+    /// the VRF output.
+    pub fn public_key(&self) -> &[u8; 32] {
+        &self.inner.candidates[self.inner.next_candidate].public_key
+    }
+
+    /// Returns the slot the VRF output is generated for.
+    pub fn slot_number(&self) -> u64 {
+        self.inner.slot_number
+    }
+
+    /// Returns the label of the transcript to use to generate the VRF output.
+    pub fn transcript_label(&self) -> &'static [u8] {
+        b"BABE"
+    }
+
+    /// Returns the list of items to append, in order, to the transcript used to generate the VRF
+    /// output. Each item consists of a label and of either a message or a `u64`.
+    pub fn transcript_items(
+        &'_ self,
+    ) -> impl Iterator<Item = (&'static [u8], either::Either<&'_ [u8], u64>)> + Clone + '_ {
+        [
+            (&b"slot number"[..], either::Right(self.inner.slot_number)),
+            (&b"current epoch"[..], either::Right(self.inner.epoch_index)),
+            (
+                &b"chain randomness"[..],
+                either::Left(&self.inner.randomness[..]),
+            ),
+        ]
+        .into_iter()
+    }
+
+    /// Injects the VRF output and proof generated using the transcript and the private key of
+    /// the authority, and resumes the search.
+    ///
+    /// If `output` isn't a valid VRF output, the authority is considered as unable to claim the
+    /// slot as a primary slot claim.
+    pub fn inject_vrf_output(mut self, output: [u8; 32], proof: [u8; 64]) -> SlotClaimSearch {
+        let candidate_index = self.inner.next_candidate;
+        let candidate = &self.inner.candidates[candidate_index];
+
+        let in_out = {
+            let mut transcript = merlin::Transcript::new(self.transcript_label());
+            for (label, value) in self.transcript_items() {
+                match value {
+                    either::Left(bytes) => transcript.append_message(label, bytes),
+                    either::Right(value) => transcript.append_u64(label, value),
+                }
+            }
+
+            schnorrkel::PublicKey::from_bytes(&candidate.public_key)
+                .and_then(|public_key| {
+                    schnorrkel::vrf::VRFPreOut::from_bytes(&output)?
+                        .attach_input_hash(&public_key, transcript)
+                })
+                .ok()
+        };
+
+        // A primary slot claim is possible if the VRF output is below the threshold of the
+        // authority.
+        if let Some(in_out) = in_out {
+            if u128::from_le_bytes(in_out.make_bytes::<[u8; 16]>(b"substrate-babe-vrf"))
+                < candidate.primary_threshold
+            {
+                let pre_digest = header::BabePreDigest::Primary(header::BabePrimaryPreDigest {
+                    authority_index: candidate.authority_index,
+                    slot_number: self.inner.slot_number,
+                    vrf_output: output,
+                    vrf_proof: proof,
+                });
+                return SlotClaimSearch::Finished(Some(
+                    self.inner.claim(candidate_index, pre_digest),
+                ));
+            }
+        }
+
+        // The VRF output is kept in case the authority is allowed to claim the slot as a
+        // secondary slot claim.
+        if self.inner.secondary_candidate == Some(candidate_index) {
+            self.inner.secondary_vrf = Some((output, proof));
+        }
+
+        self.inner.next_candidate += 1;
+        self.inner.run()
+    }
+}
+
+/// Slot happening now or in the future and that can be claimed by one of the authorities in
+/// [`Config::local_authorities`].
+///
+/// See also [`next_slot_claim`].
+#[derive(Debug, Clone)]
+pub struct SlotClaim {
+    /// UNIX time when the slot starts. Can be inferior to the value passed to
+    /// [`Config::now_from_unix_epoch`] if the slot has already started.
+    pub slot_start_from_unix_epoch: Duration,
+    /// UNIX time when the slot ends.
+    pub slot_end_from_unix_epoch: Duration,
+    /// Slot number of the claim. Used when building the block.
+    pub slot_number: u64,
+    /// Index within [`Config::local_authorities`] of the authority that can produce the block.
+    pub local_authorities_index: usize,
+    /// Babe pre-runtime digest item to put in the header of the block.
+    pub pre_digest: header::BabePreDigest,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// See [`Config::slot_duration`].
+    slot_duration: NonZeroU64,
+    /// Index of the epoch the block to author belongs to.
+    epoch_index: u64,
+    /// Randomness value of the epoch the block to author belongs to.
+    randomness: [u8; 32],
+    /// Types of slot claims allowed in the epoch the block to author belongs to.
+    allowed_slots: header::BabeAllowedSlots,
+    /// Number of authorities in the epoch the block to author belongs to.
+    num_authorities: usize,
+    /// List of local authorities that are part of the authorities of the epoch. Never empty.
+    candidates: Vec<Candidate>,
+    /// Slot currently being examined.
+    slot_number: u64,
+    /// Slot at which the search stops. Never reached.
+    end_slot: u64,
+    /// Index within [`Inner::candidates`] of the next candidate whose VRF output must be
+    /// generated for [`Inner::slot_number`].
+    next_candidate: usize,
+    /// If one of the candidates is allowed to claim [`Inner::slot_number`] as a secondary slot
+    /// claim, contains its index within [`Inner::candidates`].
+    secondary_candidate: Option<usize>,
+    /// VRF output and proof of [`Inner::secondary_candidate`] for [`Inner::slot_number`], if it
+    /// has been generated already.
+    secondary_vrf: Option<([u8; 32], [u8; 64])>,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    /// Index within [`Config::local_authorities`].
+    local_authorities_index: usize,
+    /// Index within the list of authorities of the epoch.
+    authority_index: u32,
+    /// Sr25519 public key of the authority.
+    public_key: [u8; 32],
+    /// VRF outputs below this threshold are allowed to claim a primary slot.
+    primary_threshold: u128,
+}
+
+impl Inner {
+    /// Resets the state of the search for the current value of [`Inner::slot_number`].
+    fn start_slot(&mut self) {
+        let secondary_author = verify_babe::calculate_secondary_slot_author(
+            &self.randomness,
+            self.slot_number,
+            self.num_authorities,
+        );
+
+        self.next_candidate = 0;
+        self.secondary_vrf = None;
+        self.secondary_candidate = self
+            .candidates
+            .iter()
+            .position(|c| usize::try_from(c.authority_index).unwrap() == secondary_author);
+    }
+
+    fn run(mut self) -> SlotClaimSearch {
+        loop {
+            if self.next_candidate < self.candidates.len() {
+                return SlotClaimSearch::VrfSign(VrfSign { inner: self });
+            }
+
+            // None of the candidates can claim the slot as a primary slot claim. Check whether
+            // one of them can claim it as a secondary slot claim.
+            if let Some(candidate_index) = self.secondary_candidate.take() {
+                let authority_index = self.candidates[candidate_index].authority_index;
+                let pre_digest = match (self.allowed_slots, self.secondary_vrf.take()) {
+                    (header::BabeAllowedSlots::PrimarySlots, _) => None,
+                    (header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots, _) => {
+                        Some(header::BabePreDigest::SecondaryPlain(
+                            header::BabeSecondaryPlainPreDigest {
+                                authority_index,
+                                slot_number: self.slot_number,
+                            },
+                        ))
+                    }
+                    (
+                        header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots,
+                        Some((output, proof)),
+                    ) => Some(header::BabePreDigest::SecondaryVRF(
+                        header::BabeSecondaryVRFPreDigest {
+                            authority_index,
+                            slot_number: self.slot_number,
+                            vrf_output: output,
+                            vrf_proof: proof,
+                        },
+                    )),
+                    (header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots, None) => {
+                        // The VRF output of all the candidates is always generated before
+                        // reaching this point.
+                        unreachable!()
+                    }
+                };
+
+                if let Some(pre_digest) = pre_digest {
+                    return SlotClaimSearch::Finished(Some(
+                        self.claim(candidate_index, pre_digest),
+                    ));
+                }
+            }
+
+            // Switch to the next slot.
+            self.slot_number += 1;
+            if self.slot_number >= self.end_slot {
+                return SlotClaimSearch::Finished(None);
+            }
+            self.start_slot();
+        }
+    }
+
+    fn claim(&self, candidate_index: usize, pre_digest: header::BabePreDigest) -> SlotClaim {
+        let slot_start_from_unix_epoch = Duration::from_millis(
+            self.slot_number
+                .checked_mul(self.slot_duration.get())
+                .unwrap(),
+        );
+        let slot_end_from_unix_epoch =
+            slot_start_from_unix_epoch + Duration::from_millis(self.slot_duration.get());
+
+        SlotClaim {
+            slot_start_from_unix_epoch,
+            slot_end_from_unix_epoch,
+            slot_number: self.slot_number,
+            local_authorities_index: self.candidates[candidate_index].local_authorities_index,
+            pre_digest,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_slot_claim, Config, SlotClaim, SlotClaimSearch};
+    use crate::{chain::chain_information, header, verify::babe as verify_babe};
+
+    use core::{iter, num::NonZeroU64, time::Duration};
+
+    const SLOT_DURATION: u64 = 6000;
+    const SLOTS_PER_EPOCH: u64 = 100;
+
+    fn keypair() -> schnorrkel::Keypair {
+        schnorrkel::MiniSecretKey::from_bytes(&[7; 32])
+            .unwrap()
+            .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519)
+    }
+
+    fn epoch<'a>(
+        epoch_index: u64,
+        start_slot_number: Option<u64>,
+        authorities: &'a [header::BabeAuthority],
+        randomness: &'a [u8; 32],
+        c: (u64, u64),
+        allowed_slots: header::BabeAllowedSlots,
+    ) -> chain_information::BabeEpochInformationRef<'a> {
+        chain_information::BabeEpochInformationRef {
+            epoch_index,
+            start_slot_number,
+            authorities: header::BabeAuthoritiesIter::from_slice(authorities),
+            randomness,
+            c,
+            allowed_slots,
+        }
+    }
+
+    /// Runs the search to completion, generating the VRF outputs with [`keypair`]. Also returns
+    /// the epoch index found in the first transcript.
+    fn run_search(mut search: SlotClaimSearch) -> (Option<SlotClaim>, Option<u64>) {
+        let keypair = keypair();
+        let mut epoch_index = None;
+
+        loop {
+            match search {
+                SlotClaimSearch::Finished(claim) => return (claim, epoch_index),
+                SlotClaimSearch::VrfSign(vrf_sign) => {
+                    assert_eq!(vrf_sign.local_authorities_index(), 0);
+                    assert_eq!(*vrf_sign.public_key(), keypair.public.to_bytes());
+
+                    let mut transcript = merlin::Transcript::new(vrf_sign.transcript_label());
+                    for (label, value) in vrf_sign.transcript_items() {
+                        match value {
+                            either::Left(bytes) => transcript.append_message(label, bytes),
+                            either::Right(value) => {
+                                if label == b"current epoch" && epoch_index.is_none() {
+                                    epoch_index = Some(value);
+                                }
+                                transcript.append_u64(label, value)
+                            }
+                        }
+                    }
+
+                    let (in_out, proof, _) = keypair.vrf_sign(transcript);
+                    search =
+                        vrf_sign.inject_vrf_output(in_out.to_preout().to_bytes(), proof.to_bytes());
+                }
+            }
+        }
+    }
+
+    /// Builds and seals a child of `parent_header` that uses the given claim, then verifies it.
+    fn verify(
+        claim: &SlotClaim,
+        parent_header: header::HeaderRef,
+        parent_block_epoch: Option<chain_information::BabeEpochInformationRef>,
+        parent_block_next_epoch: chain_information::BabeEpochInformationRef,
+        epoch_change: bool,
+    ) -> Result<verify_babe::VerifySuccess, verify_babe::VerifyError> {
+        let keypair = keypair();
+        let parent_hash = parent_header.hash(4);
+
+        let mut digest_items = vec![header::DigestItem::BabePreDigest(claim.pre_digest.clone())];
+        if epoch_change {
+            digest_items.push(header::DigestItem::BabeConsensus(
+                header::BabeConsensusLog::NextEpochData(header::BabeNextEpoch {
+                    authorities: vec![header::BabeAuthority {
+                        public_key: keypair.public.to_bytes(),
+                        weight: 1,
+                    }],
+                    randomness: [0; 32],
+                }),
+            ));
+        }
+
+        let pre_seal_hash = header::HeaderRef {
+            parent_hash: &parent_hash,
+            number: parent_header.number + 1,
+            state_root: &[0; 32],
+            extrinsics_root: &[0; 32],
+            digest: header::DigestRef::from_slice(&digest_items).unwrap(),
+        }
+        .hash(4);
+        digest_items.push(header::DigestItem::BabeSeal(
+            keypair.sign_simple(b"substrate", &pre_seal_hash).to_bytes(),
+        ));
+
+        verify_babe::verify_header(verify_babe::VerifyConfig {
+            header: header::HeaderRef {
+                parent_hash: &parent_hash,
+                number: parent_header.number + 1,
+                state_root: &[0; 32],
+                extrinsics_root: &[0; 32],
+                digest: header::DigestRef::from_slice(&digest_items).unwrap(),
+            },
+            block_number_bytes: 4,
+            parent_block_header: parent_header,
+            now_from_unix_epoch: claim.slot_start_from_unix_epoch,
+            slots_per_epoch: NonZeroU64::new(SLOTS_PER_EPOCH).unwrap(),
+            parent_block_epoch,
+            parent_block_next_epoch,
+        })
+    }
+
+    /// Non-genesis parent block at slot 150, in epoch #1 that started at slot 100.
+    fn parent_digest_items() -> Vec<header::DigestItem> {
+        vec![header::DigestItem::BabePreDigest(
+            header::BabePreDigest::SecondaryPlain(header::BabeSecondaryPlainPreDigest {
+                authority_index: 0,
+                slot_number: 150,
+            }),
+        )]
+    }
+
+    fn claim_after_parent(
+        now_slot: u64,
+        c: (u64, u64),
+        allowed_slots: header::BabeAllowedSlots,
+        check: impl FnOnce(Option<SlotClaim>, Option<u64>, bool),
+    ) {
+        let public_key = keypair().public.to_bytes();
+        let authorities = [header::BabeAuthority {
+            public_key,
+            weight: 1,
+        }];
+        let randomness1 = [1; 32];
+        let randomness2 = [2; 32];
+        let parent_epoch = epoch(1, Some(100), &authorities, &randomness1, c, allowed_slots);
+        let next_epoch = epoch(2, Some(200), &authorities, &randomness2, c, allowed_slots);
+
+        let (claim, epoch_index) = run_search(next_slot_claim(Config {
+            now_from_unix_epoch: Duration::from_millis(now_slot * SLOT_DURATION),
+            slot_duration: NonZeroU64::new(SLOT_DURATION).unwrap(),
+            slots_per_epoch: NonZeroU64::new(SLOTS_PER_EPOCH).unwrap(),
+            parent_block_slot_number: Some(150),
+            parent_block_epoch: Some(parent_epoch.clone()),
+            parent_block_next_epoch: next_epoch.clone(),
+            local_authorities: iter::once(&public_key),
+        }));
+
+        // `true` if a claim has been found and the block that uses it passes the verification.
+        let verified = match &claim {
+            None => false,
+            Some(claim) => {
+                let parent_digest_items = parent_digest_items();
+                let parent_header = header::HeaderRef {
+                    parent_hash: &[0; 32],
+                    number: 5,
+                    state_root: &[0; 32],
+                    extrinsics_root: &[0; 32],
+                    digest: header::DigestRef::from_slice(&parent_digest_items).unwrap(),
+                };
+                let epoch_change = claim.slot_number >= 200;
+                verify(
+                    claim,
+                    parent_header,
+                    Some(parent_epoch),
+                    next_epoch,
+                    epoch_change,
+                )
+                .is_ok()
+            }
+        };
+
+        check(claim, epoch_index, verified);
+    }
+
+    #[test]
+    fn genesis() {
+        let public_key = keypair().public.to_bytes();
+        let authorities = [header::BabeAuthority {
+            public_key,
+            weight: 1,
+        }];
+        let randomness = [0; 32];
+        let next_epoch = epoch(
+            0,
+            None,
+            &authorities,
+            &randomness,
+            (0, 1),
+            header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+        );
+
+        let (claim, epoch_index) = run_search(next_slot_claim(Config {
+            now_from_unix_epoch: Duration::from_millis(1000 * SLOT_DURATION + 10),
+            slot_duration: NonZeroU64::new(SLOT_DURATION).unwrap(),
+            slots_per_epoch: NonZeroU64::new(SLOTS_PER_EPOCH).unwrap(),
+            parent_block_slot_number: None,
+            parent_block_epoch: None,
+            parent_block_next_epoch: next_epoch.clone(),
+            local_authorities: iter::once(&public_key),
+        }));
+
+        let claim = claim.unwrap();
+        assert_eq!(epoch_index, Some(0));
+        assert_eq!(claim.slot_number, 1000);
+        assert_eq!(
+            claim.slot_start_from_unix_epoch,
+            Duration::from_millis(1000 * SLOT_DURATION)
+        );
+        assert_eq!(
+            claim.slot_end_from_unix_epoch,
+            Duration::from_millis(1001 * SLOT_DURATION)
+        );
+        assert_eq!(claim.local_authorities_index, 0);
+
+        let genesis_header = header::HeaderRef {
+            parent_hash: &[0; 32],
+            number: 0,
+            state_root: &[0; 32],
+            extrinsics_root: &[0; 32],
+            digest: header::DigestRef::empty(),
+        };
+        verify(&claim, genesis_header, None, next_epoch, true).unwrap();
+    }
+
+    #[test]
+    fn primary_claim() {
+        claim_after_parent(
+            160,
+            (1, 2),
+            header::BabeAllowedSlots::PrimarySlots,
+            |claim, epoch_index, verified| {
+                let claim = claim.unwrap();
+                assert_eq!(epoch_index, Some(1));
+                assert!(matches!(
+                    claim.pre_digest,
+                    header::BabePreDigest::Primary(ref d)
+                        if d.authority_index == 0 && d.slot_number == claim.slot_number
+                ));
+                assert!((160..200).contains(&claim.slot_number));
+                assert!(verified);
+            },
+        );
+    }
+
+    #[test]
+    fn parent_slot_in_future() {
+        claim_after_parent(
+            100,
+            (0, 1),
+            header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+            |claim, _, verified| {
+                assert_eq!(claim.unwrap().slot_number, 151);
+                assert!(verified);
+            },
+        );
+    }
+
+    #[test]
+    fn secondary_plain_claim() {
+        claim_after_parent(
+            160,
+            (0, 1),
+            header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+            |claim, _, verified| {
+                let claim = claim.unwrap();
+                assert_eq!(claim.slot_number, 160);
+                assert!(matches!(
+                    claim.pre_digest,
+                    header::BabePreDigest::SecondaryPlain(ref d)
+                        if d.authority_index == 0 && d.slot_number == 160
+                ));
+                assert!(verified);
+            },
+        );
+    }
+
+    #[test]
+    fn secondary_vrf_claim() {
+        claim_after_parent(
+            160,
+            (0, 1),
+            header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots,
+            |claim, _, verified| {
+                let claim = claim.unwrap();
+                assert_eq!(claim.slot_number, 160);
+                assert!(matches!(
+                    claim.pre_digest,
+                    header::BabePreDigest::SecondaryVRF(ref d)
+                        if d.authority_index == 0 && d.slot_number == 160
+                ));
+                assert!(verified);
+            },
+        );
+    }
+
+    #[test]
+    fn no_secondary_claim_if_forbidden() {
+        claim_after_parent(
+            160,
+            (0, 1),
+            header::BabeAllowedSlots::PrimarySlots,
+            |claim, _, _| assert!(claim.is_none()),
+        );
+    }
+
+    #[test]
+    fn next_epoch_selected() {
+        claim_after_parent(
+            250,
+            (0, 1),
+            header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots,
+            |claim, epoch_index, verified| {
+                assert_eq!(epoch_index, Some(2));
+                assert_eq!(claim.unwrap().slot_number, 250);
+                assert!(verified);
+            },
+        );
+    }
+
+    #[test]
+    fn search_stops_at_next_epoch_start() {
+        // The search starts in epoch #1 and must not continue into epoch #2.
+        claim_after_parent(
+            199,
+            (0, 1),
+            header::BabeAllowedSlots::PrimarySlots,
+            |claim, epoch_index, _| {
+                assert!(claim.is_none());
+                assert_eq!(epoch_index, Some(1));
+            },
+        );
+    }
+
+    #[test]
+    fn skipped_epoch() {
+        claim_after_parent(
+            300,
+            (0, 1),
+            header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+            |claim, epoch_index, _| {
+                assert!(claim.is_none());
+                assert!(epoch_index.is_none());
+            },
+        );
+    }
+
+    #[test]
+    fn not_an_authority() {
+        let public_key = keypair().public.to_bytes();
+        let authorities = [header::BabeAuthority {
+            public_key: [0xff; 32],
+            weight: 1,
+        }];
+        let randomness = [1; 32];
+        let current_epoch = epoch(
+            1,
+            Some(100),
+            &authorities,
+            &randomness,
+            (1, 2),
+            header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+        );
+        let next_epoch = epoch(
+            2,
+            Some(200),
+            &authorities,
+            &randomness,
+            (1, 2),
+            header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+        );
+
+        let search = next_slot_claim(Config {
+            now_from_unix_epoch: Duration::from_millis(160 * SLOT_DURATION),
+            slot_duration: NonZeroU64::new(SLOT_DURATION).unwrap(),
+            slots_per_epoch: NonZeroU64::new(SLOTS_PER_EPOCH).unwrap(),
+            parent_block_slot_number: Some(150),
+            parent_block_epoch: Some(current_epoch),
+            parent_block_next_epoch: next_epoch,
+            local_authorities: iter::once(&public_key),
+        });
+        assert!(matches!(search, SlotClaimSearch::Finished(None)));
+    }
+}
@@ -18,7 +18,7 @@
 // TODO: docs
 
 use crate::{
-    author::{aura, runtime},
+    author::{aura, babe, runtime},
     executor::host,
     header,
     trie::calculate_root,
@@ -55,7 +55,17 @@ pub enum ConfigConsensus<'a, TLocAuth> {
         /// Must implement `Iterator<Item = &[u8; 32]>`.
         local_authorities: TLocAuth,
     },
-    // TODO: Babe isn't supported yet
+
+    /// Chain is using the Babe consensus algorithm.
+    Babe {
+        /// Time elapsed since [the Unix Epoch](https://en.wikipedia.org/wiki/Unix_time) (i.e.
+        /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
+        now_from_unix_epoch: Duration,
+
+        /// Slot that has been claimed by one of the local authorities. Can be obtained through
+        /// [`babe::next_slot_claim`].
+        slot_claim: babe::SlotClaim,
+    },
 }
 
 /// Current state of the block building process.
@@ -100,6 +110,18 @@ impl Builder {
 
                 (WaitSlotConsensus::Aura(consensus), ready)
             }
+            ConfigConsensus::Babe {
+                now_from_unix_epoch,
+                slot_claim,
+            } => {
+                // The slot claim might have been determined a long time ago.
+                if now_from_unix_epoch >= slot_claim.slot_end_from_unix_epoch {
+                    return Builder::Idle;
+                }
+
+                let ready = now_from_unix_epoch >= slot_claim.slot_start_from_unix_epoch;
+                (WaitSlotConsensus::Babe(slot_claim), ready)
+            }
         };
 
         if ready {
@@ -160,6 +182,7 @@ pub struct WaitSlot {
 #[derive(Debug)]
 enum WaitSlotConsensus {
     Aura(aura::SlotClaim),
+    Babe(babe::SlotClaim),
 }
 
 impl WaitSlot {
@@ -167,8 +190,9 @@ impl WaitSlot {
     /// the UNIX epoch, ignoring leap seconds).
     pub fn when(&self) -> Duration {
         // TODO: we can actually start building the block before our slot in some situations?
-        match self.consensus {
+        match &self.consensus {
             WaitSlotConsensus::Aura(claim) => claim.slot_start_from_unix_epoch,
+            WaitSlotConsensus::Babe(claim) => claim.slot_start_from_unix_epoch,
         }
     }
 
@@ -193,8 +217,9 @@ impl AuthoringStart {
     /// Returns when the authoring slot start, as a UNIX timestamp (i.e. number of seconds since
     /// the UNIX epoch, ignoring leap seconds).
     pub fn slot_start_from_unix_epoch(&self) -> Duration {
-        match self.consensus {
+        match &self.consensus {
             WaitSlotConsensus::Aura(claim) => claim.slot_start_from_unix_epoch,
+            WaitSlotConsensus::Babe(claim) => claim.slot_start_from_unix_epoch,
         }
     }
 
@@ -206,8 +231,9 @@ impl AuthoringStart {
     /// authored **and** propagated throughout the entire peer-to-peer network before the slot
     /// ends.
     pub fn slot_end_from_unix_epoch(&self) -> Duration {
-        match self.consensus {
+        match &self.consensus {
             WaitSlotConsensus::Aura(claim) => claim.slot_end_from_unix_epoch,
+            WaitSlotConsensus::Babe(claim) => claim.slot_end_from_unix_epoch,
         }
     }
 
@@ -220,12 +246,15 @@ impl AuthoringStart {
             parent_runtime: config.parent_runtime,
             top_trie_root_calculation_cache: config.top_trie_root_calculation_cache,
             block_body_capacity: config.block_body_capacity,
-            consensus_digest_log_item: match self.consensus {
+            consensus_digest_log_item: match &self.consensus {
                 WaitSlotConsensus::Aura(slot) => {
                     runtime::ConfigPreRuntime::Aura(header::AuraPreDigest {
                         slot_number: slot.slot_number,
                     })
                 }
+                WaitSlotConsensus::Babe(slot) => {
+                    runtime::ConfigPreRuntime::Babe((&slot.pre_digest).into())
+                }
             },
         });
 
@@ -307,6 +336,12 @@ impl StorageGet {
         self.0.key()
     }
 
+    /// If `Some`, the key must be read from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.0.child_trie()
+    }
+
     /// Injects the corresponding storage value.
     pub fn inject_value(
         self,
@@ -327,6 +362,12 @@ impl PrefixKeys {
         self.0.prefix()
     }
 
+    /// If `Some`, the keys must be loaded from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.0.child_trie()
+    }
+
     /// Injects the list of keys ordered lexicographically.
     pub fn inject_keys_ordered(
         self,
@@ -347,6 +388,13 @@ impl NextKey {
         self.0.key()
     }
 
+    /// If `Some`, the next key must be searched in the given default child trie rather than in
+    /// the main trie. The child trie identifier doesn't include the `:child_storage:default:`
+    /// prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.0.child_trie()
+    }
+
     /// Injects the key.
     ///
     /// # Panic
@@ -379,10 +427,12 @@ impl Seal {
     /// Returns the index within the list of authorities of the authority that must sign the
     /// block.
     ///
-    /// See [`ConfigConsensus::Aura::local_authorities`].
+    /// See [`ConfigConsensus::Aura::local_authorities`] and
+    /// [`babe::SlotClaim::local_authorities_index`].
     pub fn authority_index(&self) -> usize {
-        match self.shared.slot_claim {
+        match &self.shared.slot_claim {
             WaitSlotConsensus::Aura(slot) => slot.local_authorities_index,
+            WaitSlotConsensus::Babe(slot) => slot.local_authorities_index,
         }
     }
 
@@ -399,9 +449,13 @@ impl Seal {
         .unwrap()
         .into();
 
-        // `push_aura_seal` error if there is already an Aura seal, indicating that the runtime
-        // code is misbehaving. This condition is already verified when the `Seal` is created.
-        header.digest.push_aura_seal(signature).unwrap();
+        // `push_aura_seal` and `push_babe_seal` error if there is already a seal, indicating that
+        // the runtime code is misbehaving. This condition is already verified when the `Seal` is
+        // created.
+        match self.shared.slot_claim {
+            WaitSlotConsensus::Aura(_) => header.digest.push_aura_seal(signature).unwrap(),
+            WaitSlotConsensus::Babe(_) => header.digest.push_babe_seal(signature).unwrap(),
+        }
 
         self.block.scale_encoded_header = header
             .scale_encoding(self.shared.block_number_bytes)
@@ -55,7 +55,7 @@ use crate::{
     verify::inherents,
 };
 
-use alloc::{borrow::ToOwned as _, string::String, vec::Vec};
+use alloc::{borrow::ToOwned as _, collections::BTreeMap, string::String, vec::Vec};
 use core::{iter, mem};
 
 /// Configuration for a block generation.
@@ -110,6 +110,9 @@ pub struct Success {
     pub parent_runtime: host::HostVmPrototype,
     /// List of changes to the storage top trie that the block performs.
     pub storage_top_trie_changes: storage_diff::StorageDiff,
+    /// List of changes to the default child tries that the block performs. Keys are the
+    /// identifiers of the child tries, without the `:child_storage:default:` prefix.
+    pub storage_child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
     /// List of changes to the off-chain storage that this block performs.
     pub offchain_storage_changes: storage_diff::StorageDiff,
     /// Cache used for calculating the top trie root of the new block.
@@ -187,6 +190,7 @@ pub fn build_block(config: Config) -> BlockBuild {
         },
         top_trie_root_calculation_cache: config.top_trie_root_calculation_cache,
         storage_top_trie_changes: Default::default(),
+        storage_child_tries_changes: Default::default(),
         offchain_storage_changes: Default::default(),
     });
 
@@ -273,6 +277,9 @@ impl BlockBuild {
                 (Inner::Runtime(runtime_host::RuntimeHostVm::NextKey(inner)), _) => {
                     return BlockBuild::NextKey(NextKey(inner, shared))
                 }
+                (Inner::Runtime(runtime_host::RuntimeHostVm::Offchain(ctx)), _) => {
+                    return BlockBuild::Finished(Err(Error::WasmVm(ctx.forbid())))
+                }
 
                 (
                     Inner::Runtime(runtime_host::RuntimeHostVm::Finished(Ok(success))),
@@ -289,6 +296,7 @@ impl BlockBuild {
                         shared,
                         parent_runtime: success.virtual_machine.into_prototype(),
                         storage_top_trie_changes: success.storage_top_trie_changes,
+                        storage_child_tries_changes: success.storage_child_tries_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
                         top_trie_root_calculation_cache: success.top_trie_root_calculation_cache,
                     });
@@ -324,6 +332,7 @@ impl BlockBuild {
                             success.top_trie_root_calculation_cache,
                         ),
                         storage_top_trie_changes: success.storage_top_trie_changes,
+                        storage_child_tries_changes: success.storage_child_tries_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
                     });
 
@@ -340,6 +349,7 @@ impl BlockBuild {
                         shared,
                         parent_runtime: success.virtual_machine.into_prototype(),
                         storage_top_trie_changes: success.storage_top_trie_changes,
+                        storage_child_tries_changes: success.storage_child_tries_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
                         top_trie_root_calculation_cache: success.top_trie_root_calculation_cache,
                     });
@@ -410,6 +420,7 @@ impl BlockBuild {
                             shared,
                             parent_runtime: success.virtual_machine.into_prototype(),
                             storage_top_trie_changes: success.storage_top_trie_changes,
+                            storage_child_tries_changes: success.storage_child_tries_changes,
                             offchain_storage_changes: success.offchain_storage_changes,
                             top_trie_root_calculation_cache: success
                                 .top_trie_root_calculation_cache,
@@ -428,6 +439,7 @@ impl BlockBuild {
                         body: shared.block_body,
                         parent_runtime: success.virtual_machine.into_prototype(),
                         storage_top_trie_changes: success.storage_top_trie_changes,
+                        storage_child_tries_changes: success.storage_child_tries_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
                         top_trie_root_calculation_cache: success.top_trie_root_calculation_cache,
                         logs: shared.logs,
@@ -471,6 +483,7 @@ pub struct InherentExtrinsics {
     shared: Shared,
     parent_runtime: host::HostVmPrototype,
     storage_top_trie_changes: storage_diff::StorageDiff,
+    storage_child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
     offchain_storage_changes: storage_diff::StorageDiff,
     top_trie_root_calculation_cache: calculate_root::CalculationCache,
 }
@@ -516,6 +529,7 @@ impl InherentExtrinsics {
             },
             top_trie_root_calculation_cache: Some(self.top_trie_root_calculation_cache),
             storage_top_trie_changes: self.storage_top_trie_changes,
+            storage_child_tries_changes: self.storage_child_tries_changes,
             offchain_storage_changes: self.offchain_storage_changes,
         });
 
@@ -534,6 +548,7 @@ pub struct ApplyExtrinsic {
     shared: Shared,
     parent_runtime: host::HostVmPrototype,
     storage_top_trie_changes: storage_diff::StorageDiff,
+    storage_child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
     offchain_storage_changes: storage_diff::StorageDiff,
     top_trie_root_calculation_cache: calculate_root::CalculationCache,
 }
@@ -549,6 +564,7 @@ impl ApplyExtrinsic {
             parameter: iter::once(&extrinsic),
             top_trie_root_calculation_cache: Some(self.top_trie_root_calculation_cache),
             storage_top_trie_changes: self.storage_top_trie_changes,
+            storage_child_tries_changes: self.storage_child_tries_changes,
             offchain_storage_changes: self.offchain_storage_changes,
         });
 
@@ -572,6 +588,7 @@ impl ApplyExtrinsic {
             parameter: iter::empty::<&[u8]>(),
             top_trie_root_calculation_cache: Some(self.top_trie_root_calculation_cache),
             storage_top_trie_changes: self.storage_top_trie_changes,
+            storage_child_tries_changes: self.storage_child_tries_changes,
             offchain_storage_changes: self.offchain_storage_changes,
         });
 
@@ -594,6 +611,12 @@ impl StorageGet {
         self.0.key()
     }
 
+    /// If `Some`, the key must be read from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.0.child_trie()
+    }
+
     /// Injects the corresponding storage value.
     pub fn inject_value(self, value: Option<impl Iterator<Item = impl AsRef<[u8]>>>) -> BlockBuild {
         BlockBuild::from_inner(self.0.inject_value(value), self.1)
@@ -611,6 +634,12 @@ impl PrefixKeys {
         self.0.prefix()
     }
 
+    /// If `Some`, the keys must be loaded from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.0.child_trie()
+    }
+
     /// Injects the list of keys ordered lexicographically.
     pub fn inject_keys_ordered(self, keys: impl Iterator<Item = impl AsRef<[u8]>>) -> BlockBuild {
         BlockBuild::from_inner(self.0.inject_keys_ordered(keys), self.1)
@@ -628,6 +657,13 @@ impl NextKey {
         self.0.key()
     }
 
+    /// If `Some`, the next key must be searched in the given default child trie rather than in
+    /// the main trie. The child trie identifier doesn't include the `:child_storage:default:`
+    /// prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.0.child_trie()
+    }
+
     /// Injects the key.
     ///
     /// # Panic
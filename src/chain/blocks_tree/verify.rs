@@ -33,7 +33,7 @@ use super::{
     FinalizedConsensus, NonFinalizedTree, NonFinalizedTreeInner, Vec,
 };
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, collections::BTreeMap};
 use core::cmp::Ordering;
 
 impl<T> NonFinalizedTree<T> {
@@ -600,6 +600,7 @@ impl<T> VerifyContext<T> {
                     parent_runtime: success.parent_runtime,
                     new_runtime: success.new_runtime,
                     storage_top_trie_changes: success.storage_top_trie_changes,
+                    storage_child_tries_changes: success.storage_child_tries_changes,
                     offchain_storage_changes: success.offchain_storage_changes,
                     top_trie_root_calculation_cache: success.top_trie_root_calculation_cache,
                     insert: BodyInsert {
@@ -845,6 +846,9 @@ pub enum BodyVerifyStep2<T> {
         new_runtime: Option<host::HostVmPrototype>,
         /// List of changes to the storage top trie that the block performs.
         storage_top_trie_changes: storage_diff::StorageDiff,
+        /// List of changes to the default child tries that the block performs. Keys are the
+        /// identifiers of the child tries, without the `:child_storage:default:` prefix.
+        storage_child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
         /// List of changes to the off-chain storage that this block performs.
         offchain_storage_changes: storage_diff::StorageDiff,
         /// Cache of calculation for the storage trie of the best block.
@@ -901,6 +905,12 @@ impl<T> StorageGet<T> {
         self.inner.key()
     }
 
+    /// If `Some`, the key must be read from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Access to the Nth ancestor's information and hierarchy. Returns `None` if `n` is too
     /// large. A value of `0` for `n` corresponds to the parent block. A value of `1` corresponds
     /// to the parent's parent. And so on.
@@ -960,6 +970,12 @@ impl<T> StoragePrefixKeys<T> {
         self.inner.prefix()
     }
 
+    /// If `Some`, the keys must be loaded from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Access to the Nth ancestor's information and hierarchy. Returns `None` if `n` is too
     /// large. A value of `0` for `n` corresponds to the parent block. A value of `1` corresponds
     /// to the parent's parent. And so on.
@@ -1019,6 +1035,13 @@ impl<T> StorageNextKey<T> {
         self.inner.key()
     }
 
+    /// If `Some`, the next key must be searched in the given default child trie rather than in
+    /// the main trie. The child trie identifier doesn't include the `:child_storage:default:`
+    /// prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Access to the Nth ancestor's information and hierarchy. Returns `None` if `n` is too
     /// large. A value of `0` for `n` corresponds to the parent block. A value of `1` corresponds
     /// to the parent's parent. And so on.
@@ -22,3 +22,4 @@
 
 pub mod finalized_serialize;
 pub mod full_sqlite;
+pub mod transactions_serialize;
@@ -35,10 +35,18 @@
 //! Any block that isn't an ancestor or descendant will be removed. Reverting finalization is
 //! not supported.
 //!
-//! In order to minimize disk usage, it is not possible to efficiently retrieve the storage items
-//! of blocks that are ancestors of the finalized block. When a block is finalized, the storage of
-//! its ancestors is lost, and the only way to reconstruct it is to execute all blocks starting
-//! from the genesis to the desired one.
+//! In order to minimize disk usage, the storage of the blocks that are ancestors of the finalized
+//! block is by default discarded when a block is finalized, and the only way to reconstruct it is
+//! to execute all blocks starting from the genesis to the desired one. The [`PruningMode`] passed
+//! to [`open()`] makes it possible to instead keep the storage of a certain number of ancestors
+//! of the finalized block, or of all of them (also known as "archive mode"). The storage of these
+//! ancestors can then be accessed in the same way as the storage of the non-finalized blocks.
+//!
+//! Use [`SqliteFullDatabase::export_finalized_state`] to write the finalized block and its
+//! storage to a portable snapshot, and [`DatabaseEmpty::import_finalized_state`] to initialize
+//! a database from such a snapshot, for example on a different machine.
+//! [`SqliteFullDatabase::reset`] replaces the entire content of an existing database with a
+//! new finalized block and its storage, for example after it has been downloaded from the network.
 //!
 //! # About errors handling
 //!
@@ -70,14 +78,18 @@
 #![cfg(feature = "database-sqlite")]
 #![cfg_attr(docsrs, doc(cfg(feature = "database-sqlite")))]
 
-use crate::{chain::chain_information, header, util};
+use crate::{chain::chain_information, database::finalized_serialize, executor, header, util};
 
-use core::{fmt, iter, num::NonZeroU64};
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::{cmp, fmt, iter, num::NonZeroU64, ops};
 use parking_lot::Mutex;
+use std::io;
 
-pub use open::{open, Config, ConfigTy, DatabaseEmpty, DatabaseOpen};
+pub use open::{open, Config, ConfigTy, DatabaseEmpty, DatabaseOpen, PruningMode};
 
 mod open;
+mod snapshot;
+mod tests;
 
 /// An open database. Holds file descriptors.
 pub struct SqliteFullDatabase {
@@ -306,7 +318,9 @@ impl SqliteFullDatabase {
     /// Insert a new block in the database.
     ///
     /// Must pass the header and body of the block, and the changes to the storage that this block
-    /// performs relative to its parent.
+    /// performs relative to its parent. The changes to the default child tries are passed as
+    /// tuples of child trie identifier (without the `:child_storage:default:` prefix), key, and
+    /// value.
     ///
     /// Blocks must be inserted in the correct order. An error is returned if the parent of the
     /// newly-inserted block isn't present in the database.
@@ -317,6 +331,9 @@ impl SqliteFullDatabase {
         body: impl ExactSizeIterator<Item = impl AsRef<[u8]>>,
         storage_top_trie_changes: impl Iterator<Item = (impl AsRef<[u8]>, Option<impl AsRef<[u8]>>)>
             + Clone,
+        storage_child_tries_changes: impl Iterator<
+            Item = (impl AsRef<[u8]>, impl AsRef<[u8]>, Option<impl AsRef<[u8]>>),
+        >,
     ) -> Result<(), InsertError> {
         // Calculate the hash of the new best block.
         let block_hash = header::hash_from_scale_encoded_header(scale_encoded_header);
@@ -393,6 +410,27 @@ impl SqliteFullDatabase {
             statement = statement.reset().unwrap();
         }
 
+        let mut statement = connection
+            .prepare("INSERT INTO non_finalized_child_tries_changes(hash, child_trie, key, value) VALUES (?, ?, ?, ?)")
+            .unwrap();
+        for (child_trie, key, value) in storage_child_tries_changes {
+            statement = statement
+                .bind(1, &block_hash[..])
+                .unwrap()
+                .bind(2, child_trie.as_ref())
+                .unwrap()
+                .bind(3, key.as_ref())
+                .unwrap();
+            if let Some(value) = value {
+                statement = statement.bind(4, value.as_ref()).unwrap();
+            } else {
+                // Binds NULL.
+                statement = statement.bind(4, ()).unwrap();
+            }
+            statement.next().unwrap();
+            statement = statement.reset().unwrap();
+        }
+
         // Various other updates.
         if is_new_best {
             meta_set_blob(&connection, "best", &block_hash)?;
@@ -444,6 +482,19 @@ impl SqliteFullDatabase {
             return Err(SetFinalizedError::RevertForbidden);
         }
 
+        // Determine the oldest ancestor of the new finalized block whose storage must remain
+        // available.
+        let new_storage_history_start = {
+            let current_start = storage_history_start(&connection)?;
+            match pruning_mode(&connection)? {
+                PruningMode::Archive => current_start,
+                PruningMode::Prune { keep_ancestors } => cmp::max(
+                    current_start,
+                    new_finalized_header.number.saturating_sub(keep_ancestors),
+                ),
+            }
+        };
+
         // At this point, we are sure that the operation will succeed unless the database is
         // corrupted.
         // Update the finalized block in meta.
@@ -533,6 +584,27 @@ impl SqliteFullDatabase {
                     CorruptedError::MissingBlockHeader,
                 )))?;
 
+            // Before modifying the finalized storage, save the values that are about to be
+            // overwritten, in order to be able to later rebuild the storage of the parent of
+            // this block. This is skipped if the storage of the parent would immediately be
+            // discarded.
+            if height > new_storage_history_start {
+                let mut statement = connection
+                    .prepare(
+                        "INSERT INTO finalized_storage_history(hash, number, key, value)
+                    SELECT non_finalized_changes.hash, ?, non_finalized_changes.key, finalized_storage_top_trie.value
+                    FROM non_finalized_changes
+                    LEFT JOIN finalized_storage_top_trie ON finalized_storage_top_trie.key = non_finalized_changes.key
+                    WHERE non_finalized_changes.hash = ?",
+                    )
+                    .unwrap()
+                    .bind(1, i64::try_from(height).unwrap())
+                    .unwrap()
+                    .bind(2, &block_hash[..])
+                    .unwrap();
+                statement.next().unwrap();
+            }
+
             let mut statement = connection
                 .prepare(
                     "DELETE FROM finalized_storage_top_trie
@@ -565,6 +637,39 @@ impl SqliteFullDatabase {
                 .unwrap();
             statement.next().unwrap();
 
+            // Same as above, but for the child tries. Contrary to the top trie, no history of
+            // the child tries is kept.
+            let mut statement = connection
+                .prepare(
+                    "DELETE FROM finalized_storage_child_tries
+                WHERE (child_trie, key) IN (
+                    SELECT child_trie, key FROM non_finalized_child_tries_changes WHERE hash = ? AND value IS NULL
+                );",
+                )
+                .unwrap()
+                .bind(1, &block_hash[..])
+                .unwrap();
+            statement.next().unwrap();
+
+            let mut statement = connection
+                .prepare(
+                    "INSERT OR REPLACE INTO finalized_storage_child_tries(child_trie, key, value)
+                SELECT child_trie, key, value
+                FROM non_finalized_child_tries_changes
+                WHERE non_finalized_child_tries_changes.hash = ? AND non_finalized_child_tries_changes.value IS NOT NULL",
+                )
+                .unwrap()
+                .bind(1, &block_hash[..])
+                .unwrap();
+            statement.next().unwrap();
+
+            let mut statement = connection
+                .prepare("DELETE FROM non_finalized_child_tries_changes WHERE hash = ?")
+                .unwrap()
+                .bind(1, &block_hash[..])
+                .unwrap();
+            statement.next().unwrap();
+
             // TODO: the code below is very verbose and redundant with other similar code in smoldot ; could be improved
 
             if let Some((new_epoch, next_config)) = block_header.digest.babe_epoch_information() {
@@ -655,6 +760,19 @@ impl SqliteFullDatabase {
             }
         }
 
+        // Discard the storage of the ancestors of the finalized block that are no longer needed.
+        let mut statement = connection
+            .prepare("DELETE FROM finalized_storage_history WHERE number <= ?")
+            .unwrap()
+            .bind(1, i64::try_from(new_storage_history_start).unwrap())
+            .unwrap();
+        statement.next().unwrap();
+        meta_set_number(
+            &connection,
+            "storage_history_start",
+            new_storage_history_start,
+        )?;
+
         // It is possible that the best block has been pruned.
         // TODO: ^ yeah, how do we handle that exactly ^ ?
 
@@ -704,6 +822,215 @@ impl SqliteFullDatabase {
         Ok(out)
     }
 
+    /// Returns all the entries of the default child tries of the finalized block, as tuples of
+    /// child trie identifier (without the `:child_storage:default:` prefix), key, and value.
+    ///
+    /// In order to avoid race conditions, the known finalized block hash must be passed as
+    /// parameter. If the finalized block in the database doesn't match the hash passed as
+    /// parameter, most likely because it has been updated in a parallel thread, a
+    /// [`FinalizedAccessError::Obsolete`] error is returned.
+    pub fn finalized_block_storage_child_tries<T: FromIterator<(Vec<u8>, Vec<u8>, Vec<u8>)>>(
+        &self,
+        finalized_block_hash: &[u8; 32],
+    ) -> Result<T, FinalizedAccessError> {
+        let connection = self.database.lock();
+
+        if finalized_hash(&connection)? != *finalized_block_hash {
+            return Err(FinalizedAccessError::Obsolete);
+        }
+
+        let mut statement = connection
+            .prepare(r#"SELECT child_trie, key, value FROM finalized_storage_child_tries"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)
+            .map_err(FinalizedAccessError::Access)?;
+
+        let out: T = iter::from_fn(|| {
+            if !matches!(statement.next().unwrap(), sqlite::State::Row) {
+                return None;
+            }
+
+            let child_trie = statement.read::<Vec<u8>>(0).unwrap();
+            let key = statement.read::<Vec<u8>>(1).unwrap();
+            let value = statement.read::<Vec<u8>>(2).unwrap();
+            Some((child_trie, key, value))
+        })
+        .collect();
+
+        Ok(out)
+    }
+
+    /// Discards all the blocks and storage in the database and replaces them with the given
+    /// finalized block, its storage, and the information about the finalized chain.
+    ///
+    /// The entries of the default child tries are passed as `(child_trie, key, value)` tuples,
+    /// where `child_trie` doesn't include the `:child_storage:default:` prefix. The main trie
+    /// entries must include the roots of these child tries.
+    ///
+    /// This is typically used after the storage of a recent finalized block has been downloaded
+    /// from the network, in which case the new finalized block doesn't need to be a descendant
+    /// of the current one. The pruning mode and the off-chain storage are left untouched.
+    ///
+    /// The history of the storage starts at the new finalized block, even in archive mode.
+    pub fn reset<'a>(
+        &self,
+        chain_information: impl Into<chain_information::ChainInformationRef<'a>>,
+        finalized_block_body: impl ExactSizeIterator<Item = &'a [u8]>,
+        finalized_block_justification: Option<Vec<u8>>,
+        finalized_block_storage_top_trie_entries: impl Iterator<Item = (&'a [u8], &'a [u8])>,
+        finalized_block_storage_child_tries_entries: impl Iterator<
+            Item = (&'a [u8], &'a [u8], &'a [u8]),
+        >,
+    ) -> Result<(), AccessError> {
+        let connection = self.database.lock();
+
+        connection
+            .execute(
+                r#"
+DELETE FROM blocks_body;
+DELETE FROM non_finalized_changes;
+DELETE FROM non_finalized_child_tries_changes;
+DELETE FROM finalized_storage_history;
+DELETE FROM blocks;
+DELETE FROM finalized_storage_top_trie;
+DELETE FROM finalized_storage_child_tries;
+DELETE FROM grandpa_triggered_authorities;
+DELETE FROM grandpa_scheduled_authorities;
+DELETE FROM aura_finalized_authorities;
+DELETE FROM meta WHERE key NOT IN ("schema_version", "pruning_keep_ancestors");
+"#,
+            )
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?;
+
+        open::insert_finalized_state(
+            &connection,
+            self.block_number_bytes,
+            chain_information.into(),
+            finalized_block_body,
+            finalized_block_justification,
+            finalized_block_storage_top_trie_entries,
+        );
+
+        {
+            let mut statement = connection
+                .prepare(
+                    "INSERT INTO finalized_storage_child_tries(child_trie, key, value) VALUES(?, ?, ?)",
+                )
+                .unwrap();
+            for (child_trie, key, value) in finalized_block_storage_child_tries_entries {
+                statement = statement
+                    .bind(1, child_trie)
+                    .unwrap()
+                    .bind(2, key)
+                    .unwrap()
+                    .bind(3, value)
+                    .unwrap();
+                statement.next().unwrap();
+                statement = statement.reset().unwrap();
+            }
+        }
+
+        flush(&connection)
+    }
+
+    /// Writes to `out` a snapshot containing the header, body, and storage of the finalized
+    /// block (including its default child tries), plus the information about the finalized
+    /// chain.
+    ///
+    /// The snapshot can later be passed to [`DatabaseEmpty::import_finalized_state`] in order to
+    /// initialize a new database. The snapshot is written progressively and ends with a checksum
+    /// of its content.
+    ///
+    /// `genesis_block_hash` is the hash of the genesis block of the chain, and is written as is
+    /// in the snapshot. It is used when importing the snapshot in order to make sure that it
+    /// belongs to the expected chain. The genesis block itself isn't necessarily in the database.
+    ///
+    /// In order to avoid race conditions, the known finalized block hash must be passed as
+    /// parameter. If the finalized block in the database doesn't match the hash passed as
+    /// parameter, most likely because it has been updated in a parallel thread, a
+    /// [`FinalizedAccessError::Obsolete`] error is returned.
+    pub fn export_finalized_state(
+        &self,
+        finalized_block_hash: &[u8; 32],
+        genesis_block_hash: &[u8; 32],
+        out: impl io::Write,
+    ) -> Result<(), ExportStateError> {
+        let chain_information = self.to_chain_information(finalized_block_hash)?;
+        let body = self
+            .block_extrinsics(finalized_block_hash)
+            .map_err(FinalizedAccessError::Access)?
+            .ok_or(FinalizedAccessError::Obsolete)?;
+
+        let connection = self.database.lock();
+        if finalized_hash(&connection).map_err(FinalizedAccessError::Access)?
+            != *finalized_block_hash
+        {
+            return Err(ExportStateError::Database(FinalizedAccessError::Obsolete));
+        }
+
+        let mut writer =
+            snapshot::Writer::new(out, genesis_block_hash).map_err(ExportStateError::Io)?;
+
+        let scale_encoded_header = chain_information
+            .as_ref()
+            .finalized_block_header
+            .scale_encoding_vec(self.block_number_bytes);
+        writer
+            .write_bytes(&scale_encoded_header)
+            .map_err(ExportStateError::Io)?;
+        writer
+            .write_bytes(
+                finalized_serialize::encode_chain(&chain_information, self.block_number_bytes)
+                    .as_bytes(),
+            )
+            .map_err(ExportStateError::Io)?;
+
+        writer
+            .write_u32(u32::try_from(body.len()).unwrap())
+            .map_err(ExportStateError::Io)?;
+        for extrinsic in body {
+            writer
+                .write_bytes(&extrinsic)
+                .map_err(ExportStateError::Io)?;
+        }
+
+        let mut statement = connection
+            .prepare(r#"SELECT key, value FROM finalized_storage_top_trie ORDER BY key"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)
+            .map_err(FinalizedAccessError::Access)?;
+        while matches!(statement.next().unwrap(), sqlite::State::Row) {
+            let key = statement.read::<Vec<u8>>(0).unwrap();
+            let value = statement.read::<Vec<u8>>(1).unwrap();
+            writer
+                .write_storage_entry(&key, &value)
+                .map_err(ExportStateError::Io)?;
+        }
+
+        let mut statement = connection
+            .prepare(
+                r#"SELECT child_trie, key, value FROM finalized_storage_child_tries ORDER BY child_trie, key"#,
+            )
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)
+            .map_err(FinalizedAccessError::Access)?;
+        while matches!(statement.next().unwrap(), sqlite::State::Row) {
+            let child_trie = statement.read::<Vec<u8>>(0).unwrap();
+            let key = statement.read::<Vec<u8>>(1).unwrap();
+            let value = statement.read::<Vec<u8>>(2).unwrap();
+            writer
+                .write_child_trie_entry(&child_trie, &key, &value)
+                .map_err(ExportStateError::Io)?;
+        }
+
+        writer.finish().map_err(ExportStateError::Io)
+    }
+
     /// Returns the value associated to a key in the storage of the finalized block.
     ///
     /// In order to avoid race conditions, the known finalized block hash must be passed as
@@ -827,6 +1154,345 @@ impl SqliteFullDatabase {
 
         Ok(out)
     }
+
+    /// Returns the value associated to a key in the storage of the given block.
+    ///
+    /// The block must be either the finalized block, one of its non-finalized descendants, or one
+    /// of its ancestors whose storage hasn't been discarded according to the [`PruningMode`].
+    /// A [`StorageAccessError::StoragePruned`] error is returned for the ancestors of the
+    /// finalized block whose storage has been discarded.
+    ///
+    /// The storage of non-finalized blocks isn't directly stored in the database. Instead, this
+    /// function goes through the changes performed by each non-finalized ancestor of the block.
+    /// Similarly, the storage of the ancestors of the finalized block is rebuilt by reverting the
+    /// changes performed by their descendants.
+    pub fn block_storage_top_trie_get(
+        &self,
+        block_hash: &[u8; 32],
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, StorageAccessError> {
+        let connection = self.database.lock();
+
+        let ancestry = match block_storage(&connection, block_hash, self.block_number_bytes)? {
+            BlockStorage::NonFinalized(ancestry) => ancestry,
+            BlockStorage::Historical(number) => {
+                return Ok(historical_storage_get(&connection, number, key)?)
+            }
+        };
+
+        // Changes are looked for from the requested block towards the finalized block. The
+        // first change found is the one that applies.
+        let mut statement = connection
+            .prepare(r#"SELECT value FROM non_finalized_changes WHERE hash = ? AND key = ?"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?;
+        for hash in &ancestry {
+            statement = statement.bind(1, &hash[..]).unwrap().bind(2, key).unwrap();
+            if matches!(statement.next().unwrap(), sqlite::State::Row) {
+                let value = statement
+                    .read::<Option<Vec<u8>>>(0)
+                    .map_err(InternalError)
+                    .map_err(CorruptedError::Internal)
+                    .map_err(AccessError::Corrupted)?;
+                return Ok(value);
+            }
+            statement = statement.reset().unwrap();
+        }
+
+        Ok(finalized_storage_get(&connection, key)?)
+    }
+
+    /// Returns the key in the storage of the given block that immediately follows the key passed
+    /// as parameter.
+    ///
+    /// See [`SqliteFullDatabase::block_storage_top_trie_get`] for information about which blocks
+    /// can be accessed.
+    pub fn block_storage_top_trie_next_key(
+        &self,
+        block_hash: &[u8; 32],
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, StorageAccessError> {
+        let connection = self.database.lock();
+
+        let overlay = match block_storage(&connection, block_hash, self.block_number_bytes)? {
+            BlockStorage::NonFinalized(ancestry) => non_finalized_overlay(&connection, &ancestry)?,
+            BlockStorage::Historical(number) => {
+                return Ok(historical_storage_next(&connection, number, key, false)?)
+            }
+        };
+
+        // Find the first key of the finalized storage that hasn't been removed by one of the
+        // non-finalized blocks.
+        let mut statement = connection
+            .prepare(r#"SELECT key FROM finalized_storage_top_trie WHERE key > ? ORDER BY key ASC LIMIT 1"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?;
+        let mut cursor = key.to_vec();
+        let finalized_next = loop {
+            statement = statement.bind(1, &cursor[..]).unwrap();
+            if !matches!(statement.next().unwrap(), sqlite::State::Row) {
+                break None;
+            }
+            let next = statement
+                .read::<Vec<u8>>(0)
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)?;
+            statement = statement.reset().unwrap();
+            if let Some(false) = overlay.get(&next) {
+                cursor = next;
+                continue;
+            }
+            break Some(next);
+        };
+
+        let overlay_next = overlay
+            .range::<[u8], _>((ops::Bound::Excluded(key), ops::Bound::Unbounded))
+            .find(|(_, is_present)| **is_present)
+            .map(|(k, _)| k.clone());
+
+        Ok(match (finalized_next, overlay_next) {
+            (Some(a), Some(b)) => Some(if a < b { a } else { b }),
+            (Some(a), None) => Some(a),
+            (None, Some(b)) => Some(b),
+            (None, None) => None,
+        })
+    }
+
+    /// Returns the list of keys of the storage of the given block that start with the given
+    /// prefix. Pass `&[]` for the prefix to get the list of all keys. The keys are returned in
+    /// lexicographic order.
+    ///
+    /// See [`SqliteFullDatabase::block_storage_top_trie_get`] for information about which blocks
+    /// can be accessed.
+    pub fn block_storage_top_trie_keys(
+        &self,
+        block_hash: &[u8; 32],
+        prefix: &[u8],
+    ) -> Result<Vec<Vec<u8>>, StorageAccessError> {
+        let connection = self.database.lock();
+
+        let overlay = match block_storage(&connection, block_hash, self.block_number_bytes)? {
+            BlockStorage::NonFinalized(ancestry) => non_finalized_overlay(&connection, &ancestry)?,
+            BlockStorage::Historical(number) => {
+                let mut out = Vec::new();
+                let mut next = historical_storage_next(&connection, number, prefix, true)?;
+                while let Some(key) = next {
+                    if !key.starts_with(prefix) {
+                        break;
+                    }
+                    next = historical_storage_next(&connection, number, &key, false)?;
+                    out.push(key);
+                }
+                return Ok(out);
+            }
+        };
+
+        let mut statement = connection
+            .prepare(
+                r#"SELECT key FROM finalized_storage_top_trie WHERE key >= ? ORDER BY key ASC"#,
+            )
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?
+            .bind(1, prefix)
+            .unwrap();
+
+        let mut out = BTreeSet::new();
+        while matches!(statement.next().unwrap(), sqlite::State::Row) {
+            let key = statement
+                .read::<Vec<u8>>(0)
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)?;
+
+            // Keys are ordered, meaning that all the keys that start with `prefix` are
+            // contiguous.
+            if !key.starts_with(prefix) {
+                break;
+            }
+
+            out.insert(key);
+        }
+
+        for (key, is_present) in overlay {
+            if !key.starts_with(prefix) {
+                continue;
+            }
+
+            if is_present {
+                out.insert(key);
+            } else {
+                out.remove(&key);
+            }
+        }
+
+        Ok(out.into_iter().collect())
+    }
+
+    /// Calls `callback` with each key and value of the storage of the given block whose key is
+    /// superior or equal to `start_key`, in lexicographic order of the keys. The iteration stops
+    /// as soon as `callback` returns `false`.
+    ///
+    /// See [`SqliteFullDatabase::block_storage_top_trie_get`] for information about which blocks
+    /// can be accessed.
+    ///
+    /// The entries are read progressively from the database, and are never all loaded in memory
+    /// at the same time.
+    pub fn block_storage_top_trie_for_each(
+        &self,
+        block_hash: &[u8; 32],
+        start_key: &[u8],
+        mut callback: impl FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StorageAccessError> {
+        let connection = self.database.lock();
+
+        let overlay = match block_storage(&connection, block_hash, self.block_number_bytes)? {
+            BlockStorage::NonFinalized(ancestry) => {
+                non_finalized_overlay_values(&connection, &ancestry)?
+            }
+            BlockStorage::Historical(number) => {
+                let mut next = historical_storage_next(&connection, number, start_key, true)?;
+                while let Some(key) = next {
+                    // The key has been found in the storage, and thus has a value.
+                    let value = historical_storage_get(&connection, number, &key)?.unwrap();
+                    if !callback(&key, &value) {
+                        break;
+                    }
+                    next = historical_storage_next(&connection, number, &key, false)?;
+                }
+                return Ok(());
+            }
+        };
+        let mut overlay = overlay
+            .range::<[u8], _>((ops::Bound::Included(start_key), ops::Bound::Unbounded))
+            .peekable();
+
+        let mut statement = connection
+            .prepare(
+                r#"SELECT key, value FROM finalized_storage_top_trie WHERE key >= ? ORDER BY key ASC"#,
+            )
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?
+            .bind(1, start_key)
+            .unwrap();
+
+        // The entries of the finalized storage are merged with the ones of the overlay. When a
+        // key is found in both, the overlay has priority.
+        let mut finalized_entry: Option<(Vec<u8>, Vec<u8>)> = None;
+        let mut finalized_finished = false;
+        loop {
+            if finalized_entry.is_none() && !finalized_finished {
+                if matches!(statement.next().unwrap(), sqlite::State::Row) {
+                    let key = statement
+                        .read::<Vec<u8>>(0)
+                        .map_err(InternalError)
+                        .map_err(CorruptedError::Internal)
+                        .map_err(AccessError::Corrupted)?;
+                    let value = statement
+                        .read::<Vec<u8>>(1)
+                        .map_err(InternalError)
+                        .map_err(CorruptedError::Internal)
+                        .map_err(AccessError::Corrupted)?;
+                    finalized_entry = Some((key, value));
+                } else {
+                    finalized_finished = true;
+                }
+            }
+
+            let ordering = match (&finalized_entry, overlay.peek()) {
+                (None, None) => break,
+                (Some(_), None) => cmp::Ordering::Less,
+                (None, Some(_)) => cmp::Ordering::Greater,
+                (Some((finalized_key, _)), Some((overlay_key, _))) => {
+                    finalized_key[..].cmp(&overlay_key[..])
+                }
+            };
+
+            let go_on = if ordering == cmp::Ordering::Less {
+                let (key, value) = finalized_entry.take().unwrap();
+                callback(&key, &value)
+            } else {
+                if ordering == cmp::Ordering::Equal {
+                    finalized_entry = None;
+                }
+                match overlay.next().unwrap() {
+                    (key, Some(value)) => callback(key, value),
+                    (_, None) => true,
+                }
+            };
+
+            if !go_on {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the value associated to a key in the off-chain storage.
+    ///
+    /// The off-chain storage is a key-value storage, unrelated to the storage of the chain, that
+    /// off-chain workers can read and write.
+    pub fn offchain_storage_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, AccessError> {
+        let connection = self.database.lock();
+        offchain_storage_get(&connection, key)
+    }
+
+    /// Sets the value associated to a key in the off-chain storage. Passing `None` removes the
+    /// key from the storage.
+    ///
+    /// See [`SqliteFullDatabase::offchain_storage_get`].
+    pub fn offchain_storage_set(
+        &self,
+        key: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<(), AccessError> {
+        let connection = self.database.lock();
+        offchain_storage_set(&connection, key, value)
+    }
+
+    /// Sets the value associated to a key in the off-chain storage, but only if its current
+    /// value is equal to `old_value`, where `None` means that the key is absent.
+    ///
+    /// Returns `true` if the value has been replaced.
+    ///
+    /// See [`SqliteFullDatabase::offchain_storage_get`].
+    pub fn offchain_storage_compare_and_set(
+        &self,
+        key: &[u8],
+        old_value: Option<&[u8]>,
+        new_value: &[u8],
+    ) -> Result<bool, AccessError> {
+        let connection = self.database.lock();
+
+        // The database is locked during the entire operation, which guarantees its atomicity.
+        if offchain_storage_get(&connection, key)?.as_deref() != old_value {
+            return Ok(false);
+        }
+
+        offchain_storage_set(&connection, key, Some(new_value))?;
+        Ok(true)
+    }
+
+    /// Returns the state of the GrandPa voter previously stored with
+    /// [`SqliteFullDatabase::set_grandpa_voter_state`], or `None` if none has been stored.
+    pub fn grandpa_voter_state(&self) -> Result<Option<Vec<u8>>, AccessError> {
+        let connection = self.database.lock();
+        meta_get_blob(&connection, "grandpa_voter_state")
+    }
+
+    /// Stores the state of the GrandPa voter. This state must survive restarts of the node in
+    /// order for the voter to not cast conflicting votes.
+    ///
+    /// The value is opaque from the point of view of the database.
+    pub fn set_grandpa_voter_state(&self, state: &[u8]) -> Result<(), AccessError> {
+        let connection = self.database.lock();
+        meta_set_blob(&connection, "grandpa_voter_state", state)
+    }
 }
 
 impl fmt::Debug for SqliteFullDatabase {
@@ -859,6 +1525,20 @@ pub enum AccessError {
     /// be useful for debugging purposes.
     #[display(fmt = "Database corrupted: {}", _0)]
     Corrupted(CorruptedError),
+    /// Database has been created by a more recent version of the code and uses a schema that
+    /// isn't supported.
+    #[display(
+        fmt = "Database schema version {} is more recent than the latest supported version {}",
+        version,
+        latest_supported
+    )]
+    #[from(ignore)]
+    SchemaVersionTooRecent {
+        /// Version of the schema of the database.
+        version: u64,
+        /// Latest version of the schema supported by this code.
+        latest_supported: u64,
+    },
 }
 
 /// Error while calling [`SqliteFullDatabase::insert`].
@@ -898,6 +1578,74 @@ pub enum FinalizedAccessError {
     Obsolete,
 }
 
+/// Error while calling [`SqliteFullDatabase::export_finalized_state`].
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum ExportStateError {
+    /// Error accessing the database.
+    #[display(fmt = "{}", _0)]
+    Database(FinalizedAccessError),
+    /// Error while writing the snapshot.
+    #[display(fmt = "Failed to write snapshot: {}", _0)]
+    Io(io::Error),
+}
+
+/// Error while calling [`DatabaseEmpty::import_finalized_state`].
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum ImportStateError {
+    /// Error accessing the database.
+    #[display(fmt = "{}", _0)]
+    Access(AccessError),
+    /// Error while reading the snapshot.
+    #[display(fmt = "Failed to read snapshot: {}", _0)]
+    Io(io::Error),
+    /// Snapshot is truncated or isn't a snapshot.
+    InvalidFormat,
+    /// Snapshot has been produced with an unsupported version of the format.
+    #[display(fmt = "Unsupported snapshot format version: {}", _0)]
+    #[from(ignore)]
+    UnsupportedVersion(u8),
+    /// Checksum at the end of the snapshot doesn't match its content.
+    ChecksumMismatch,
+    /// The snapshot belongs to a chain with a different genesis block.
+    GenesisHashMismatch,
+    /// The finalized block header found in the snapshot has failed to decode.
+    #[display(fmt = "Invalid finalized block header: {}", _0)]
+    InvalidHeader(header::Error),
+    /// The information about the finalized chain found in the snapshot has failed to decode.
+    #[display(fmt = "Invalid chain information: {}", _0)]
+    InvalidChainInformation(finalized_serialize::CorruptedError),
+    /// The finalized block header doesn't match the one found in the chain information.
+    HeaderMismatch,
+    /// The storage found in the snapshot doesn't contain any runtime.
+    RuntimeNotFound,
+    /// Failed to decode the heap pages found in the storage of the snapshot.
+    #[display(fmt = "Failed to decode heap pages from the storage: {}", _0)]
+    HeapPagesDecode(executor::InvalidHeapPagesError),
+    /// Error when initializing the runtime found in the storage of the snapshot.
+    #[display(fmt = "Error when initializing the runtime: {}", _0)]
+    RuntimeInitialization(executor::host::NewErr),
+    /// State version in the specification of the runtime is not supported.
+    UnknownStateVersion,
+    /// The storage found in the snapshot doesn't match the state root of the finalized block
+    /// header.
+    StateRootMismatch,
+    /// The entries of a child trie found in the snapshot don't match the root of this child trie
+    /// found in the main trie.
+    ChildTrieRootMismatch,
+}
+
+/// Error while accessing the storage of a block.
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum StorageAccessError {
+    /// Error accessing the database.
+    Access(AccessError),
+    /// Requested block couldn't be found in the database.
+    UnknownBlock,
+    /// Requested block is an ancestor of the finalized block or isn't in the canonical chain.
+    /// Its storage is no longer available.
+    StoragePruned,
+}
+
 /// Error in the content of the database.
 // TODO: document and see if any entry is unused
 #[derive(Debug, derive_more::Display)]
@@ -1012,6 +1760,58 @@ fn meta_set_number(
     Ok(())
 }
 
+fn offchain_storage_get(
+    database: &sqlite::Connection,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>, AccessError> {
+    let mut statement = database
+        .prepare(r#"SELECT value FROM offchain_storage WHERE key = ?"#)
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?
+        .bind(1, key)
+        .unwrap();
+
+    if !matches!(statement.next().unwrap(), sqlite::State::Row) {
+        return Ok(None);
+    }
+
+    let value = statement
+        .read::<Vec<u8>>(0)
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?;
+    Ok(Some(value))
+}
+
+fn offchain_storage_set(
+    database: &sqlite::Connection,
+    key: &[u8],
+    value: Option<&[u8]>,
+) -> Result<(), AccessError> {
+    let mut statement = if let Some(value) = value {
+        database
+            .prepare(r#"INSERT OR REPLACE INTO offchain_storage(key, value) VALUES (?, ?)"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?
+            .bind(1, key)
+            .unwrap()
+            .bind(2, value)
+            .unwrap()
+    } else {
+        database
+            .prepare(r#"DELETE FROM offchain_storage WHERE key = ?"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?
+            .bind(1, key)
+            .unwrap()
+    };
+    statement.next().unwrap();
+    Ok(())
+}
+
 fn has_block(database: &sqlite::Connection, hash: &[u8]) -> Result<bool, AccessError> {
     let mut statement = database
         .prepare(r#"SELECT COUNT(*) FROM blocks WHERE hash = ?"#)
@@ -1134,6 +1934,7 @@ fn purge_block(database: &sqlite::Connection, hash: &[u8; 32]) -> Result<(), Acc
     let mut statement = database
         .prepare(
             "DELETE FROM non_finalized_changes WHERE hash = :hash;
+        DELETE FROM non_finalized_child_tries_changes WHERE hash = :hash;
         DELETE FROM blocks_body WHERE hash = :hash;
         DELETE FROM blocks WHERE hash = :hash;",
         )
@@ -1145,6 +1946,337 @@ fn purge_block(database: &sqlite::Connection, hash: &[u8; 32]) -> Result<(), Acc
     Ok(())
 }
 
+/// Where to find the storage of a block. See [`block_storage`].
+enum BlockStorage {
+    /// Block is the finalized block or one of its descendants. Contains the list of blocks
+    /// between the given block (inclusive) and the finalized block (exclusive), as returned by
+    /// [`non_finalized_ancestry`].
+    NonFinalized(Vec<[u8; 32]>),
+    /// Block is an ancestor of the finalized block whose storage hasn't been discarded. Contains
+    /// the height of the block.
+    Historical(u64),
+}
+
+/// Determines where to find the storage of the given block.
+///
+/// Returns an error if the block is unknown or if its storage has been discarded.
+fn block_storage(
+    database: &sqlite::Connection,
+    block_hash: &[u8; 32],
+    block_number_bytes: usize,
+) -> Result<BlockStorage, StorageAccessError> {
+    let header = block_header(database, block_hash, block_number_bytes)?
+        .ok_or(StorageAccessError::UnknownBlock)?;
+
+    if header.number >= finalized_num(database)? {
+        return Ok(BlockStorage::NonFinalized(non_finalized_ancestry(
+            database,
+            block_hash,
+            block_number_bytes,
+        )?));
+    }
+
+    if header.number < storage_history_start(database)? {
+        return Err(StorageAccessError::StoragePruned);
+    }
+
+    // Blocks that aren't descendants of the finalized block are removed from the database
+    // when a block is finalized. Since the block is in the database and its height is inferior
+    // to the finalized block's, it is necessarily an ancestor of the finalized block.
+    Ok(BlockStorage::Historical(header.number))
+}
+
+/// Similar to [`non_finalized_overlay`], but the value of each key of the returned map is the
+/// value of this key in the storage of the first block of the list, or `None` if it has been
+/// removed.
+fn non_finalized_overlay_values(
+    database: &sqlite::Connection,
+    ancestry: &[[u8; 32]],
+) -> Result<BTreeMap<Vec<u8>, Option<Vec<u8>>>, AccessError> {
+    let mut statement = database
+        .prepare(r#"SELECT key, value FROM non_finalized_changes WHERE hash = ?"#)
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?;
+
+    // Changes are applied from the oldest block to the newest, so that the changes of the
+    // newest blocks overwrite the changes of their ancestors.
+    let mut out = BTreeMap::new();
+    for hash in ancestry.iter().rev() {
+        statement = statement.bind(1, &hash[..]).unwrap();
+        while matches!(statement.next().unwrap(), sqlite::State::Row) {
+            let key = statement
+                .read::<Vec<u8>>(0)
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)?;
+            let value = statement
+                .read::<Option<Vec<u8>>>(1)
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)?;
+            out.insert(key, value);
+        }
+        statement = statement.reset().unwrap();
+    }
+
+    Ok(out)
+}
+
+/// Returns the list of blocks between the given block (inclusive) and the finalized block
+/// (exclusive), in decreasing order of height.
+///
+/// Returns an error if the block isn't the finalized block or a descendant of the finalized
+/// block.
+fn non_finalized_ancestry(
+    database: &sqlite::Connection,
+    block_hash: &[u8; 32],
+    block_number_bytes: usize,
+) -> Result<Vec<[u8; 32]>, StorageAccessError> {
+    let finalized_hash = finalized_hash(database)?;
+    let finalized_num = finalized_num(database)?;
+
+    let mut out = Vec::new();
+    let mut iter_hash = *block_hash;
+    loop {
+        if iter_hash == finalized_hash {
+            return Ok(out);
+        }
+
+        let header = match block_header(database, &iter_hash, block_number_bytes)? {
+            Some(h) => h,
+            None if out.is_empty() => return Err(StorageAccessError::UnknownBlock),
+            None => {
+                return Err(StorageAccessError::Access(AccessError::Corrupted(
+                    CorruptedError::BrokenChain,
+                )))
+            }
+        };
+
+        if header.number <= finalized_num {
+            return Err(StorageAccessError::StoragePruned);
+        }
+
+        out.push(iter_hash);
+        iter_hash = header.parent_hash;
+    }
+}
+
+/// Merges the storage changes performed by the given list of non-finalized blocks, as returned
+/// by [`non_finalized_ancestry`].
+///
+/// Each key of the returned map is a key modified by at least one of the blocks. The value is
+/// `true` if the key exists in the storage of the first block of the list, and `false` if it has
+/// been removed.
+fn non_finalized_overlay(
+    database: &sqlite::Connection,
+    ancestry: &[[u8; 32]],
+) -> Result<BTreeMap<Vec<u8>, bool>, AccessError> {
+    let mut statement = database
+        .prepare(r#"SELECT key, value IS NOT NULL FROM non_finalized_changes WHERE hash = ?"#)
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?;
+
+    // Changes are applied from the oldest block to the newest, so that the changes of the
+    // newest blocks overwrite the changes of their ancestors.
+    let mut out = BTreeMap::new();
+    for hash in ancestry.iter().rev() {
+        statement = statement.bind(1, &hash[..]).unwrap();
+        while matches!(statement.next().unwrap(), sqlite::State::Row) {
+            let key = statement
+                .read::<Vec<u8>>(0)
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)?;
+            let is_present = statement
+                .read::<i64>(1)
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)?
+                != 0;
+            out.insert(key, is_present);
+        }
+        statement = statement.reset().unwrap();
+    }
+
+    Ok(out)
+}
+
+/// Returns the value of the given key in the storage of the finalized block.
+fn finalized_storage_get(
+    database: &sqlite::Connection,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>, AccessError> {
+    let mut statement = database
+        .prepare(r#"SELECT value FROM finalized_storage_top_trie WHERE key = ?"#)
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?
+        .bind(1, key)
+        .unwrap();
+
+    if !matches!(statement.next().unwrap(), sqlite::State::Row) {
+        return Ok(None);
+    }
+
+    let value = statement
+        .read::<Vec<u8>>(0)
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?;
+    Ok(Some(value))
+}
+
+/// Returns the value of the given key in the storage of the ancestor of the finalized block
+/// with the given height.
+///
+/// The first descendant of the block that has modified the key contains the value of this key in
+/// the storage of the block. If no descendant has modified it, the value is the one in the
+/// storage of the finalized block.
+fn historical_storage_get(
+    database: &sqlite::Connection,
+    number: u64,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>, AccessError> {
+    let mut statement = database
+        .prepare(r#"SELECT value FROM finalized_storage_history WHERE key = ? AND number > ? ORDER BY number ASC LIMIT 1"#)
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?
+        .bind(1, key)
+        .unwrap()
+        .bind(2, i64::try_from(number).unwrap())
+        .unwrap();
+    if matches!(statement.next().unwrap(), sqlite::State::Row) {
+        return statement
+            .read::<Option<Vec<u8>>>(0)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted);
+    }
+
+    finalized_storage_get(database, key)
+}
+
+/// Returns the first key of the storage of the ancestor of the finalized block with the given
+/// height that is superior (or equal, if `or_equal` is `true`) to `key`.
+///
+/// The keys of the storage of the ancestor are the keys of the storage of the finalized block,
+/// plus the keys modified by the descendants of the ancestor, minus the keys that don't exist
+/// in the storage of the ancestor. Both lists are walked in parallel through their indices,
+/// meaning that only the keys between `key` and the returned key are visited.
+fn historical_storage_next(
+    database: &sqlite::Connection,
+    number: u64,
+    key: &[u8],
+    or_equal: bool,
+) -> Result<Option<Vec<u8>>, AccessError> {
+    let mut finalized_statement = database
+        .prepare(if or_equal {
+            r#"SELECT key FROM finalized_storage_top_trie WHERE key >= ? ORDER BY key ASC LIMIT 1"#
+        } else {
+            r#"SELECT key FROM finalized_storage_top_trie WHERE key > ? ORDER BY key ASC LIMIT 1"#
+        })
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?;
+    let mut history_statement = database
+        .prepare(if or_equal {
+            r#"SELECT key FROM finalized_storage_history WHERE key >= ? AND number > ? ORDER BY key ASC LIMIT 1"#
+        } else {
+            r#"SELECT key FROM finalized_storage_history WHERE key > ? AND number > ? ORDER BY key ASC LIMIT 1"#
+        })
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?;
+
+    let mut cursor = key.to_vec();
+    loop {
+        finalized_statement = finalized_statement.bind(1, &cursor[..]).unwrap();
+        let finalized_next = if matches!(finalized_statement.next().unwrap(), sqlite::State::Row) {
+            Some(
+                finalized_statement
+                    .read::<Vec<u8>>(0)
+                    .map_err(InternalError)
+                    .map_err(CorruptedError::Internal)
+                    .map_err(AccessError::Corrupted)?,
+            )
+        } else {
+            None
+        };
+        finalized_statement = finalized_statement.reset().unwrap();
+
+        history_statement = history_statement
+            .bind(1, &cursor[..])
+            .unwrap()
+            .bind(2, i64::try_from(number).unwrap())
+            .unwrap();
+        let history_next = if matches!(history_statement.next().unwrap(), sqlite::State::Row) {
+            Some(
+                history_statement
+                    .read::<Vec<u8>>(0)
+                    .map_err(InternalError)
+                    .map_err(CorruptedError::Internal)
+                    .map_err(AccessError::Corrupted)?,
+            )
+        } else {
+            None
+        };
+        history_statement = history_statement.reset().unwrap();
+
+        let candidate = match (finalized_next, history_next) {
+            (Some(a), Some(b)) => cmp::min(a, b),
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => return Ok(None),
+        };
+
+        // The candidate might have been removed from the storage of the ancestor, in which case
+        // the search continues after it.
+        if historical_storage_get(database, number, &candidate)?.is_some() {
+            return Ok(Some(candidate));
+        }
+
+        // The search must now exclude the candidate. If the statements include the cursor, the
+        // candidate is extended to the smallest key that is strictly superior to it.
+        cursor = candidate;
+        if or_equal {
+            cursor.push(0);
+        }
+    }
+}
+
+/// Returns the height of the oldest block whose storage is available.
+fn storage_history_start(database: &sqlite::Connection) -> Result<u64, AccessError> {
+    meta_get_number(database, "storage_history_start")?
+        .ok_or(AccessError::Corrupted(CorruptedError::MissingMetaKey))
+}
+
+fn pruning_mode(database: &sqlite::Connection) -> Result<PruningMode, AccessError> {
+    Ok(match meta_get_number(database, "pruning_keep_ancestors")? {
+        Some(keep_ancestors) => PruningMode::Prune { keep_ancestors },
+        None => PruningMode::Archive,
+    })
+}
+
+fn set_pruning_mode(database: &sqlite::Connection, mode: PruningMode) -> Result<(), AccessError> {
+    match mode {
+        PruningMode::Prune { keep_ancestors } => {
+            meta_set_number(database, "pruning_keep_ancestors", keep_ancestors)
+        }
+        PruningMode::Archive => {
+            let mut statement = database
+                .prepare(r#"DELETE FROM meta WHERE key = "pruning_keep_ancestors""#)
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)?;
+            statement.next().unwrap();
+            Ok(())
+        }
+    }
+}
+
 fn grandpa_authorities_set_id(database: &sqlite::Connection) -> Result<Option<u64>, AccessError> {
     meta_get_number(database, "grandpa_authorities_set_id")
 }
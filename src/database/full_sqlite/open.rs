@@ -19,15 +19,24 @@
 //!
 //! Contains everything related to the opening and initialization of the database.
 
-use super::{encode_babe_epoch_information, AccessError, SqliteFullDatabase};
-use crate::chain::chain_information;
+use super::{
+    encode_babe_epoch_information, snapshot, AccessError, CorruptedError, ImportStateError,
+    InternalError, SqliteFullDatabase,
+};
+use crate::{
+    chain::chain_information, database::finalized_serialize, executor, header,
+    trie::TrieEntryVersion,
+};
 
-use std::{fs, path::Path};
+use std::{fs, io, path::Path};
 
 /// Opens the database using the given [`Config`].
 ///
 /// Note that this doesn't return a [`SqliteFullDatabase`], but rather a [`DatabaseOpen`].
-pub fn open(config: Config) -> Result<DatabaseOpen, super::InternalError> {
+/// If the database already exists and has been created with an older version of the schema, it is
+/// automatically migrated to the latest version. An [`AccessError::SchemaVersionTooRecent`] error
+/// is returned if the database has been created with a more recent version of the schema.
+pub fn open(config: Config) -> Result<DatabaseOpen, AccessError> {
     let flags = sqlite::OpenFlags::new()
         .set_create()
         .set_read_write()
@@ -41,7 +50,8 @@ pub fn open(config: Config) -> Result<DatabaseOpen, super::InternalError> {
 
     let database = match config.ty {
         ConfigTy::Disk(path) => {
-            // We put a `/v1/` behind the path in case we change the schema.
+            // We put a `/v1/` behind the path in case we change the schema in a way that can't
+            // be migrated.
             let path = path.join("v1");
             // Ignoring errors in `create_dir_all`, in order to avoid making the API of this
             // function more complex. If `create_dir_all` fails, opening the database will most
@@ -51,7 +61,9 @@ pub fn open(config: Config) -> Result<DatabaseOpen, super::InternalError> {
         }
         ConfigTy::Memory => sqlite::Connection::open_with_flags(":memory:", flags),
     }
-    .map_err(super::InternalError)?;
+    .map_err(InternalError)
+    .map_err(CorruptedError::Internal)
+    .map_err(AccessError::Corrupted)?;
 
     database
         .execute(
@@ -63,6 +75,187 @@ PRAGMA locking_mode = EXCLUSIVE;
 PRAGMA auto_vacuum = FULL;
 PRAGMA encoding = 'UTF-8';
 PRAGMA trusted_schema = false; 
+    "#,
+        )
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?;
+
+    // A database that doesn't contain any table has just been created, in which case the latest
+    // version of the schema is created. Otherwise, the database might have been created with an
+    // older version of the schema and needs to be migrated.
+    let is_new = {
+        let mut statement = database
+            .prepare("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'")
+            .unwrap();
+        statement.next().unwrap();
+        statement.read::<i64>(0).unwrap() == 0
+    };
+
+    if is_new {
+        create_schema(&database)?;
+    } else {
+        migrate(&database, MIGRATIONS)?;
+    }
+
+    let is_empty = {
+        let mut statement = database
+            .prepare("SELECT COUNT(*) FROM meta WHERE key = ?")
+            .unwrap()
+            .bind(1, "best")
+            .unwrap();
+        statement.next().unwrap();
+        statement.read::<i64>(0).unwrap() == 0
+    };
+
+    // The database is *always* within a transaction.
+    database.execute("BEGIN TRANSACTION").unwrap();
+
+    Ok(if !is_empty {
+        // The pruning mode of an existing database is overwritten with the one passed in the
+        // configuration. Changing the pruning mode doesn't make the storage of blocks whose
+        // storage has already been discarded available again.
+        if let Some(pruning_mode) = config.pruning_mode {
+            super::set_pruning_mode(&database, pruning_mode).unwrap();
+        }
+
+        DatabaseOpen::Open(SqliteFullDatabase {
+            database: parking_lot::Mutex::new(database),
+            block_number_bytes: config.block_number_bytes, // TODO: consider storing this value in the DB and check it when opening
+        })
+    } else {
+        DatabaseOpen::Empty(DatabaseEmpty {
+            database,
+            block_number_bytes: config.block_number_bytes,
+            pruning_mode: config
+                .pruning_mode
+                .unwrap_or(PruningMode::Prune { keep_ancestors: 0 }),
+        })
+    })
+}
+
+/// Prefix of the keys of the main trie that contain the root of a default child trie.
+const CHILD_STORAGE_DEFAULT_PREFIX: &[u8] = b":child_storage:default:";
+
+/// Version of the schema created by [`create_schema`]. Written in the `meta` table under the
+/// `schema_version` key.
+const LATEST_SCHEMA_VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// List of migrations to apply to databases created with an older version of the schema.
+///
+/// The element at index `n` turns a database whose schema version is `n` into a database whose
+/// schema version is `n + 1`. Migrations must never be modified once they have been published,
+/// and any modification to the schema created by [`create_schema`] must be accompanied with a new
+/// migration.
+const MIGRATIONS: &[&str] = &[
+    // Version 0 to 1: introduction of `finalized_storage_history`, in order to support
+    // keeping the storage of the ancestors of the finalized block, and of `offchain_storage`,
+    // in order to support off-chain workers.
+    // Databases created before this version never kept the storage of the ancestors of the
+    // finalized block, which corresponds to pruning with `keep_ancestors` equal to 0. The
+    // absence of the `pruning_keep_ancestors` key would otherwise indicate an archive node.
+    r#"
+CREATE TABLE IF NOT EXISTS offchain_storage(
+    key BLOB NOT NULL PRIMARY KEY,
+    value BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS finalized_storage_history(
+    hash BLOB NOT NULL,
+    number INTEGER NOT NULL,
+    key BLOB NOT NULL,
+    value BLOB,
+    UNIQUE(hash, key),
+    CHECK(length(hash) == 32),
+    FOREIGN KEY (hash) REFERENCES blocks(hash) ON UPDATE CASCADE ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS finalized_storage_history_by_key ON finalized_storage_history(key, number);
+INSERT OR IGNORE INTO meta(key, value_number) SELECT "storage_history_start", value_number FROM meta WHERE key = "finalized";
+INSERT OR IGNORE INTO meta(key, value_number) VALUES ("pruning_keep_ancestors", 0);
+    "#,
+    // Version 1 to 2: introduction of `finalized_storage_child_tries` and
+    // `non_finalized_child_tries_changes`, in order to store the default child tries.
+    // Databases created before this version never stored any child trie. Their content is
+    // correct only if the chain doesn't use child tries.
+    r#"
+CREATE TABLE IF NOT EXISTS finalized_storage_child_tries(
+    child_trie BLOB NOT NULL,
+    key BLOB NOT NULL,
+    value BLOB NOT NULL,
+    PRIMARY KEY(child_trie, key)
+);
+CREATE TABLE IF NOT EXISTS non_finalized_child_tries_changes(
+    hash BLOB NOT NULL,
+    child_trie BLOB NOT NULL,
+    key BLOB NOT NULL,
+    value BLOB,
+    UNIQUE(hash, child_trie, key),
+    CHECK(length(hash) == 32),
+    FOREIGN KEY (hash) REFERENCES blocks(hash) ON UPDATE CASCADE ON DELETE CASCADE
+);
+    "#,
+];
+
+/// Upgrades the schema of the given database to the latest version by applying the necessary
+/// migrations. `migrations` is always [`MIGRATIONS`], except in tests.
+///
+/// Each migration is applied within its own transaction, meaning that the database is never
+/// left in a partially-migrated state.
+fn migrate(database: &sqlite::Connection, migrations: &[&str]) -> Result<(), AccessError> {
+    let latest_version = u64::try_from(migrations.len()).unwrap();
+
+    // Databases created before the introduction of the `schema_version` key don't contain it.
+    let version = super::meta_get_number(database, "schema_version")?.unwrap_or(0);
+
+    if version > latest_version {
+        return Err(AccessError::SchemaVersionTooRecent {
+            version,
+            latest_supported: latest_version,
+        });
+    }
+
+    for (migration_index, migration) in migrations
+        .iter()
+        .enumerate()
+        .skip(usize::try_from(version).unwrap())
+    {
+        // The new version number is written within the same transaction as the migration
+        // itself, so that both are either committed or reverted together.
+        let result = database
+            .execute("BEGIN TRANSACTION")
+            .and_then(|()| database.execute(migration))
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)
+            .and_then(|()| {
+                super::meta_set_number(
+                    database,
+                    "schema_version",
+                    u64::try_from(migration_index).unwrap() + 1,
+                )
+            })
+            .and_then(|()| {
+                database
+                    .execute("COMMIT")
+                    .map_err(InternalError)
+                    .map_err(CorruptedError::Internal)
+                    .map_err(AccessError::Corrupted)
+            });
+
+        if let Err(err) = result {
+            let _ = database.execute("ROLLBACK");
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the latest version of the schema in the given empty database.
+fn create_schema(database: &sqlite::Connection) -> Result<(), AccessError> {
+    let result = database
+        .execute(
+            r#"
+BEGIN TRANSACTION;
 
 /*
 Contains all the "global" values in the database.
@@ -70,6 +263,9 @@ A value must be present either in `value_blob` or `value_number` depending on th
 
 Keys in that table:
 
+ - `schema_version` (number): Version of the schema of the database. See `LATEST_SCHEMA_VERSION`
+ and `MIGRATIONS`.
+
  - `best` (blob): Hash of the best block.
 
  - `finalized` (number): Height of the finalized block, as a 64bits big endian number.
@@ -99,6 +295,14 @@ Keys in that table:
  finalized block is block #0, then this contains information about epoch #0. Missing if and
  only if the chain doesn't use Babe.
 
+ - `pruning_keep_ancestors` (number): Number of ancestors of the finalized block whose storage
+ is kept in `finalized_storage_history`. Missing if and only if the database is in archive mode,
+ in which case the storage of the ancestors of the finalized block is never discarded.
+
+ - `storage_history_start` (number): Height of the oldest ancestor of the finalized block whose
+ storage can be rebuilt from `finalized_storage_history`. Equal to the height of the finalized
+ block if the storage of no ancestor is available.
+
 */
 CREATE TABLE IF NOT EXISTS meta(
     key STRING NOT NULL PRIMARY KEY,
@@ -160,6 +364,53 @@ CREATE TABLE IF NOT EXISTS non_finalized_changes(
     FOREIGN KEY (hash) REFERENCES blocks(hash) ON UPDATE CASCADE ON DELETE CASCADE
 );
 
+/*
+Same as `finalized_storage_top_trie`, but for the default child tries. `child_trie` is the
+identifier of the child trie, without the `:child_storage:default:` prefix. The storage of the
+ancestors of the finalized block found in `finalized_storage_history` doesn't cover child tries.
+*/
+CREATE TABLE IF NOT EXISTS finalized_storage_child_tries(
+    child_trie BLOB NOT NULL,
+    key BLOB NOT NULL,
+    value BLOB NOT NULL,
+    PRIMARY KEY(child_trie, key)
+);
+
+/*
+Same as `non_finalized_changes`, but for the default child tries. When a block gets finalized,
+these changes get merged into `finalized_storage_child_tries`.
+*/
+CREATE TABLE IF NOT EXISTS non_finalized_child_tries_changes(
+    hash BLOB NOT NULL,
+    child_trie BLOB NOT NULL,
+    key BLOB NOT NULL,
+    -- `value` is NULL if the block removes the key from the child trie.
+    value BLOB,
+    UNIQUE(hash, child_trie, key),
+    CHECK(length(hash) == 32),
+    FOREIGN KEY (hash) REFERENCES blocks(hash) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+/*
+For ancestors of the finalized block whose height is strictly superior to `storage_history_start`
+(see `meta`), and for the finalized block itself, contains for each key modified by the block the
+value that this key had in the storage of the parent of the block.
+The storage of an ancestor of the finalized block can be rebuilt by applying on top of
+`finalized_storage_top_trie` the entries of the blocks between this ancestor (exclusive) and the
+finalized block (inclusive), from the newest block to the oldest.
+*/
+CREATE TABLE IF NOT EXISTS finalized_storage_history(
+    hash BLOB NOT NULL,
+    number INTEGER NOT NULL,
+    key BLOB NOT NULL,
+    -- `value` is NULL if the key didn't exist in the storage of the parent of the block.
+    value BLOB,
+    UNIQUE(hash, key),
+    CHECK(length(hash) == 32),
+    FOREIGN KEY (hash) REFERENCES blocks(hash) ON UPDATE CASCADE ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS finalized_storage_history_by_key ON finalized_storage_history(key, number);
+
 /*
 List of public keys and weights of the GrandPa authorities that must finalize the children of the
 finalized block. Empty if the chain doesn't use Grandpa.
@@ -191,34 +442,37 @@ CREATE TABLE IF NOT EXISTS aura_finalized_authorities(
     CHECK(length(public_key) == 32)
 );
 
+/*
+Persistent storage of the off-chain workers. Unrelated to the storage of the chain, and not tied
+to any specific block.
+*/
+CREATE TABLE IF NOT EXISTS offchain_storage(
+    key BLOB NOT NULL PRIMARY KEY,
+    value BLOB NOT NULL
+);
+
     "#,
         )
-        .map_err(super::InternalError)?;
-
-    let is_empty = {
-        let mut statement = database
-            .prepare("SELECT COUNT(*) FROM meta WHERE key = ?")
-            .unwrap()
-            .bind(1, "best")
-            .unwrap();
-        statement.next().unwrap();
-        statement.read::<i64>(0).unwrap() == 0
-    };
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)
+        .and_then(|()| {
+            super::meta_set_number(database, "schema_version", LATEST_SCHEMA_VERSION)
+        })
+        .and_then(|()| {
+            database
+                .execute("COMMIT")
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)
+        });
 
-    // The database is *always* within a transaction.
-    database.execute("BEGIN TRANSACTION").unwrap();
+    if let Err(err) = result {
+        let _ = database.execute("ROLLBACK");
+        return Err(err);
+    }
 
-    Ok(if !is_empty {
-        DatabaseOpen::Open(SqliteFullDatabase {
-            database: parking_lot::Mutex::new(database),
-            block_number_bytes: config.block_number_bytes, // TODO: consider storing this value in the DB and check it when opening
-        })
-    } else {
-        DatabaseOpen::Empty(DatabaseEmpty {
-            database,
-            block_number_bytes: config.block_number_bytes,
-        })
-    })
+    Ok(())
 }
 
 /// Configuration for the database.
@@ -229,6 +483,34 @@ pub struct Config<'a> {
 
     /// Number of bytes used to encode the block number.
     pub block_number_bytes: usize,
+
+    /// Which storage to keep when a block is finalized.
+    ///
+    /// If the database already exists, its pruning mode is replaced with this one. If `None`,
+    /// the pruning mode of an existing database is left untouched, and a new database uses
+    /// `PruningMode::Prune { keep_ancestors: 0 }`.
+    pub pruning_mode: Option<PruningMode>,
+}
+
+/// See [`Config::pruning_mode`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PruningMode {
+    /// Keep the storage of the finalized block and of the given number of its most recent
+    /// ancestors. The storage of older blocks is discarded.
+    ///
+    /// Passing `0` means that only the storage of the finalized block and of its descendants
+    /// is kept.
+    Prune {
+        /// Number of ancestors of the finalized block whose storage is kept.
+        keep_ancestors: u64,
+    },
+
+    /// Never discard the storage of the ancestors of the finalized block.
+    ///
+    /// The storage of the blocks that were finalized before the database was switched to
+    /// archive mode (including the blocks that were finalized before the database was created)
+    /// isn't available.
+    Archive,
 }
 
 /// Type of database.
@@ -260,9 +542,149 @@ pub struct DatabaseEmpty {
 
     /// See the similar field in [`SqliteFullDatabase`].
     block_number_bytes: usize,
+
+    /// See [`Config::pruning_mode`].
+    pruning_mode: PruningMode,
 }
 
 impl DatabaseEmpty {
+    /// Reads a snapshot produced by [`SqliteFullDatabase::export_finalized_state`] and uses its
+    /// content in order to turn the database prototype into an actual database.
+    ///
+    /// `genesis_block_hash` is the hash of the genesis block of the chain the database belongs
+    /// to. An error is returned if the snapshot belongs to a different chain.
+    ///
+    /// The checksum of the snapshot is verified, and so is the fact that its storage matches the
+    /// state root of its finalized block header and that its child tries match their root found
+    /// in the main trie. The storage is verified using the state version of the runtime found in
+    /// the snapshot. Nothing is written to the database if any of these verifications fails.
+    pub fn import_finalized_state(
+        self,
+        genesis_block_hash: &[u8; 32],
+        input: impl io::Read,
+    ) -> Result<SqliteFullDatabase, ImportStateError> {
+        let mut reader = snapshot::Reader::new(input)?;
+        if reader.genesis_block_hash() != genesis_block_hash {
+            return Err(ImportStateError::GenesisHashMismatch);
+        }
+
+        let scale_encoded_header = reader.read_bytes()?;
+        let finalized_block_header = header::decode(&scale_encoded_header, self.block_number_bytes)
+            .map_err(ImportStateError::InvalidHeader)?;
+
+        let (chain_information, _) = {
+            let encoded = reader.read_bytes()?;
+            let encoded =
+                core::str::from_utf8(&encoded).map_err(|_| ImportStateError::InvalidFormat)?;
+            finalized_serialize::decode_chain(encoded, self.block_number_bytes)
+                .map_err(ImportStateError::InvalidChainInformation)?
+        };
+        if chain_information
+            .as_ref()
+            .finalized_block_header
+            .hash(self.block_number_bytes)
+            != header::hash_from_scale_encoded_header(&scale_encoded_header)
+        {
+            return Err(ImportStateError::HeaderMismatch);
+        }
+
+        let body = {
+            let num_extrinsics = reader.read_u32()?;
+            let mut body = Vec::new();
+            for _ in 0..num_extrinsics {
+                body.push(reader.read_bytes()?);
+            }
+            body
+        };
+
+        let storage = reader.read_storage_entries()?;
+
+        reader.finish()?;
+
+        let state_version = {
+            let code = storage
+                .main_trie
+                .get(&b":code"[..])
+                .ok_or(ImportStateError::RuntimeNotFound)?;
+            let heap_pages = executor::storage_heap_pages_to_value(
+                storage.main_trie.get(&b":heappages"[..]).map(|v| &v[..]),
+            )
+            .map_err(ImportStateError::HeapPagesDecode)?;
+            let vm_prototype = executor::host::HostVmPrototype::new(executor::host::Config {
+                module: code,
+                heap_pages,
+                exec_hint: executor::vm::ExecHint::Oneshot,
+                allow_unresolved_imports: true,
+            })
+            .map_err(ImportStateError::RuntimeInitialization)?;
+
+            match vm_prototype.runtime_version().decode().state_version {
+                Some(0) | None => TrieEntryVersion::V0,
+                Some(1) => TrieEntryVersion::V1,
+                Some(_) => return Err(ImportStateError::UnknownStateVersion),
+            }
+        };
+
+        if snapshot::trie_root_hash(&storage.main_trie, state_version)
+            != *finalized_block_header.state_root
+        {
+            return Err(ImportStateError::StateRootMismatch);
+        }
+
+        // Each child trie must match the root found in the main trie, and each child trie
+        // root found in the main trie must correspond to a child trie.
+        for (child_trie, entries) in &storage.child_tries {
+            let root_key = [CHILD_STORAGE_DEFAULT_PREFIX, &child_trie[..]].concat();
+            if storage.main_trie.get(&root_key).map(|v| &v[..])
+                != Some(&snapshot::trie_root_hash(entries, state_version)[..])
+            {
+                return Err(ImportStateError::ChildTrieRootMismatch);
+            }
+        }
+        if storage
+            .main_trie
+            .range(CHILD_STORAGE_DEFAULT_PREFIX.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(CHILD_STORAGE_DEFAULT_PREFIX))
+            .any(|(key, _)| {
+                !storage
+                    .child_tries
+                    .contains_key(&key[CHILD_STORAGE_DEFAULT_PREFIX.len()..])
+            })
+        {
+            return Err(ImportStateError::ChildTrieRootMismatch);
+        }
+
+        {
+            let mut statement = self
+                .database
+                .prepare(
+                    "INSERT INTO finalized_storage_child_tries(child_trie, key, value) VALUES(?, ?, ?)",
+                )
+                .unwrap();
+            for (child_trie, entries) in &storage.child_tries {
+                for (key, value) in entries {
+                    statement = statement
+                        .bind(1, &child_trie[..])
+                        .unwrap()
+                        .bind(2, &key[..])
+                        .unwrap()
+                        .bind(3, &value[..])
+                        .unwrap();
+                    statement.next().unwrap();
+                    statement = statement.reset().unwrap();
+                }
+            }
+        }
+
+        // The justification of the finalized block isn't part of the snapshot.
+        Ok(self.initialize(
+            chain_information.as_ref(),
+            body.iter().map(|e| &e[..]),
+            None,
+            storage.main_trie.iter().map(|(k, v)| (&k[..], &v[..])),
+        )?)
+    }
+
     /// Inserts the given [`chain_information::ChainInformationRef`] in the database prototype in
     /// order to turn it into an actual database.
     ///
@@ -274,103 +696,150 @@ impl DatabaseEmpty {
         finalized_block_justification: Option<Vec<u8>>,
         finalized_block_storage_top_trie_entries: impl Iterator<Item = (&'a [u8], &'a [u8])> + Clone,
     ) -> Result<SqliteFullDatabase, AccessError> {
-        let chain_information = chain_information.into();
+        insert_finalized_state(
+            &self.database,
+            self.block_number_bytes,
+            chain_information.into(),
+            finalized_block_body,
+            finalized_block_justification,
+            finalized_block_storage_top_trie_entries,
+        );
+        super::set_pruning_mode(&self.database, self.pruning_mode).unwrap();
 
-        let finalized_block_hash = chain_information
-            .finalized_block_header
-            .hash(self.block_number_bytes);
+        super::flush(&self.database)?;
 
-        let scale_encoded_finalized_block_header = chain_information
-            .finalized_block_header
-            .scale_encoding(self.block_number_bytes)
-            .fold(Vec::new(), |mut a, b| {
-                a.extend_from_slice(b.as_ref());
-                a
-            });
+        Ok(SqliteFullDatabase {
+            database: parking_lot::Mutex::new(self.database),
+            block_number_bytes: self.block_number_bytes,
+        })
+    }
+}
 
-        {
-            let mut statement = self
-                .database
-                .prepare("INSERT INTO finalized_storage_top_trie(key, value) VALUES(?, ?)")
+/// Inserts in the database the finalized block and its storage, and the information about the
+/// finalized chain.
+///
+/// The tables concerned are assumed to be empty.
+pub(super) fn insert_finalized_state<'a>(
+    database: &sqlite::Connection,
+    block_number_bytes: usize,
+    chain_information: chain_information::ChainInformationRef<'a>,
+    finalized_block_body: impl ExactSizeIterator<Item = &'a [u8]>,
+    finalized_block_justification: Option<Vec<u8>>,
+    finalized_block_storage_top_trie_entries: impl Iterator<Item = (&'a [u8], &'a [u8])>,
+) {
+    let finalized_block_hash = chain_information
+        .finalized_block_header
+        .hash(block_number_bytes);
+
+    let scale_encoded_finalized_block_header = chain_information
+        .finalized_block_header
+        .scale_encoding(block_number_bytes)
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+    {
+        let mut statement = database
+            .prepare("INSERT INTO finalized_storage_top_trie(key, value) VALUES(?, ?)")
+            .unwrap();
+        for (key, value) in finalized_block_storage_top_trie_entries {
+            statement = statement.bind(1, key).unwrap().bind(2, value).unwrap();
+            statement.next().unwrap();
+            statement = statement.reset().unwrap();
+        }
+    }
+
+    {
+        let mut statement = database
+            .prepare("INSERT INTO blocks(hash, number, header, justification) VALUES(?, ?, ?, ?)")
+            .unwrap()
+            .bind(1, &finalized_block_hash[..])
+            .unwrap()
+            .bind(
+                2,
+                i64::try_from(chain_information.finalized_block_header.number).unwrap(),
+            )
+            .unwrap()
+            .bind(3, &scale_encoded_finalized_block_header[..])
+            .unwrap();
+        if let Some(finalized_block_justification) = &finalized_block_justification {
+            statement = statement
+                .bind(4, &finalized_block_justification[..])
                 .unwrap();
-            for (key, value) in finalized_block_storage_top_trie_entries {
-                statement = statement.bind(1, key).unwrap().bind(2, value).unwrap();
-                statement.next().unwrap();
-                statement = statement.reset().unwrap();
-            }
+        } else {
+            statement = statement.bind(4, ()).unwrap();
         }
+        statement.next().unwrap();
+    }
 
-        {
-            let mut statement = self
-                .database
-                .prepare(
-                    "INSERT INTO blocks(hash, number, header, justification) VALUES(?, ?, ?, ?)",
-                )
-                .unwrap()
+    {
+        let mut statement = database
+            .prepare("INSERT INTO blocks_body(hash, idx, extrinsic) VALUES(?, ?, ?)")
+            .unwrap();
+        for (index, item) in finalized_block_body.enumerate() {
+            statement = statement
                 .bind(1, &finalized_block_hash[..])
                 .unwrap()
-                .bind(
-                    2,
-                    i64::try_from(chain_information.finalized_block_header.number).unwrap(),
-                )
+                .bind(2, i64::try_from(index).unwrap())
                 .unwrap()
-                .bind(3, &scale_encoded_finalized_block_header[..])
+                .bind(3, item)
                 .unwrap();
-            if let Some(finalized_block_justification) = &finalized_block_justification {
-                statement = statement
-                    .bind(4, &finalized_block_justification[..])
-                    .unwrap();
-            } else {
-                statement = statement.bind(4, ()).unwrap();
-            }
             statement.next().unwrap();
+            statement = statement.reset().unwrap();
         }
+    }
 
-        {
-            let mut statement = self
-                .database
-                .prepare("INSERT INTO blocks_body(hash, idx, extrinsic) VALUES(?, ?, ?)")
+    super::meta_set_blob(database, "best", &finalized_block_hash[..]).unwrap();
+    super::meta_set_number(
+        database,
+        "finalized",
+        chain_information.finalized_block_header.number,
+    )
+    .unwrap();
+    super::meta_set_number(
+        database,
+        "storage_history_start",
+        chain_information.finalized_block_header.number,
+    )
+    .unwrap();
+
+    match &chain_information.finality {
+        chain_information::ChainInformationFinalityRef::Outsourced => {}
+        chain_information::ChainInformationFinalityRef::Grandpa {
+            finalized_triggered_authorities,
+            after_finalized_block_authorities_set_id,
+            finalized_scheduled_change,
+        } => {
+            super::meta_set_number(
+                database,
+                "grandpa_authorities_set_id",
+                *after_finalized_block_authorities_set_id,
+            )
+            .unwrap();
+
+            let mut statement = database
+                .prepare("INSERT INTO grandpa_triggered_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
                 .unwrap();
-            for (index, item) in finalized_block_body.enumerate() {
+            for (index, item) in finalized_triggered_authorities.iter().enumerate() {
                 statement = statement
-                    .bind(1, &finalized_block_hash[..])
+                    .bind(1, i64::try_from(index).unwrap())
                     .unwrap()
-                    .bind(2, i64::try_from(index).unwrap())
+                    .bind(2, &item.public_key[..])
                     .unwrap()
-                    .bind(3, item)
+                    .bind(3, i64::from_ne_bytes(item.weight.get().to_ne_bytes()))
                     .unwrap();
                 statement.next().unwrap();
                 statement = statement.reset().unwrap();
             }
-        }
 
-        super::meta_set_blob(&self.database, "best", &finalized_block_hash[..]).unwrap();
-        super::meta_set_number(
-            &self.database,
-            "finalized",
-            chain_information.finalized_block_header.number,
-        )
-        .unwrap();
-
-        match &chain_information.finality {
-            chain_information::ChainInformationFinalityRef::Outsourced => {}
-            chain_information::ChainInformationFinalityRef::Grandpa {
-                finalized_triggered_authorities,
-                after_finalized_block_authorities_set_id,
-                finalized_scheduled_change,
-            } => {
-                super::meta_set_number(
-                    &self.database,
-                    "grandpa_authorities_set_id",
-                    *after_finalized_block_authorities_set_id,
-                )
-                .unwrap();
+            if let Some((height, list)) = finalized_scheduled_change {
+                super::meta_set_number(database, "grandpa_scheduled_target", *height).unwrap();
 
-                let mut statement = self
-                    .database
-                    .prepare("INSERT INTO grandpa_triggered_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
+                let mut statement = database
+                    .prepare("INSERT INTO grandpa_scheduled_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
                     .unwrap();
-                for (index, item) in finalized_triggered_authorities.iter().enumerate() {
+                for (index, item) in list.iter().enumerate() {
                     statement = statement
                         .bind(1, i64::try_from(index).unwrap())
                         .unwrap()
@@ -381,84 +850,218 @@ impl DatabaseEmpty {
                     statement.next().unwrap();
                     statement = statement.reset().unwrap();
                 }
-
-                if let Some((height, list)) = finalized_scheduled_change {
-                    super::meta_set_number(&self.database, "grandpa_scheduled_target", *height)
-                        .unwrap();
-
-                    let mut statement = self
-                        .database
-                        .prepare("INSERT INTO grandpa_scheduled_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
-                        .unwrap();
-                    for (index, item) in list.iter().enumerate() {
-                        statement = statement
-                            .bind(1, i64::try_from(index).unwrap())
-                            .unwrap()
-                            .bind(2, &item.public_key[..])
-                            .unwrap()
-                            .bind(3, i64::from_ne_bytes(item.weight.get().to_ne_bytes()))
-                            .unwrap();
-                        statement.next().unwrap();
-                        statement = statement.reset().unwrap();
-                    }
-                }
             }
         }
+    }
 
-        match &chain_information.consensus {
-            chain_information::ChainInformationConsensusRef::Unknown => {}
-            chain_information::ChainInformationConsensusRef::Aura {
-                finalized_authorities_list,
-                slot_duration,
-            } => {
-                super::meta_set_number(&self.database, "aura_slot_duration", slot_duration.get())
-                    .unwrap();
+    match &chain_information.consensus {
+        chain_information::ChainInformationConsensusRef::Unknown => {}
+        chain_information::ChainInformationConsensusRef::Aura {
+            finalized_authorities_list,
+            slot_duration,
+        } => {
+            super::meta_set_number(database, "aura_slot_duration", slot_duration.get()).unwrap();
 
-                let mut statement = self
-                    .database
-                    .prepare("INSERT INTO aura_finalized_authorities(idx, public_key) VALUES(?, ?)")
+            let mut statement = database
+                .prepare("INSERT INTO aura_finalized_authorities(idx, public_key) VALUES(?, ?)")
+                .unwrap();
+            for (index, item) in finalized_authorities_list.clone().enumerate() {
+                statement = statement
+                    .bind(1, i64::try_from(index).unwrap())
+                    .unwrap()
+                    .bind(2, &item.public_key[..])
                     .unwrap();
-                for (index, item) in finalized_authorities_list.clone().enumerate() {
-                    statement = statement
-                        .bind(1, i64::try_from(index).unwrap())
-                        .unwrap()
-                        .bind(2, &item.public_key[..])
-                        .unwrap();
-                    statement.next().unwrap();
-                    statement = statement.reset().unwrap();
-                }
+                statement.next().unwrap();
+                statement = statement.reset().unwrap();
             }
-            chain_information::ChainInformationConsensusRef::Babe {
-                slots_per_epoch,
-                finalized_next_epoch_transition,
-                finalized_block_epoch_information,
-            } => {
-                super::meta_set_number(
-                    &self.database,
-                    "babe_slots_per_epoch",
-                    slots_per_epoch.get(),
-                )
+        }
+        chain_information::ChainInformationConsensusRef::Babe {
+            slots_per_epoch,
+            finalized_next_epoch_transition,
+            finalized_block_epoch_information,
+        } => {
+            super::meta_set_number(database, "babe_slots_per_epoch", slots_per_epoch.get())
                 .unwrap();
+            super::meta_set_blob(
+                database,
+                "babe_finalized_next_epoch",
+                &encode_babe_epoch_information(finalized_next_epoch_transition.clone())[..],
+            )
+            .unwrap();
+
+            if let Some(finalized_block_epoch_information) = finalized_block_epoch_information {
                 super::meta_set_blob(
-                    &self.database,
-                    "babe_finalized_next_epoch",
-                    &encode_babe_epoch_information(finalized_next_epoch_transition.clone())[..],
+                    database,
+                    "babe_finalized_epoch",
+                    &encode_babe_epoch_information(finalized_block_epoch_information.clone())[..],
                 )
                 .unwrap();
-
-                if let Some(finalized_block_epoch_information) = finalized_block_epoch_information {
-                    super::meta_set_blob(&self.database, "babe_finalized_epoch", &encode_babe_epoch_information(
-            finalized_block_epoch_information.clone(),
-        )[..]).unwrap();
-                }
             }
         }
+    }
+}
 
-        super::flush(&self.database)?;
+#[cfg(test)]
+mod tests {
+    use super::{create_schema, migrate, AccessError, LATEST_SCHEMA_VERSION, MIGRATIONS};
 
-        Ok(SqliteFullDatabase {
-            database: parking_lot::Mutex::new(self.database),
-            block_number_bytes: self.block_number_bytes,
-        })
+    /// Schema of the databases created before the introduction of the `schema_version` key.
+    const SCHEMA_V0: &str = r#"
+BEGIN TRANSACTION;
+CREATE TABLE meta(
+    key STRING NOT NULL PRIMARY KEY,
+    value_blob BLOB,
+    value_number INTEGER,
+    CHECK((value_blob IS NULL OR value_number IS NULL) AND (value_blob IS NOT NULL OR value_number IS NOT NULL))
+);
+CREATE TABLE blocks(
+    hash BLOB NOT NULL PRIMARY KEY,
+    number INTEGER NOT NULL,
+    header BLOB NOT NULL,
+    justification BLOB,
+    UNIQUE(number, hash),
+    CHECK(length(hash) == 32)
+);
+CREATE INDEX blocks_by_number ON blocks(number);
+CREATE TABLE blocks_body(
+    hash BLOB NOT NULL,
+    idx INTEGER NOT NULL,
+    extrinsic BLOB NOT NULL,
+    UNIQUE(hash, idx),
+    CHECK(length(hash) == 32),
+    FOREIGN KEY (hash) REFERENCES blocks(hash) ON UPDATE CASCADE ON DELETE CASCADE
+);
+CREATE TABLE finalized_storage_top_trie(
+    key BLOB NOT NULL PRIMARY KEY,
+    value BLOB NOT NULL
+);
+CREATE TABLE non_finalized_changes(
+    hash BLOB NOT NULL,
+    key BLOB NOT NULL,
+    value BLOB,
+    UNIQUE(hash, key),
+    CHECK(length(hash) == 32),
+    FOREIGN KEY (hash) REFERENCES blocks(hash) ON UPDATE CASCADE ON DELETE CASCADE
+);
+CREATE TABLE grandpa_triggered_authorities(
+    idx INTEGER NOT NULL PRIMARY KEY,
+    public_key BLOB NOT NULL,
+    weight INTEGER NOT NULL,
+    CHECK(length(public_key) == 32)
+);
+CREATE TABLE grandpa_scheduled_authorities(
+    idx INTEGER NOT NULL PRIMARY KEY,
+    public_key BLOB NOT NULL,
+    weight INTEGER NOT NULL,
+    CHECK(length(public_key) == 32)
+);
+CREATE TABLE aura_finalized_authorities(
+    idx INTEGER NOT NULL PRIMARY KEY,
+    public_key BLOB NOT NULL,
+    CHECK(length(public_key) == 32)
+);
+INSERT INTO meta(key, value_number) VALUES ("finalized", 5);
+COMMIT;
+    "#;
+
+    fn memory_database() -> sqlite::Connection {
+        sqlite::Connection::open_with_flags(
+            ":memory:",
+            sqlite::OpenFlags::new().set_create().set_read_write(),
+        )
+        .unwrap()
+    }
+
+    /// Returns the list of tables and indices of the database, and the columns of each table.
+    fn schema_of(database: &sqlite::Connection) -> Vec<(String, String)> {
+        let mut statement = database
+            .prepare(
+                r#"
+SELECT m.name, COALESCE(p.name, '')
+FROM sqlite_master m LEFT JOIN pragma_table_info(m.name) p
+WHERE m.name NOT LIKE 'sqlite_%'
+ORDER BY m.name, p.cid"#,
+            )
+            .unwrap();
+
+        let mut out = Vec::new();
+        while matches!(statement.next().unwrap(), sqlite::State::Row) {
+            out.push((
+                statement.read::<String>(0).unwrap(),
+                statement.read::<String>(1).unwrap(),
+            ));
+        }
+        out
+    }
+
+    fn schema_version(database: &sqlite::Connection) -> Option<u64> {
+        super::super::meta_get_number(database, "schema_version").unwrap()
+    }
+
+    #[test]
+    fn migrate_from_v0() {
+        let database = memory_database();
+        database.execute(SCHEMA_V0).unwrap();
+        migrate(&database, MIGRATIONS).unwrap();
+
+        let fresh_database = memory_database();
+        create_schema(&fresh_database).unwrap();
+
+        assert_eq!(schema_of(&database), schema_of(&fresh_database));
+        assert_eq!(schema_version(&database), Some(LATEST_SCHEMA_VERSION));
+        assert_eq!(
+            super::super::meta_get_number(&database, "storage_history_start").unwrap(),
+            Some(5)
+        );
+        assert_eq!(
+            super::super::pruning_mode(&database).unwrap(),
+            super::super::PruningMode::Prune { keep_ancestors: 0 }
+        );
+    }
+
+    #[test]
+    fn migrate_latest_version_no_op() {
+        let database = memory_database();
+        create_schema(&database).unwrap();
+        let schema_before = schema_of(&database);
+
+        migrate(&database, MIGRATIONS).unwrap();
+        assert_eq!(schema_of(&database), schema_before);
+        assert_eq!(schema_version(&database), Some(LATEST_SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn migrate_version_too_recent() {
+        let database = memory_database();
+        create_schema(&database).unwrap();
+        super::super::meta_set_number(&database, "schema_version", LATEST_SCHEMA_VERSION + 1)
+            .unwrap();
+
+        assert!(matches!(
+            migrate(&database, MIGRATIONS),
+            Err(AccessError::SchemaVersionTooRecent { version, latest_supported })
+                if version == LATEST_SCHEMA_VERSION + 1 && latest_supported == LATEST_SCHEMA_VERSION
+        ));
+    }
+
+    #[test]
+    fn failed_migration_rolled_back() {
+        let database = memory_database();
+        database.execute(SCHEMA_V0).unwrap();
+
+        let migrations = &[
+            "CREATE TABLE foo(a INTEGER);",
+            "CREATE TABLE bar(a INTEGER); THIS IS NOT SQL;",
+        ];
+        assert!(migrate(&database, migrations).is_err());
+
+        // The first migration has been applied, while the second one has been entirely reverted.
+        assert_eq!(schema_version(&database), Some(1));
+        let tables = schema_of(&database);
+        assert!(tables.iter().any(|(table, _)| table == "foo"));
+        assert!(!tables.iter().any(|(table, _)| table == "bar"));
+
+        // The database is usable afterwards.
+        database.execute("BEGIN TRANSACTION; COMMIT;").unwrap();
     }
 }
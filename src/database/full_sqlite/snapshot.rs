@@ -0,0 +1,431 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Format of the state snapshots.
+//!
+//! Snapshots are produced by [`super::SqliteFullDatabase::export_finalized_state`] and consumed
+//! by [`super::DatabaseEmpty::import_finalized_state`]. They are designed to be written and read
+//! in a streaming way, without having to know their total size in advance.
+//!
+//! A snapshot consists of, in order:
+//!
+//! - The 8 bytes `smdtsnap`, followed with one byte containing the version of the format.
+//!   Only version `1` currently exists.
+//! - The 32 bytes hash of the genesis block of the chain the snapshot belongs to.
+//! - The SCALE-encoded header of the finalized block, prefixed with its length.
+//! - The information about the finalized chain, as encoded by
+//!   [`crate::database::finalized_serialize::encode_chain`], prefixed with its length.
+//! - The number of extrinsics in the body of the finalized block, followed with each extrinsic
+//!   prefixed with its length.
+//! - For each entry of the main trie of the storage of the finalized block, the byte `1`
+//!   followed with the key and the value of the entry, each prefixed with its length. For each
+//!   entry of a default child trie of the finalized block, the byte `2` followed with the
+//!   identifier of the child trie (without the `:child_storage:default:` prefix), the key, and
+//!   the value of the entry, each prefixed with its length. The list is terminated with the
+//!   byte `0`.
+//! - The 32 bytes BLAKE2 hash of everything that precedes.
+//!
+//! All lengths and numbers are encoded as 32 bits little endian integers.
+
+use super::ImportStateError;
+use crate::trie;
+
+use alloc::collections::BTreeMap;
+use std::io;
+
+/// Bytes found at the start of every snapshot.
+const MAGIC: &[u8; 8] = b"smdtsnap";
+
+/// Version of the format written by [`Writer`].
+const FORMAT_VERSION: u8 = 1;
+
+/// Writes a snapshot to an [`io::Write`].
+pub(super) struct Writer<W> {
+    inner: W,
+    hasher: blake2_rfc::blake2b::Blake2b,
+}
+
+impl<W: io::Write> Writer<W> {
+    /// Writes the beginning of a snapshot to the given writer.
+    pub(super) fn new(inner: W, genesis_block_hash: &[u8; 32]) -> io::Result<Self> {
+        let mut writer = Writer {
+            inner,
+            hasher: blake2_rfc::blake2b::Blake2b::new(32),
+        };
+        writer.write_raw(MAGIC)?;
+        writer.write_raw(&[FORMAT_VERSION])?;
+        writer.write_raw(genesis_block_hash)?;
+        Ok(writer)
+    }
+
+    /// Writes a number.
+    pub(super) fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.write_raw(&value.to_le_bytes())
+    }
+
+    /// Writes a length-prefixed list of bytes.
+    pub(super) fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_u32(u32::try_from(bytes.len()).map_err(|_| io::ErrorKind::InvalidInput)?)?;
+        self.write_raw(bytes)
+    }
+
+    /// Writes an entry of the main trie of the storage of the finalized block.
+    pub(super) fn write_storage_entry(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.write_raw(&[1])?;
+        self.write_bytes(key)?;
+        self.write_bytes(value)
+    }
+
+    /// Writes an entry of a default child trie of the storage of the finalized block.
+    /// `child_trie` doesn't include the `:child_storage:default:` prefix.
+    pub(super) fn write_child_trie_entry(
+        &mut self,
+        child_trie: &[u8],
+        key: &[u8],
+        value: &[u8],
+    ) -> io::Result<()> {
+        self.write_raw(&[2])?;
+        self.write_bytes(child_trie)?;
+        self.write_bytes(key)?;
+        self.write_bytes(value)
+    }
+
+    /// Terminates the list of storage entries, writes the checksum, and flushes the writer.
+    pub(super) fn finish(mut self) -> io::Result<()> {
+        self.write_raw(&[0])?;
+        let checksum = self.hasher.finalize();
+        self.inner.write_all(checksum.as_bytes())?;
+        self.inner.flush()
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.hasher.update(bytes);
+        self.inner.write_all(bytes)
+    }
+}
+
+/// Reads a snapshot from an [`io::Read`].
+pub(super) struct Reader<R> {
+    inner: R,
+    hasher: blake2_rfc::blake2b::Blake2b,
+    genesis_block_hash: [u8; 32],
+}
+
+impl<R: io::Read> Reader<R> {
+    /// Reads the beginning of a snapshot from the given reader.
+    pub(super) fn new(inner: R) -> Result<Self, ImportStateError> {
+        let mut reader = Reader {
+            inner,
+            hasher: blake2_rfc::blake2b::Blake2b::new(32),
+            genesis_block_hash: [0; 32],
+        };
+
+        let mut magic = [0; 8];
+        reader.read_raw(&mut magic)?;
+        if magic != *MAGIC {
+            return Err(ImportStateError::InvalidFormat);
+        }
+
+        let mut version = [0];
+        reader.read_raw(&mut version)?;
+        if version[0] != FORMAT_VERSION {
+            return Err(ImportStateError::UnsupportedVersion(version[0]));
+        }
+
+        let mut genesis_block_hash = [0; 32];
+        reader.read_raw(&mut genesis_block_hash)?;
+        reader.genesis_block_hash = genesis_block_hash;
+
+        Ok(reader)
+    }
+
+    /// Returns the hash of the genesis block of the chain the snapshot belongs to.
+    pub(super) fn genesis_block_hash(&self) -> &[u8; 32] {
+        &self.genesis_block_hash
+    }
+
+    /// Reads a number.
+    pub(super) fn read_u32(&mut self) -> Result<u32, ImportStateError> {
+        let mut bytes = [0; 4];
+        self.read_raw(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Reads a length-prefixed list of bytes.
+    pub(super) fn read_bytes(&mut self) -> Result<Vec<u8>, ImportStateError> {
+        let len = self.read_u32()?;
+
+        // The buffer isn't pre-allocated with the announced length, in order to not allocate an
+        // unreasonable amount of memory in case of a corrupted snapshot.
+        let mut out = Vec::new();
+        let read = io::Read::read_to_end(
+            &mut io::Read::take(&mut self.inner, u64::from(len)),
+            &mut out,
+        )
+        .map_err(ImportStateError::Io)?;
+        if read != usize::try_from(len).unwrap() {
+            return Err(ImportStateError::InvalidFormat);
+        }
+
+        self.hasher.update(&out);
+        Ok(out)
+    }
+
+    /// Reads the list of entries of the storage of the finalized block.
+    pub(super) fn read_storage_entries(&mut self) -> Result<StorageEntries, ImportStateError> {
+        let mut entries = StorageEntries {
+            main_trie: BTreeMap::new(),
+            child_tries: BTreeMap::new(),
+        };
+
+        loop {
+            let mut tag = [0];
+            self.read_raw(&mut tag)?;
+            let (trie, key) = match tag[0] {
+                0 => break Ok(entries),
+                1 => (&mut entries.main_trie, self.read_bytes()?),
+                2 => {
+                    let child_trie = self.read_bytes()?;
+                    let key = self.read_bytes()?;
+                    (entries.child_tries.entry(child_trie).or_default(), key)
+                }
+                _ => return Err(ImportStateError::InvalidFormat),
+            };
+
+            let value = self.read_bytes()?;
+            if trie.insert(key, value).is_some() {
+                return Err(ImportStateError::InvalidFormat);
+            }
+        }
+    }
+
+    /// Reads the checksum at the end of the snapshot and compares it with the data that has
+    /// been read.
+    pub(super) fn finish(mut self) -> Result<(), ImportStateError> {
+        let mut checksum = [0; 32];
+        io::Read::read_exact(&mut self.inner, &mut checksum).map_err(map_read_error)?;
+        if self.hasher.finalize().as_bytes() != checksum {
+            return Err(ImportStateError::ChecksumMismatch);
+        }
+
+        // Make sure that there isn't any trailing data.
+        let mut trailing = [0];
+        if io::Read::read(&mut self.inner, &mut trailing).map_err(ImportStateError::Io)? != 0 {
+            return Err(ImportStateError::InvalidFormat);
+        }
+
+        Ok(())
+    }
+
+    fn read_raw(&mut self, out: &mut [u8]) -> Result<(), ImportStateError> {
+        io::Read::read_exact(&mut self.inner, out).map_err(map_read_error)?;
+        self.hasher.update(out);
+        Ok(())
+    }
+}
+
+/// Storage of the finalized block found in a snapshot.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct StorageEntries {
+    /// Entries of the main trie.
+    pub main_trie: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Entries of each default child trie, indexed by the identifier of the child trie without
+    /// the `:child_storage:default:` prefix. Never contains any empty child trie.
+    pub child_tries: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+/// Calculates the Merkle value of the root of the trie containing the given entries.
+pub(super) fn trie_root_hash(
+    entries: &BTreeMap<Vec<u8>, Vec<u8>>,
+    version: trie::TrieEntryVersion,
+) -> [u8; 32] {
+    let mut calculation = trie::calculate_root::root_merkle_value(None);
+
+    loop {
+        match calculation {
+            trie::calculate_root::RootMerkleValueCalculation::Finished { hash, .. } => break hash,
+            trie::calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
+                calculation = keys.inject(entries.keys().map(|k| k.iter().copied()));
+            }
+            trie::calculate_root::RootMerkleValueCalculation::StorageValue(value) => {
+                let key = value.key().collect::<Vec<_>>();
+                calculation = value.inject(version, entries.get(&key));
+            }
+        }
+    }
+}
+
+/// A truncated snapshot is reported as an invalid format rather than as an I/O error.
+fn map_read_error(err: io::Error) -> ImportStateError {
+    if err.kind() == io::ErrorKind::UnexpectedEof {
+        ImportStateError::InvalidFormat
+    } else {
+        ImportStateError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImportStateError, Reader, StorageEntries, Writer};
+    use alloc::collections::BTreeMap;
+
+    /// Writes a snapshot whose content is unrelated to any actual chain, as the writer and
+    /// reader don't interpret the data.
+    fn write_snapshot(duplicate_entry: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut writer = Writer::new(&mut out, &[0xaa; 32]).unwrap();
+        writer.write_bytes(b"header").unwrap();
+        writer.write_u32(2).unwrap();
+        writer.write_bytes(b"extrinsic1").unwrap();
+        writer.write_bytes(b"").unwrap();
+        writer.write_storage_entry(b"a", b"1").unwrap();
+        writer.write_storage_entry(b"b", b"").unwrap();
+        if duplicate_entry {
+            writer.write_storage_entry(b"a", b"2").unwrap();
+        }
+        writer.write_child_trie_entry(b"foo", b"a", b"3").unwrap();
+        writer.write_child_trie_entry(b"foo", b"c", b"4").unwrap();
+        writer.write_child_trie_entry(b"bar", b"a", b"5").unwrap();
+        writer.finish().unwrap();
+        out
+    }
+
+    fn read_snapshot(
+        snapshot: &[u8],
+    ) -> Result<([u8; 32], Vec<u8>, Vec<Vec<u8>>, StorageEntries), ImportStateError> {
+        let mut reader = Reader::new(snapshot)?;
+        let genesis_block_hash = *reader.genesis_block_hash();
+        let header = reader.read_bytes()?;
+        let num_extrinsics = reader.read_u32()?;
+        let body = (0..num_extrinsics)
+            .map(|_| reader.read_bytes())
+            .collect::<Result<Vec<_>, _>>()?;
+        let storage = reader.read_storage_entries()?;
+        reader.finish()?;
+        Ok((genesis_block_hash, header, body, storage))
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let (genesis_block_hash, header, body, storage) =
+            read_snapshot(&write_snapshot(false)).unwrap();
+
+        assert_eq!(genesis_block_hash, [0xaa; 32]);
+        assert_eq!(header, b"header");
+        assert_eq!(body, vec![b"extrinsic1".to_vec(), Vec::new()]);
+        assert_eq!(
+            storage,
+            StorageEntries {
+                main_trie: [(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), Vec::new())]
+                    .into_iter()
+                    .collect(),
+                child_tries: [
+                    (
+                        b"bar".to_vec(),
+                        [(b"a".to_vec(), b"5".to_vec())].into_iter().collect()
+                    ),
+                    (
+                        b"foo".to_vec(),
+                        [
+                            (b"a".to_vec(), b"3".to_vec()),
+                            (b"c".to_vec(), b"4".to_vec())
+                        ]
+                        .into_iter()
+                        .collect::<BTreeMap<_, _>>()
+                    ),
+                ]
+                .into_iter()
+                .collect(),
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_snapshot() {
+        let snapshot = write_snapshot(false);
+        for len in 0..snapshot.len() {
+            assert!(matches!(
+                read_snapshot(&snapshot[..len]),
+                Err(ImportStateError::InvalidFormat)
+            ));
+        }
+    }
+
+    #[test]
+    fn corrupted_byte() {
+        let snapshot = write_snapshot(false);
+        for index in 0..snapshot.len() {
+            let mut corrupted = snapshot.clone();
+            corrupted[index] ^= 0x1;
+            assert!(read_snapshot(&corrupted).is_err());
+        }
+    }
+
+    #[test]
+    fn corrupted_content_checksum_mismatch() {
+        let mut snapshot = write_snapshot(false);
+        // Modifies the last byte of `extrinsic1`, which doesn't change the structure of the
+        // snapshot.
+        let position = snapshot
+            .windows(10)
+            .position(|w| w == b"extrinsic1")
+            .unwrap();
+        snapshot[position + 9] = b'2';
+        assert!(matches!(
+            read_snapshot(&snapshot),
+            Err(ImportStateError::ChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn wrong_magic() {
+        let mut snapshot = write_snapshot(false);
+        snapshot[0] = b'x';
+        assert!(matches!(
+            read_snapshot(&snapshot),
+            Err(ImportStateError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut snapshot = write_snapshot(false);
+        snapshot[8] = 2;
+        assert!(matches!(
+            read_snapshot(&snapshot),
+            Err(ImportStateError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn trailing_data() {
+        let mut snapshot = write_snapshot(false);
+        snapshot.push(0);
+        assert!(matches!(
+            read_snapshot(&snapshot),
+            Err(ImportStateError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn duplicate_storage_entry() {
+        assert!(matches!(
+            read_snapshot(&write_snapshot(true)),
+            Err(ImportStateError::InvalidFormat)
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use super::{
+    open, Config, ConfigTy, DatabaseOpen, PruningMode, SqliteFullDatabase, StorageAccessError,
+};
+use crate::{chain::chain_information, header};
+
+use alloc::collections::BTreeMap;
+use core::iter;
+
+/// Key and new value of an entry of the storage.
+type StorageChange = (&'static [u8], Option<&'static [u8]>);
+
+/// Content of the storage of a block.
+type Storage = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Storage changes performed by each block after the genesis block.
+const CHANGES: &[&[StorageChange]] = &[
+    &[(b"a", Some(b"1")), (b"b", None)],
+    &[(b"a", Some(b"2")), (b"d", Some(b"2"))],
+    &[(b"c", None)],
+];
+
+fn genesis_storage() -> Storage {
+    [(b"a", b"0"), (b"b", b"0"), (b"c", b"0")]
+        .into_iter()
+        .map(|(k, v)| (k.to_vec(), v.to_vec()))
+        .collect()
+}
+
+fn child_header(parent: &header::Header) -> header::Header {
+    header::Header {
+        parent_hash: parent.hash(4),
+        number: parent.number + 1,
+        state_root: [1; 32],
+        extrinsics_root: [2; 32],
+        digest: header::DigestRef::empty().into(),
+    }
+}
+
+/// Builds a database containing a genesis block and one block for each element of [`CHANGES`],
+/// all finalized. Returns the database, plus the hash and expected storage of each block.
+fn build_database(pruning_mode: PruningMode) -> (SqliteFullDatabase, Vec<([u8; 32], Storage)>) {
+    let genesis_header = header::Header {
+        parent_hash: [0; 32],
+        number: 0,
+        state_root: [1; 32],
+        extrinsics_root: [2; 32],
+        digest: header::DigestRef::empty().into(),
+    };
+
+    let empty = match open(Config {
+        ty: ConfigTy::Memory,
+        block_number_bytes: 4,
+        pruning_mode: Some(pruning_mode),
+    })
+    .unwrap()
+    {
+        DatabaseOpen::Empty(empty) => empty,
+        DatabaseOpen::Open(_) => unreachable!(),
+    };
+
+    let mut storage = genesis_storage();
+    let database = empty
+        .initialize(
+            chain_information::ChainInformationRef {
+                finalized_block_header: (&genesis_header).into(),
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            storage.iter().map(|(k, v)| (&k[..], &v[..])),
+        )
+        .unwrap();
+
+    let mut blocks = vec![(genesis_header.hash(4), storage.clone())];
+    let mut parent = genesis_header;
+    for changes in CHANGES {
+        let header = child_header(&parent);
+        database
+            .insert(
+                &header.scale_encoding_vec(4),
+                true,
+                iter::empty::<Vec<u8>>(),
+                changes.iter().copied(),
+                iter::empty::<(Vec<u8>, Vec<u8>, Option<Vec<u8>>)>(),
+            )
+            .unwrap();
+        database.set_finalized(&header.hash(4)).unwrap();
+
+        for (key, value) in changes.iter() {
+            match value {
+                Some(value) => storage.insert(key.to_vec(), value.to_vec()),
+                None => storage.remove(*key),
+            };
+        }
+        blocks.push((header.hash(4), storage.clone()));
+        parent = header;
+    }
+
+    (database, blocks)
+}
+
+/// Checks that all the functions that access the storage of the given block return results
+/// that match `expected`.
+fn check_storage(database: &SqliteFullDatabase, block_hash: &[u8; 32], expected: &Storage) {
+    for key in [&b"a"[..], b"b", b"c", b"d", b"e"] {
+        assert_eq!(
+            database
+                .block_storage_top_trie_get(block_hash, key)
+                .unwrap(),
+            expected.get(key).cloned()
+        );
+    }
+
+    for key in [&b""[..], b"a", b"b", b"c", b"d"] {
+        assert_eq!(
+            database
+                .block_storage_top_trie_next_key(block_hash, key)
+                .unwrap(),
+            expected
+                .range::<[u8], _>((core::ops::Bound::Excluded(key), core::ops::Bound::Unbounded))
+                .next()
+                .map(|(k, _)| k.clone())
+        );
+    }
+
+    assert_eq!(
+        database
+            .block_storage_top_trie_keys(block_hash, b"")
+            .unwrap(),
+        expected.keys().cloned().collect::<Vec<_>>()
+    );
+
+    let mut entries = Vec::new();
+    database
+        .block_storage_top_trie_for_each(block_hash, b"", |key, value| {
+            entries.push((key.to_vec(), value.to_vec()));
+            true
+        })
+        .unwrap();
+    assert_eq!(
+        entries,
+        expected
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn archive_storage_of_old_blocks() {
+    let (database, blocks) = build_database(PruningMode::Archive);
+    for (block_hash, expected) in &blocks {
+        check_storage(&database, block_hash, expected);
+    }
+}
+
+#[test]
+fn pruned_storage_refused() {
+    let (database, blocks) = build_database(PruningMode::Prune { keep_ancestors: 1 });
+
+    // The finalized block and its parent are still accessible.
+    for (block_hash, expected) in &blocks[2..] {
+        check_storage(&database, block_hash, expected);
+    }
+
+    for (block_hash, _) in &blocks[..2] {
+        assert!(matches!(
+            database.block_storage_top_trie_get(block_hash, b"a"),
+            Err(StorageAccessError::StoragePruned)
+        ));
+        assert!(matches!(
+            database.block_storage_top_trie_next_key(block_hash, b"a"),
+            Err(StorageAccessError::StoragePruned)
+        ));
+        assert!(matches!(
+            database.block_storage_top_trie_keys(block_hash, b""),
+            Err(StorageAccessError::StoragePruned)
+        ));
+        assert!(matches!(
+            database.block_storage_top_trie_for_each(block_hash, b"", |_, _| true),
+            Err(StorageAccessError::StoragePruned)
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Serializing/deserializing a list of pending transactions.
+//!
+//! This module contains the [`encode_transactions`] and [`decode_transactions`] functions that
+//! can turn a list of [`PendingTransaction`]s into a string and back.
+//!
+//! The string format designed to be stable even if the structure of [`PendingTransaction`] is
+//! later modified.
+//!
+//! This feature is expected to be used for example by light clients in order to store the
+//! content of their [`light_pool::LightPool`](crate::transactions::light_pool::LightPool)
+//! alongside with the state of the finalized chain (see [the `finalized_serialize`
+//! module](super::finalized_serialize)), and submit these transactions again after a restart.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+/// Transaction found in a transactions pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingTransaction {
+    /// SCALE-encoded transaction.
+    pub scale_encoded: Vec<u8>,
+
+    /// Outcome of the latest validation of the transaction against the best chain.
+    pub validation: ValidationStatus,
+
+    /// If the transaction has been included in a block of the best chain, contains the hash
+    /// of this block and the index of the transaction within its body.
+    pub included_block: Option<([u8; 32], u32)>,
+}
+
+/// See [`PendingTransaction::validation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationStatus {
+    /// Transaction hasn't been validated against the best chain yet, or its validation has
+    /// expired.
+    NotValidated,
+    /// Transaction has been successfully validated.
+    Valid,
+    /// Transaction has been found to be invalid, or an error happened during its validation.
+    Invalid,
+}
+
+/// Serializes the given list of transactions as a JSON string.
+pub fn encode_transactions<'a>(
+    transactions: impl IntoIterator<Item = &'a PendingTransaction>,
+) -> String {
+    let encoded = SerializedTransactions::V1(
+        transactions
+            .into_iter()
+            .map(SerializedTransactionV1::from)
+            .collect(),
+    );
+
+    serde_json::to_string(&encoded).unwrap()
+}
+
+/// Deserializes a list of transactions.
+///
+/// This is the invert operation of [`encode_transactions`].
+pub fn decode_transactions(encoded: &str) -> Result<Vec<PendingTransaction>, CorruptedError> {
+    let encoded: SerializedTransactions = serde_json::from_str(encoded).map_err(CorruptedError)?;
+
+    Ok(match encoded {
+        SerializedTransactions::V1(list) => list.into_iter().map(From::from).collect(),
+    })
+}
+
+/// Opaque error indicating a corruption in the data stored in the local storage.
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "{}", _0)]
+pub struct CorruptedError(serde_json::Error);
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "version", content = "transactions")]
+enum SerializedTransactions {
+    #[serde(rename = "1")]
+    V1(Vec<SerializedTransactionV1>),
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SerializedTransactionV1 {
+    #[serde(
+        serialize_with = "serialize_bytes",
+        deserialize_with = "deserialize_bytes"
+    )]
+    scale_encoded: Vec<u8>,
+    validation: SerializedValidationStatusV1,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    included_block: Option<SerializedIncludedBlockV1>,
+}
+
+impl<'a> From<&'a PendingTransaction> for SerializedTransactionV1 {
+    fn from(from: &'a PendingTransaction) -> Self {
+        SerializedTransactionV1 {
+            scale_encoded: from.scale_encoded.clone(),
+            validation: match from.validation {
+                ValidationStatus::NotValidated => SerializedValidationStatusV1::NotValidated,
+                ValidationStatus::Valid => SerializedValidationStatusV1::Valid,
+                ValidationStatus::Invalid => SerializedValidationStatusV1::Invalid,
+            },
+            included_block: from
+                .included_block
+                .map(|(hash, index)| SerializedIncludedBlockV1 { hash, index }),
+        }
+    }
+}
+
+impl From<SerializedTransactionV1> for PendingTransaction {
+    fn from(from: SerializedTransactionV1) -> Self {
+        PendingTransaction {
+            scale_encoded: from.scale_encoded,
+            validation: match from.validation {
+                SerializedValidationStatusV1::NotValidated => ValidationStatus::NotValidated,
+                SerializedValidationStatusV1::Valid => ValidationStatus::Valid,
+                SerializedValidationStatusV1::Invalid => ValidationStatus::Invalid,
+            },
+            included_block: from.included_block.map(|b| (b.hash, b.index)),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+enum SerializedValidationStatusV1 {
+    #[serde(rename = "not-validated")]
+    NotValidated,
+    #[serde(rename = "valid")]
+    Valid,
+    #[serde(rename = "invalid")]
+    Invalid,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SerializedIncludedBlockV1 {
+    #[serde(
+        serialize_with = "serialize_bytes",
+        deserialize_with = "deserialize_hash32"
+    )]
+    hash: [u8; 32],
+    index: u32,
+}
+
+fn serialize_bytes<S: serde::Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    struct Writer<'a>(&'a [u8]);
+    impl<'a> fmt::Display for Writer<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            for byte in self.0 {
+                write!(f, "{:02x}", byte)?;
+            }
+            Ok(())
+        }
+    }
+
+    serializer.collect_str(&Writer(data))
+}
+
+fn deserialize_bytes<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<u8>, D::Error> {
+    let string = <&str as serde::Deserialize>::deserialize(deserializer)?;
+    hex::decode(string).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_hash32<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<[u8; 32], D::Error> {
+    let string = <&str as serde::Deserialize>::deserialize(deserializer)?;
+    let mut out = [0u8; 32];
+    hex::decode_to_slice(string, &mut out).map_err(serde::de::Error::custom)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PendingTransaction, ValidationStatus};
+
+    #[test]
+    fn encode_decode() {
+        let transactions = vec![
+            PendingTransaction {
+                scale_encoded: vec![8, 1, 2],
+                validation: ValidationStatus::NotValidated,
+                included_block: None,
+            },
+            PendingTransaction {
+                scale_encoded: vec![0],
+                validation: ValidationStatus::Valid,
+                included_block: Some(([0xab; 32], 3)),
+            },
+            PendingTransaction {
+                scale_encoded: vec![4, 5],
+                validation: ValidationStatus::Invalid,
+                included_block: None,
+            },
+        ];
+
+        let encoded = super::encode_transactions(&transactions);
+        let decoded = super::decode_transactions(&encoded).unwrap();
+        assert_eq!(decoded, transactions);
+    }
+
+    #[test]
+    fn decode_corrupted() {
+        assert!(super::decode_transactions("").is_err());
+        assert!(super::decode_transactions(r#"{"version":"2","transactions":[]}"#).is_err());
+        assert!(super::decode_transactions(
+            r#"{"version":"1","transactions":[{"scale_encoded":"zz","validation":"valid"}]}"#
+        )
+        .is_err());
+    }
+}
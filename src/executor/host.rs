@@ -210,6 +210,7 @@ pub use runtime_version::{CoreVersion, CoreVersionError, CoreVersionRef};
 pub use vm::HeapPages;
 pub use zstd::Error as ModuleFormatError;
 
+mod sandbox;
 mod zstd;
 
 /// Configuration for [`HostVmPrototype::new`].
@@ -488,6 +489,7 @@ impl HostVmPrototype {
                 registered_functions: self.registered_functions,
                 storage_transaction_depth: 0,
                 allocator,
+                sandbox: sandbox::Sandbox::new(),
             },
         })
     }
@@ -549,9 +551,75 @@ pub enum HostVm {
     /// Need to provide the storage key that follows a specific one.
     #[from]
     ExternalStorageNextKey(ExternalStorageNextKey),
+    /// Must load a storage value of a child trie.
+    #[from]
+    ExternalChildStorageGet(ExternalChildStorageGet),
+    /// Must set a storage value of a child trie.
+    #[from]
+    ExternalChildStorageSet(ExternalChildStorageSet),
+    /// Must remove all the storage values of a child trie starting with a certain prefix.
+    #[from]
+    ExternalChildStorageClearPrefix(ExternalChildStorageClearPrefix),
+    /// Need to provide the trie root of a child trie.
+    #[from]
+    ExternalChildStorageRoot(ExternalChildStorageRoot),
+    /// Need to provide the storage key of a child trie that follows a specific one.
+    #[from]
+    ExternalChildStorageNextKey(ExternalChildStorageNextKey),
     /// Must the set value of an off-chain storage entry.
     #[from]
     ExternalOffchainStorageSet(ExternalOffchainStorageSet),
+    /// Need to indicate whether the local node is a validator.
+    #[from]
+    OffchainIsValidator(OffchainIsValidator),
+    /// Need to submit a transaction to the transactions pool.
+    #[from]
+    OffchainSubmitTransaction(OffchainSubmitTransaction),
+    /// Need to provide the network state of the local node.
+    #[from]
+    OffchainNetworkState(OffchainNetworkState),
+    /// Need to provide the current UNIX timestamp in milliseconds.
+    #[from]
+    OffchainTimestamp(OffchainTimestamp),
+    /// Must pause execution until the given deadline.
+    #[from]
+    OffchainSleepUntil(OffchainSleepUntil),
+    /// Need to provide a randomly-generated seed.
+    #[from]
+    OffchainRandomSeed(OffchainRandomSeed),
+    /// Must load a value from the off-chain local storage.
+    #[from]
+    OffchainLocalStorageGet(OffchainLocalStorageGet),
+    /// Must set or clear a value of the off-chain local storage.
+    #[from]
+    OffchainLocalStorageSet(OffchainLocalStorageSet),
+    /// Must atomically compare and set a value of the off-chain local storage.
+    #[from]
+    OffchainLocalStorageCompareAndSet(OffchainLocalStorageCompareAndSet),
+    /// Must start a new HTTP request.
+    #[from]
+    OffchainHttpRequestStart(OffchainHttpRequestStart),
+    /// Must add a header to an HTTP request that hasn't been sent yet.
+    #[from]
+    OffchainHttpRequestAddHeader(OffchainHttpRequestAddHeader),
+    /// Must write a chunk of the body of an HTTP request.
+    #[from]
+    OffchainHttpRequestWriteBody(OffchainHttpRequestWriteBody),
+    /// Must wait for HTTP requests to receive a response.
+    #[from]
+    OffchainHttpResponseWait(OffchainHttpResponseWait),
+    /// Need to provide the headers of the response of an HTTP request.
+    #[from]
+    OffchainHttpResponseHeaders(OffchainHttpResponseHeaders),
+    /// Need to provide a chunk of the body of the response of an HTTP request.
+    #[from]
+    OffchainHttpResponseReadBody(OffchainHttpResponseReadBody),
+    /// Need to generate a new ECDSA key pair and store it in the keystore.
+    #[from]
+    CryptoEcdsaGenerate(CryptoEcdsaGenerate),
+    /// Need to provide the list of ECDSA public keys found in the keystore.
+    #[from]
+    CryptoEcdsaPublicKeys(CryptoEcdsaPublicKeys),
     /// Need to verify whether a signature is valid.
     #[from]
     SignatureVerification(SignatureVerification),
@@ -596,7 +664,29 @@ impl HostVm {
             HostVm::ExternalStorageClearPrefix(inner) => inner.inner.into_prototype(),
             HostVm::ExternalStorageRoot(inner) => inner.inner.into_prototype(),
             HostVm::ExternalStorageNextKey(inner) => inner.inner.into_prototype(),
+            HostVm::ExternalChildStorageGet(inner) => inner.inner.inner.into_prototype(),
+            HostVm::ExternalChildStorageSet(inner) => inner.inner.into_prototype(),
+            HostVm::ExternalChildStorageClearPrefix(inner) => inner.inner.into_prototype(),
+            HostVm::ExternalChildStorageRoot(inner) => inner.inner.into_prototype(),
+            HostVm::ExternalChildStorageNextKey(inner) => inner.inner.into_prototype(),
             HostVm::ExternalOffchainStorageSet(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainIsValidator(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainSubmitTransaction(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainNetworkState(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainTimestamp(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainSleepUntil(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainRandomSeed(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainLocalStorageGet(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainLocalStorageSet(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainLocalStorageCompareAndSet(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpRequestStart(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpRequestAddHeader(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpRequestWriteBody(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpResponseWait(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpResponseHeaders(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpResponseReadBody(inner) => inner.inner.into_prototype(),
+            HostVm::CryptoEcdsaGenerate(inner) => inner.inner.into_prototype(),
+            HostVm::CryptoEcdsaPublicKeys(inner) => inner.inner.into_prototype(),
             HostVm::SignatureVerification(inner) => inner.inner.into_prototype(),
             HostVm::CallRuntimeVersion(inner) => inner.inner.into_prototype(),
             HostVm::StartStorageTransaction(inner) => inner.inner.into_prototype(),
@@ -632,6 +722,13 @@ impl ReadyToRun {
         let (id, params) = match self.inner.vm.run(self.resume_value) {
             Ok(vm::ExecOutcome::Interrupted { id, params }) => (id, params),
 
+            Ok(vm::ExecOutcome::Finished {
+                return_value: Ok(return_value),
+            }) if self.inner.sandbox.is_dispatch_thunk_running() => {
+                // The dispatch thunk, called on behalf of a sandboxed guest, has returned.
+                return self.inner.dispatch_thunk_finished(return_value);
+            }
+
             Ok(vm::ExecOutcome::Finished {
                 return_value: Ok(Some(vm::WasmValue::I64(ret))),
             }) => {
@@ -935,6 +1032,120 @@ impl ReadyToRun {
             }};
         }
 
+        macro_rules! expect_max_keys_to_remove {
+            ($num:expr) => {{
+                let max_keys_to_remove = {
+                    let input = expect_pointer_size!($num);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(util::nom_option_decode(
+                            nom::number::complete::le_u32,
+                        ))(input.as_ref())
+                        .map(|(_, parse_result)| parse_result);
+
+                    match parsing_result {
+                        Ok(val) => Ok(val),
+                        Err(_) => Err(()),
+                    }
+                };
+
+                match max_keys_to_remove {
+                    Ok(l) => l,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        };
+                    }
+                }
+            }};
+        }
+
+        macro_rules! expect_offchain_storage_kind {
+            ($num:expr) => {{
+                match &params[$num] {
+                    vm::WasmValue::I32(1) => OffchainStorageKind::Persistent,
+                    vm::WasmValue::I32(2) => OffchainStorageKind::Local,
+                    vm::WasmValue::I32(_) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                    v => {
+                        return HostVm::Error {
+                            error: Error::WrongParamTy {
+                                function: host_fn.name(),
+                                param_num: $num,
+                                expected: vm::ValueType::I32,
+                                actual: v.ty(),
+                            },
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                }
+            }};
+        }
+
+        macro_rules! expect_http_request_id {
+            ($num:expr) => {{
+                match u16::try_from(expect_u32!($num)) {
+                    Ok(id) => id,
+                    Err(_) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                }
+            }};
+        }
+
+        // Passed a parameter index. Decodes a SCALE-encoded `Option<u64>`.
+        macro_rules! expect_offchain_deadline {
+            ($num:expr) => {{
+                let deadline = {
+                    let input = expect_pointer_size!($num);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(util::nom_option_decode(
+                            nom::number::complete::le_u64,
+                        ))(input.as_ref())
+                        .map(|(_, parse_result)| parse_result);
+                    parsing_result.map_err(|_| ())
+                };
+
+                match deadline {
+                    Ok(d) => d,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        };
+                    }
+                }
+            }};
+        }
+
+        // Same as `expect_pointer_size_raw`, but additionally checks that the data is UTF-8.
+        macro_rules! expect_utf8_pointer_size_raw {
+            ($num:expr) => {{
+                let (ptr, size) = expect_pointer_size_raw!($num);
+                let utf8_check =
+                    str::from_utf8(self.inner.vm.read_memory(ptr, size).unwrap().as_ref())
+                        .map(|_| ());
+                if let Err(error) = utf8_check {
+                    return HostVm::Error {
+                        error: Error::Utf8Error {
+                            function: host_fn.name(),
+                            param_num: $num,
+                            error,
+                        },
+                        prototype: self.inner.into_prototype(),
+                    };
+                }
+                (ptr, size)
+            }};
+        }
+
         // TODO: implement all functions and remove this macro
         macro_rules! host_fn_not_implemented {
             () => {{
@@ -1020,31 +1231,7 @@ impl ReadyToRun {
             }
             HostFunction::ext_storage_clear_prefix_version_2 => {
                 let (prefix_ptr, prefix_size) = expect_pointer_size_raw!(0);
-
-                let max_keys_to_remove = {
-                    let input = expect_pointer_size!(1);
-                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
-                        nom::combinator::all_consuming(util::nom_option_decode(
-                            nom::number::complete::le_u32,
-                        ))(input.as_ref())
-                        .map(|(_, parse_result)| parse_result);
-
-                    match parsing_result {
-                        Ok(val) => Ok(val),
-                        Err(_) => Err(()),
-                    }
-                };
-
-                let max_keys_to_remove = match max_keys_to_remove {
-                    Ok(l) => l,
-                    Err(()) => {
-                        return HostVm::Error {
-                            error: Error::ParamDecodeError,
-                            prototype: self.inner.into_prototype(),
-                        };
-                    }
-                };
-
+                let max_keys_to_remove = expect_max_keys_to_remove!(1);
                 HostVm::ExternalStorageClearPrefix(ExternalStorageClearPrefix {
                     prefix_ptr,
                     prefix_size,
@@ -1141,31 +1328,168 @@ impl ReadyToRun {
                     rollback: false,
                 }
             }
-            HostFunction::ext_default_child_storage_get_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_default_child_storage_read_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_default_child_storage_storage_kill_version_1 => {
-                host_fn_not_implemented!()
+            HostFunction::ext_default_child_storage_get_version_1 => {
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                HostVm::ExternalChildStorageGet(ExternalChildStorageGet {
+                    child_trie_ptr,
+                    child_trie_size,
+                    inner: ExternalStorageGet {
+                        key_ptr,
+                        key_size,
+                        calling: id,
+                        value_out_ptr: None,
+                        offset: 0,
+                        max_size: u32::MAX,
+                        inner: self.inner,
+                    },
+                })
             }
-            HostFunction::ext_default_child_storage_storage_kill_version_2 => {
-                host_fn_not_implemented!()
+            HostFunction::ext_default_child_storage_read_version_1 => {
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                let (value_out_ptr, value_out_size) = expect_pointer_size_raw!(2);
+                let offset = expect_u32!(3);
+                HostVm::ExternalChildStorageGet(ExternalChildStorageGet {
+                    child_trie_ptr,
+                    child_trie_size,
+                    inner: ExternalStorageGet {
+                        key_ptr,
+                        key_size,
+                        calling: id,
+                        value_out_ptr: Some(value_out_ptr),
+                        offset,
+                        max_size: value_out_size,
+                        inner: self.inner,
+                    },
+                })
+            }
+            HostFunction::ext_default_child_storage_exists_version_1 => {
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                HostVm::ExternalChildStorageGet(ExternalChildStorageGet {
+                    child_trie_ptr,
+                    child_trie_size,
+                    inner: ExternalStorageGet {
+                        key_ptr,
+                        key_size,
+                        calling: id,
+                        value_out_ptr: None,
+                        offset: 0,
+                        max_size: 0,
+                        inner: self.inner,
+                    },
+                })
             }
-            HostFunction::ext_default_child_storage_storage_kill_version_3 => {
-                host_fn_not_implemented!()
+            HostFunction::ext_default_child_storage_storage_kill_version_1 => {
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                HostVm::ExternalChildStorageClearPrefix(ExternalChildStorageClearPrefix {
+                    child_trie_ptr,
+                    child_trie_size,
+                    prefix: None,
+                    max_keys_to_remove: None,
+                    calling: id,
+                    inner: self.inner,
+                })
+            }
+            HostFunction::ext_default_child_storage_storage_kill_version_2
+            | HostFunction::ext_default_child_storage_storage_kill_version_3 => {
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                let max_keys_to_remove = expect_max_keys_to_remove!(1);
+                HostVm::ExternalChildStorageClearPrefix(ExternalChildStorageClearPrefix {
+                    child_trie_ptr,
+                    child_trie_size,
+                    prefix: None,
+                    max_keys_to_remove,
+                    calling: id,
+                    inner: self.inner,
+                })
             }
             HostFunction::ext_default_child_storage_clear_prefix_version_1 => {
-                host_fn_not_implemented!()
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                let prefix = expect_pointer_size_raw!(1);
+                HostVm::ExternalChildStorageClearPrefix(ExternalChildStorageClearPrefix {
+                    child_trie_ptr,
+                    child_trie_size,
+                    prefix: Some(prefix),
+                    max_keys_to_remove: None,
+                    calling: id,
+                    inner: self.inner,
+                })
             }
             HostFunction::ext_default_child_storage_clear_prefix_version_2 => {
-                host_fn_not_implemented!()
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                let prefix = expect_pointer_size_raw!(1);
+                let max_keys_to_remove = expect_max_keys_to_remove!(2);
+                HostVm::ExternalChildStorageClearPrefix(ExternalChildStorageClearPrefix {
+                    child_trie_ptr,
+                    child_trie_size,
+                    prefix: Some(prefix),
+                    max_keys_to_remove,
+                    calling: id,
+                    inner: self.inner,
+                })
+            }
+            HostFunction::ext_default_child_storage_set_version_1 => {
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                let (value_ptr, value_size) = expect_pointer_size_raw!(2);
+                HostVm::ExternalChildStorageSet(ExternalChildStorageSet {
+                    child_trie_ptr,
+                    child_trie_size,
+                    key_ptr,
+                    key_size,
+                    value: Some((value_ptr, value_size)),
+                    inner: self.inner,
+                })
+            }
+            HostFunction::ext_default_child_storage_clear_version_1 => {
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                HostVm::ExternalChildStorageSet(ExternalChildStorageSet {
+                    child_trie_ptr,
+                    child_trie_size,
+                    key_ptr,
+                    key_size,
+                    value: None,
+                    inner: self.inner,
+                })
             }
-            HostFunction::ext_default_child_storage_set_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_default_child_storage_clear_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_default_child_storage_exists_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_default_child_storage_next_key_version_1 => {
-                host_fn_not_implemented!()
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                HostVm::ExternalChildStorageNextKey(ExternalChildStorageNextKey {
+                    child_trie_ptr,
+                    child_trie_size,
+                    key_ptr,
+                    key_size,
+                    inner: self.inner,
+                })
+            }
+            HostFunction::ext_default_child_storage_root_version_1 => {
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                HostVm::ExternalChildStorageRoot(ExternalChildStorageRoot {
+                    child_trie_ptr,
+                    child_trie_size,
+                    calling: id,
+                    inner: self.inner,
+                })
+            }
+            HostFunction::ext_default_child_storage_root_version_2 => {
+                let (child_trie_ptr, child_trie_size) = expect_pointer_size_raw!(0);
+                let state_version = expect_state_version!(1);
+                match state_version {
+                    trie::TrieEntryVersion::V0 => {
+                        HostVm::ExternalChildStorageRoot(ExternalChildStorageRoot {
+                            child_trie_ptr,
+                            child_trie_size,
+                            calling: id,
+                            inner: self.inner,
+                        })
+                    }
+                    trie::TrieEntryVersion::V1 => host_fn_not_implemented!(), // TODO: https://github.com/paritytech/smoldot/issues/1967
+                }
             }
-            HostFunction::ext_default_child_storage_root_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_default_child_storage_root_version_2 => host_fn_not_implemented!(),
             HostFunction::ext_crypto_ed25519_public_keys_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_crypto_ed25519_generate_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_crypto_ed25519_sign_version_1 => host_fn_not_implemented!(),
@@ -1205,7 +1529,34 @@ impl ReadyToRun {
                     inner: self.inner,
                 })
             }
-            HostFunction::ext_crypto_ecdsa_generate_version_1 => host_fn_not_implemented!(),
+            HostFunction::ext_crypto_ecdsa_generate_version_1 => {
+                let key_type_id = expect_pointer_constant_size!(0, 4);
+
+                let seed = {
+                    let input = expect_pointer_size!(1);
+                    let parsing_result: Result<_, nom::Err<nom::error::Error<&[u8]>>> =
+                        nom::combinator::all_consuming(util::nom_option_decode(
+                            util::nom_string_decode,
+                        ))(input.as_ref())
+                        .map(|(_, parse_result)| parse_result.map(|v| v.to_owned()));
+                    parsing_result.map_err(|_| ())
+                };
+                let seed = match seed {
+                    Ok(s) => s,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        };
+                    }
+                };
+
+                HostVm::CryptoEcdsaGenerate(CryptoEcdsaGenerate {
+                    inner: self.inner,
+                    key_type_id,
+                    seed,
+                })
+            }
             HostFunction::ext_crypto_ecdsa_sign_version_1 => {
                 // NOTE: safe to unwrap here because we supply the nn to blake2b fn
                 let data = <[u8; 32]>::try_from(
@@ -1232,7 +1583,13 @@ impl ReadyToRun {
                     }
                 }
             }
-            HostFunction::ext_crypto_ecdsa_public_keys_version_1 => host_fn_not_implemented!(),
+            HostFunction::ext_crypto_ecdsa_public_keys_version_1 => {
+                let key_type_id = expect_pointer_constant_size!(0, 4);
+                HostVm::CryptoEcdsaPublicKeys(CryptoEcdsaPublicKeys {
+                    inner: self.inner,
+                    key_type_id,
+                })
+            }
             HostFunction::ext_crypto_ecdsa_verify_version_1 => {
                 let (message_ptr, message_size) = expect_pointer_size_raw!(1);
                 HostVm::SignatureVerification(SignatureVerification {
@@ -1506,62 +1863,496 @@ impl ReadyToRun {
                     inner: self.inner,
                 })
             }
-            HostFunction::ext_offchain_is_validator_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_submit_transaction_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_network_state_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_timestamp_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_sleep_until_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_random_seed_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_local_storage_set_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_local_storage_compare_and_set_version_1 => {
-                host_fn_not_implemented!()
+            HostFunction::ext_offchain_is_validator_version_1 => {
+                HostVm::OffchainIsValidator(OffchainIsValidator { inner: self.inner })
             }
-            HostFunction::ext_offchain_local_storage_get_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_local_storage_clear_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_http_request_start_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_http_request_add_header_version_1 => {
-                host_fn_not_implemented!()
+            HostFunction::ext_offchain_submit_transaction_version_1 => {
+                let (tx_ptr, tx_size) = expect_pointer_size_raw!(0);
+                HostVm::OffchainSubmitTransaction(OffchainSubmitTransaction {
+                    inner: self.inner,
+                    tx_ptr,
+                    tx_size,
+                })
             }
-            HostFunction::ext_offchain_http_request_write_body_version_1 => {
-                host_fn_not_implemented!()
+            HostFunction::ext_offchain_network_state_version_1 => {
+                HostVm::OffchainNetworkState(OffchainNetworkState { inner: self.inner })
             }
-            HostFunction::ext_offchain_http_response_wait_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_http_response_headers_version_1 => {
-                host_fn_not_implemented!()
+            HostFunction::ext_offchain_timestamp_version_1 => {
+                HostVm::OffchainTimestamp(OffchainTimestamp { inner: self.inner })
             }
-            HostFunction::ext_offchain_http_response_read_body_version_1 => {
-                host_fn_not_implemented!()
-            }
-            HostFunction::ext_sandbox_instantiate_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_sandbox_invoke_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_sandbox_memory_new_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_sandbox_memory_get_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_sandbox_memory_set_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_sandbox_memory_teardown_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_sandbox_instance_teardown_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_sandbox_get_global_val_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_trie_blake2_256_root_version_1
-            | HostFunction::ext_trie_blake2_256_root_version_2 => {
-                let state_version =
-                    if matches!(host_fn, HostFunction::ext_trie_blake2_256_root_version_2) {
-                        expect_state_version!(1)
-                    } else {
-                        trie::TrieEntryVersion::V0
-                    };
+            HostFunction::ext_offchain_sleep_until_version_1 => {
+                let deadline = match &params[0] {
+                    vm::WasmValue::I64(v) => u64::from_ne_bytes(v.to_ne_bytes()),
+                    v => {
+                        return HostVm::Error {
+                            error: Error::WrongParamTy {
+                                function: host_fn.name(),
+                                param_num: 0,
+                                expected: vm::ValueType::I64,
+                                actual: v.ty(),
+                            },
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                };
 
-                let result = {
-                    let input = expect_pointer_size!(0);
-                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
-                        nom::combinator::all_consuming(nom::combinator::flat_map(
-                            crate::util::nom_scale_compact_usize,
-                            |num_elems| {
-                                nom::multi::many_m_n(
-                                    num_elems,
-                                    num_elems,
-                                    nom::sequence::tuple((
-                                        nom::combinator::flat_map(
-                                            crate::util::nom_scale_compact_usize,
-                                            nom::bytes::complete::take,
+                HostVm::OffchainSleepUntil(OffchainSleepUntil {
+                    inner: self.inner,
+                    deadline,
+                })
+            }
+            HostFunction::ext_offchain_random_seed_version_1 => {
+                HostVm::OffchainRandomSeed(OffchainRandomSeed { inner: self.inner })
+            }
+            HostFunction::ext_offchain_local_storage_set_version_1 => {
+                let kind = expect_offchain_storage_kind!(0);
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                let (value_ptr, value_size) = expect_pointer_size_raw!(2);
+                HostVm::OffchainLocalStorageSet(OffchainLocalStorageSet {
+                    inner: self.inner,
+                    kind,
+                    key_ptr,
+                    key_size,
+                    value: Some((value_ptr, value_size)),
+                })
+            }
+            HostFunction::ext_offchain_local_storage_compare_and_set_version_1 => {
+                let kind = expect_offchain_storage_kind!(0);
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+
+                let old_value = {
+                    let input = expect_pointer_size!(2);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(util::nom_option_decode(
+                            util::nom_bytes_decode,
+                        ))(input.as_ref())
+                        .map(|(_, parse_result)| parse_result.map(|v| v.to_vec()));
+                    parsing_result.map_err(|_| ())
+                };
+                let old_value = match old_value {
+                    Ok(v) => v,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        };
+                    }
+                };
+
+                let (new_value_ptr, new_value_size) = expect_pointer_size_raw!(3);
+                HostVm::OffchainLocalStorageCompareAndSet(OffchainLocalStorageCompareAndSet {
+                    inner: self.inner,
+                    kind,
+                    key_ptr,
+                    key_size,
+                    old_value,
+                    new_value_ptr,
+                    new_value_size,
+                })
+            }
+            HostFunction::ext_offchain_local_storage_get_version_1 => {
+                let kind = expect_offchain_storage_kind!(0);
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                HostVm::OffchainLocalStorageGet(OffchainLocalStorageGet {
+                    inner: self.inner,
+                    kind,
+                    key_ptr,
+                    key_size,
+                })
+            }
+            HostFunction::ext_offchain_local_storage_clear_version_1 => {
+                let kind = expect_offchain_storage_kind!(0);
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                HostVm::OffchainLocalStorageSet(OffchainLocalStorageSet {
+                    inner: self.inner,
+                    kind,
+                    key_ptr,
+                    key_size,
+                    value: None,
+                })
+            }
+            HostFunction::ext_offchain_http_request_start_version_1 => {
+                let (method_ptr, method_size) = expect_utf8_pointer_size_raw!(0);
+                let (uri_ptr, uri_size) = expect_utf8_pointer_size_raw!(1);
+                let (meta_ptr, meta_size) = expect_pointer_size_raw!(2);
+                HostVm::OffchainHttpRequestStart(OffchainHttpRequestStart {
+                    inner: self.inner,
+                    method_ptr,
+                    method_size,
+                    uri_ptr,
+                    uri_size,
+                    meta_ptr,
+                    meta_size,
+                })
+            }
+            HostFunction::ext_offchain_http_request_add_header_version_1 => {
+                let request_id = expect_http_request_id!(0);
+                let (name_ptr, name_size) = expect_utf8_pointer_size_raw!(1);
+                let (value_ptr, value_size) = expect_utf8_pointer_size_raw!(2);
+                HostVm::OffchainHttpRequestAddHeader(OffchainHttpRequestAddHeader {
+                    inner: self.inner,
+                    request_id,
+                    name_ptr,
+                    name_size,
+                    value_ptr,
+                    value_size,
+                })
+            }
+            HostFunction::ext_offchain_http_request_write_body_version_1 => {
+                let request_id = expect_http_request_id!(0);
+                let (chunk_ptr, chunk_size) = expect_pointer_size_raw!(1);
+                let deadline = expect_offchain_deadline!(2);
+                HostVm::OffchainHttpRequestWriteBody(OffchainHttpRequestWriteBody {
+                    inner: self.inner,
+                    request_id,
+                    chunk_ptr,
+                    chunk_size,
+                    deadline,
+                })
+            }
+            HostFunction::ext_offchain_http_response_wait_version_1 => {
+                let request_ids = {
+                    let input = expect_pointer_size!(0);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(nom::combinator::flat_map(
+                            util::nom_scale_compact_usize,
+                            |num_elems| {
+                                nom::multi::many_m_n(
+                                    num_elems,
+                                    num_elems,
+                                    nom::number::complete::le_u16,
+                                )
+                            },
+                        ))(input.as_ref())
+                        .map(|(_, parse_result)| parse_result);
+                    parsing_result.map_err(|_| ())
+                };
+                let request_ids = match request_ids {
+                    Ok(ids) => ids,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        };
+                    }
+                };
+
+                let deadline = expect_offchain_deadline!(1);
+                HostVm::OffchainHttpResponseWait(OffchainHttpResponseWait {
+                    inner: self.inner,
+                    request_ids,
+                    deadline,
+                })
+            }
+            HostFunction::ext_offchain_http_response_headers_version_1 => {
+                let request_id = expect_http_request_id!(0);
+                HostVm::OffchainHttpResponseHeaders(OffchainHttpResponseHeaders {
+                    inner: self.inner,
+                    request_id,
+                })
+            }
+            HostFunction::ext_offchain_http_response_read_body_version_1 => {
+                let request_id = expect_http_request_id!(0);
+                let (buffer_ptr, buffer_size) = expect_pointer_size_raw!(1);
+                let deadline = expect_offchain_deadline!(2);
+                HostVm::OffchainHttpResponseReadBody(OffchainHttpResponseReadBody {
+                    inner: self.inner,
+                    request_id,
+                    buffer_ptr,
+                    buffer_size,
+                    deadline,
+                })
+            }
+            HostFunction::ext_sandbox_instantiate_version_1 => {
+                let dispatch_thunk = expect_u32!(0);
+                let (code_ptr, code_size) = expect_pointer_size_raw!(1);
+                let (env_def_ptr, env_def_size) = expect_pointer_size_raw!(2);
+                // The fourth parameter, the `state`, is unused and only kept for backwards
+                // compatibility.
+                let _ = expect_u32!(3);
+
+                let result = self.inner.sandbox.instantiate(
+                    dispatch_thunk,
+                    self.inner
+                        .vm
+                        .read_memory(code_ptr, code_size)
+                        .unwrap()
+                        .as_ref(),
+                    self.inner
+                        .vm
+                        .read_memory(env_def_ptr, env_def_size)
+                        .unwrap()
+                        .as_ref(),
+                );
+
+                let ret = match result {
+                    Ok(instance_idx) => instance_idx,
+                    Err(error_code) => error_code,
+                };
+
+                HostVm::ReadyToRun(ReadyToRun {
+                    inner: self.inner,
+                    resume_value: Some(vm::WasmValue::I32(i32::from_ne_bytes(ret.to_ne_bytes()))),
+                })
+            }
+            HostFunction::ext_sandbox_invoke_version_1 => {
+                let instance_idx = expect_u32!(0);
+                let (function_ptr, function_size) = expect_utf8_pointer_size_raw!(1);
+                let (args_ptr, args_size) = expect_pointer_size_raw!(2);
+                let return_value_ptr = expect_u32!(3);
+                let return_value_len = expect_u32!(4);
+                let state = expect_u32!(5);
+
+                if return_value_len.saturating_add(return_value_ptr)
+                    > u32::from(self.inner.vm.memory_size()) * 64 * 1024
+                {
+                    return HostVm::Error {
+                        error: Error::ParamOutOfRange {
+                            function: host_fn.name(),
+                            param_num: 3,
+                            pointer: return_value_ptr,
+                            length: return_value_len,
+                        },
+                        prototype: self.inner.into_prototype(),
+                    };
+                }
+
+                let result = self.inner.sandbox.start_invocation(
+                    instance_idx,
+                    str::from_utf8(
+                        self.inner
+                            .vm
+                            .read_memory(function_ptr, function_size)
+                            .unwrap()
+                            .as_ref(),
+                    )
+                    .unwrap(),
+                    self.inner
+                        .vm
+                        .read_memory(args_ptr, args_size)
+                        .unwrap()
+                        .as_ref(),
+                    return_value_ptr,
+                    return_value_len,
+                    state,
+                );
+
+                match result {
+                    Ok(outcome) => self.inner.handle_sandbox_invocation_outcome(outcome),
+                    Err(sandbox::StartInvocationError::Execution) => {
+                        HostVm::ReadyToRun(ReadyToRun {
+                            inner: self.inner,
+                            resume_value: Some(vm::WasmValue::I32(i32::from_ne_bytes(
+                                sandbox::ERR_EXECUTION.to_ne_bytes(),
+                            ))),
+                        })
+                    }
+                    Err(sandbox::StartInvocationError::ArgsDecode) => HostVm::Error {
+                        error: Error::ParamDecodeError,
+                        prototype: self.inner.into_prototype(),
+                    },
+                    Err(sandbox::StartInvocationError::Sandbox(error)) => HostVm::Error {
+                        error: Error::Sandbox {
+                            function: host_fn.name(),
+                            error,
+                        },
+                        prototype: self.inner.into_prototype(),
+                    },
+                }
+            }
+            HostFunction::ext_sandbox_memory_new_version_1 => {
+                let initial = expect_u32!(0);
+                let maximum = expect_u32!(1);
+                match self.inner.sandbox.memory_new(initial, maximum) {
+                    Ok(memory_idx) => HostVm::ReadyToRun(ReadyToRun {
+                        inner: self.inner,
+                        resume_value: Some(vm::WasmValue::I32(i32::from_ne_bytes(
+                            memory_idx.to_ne_bytes(),
+                        ))),
+                    }),
+                    Err(error) => HostVm::Error {
+                        error: Error::Sandbox {
+                            function: host_fn.name(),
+                            error,
+                        },
+                        prototype: self.inner.into_prototype(),
+                    },
+                }
+            }
+            HostFunction::ext_sandbox_memory_get_version_1 => {
+                let memory_idx = expect_u32!(0);
+                let offset = expect_u32!(1);
+                let buf_ptr = expect_u32!(2);
+                let buf_len = expect_u32!(3);
+
+                if buf_len.saturating_add(buf_ptr)
+                    > u32::from(self.inner.vm.memory_size()) * 64 * 1024
+                {
+                    return HostVm::Error {
+                        error: Error::ParamOutOfRange {
+                            function: host_fn.name(),
+                            param_num: 2,
+                            pointer: buf_ptr,
+                            length: buf_len,
+                        },
+                        prototype: self.inner.into_prototype(),
+                    };
+                }
+
+                let ret = match self.inner.sandbox.memory_get(memory_idx, offset, buf_len) {
+                    Ok(Some(data)) => {
+                        self.inner.vm.write_memory(buf_ptr, data).unwrap();
+                        sandbox::ERR_OK
+                    }
+                    Ok(None) => sandbox::ERR_OUT_OF_BOUNDS,
+                    Err(error) => {
+                        return HostVm::Error {
+                            error: Error::Sandbox {
+                                function: host_fn.name(),
+                                error,
+                            },
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                };
+
+                HostVm::ReadyToRun(ReadyToRun {
+                    inner: self.inner,
+                    resume_value: Some(vm::WasmValue::I32(i32::from_ne_bytes(ret.to_ne_bytes()))),
+                })
+            }
+            HostFunction::ext_sandbox_memory_set_version_1 => {
+                let memory_idx = expect_u32!(0);
+                let offset = expect_u32!(1);
+                let val_ptr = expect_u32!(2);
+                let val_len = expect_u32!(3);
+
+                if val_len.saturating_add(val_ptr)
+                    > u32::from(self.inner.vm.memory_size()) * 64 * 1024
+                {
+                    return HostVm::Error {
+                        error: Error::ParamOutOfRange {
+                            function: host_fn.name(),
+                            param_num: 2,
+                            pointer: val_ptr,
+                            length: val_len,
+                        },
+                        prototype: self.inner.into_prototype(),
+                    };
+                }
+
+                let result = self.inner.sandbox.memory_set(
+                    memory_idx,
+                    offset,
+                    self.inner
+                        .vm
+                        .read_memory(val_ptr, val_len)
+                        .unwrap()
+                        .as_ref(),
+                );
+
+                let ret = match result {
+                    Ok(true) => sandbox::ERR_OK,
+                    Ok(false) => sandbox::ERR_OUT_OF_BOUNDS,
+                    Err(error) => {
+                        return HostVm::Error {
+                            error: Error::Sandbox {
+                                function: host_fn.name(),
+                                error,
+                            },
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                };
+
+                HostVm::ReadyToRun(ReadyToRun {
+                    inner: self.inner,
+                    resume_value: Some(vm::WasmValue::I32(i32::from_ne_bytes(ret.to_ne_bytes()))),
+                })
+            }
+            HostFunction::ext_sandbox_memory_teardown_version_1 => {
+                let memory_idx = expect_u32!(0);
+                match self.inner.sandbox.memory_teardown(memory_idx) {
+                    Ok(()) => HostVm::ReadyToRun(ReadyToRun {
+                        inner: self.inner,
+                        resume_value: None,
+                    }),
+                    Err(error) => HostVm::Error {
+                        error: Error::Sandbox {
+                            function: host_fn.name(),
+                            error,
+                        },
+                        prototype: self.inner.into_prototype(),
+                    },
+                }
+            }
+            HostFunction::ext_sandbox_instance_teardown_version_1 => {
+                let instance_idx = expect_u32!(0);
+                match self.inner.sandbox.instance_teardown(instance_idx) {
+                    Ok(()) => HostVm::ReadyToRun(ReadyToRun {
+                        inner: self.inner,
+                        resume_value: None,
+                    }),
+                    Err(error) => HostVm::Error {
+                        error: Error::Sandbox {
+                            function: host_fn.name(),
+                            error,
+                        },
+                        prototype: self.inner.into_prototype(),
+                    },
+                }
+            }
+            HostFunction::ext_sandbox_get_global_val_version_1 => {
+                let instance_idx = expect_u32!(0);
+                let (name_ptr, name_size) = expect_utf8_pointer_size_raw!(1);
+
+                let result = self.inner.sandbox.global_value(
+                    instance_idx,
+                    str::from_utf8(
+                        self.inner
+                            .vm
+                            .read_memory(name_ptr, name_size)
+                            .unwrap()
+                            .as_ref(),
+                    )
+                    .unwrap(),
+                );
+
+                match result {
+                    Ok(value) => self
+                        .inner
+                        .alloc_write_and_return_pointer_size(host_fn.name(), iter::once(value)),
+                    Err(error) => HostVm::Error {
+                        error: Error::Sandbox {
+                            function: host_fn.name(),
+                            error,
+                        },
+                        prototype: self.inner.into_prototype(),
+                    },
+                }
+            }
+            HostFunction::ext_trie_blake2_256_root_version_1
+            | HostFunction::ext_trie_blake2_256_root_version_2 => {
+                let state_version =
+                    if matches!(host_fn, HostFunction::ext_trie_blake2_256_root_version_2) {
+                        expect_state_version!(1)
+                    } else {
+                        trie::TrieEntryVersion::V0
+                    };
+
+                let result = {
+                    let input = expect_pointer_size!(0);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(nom::combinator::flat_map(
+                            crate::util::nom_scale_compact_usize,
+                            |num_elems| {
+                                nom::multi::many_m_n(
+                                    num_elems,
+                                    num_elems,
+                                    nom::sequence::tuple((
+                                        nom::combinator::flat_map(
+                                            crate::util::nom_scale_compact_usize,
+                                            nom::bytes::complete::take,
                                         ),
                                         nom::combinator::flat_map(
                                             crate::util::nom_scale_compact_usize,
@@ -1952,7 +2743,8 @@ impl ExternalStorageGet {
         };
 
         match host_fn {
-            HostFunction::ext_storage_get_version_1 => {
+            HostFunction::ext_storage_get_version_1
+            | HostFunction::ext_default_child_storage_get_version_1 => {
                 if let Some((value, value_total_len)) = value {
                     // Writing `Some(value)`.
                     debug_assert_eq!(
@@ -1973,7 +2765,8 @@ impl ExternalStorageGet {
                         .alloc_write_and_return_pointer_size(host_fn.name(), iter::once(&[0]))
                 }
             }
-            HostFunction::ext_storage_read_version_1 => {
+            HostFunction::ext_storage_read_version_1
+            | HostFunction::ext_default_child_storage_read_version_1 => {
                 let outcome = if let Some((value, value_total_len)) = value {
                     let mut remaining_max_allowed = usize::try_from(self.max_size).unwrap();
                     let mut offset = self.value_out_ptr.unwrap();
@@ -2004,14 +2797,17 @@ impl ExternalStorageGet {
                     },
                 );
             }
-            HostFunction::ext_storage_exists_version_1 => HostVm::ReadyToRun(ReadyToRun {
-                inner: self.inner,
-                resume_value: Some(if value.is_some() {
-                    vm::WasmValue::I32(1)
-                } else {
-                    vm::WasmValue::I32(0)
-                }),
-            }),
+            HostFunction::ext_storage_exists_version_1
+            | HostFunction::ext_default_child_storage_exists_version_1 => {
+                HostVm::ReadyToRun(ReadyToRun {
+                    inner: self.inner,
+                    resume_value: Some(if value.is_some() {
+                        vm::WasmValue::I32(1)
+                    } else {
+                        vm::WasmValue::I32(0)
+                    }),
+                })
+            }
             _ => unreachable!(),
         }
     }
@@ -2265,302 +3061,1518 @@ impl fmt::Debug for ExternalStorageNextKey {
     }
 }
 
-/// Must verify whether a signature is correct.
-pub struct SignatureVerification {
-    inner: Inner,
-    /// Which cryptographic algorithm.
-    algorithm: SignatureVerificationAlgorithm,
-    /// Pointer to the signature. The size of the signature depends on the algorithm. Guaranteed
-    /// to be in range.
-    signature_ptr: u32,
-    /// Pointer to the public key. The size of the public key depends on the algorithm. Guaranteed
-    /// to be in range.
-    public_key_ptr: u32,
-    /// Pointer to the message. Guaranteed to be in range.
-    message_ptr: u32,
-    /// Size of the message. Guaranteed to be in range.
-    message_size: u32,
-}
-
-enum SignatureVerificationAlgorithm {
-    Ed25519,
-    Sr25519V1,
-    Sr25519V2,
-    Ecdsa,
-    EcdsaPrehashed,
+/// Must provide the value of an entry of a child trie.
+///
+/// Identical to [`ExternalStorageGet`], except that the value must be read from the child trie
+/// indicated by [`ExternalChildStorageGet::child_trie`].
+pub struct ExternalChildStorageGet {
+    inner: ExternalStorageGet,
+
+    /// Pointer to the identifier of the child trie. Guaranteed to be in range.
+    child_trie_ptr: u32,
+    /// Size of the identifier of the child trie. Guaranteed to be in range.
+    child_trie_size: u32,
 }
 
-impl SignatureVerification {
-    /// Returns the message that the signature is expected to sign.
-    pub fn message(&'_ self) -> impl AsRef<[u8]> + '_ {
+impl ExternalChildStorageGet {
+    /// Returns the identifier of the child trie whose value must be loaded.
+    ///
+    /// This identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> impl AsRef<[u8]> + '_ {
         self.inner
+            .inner
             .vm
-            .read_memory(self.message_ptr, self.message_size)
+            .read_memory(self.child_trie_ptr, self.child_trie_size)
             .unwrap()
     }
 
-    /// Returns the signature.
+    /// Returns the key whose value must be provided back with [`ExternalChildStorageGet::resume`].
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner.key()
+    }
+
+    /// Offset within the value that is requested.
+    pub fn offset(&self) -> u32 {
+        self.inner.offset()
+    }
+
+    /// Maximum size of the value to pass back.
     ///
-    /// > **Note**: Be aware that this signature is untrusted input and might not be part of the
-    /// >           set of valid signatures.
-    pub fn signature(&'_ self) -> impl AsRef<[u8]> + '_ {
-        let signature_size = match self.algorithm {
-            SignatureVerificationAlgorithm::Ed25519 => 64,
-            SignatureVerificationAlgorithm::Sr25519V1 => 64,
-            SignatureVerificationAlgorithm::Sr25519V2 => 64,
-            SignatureVerificationAlgorithm::Ecdsa => 65,
-            SignatureVerificationAlgorithm::EcdsaPrehashed => 65,
-        };
+    /// > **Note**: This can be 0 if we only want to know whether a value exists.
+    pub fn max_size(&self) -> u32 {
+        self.inner.max_size()
+    }
+
+    /// See [`ExternalStorageGet::resume_full_value`].
+    pub fn resume_full_value(self, value: Option<&[u8]>) -> HostVm {
+        self.inner.resume_full_value(value)
+    }
+
+    /// See [`ExternalStorageGet::resume`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the value is longer than what [`ExternalChildStorageGet::max_size`] returns.
+    ///
+    pub fn resume(self, value: Option<(&[u8], usize)>) -> HostVm {
+        self.inner.resume(value)
+    }
+
+    /// See [`ExternalStorageGet::resume_vectored`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the value is longer than what [`ExternalChildStorageGet::max_size`] returns.
+    ///
+    pub fn resume_vectored(
+        self,
+        value: Option<(impl Iterator<Item = impl AsRef<[u8]>> + Clone, usize)>,
+    ) -> HostVm {
+        self.inner.resume_vectored(value)
+    }
+}
+
+impl fmt::Debug for ExternalChildStorageGet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ExternalChildStorageGet").finish()
+    }
+}
+
+/// Must set the value of an entry of a child trie.
+pub struct ExternalChildStorageSet {
+    inner: Inner,
+
+    /// Pointer to the identifier of the child trie. Guaranteed to be in range.
+    child_trie_ptr: u32,
+    /// Size of the identifier of the child trie. Guaranteed to be in range.
+    child_trie_size: u32,
+
+    /// Pointer to the key whose value must be set. Guaranteed to be in range.
+    key_ptr: u32,
+    /// Size of the key whose value must be set. Guaranteed to be in range.
+    key_size: u32,
+
+    /// Pointer and size of the value to set. `None` for clearing. Guaranteed to be in range.
+    value: Option<(u32, u32)>,
+}
 
+impl ExternalChildStorageSet {
+    /// Returns the identifier of the child trie whose value must be set.
+    ///
+    /// This identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> impl AsRef<[u8]> + '_ {
         self.inner
             .vm
-            .read_memory(self.signature_ptr, signature_size)
+            .read_memory(self.child_trie_ptr, self.child_trie_size)
             .unwrap()
     }
 
-    /// Returns the public key the signature is against.
+    /// Returns the key whose value must be set.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.key_ptr, self.key_size)
+            .unwrap()
+    }
+
+    /// Returns the value to set.
     ///
-    /// > **Note**: Be aware that this public key is untrusted input and might not be part of the
-    /// >           set of valid public keys.
-    pub fn public_key(&'_ self) -> impl AsRef<[u8]> + '_ {
-        let public_key_size = match self.algorithm {
-            SignatureVerificationAlgorithm::Ed25519 => 32,
-            SignatureVerificationAlgorithm::Sr25519V1 => 32,
-            SignatureVerificationAlgorithm::Sr25519V2 => 32,
-            SignatureVerificationAlgorithm::Ecdsa => 33,
-            SignatureVerificationAlgorithm::EcdsaPrehashed => 33,
-        };
+    /// If `None` is returned, the key should be removed from the child trie entirely.
+    pub fn value(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        if let Some((ptr, size)) = self.value {
+            Some(self.inner.vm.read_memory(ptr, size).unwrap())
+        } else {
+            None
+        }
+    }
+
+    /// Resumes execution after having set the value.
+    pub fn resume(self) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: None,
+        })
+    }
+}
+
+impl fmt::Debug for ExternalChildStorageSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ExternalChildStorageSet").finish()
+    }
+}
+
+/// Must remove from a child trie the keys which start with a certain prefix. Use
+/// [`ExternalChildStorageClearPrefix::max_keys_to_remove`] to determine the maximum number of
+/// keys to remove.
+///
+/// This is also used to remove a child trie entirely, in which case the prefix is empty.
+pub struct ExternalChildStorageClearPrefix {
+    inner: Inner,
+
+    /// Function currently being called by the Wasm code. Refers to an index within
+    /// [`Inner::registered_functions`]. Guaranteed to be [`FunctionImport::Resolved`̀].
+    calling: usize,
 
+    /// Pointer to the identifier of the child trie. Guaranteed to be in range.
+    child_trie_ptr: u32,
+    /// Size of the identifier of the child trie. Guaranteed to be in range.
+    child_trie_size: u32,
+
+    /// Pointer and size of the prefix to remove. `None` if the entire child trie must be
+    /// removed. Guaranteed to be in range.
+    prefix: Option<(u32, u32)>,
+
+    /// Maximum number of keys to remove.
+    max_keys_to_remove: Option<u32>,
+}
+
+impl ExternalChildStorageClearPrefix {
+    /// Returns the identifier of the child trie whose keys must be removed.
+    ///
+    /// This identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> impl AsRef<[u8]> + '_ {
         self.inner
             .vm
-            .read_memory(self.public_key_ptr, public_key_size)
+            .read_memory(self.child_trie_ptr, self.child_trie_size)
             .unwrap()
     }
 
-    /// Verify the signature. Returns `true` if it is valid.
-    pub fn is_valid(&self) -> bool {
-        match self.algorithm {
-            SignatureVerificationAlgorithm::Ed25519 => {
-                let public_key =
-                    ed25519_zebra::VerificationKey::try_from(self.public_key().as_ref());
+    /// Returns the prefix whose keys must be removed.
+    pub fn prefix(&'_ self) -> impl AsRef<[u8]> + '_ {
+        if let Some((ptr, size)) = self.prefix {
+            either::Left(self.inner.vm.read_memory(ptr, size).unwrap())
+        } else {
+            either::Right(&[][..])
+        }
+    }
 
-                if let Ok(public_key) = public_key {
-                    let signature = ed25519_zebra::Signature::from(
-                        <[u8; 64]>::try_from(self.signature().as_ref()).unwrap(),
-                    );
-                    public_key
-                        .verify(&signature, self.message().as_ref())
-                        .is_ok()
-                } else {
-                    false
-                }
-            }
-            SignatureVerificationAlgorithm::Sr25519V1 => {
-                schnorrkel::PublicKey::from_bytes(&self.public_key().as_ref()).map_or(false, |pk| {
-                    pk.verify_simple_preaudit_deprecated(
-                        b"substrate",
-                        self.message().as_ref(),
-                        self.signature().as_ref(),
-                    )
-                    .is_ok()
+    /// Returns the maximum number of keys to remove. `None` means "infinity".
+    pub fn max_keys_to_remove(&self) -> Option<u32> {
+        self.max_keys_to_remove
+    }
+
+    /// Resumes execution after having cleared the values.
+    ///
+    /// Must be passed how many keys have been cleared, and whether some keys remaining to be
+    /// cleared.
+    pub fn resume(self, num_cleared: u32, some_keys_remain: bool) -> HostVm {
+        let host_fn = match self.inner.registered_functions[self.calling] {
+            FunctionImport::Resolved(f) => f,
+            FunctionImport::Unresolved { .. } => unreachable!(),
+        };
+
+        match host_fn {
+            HostFunction::ext_default_child_storage_storage_kill_version_1
+            | HostFunction::ext_default_child_storage_clear_prefix_version_1 => {
+                HostVm::ReadyToRun(ReadyToRun {
+                    inner: self.inner,
+                    resume_value: None,
                 })
             }
-            SignatureVerificationAlgorithm::Sr25519V2 => {
-                schnorrkel::PublicKey::from_bytes(&self.public_key().as_ref()).map_or(false, |pk| {
-                    pk.verify_simple(
-                        b"substrate",
-                        self.message().as_ref(),
-                        &schnorrkel::Signature::from_bytes(self.signature().as_ref()).unwrap(),
-                    )
-                    .is_ok()
+            HostFunction::ext_default_child_storage_storage_kill_version_2 => {
+                HostVm::ReadyToRun(ReadyToRun {
+                    inner: self.inner,
+                    resume_value: Some(if some_keys_remain {
+                        vm::WasmValue::I32(0)
+                    } else {
+                        vm::WasmValue::I32(1)
+                    }),
                 })
             }
-            SignatureVerificationAlgorithm::Ecdsa => {
-                // NOTE: safe to unwrap here because we supply the nn to blake2b fn
-                let data = <[u8; 32]>::try_from(
-                    blake2_rfc::blake2b::blake2b(32, &[], self.message().as_ref()).as_bytes(),
+            HostFunction::ext_default_child_storage_storage_kill_version_3
+            | HostFunction::ext_default_child_storage_clear_prefix_version_2 => {
+                self.inner.alloc_write_and_return_pointer_size(
+                    host_fn.name(),
+                    [
+                        either::Left(if some_keys_remain { [1u8] } else { [0u8] }),
+                        either::Right(num_cleared.to_le_bytes()),
+                    ]
+                    .into_iter(),
                 )
-                .unwrap();
-                let message = libsecp256k1::Message::parse(&data);
-
-                // signature (64 bytes) + recovery ID (1 byte)
-                let sig_bytes = self.signature();
-                libsecp256k1::Signature::parse_standard_slice(&sig_bytes.as_ref()[..64])
-                    .and_then(|sig| {
-                        libsecp256k1::RecoveryId::parse(sig_bytes.as_ref()[64])
-                            .and_then(|ri| libsecp256k1::recover(&message, &sig, &ri))
-                    })
-                    .map_or(false, |actual| {
-                        self.public_key().as_ref()[..] == actual.serialize_compressed()[..]
-                    })
-            }
-            SignatureVerificationAlgorithm::EcdsaPrehashed => {
-                // We can safely unwrap, as the size is checked when the `SignatureVerification`
-                // is constructed.
-                let message = libsecp256k1::Message::parse(
-                    &<[u8; 32]>::try_from(self.message().as_ref()).unwrap(),
-                );
-
-                // signature (64 bytes) + recovery ID (1 byte)
-                let sig_bytes = self.signature();
-                if let Ok(sig) =
-                    libsecp256k1::Signature::parse_standard_slice(&sig_bytes.as_ref()[..64])
-                {
-                    if let Ok(ri) = libsecp256k1::RecoveryId::parse(sig_bytes.as_ref()[64]) {
-                        if let Ok(actual) = libsecp256k1::recover(&message, &sig, &ri) {
-                            self.public_key().as_ref()[..] == actual.serialize_compressed()[..]
-                        } else {
-                            false
-                        }
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                }
             }
+            _ => unreachable!(),
         }
     }
+}
+
+impl fmt::Debug for ExternalChildStorageClearPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ExternalChildStorageClearPrefix").finish()
+    }
+}
+
+/// Must provide the trie root hash of a child trie.
+pub struct ExternalChildStorageRoot {
+    inner: Inner,
+
+    /// Function currently being called by the Wasm code. Refers to an index within
+    /// [`Inner::registered_functions`]. Guaranteed to be [`FunctionImport::Resolved`̀].
+    calling: usize,
+
+    /// Pointer to the identifier of the child trie. Guaranteed to be in range.
+    child_trie_ptr: u32,
+    /// Size of the identifier of the child trie. Guaranteed to be in range.
+    child_trie_size: u32,
+}
+
+impl ExternalChildStorageRoot {
+    /// Returns the identifier of the child trie whose root hash must be provided.
+    ///
+    /// This identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.child_trie_ptr, self.child_trie_size)
+            .unwrap()
+    }
+
+    /// Writes the trie root hash to the Wasm VM and prepares it for resume.
+    pub fn resume(self, hash: &[u8; 32]) -> HostVm {
+        let host_fn = match self.inner.registered_functions[self.calling] {
+            FunctionImport::Resolved(f) => f,
+            FunctionImport::Unresolved { .. } => unreachable!(),
+        };
+
+        self.inner
+            .alloc_write_and_return_pointer_size(host_fn.name(), iter::once(hash))
+    }
+}
+
+impl fmt::Debug for ExternalChildStorageRoot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ExternalChildStorageRoot").finish()
+    }
+}
+
+/// Must provide the key of a child trie that follows, in lexicographic order, a specific one.
+pub struct ExternalChildStorageNextKey {
+    inner: Inner,
+
+    /// Pointer to the identifier of the child trie. Guaranteed to be in range.
+    child_trie_ptr: u32,
+    /// Size of the identifier of the child trie. Guaranteed to be in range.
+    child_trie_size: u32,
+
+    /// Pointer to the key whose follow-up must be found. Guaranteed to be in range.
+    key_ptr: u32,
+    /// Size of the key whose follow-up must be found. Guaranteed to be in range.
+    key_size: u32,
+}
+
+impl ExternalChildStorageNextKey {
+    /// Returns the identifier of the child trie in which to search.
+    ///
+    /// This identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.child_trie_ptr, self.child_trie_size)
+            .unwrap()
+    }
+
+    /// Returns the key whose following key must be returned.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.key_ptr, self.key_size)
+            .unwrap()
+    }
+
+    /// Writes the follow-up key in the Wasm VM memory and prepares it for execution.
+    ///
+    /// Must be passed `None` if the key is the last one in the child trie.
+    pub fn resume(self, follow_up: Option<&[u8]>) -> HostVm {
+        if let Some(follow_up) = follow_up {
+            let value_len_enc = util::encode_scale_compact_usize(follow_up.len());
+            self.inner.alloc_write_and_return_pointer_size(
+                HostFunction::ext_default_child_storage_next_key_version_1.name(),
+                iter::once(&[1][..])
+                    .chain(iter::once(value_len_enc.as_ref()))
+                    .chain(iter::once(follow_up)),
+            )
+        } else {
+            // Write a SCALE-encoded `None`.
+            self.inner.alloc_write_and_return_pointer_size(
+                HostFunction::ext_default_child_storage_next_key_version_1.name(),
+                iter::once(&[0]),
+            )
+        }
+    }
+}
+
+impl fmt::Debug for ExternalChildStorageNextKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ExternalChildStorageNextKey").finish()
+    }
+}
+
+/// Must generate a new ECDSA key pair and store it in the keystore.
+pub struct CryptoEcdsaGenerate {
+    inner: Inner,
+
+    /// Identifier of the type of key, such as `beef` or `imon`.
+    key_type_id: [u8; 4],
+
+    /// Seed phrase to derive the key from, if any.
+    seed: Option<String>,
+}
+
+impl CryptoEcdsaGenerate {
+    /// Returns the identifier of the type of key to generate, such as `beef` for BEEFY.
+    pub fn key_type_id(&self) -> &[u8; 4] {
+        &self.key_type_id
+    }
+
+    /// Returns the seed phrase that the private key must be derived from.
+    ///
+    /// If `None`, the private key must be randomly generated.
+    pub fn seed(&self) -> Option<&str> {
+        self.seed.as_deref()
+    }
+
+    /// Writes the compressed public key of the newly-generated key pair in the Wasm VM's memory
+    /// and prepares the virtual machine to resume execution.
+    pub fn resume(self, public_key: &[u8; 33]) -> HostVm {
+        self.inner.alloc_write_and_return_pointer(
+            HostFunction::ext_crypto_ecdsa_generate_version_1.name(),
+            iter::once(public_key),
+        )
+    }
+}
+
+impl fmt::Debug for CryptoEcdsaGenerate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CryptoEcdsaGenerate")
+            .field(&self.key_type_id)
+            .finish()
+    }
+}
+
+/// Must provide the list of ECDSA public keys found in the keystore.
+pub struct CryptoEcdsaPublicKeys {
+    inner: Inner,
+
+    /// Identifier of the type of key, such as `beef` or `imon`.
+    key_type_id: [u8; 4],
+}
+
+impl CryptoEcdsaPublicKeys {
+    /// Returns the identifier of the type of key whose public keys must be provided, such as
+    /// `beef` for BEEFY.
+    pub fn key_type_id(&self) -> &[u8; 4] {
+        &self.key_type_id
+    }
+
+    /// Writes the list of compressed public keys in the Wasm VM's memory and prepares the
+    /// virtual machine to resume execution.
+    pub fn resume(self, public_keys: impl ExactSizeIterator<Item = [u8; 33]>) -> HostVm {
+        let mut encoded = util::encode_scale_compact_usize(public_keys.len())
+            .as_ref()
+            .to_vec();
+        for public_key in public_keys {
+            encoded.extend_from_slice(&public_key);
+        }
+
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_crypto_ecdsa_public_keys_version_1.name(),
+            iter::once(encoded),
+        )
+    }
+}
+
+impl fmt::Debug for CryptoEcdsaPublicKeys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CryptoEcdsaPublicKeys")
+            .field(&self.key_type_id)
+            .finish()
+    }
+}
+
+/// Must verify whether a signature is correct.
+pub struct SignatureVerification {
+    inner: Inner,
+    /// Which cryptographic algorithm.
+    algorithm: SignatureVerificationAlgorithm,
+    /// Pointer to the signature. The size of the signature depends on the algorithm. Guaranteed
+    /// to be in range.
+    signature_ptr: u32,
+    /// Pointer to the public key. The size of the public key depends on the algorithm. Guaranteed
+    /// to be in range.
+    public_key_ptr: u32,
+    /// Pointer to the message. Guaranteed to be in range.
+    message_ptr: u32,
+    /// Size of the message. Guaranteed to be in range.
+    message_size: u32,
+}
+
+enum SignatureVerificationAlgorithm {
+    Ed25519,
+    Sr25519V1,
+    Sr25519V2,
+    Ecdsa,
+    EcdsaPrehashed,
+}
+
+impl SignatureVerification {
+    /// Returns the message that the signature is expected to sign.
+    pub fn message(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.message_ptr, self.message_size)
+            .unwrap()
+    }
+
+    /// Returns the signature.
+    ///
+    /// > **Note**: Be aware that this signature is untrusted input and might not be part of the
+    /// >           set of valid signatures.
+    pub fn signature(&'_ self) -> impl AsRef<[u8]> + '_ {
+        let signature_size = match self.algorithm {
+            SignatureVerificationAlgorithm::Ed25519 => 64,
+            SignatureVerificationAlgorithm::Sr25519V1 => 64,
+            SignatureVerificationAlgorithm::Sr25519V2 => 64,
+            SignatureVerificationAlgorithm::Ecdsa => 65,
+            SignatureVerificationAlgorithm::EcdsaPrehashed => 65,
+        };
+
+        self.inner
+            .vm
+            .read_memory(self.signature_ptr, signature_size)
+            .unwrap()
+    }
+
+    /// Returns the public key the signature is against.
+    ///
+    /// > **Note**: Be aware that this public key is untrusted input and might not be part of the
+    /// >           set of valid public keys.
+    pub fn public_key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        let public_key_size = match self.algorithm {
+            SignatureVerificationAlgorithm::Ed25519 => 32,
+            SignatureVerificationAlgorithm::Sr25519V1 => 32,
+            SignatureVerificationAlgorithm::Sr25519V2 => 32,
+            SignatureVerificationAlgorithm::Ecdsa => 33,
+            SignatureVerificationAlgorithm::EcdsaPrehashed => 33,
+        };
+
+        self.inner
+            .vm
+            .read_memory(self.public_key_ptr, public_key_size)
+            .unwrap()
+    }
+
+    /// Verify the signature. Returns `true` if it is valid.
+    pub fn is_valid(&self) -> bool {
+        match self.algorithm {
+            SignatureVerificationAlgorithm::Ed25519 => {
+                let public_key =
+                    ed25519_zebra::VerificationKey::try_from(self.public_key().as_ref());
+
+                if let Ok(public_key) = public_key {
+                    let signature = ed25519_zebra::Signature::from(
+                        <[u8; 64]>::try_from(self.signature().as_ref()).unwrap(),
+                    );
+                    public_key
+                        .verify(&signature, self.message().as_ref())
+                        .is_ok()
+                } else {
+                    false
+                }
+            }
+            SignatureVerificationAlgorithm::Sr25519V1 => {
+                schnorrkel::PublicKey::from_bytes(&self.public_key().as_ref()).map_or(false, |pk| {
+                    pk.verify_simple_preaudit_deprecated(
+                        b"substrate",
+                        self.message().as_ref(),
+                        self.signature().as_ref(),
+                    )
+                    .is_ok()
+                })
+            }
+            SignatureVerificationAlgorithm::Sr25519V2 => {
+                schnorrkel::PublicKey::from_bytes(&self.public_key().as_ref()).map_or(false, |pk| {
+                    pk.verify_simple(
+                        b"substrate",
+                        self.message().as_ref(),
+                        &schnorrkel::Signature::from_bytes(self.signature().as_ref()).unwrap(),
+                    )
+                    .is_ok()
+                })
+            }
+            SignatureVerificationAlgorithm::Ecdsa => {
+                // NOTE: safe to unwrap here because we supply the nn to blake2b fn
+                let data = <[u8; 32]>::try_from(
+                    blake2_rfc::blake2b::blake2b(32, &[], self.message().as_ref()).as_bytes(),
+                )
+                .unwrap();
+                let message = libsecp256k1::Message::parse(&data);
+
+                // signature (64 bytes) + recovery ID (1 byte)
+                let sig_bytes = self.signature();
+                libsecp256k1::Signature::parse_standard_slice(&sig_bytes.as_ref()[..64])
+                    .and_then(|sig| {
+                        libsecp256k1::RecoveryId::parse(sig_bytes.as_ref()[64])
+                            .and_then(|ri| libsecp256k1::recover(&message, &sig, &ri))
+                    })
+                    .map_or(false, |actual| {
+                        self.public_key().as_ref()[..] == actual.serialize_compressed()[..]
+                    })
+            }
+            SignatureVerificationAlgorithm::EcdsaPrehashed => {
+                // We can safely unwrap, as the size is checked when the `SignatureVerification`
+                // is constructed.
+                let message = libsecp256k1::Message::parse(
+                    &<[u8; 32]>::try_from(self.message().as_ref()).unwrap(),
+                );
+
+                // signature (64 bytes) + recovery ID (1 byte)
+                let sig_bytes = self.signature();
+                if let Ok(sig) =
+                    libsecp256k1::Signature::parse_standard_slice(&sig_bytes.as_ref()[..64])
+                {
+                    if let Ok(ri) = libsecp256k1::RecoveryId::parse(sig_bytes.as_ref()[64]) {
+                        if let Ok(actual) = libsecp256k1::recover(&message, &sig, &ri) {
+                            self.public_key().as_ref()[..] == actual.serialize_compressed()[..]
+                        } else {
+                            false
+                        }
+                    } else {
+                        false
+                    }
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    /// Verify the signature and resume execution.
+    pub fn verify_and_resume(self) -> HostVm {
+        let success = self.is_valid();
+        self.resume(success)
+    }
+
+    /// Resume the execution assuming that the signature is valid.
+    ///
+    /// > **Note**: You are strongly encouraged to call
+    /// >           [`SignatureVerification::verify_and_resume`]. This function is meant to be
+    /// >           used only in debugging situations.
+    pub fn resume_success(self) -> HostVm {
+        self.resume(true)
+    }
+
+    /// Resume the execution assuming that the signature is invalid.
+    ///
+    /// > **Note**: You are strongly encouraged to call
+    /// >           [`SignatureVerification::verify_and_resume`]. This function is meant to be
+    /// >           used only in debugging situations.
+    pub fn resume_failed(self) -> HostVm {
+        self.resume(false)
+    }
+
+    fn resume(self, success: bool) -> HostVm {
+        // All signature-related host functions work the same way in terms of return value.
+        HostVm::ReadyToRun(ReadyToRun {
+            resume_value: Some(vm::WasmValue::I32(if success { 1 } else { 0 })),
+            inner: self.inner,
+        })
+    }
+}
+
+impl fmt::Debug for SignatureVerification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SignatureVerification")
+            .field("message", &self.message().as_ref())
+            .field("signature", &self.signature().as_ref())
+            .field("public_key", &self.public_key().as_ref())
+            .finish()
+    }
+}
+
+/// Must provide the runtime version obtained by calling the `Core_version` entry point of a Wasm
+/// blob.
+pub struct CallRuntimeVersion {
+    inner: Inner,
+
+    /// Pointer to the wasm code whose runtime version must be provided. Guaranteed to be in range.
+    wasm_blob_ptr: u32,
+    /// Size of the wasm code whose runtime version must be provided. Guaranteed to be in range.
+    wasm_blob_size: u32,
+}
+
+impl CallRuntimeVersion {
+    /// Returns the Wasm code whose runtime version must be provided.
+    pub fn wasm_code(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.wasm_blob_ptr, self.wasm_blob_size)
+            .unwrap()
+    }
+
+    /// Writes the SCALE-encoded runtime version to the memory and prepares for execution.
+    ///
+    /// If an error happened during the execution (such as an invalid Wasm binary code), pass
+    /// an `Err`.
+    pub fn resume(self, scale_encoded_runtime_version: Result<&[u8], ()>) -> HostVm {
+        if let Ok(scale_encoded_runtime_version) = scale_encoded_runtime_version {
+            self.inner.alloc_write_and_return_pointer_size(
+                HostFunction::ext_misc_runtime_version_version_1.name(),
+                iter::once(either::Left([1]))
+                    .chain(iter::once(either::Right(either::Left(
+                        util::encode_scale_compact_usize(scale_encoded_runtime_version.len()),
+                    ))))
+                    .chain(iter::once(either::Right(either::Right(
+                        scale_encoded_runtime_version,
+                    )))),
+            )
+        } else {
+            self.inner.alloc_write_and_return_pointer_size(
+                HostFunction::ext_misc_runtime_version_version_1.name(),
+                iter::once([0]),
+            )
+        }
+    }
+}
+
+impl fmt::Debug for CallRuntimeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CallRuntimeVersion").finish()
+    }
+}
+
+/// Must set the value of the off-chain storage.
+pub struct ExternalOffchainStorageSet {
+    inner: Inner,
+
+    /// Pointer to the key whose value must be set. Guaranteed to be in range.
+    key_ptr: u32,
+    /// Size of the key whose value must be set. Guaranteed to be in range.
+    key_size: u32,
+
+    /// Pointer and size of the value to set. `None` for clearing. Guaranteed to be in range.
+    value: Option<(u32, u32)>,
+}
+
+impl ExternalOffchainStorageSet {
+    /// Returns the key whose value must be set.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.key_ptr, self.key_size)
+            .unwrap()
+    }
+
+    /// Returns the value to set.
+    ///
+    /// If `None` is returned, the key should be removed from the storage entirely.
+    pub fn value(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        if let Some((ptr, size)) = self.value {
+            Some(self.inner.vm.read_memory(ptr, size).unwrap())
+        } else {
+            None
+        }
+    }
+
+    /// Resumes execution after having set the value.
+    pub fn resume(self) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: None,
+        })
+    }
+}
+
+impl fmt::Debug for ExternalOffchainStorageSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ExternalOffchainStorageSet").finish()
+    }
+}
+
+/// Kind of off-chain local storage that an off-chain worker accesses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OffchainStorageKind {
+    /// Storage that is persisted across restarts of the node, and that is shared with the
+    /// off-chain indexing (see [`HostVm::ExternalOffchainStorageSet`]).
+    Persistent,
+    /// Storage that is local to the node and that isn't necessarily persisted.
+    Local,
+}
+
+/// Error that can happen when performing an HTTP request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OffchainHttpError {
+    /// The deadline has been reached before the operation could be completed.
+    DeadlineReached,
+    /// An I/O error happened while processing the request. The request is now considered as
+    /// destroyed.
+    IoError,
+    /// The request identifier is invalid in this context.
+    Invalid,
+}
+
+impl OffchainHttpError {
+    fn scale_encoding(&self) -> u8 {
+        match self {
+            OffchainHttpError::DeadlineReached => 1,
+            OffchainHttpError::IoError => 2,
+            OffchainHttpError::Invalid => 3,
+        }
+    }
+}
+
+/// Status of an HTTP request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OffchainHttpRequestStatus {
+    /// The deadline has been reached while waiting for this request to finish.
+    DeadlineReached,
+    /// An I/O error happened while processing the request. The request is now considered as
+    /// destroyed.
+    IoError,
+    /// The request identifier is invalid in this context.
+    Invalid,
+    /// The request has finished with the given HTTP status code.
+    Finished(u16),
+}
+
+/// Must indicate whether the local node is a validator.
+pub struct OffchainIsValidator {
+    inner: Inner,
+}
+
+impl OffchainIsValidator {
+    /// Resumes execution after indicating whether the local node is a validator.
+    pub fn resume(self, is_validator: bool) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: Some(vm::WasmValue::I32(if is_validator { 1 } else { 0 })),
+        })
+    }
+}
+
+impl fmt::Debug for OffchainIsValidator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainIsValidator").finish()
+    }
+}
+
+/// Must submit a transaction to the transactions pool.
+pub struct OffchainSubmitTransaction {
+    inner: Inner,
+
+    /// Pointer to the SCALE-encoded transaction. Guaranteed to be in range.
+    tx_ptr: u32,
+    /// Size of the SCALE-encoded transaction. Guaranteed to be in range.
+    tx_size: u32,
+}
+
+impl OffchainSubmitTransaction {
+    /// Returns the SCALE-encoded transaction to submit.
+    pub fn transaction(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.tx_ptr, self.tx_size)
+            .unwrap()
+    }
+
+    /// Resumes execution after having tried to submit the transaction.
+    ///
+    /// `success` must be `false` if the transaction couldn't be submitted.
+    pub fn resume(self, success: bool) -> HostVm {
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_submit_transaction_version_1.name(),
+            iter::once(if success { &[0] } else { &[1] }),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainSubmitTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainSubmitTransaction").finish()
+    }
+}
+
+/// Must provide the network state of the local node.
+pub struct OffchainNetworkState {
+    inner: Inner,
+}
+
+impl OffchainNetworkState {
+    /// Resumes execution after having obtained the network state.
+    ///
+    /// If `Ok`, must contain the encoded `PeerId` of the local node and the list of the
+    /// encoded multiaddresses it is reachable at.
+    pub fn resume(
+        self,
+        state: Result<(&[u8], impl ExactSizeIterator<Item = impl AsRef<[u8]>>), ()>,
+    ) -> HostVm {
+        let mut encoded = Vec::new();
+        match state {
+            Ok((peer_id, addresses)) => {
+                encoded.push(0);
+                encoded.extend_from_slice(util::encode_scale_compact_usize(peer_id.len()).as_ref());
+                encoded.extend_from_slice(peer_id);
+                encoded
+                    .extend_from_slice(util::encode_scale_compact_usize(addresses.len()).as_ref());
+                for address in addresses {
+                    let address = address.as_ref();
+                    encoded.extend_from_slice(
+                        util::encode_scale_compact_usize(address.len()).as_ref(),
+                    );
+                    encoded.extend_from_slice(address);
+                }
+            }
+            Err(()) => encoded.push(1),
+        }
+
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_network_state_version_1.name(),
+            iter::once(&encoded),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainNetworkState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainNetworkState").finish()
+    }
+}
+
+/// Must provide the current UNIX timestamp.
+pub struct OffchainTimestamp {
+    inner: Inner,
+}
+
+impl OffchainTimestamp {
+    /// Resumes execution after having obtained the current UNIX timestamp, in milliseconds.
+    pub fn resume(self, timestamp_ms: u64) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: Some(vm::WasmValue::I64(i64::from_ne_bytes(
+                timestamp_ms.to_ne_bytes(),
+            ))),
+        })
+    }
+}
+
+impl fmt::Debug for OffchainTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainTimestamp").finish()
+    }
+}
+
+/// Must pause execution until a certain deadline.
+pub struct OffchainSleepUntil {
+    inner: Inner,
+    deadline: u64,
+}
+
+impl OffchainSleepUntil {
+    /// Returns the UNIX timestamp, in milliseconds, until which execution must be paused.
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    /// Resumes execution after the deadline has been reached.
+    pub fn resume(self) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: None,
+        })
+    }
+}
+
+impl fmt::Debug for OffchainSleepUntil {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainSleepUntil")
+            .field(&self.deadline)
+            .finish()
+    }
+}
+
+/// Must provide a randomly-generated seed.
+pub struct OffchainRandomSeed {
+    inner: Inner,
+}
+
+impl OffchainRandomSeed {
+    /// Resumes execution after having generated a seed.
+    ///
+    /// The seed should be generated using a cryptographically-secure source of randomness.
+    pub fn resume(self, seed: &[u8; 32]) -> HostVm {
+        self.inner.alloc_write_and_return_pointer(
+            HostFunction::ext_offchain_random_seed_version_1.name(),
+            iter::once(seed),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainRandomSeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainRandomSeed").finish()
+    }
+}
+
+/// Must load a value from the off-chain local storage.
+pub struct OffchainLocalStorageGet {
+    inner: Inner,
+    kind: OffchainStorageKind,
+
+    /// Pointer to the key whose value must be loaded. Guaranteed to be in range.
+    key_ptr: u32,
+    /// Size of the key whose value must be loaded. Guaranteed to be in range.
+    key_size: u32,
+}
+
+impl OffchainLocalStorageGet {
+    /// Returns the kind of storage to load the value from.
+    pub fn kind(&self) -> OffchainStorageKind {
+        self.kind
+    }
+
+    /// Returns the key whose value must be provided back with
+    /// [`OffchainLocalStorageGet::resume`].
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.key_ptr, self.key_size)
+            .unwrap()
+    }
+
+    /// Writes the value in the Wasm VM's memory and prepares the virtual machine to resume
+    /// execution.
+    pub fn resume(self, value: Option<&[u8]>) -> HostVm {
+        let function_name = HostFunction::ext_offchain_local_storage_get_version_1.name();
+        if let Some(value) = value {
+            let value_len_enc = util::encode_scale_compact_usize(value.len());
+            self.inner.alloc_write_and_return_pointer_size(
+                function_name,
+                iter::once(&[1][..])
+                    .chain(iter::once(value_len_enc.as_ref()))
+                    .chain(iter::once(value)),
+            )
+        } else {
+            self.inner
+                .alloc_write_and_return_pointer_size(function_name, iter::once(&[0]))
+        }
+    }
+}
+
+impl fmt::Debug for OffchainLocalStorageGet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainLocalStorageGet")
+            .field(&self.kind)
+            .finish()
+    }
+}
+
+/// Must set or clear a value of the off-chain local storage.
+pub struct OffchainLocalStorageSet {
+    inner: Inner,
+    kind: OffchainStorageKind,
+
+    /// Pointer to the key whose value must be set. Guaranteed to be in range.
+    key_ptr: u32,
+    /// Size of the key whose value must be set. Guaranteed to be in range.
+    key_size: u32,
+
+    /// Pointer and size of the value to set. `None` for clearing. Guaranteed to be in range.
+    value: Option<(u32, u32)>,
+}
+
+impl OffchainLocalStorageSet {
+    /// Returns the kind of storage to modify.
+    pub fn kind(&self) -> OffchainStorageKind {
+        self.kind
+    }
+
+    /// Returns the key whose value must be set.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.key_ptr, self.key_size)
+            .unwrap()
+    }
+
+    /// Returns the value to set.
+    ///
+    /// If `None` is returned, the key should be removed from the storage entirely.
+    pub fn value(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        if let Some((ptr, size)) = self.value {
+            Some(self.inner.vm.read_memory(ptr, size).unwrap())
+        } else {
+            None
+        }
+    }
+
+    /// Resumes execution after having set the value.
+    pub fn resume(self) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: None,
+        })
+    }
+}
+
+impl fmt::Debug for OffchainLocalStorageSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainLocalStorageSet")
+            .field(&self.kind)
+            .finish()
+    }
+}
+
+/// Must set a value of the off-chain local storage, but only if the current value matches the
+/// expected one.
+pub struct OffchainLocalStorageCompareAndSet {
+    inner: Inner,
+    kind: OffchainStorageKind,
+
+    /// Pointer to the key whose value must be set. Guaranteed to be in range.
+    key_ptr: u32,
+    /// Size of the key whose value must be set. Guaranteed to be in range.
+    key_size: u32,
+
+    /// Value that the storage entry is expected to have.
+    old_value: Option<Vec<u8>>,
+
+    /// Pointer to the value to set. Guaranteed to be in range.
+    new_value_ptr: u32,
+    /// Size of the value to set. Guaranteed to be in range.
+    new_value_size: u32,
+}
+
+impl OffchainLocalStorageCompareAndSet {
+    /// Returns the kind of storage to modify.
+    pub fn kind(&self) -> OffchainStorageKind {
+        self.kind
+    }
+
+    /// Returns the key whose value must be compared and set.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.key_ptr, self.key_size)
+            .unwrap()
+    }
+
+    /// Returns the value that the storage entry must currently have in order for the new value
+    /// to be set. `None` means that the storage entry must be absent.
+    pub fn old_value(&self) -> Option<&[u8]> {
+        self.old_value.as_deref()
+    }
+
+    /// Returns the value to set if the current value matches [`Self::old_value`].
+    pub fn new_value(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.new_value_ptr, self.new_value_size)
+            .unwrap()
+    }
+
+    /// Resumes execution. `replaced` must be `true` if the current value has matched
+    /// [`Self::old_value`] and has been replaced with [`Self::new_value`].
+    pub fn resume(self, replaced: bool) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: Some(vm::WasmValue::I32(if replaced { 1 } else { 0 })),
+        })
+    }
+}
+
+impl fmt::Debug for OffchainLocalStorageCompareAndSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainLocalStorageCompareAndSet")
+            .field(&self.kind)
+            .finish()
+    }
+}
+
+/// Must start a new HTTP request.
+pub struct OffchainHttpRequestStart {
+    inner: Inner,
+
+    /// Pointer to the HTTP method. Guaranteed to be in range and to be UTF-8.
+    method_ptr: u32,
+    /// Size of the HTTP method. Guaranteed to be in range and to be UTF-8.
+    method_size: u32,
+    /// Pointer to the URI. Guaranteed to be in range and to be UTF-8.
+    uri_ptr: u32,
+    /// Size of the URI. Guaranteed to be in range and to be UTF-8.
+    uri_size: u32,
+    /// Pointer to the meta-data. Guaranteed to be in range.
+    meta_ptr: u32,
+    /// Size of the meta-data. Guaranteed to be in range.
+    meta_size: u32,
+}
+
+impl OffchainHttpRequestStart {
+    /// Returns the HTTP method of the request, such as `GET` or `POST`.
+    pub fn method(&'_ self) -> impl AsRef<str> + '_ {
+        Utf8Memory(
+            self.inner
+                .vm
+                .read_memory(self.method_ptr, self.method_size)
+                .unwrap(),
+        )
+    }
+
+    /// Returns the URI the request must be sent to.
+    pub fn uri(&'_ self) -> impl AsRef<str> + '_ {
+        Utf8Memory(
+            self.inner
+                .vm
+                .read_memory(self.uri_ptr, self.uri_size)
+                .unwrap(),
+        )
+    }
+
+    /// Returns additional meta-data about the request. Reserved for future use, and currently
+    /// always empty in practice.
+    pub fn meta(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.meta_ptr, self.meta_size)
+            .unwrap()
+    }
+
+    /// Resumes execution after having started the request.
+    ///
+    /// If `Ok`, must contain the identifier of the newly-started request. This identifier is
+    /// then used by the runtime in the other HTTP-related functions.
+    pub fn resume(self, request_id: Result<u16, ()>) -> HostVm {
+        let function_name = HostFunction::ext_offchain_http_request_start_version_1.name();
+        match request_id {
+            Ok(id) => {
+                let id = id.to_le_bytes();
+                self.inner.alloc_write_and_return_pointer_size(
+                    function_name,
+                    iter::once(&[0][..]).chain(iter::once(&id[..])),
+                )
+            }
+            Err(()) => self
+                .inner
+                .alloc_write_and_return_pointer_size(function_name, iter::once(&[1])),
+        }
+    }
+}
+
+impl fmt::Debug for OffchainHttpRequestStart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpRequestStart")
+            .field(&self.method().as_ref())
+            .field(&self.uri().as_ref())
+            .finish()
+    }
+}
+
+/// Must add a header to an HTTP request.
+pub struct OffchainHttpRequestAddHeader {
+    inner: Inner,
+    request_id: u16,
+
+    /// Pointer to the name of the header. Guaranteed to be in range and to be UTF-8.
+    name_ptr: u32,
+    /// Size of the name of the header. Guaranteed to be in range and to be UTF-8.
+    name_size: u32,
+    /// Pointer to the value of the header. Guaranteed to be in range and to be UTF-8.
+    value_ptr: u32,
+    /// Size of the value of the header. Guaranteed to be in range and to be UTF-8.
+    value_size: u32,
+}
+
+impl OffchainHttpRequestAddHeader {
+    /// Returns the identifier of the request, as previously returned with
+    /// [`OffchainHttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Returns the name of the header to add.
+    pub fn name(&'_ self) -> impl AsRef<str> + '_ {
+        Utf8Memory(
+            self.inner
+                .vm
+                .read_memory(self.name_ptr, self.name_size)
+                .unwrap(),
+        )
+    }
+
+    /// Returns the value of the header to add.
+    pub fn value(&'_ self) -> impl AsRef<str> + '_ {
+        Utf8Memory(
+            self.inner
+                .vm
+                .read_memory(self.value_ptr, self.value_size)
+                .unwrap(),
+        )
+    }
+
+    /// Resumes execution after having added the header.
+    ///
+    /// An error must be returned if the request identifier is invalid or if the request has
+    /// already been sent.
+    pub fn resume(self, result: Result<(), ()>) -> HostVm {
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_http_request_add_header_version_1.name(),
+            iter::once(if result.is_ok() { &[0] } else { &[1] }),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainHttpRequestAddHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpRequestAddHeader")
+            .field(&self.request_id)
+            .field(&self.name().as_ref())
+            .field(&self.value().as_ref())
+            .finish()
+    }
+}
 
-    /// Verify the signature and resume execution.
-    pub fn verify_and_resume(self) -> HostVm {
-        let success = self.is_valid();
-        self.resume(success)
+/// Must write a chunk of the body of an HTTP request.
+pub struct OffchainHttpRequestWriteBody {
+    inner: Inner,
+    request_id: u16,
+
+    /// Pointer to the chunk of body to write. Guaranteed to be in range.
+    chunk_ptr: u32,
+    /// Size of the chunk of body to write. Guaranteed to be in range.
+    chunk_size: u32,
+
+    deadline: Option<u64>,
+}
+
+impl OffchainHttpRequestWriteBody {
+    /// Returns the identifier of the request, as previously returned with
+    /// [`OffchainHttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        self.request_id
     }
 
-    /// Resume the execution assuming that the signature is valid.
+    /// Returns the chunk of body to write.
     ///
-    /// > **Note**: You are strongly encouraged to call
-    /// >           [`SignatureVerification::verify_and_resume`]. This function is meant to be
-    /// >           used only in debugging situations.
-    pub fn resume_success(self) -> HostVm {
-        self.resume(true)
+    /// If the chunk is empty, the body is complete and the request must be finalized.
+    pub fn chunk(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.chunk_ptr, self.chunk_size)
+            .unwrap()
     }
 
-    /// Resume the execution assuming that the signature is invalid.
-    ///
-    /// > **Note**: You are strongly encouraged to call
-    /// >           [`SignatureVerification::verify_and_resume`]. This function is meant to be
-    /// >           used only in debugging situations.
-    pub fn resume_failed(self) -> HostVm {
-        self.resume(false)
+    /// Returns the UNIX timestamp, in milliseconds, after which the operation must be aborted.
+    /// `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
     }
 
-    fn resume(self, success: bool) -> HostVm {
-        // All signature-related host functions work the same way in terms of return value.
-        HostVm::ReadyToRun(ReadyToRun {
-            resume_value: Some(vm::WasmValue::I32(if success { 1 } else { 0 })),
-            inner: self.inner,
-        })
+    /// Resumes execution after having written the chunk.
+    pub fn resume(self, result: Result<(), OffchainHttpError>) -> HostVm {
+        let function_name = HostFunction::ext_offchain_http_request_write_body_version_1.name();
+        match result {
+            Ok(()) => self
+                .inner
+                .alloc_write_and_return_pointer_size(function_name, iter::once(&[0])),
+            Err(err) => self.inner.alloc_write_and_return_pointer_size(
+                function_name,
+                iter::once(&[1, err.scale_encoding()]),
+            ),
+        }
     }
 }
 
-impl fmt::Debug for SignatureVerification {
+impl fmt::Debug for OffchainHttpRequestWriteBody {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("SignatureVerification")
-            .field("message", &self.message().as_ref())
-            .field("signature", &self.signature().as_ref())
-            .field("public_key", &self.public_key().as_ref())
+        f.debug_tuple("OffchainHttpRequestWriteBody")
+            .field(&self.request_id)
+            .field(&self.deadline)
             .finish()
     }
 }
 
-/// Must provide the runtime version obtained by calling the `Core_version` entry point of a Wasm
-/// blob.
-pub struct CallRuntimeVersion {
+/// Must wait for a list of HTTP requests to finish.
+pub struct OffchainHttpResponseWait {
     inner: Inner,
+    request_ids: Vec<u16>,
+    deadline: Option<u64>,
+}
 
-    /// Pointer to the wasm code whose runtime version must be provided. Guaranteed to be in range.
-    wasm_blob_ptr: u32,
-    /// Size of the wasm code whose runtime version must be provided. Guaranteed to be in range.
-    wasm_blob_size: u32,
+impl OffchainHttpResponseWait {
+    /// Returns the identifiers of the requests to wait for.
+    pub fn request_ids(&self) -> &[u16] {
+        &self.request_ids
+    }
+
+    /// Returns the UNIX timestamp, in milliseconds, after which waiting must stop. `None` if
+    /// there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Resumes execution after having waited for the requests.
+    ///
+    /// Must be passed the status of each request, in the same order as
+    /// [`OffchainHttpResponseWait::request_ids`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the number of statuses doesn't match the number of requests.
+    ///
+    pub fn resume(
+        self,
+        statuses: impl ExactSizeIterator<Item = OffchainHttpRequestStatus>,
+    ) -> HostVm {
+        assert_eq!(statuses.len(), self.request_ids.len());
+
+        let mut encoded = Vec::with_capacity(1 + statuses.len() * 3);
+        encoded.extend_from_slice(util::encode_scale_compact_usize(statuses.len()).as_ref());
+        for status in statuses {
+            match status {
+                OffchainHttpRequestStatus::DeadlineReached => encoded.push(0),
+                OffchainHttpRequestStatus::IoError => encoded.push(10),
+                OffchainHttpRequestStatus::Invalid => encoded.push(20),
+                OffchainHttpRequestStatus::Finished(code) => {
+                    encoded.push(30);
+                    encoded.extend_from_slice(&code.to_le_bytes());
+                }
+            }
+        }
+
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_http_response_wait_version_1.name(),
+            iter::once(&encoded),
+        )
+    }
 }
 
-impl CallRuntimeVersion {
-    /// Returns the Wasm code whose runtime version must be provided.
-    pub fn wasm_code(&'_ self) -> impl AsRef<[u8]> + '_ {
-        self.inner
-            .vm
-            .read_memory(self.wasm_blob_ptr, self.wasm_blob_size)
-            .unwrap()
+impl fmt::Debug for OffchainHttpResponseWait {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpResponseWait")
+            .field(&self.request_ids)
+            .field(&self.deadline)
+            .finish()
     }
+}
 
-    /// Writes the SCALE-encoded runtime version to the memory and prepares for execution.
+/// Must provide the headers of the response of an HTTP request.
+pub struct OffchainHttpResponseHeaders {
+    inner: Inner,
+    request_id: u16,
+}
+
+impl OffchainHttpResponseHeaders {
+    /// Returns the identifier of the request, as previously returned with
+    /// [`OffchainHttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Resumes execution after having obtained the list of headers of the response.
     ///
-    /// If an error happened during the execution (such as an invalid Wasm binary code), pass
-    /// an `Err`.
-    pub fn resume(self, scale_encoded_runtime_version: Result<&[u8], ()>) -> HostVm {
-        if let Ok(scale_encoded_runtime_version) = scale_encoded_runtime_version {
-            self.inner.alloc_write_and_return_pointer_size(
-                HostFunction::ext_misc_runtime_version_version_1.name(),
-                iter::once(either::Left([1]))
-                    .chain(iter::once(either::Right(either::Left(
-                        util::encode_scale_compact_usize(scale_encoded_runtime_version.len()),
-                    ))))
-                    .chain(iter::once(either::Right(either::Right(
-                        scale_encoded_runtime_version,
-                    )))),
-            )
-        } else {
-            self.inner.alloc_write_and_return_pointer_size(
-                HostFunction::ext_misc_runtime_version_version_1.name(),
-                iter::once([0]),
-            )
+    /// An empty list must be passed if the request identifier is invalid or if no response has
+    /// been received yet.
+    pub fn resume(
+        self,
+        headers: impl ExactSizeIterator<Item = (impl AsRef<[u8]>, impl AsRef<[u8]>)>,
+    ) -> HostVm {
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(util::encode_scale_compact_usize(headers.len()).as_ref());
+        for (name, value) in headers {
+            let (name, value) = (name.as_ref(), value.as_ref());
+            encoded.extend_from_slice(util::encode_scale_compact_usize(name.len()).as_ref());
+            encoded.extend_from_slice(name);
+            encoded.extend_from_slice(util::encode_scale_compact_usize(value.len()).as_ref());
+            encoded.extend_from_slice(value);
         }
+
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_http_response_headers_version_1.name(),
+            iter::once(&encoded),
+        )
     }
 }
 
-impl fmt::Debug for CallRuntimeVersion {
+impl fmt::Debug for OffchainHttpResponseHeaders {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_tuple("CallRuntimeVersion").finish()
+        f.debug_tuple("OffchainHttpResponseHeaders")
+            .field(&self.request_id)
+            .finish()
     }
 }
 
-/// Must set the value of the off-chain storage.
-pub struct ExternalOffchainStorageSet {
+/// Must provide a chunk of the body of the response of an HTTP request.
+pub struct OffchainHttpResponseReadBody {
     inner: Inner,
+    request_id: u16,
 
-    /// Pointer to the key whose value must be set. Guaranteed to be in range.
-    key_ptr: u32,
-    /// Size of the key whose value must be set. Guaranteed to be in range.
-    key_size: u32,
+    /// Pointer to the buffer where to write the body. Guaranteed to be in range.
+    buffer_ptr: u32,
+    /// Size of the buffer where to write the body. Guaranteed to be in range.
+    buffer_size: u32,
 
-    /// Pointer and size of the value to set. `None` for clearing. Guaranteed to be in range.
-    value: Option<(u32, u32)>,
+    deadline: Option<u64>,
 }
 
-impl ExternalOffchainStorageSet {
-    /// Returns the key whose value must be set.
-    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
-        self.inner
-            .vm
-            .read_memory(self.key_ptr, self.key_size)
-            .unwrap()
+impl OffchainHttpResponseReadBody {
+    /// Returns the identifier of the request, as previously returned with
+    /// [`OffchainHttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        self.request_id
     }
 
-    /// Returns the value to set.
+    /// Returns the maximum number of bytes that can be passed back.
+    pub fn max_size(&self) -> u32 {
+        self.buffer_size
+    }
+
+    /// Returns the UNIX timestamp, in milliseconds, after which the operation must be aborted.
+    /// `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Resumes execution after having read a chunk of the body.
     ///
-    /// If `None` is returned, the key should be removed from the storage entirely.
-    pub fn value(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
-        if let Some((ptr, size)) = self.value {
-            Some(self.inner.vm.read_memory(ptr, size).unwrap())
-        } else {
-            None
+    /// An empty chunk indicates that the end of the body has been reached.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chunk is longer than what [`OffchainHttpResponseReadBody::max_size`]
+    /// returns.
+    ///
+    pub fn resume(mut self, result: Result<&[u8], OffchainHttpError>) -> HostVm {
+        let function_name = HostFunction::ext_offchain_http_response_read_body_version_1.name();
+        match result {
+            Ok(chunk) => {
+                assert!(chunk.len() <= usize::try_from(self.buffer_size).unwrap());
+                self.inner.vm.write_memory(self.buffer_ptr, chunk).unwrap();
+                let len = u32::try_from(chunk.len()).unwrap().to_le_bytes();
+                self.inner.alloc_write_and_return_pointer_size(
+                    function_name,
+                    iter::once(&[0][..]).chain(iter::once(&len[..])),
+                )
+            }
+            Err(err) => self.inner.alloc_write_and_return_pointer_size(
+                function_name,
+                iter::once(&[1, err.scale_encoding()]),
+            ),
         }
     }
+}
 
-    /// Resumes execution after having set the value.
-    pub fn resume(self) -> HostVm {
-        HostVm::ReadyToRun(ReadyToRun {
-            inner: self.inner,
-            resume_value: None,
-        })
+impl fmt::Debug for OffchainHttpResponseReadBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpResponseReadBody")
+            .field(&self.request_id)
+            .field(&self.deadline)
+            .finish()
     }
 }
 
-impl fmt::Debug for ExternalOffchainStorageSet {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_tuple("ExternalOffchainStorageSet").finish()
+/// Wraps around a buffer of memory that is known to contain UTF-8 data.
+struct Utf8Memory<T>(T);
+
+impl<T: AsRef<[u8]>> AsRef<str> for Utf8Memory<T> {
+    fn as_ref(&self) -> &str {
+        str::from_utf8(self.0.as_ref()).unwrap()
     }
 }
 
@@ -2757,6 +4769,9 @@ struct Inner {
 
     /// Memory allocator in order to answer the calls to `malloc` and `free`.
     allocator: allocator::FreeingBumpHeapAllocator,
+
+    /// Memories and instances created through the `ext_sandbox_*` host functions.
+    sandbox: sandbox::Sandbox,
 }
 
 impl Inner {
@@ -2911,6 +4926,138 @@ impl Inner {
         Ok(dest_ptr)
     }
 
+    /// Must be called when the dispatch thunk of the sandbox has returned, with the value it has
+    /// returned. Resumes the execution of the sandboxed guest.
+    fn dispatch_thunk_finished(mut self, return_value: Option<vm::WasmValue>) -> HostVm {
+        let function_name = HostFunction::ext_sandbox_invoke_version_1.name();
+
+        // The dispatch thunk returns a pointer and size to a SCALE-encoded
+        // `Result<ReturnValue, HostError>`.
+        let output = match return_value {
+            Some(vm::WasmValue::I64(ret)) => {
+                let ret = u64::from_ne_bytes(ret.to_ne_bytes());
+                let output_size = u32::try_from(ret >> 32).unwrap();
+                let output_ptr = u32::try_from(ret & 0xffff_ffff).unwrap();
+                self.vm
+                    .read_memory(output_ptr, output_size)
+                    .ok()
+                    .and_then(|output| sandbox::decode_dispatch_thunk_output(output.as_ref()))
+                    .map(|output| (output, output_ptr))
+            }
+            _ => None,
+        };
+
+        let (value, output_ptr) = match output {
+            Some(output) => output,
+            None => {
+                return HostVm::Error {
+                    error: Error::Sandbox {
+                        function: function_name,
+                        error: SandboxError::InvalidDispatchThunkOutput,
+                    },
+                    prototype: self.into_prototype(),
+                }
+            }
+        };
+
+        let (args_ptr, outcome) = self.sandbox.resume_invocation(value);
+
+        // Both the arguments and the output of the dispatch thunk are owned by the host.
+        for pointer in [args_ptr, output_ptr] {
+            let result = self.allocator.deallocate(
+                &mut MemAccess {
+                    vm: &mut self.vm,
+                    memory_total_pages: self.memory_total_pages,
+                },
+                pointer,
+            );
+            if result.is_err() {
+                return HostVm::Error {
+                    error: Error::FreeError { pointer },
+                    prototype: self.into_prototype(),
+                };
+            }
+        }
+
+        self.handle_sandbox_invocation_outcome(outcome)
+    }
+
+    /// Handles the outcome of the execution of a sandboxed guest, either by calling the
+    /// dispatch thunk or by returning from `ext_sandbox_invoke_version_1`.
+    fn handle_sandbox_invocation_outcome(mut self, outcome: sandbox::InvocationOutcome) -> HostVm {
+        let function_name = HostFunction::ext_sandbox_invoke_version_1.name();
+
+        match outcome {
+            sandbox::InvocationOutcome::Finished {
+                result,
+                return_value_ptr,
+                return_value_len,
+            } => {
+                let ret = match result {
+                    Ok(encoded) if encoded.len() <= usize::try_from(return_value_len).unwrap() => {
+                        // The pointer has been checked when the invocation was started, and the
+                        // memory can't shrink.
+                        self.vm.write_memory(return_value_ptr, &encoded).unwrap();
+                        sandbox::ERR_OK
+                    }
+                    _ => sandbox::ERR_EXECUTION,
+                };
+
+                HostVm::ReadyToRun(ReadyToRun {
+                    inner: self,
+                    resume_value: Some(vm::WasmValue::I32(i32::from_ne_bytes(ret.to_ne_bytes()))),
+                })
+            }
+            sandbox::InvocationOutcome::DispatchThunkCall {
+                dispatch_thunk,
+                scale_encoded_params,
+                state,
+                function,
+            } => {
+                let params_len = u32::try_from(scale_encoded_params.len()).unwrap();
+                let params_ptr = match self.alloc(function_name, params_len) {
+                    Ok(p) => p,
+                    Err(error) => {
+                        return HostVm::Error {
+                            error,
+                            prototype: self.into_prototype(),
+                        }
+                    }
+                };
+                self.vm
+                    .write_memory(params_ptr, &scale_encoded_params)
+                    .unwrap();
+
+                let result = self.vm.start_nested_indirect(
+                    dispatch_thunk,
+                    &[
+                        vm::WasmValue::I32(i32::from_ne_bytes(params_ptr.to_ne_bytes())),
+                        vm::WasmValue::I32(i32::from_ne_bytes(params_len.to_ne_bytes())),
+                        vm::WasmValue::I32(i32::from_ne_bytes(state.to_ne_bytes())),
+                        vm::WasmValue::I32(i32::from_ne_bytes(function.to_ne_bytes())),
+                    ],
+                );
+
+                match result {
+                    Ok(()) => {
+                        self.sandbox.set_dispatch_thunk_args_ptr(params_ptr);
+                        HostVm::ReadyToRun(ReadyToRun {
+                            inner: self,
+                            resume_value: None,
+                        })
+                    }
+                    Err(error) => HostVm::Error {
+                        error: Error::Sandbox {
+                            function: function_name,
+                            error: SandboxError::DispatchThunkCall(error),
+                        },
+                        prototype: self.into_prototype(),
+                    },
+                }
+            }
+        }
+    }
+
     /// Turns the virtual machine back into a prototype.
     fn into_prototype(self) -> HostVmPrototype {
         HostVmPrototype {
@@ -3092,6 +5239,14 @@ pub enum Error {
         /// Pointer that was expected to be freed.
         pointer: u32,
     },
+    /// Error in one of the `ext_sandbox_*` host functions.
+    #[display(fmt = "Error in {}: {}", function, error)]
+    Sandbox {
+        /// Name of the function being called.
+        function: &'static str,
+        /// Error that happened.
+        error: SandboxError,
+    },
     /// The host function isn't implemented.
     // TODO: this variant should eventually disappear as all functions are implemented
     #[display(fmt = "Host function not implemented: {}", function)]
@@ -3101,6 +5256,34 @@ pub enum Error {
     },
 }
 
+/// Error that can happen in one of the `ext_sandbox_*` host functions.
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum SandboxError {
+    /// The requested sandbox memory doesn't exist.
+    #[display(fmt = "Invalid sandbox memory index: {}", _0)]
+    InvalidMemoryIndex(u32),
+    /// The requested sandbox instance doesn't exist.
+    #[display(fmt = "Invalid sandbox instance index: {}", _0)]
+    InvalidInstanceIndex(u32),
+    /// The requested sandbox instance is currently being executed.
+    #[display(fmt = "Sandbox instance {} is being executed", _0)]
+    InstanceBusy(u32),
+    /// The requested sandbox memory is imported by an instance that is currently being executed.
+    #[display(fmt = "Sandbox memory {} is in use", _0)]
+    MemoryBusy(u32),
+    /// The requested size of a new sandbox memory is invalid.
+    #[display(fmt = "Invalid sandbox memory size")]
+    InvalidMemorySize,
+    /// Failed to call the dispatch thunk of the runtime.
+    ///
+    /// > **Note**: This notably happens if the runtime isn't executed by the interpreter.
+    #[display(fmt = "Failed to call the dispatch thunk: {}", _0)]
+    DispatchThunkCall(vm::NestedCallErr),
+    /// The value returned by the dispatch thunk is invalid.
+    #[display(fmt = "Invalid value returned by the dispatch thunk")]
+    InvalidDispatchThunkOutput,
+}
+
 macro_rules! externalities {
     ($($ext:ident,)*) => {
         /// List of possible externalities.
@@ -3268,19 +5451,19 @@ impl HostFunction {
             HostFunction::ext_storage_start_transaction_version_1 => 0,
             HostFunction::ext_storage_rollback_transaction_version_1 => 0,
             HostFunction::ext_storage_commit_transaction_version_1 => 0,
-            HostFunction::ext_default_child_storage_get_version_1 => todo!(),
-            HostFunction::ext_default_child_storage_read_version_1 => todo!(),
-            HostFunction::ext_default_child_storage_storage_kill_version_1 => todo!(),
-            HostFunction::ext_default_child_storage_storage_kill_version_2 => todo!(),
-            HostFunction::ext_default_child_storage_storage_kill_version_3 => todo!(),
-            HostFunction::ext_default_child_storage_clear_prefix_version_1 => todo!(),
-            HostFunction::ext_default_child_storage_clear_prefix_version_2 => todo!(),
-            HostFunction::ext_default_child_storage_set_version_1 => todo!(),
-            HostFunction::ext_default_child_storage_clear_version_1 => todo!(),
-            HostFunction::ext_default_child_storage_exists_version_1 => todo!(),
-            HostFunction::ext_default_child_storage_next_key_version_1 => todo!(),
-            HostFunction::ext_default_child_storage_root_version_1 => todo!(),
-            HostFunction::ext_default_child_storage_root_version_2 => todo!(),
+            HostFunction::ext_default_child_storage_get_version_1 => 2,
+            HostFunction::ext_default_child_storage_read_version_1 => 4,
+            HostFunction::ext_default_child_storage_storage_kill_version_1 => 1,
+            HostFunction::ext_default_child_storage_storage_kill_version_2 => 2,
+            HostFunction::ext_default_child_storage_storage_kill_version_3 => 2,
+            HostFunction::ext_default_child_storage_clear_prefix_version_1 => 2,
+            HostFunction::ext_default_child_storage_clear_prefix_version_2 => 3,
+            HostFunction::ext_default_child_storage_set_version_1 => 3,
+            HostFunction::ext_default_child_storage_clear_version_1 => 2,
+            HostFunction::ext_default_child_storage_exists_version_1 => 2,
+            HostFunction::ext_default_child_storage_next_key_version_1 => 2,
+            HostFunction::ext_default_child_storage_root_version_1 => 1,
+            HostFunction::ext_default_child_storage_root_version_2 => 2,
             HostFunction::ext_crypto_ed25519_public_keys_version_1 => todo!(),
             HostFunction::ext_crypto_ed25519_generate_version_1 => todo!(),
             HostFunction::ext_crypto_ed25519_sign_version_1 => todo!(),
@@ -3290,9 +5473,9 @@ impl HostFunction {
             HostFunction::ext_crypto_sr25519_sign_version_1 => todo!(),
             HostFunction::ext_crypto_sr25519_verify_version_1 => 3,
             HostFunction::ext_crypto_sr25519_verify_version_2 => 3,
-            HostFunction::ext_crypto_ecdsa_generate_version_1 => todo!(),
+            HostFunction::ext_crypto_ecdsa_generate_version_1 => 2,
             HostFunction::ext_crypto_ecdsa_sign_version_1 => 2,
-            HostFunction::ext_crypto_ecdsa_public_keys_version_1 => todo!(),
+            HostFunction::ext_crypto_ecdsa_public_keys_version_1 => 1,
             HostFunction::ext_crypto_ecdsa_verify_version_1 => 3,
             HostFunction::ext_crypto_ecdsa_sign_prehashed_version_1 => 2,
             HostFunction::ext_crypto_ecdsa_verify_prehashed_version_1 => 3,
@@ -3311,30 +5494,30 @@ impl HostFunction {
             HostFunction::ext_hashing_twox_256_version_1 => 1,
             HostFunction::ext_offchain_index_set_version_1 => 2,
             HostFunction::ext_offchain_index_clear_version_1 => 1,
-            HostFunction::ext_offchain_is_validator_version_1 => todo!(),
-            HostFunction::ext_offchain_submit_transaction_version_1 => todo!(),
-            HostFunction::ext_offchain_network_state_version_1 => todo!(),
-            HostFunction::ext_offchain_timestamp_version_1 => todo!(),
-            HostFunction::ext_offchain_sleep_until_version_1 => todo!(),
-            HostFunction::ext_offchain_random_seed_version_1 => todo!(),
-            HostFunction::ext_offchain_local_storage_set_version_1 => todo!(),
-            HostFunction::ext_offchain_local_storage_compare_and_set_version_1 => todo!(),
-            HostFunction::ext_offchain_local_storage_get_version_1 => todo!(),
-            HostFunction::ext_offchain_local_storage_clear_version_1 => todo!(),
-            HostFunction::ext_offchain_http_request_start_version_1 => todo!(),
-            HostFunction::ext_offchain_http_request_add_header_version_1 => todo!(),
-            HostFunction::ext_offchain_http_request_write_body_version_1 => todo!(),
-            HostFunction::ext_offchain_http_response_wait_version_1 => todo!(),
-            HostFunction::ext_offchain_http_response_headers_version_1 => todo!(),
-            HostFunction::ext_offchain_http_response_read_body_version_1 => todo!(),
-            HostFunction::ext_sandbox_instantiate_version_1 => todo!(),
-            HostFunction::ext_sandbox_invoke_version_1 => todo!(),
-            HostFunction::ext_sandbox_memory_new_version_1 => todo!(),
-            HostFunction::ext_sandbox_memory_get_version_1 => todo!(),
-            HostFunction::ext_sandbox_memory_set_version_1 => todo!(),
-            HostFunction::ext_sandbox_memory_teardown_version_1 => todo!(),
-            HostFunction::ext_sandbox_instance_teardown_version_1 => todo!(),
-            HostFunction::ext_sandbox_get_global_val_version_1 => todo!(),
+            HostFunction::ext_offchain_is_validator_version_1 => 0,
+            HostFunction::ext_offchain_submit_transaction_version_1 => 1,
+            HostFunction::ext_offchain_network_state_version_1 => 0,
+            HostFunction::ext_offchain_timestamp_version_1 => 0,
+            HostFunction::ext_offchain_sleep_until_version_1 => 1,
+            HostFunction::ext_offchain_random_seed_version_1 => 0,
+            HostFunction::ext_offchain_local_storage_set_version_1 => 3,
+            HostFunction::ext_offchain_local_storage_compare_and_set_version_1 => 4,
+            HostFunction::ext_offchain_local_storage_get_version_1 => 2,
+            HostFunction::ext_offchain_local_storage_clear_version_1 => 2,
+            HostFunction::ext_offchain_http_request_start_version_1 => 3,
+            HostFunction::ext_offchain_http_request_add_header_version_1 => 3,
+            HostFunction::ext_offchain_http_request_write_body_version_1 => 3,
+            HostFunction::ext_offchain_http_response_wait_version_1 => 2,
+            HostFunction::ext_offchain_http_response_headers_version_1 => 1,
+            HostFunction::ext_offchain_http_response_read_body_version_1 => 3,
+            HostFunction::ext_sandbox_instantiate_version_1 => 4,
+            HostFunction::ext_sandbox_invoke_version_1 => 6,
+            HostFunction::ext_sandbox_memory_new_version_1 => 2,
+            HostFunction::ext_sandbox_memory_get_version_1 => 4,
+            HostFunction::ext_sandbox_memory_set_version_1 => 4,
+            HostFunction::ext_sandbox_memory_teardown_version_1 => 1,
+            HostFunction::ext_sandbox_instance_teardown_version_1 => 1,
+            HostFunction::ext_sandbox_get_global_val_version_1 => 2,
             HostFunction::ext_trie_blake2_256_root_version_1 => 1,
             HostFunction::ext_trie_blake2_256_root_version_2 => 2,
             HostFunction::ext_trie_blake2_256_ordered_root_version_1 => 1,
@@ -3449,11 +5632,168 @@ impl<'a> allocator::Memory for MemAccess<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::HostVm;
+    use super::{Config, HeapPages, HostVm, HostVmPrototype};
 
     #[test]
     fn is_send() {
         fn req<T: Send>() {}
         req::<HostVm>();
     }
+
+    /// Builds a module whose exported functions each call one of the default child storage host
+    /// functions with the child trie `foo`, the key `key`, the value `value`, and a limit of 5
+    /// keys to remove, and return the output of the host function.
+    fn child_storage_test_module() -> HostVmPrototype {
+        let module = wat::parse_str(
+            r#"
+(module
+    (import "env" "memory" (memory 1))
+    (import "env" "ext_default_child_storage_get_version_1" (func $get (param i64 i64) (result i64)))
+    (import "env" "ext_default_child_storage_set_version_1" (func $set (param i64 i64 i64)))
+    (import "env" "ext_default_child_storage_clear_version_1" (func $clear (param i64 i64)))
+    (import "env" "ext_default_child_storage_next_key_version_1" (func $next_key (param i64 i64) (result i64)))
+    (import "env" "ext_default_child_storage_root_version_1" (func $root (param i64) (result i64)))
+    (import "env" "ext_default_child_storage_storage_kill_version_3" (func $kill (param i64 i64) (result i64)))
+    (global (export "__heap_base") i32 (i32.const 1024))
+
+    ;; SCALE-encoded runtime version, returned by `Core_version`.
+    (data (i32.const 0) "\10test\10test\00\00\00\00\00\00\00\00\00\00\00\00\00")
+    (data (i32.const 32) "foo")
+    (data (i32.const 40) "key")
+    (data (i32.const 48) "value")
+    (data (i32.const 56) "\01\05\00\00\00")
+
+    (func (export "Core_version") (param i32 i32) (result i64)
+        i64.const 0x0000001700000000)
+    (func (export "get") (param i32 i32) (result i64)
+        (call $get (i64.const 0x0000000300000020) (i64.const 0x0000000300000028)))
+    (func (export "set") (param i32 i32) (result i64)
+        (call $set (i64.const 0x0000000300000020) (i64.const 0x0000000300000028) (i64.const 0x0000000500000030))
+        i64.const 0)
+    (func (export "clear") (param i32 i32) (result i64)
+        (call $clear (i64.const 0x0000000300000020) (i64.const 0x0000000300000028))
+        i64.const 0)
+    (func (export "next_key") (param i32 i32) (result i64)
+        (call $next_key (i64.const 0x0000000300000020) (i64.const 0x0000000300000028)))
+    (func (export "root") (param i32 i32) (result i64)
+        (call $root (i64.const 0x0000000300000020)))
+    (func (export "kill") (param i32 i32) (result i64)
+        (call $kill (i64.const 0x0000000300000020) (i64.const 0x0000000500000038)))
+)
+            "#,
+        )
+        .unwrap();
+
+        HostVmPrototype::new(Config {
+            module: &module,
+            heap_pages: HeapPages::new(1),
+            exec_hint: crate::executor::vm::ExecHint::ForceWasmi,
+            allow_unresolved_imports: false,
+        })
+        .unwrap()
+    }
+
+    fn start(function: &str) -> HostVm {
+        child_storage_test_module()
+            .run(function, &[])
+            .unwrap()
+            .run()
+    }
+
+    fn expect_finished(vm: HostVm) -> Vec<u8> {
+        match vm {
+            HostVm::ReadyToRun(r) => expect_finished(r.run()),
+            HostVm::Finished(finished) => finished.value().as_ref().to_vec(),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn child_storage_get() {
+        let req = match start("get") {
+            HostVm::ExternalChildStorageGet(req) => req,
+            _ => panic!(),
+        };
+        assert_eq!(req.child_trie().as_ref(), b"foo");
+        assert_eq!(req.key().as_ref(), b"key");
+        assert_eq!(req.offset(), 0);
+        let output = expect_finished(req.resume_full_value(Some(b"hello")));
+        assert_eq!(output, b"\x01\x14hello");
+    }
+
+    #[test]
+    fn child_storage_get_missing() {
+        let req = match start("get") {
+            HostVm::ExternalChildStorageGet(req) => req,
+            _ => panic!(),
+        };
+        let output = expect_finished(req.resume_full_value(None));
+        assert_eq!(output, b"\x00");
+    }
+
+    #[test]
+    fn child_storage_set() {
+        let req = match start("set") {
+            HostVm::ExternalChildStorageSet(req) => req,
+            _ => panic!(),
+        };
+        assert_eq!(req.child_trie().as_ref(), b"foo");
+        assert_eq!(req.key().as_ref(), b"key");
+        assert_eq!(req.value().unwrap().as_ref(), b"value");
+        assert!(expect_finished(req.resume()).is_empty());
+    }
+
+    #[test]
+    fn child_storage_clear() {
+        let req = match start("clear") {
+            HostVm::ExternalChildStorageSet(req) => req,
+            _ => panic!(),
+        };
+        assert_eq!(req.child_trie().as_ref(), b"foo");
+        assert_eq!(req.key().as_ref(), b"key");
+        assert!(req.value().is_none());
+        assert!(expect_finished(req.resume()).is_empty());
+    }
+
+    #[test]
+    fn child_storage_next_key() {
+        let req = match start("next_key") {
+            HostVm::ExternalChildStorageNextKey(req) => req,
+            _ => panic!(),
+        };
+        assert_eq!(req.child_trie().as_ref(), b"foo");
+        assert_eq!(req.key().as_ref(), b"key");
+        let output = expect_finished(req.resume(Some(b"keyz")));
+        assert_eq!(output, b"\x01\x10keyz");
+
+        let req = match start("next_key") {
+            HostVm::ExternalChildStorageNextKey(req) => req,
+            _ => panic!(),
+        };
+        assert_eq!(expect_finished(req.resume(None)), b"\x00");
+    }
+
+    #[test]
+    fn child_storage_root() {
+        let req = match start("root") {
+            HostVm::ExternalChildStorageRoot(req) => req,
+            _ => panic!(),
+        };
+        assert_eq!(req.child_trie().as_ref(), b"foo");
+        let output = expect_finished(req.resume(&[0xab; 32]));
+        assert_eq!(output, [0xab; 32]);
+    }
+
+    #[test]
+    fn child_storage_kill() {
+        let req = match start("kill") {
+            HostVm::ExternalChildStorageClearPrefix(req) => req,
+            _ => panic!(),
+        };
+        assert_eq!(req.child_trie().as_ref(), b"foo");
+        assert!(req.prefix().as_ref().is_empty());
+        assert_eq!(req.max_keys_to_remove(), Some(5));
+        let output = expect_finished(req.resume(5, true));
+        assert_eq!(output, [1, 5, 0, 0, 0]);
+    }
 }
@@ -0,0 +1,791 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! State of the Wasm sandbox, as exposed to the runtime through the `ext_sandbox_*` host
+//! functions.
+//!
+//! The sandbox allows the runtime, called the *supervisor*, to instantiate and execute other
+//! Wasm modules, called *guests*. Guests can import memories created by the supervisor, and
+//! functions. When a guest calls one of its imported functions, the supervisor's *dispatch thunk*
+//! (a function of its `__indirect_function_table`) must be called in order to handle the call.
+//! Calling the dispatch thunk is the responsibility of the user of this module.
+//!
+//! Guests are always executed with the interpreter, as their code is untrusted.
+//!
+//! The memories of the sandbox are stored in this module, and copied to and from the memory of
+//! the guest that imports them whenever the guest starts or stops executing.
+
+use super::SandboxError;
+use crate::{executor::vm, util};
+
+use alloc::vec::Vec;
+use core::iter;
+
+/// Value returned by the host functions in case of success.
+pub(super) const ERR_OK: u32 = 0;
+/// Value returned by the host functions if the module couldn't be instantiated.
+pub(super) const ERR_MODULE: u32 = -1i32 as u32;
+/// Value returned by the host functions in case of out of bounds memory access.
+pub(super) const ERR_OUT_OF_BOUNDS: u32 = -2i32 as u32;
+/// Value returned by the host functions if the execution of the guest has failed.
+pub(super) const ERR_EXECUTION: u32 = -3i32 as u32;
+
+/// Value of the `maximum` parameter of `ext_sandbox_memory_new_version_1` indicating that the
+/// memory has no maximum size.
+const MEMORY_UNLIMITED: u32 = u32::MAX;
+
+/// Maximum number of pages that a memory of the sandbox can have, which corresponds to 128 MiB.
+///
+/// Memories are copied to and from guests whenever they start or stop executing, and are
+/// therefore kept considerably smaller than the 4 GiB that a Wasm memory can theoretically reach.
+const MAX_MEMORY_PAGES: u32 = 2048;
+
+/// Size in bytes of a Wasm memory page.
+const PAGE_SIZE: usize = 64 * 1024;
+
+/// Memories and instances of the sandbox of a runtime.
+pub(super) struct Sandbox {
+    /// List of memories. Indices are the memory identifiers passed to the supervisor. `None` if
+    /// the memory has been torn down.
+    memories: Vec<Option<Memory>>,
+
+    /// List of guest instances. Indices are the instance identifiers passed to the supervisor.
+    /// `None` if the instance has been torn down.
+    instances: Vec<Option<Instance>>,
+
+    /// Calls to guest functions currently in progress. The last element is the innermost call.
+    /// Calls can be nested because the dispatch thunk can invoke guests as well.
+    invocations: Vec<Invocation>,
+}
+
+struct Memory {
+    /// Content of the memory. The length is always a multiple of 64kiB.
+    data: Vec<u8>,
+}
+
+struct Instance {
+    /// Virtual machine of the guest. `None` while a function of this instance is being executed.
+    vm: Option<vm::VirtualMachinePrototype>,
+
+    /// Index of the dispatch thunk in the `__indirect_function_table` of the supervisor.
+    dispatch_thunk: u32,
+
+    /// For each function imported by the guest, the value to pass to the dispatch thunk. The
+    /// indices of this list correspond to the identifiers passed to the virtual machine.
+    imported_functions: Vec<u32>,
+
+    /// Index within [`Sandbox::memories`] of the memory imported by the guest, if any.
+    memory: Option<u32>,
+}
+
+struct Invocation {
+    /// Index within [`Sandbox::instances`] of the instance being executed.
+    instance_idx: u32,
+
+    /// Virtual machine executing the guest function.
+    vm: vm::VirtualMachine,
+
+    /// Pointer, in the memory of the supervisor, where to write the return value of the guest.
+    return_value_ptr: u32,
+
+    /// Size of the buffer at [`Invocation::return_value_ptr`].
+    return_value_len: u32,
+
+    /// Opaque value passed by the supervisor, and passed back to the dispatch thunk.
+    state: u32,
+
+    /// If `Some`, the dispatch thunk is being executed by the supervisor in order to handle a
+    /// call of the guest to one of its imported functions. Contains the pointer, in the memory of
+    /// the supervisor, of the arguments passed to the dispatch thunk.
+    dispatch_thunk_args_ptr: Option<u32>,
+}
+
+/// Outcome of [`Sandbox::start_invocation`] or [`Sandbox::resume_invocation`].
+pub(super) enum InvocationOutcome {
+    /// The invocation is over and has been removed from the sandbox.
+    Finished {
+        /// SCALE-encoded `ReturnValue` of the function if it was successful.
+        result: Result<Vec<u8>, ()>,
+        /// See [`Invocation::return_value_ptr`].
+        return_value_ptr: u32,
+        /// See [`Invocation::return_value_len`].
+        return_value_len: u32,
+    },
+
+    /// The guest has called one of its imported functions. The dispatch thunk must be called,
+    /// after which [`Sandbox::set_dispatch_thunk_args_ptr`] must be called.
+    DispatchThunkCall {
+        /// Index of the dispatch thunk in the `__indirect_function_table` of the supervisor.
+        dispatch_thunk: u32,
+        /// SCALE-encoded list of parameters of the call.
+        scale_encoded_params: Vec<u8>,
+        /// Opaque value passed by the supervisor when invoking the guest.
+        state: u32,
+        /// Identifier of the function being called, as found in the environment definition.
+        function: u32,
+    },
+}
+
+/// Error returned by [`Sandbox::start_invocation`].
+pub(super) enum StartInvocationError {
+    /// Error to report to the supervisor as a trap.
+    Sandbox(SandboxError),
+    /// Failed to decode the arguments.
+    ArgsDecode,
+    /// Failed to start the function. [`ERR_EXECUTION`] must be returned to the supervisor.
+    Execution,
+}
+
+impl Sandbox {
+    /// Creates a new empty sandbox.
+    pub(super) fn new() -> Self {
+        Sandbox {
+            memories: Vec::new(),
+            instances: Vec::new(),
+            invocations: Vec::new(),
+        }
+    }
+
+    /// Creates a new memory and returns its index.
+    pub(super) fn memory_new(&mut self, initial: u32, maximum: u32) -> Result<u32, SandboxError> {
+        if initial > MAX_MEMORY_PAGES || (maximum != MEMORY_UNLIMITED && initial > maximum) {
+            return Err(SandboxError::InvalidMemorySize);
+        }
+
+        // TODO: the maximum is ignored, as guests use the maximum found in their own code
+        let size = usize::try_from(initial)
+            .ok()
+            .and_then(|pages| pages.checked_mul(PAGE_SIZE))
+            .ok_or(SandboxError::InvalidMemorySize)?;
+        self.memories.push(Some(Memory {
+            data: vec![0; size],
+        }));
+        Ok(u32::try_from(self.memories.len() - 1).unwrap())
+    }
+
+    /// Returns the content of the given memory range. Returns `Ok(None)` if out of bounds.
+    pub(super) fn memory_get(
+        &self,
+        memory_idx: u32,
+        offset: u32,
+        size: u32,
+    ) -> Result<Option<&[u8]>, SandboxError> {
+        let memory = self.memory(memory_idx)?;
+        let start = usize::try_from(offset).unwrap();
+        let end = start.saturating_add(usize::try_from(size).unwrap());
+        Ok(memory.data.get(start..end))
+    }
+
+    /// Writes the given data to memory. Returns `Ok(false)` if out of bounds.
+    pub(super) fn memory_set(
+        &mut self,
+        memory_idx: u32,
+        offset: u32,
+        data: &[u8],
+    ) -> Result<bool, SandboxError> {
+        let memory = self
+            .memories
+            .get_mut(usize::try_from(memory_idx).unwrap())
+            .and_then(|m| m.as_mut())
+            .ok_or(SandboxError::InvalidMemoryIndex(memory_idx))?;
+        let start = usize::try_from(offset).unwrap();
+        let end = start.saturating_add(data.len());
+        match memory.data.get_mut(start..end) {
+            Some(range) => {
+                range.copy_from_slice(data);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Destroys the given memory.
+    ///
+    /// Returns an error if the memory is imported by an instance that is currently being
+    /// executed.
+    pub(super) fn memory_teardown(&mut self, memory_idx: u32) -> Result<(), SandboxError> {
+        let in_use = self.invocations.iter().any(|invocation| {
+            matches!(
+                &self.instances[usize::try_from(invocation.instance_idx).unwrap()],
+                Some(instance) if instance.memory == Some(memory_idx)
+            )
+        });
+        if in_use {
+            return Err(SandboxError::MemoryBusy(memory_idx));
+        }
+
+        match self.memories.get_mut(usize::try_from(memory_idx).unwrap()) {
+            Some(memory @ Some(_)) => {
+                *memory = None;
+                Ok(())
+            }
+            _ => Err(SandboxError::InvalidMemoryIndex(memory_idx)),
+        }
+    }
+
+    /// Instantiates a guest. Returns the index of the new instance, or the error code to return
+    /// to the supervisor.
+    pub(super) fn instantiate(
+        &mut self,
+        dispatch_thunk: u32,
+        wasm_code: &[u8],
+        scale_encoded_env_def: &[u8],
+    ) -> Result<u32, u32> {
+        let env_def = decode_environment_definition(scale_encoded_env_def).ok_or(ERR_MODULE)?;
+
+        let memory = env_def
+            .iter()
+            .find_map(|(module_name, field_name, entity)| match entity {
+                ExternEntity::Memory(idx) if *module_name == b"env" && *field_name == b"memory" => {
+                    Some(*idx)
+                }
+                _ => None,
+            });
+        if let Some(memory) = memory {
+            if self.memory(memory).is_err() {
+                return Err(ERR_MODULE);
+            }
+        }
+
+        let module = vm::Module::new(wasm_code, vm::ExecHint::Untrusted).map_err(|_| ERR_MODULE)?;
+
+        let mut imported_functions = Vec::new();
+        let vm = vm::VirtualMachinePrototype::new(&module, |module_name, field_name, _| {
+            let function = env_def
+                .iter()
+                .find_map(|(module, field, entity)| match entity {
+                    ExternEntity::Function(idx)
+                        if *module == module_name.as_bytes() && *field == field_name.as_bytes() =>
+                    {
+                        Some(*idx)
+                    }
+                    _ => None,
+                })
+                .ok_or(())?;
+            imported_functions.push(function);
+            Ok(imported_functions.len() - 1)
+        })
+        .map_err(|_| ERR_MODULE)?;
+
+        self.instances.push(Some(Instance {
+            vm: Some(vm),
+            dispatch_thunk,
+            imported_functions,
+            memory,
+        }));
+
+        Ok(u32::try_from(self.instances.len() - 1).unwrap())
+    }
+
+    /// Destroys the given instance.
+    pub(super) fn instance_teardown(&mut self, instance_idx: u32) -> Result<(), SandboxError> {
+        match self
+            .instances
+            .get_mut(usize::try_from(instance_idx).unwrap())
+        {
+            Some(Some(instance)) if instance.vm.is_none() => {
+                Err(SandboxError::InstanceBusy(instance_idx))
+            }
+            Some(instance @ Some(_)) => {
+                *instance = None;
+                Ok(())
+            }
+            _ => Err(SandboxError::InvalidInstanceIndex(instance_idx)),
+        }
+    }
+
+    /// Returns the SCALE-encoded `Option<Value>` containing the value of the given global of
+    /// the given instance.
+    pub(super) fn global_value(
+        &mut self,
+        instance_idx: u32,
+        name: &str,
+    ) -> Result<Vec<u8>, SandboxError> {
+        let vm = self
+            .instances
+            .get_mut(usize::try_from(instance_idx).unwrap())
+            .and_then(|i| i.as_mut())
+            .ok_or(SandboxError::InvalidInstanceIndex(instance_idx))?
+            .vm
+            .as_mut()
+            .ok_or(SandboxError::InstanceBusy(instance_idx))?;
+
+        // TODO: globals that aren't `i32`s are reported as missing
+        Ok(match vm.global_value(name) {
+            Ok(value) => iter::once(1)
+                .chain(encode_value(vm::WasmValue::I32(i32::from_ne_bytes(
+                    value.to_ne_bytes(),
+                ))))
+                .collect(),
+            Err(_) => vec![0],
+        })
+    }
+
+    /// Starts calling a function of a guest.
+    pub(super) fn start_invocation(
+        &mut self,
+        instance_idx: u32,
+        function: &str,
+        scale_encoded_args: &[u8],
+        return_value_ptr: u32,
+        return_value_len: u32,
+        state: u32,
+    ) -> Result<InvocationOutcome, StartInvocationError> {
+        let instance = self
+            .instances
+            .get_mut(usize::try_from(instance_idx).unwrap())
+            .and_then(|i| i.as_mut())
+            .ok_or(StartInvocationError::Sandbox(
+                SandboxError::InvalidInstanceIndex(instance_idx),
+            ))?;
+
+        let args = {
+            let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                nom::combinator::all_consuming(nom::multi::length_count(
+                    util::nom_scale_compact_usize,
+                    decode_value,
+                ))(scale_encoded_args)
+                .map(|(_, parse_result)| parse_result);
+            parsing_result.map_err(|_| StartInvocationError::ArgsDecode)?
+        };
+
+        // Floating point values aren't supported by the virtual machine.
+        let args = args
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or(StartInvocationError::Execution)?;
+
+        let memory_idx = instance.memory;
+        let min_memory_pages = match memory_idx {
+            Some(idx) => match self.memories.get(usize::try_from(idx).unwrap()) {
+                Some(Some(memory)) => {
+                    vm::HeapPages::new(u32::try_from(memory.data.len() / PAGE_SIZE).unwrap())
+                }
+                _ => return Err(StartInvocationError::Execution),
+            },
+            None => vm::HeapPages::new(0),
+        };
+
+        let prototype =
+            instance
+                .vm
+                .take()
+                .ok_or(StartInvocationError::Sandbox(SandboxError::InstanceBusy(
+                    instance_idx,
+                )))?;
+
+        let mut vm = match prototype.start(min_memory_pages, function, &args) {
+            Ok(vm) => vm,
+            Err((_, prototype)) => {
+                instance.vm = Some(prototype);
+                return Err(StartInvocationError::Execution);
+            }
+        };
+
+        if let Some(memory_idx) = memory_idx {
+            let memory = self.memories[usize::try_from(memory_idx).unwrap()]
+                .as_ref()
+                .unwrap();
+            if copy_memory_to_guest(memory, &mut vm).is_err() {
+                instance.vm = Some(vm.into_prototype());
+                return Err(StartInvocationError::Execution);
+            }
+        }
+
+        self.invocations.push(Invocation {
+            instance_idx,
+            vm,
+            return_value_ptr,
+            return_value_len,
+            state,
+            dispatch_thunk_args_ptr: None,
+        });
+
+        Ok(self.run_invocation(None))
+    }
+
+    /// Returns `true` if the dispatch thunk is being executed on behalf of the innermost
+    /// invocation.
+    pub(super) fn is_dispatch_thunk_running(&self) -> bool {
+        matches!(self.invocations.last(), Some(inv) if inv.dispatch_thunk_args_ptr.is_some())
+    }
+
+    /// Must be called after [`InvocationOutcome::DispatchThunkCall`] has been returned and the
+    /// dispatch thunk has been started. Stores the pointer to its arguments.
+    pub(super) fn set_dispatch_thunk_args_ptr(&mut self, ptr: u32) {
+        let invocation = self.invocations.last_mut().unwrap();
+        debug_assert!(invocation.dispatch_thunk_args_ptr.is_none());
+        invocation.dispatch_thunk_args_ptr = Some(ptr);
+    }
+
+    /// Must be called when the dispatch thunk has finished executing. Resumes the execution of
+    /// the guest.
+    ///
+    /// If `value` is `Err`, the guest is aborted.
+    ///
+    /// Returns the pointer previously passed to [`Sandbox::set_dispatch_thunk_args_ptr`].
+    pub(super) fn resume_invocation(
+        &mut self,
+        value: Result<Option<vm::WasmValue>, ()>,
+    ) -> (u32, InvocationOutcome) {
+        let invocation = self.invocations.last_mut().unwrap();
+        let args_ptr = invocation.dispatch_thunk_args_ptr.take().unwrap();
+
+        let value = match value {
+            Ok(value) => value,
+            Err(()) => return (args_ptr, self.finish_invocation(Err(()))),
+        };
+
+        // The dispatch thunk might have modified the memory.
+        let memory = self.invocation_memory();
+        if let Some(memory_idx) = memory {
+            let memory = self.memories[usize::try_from(memory_idx).unwrap()]
+                .as_ref()
+                .unwrap();
+            let invocation = self.invocations.last_mut().unwrap();
+            if copy_memory_to_guest(memory, &mut invocation.vm).is_err() {
+                return (args_ptr, self.finish_invocation(Err(())));
+            }
+        }
+
+        (args_ptr, self.run_invocation(value))
+    }
+
+    /// Runs the innermost invocation until it finishes or calls an imported function.
+    fn run_invocation(&mut self, value: Option<vm::WasmValue>) -> InvocationOutcome {
+        let invocation = self.invocations.last_mut().unwrap();
+        match invocation.vm.run(value) {
+            Ok(vm::ExecOutcome::Finished {
+                return_value: Ok(value),
+            }) => {
+                let encoded = match value {
+                    Some(value) => iter::once(1).chain(encode_value(value)).collect(),
+                    None => vec![0],
+                };
+                self.finish_invocation(Ok(encoded))
+            }
+            Ok(vm::ExecOutcome::Finished {
+                return_value: Err(_),
+            })
+            | Err(vm::RunErr::BadValueTy { .. }) => self.finish_invocation(Err(())),
+            Ok(vm::ExecOutcome::Interrupted { id, params }) => {
+                let instance_idx = invocation.instance_idx;
+                let state = invocation.state;
+                let instance = self.instances[usize::try_from(instance_idx).unwrap()]
+                    .as_ref()
+                    .unwrap();
+                let dispatch_thunk = instance.dispatch_thunk;
+                let function = instance.imported_functions[id];
+
+                // Make the memory modifications of the guest visible to the supervisor.
+                // TODO: copying the entire memory at each call is very inefficient; the cost is
+                // bounded by `MAX_MEMORY_PAGES`, but only the pages modified by the guest should
+                // be copied
+                if let Some(memory_idx) = self.invocation_memory() {
+                    let invocation = self.invocations.last().unwrap();
+                    if let Some(Some(memory)) =
+                        self.memories.get_mut(usize::try_from(memory_idx).unwrap())
+                    {
+                        if copy_memory_from_guest(memory, &invocation.vm).is_err() {
+                            return self.finish_invocation(Err(()));
+                        }
+                    }
+                }
+
+                let mut scale_encoded_params = util::encode_scale_compact_usize(params.len())
+                    .as_ref()
+                    .to_vec();
+                for param in params {
+                    scale_encoded_params.extend(encode_value(param));
+                }
+
+                InvocationOutcome::DispatchThunkCall {
+                    dispatch_thunk,
+                    scale_encoded_params,
+                    state,
+                    function,
+                }
+            }
+            Err(vm::RunErr::Poisoned) => unreachable!(),
+        }
+    }
+
+    /// Removes the innermost invocation and puts back the virtual machine in its instance.
+    fn finish_invocation(&mut self, mut result: Result<Vec<u8>, ()>) -> InvocationOutcome {
+        let memory_idx = self.invocation_memory();
+        let invocation = self.invocations.pop().unwrap();
+
+        if let Some(memory_idx) = memory_idx {
+            if let Some(Some(memory)) = self.memories.get_mut(usize::try_from(memory_idx).unwrap())
+            {
+                if copy_memory_from_guest(memory, &invocation.vm).is_err() {
+                    result = Err(());
+                }
+            }
+        }
+
+        let instance = self.instances[usize::try_from(invocation.instance_idx).unwrap()]
+            .as_mut()
+            .unwrap();
+        debug_assert!(instance.vm.is_none());
+        instance.vm = Some(invocation.vm.into_prototype());
+
+        InvocationOutcome::Finished {
+            result,
+            return_value_ptr: invocation.return_value_ptr,
+            return_value_len: invocation.return_value_len,
+        }
+    }
+
+    /// Returns the index of the memory imported by the instance of the innermost invocation, if
+    /// that memory still exists.
+    fn invocation_memory(&self) -> Option<u32> {
+        let invocation = self.invocations.last().unwrap();
+        let memory_idx = self.instances[usize::try_from(invocation.instance_idx).unwrap()]
+            .as_ref()
+            .unwrap()
+            .memory?;
+        match self.memories.get(usize::try_from(memory_idx).unwrap()) {
+            Some(Some(_)) => Some(memory_idx),
+            _ => None,
+        }
+    }
+
+    fn memory(&self, memory_idx: u32) -> Result<&Memory, SandboxError> {
+        self.memories
+            .get(usize::try_from(memory_idx).unwrap())
+            .and_then(|m| m.as_ref())
+            .ok_or(SandboxError::InvalidMemoryIndex(memory_idx))
+    }
+}
+
+/// Decodes the output of the dispatch thunk, which is a SCALE-encoded
+/// `Result<ReturnValue, HostError>`.
+///
+/// Returns `None` if the output is invalid. Returns `Some(Err(()))` if the function has failed or
+/// returned a value that isn't supported.
+pub(super) fn decode_dispatch_thunk_output(
+    output: &[u8],
+) -> Option<Result<Option<vm::WasmValue>, ()>> {
+    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+        nom::combinator::all_consuming(nom::branch::alt((
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[0][..]),
+                nom::branch::alt((
+                    nom::combinator::map(nom::bytes::complete::tag(&[0][..]), |_| Ok(None)),
+                    nom::combinator::map(
+                        nom::sequence::preceded(nom::bytes::complete::tag(&[1][..]), decode_value),
+                        |value| value.map(Some).ok_or(()),
+                    ),
+                )),
+            ),
+            nom::combinator::map(nom::bytes::complete::tag(&[1][..]), |_| Err(())),
+        )))(output)
+        .map(|(_, parse_result)| parse_result);
+    parsing_result.ok()
+}
+
+/// Entity found in the environment definition passed when instantiating a guest.
+enum ExternEntity {
+    /// Function whose calls must be dispatched to the dispatch thunk with the given identifier.
+    Function(u32),
+    /// Memory of the sandbox with the given index.
+    Memory(u32),
+}
+
+/// Module name, field name, and entity of an entry of the environment definition.
+type EnvironmentEntry<'a> = (&'a [u8], &'a [u8], ExternEntity);
+
+/// Decodes the SCALE-encoded environment definition passed when instantiating a guest.
+fn decode_environment_definition(bytes: &[u8]) -> Option<Vec<EnvironmentEntry<'_>>> {
+    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+        nom::combinator::all_consuming(nom::multi::length_count(
+            util::nom_scale_compact_usize,
+            nom::sequence::tuple((
+                util::nom_bytes_decode,
+                util::nom_bytes_decode,
+                nom::branch::alt((
+                    nom::combinator::map(
+                        nom::sequence::preceded(
+                            nom::bytes::complete::tag(&[1][..]),
+                            nom::number::complete::le_u32,
+                        ),
+                        ExternEntity::Function,
+                    ),
+                    nom::combinator::map(
+                        nom::sequence::preceded(
+                            nom::bytes::complete::tag(&[2][..]),
+                            nom::number::complete::le_u32,
+                        ),
+                        ExternEntity::Memory,
+                    ),
+                )),
+            )),
+        ))(bytes)
+        .map(|(_, parse_result)| parse_result);
+    parsing_result.ok()
+}
+
+/// Decodes a SCALE-encoded `Value`. Floating point values, which aren't supported by the virtual
+/// machine, are decoded as `None`.
+fn decode_value<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], Option<vm::WasmValue>, E> {
+    nom::branch::alt((
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[0][..]),
+                nom::number::complete::le_i32,
+            ),
+            |v| Some(vm::WasmValue::I32(v)),
+        ),
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[1][..]),
+                nom::number::complete::le_i64,
+            ),
+            |v| Some(vm::WasmValue::I64(v)),
+        ),
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[2][..]),
+                nom::number::complete::le_u32,
+            ),
+            |_| None,
+        ),
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[3][..]),
+                nom::number::complete::le_u64,
+            ),
+            |_| None,
+        ),
+    ))(bytes)
+}
+
+/// Returns the SCALE encoding of a `Value`.
+fn encode_value(value: vm::WasmValue) -> impl Iterator<Item = u8> {
+    let (discriminant, bytes) = match value {
+        vm::WasmValue::I32(v) => (0, either::Left(v.to_le_bytes().into_iter())),
+        vm::WasmValue::I64(v) => (1, either::Right(v.to_le_bytes().into_iter())),
+    };
+    iter::once(discriminant).chain(bytes)
+}
+
+/// Copies the content of `memory` to the memory of the guest.
+fn copy_memory_to_guest(memory: &Memory, vm: &mut vm::VirtualMachine) -> Result<(), ()> {
+    let num_pages = u32::try_from(memory.data.len() / PAGE_SIZE).unwrap();
+    let current_pages = u32::from(vm.memory_size());
+    if current_pages < num_pages {
+        vm.grow_memory(vm::HeapPages::new(num_pages - current_pages))
+            .map_err(|_| ())?;
+    }
+    vm.write_memory(0, &memory.data).map_err(|_| ())
+}
+
+/// Copies the memory of the guest to `memory`.
+///
+/// Returns an error if the guest has grown its memory beyond [`MAX_MEMORY_PAGES`], in which case
+/// `memory` is left untouched.
+fn copy_memory_from_guest(memory: &mut Memory, vm: &vm::VirtualMachine) -> Result<(), ()> {
+    let num_pages = u32::from(vm.memory_size());
+    if num_pages > MAX_MEMORY_PAGES {
+        return Err(());
+    }
+
+    let size = num_pages
+        .checked_mul(u32::try_from(PAGE_SIZE).unwrap())
+        .ok_or(())?;
+    memory.data.clear();
+    memory
+        .data
+        .extend_from_slice(vm.read_memory(0, size).map_err(|_| ())?.as_ref());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InvocationOutcome, Sandbox, SandboxError, MAX_MEMORY_PAGES, MEMORY_UNLIMITED};
+
+    #[test]
+    fn memory_new_too_large() {
+        let mut sandbox = Sandbox::new();
+        assert!(matches!(
+            sandbox.memory_new(MAX_MEMORY_PAGES + 1, MEMORY_UNLIMITED),
+            Err(SandboxError::InvalidMemorySize)
+        ));
+        assert!(matches!(
+            sandbox.memory_new(u32::MAX, MEMORY_UNLIMITED),
+            Err(SandboxError::InvalidMemorySize)
+        ));
+        assert!(matches!(
+            sandbox.memory_new(2, 1),
+            Err(SandboxError::InvalidMemorySize)
+        ));
+        assert!(matches!(
+            sandbox.memory_new(MAX_MEMORY_PAGES, MEMORY_UNLIMITED),
+            Ok(0)
+        ));
+    }
+
+    #[test]
+    fn memory_teardown_while_in_use() {
+        let mut sandbox = Sandbox::new();
+        let memory_idx = sandbox.memory_new(1, MEMORY_UNLIMITED).unwrap();
+
+        let guest = wat::parse_str(
+            r#"
+(module
+    (import "env" "memory" (memory 1))
+    (import "env" "f" (func $f))
+    (func (export "main") call $f)
+)
+            "#,
+        )
+        .unwrap();
+
+        // Environment definition importing `env.memory` as memory 0 and `env.f` as function 7.
+        let env_def = b"\x08\x0cenv\x18memory\x02\x00\x00\x00\x00\x0cenv\x04f\x01\x07\x00\x00\x00";
+        let instance_idx = match sandbox.instantiate(0, &guest, env_def) {
+            Ok(idx) => idx,
+            Err(_) => panic!(),
+        };
+
+        match sandbox.start_invocation(instance_idx, "main", &[0], 0, 0, 0) {
+            Ok(InvocationOutcome::DispatchThunkCall { function: 7, .. }) => {}
+            _ => panic!(),
+        }
+        sandbox.set_dispatch_thunk_args_ptr(0);
+
+        // The guest is blocked in a call to `f`, and its memory can't be destroyed.
+        assert!(matches!(
+            sandbox.memory_teardown(memory_idx),
+            Err(SandboxError::MemoryBusy(idx)) if idx == memory_idx
+        ));
+
+        match sandbox.resume_invocation(Ok(None)) {
+            (_, InvocationOutcome::Finished { result: Ok(_), .. }) => {}
+            _ => panic!(),
+        }
+
+        assert!(sandbox.memory_teardown(memory_idx).is_ok());
+        assert!(matches!(
+            sandbox.memory_teardown(memory_idx),
+            Err(SandboxError::InvalidMemoryIndex(_))
+        ));
+    }
+}
@@ -0,0 +1,1163 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Execution of the off-chain worker of a block.
+//!
+//! Off-chain workers are pieces of runtime code that are executed after a block has been
+//! imported, and whose role is to perform tasks that would be too expensive or impossible to
+//! perform on-chain, such as sending HTTP requests. Off-chain workers can't modify the storage
+//! of the chain. They can, however, submit transactions to the transactions pool and read and
+//! write a storage local to the node.
+//!
+//! Use [`run`] in order to start running the off-chain worker of a block, then answer the
+//! various requests that it yields until [`OffchainWorker::Finished`] is reached.
+//!
+//! Any modification made by the off-chain worker to the storage of the chain is discarded.
+
+use crate::{
+    executor::{host, runtime_host, storage_diff},
+    header,
+};
+
+use core::iter;
+
+pub use host::{
+    OffchainHttpError as HttpError, OffchainHttpRequestStatus as HttpRequestStatus,
+    OffchainStorageKind as StorageKind,
+};
+
+/// Configuration for an off-chain worker execution.
+pub struct Config<'a> {
+    /// Runtime used to run the off-chain worker. Must be built using the Wasm code found at the
+    /// `:code` key of the storage of the block.
+    pub runtime: host::HostVmPrototype,
+
+    /// Header of the block whose off-chain worker to run, in SCALE encoding. The runtime of this
+    /// block must be the one in [`Config::runtime`].
+    pub scale_encoded_header: &'a [u8],
+
+    /// Number of bytes used to encode the block number in the header.
+    pub block_number_bytes: usize,
+}
+
+/// Problem encountered during an off-chain worker execution.
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum Error {
+    /// Error while decoding the block header.
+    #[display(fmt = "Failed to decode block header: {}", _0)]
+    InvalidHeader(header::Error),
+    /// Runtime doesn't support off-chain workers, or off-chain worker API version unrecognized.
+    UnknownApiVersion,
+    /// Error while starting the Wasm virtual machine.
+    #[display(fmt = "{}", _0)]
+    WasmStart(host::StartErr),
+    /// Error while running the Wasm virtual machine.
+    #[display(fmt = "{}", _0)]
+    WasmVm(runtime_host::ErrorDetail),
+    /// Off-chain worker has returned a non-empty output.
+    NonEmptyOutput,
+}
+
+/// Starts the off-chain worker of the given block.
+///
+/// The runtime function that is called depends on the version of the `OffchainWorkerApi` that
+/// the runtime supports.
+pub fn run(config: Config) -> OffchainWorker {
+    let decoded_header =
+        match header::decode(config.scale_encoded_header, config.block_number_bytes) {
+            Ok(h) => h,
+            Err(err) => {
+                return OffchainWorker::Finished {
+                    result: Err(Error::InvalidHeader(err)),
+                    virtual_machine: config.runtime,
+                }
+            }
+        };
+
+    let api_version = {
+        let expected = blake2_rfc::blake2b::blake2b(8, &[], b"OffchainWorkerApi");
+        config
+            .runtime
+            .runtime_version()
+            .decode()
+            .apis
+            .find(|api| api.name_hash == expected.as_ref())
+            .map(|api| api.version)
+    };
+
+    // In version 1, the off-chain worker is passed the number of the block. Starting from
+    // version 2, it is passed the entire header.
+    let parameter = match api_version {
+        Some(1) => {
+            let mut number = decoded_header.number.to_le_bytes().to_vec();
+            number.resize(config.block_number_bytes, 0);
+            number
+        }
+        Some(2) => config.scale_encoded_header.to_vec(),
+        _ => {
+            return OffchainWorker::Finished {
+                result: Err(Error::UnknownApiVersion),
+                virtual_machine: config.runtime,
+            }
+        }
+    };
+
+    let vm = runtime_host::run(runtime_host::Config {
+        virtual_machine: config.runtime,
+        function_to_call: "OffchainWorkerApi_offchain_worker",
+        parameter: iter::once(parameter),
+        top_trie_root_calculation_cache: None,
+        storage_top_trie_changes: storage_diff::StorageDiff::empty(),
+        storage_child_tries_changes: Default::default(),
+        offchain_storage_changes: storage_diff::StorageDiff::empty(),
+    });
+
+    match vm {
+        Ok(vm) => OffchainWorker::from_inner(vm),
+        Err((err, virtual_machine)) => OffchainWorker::Finished {
+            result: Err(Error::WasmStart(err)),
+            virtual_machine,
+        },
+    }
+}
+
+/// Current state of the execution.
+#[must_use]
+pub enum OffchainWorker {
+    /// Execution is over.
+    Finished {
+        /// Outcome of the execution.
+        result: Result<(), Error>,
+        /// Virtual machine initially passed through the configuration.
+        virtual_machine: host::HostVmPrototype,
+    },
+    /// Loading a storage value is required in order to continue.
+    StorageGet(StorageGet),
+    /// Fetching the key that follows a given one is required in order to continue.
+    NextKey(NextKey),
+    /// Fetching the list of keys with a given prefix from the storage is required in order to
+    /// continue.
+    PrefixKeys(PrefixKeys),
+    /// Need to indicate whether the local node is a validator.
+    IsValidator(IsValidator),
+    /// Need to submit a transaction to the transactions pool.
+    SubmitTransaction(SubmitTransaction),
+    /// Need to provide the network state of the local node.
+    NetworkState(NetworkState),
+    /// Need to provide the current UNIX timestamp.
+    Timestamp(Timestamp),
+    /// Must pause execution until a certain deadline.
+    SleepUntil(SleepUntil),
+    /// Need to provide a randomly-generated seed.
+    RandomSeed(RandomSeed),
+    /// Must load a value from the off-chain local storage.
+    LocalStorageGet(LocalStorageGet),
+    /// Must set or clear a value of the off-chain local storage.
+    LocalStorageSet(LocalStorageSet),
+    /// Must atomically compare and set a value of the off-chain local storage.
+    LocalStorageCompareAndSet(LocalStorageCompareAndSet),
+    /// Must start a new HTTP request.
+    HttpRequestStart(HttpRequestStart),
+    /// Must add a header to an HTTP request that hasn't been sent yet.
+    HttpRequestAddHeader(HttpRequestAddHeader),
+    /// Must write a chunk of the body of an HTTP request.
+    HttpRequestWriteBody(HttpRequestWriteBody),
+    /// Must wait for HTTP requests to receive a response.
+    HttpResponseWait(HttpResponseWait),
+    /// Need to provide the headers of the response of an HTTP request.
+    HttpResponseHeaders(HttpResponseHeaders),
+    /// Need to provide a chunk of the body of the response of an HTTP request.
+    HttpResponseReadBody(HttpResponseReadBody),
+    /// Need to generate a new ECDSA key pair and store it in the keystore.
+    EcdsaGenerate(EcdsaGenerate),
+    /// Need to provide the list of ECDSA public keys found in the keystore.
+    EcdsaPublicKeys(EcdsaPublicKeys),
+}
+
+impl OffchainWorker {
+    /// Cancels execution of the virtual machine and returns back the prototype.
+    pub fn into_prototype(self) -> host::HostVmPrototype {
+        match self {
+            OffchainWorker::Finished {
+                virtual_machine, ..
+            } => virtual_machine,
+            OffchainWorker::StorageGet(StorageGet(inner)) => {
+                runtime_host::RuntimeHostVm::StorageGet(inner).into_prototype()
+            }
+            OffchainWorker::NextKey(NextKey(inner)) => {
+                runtime_host::RuntimeHostVm::NextKey(inner).into_prototype()
+            }
+            OffchainWorker::PrefixKeys(PrefixKeys(inner)) => {
+                runtime_host::RuntimeHostVm::PrefixKeys(inner).into_prototype()
+            }
+            OffchainWorker::IsValidator(IsValidator(inner))
+            | OffchainWorker::SubmitTransaction(SubmitTransaction(inner))
+            | OffchainWorker::NetworkState(NetworkState(inner))
+            | OffchainWorker::Timestamp(Timestamp(inner))
+            | OffchainWorker::SleepUntil(SleepUntil(inner))
+            | OffchainWorker::RandomSeed(RandomSeed(inner))
+            | OffchainWorker::LocalStorageGet(LocalStorageGet(inner))
+            | OffchainWorker::LocalStorageSet(LocalStorageSet(inner))
+            | OffchainWorker::LocalStorageCompareAndSet(LocalStorageCompareAndSet(inner))
+            | OffchainWorker::HttpRequestStart(HttpRequestStart(inner))
+            | OffchainWorker::HttpRequestAddHeader(HttpRequestAddHeader(inner))
+            | OffchainWorker::HttpRequestWriteBody(HttpRequestWriteBody(inner))
+            | OffchainWorker::HttpResponseWait(HttpResponseWait(inner))
+            | OffchainWorker::HttpResponseHeaders(HttpResponseHeaders(inner))
+            | OffchainWorker::HttpResponseReadBody(HttpResponseReadBody(inner))
+            | OffchainWorker::EcdsaGenerate(EcdsaGenerate(inner))
+            | OffchainWorker::EcdsaPublicKeys(EcdsaPublicKeys(inner)) => {
+                runtime_host::RuntimeHostVm::Offchain(inner).into_prototype()
+            }
+        }
+    }
+
+    fn from_inner(mut inner: runtime_host::RuntimeHostVm) -> Self {
+        loop {
+            break match inner {
+                runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                    let result = if success.virtual_machine.value().as_ref().is_empty() {
+                        Ok(())
+                    } else {
+                        Err(Error::NonEmptyOutput)
+                    };
+
+                    OffchainWorker::Finished {
+                        result,
+                        virtual_machine: success.virtual_machine.into_prototype(),
+                    }
+                }
+                runtime_host::RuntimeHostVm::Finished(Err(err)) => OffchainWorker::Finished {
+                    result: Err(Error::WasmVm(err.detail)),
+                    virtual_machine: err.prototype,
+                },
+                runtime_host::RuntimeHostVm::StorageGet(i) => {
+                    OffchainWorker::StorageGet(StorageGet(i))
+                }
+                runtime_host::RuntimeHostVm::PrefixKeys(i) => {
+                    OffchainWorker::PrefixKeys(PrefixKeys(i))
+                }
+                runtime_host::RuntimeHostVm::NextKey(i) => OffchainWorker::NextKey(NextKey(i)),
+                runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                    inner = sig.verify_and_resume();
+                    continue;
+                }
+                runtime_host::RuntimeHostVm::Offchain(ctx) => match ctx.host_vm() {
+                    host::HostVm::OffchainIsValidator(_) => {
+                        OffchainWorker::IsValidator(IsValidator(ctx))
+                    }
+                    host::HostVm::OffchainSubmitTransaction(_) => {
+                        OffchainWorker::SubmitTransaction(SubmitTransaction(ctx))
+                    }
+                    host::HostVm::OffchainNetworkState(_) => {
+                        OffchainWorker::NetworkState(NetworkState(ctx))
+                    }
+                    host::HostVm::OffchainTimestamp(_) => OffchainWorker::Timestamp(Timestamp(ctx)),
+                    host::HostVm::OffchainSleepUntil(_) => {
+                        OffchainWorker::SleepUntil(SleepUntil(ctx))
+                    }
+                    host::HostVm::OffchainRandomSeed(_) => {
+                        OffchainWorker::RandomSeed(RandomSeed(ctx))
+                    }
+                    host::HostVm::OffchainLocalStorageGet(_) => {
+                        OffchainWorker::LocalStorageGet(LocalStorageGet(ctx))
+                    }
+                    host::HostVm::OffchainLocalStorageSet(_) => {
+                        OffchainWorker::LocalStorageSet(LocalStorageSet(ctx))
+                    }
+                    host::HostVm::OffchainLocalStorageCompareAndSet(_) => {
+                        OffchainWorker::LocalStorageCompareAndSet(LocalStorageCompareAndSet(ctx))
+                    }
+                    host::HostVm::OffchainHttpRequestStart(_) => {
+                        OffchainWorker::HttpRequestStart(HttpRequestStart(ctx))
+                    }
+                    host::HostVm::OffchainHttpRequestAddHeader(_) => {
+                        OffchainWorker::HttpRequestAddHeader(HttpRequestAddHeader(ctx))
+                    }
+                    host::HostVm::OffchainHttpRequestWriteBody(_) => {
+                        OffchainWorker::HttpRequestWriteBody(HttpRequestWriteBody(ctx))
+                    }
+                    host::HostVm::OffchainHttpResponseWait(_) => {
+                        OffchainWorker::HttpResponseWait(HttpResponseWait(ctx))
+                    }
+                    host::HostVm::OffchainHttpResponseHeaders(_) => {
+                        OffchainWorker::HttpResponseHeaders(HttpResponseHeaders(ctx))
+                    }
+                    host::HostVm::OffchainHttpResponseReadBody(_) => {
+                        OffchainWorker::HttpResponseReadBody(HttpResponseReadBody(ctx))
+                    }
+                    host::HostVm::CryptoEcdsaGenerate(_) => {
+                        OffchainWorker::EcdsaGenerate(EcdsaGenerate(ctx))
+                    }
+                    host::HostVm::CryptoEcdsaPublicKeys(_) => {
+                        OffchainWorker::EcdsaPublicKeys(EcdsaPublicKeys(ctx))
+                    }
+                    _ => unreachable!(),
+                },
+            };
+        }
+    }
+}
+
+/// Loading a storage value is required in order to continue.
+#[must_use]
+pub struct StorageGet(runtime_host::StorageGet);
+
+impl StorageGet {
+    /// Returns the key whose value must be passed to [`StorageGet::inject_value`].
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.0.key()
+    }
+
+    /// If `Some`, the key must be read from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.0.child_trie()
+    }
+
+    /// Injects the corresponding storage value.
+    pub fn inject_value(
+        self,
+        value: Option<impl Iterator<Item = impl AsRef<[u8]>>>,
+    ) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.inject_value(value))
+    }
+}
+
+/// Fetching the key that follows a given one is required in order to continue.
+#[must_use]
+pub struct NextKey(runtime_host::NextKey);
+
+impl NextKey {
+    /// Returns the key whose next key must be passed back.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.0.key()
+    }
+
+    /// If `Some`, the next key must be searched in the given default child trie rather than in
+    /// the main trie. The child trie identifier doesn't include the `:child_storage:default:`
+    /// prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.0.child_trie()
+    }
+
+    /// Injects the key.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the key passed as parameter isn't strictly superior to the requested key.
+    ///
+    pub fn inject_key(self, key: Option<impl AsRef<[u8]>>) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.inject_key(key))
+    }
+}
+
+/// Fetching the list of keys with a given prefix from the storage is required in order to
+/// continue.
+#[must_use]
+pub struct PrefixKeys(runtime_host::PrefixKeys);
+
+impl PrefixKeys {
+    /// Returns the prefix whose keys to load.
+    pub fn prefix(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.0.prefix()
+    }
+
+    /// If `Some`, the keys must be loaded from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.0.child_trie()
+    }
+
+    /// Injects the list of keys ordered lexicographically.
+    pub fn inject_keys_ordered(
+        self,
+        keys: impl Iterator<Item = impl AsRef<[u8]>>,
+    ) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.inject_keys_ordered(keys))
+    }
+}
+
+/// Need to indicate whether the local node is a validator.
+#[must_use]
+pub struct IsValidator(runtime_host::OffchainContext);
+
+impl IsValidator {
+    /// Resumes execution after indicating whether the local node is a validator.
+    pub fn resume(self, is_validator: bool) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainIsValidator(req) => req.resume(is_validator),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Need to submit a transaction to the transactions pool.
+#[must_use]
+pub struct SubmitTransaction(runtime_host::OffchainContext);
+
+impl SubmitTransaction {
+    /// Returns the SCALE-encoded transaction to submit.
+    pub fn transaction(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match self.0.host_vm() {
+            host::HostVm::OffchainSubmitTransaction(req) => req.transaction(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having tried to submit the transaction.
+    ///
+    /// `success` must be `false` if the transaction couldn't be submitted.
+    pub fn resume(self, success: bool) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainSubmitTransaction(req) => req.resume(success),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Need to provide the network state of the local node.
+#[must_use]
+pub struct NetworkState(runtime_host::OffchainContext);
+
+impl NetworkState {
+    /// Resumes execution after having obtained the network state.
+    ///
+    /// If `Ok`, must contain the encoded `PeerId` of the local node and the list of the
+    /// encoded multiaddresses it is reachable at.
+    pub fn resume(
+        self,
+        state: Result<(&[u8], impl ExactSizeIterator<Item = impl AsRef<[u8]>>), ()>,
+    ) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainNetworkState(req) => req.resume(state),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Need to provide the current UNIX timestamp.
+#[must_use]
+pub struct Timestamp(runtime_host::OffchainContext);
+
+impl Timestamp {
+    /// Resumes execution after having obtained the current UNIX timestamp, in milliseconds.
+    pub fn resume(self, timestamp_ms: u64) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainTimestamp(req) => req.resume(timestamp_ms),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Must pause execution until a certain deadline.
+#[must_use]
+pub struct SleepUntil(runtime_host::OffchainContext);
+
+impl SleepUntil {
+    /// Returns the UNIX timestamp, in milliseconds, until which execution must be paused.
+    pub fn deadline(&self) -> u64 {
+        match self.0.host_vm() {
+            host::HostVm::OffchainSleepUntil(req) => req.deadline(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after the deadline has been reached.
+    pub fn resume(self) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainSleepUntil(req) => req.resume(),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Need to provide a randomly-generated seed.
+#[must_use]
+pub struct RandomSeed(runtime_host::OffchainContext);
+
+impl RandomSeed {
+    /// Resumes execution after having generated a seed.
+    ///
+    /// The seed should be generated using a cryptographically-secure source of randomness.
+    pub fn resume(self, seed: &[u8; 32]) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainRandomSeed(req) => req.resume(seed),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Must load a value from the off-chain local storage.
+#[must_use]
+pub struct LocalStorageGet(runtime_host::OffchainContext);
+
+impl LocalStorageGet {
+    /// Returns the kind of storage to load the value from.
+    pub fn kind(&self) -> StorageKind {
+        match self.0.host_vm() {
+            host::HostVm::OffchainLocalStorageGet(req) => req.kind(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the key whose value must be passed to [`LocalStorageGet::resume`].
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match self.0.host_vm() {
+            host::HostVm::OffchainLocalStorageGet(req) => req.key(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Injects the corresponding value and resumes execution.
+    pub fn resume(self, value: Option<&[u8]>) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainLocalStorageGet(req) => req.resume(value),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Must set or clear a value of the off-chain local storage.
+#[must_use]
+pub struct LocalStorageSet(runtime_host::OffchainContext);
+
+impl LocalStorageSet {
+    /// Returns the kind of storage to modify.
+    pub fn kind(&self) -> StorageKind {
+        match self.0.host_vm() {
+            host::HostVm::OffchainLocalStorageSet(req) => req.kind(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the key whose value must be set.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match self.0.host_vm() {
+            host::HostVm::OffchainLocalStorageSet(req) => req.key(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the value to set.
+    ///
+    /// If `None` is returned, the key should be removed from the storage entirely.
+    pub fn value(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        match self.0.host_vm() {
+            host::HostVm::OffchainLocalStorageSet(req) => req.value(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having set the value.
+    pub fn resume(self) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainLocalStorageSet(req) => req.resume(),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Must set a value of the off-chain local storage, but only if the current value matches the
+/// expected one.
+#[must_use]
+pub struct LocalStorageCompareAndSet(runtime_host::OffchainContext);
+
+impl LocalStorageCompareAndSet {
+    /// Returns the kind of storage to modify.
+    pub fn kind(&self) -> StorageKind {
+        match self.0.host_vm() {
+            host::HostVm::OffchainLocalStorageCompareAndSet(req) => req.kind(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the key whose value must be compared and set.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match self.0.host_vm() {
+            host::HostVm::OffchainLocalStorageCompareAndSet(req) => req.key(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the value that the storage entry must currently have in order for the new value
+    /// to be set. `None` means that the storage entry must be absent.
+    pub fn old_value(&self) -> Option<&[u8]> {
+        match self.0.host_vm() {
+            host::HostVm::OffchainLocalStorageCompareAndSet(req) => req.old_value(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the value to set if the current value matches
+    /// [`LocalStorageCompareAndSet::old_value`].
+    pub fn new_value(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match self.0.host_vm() {
+            host::HostVm::OffchainLocalStorageCompareAndSet(req) => req.new_value(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution. `replaced` must be `true` if the current value has matched
+    /// [`LocalStorageCompareAndSet::old_value`] and has been replaced.
+    pub fn resume(self, replaced: bool) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainLocalStorageCompareAndSet(req) => req.resume(replaced),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Must start a new HTTP request.
+#[must_use]
+pub struct HttpRequestStart(runtime_host::OffchainContext);
+
+impl HttpRequestStart {
+    /// Returns the HTTP method of the request, such as `GET` or `POST`.
+    pub fn method(&'_ self) -> impl AsRef<str> + '_ {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpRequestStart(req) => req.method(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the URI the request must be sent to.
+    pub fn uri(&'_ self) -> impl AsRef<str> + '_ {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpRequestStart(req) => req.uri(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having started the request.
+    ///
+    /// If `Ok`, must contain the identifier of the newly-started request.
+    pub fn resume(self, request_id: Result<u16, ()>) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainHttpRequestStart(req) => req.resume(request_id),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Must add a header to an HTTP request that hasn't been sent yet.
+#[must_use]
+pub struct HttpRequestAddHeader(runtime_host::OffchainContext);
+
+impl HttpRequestAddHeader {
+    /// Returns the identifier of the request, as previously passed to
+    /// [`HttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => req.request_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the name of the header to add.
+    pub fn name(&'_ self) -> impl AsRef<str> + '_ {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => req.name(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the value of the header to add.
+    pub fn value(&'_ self) -> impl AsRef<str> + '_ {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => req.value(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having added the header.
+    ///
+    /// An error must be returned if the request identifier is invalid or if the request has
+    /// already been sent.
+    pub fn resume(self, result: Result<(), ()>) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => req.resume(result),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Must write a chunk of the body of an HTTP request.
+#[must_use]
+pub struct HttpRequestWriteBody(runtime_host::OffchainContext);
+
+impl HttpRequestWriteBody {
+    /// Returns the identifier of the request, as previously passed to
+    /// [`HttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => req.request_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the chunk of body to write.
+    ///
+    /// If the chunk is empty, the body is complete and the request must be finalized.
+    pub fn chunk(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => req.chunk(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the UNIX timestamp, in milliseconds, after which the operation must be aborted.
+    /// `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => req.deadline(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having written the chunk.
+    pub fn resume(self, result: Result<(), HttpError>) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => req.resume(result),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Must wait for HTTP requests to receive a response.
+#[must_use]
+pub struct HttpResponseWait(runtime_host::OffchainContext);
+
+impl HttpResponseWait {
+    /// Returns the identifiers of the requests to wait for.
+    pub fn request_ids(&self) -> &[u16] {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpResponseWait(req) => req.request_ids(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the UNIX timestamp, in milliseconds, after which waiting must stop. `None` if
+    /// there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpResponseWait(req) => req.deadline(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having waited for the requests.
+    ///
+    /// Must be passed the status of each request, in the same order as
+    /// [`HttpResponseWait::request_ids`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the number of statuses doesn't match the number of requests.
+    ///
+    pub fn resume(
+        self,
+        statuses: impl ExactSizeIterator<Item = HttpRequestStatus>,
+    ) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainHttpResponseWait(req) => req.resume(statuses),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Need to provide the headers of the response of an HTTP request.
+#[must_use]
+pub struct HttpResponseHeaders(runtime_host::OffchainContext);
+
+impl HttpResponseHeaders {
+    /// Returns the identifier of the request, as previously passed to
+    /// [`HttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpResponseHeaders(req) => req.request_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having obtained the list of headers of the response.
+    ///
+    /// An empty list must be passed if the request identifier is invalid or if no response has
+    /// been received yet.
+    pub fn resume(
+        self,
+        headers: impl ExactSizeIterator<Item = (impl AsRef<[u8]>, impl AsRef<[u8]>)>,
+    ) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainHttpResponseHeaders(req) => req.resume(headers),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Need to provide a chunk of the body of the response of an HTTP request.
+#[must_use]
+pub struct HttpResponseReadBody(runtime_host::OffchainContext);
+
+impl HttpResponseReadBody {
+    /// Returns the identifier of the request, as previously passed to
+    /// [`HttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpResponseReadBody(req) => req.request_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the maximum number of bytes that can be passed back.
+    pub fn max_size(&self) -> u32 {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpResponseReadBody(req) => req.max_size(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the UNIX timestamp, in milliseconds, after which the operation must be aborted.
+    /// `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        match self.0.host_vm() {
+            host::HostVm::OffchainHttpResponseReadBody(req) => req.deadline(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having read a chunk of the body.
+    ///
+    /// An empty chunk indicates that the end of the body has been reached.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chunk is longer than what [`HttpResponseReadBody::max_size`] returns.
+    ///
+    pub fn resume(self, result: Result<&[u8], HttpError>) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::OffchainHttpResponseReadBody(req) => req.resume(result),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Need to generate a new ECDSA key pair and store it in the keystore.
+#[must_use]
+pub struct EcdsaGenerate(runtime_host::OffchainContext);
+
+impl EcdsaGenerate {
+    /// Returns the identifier of the type of key to generate, such as `beef` for BEEFY.
+    pub fn key_type_id(&self) -> &[u8; 4] {
+        match self.0.host_vm() {
+            host::HostVm::CryptoEcdsaGenerate(req) => req.key_type_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the seed phrase that the private key must be derived from.
+    ///
+    /// If `None`, the private key must be randomly generated.
+    pub fn seed(&self) -> Option<&str> {
+        match self.0.host_vm() {
+            host::HostVm::CryptoEcdsaGenerate(req) => req.seed(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having generated the key. Must be passed the compressed public
+    /// key of the new key pair.
+    pub fn resume(self, public_key: &[u8; 33]) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::CryptoEcdsaGenerate(req) => req.resume(public_key),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Need to provide the list of ECDSA public keys found in the keystore.
+#[must_use]
+pub struct EcdsaPublicKeys(runtime_host::OffchainContext);
+
+impl EcdsaPublicKeys {
+    /// Returns the identifier of the type of key whose public keys must be provided, such as
+    /// `beef` for BEEFY.
+    pub fn key_type_id(&self) -> &[u8; 4] {
+        match self.0.host_vm() {
+            host::HostVm::CryptoEcdsaPublicKeys(req) => req.key_type_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having obtained the compressed public keys.
+    pub fn resume(self, public_keys: impl ExactSizeIterator<Item = [u8; 33]>) -> OffchainWorker {
+        OffchainWorker::from_inner(self.0.resume(|vm| match vm {
+            host::HostVm::CryptoEcdsaPublicKeys(req) => req.resume(public_keys),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, HttpRequestStatus, OffchainWorker, StorageKind};
+    use crate::{executor::host, header};
+    use core::iter;
+
+    /// Builds a runtime whose off-chain worker successively queries the current time, writes,
+    /// reads and compares-and-sets the key `key` of the local storage, performs an HTTP `GET`
+    /// request, and submits the transaction `tx`.
+    fn test_runtime() -> host::HostVmPrototype {
+        let api_name_hash = blake2_rfc::blake2b::blake2b(8, &[], b"OffchainWorkerApi");
+        let api_name_hash = api_name_hash
+            .as_bytes()
+            .iter()
+            .map(|b| format!("\\{:02x}", b))
+            .collect::<String>();
+
+        let module = wat::parse_str(format!(
+            r#"
+(module
+    (import "env" "memory" (memory 1))
+    (import "env" "ext_offchain_timestamp_version_1" (func $timestamp (result i64)))
+    (import "env" "ext_offchain_local_storage_set_version_1" (func $local_storage_set (param i32 i64 i64)))
+    (import "env" "ext_offchain_local_storage_get_version_1" (func $local_storage_get (param i32 i64) (result i64)))
+    (import "env" "ext_offchain_local_storage_compare_and_set_version_1" (func $local_storage_compare_and_set (param i32 i64 i64 i64) (result i32)))
+    (import "env" "ext_offchain_http_request_start_version_1" (func $http_request_start (param i64 i64 i64) (result i64)))
+    (import "env" "ext_offchain_http_request_add_header_version_1" (func $http_request_add_header (param i32 i64 i64) (result i64)))
+    (import "env" "ext_offchain_http_request_write_body_version_1" (func $http_request_write_body (param i32 i64 i64) (result i64)))
+    (import "env" "ext_offchain_http_response_wait_version_1" (func $http_response_wait (param i64 i64) (result i64)))
+    (import "env" "ext_offchain_http_response_headers_version_1" (func $http_response_headers (param i32) (result i64)))
+    (import "env" "ext_offchain_http_response_read_body_version_1" (func $http_response_read_body (param i32 i64 i64) (result i64)))
+    (import "env" "ext_offchain_submit_transaction_version_1" (func $submit_transaction (param i64) (result i64)))
+    (global (export "__heap_base") i32 (i32.const 1024))
+
+    ;; SCALE-encoded runtime version, returned by `Core_version`, that supports version 2 of
+    ;; the `OffchainWorkerApi`.
+    (data (i32.const 0) "\10test\10test\00\00\00\00\00\00\00\00\00\00\00\00\04{api_name_hash}\02\00\00\00")
+    (data (i32.const 64) "key")
+    (data (i32.const 72) "value")
+    (data (i32.const 80) "\01\14value")
+    (data (i32.const 96) "new")
+    (data (i32.const 104) "GET")
+    (data (i32.const 112) "http://example.com")
+    (data (i32.const 136) "\00")
+    (data (i32.const 144) "name")
+    (data (i32.const 152) "val")
+    (data (i32.const 160) "body")
+    (data (i32.const 168) "\00")
+    (data (i32.const 176) "\04\00\00")
+    (data (i32.const 184) "tx")
+
+    (func (export "Core_version") (param i32 i32) (result i64)
+        i64.const 0x0000002300000000)
+
+    (func (export "OffchainWorkerApi_offchain_worker") (param i32 i32) (result i64)
+        (if (i64.ne (call $timestamp) (i64.const 1234)) (then unreachable))
+        (call $local_storage_set (i32.const 2) (i64.const 0x0000000300000040) (i64.const 0x0000000500000048))
+        (drop (call $local_storage_get (i32.const 2) (i64.const 0x0000000300000040)))
+        (if (i32.eqz (call $local_storage_compare_and_set (i32.const 2) (i64.const 0x0000000300000040) (i64.const 0x0000000700000050) (i64.const 0x0000000300000060)))
+            (then unreachable))
+        (drop (call $http_request_start (i64.const 0x0000000300000068) (i64.const 0x0000001200000070) (i64.const 0x0000000100000088)))
+        (drop (call $http_request_add_header (i32.const 0) (i64.const 0x0000000400000090) (i64.const 0x0000000300000098)))
+        (drop (call $http_request_write_body (i32.const 0) (i64.const 0x00000004000000a0) (i64.const 0x00000001000000a8)))
+        (drop (call $http_response_wait (i64.const 0x00000003000000b0) (i64.const 0x00000001000000a8)))
+        (drop (call $http_response_headers (i32.const 0)))
+        (drop (call $http_response_read_body (i32.const 0) (i64.const 0x00000010000000c0) (i64.const 0x00000001000000a8)))
+        (drop (call $submit_transaction (i64.const 0x00000002000000b8)))
+        i64.const 0)
+)
+            "#
+        ))
+        .unwrap();
+
+        host::HostVmPrototype::new(host::Config {
+            module: &module,
+            heap_pages: host::HeapPages::new(1),
+            exec_hint: crate::executor::vm::ExecHint::ForceWasmi,
+            allow_unresolved_imports: false,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn requests_sequence() {
+        let scale_encoded_header = header::HeaderRef {
+            parent_hash: &[0; 32],
+            number: 1,
+            state_root: &[0; 32],
+            extrinsics_root: &[0; 32],
+            digest: header::DigestRef::empty(),
+        }
+        .scale_encoding_vec(4);
+
+        let worker = super::run(Config {
+            runtime: test_runtime(),
+            scale_encoded_header: &scale_encoded_header,
+            block_number_bytes: 4,
+        });
+
+        let worker = match worker {
+            OffchainWorker::Timestamp(req) => req.resume(1234),
+            _ => panic!(),
+        };
+
+        let worker = match worker {
+            OffchainWorker::LocalStorageSet(req) => {
+                assert_eq!(req.kind(), StorageKind::Local);
+                assert_eq!(req.key().as_ref(), b"key");
+                assert_eq!(req.value().unwrap().as_ref(), b"value");
+                req.resume()
+            }
+            _ => panic!(),
+        };
+
+        let worker = match worker {
+            OffchainWorker::LocalStorageGet(req) => {
+                assert_eq!(req.kind(), StorageKind::Local);
+                assert_eq!(req.key().as_ref(), b"key");
+                req.resume(Some(b"value"))
+            }
+            _ => panic!(),
+        };
+
+        let worker = match worker {
+            OffchainWorker::LocalStorageCompareAndSet(req) => {
+                assert_eq!(req.kind(), StorageKind::Local);
+                assert_eq!(req.key().as_ref(), b"key");
+                assert_eq!(req.old_value(), Some(&b"value"[..]));
+                assert_eq!(req.new_value().as_ref(), b"new");
+                req.resume(true)
+            }
+            _ => panic!(),
+        };
+
+        let worker = match worker {
+            OffchainWorker::HttpRequestStart(req) => {
+                assert_eq!(req.method().as_ref(), "GET");
+                assert_eq!(req.uri().as_ref(), "http://example.com");
+                req.resume(Ok(0))
+            }
+            _ => panic!(),
+        };
+
+        let worker = match worker {
+            OffchainWorker::HttpRequestAddHeader(req) => {
+                assert_eq!(req.request_id(), 0);
+                assert_eq!(req.name().as_ref(), "name");
+                assert_eq!(req.value().as_ref(), "val");
+                req.resume(Ok(()))
+            }
+            _ => panic!(),
+        };
+
+        let worker = match worker {
+            OffchainWorker::HttpRequestWriteBody(req) => {
+                assert_eq!(req.request_id(), 0);
+                assert_eq!(req.chunk().as_ref(), b"body");
+                assert_eq!(req.deadline(), None);
+                req.resume(Ok(()))
+            }
+            _ => panic!(),
+        };
+
+        let worker = match worker {
+            OffchainWorker::HttpResponseWait(req) => {
+                assert_eq!(req.request_ids(), &[0]);
+                assert_eq!(req.deadline(), None);
+                req.resume(iter::once(HttpRequestStatus::Finished(200)))
+            }
+            _ => panic!(),
+        };
+
+        let worker = match worker {
+            OffchainWorker::HttpResponseHeaders(req) => {
+                assert_eq!(req.request_id(), 0);
+                req.resume(iter::once((b"content-type", b"text/plain")))
+            }
+            _ => panic!(),
+        };
+
+        let worker = match worker {
+            OffchainWorker::HttpResponseReadBody(req) => {
+                assert_eq!(req.request_id(), 0);
+                assert_eq!(req.max_size(), 16);
+                assert_eq!(req.deadline(), None);
+                req.resume(Ok(b"hello"))
+            }
+            _ => panic!(),
+        };
+
+        let worker = match worker {
+            OffchainWorker::SubmitTransaction(req) => {
+                assert_eq!(req.transaction().as_ref(), b"tx");
+                req.resume(true)
+            }
+            _ => panic!(),
+        };
+
+        match worker {
+            OffchainWorker::Finished { result: Ok(()), .. } => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn unknown_api_version() {
+        let scale_encoded_header = header::HeaderRef {
+            parent_hash: &[0; 32],
+            number: 1,
+            state_root: &[0; 32],
+            extrinsics_root: &[0; 32],
+            digest: header::DigestRef::empty(),
+        }
+        .scale_encoding_vec(4);
+
+        let module = wat::parse_str(
+            r#"
+(module
+    (import "env" "memory" (memory 1))
+    (global (export "__heap_base") i32 (i32.const 1024))
+    (data (i32.const 0) "\10test\10test\00\00\00\00\00\00\00\00\00\00\00\00\00")
+    (func (export "Core_version") (param i32 i32) (result i64)
+        i64.const 0x0000001700000000)
+)
+            "#,
+        )
+        .unwrap();
+
+        let runtime = host::HostVmPrototype::new(host::Config {
+            module: &module,
+            heap_pages: host::HeapPages::new(1),
+            exec_hint: crate::executor::vm::ExecHint::ForceWasmi,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+
+        match super::run(Config {
+            runtime,
+            scale_encoded_header: &scale_encoded_header,
+            block_number_bytes: 4,
+        }) {
+            OffchainWorker::Finished {
+                result: Err(super::Error::UnknownApiVersion),
+                ..
+            } => {}
+            _ => panic!(),
+        }
+    }
+}
@@ -43,7 +43,7 @@ use crate::{
     util,
 };
 
-use alloc::{borrow::ToOwned as _, string::String, vec::Vec};
+use alloc::{borrow::ToOwned as _, collections::BTreeMap, string::String, vec::Vec};
 use core::fmt;
 use hashbrown::{hash_map::Entry, HashMap, HashSet};
 
@@ -67,6 +67,10 @@ pub struct Config<'a, TParams> {
     /// execution will be pushed over the value in this field.
     pub storage_top_trie_changes: storage_diff::StorageDiff,
 
+    /// Initial state of [`Success::storage_child_tries_changes`]. The changes made during this
+    /// execution will be pushed over the value in this field.
+    pub storage_child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
+
     /// Initial state of [`Success::offchain_storage_changes`]. The changes made during this
     /// execution will be pushed over the value in this field.
     pub offchain_storage_changes: storage_diff::StorageDiff,
@@ -83,11 +87,14 @@ pub fn run(
             .into(),
         top_trie_changes: config.storage_top_trie_changes,
         top_trie_transaction_revert: Vec::new(),
+        child_tries_changes: config.storage_child_tries_changes,
+        child_tries_transaction_revert: Vec::new(),
         offchain_storage_changes: config.offchain_storage_changes,
         top_trie_root_calculation_cache: Some(
             config.top_trie_root_calculation_cache.unwrap_or_default(),
         ),
         root_calculation: None,
+        child_tries_roots_to_fold: None,
         logs: String::new(),
     }
     .run())
@@ -101,6 +108,12 @@ pub struct Success {
     pub virtual_machine: SuccessVirtualMachine,
     /// List of changes to the storage top trie that the block performs.
     pub storage_top_trie_changes: storage_diff::StorageDiff,
+    /// List of changes to the default child tries that the block performs. Keys are the
+    /// identifiers of the child tries, without the `:child_storage:default:` prefix.
+    ///
+    /// The roots of the child tries are written in [`Success::storage_top_trie_changes`] only
+    /// when the runtime calculates the root of the top trie or of the child trie.
+    pub storage_child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
     /// List of changes to the off-chain storage that this block performs.
     pub offchain_storage_changes: storage_diff::StorageDiff,
     /// Cache used for calculating the top trie root.
@@ -153,6 +166,9 @@ pub enum ErrorDetail {
     },
     /// Size of the logs generated by the runtime exceeds the limit.
     LogsTooLong,
+    /// The runtime has called a host function that is only available to off-chain workers or
+    /// that requires access to the keystore. See [`OffchainContext::forbid`].
+    ForbiddenHostCall,
 }
 
 /// Current state of the execution.
@@ -168,6 +184,17 @@ pub enum RuntimeHostVm {
     NextKey(NextKey),
     /// Verifying whether a signature is correct is required in order to continue.
     SignatureVerification(SignatureVerification),
+    /// The runtime has called a host function that is only available to off-chain workers, or
+    /// that requires access to the keystore.
+    ///
+    /// Use the [`super::offchain_worker`] module in order to run an off-chain worker. In other
+    /// situations, call [`OffchainContext::forbid`] to abort the execution.
+    ///
+    /// > **Note**: Accessing the keystore is only supported from within off-chain workers.
+    /// >           Runtime calls that generate keys, such as
+    /// >           `SessionKeys_generate_session_keys` (used to implement `author_rotateKeys`),
+    /// >           are consequently not supported.
+    Offchain(OffchainContext),
 }
 
 impl RuntimeHostVm {
@@ -180,6 +207,7 @@ impl RuntimeHostVm {
             RuntimeHostVm::PrefixKeys(inner) => inner.inner.vm.into_prototype(),
             RuntimeHostVm::NextKey(inner) => inner.inner.vm.into_prototype(),
             RuntimeHostVm::SignatureVerification(inner) => inner.inner.vm.into_prototype(),
+            RuntimeHostVm::Offchain(inner) => inner.inner.vm.into_prototype(),
         }
     }
 }
@@ -193,30 +221,37 @@ pub struct StorageGet {
 impl StorageGet {
     /// Returns the key whose value must be passed to [`StorageGet::inject_value`].
     pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
-        enum Three<A, B, C> {
+        enum Four<A, B, C, D> {
             A(A),
             B(B),
             C(C),
+            D(D),
         }
 
-        impl<A: AsRef<[u8]>, B: AsRef<[u8]>, C: AsRef<[u8]>> AsRef<[u8]> for Three<A, B, C> {
+        impl<A: AsRef<[u8]>, B: AsRef<[u8]>, C: AsRef<[u8]>, D: AsRef<[u8]>> AsRef<[u8]>
+            for Four<A, B, C, D>
+        {
             fn as_ref(&self) -> &[u8] {
                 match self {
-                    Three::A(a) => a.as_ref(),
-                    Three::B(b) => b.as_ref(),
-                    Three::C(c) => c.as_ref(),
+                    Four::A(a) => a.as_ref(),
+                    Four::B(b) => b.as_ref(),
+                    Four::C(c) => c.as_ref(),
+                    Four::D(d) => d.as_ref(),
                 }
             }
         }
 
         match &self.inner.vm {
-            host::HostVm::ExternalStorageGet(req) => Three::A(req.key()),
-            host::HostVm::ExternalStorageAppend(req) => Three::B(req.key()),
-            host::HostVm::ExternalStorageRoot(_) => {
-                if let calculate_root::RootMerkleValueCalculation::StorageValue(value_request) =
-                    self.inner.root_calculation.as_ref().unwrap()
+            host::HostVm::ExternalStorageGet(req) => Four::A(req.key()),
+            host::HostVm::ExternalStorageAppend(req) => Four::B(req.key()),
+            host::HostVm::ExternalChildStorageGet(req) => Four::C(req.key()),
+            host::HostVm::ExternalStorageRoot(_) | host::HostVm::ExternalChildStorageRoot(_) => {
+                if let Some((
+                    _,
+                    calculate_root::RootMerkleValueCalculation::StorageValue(value_request),
+                )) = self.inner.root_calculation.as_ref()
                 {
-                    Three::C(value_request.key().collect::<Vec<_>>())
+                    Four::D(value_request.key().collect::<Vec<_>>())
                 } else {
                     // We only create a `StorageGet` if the state is `StorageValue`.
                     panic!()
@@ -228,6 +263,27 @@ impl StorageGet {
         }
     }
 
+    /// If `Some`, the key must be read from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        match &self.inner.vm {
+            host::HostVm::ExternalStorageGet(_) | host::HostVm::ExternalStorageAppend(_) => None,
+            host::HostVm::ExternalChildStorageGet(req) => Some(either::Left(req.child_trie())),
+            host::HostVm::ExternalStorageRoot(_) | host::HostVm::ExternalChildStorageRoot(_) => {
+                self.inner
+                    .root_calculation
+                    .as_ref()
+                    .unwrap()
+                    .0
+                    .as_ref()
+                    .map(either::Right)
+            }
+
+            // We only create a `StorageGet` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
     /// Injects the corresponding storage value.
     pub fn inject_value(
         mut self,
@@ -246,6 +302,10 @@ impl StorageGet {
                 // TODO: should actually report the offset and max_size in the API
                 self.inner.vm = req.resume_full_value(value.as_ref().map(|v| &v[..]));
             }
+            host::HostVm::ExternalChildStorageGet(req) => {
+                // TODO: should actually report the offset and max_size in the API
+                self.inner.vm = req.resume_full_value(value.as_ref().map(|v| &v[..]));
+            }
             host::HostVm::ExternalStorageAppend(req) => {
                 // TODO: could be less overhead?
                 let mut value = value.unwrap_or_default();
@@ -255,13 +315,17 @@ impl StorageGet {
                     .diff_insert(req.key().as_ref().to_vec(), value);
                 self.inner.vm = req.resume();
             }
-            host::HostVm::ExternalStorageRoot(_) => {
-                if let calculate_root::RootMerkleValueCalculation::StorageValue(value_request) =
-                    self.inner.root_calculation.take().unwrap()
+            host::HostVm::ExternalStorageRoot(_) | host::HostVm::ExternalChildStorageRoot(_) => {
+                if let Some((
+                    child_trie,
+                    calculate_root::RootMerkleValueCalculation::StorageValue(value_request),
+                )) = self.inner.root_calculation.take()
                 {
                     // TODO: we only support V0 for now, see https://github.com/paritytech/smoldot/issues/1967
-                    self.inner.root_calculation =
-                        Some(value_request.inject(trie::TrieEntryVersion::V0, value));
+                    self.inner.root_calculation = Some((
+                        child_trie,
+                        value_request.inject(trie::TrieEntryVersion::V0, value),
+                    ));
                 } else {
                     // We only create a `StorageGet` if the state is `StorageValue`.
                     panic!()
@@ -286,8 +350,37 @@ impl PrefixKeys {
     /// Returns the prefix whose keys to load.
     pub fn prefix(&'_ self) -> impl AsRef<[u8]> + '_ {
         match &self.inner.vm {
-            host::HostVm::ExternalStorageClearPrefix(req) => either::Left(req.prefix()),
-            host::HostVm::ExternalStorageRoot { .. } => either::Right(&[]),
+            host::HostVm::ExternalStorageClearPrefix(req) => {
+                either::Left(either::Left(req.prefix()))
+            }
+            host::HostVm::ExternalChildStorageClearPrefix(req) => {
+                either::Left(either::Right(req.prefix()))
+            }
+            host::HostVm::ExternalStorageRoot { .. }
+            | host::HostVm::ExternalChildStorageRoot { .. } => either::Right(&[]),
+
+            // We only create a `PrefixKeys` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// If `Some`, the keys must be loaded from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        match &self.inner.vm {
+            host::HostVm::ExternalStorageClearPrefix(_) => None,
+            host::HostVm::ExternalChildStorageClearPrefix(req) => {
+                Some(either::Left(req.child_trie()))
+            }
+            host::HostVm::ExternalStorageRoot(_) | host::HostVm::ExternalChildStorageRoot(_) => {
+                self.inner
+                    .root_calculation
+                    .as_ref()
+                    .unwrap()
+                    .0
+                    .as_ref()
+                    .map(either::Right)
+            }
 
             // We only create a `PrefixKeys` if the state is one of the above.
             _ => unreachable!(),
@@ -362,29 +455,95 @@ impl PrefixKeys {
                 self.inner.vm = req.resume(keys_removed_so_far, some_keys_remain);
             }
 
-            host::HostVm::ExternalStorageRoot { .. } => {
-                if let calculate_root::RootMerkleValueCalculation::AllKeys(all_keys) =
-                    self.inner.root_calculation.take().unwrap()
+            host::HostVm::ExternalChildStorageClearPrefix(req) => {
+                let max_keys_to_remove = req.max_keys_to_remove();
+                let mut keys_removed_so_far = 0u32;
+
+                let child_trie = req.child_trie().as_ref().to_owned();
+                let prefix = req.prefix().as_ref().to_owned();
+                let child_trie_changes = self
+                    .inner
+                    .child_tries_changes
+                    .entry(child_trie.clone())
+                    .or_default();
+                let mut after_overlay = child_trie_changes
+                    .storage_prefix_keys_ordered(&prefix, keys)
+                    .peekable();
+
+                let mut keys_to_remove = Vec::new(); // TODO: capacity?
+
+                let some_keys_remain = loop {
+                    // Enforce the maximum number of keys to remove.
+                    if max_keys_to_remove.map_or(false, |max| keys_removed_so_far >= max) {
+                        break after_overlay.peek().is_some();
+                    }
+
+                    match after_overlay.next() {
+                        Some(k) => {
+                            keys_to_remove.push(k.as_ref().to_owned());
+                            // See the equivalent code for the main trie above.
+                            keys_removed_so_far = keys_removed_so_far.wrapping_add(1);
+                        }
+                        None => {
+                            break false;
+                        }
+                    }
+                };
+
+                drop(after_overlay);
+
+                for key in keys_to_remove {
+                    let previous_value = child_trie_changes.diff_insert_erase(key.clone());
+
+                    if let Some(child_tries_transaction_revert) =
+                        self.inner.child_tries_transaction_revert.last_mut()
+                    {
+                        if let Entry::Vacant(entry) =
+                            child_tries_transaction_revert.entry((child_trie.clone(), key))
+                        {
+                            entry.insert(previous_value);
+                        }
+                    }
+                }
+
+                self.inner.vm = req.resume(keys_removed_so_far, some_keys_remain);
+            }
+
+            host::HostVm::ExternalStorageRoot { .. }
+            | host::HostVm::ExternalChildStorageRoot { .. } => {
+                if let Some((
+                    child_trie,
+                    calculate_root::RootMerkleValueCalculation::AllKeys(all_keys),
+                )) = self.inner.root_calculation.take()
                 {
+                    let trie_changes = match &child_trie {
+                        Some(child_trie) => self.inner.child_tries_changes.get(child_trie),
+                        None => Some(&self.inner.top_trie_changes),
+                    };
+
                     // TODO: overhead
                     let mut list = keys
                         .filter(|v| {
-                            self.inner
-                                .top_trie_changes
-                                .diff_get(v.as_ref())
+                            trie_changes
+                                .and_then(|changes| changes.diff_get(v.as_ref()))
                                 .map_or(true, |v| v.is_some())
                         })
                         .map(|v| v.as_ref().to_vec())
                         .collect::<HashSet<_, fnv::FnvBuildHasher>>();
                     // TODO: slow to iterate over everything?
-                    for (key, value) in self.inner.top_trie_changes.diff_iter_unordered() {
+                    for (key, value) in trie_changes
+                        .into_iter()
+                        .flat_map(|changes| changes.diff_iter_unordered())
+                    {
                         if value.is_none() {
                             continue;
                         }
                         list.insert(key.to_owned());
                     }
-                    self.inner.root_calculation =
-                        Some(all_keys.inject(list.into_iter().map(|k| k.into_iter())));
+                    self.inner.root_calculation = Some((
+                        child_trie,
+                        all_keys.inject(list.into_iter().map(|k| k.into_iter())),
+                    ));
                 } else {
                     // We only create a `PrefixKeys` if the state is `AllKeys`.
                     panic!()
@@ -417,7 +576,21 @@ impl NextKey {
         }
 
         match &self.inner.vm {
-            host::HostVm::ExternalStorageNextKey(req) => either::Right(req.key()),
+            host::HostVm::ExternalStorageNextKey(req) => either::Right(either::Left(req.key())),
+            host::HostVm::ExternalChildStorageNextKey(req) => {
+                either::Right(either::Right(req.key()))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// If `Some`, the next key must be searched in the given default child trie rather than in
+    /// the main trie. The child trie identifier doesn't include the `:child_storage:default:`
+    /// prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        match &self.inner.vm {
+            host::HostVm::ExternalStorageNextKey(_) => None,
+            host::HostVm::ExternalChildStorageNextKey(req) => Some(req.child_trie()),
             _ => unreachable!(),
         }
     }
@@ -460,6 +633,39 @@ impl NextKey {
                 }
             }
 
+            host::HostVm::ExternalChildStorageNextKey(req) => {
+                let search = {
+                    let req_key = req.key();
+                    let requested_key = if let Some(key_overwrite) = &self.key_overwrite {
+                        &key_overwrite[..]
+                    } else {
+                        req_key.as_ref()
+                    };
+                    match self
+                        .inner
+                        .child_tries_changes
+                        .get(req.child_trie().as_ref())
+                    {
+                        Some(changes) => changes.storage_next_key(requested_key, key),
+                        None => storage_diff::StorageNextKey::Found(key),
+                    }
+                };
+
+                match search {
+                    storage_diff::StorageNextKey::Found(k) => {
+                        self.inner.vm = req.resume(k);
+                    }
+                    storage_diff::StorageNextKey::NextOf(next) => {
+                        let key_overwrite = Some(next.to_owned());
+                        self.inner.vm = host::HostVm::ExternalChildStorageNextKey(req);
+                        return RuntimeHostVm::NextKey(NextKey {
+                            inner: self.inner,
+                            key_overwrite,
+                        });
+                    }
+                }
+            }
+
             // We only create a `NextKey` if the state is one of the above.
             _ => unreachable!(),
         };
@@ -552,6 +758,40 @@ impl SignatureVerification {
     }
 }
 
+/// The runtime has called a host function that is only available to off-chain workers, or that
+/// requires access to the keystore.
+#[must_use]
+pub struct OffchainContext {
+    inner: Inner,
+}
+
+impl OffchainContext {
+    /// Aborts the execution, as off-chain worker host functions aren't allowed in the current
+    /// context.
+    pub fn forbid(self) -> Error {
+        Error {
+            detail: ErrorDetail::ForbiddenHostCall,
+            prototype: self.inner.vm.into_prototype(),
+        }
+    }
+
+    /// Returns the underlying virtual machine. Guaranteed to be one of the `Offchain*` or
+    /// `CryptoEcdsa*` variants of [`host::HostVm`].
+    pub(crate) fn host_vm(&self) -> &host::HostVm {
+        &self.inner.vm
+    }
+
+    /// Resumes execution. The closure is passed the virtual machine returned by
+    /// [`OffchainContext::host_vm`] and must return the virtual machine after having resumed it.
+    pub(crate) fn resume(
+        mut self,
+        resume: impl FnOnce(host::HostVm) -> host::HostVm,
+    ) -> RuntimeHostVm {
+        self.inner.vm = resume(self.inner.vm);
+        self.inner.run()
+    }
+}
+
 /// Implementation detail of the execution. Shared by all the variants of [`RuntimeHostVm`]
 /// other than [`RuntimeHostVm::Finished`].
 struct Inner {
@@ -570,6 +810,15 @@ struct Inner {
     top_trie_transaction_revert:
         Vec<HashMap<Vec<u8>, Option<Option<Vec<u8>>>, fnv::FnvBuildHasher>>,
 
+    /// Pending changes to the default child tries that this execution performs. Keys are the
+    /// identifiers of the child tries, without the `:child_storage:default:` prefix.
+    child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
+
+    /// Same as [`Inner::top_trie_transaction_revert`], but for [`Inner::child_tries_changes`].
+    /// Keys are a child trie identifier and a key within that child trie.
+    child_tries_transaction_revert:
+        Vec<HashMap<(Vec<u8>, Vec<u8>), Option<Option<Vec<u8>>>, fnv::FnvBuildHasher>>,
+
     /// Pending changes to the off-chain storage that this execution performs.
     offchain_storage_changes: storage_diff::StorageDiff,
 
@@ -577,8 +826,15 @@ struct Inner {
     /// state root.
     top_trie_root_calculation_cache: Option<calculate_root::CalculationCache>,
 
-    /// Trie root calculation in progress.
-    root_calculation: Option<calculate_root::RootMerkleValueCalculation>,
+    /// Trie root calculation in progress. Contains the identifier of the child trie whose root
+    /// is being calculated, or `None` for the main trie.
+    root_calculation: Option<(Option<Vec<u8>>, calculate_root::RootMerkleValueCalculation)>,
+
+    /// Before the root of the main trie can be calculated, the roots of the child tries that
+    /// have been modified must be calculated and written in the main trie. Contains the list of
+    /// child tries whose root remains to be calculated, or `None` if the calculation of the
+    /// root of the main trie hasn't started.
+    child_tries_roots_to_fold: Option<Vec<Vec<u8>>>,
 
     /// Concatenation of all the log messages generated by the runtime.
     logs: String,
@@ -605,6 +861,7 @@ impl Inner {
                     return RuntimeHostVm::Finished(Ok(Success {
                         virtual_machine: SuccessVirtualMachine(finished),
                         storage_top_trie_changes: self.top_trie_changes,
+                        storage_child_tries_changes: self.child_tries_changes,
                         offchain_storage_changes: self.offchain_storage_changes,
                         top_trie_root_calculation_cache: self
                             .top_trie_root_calculation_cache
@@ -685,39 +942,77 @@ impl Inner {
 
                 host::HostVm::ExternalStorageRoot(req) => {
                     if self.root_calculation.is_none() {
-                        self.root_calculation = Some(calculate_root::root_merkle_value(Some(
-                            self.top_trie_root_calculation_cache.take().unwrap(),
-                        )));
+                        // The roots of the child tries that have been modified are calculated
+                        // one by one and written in the main trie before the root of the main
+                        // trie itself is calculated.
+                        // TODO: the child tries roots are calculated from scratch every time; consider caching them
+                        let child_tries_changes = &self.child_tries_changes;
+                        let child_trie = self
+                            .child_tries_roots_to_fold
+                            .get_or_insert_with(|| child_tries_changes.keys().cloned().collect())
+                            .pop();
+
+                        if let Some(child_trie) = child_trie {
+                            self.root_calculation =
+                                Some((Some(child_trie), calculate_root::root_merkle_value(None)));
+                        } else {
+                            self.child_tries_roots_to_fold = None;
+                            self.root_calculation = Some((
+                                None,
+                                calculate_root::root_merkle_value(Some(
+                                    self.top_trie_root_calculation_cache.take().unwrap(),
+                                )),
+                            ));
+                        }
                     }
 
-                    match self.root_calculation.take().unwrap() {
-                        calculate_root::RootMerkleValueCalculation::Finished { hash, cache } => {
-                            self.top_trie_root_calculation_cache = Some(cache);
-                            self.vm = req.resume(&hash);
+                    self.vm = req.into();
+                    match self.progress_root_calculation() {
+                        RootCalculationProgress::Finished(None, hash) => {
+                            self.vm = match self.vm {
+                                host::HostVm::ExternalStorageRoot(req) => req.resume(&hash),
+                                _ => unreachable!(),
+                            };
                         }
-                        calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
-                            self.vm = req.into();
-                            self.root_calculation =
-                                Some(calculate_root::RootMerkleValueCalculation::AllKeys(keys));
+                        RootCalculationProgress::Finished(Some(child_trie), hash) => {
+                            self.write_child_trie_root(child_trie, &hash);
+                        }
+                        RootCalculationProgress::PrefixKeys => {
                             return RuntimeHostVm::PrefixKeys(PrefixKeys { inner: self });
                         }
-                        calculate_root::RootMerkleValueCalculation::StorageValue(value_request) => {
-                            self.vm = req.into();
-                            // TODO: allocating a Vec, meh
-                            if let Some(overlay) = self
-                                .top_trie_changes
-                                .diff_get(&value_request.key().collect::<Vec<_>>())
-                            {
-                                // TODO: we only support V0 for now, see https://github.com/paritytech/smoldot/issues/1967
-                                self.root_calculation =
-                                    Some(value_request.inject(trie::TrieEntryVersion::V0, overlay));
-                            } else {
-                                self.root_calculation =
-                                    Some(calculate_root::RootMerkleValueCalculation::StorageValue(
-                                        value_request,
-                                    ));
-                                return RuntimeHostVm::StorageGet(StorageGet { inner: self });
+                        RootCalculationProgress::StorageGet => {
+                            return RuntimeHostVm::StorageGet(StorageGet { inner: self });
+                        }
+                    }
+                }
+
+                host::HostVm::ExternalChildStorageRoot(req) => {
+                    if self.root_calculation.is_none() {
+                        self.root_calculation = Some((
+                            Some(req.child_trie().as_ref().to_vec()),
+                            calculate_root::root_merkle_value(None),
+                        ));
+                    }
+
+                    self.vm = req.into();
+                    match self.progress_root_calculation() {
+                        RootCalculationProgress::Finished(child_trie, hash) => {
+                            let child_trie = child_trie.unwrap();
+                            // Similar to what the main trie root calculation does, the root is
+                            // also written in the main trie if the child trie has been modified.
+                            if self.child_tries_changes.contains_key(&child_trie) {
+                                self.write_child_trie_root(child_trie, &hash);
                             }
+                            self.vm = match self.vm {
+                                host::HostVm::ExternalChildStorageRoot(req) => req.resume(&hash),
+                                _ => unreachable!(),
+                            };
+                        }
+                        RootCalculationProgress::PrefixKeys => {
+                            return RuntimeHostVm::PrefixKeys(PrefixKeys { inner: self });
+                        }
+                        RootCalculationProgress::StorageGet => {
+                            return RuntimeHostVm::StorageGet(StorageGet { inner: self });
                         }
                     }
                 }
@@ -730,6 +1025,58 @@ impl Inner {
                     });
                 }
 
+                host::HostVm::ExternalChildStorageGet(req) => {
+                    let search = self
+                        .child_tries_changes
+                        .get(req.child_trie().as_ref())
+                        .and_then(|changes| changes.diff_get(req.key().as_ref()));
+                    if let Some(overlay) = search {
+                        self.vm = req.resume_full_value(overlay);
+                    } else {
+                        self.vm = req.into();
+                        return RuntimeHostVm::StorageGet(StorageGet { inner: self });
+                    }
+                }
+
+                host::HostVm::ExternalChildStorageSet(req) => {
+                    let child_trie = req.child_trie().as_ref().to_vec();
+                    let child_trie_changes = self
+                        .child_tries_changes
+                        .entry(child_trie.clone())
+                        .or_default();
+
+                    let previous_value = if let Some(value) = req.value() {
+                        child_trie_changes.diff_insert(req.key().as_ref(), value.as_ref())
+                    } else {
+                        child_trie_changes.diff_insert_erase(req.key().as_ref())
+                    };
+
+                    if let Some(child_tries_transaction_revert) =
+                        self.child_tries_transaction_revert.last_mut()
+                    {
+                        if let Entry::Vacant(entry) = child_tries_transaction_revert
+                            .entry((child_trie, req.key().as_ref().to_vec()))
+                        {
+                            entry.insert(previous_value);
+                        }
+                    }
+
+                    self.vm = req.resume();
+                }
+
+                host::HostVm::ExternalChildStorageClearPrefix(req) => {
+                    self.vm = req.into();
+                    return RuntimeHostVm::PrefixKeys(PrefixKeys { inner: self });
+                }
+
+                host::HostVm::ExternalChildStorageNextKey(req) => {
+                    self.vm = req.into();
+                    return RuntimeHostVm::NextKey(NextKey {
+                        inner: self,
+                        key_overwrite: None,
+                    });
+                }
+
                 host::HostVm::ExternalOffchainStorageSet(req) => {
                     if let Some(value) = req.value() {
                         self.offchain_storage_changes
@@ -749,6 +1096,30 @@ impl Inner {
                     });
                 }
 
+                vm @ (host::HostVm::OffchainIsValidator(_)
+                | host::HostVm::OffchainSubmitTransaction(_)
+                | host::HostVm::OffchainNetworkState(_)
+                | host::HostVm::OffchainTimestamp(_)
+                | host::HostVm::OffchainSleepUntil(_)
+                | host::HostVm::OffchainRandomSeed(_)
+                | host::HostVm::OffchainLocalStorageGet(_)
+                | host::HostVm::OffchainLocalStorageSet(_)
+                | host::HostVm::OffchainLocalStorageCompareAndSet(_)
+                | host::HostVm::OffchainHttpRequestStart(_)
+                | host::HostVm::OffchainHttpRequestAddHeader(_)
+                | host::HostVm::OffchainHttpRequestWriteBody(_)
+                | host::HostVm::OffchainHttpResponseWait(_)
+                | host::HostVm::OffchainHttpResponseHeaders(_)
+                | host::HostVm::OffchainHttpResponseReadBody(_)
+                | host::HostVm::CryptoEcdsaGenerate(_)
+                | host::HostVm::CryptoEcdsaPublicKeys(_)) => {
+                    // The keystore functions are grouped with the off-chain worker functions, as
+                    // off-chain workers are the only situation where they're supported. The
+                    // Sr25519 and Ed25519 equivalents aren't implemented at all.
+                    self.vm = vm;
+                    return RuntimeHostVm::Offchain(OffchainContext { inner: self });
+                }
+
                 host::HostVm::CallRuntimeVersion(req) => {
                     // TODO: make the user execute this ; see https://github.com/paritytech/smoldot/issues/144
                     // The code below compiles the provided WebAssembly runtime code, which is a
@@ -776,6 +1147,7 @@ impl Inner {
 
                 host::HostVm::StartStorageTransaction(tx) => {
                     self.top_trie_transaction_revert.push(Default::default());
+                    self.child_tries_transaction_revert.push(Default::default());
                     self.vm = tx.resume();
                 }
 
@@ -784,6 +1156,7 @@ impl Inner {
                     // end if it has earlier been started.
                     debug_assert!(!self.top_trie_transaction_revert.is_empty());
                     let last = self.top_trie_transaction_revert.pop().unwrap();
+                    let last_child_tries = self.child_tries_transaction_revert.pop().unwrap();
 
                     if rollback {
                         for (key, value) in last {
@@ -798,6 +1171,25 @@ impl Inner {
                             }
                         }
 
+                        for ((child_trie, key), value) in last_child_tries {
+                            let child_trie_changes =
+                                self.child_tries_changes.entry(child_trie).or_default();
+                            if let Some(value) = value {
+                                if let Some(value) = value {
+                                    let _ = child_trie_changes.diff_insert(key, value);
+                                } else {
+                                    let _ = child_trie_changes.diff_insert_erase(key);
+                                }
+                            } else {
+                                let _ = child_trie_changes.diff_remove(&key);
+                            }
+                        }
+
+                        // Child tries that no longer contain any change are removed, in order
+                        // to not needlessly write their root in the main trie.
+                        self.child_tries_changes
+                            .retain(|_, changes| changes.diff_iter_unordered().len() != 0);
+
                         // TODO: very slow; do this properly
                         self.top_trie_root_calculation_cache = Some(Default::default());
                     }
@@ -849,6 +1241,101 @@ impl Inner {
     }
 }
 
+impl Inner {
+    /// Advances [`Inner::root_calculation`] as much as possible without requiring any access to
+    /// the storage outside of the pending changes.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`Inner::root_calculation`] is `None`.
+    ///
+    fn progress_root_calculation(&mut self) -> RootCalculationProgress {
+        loop {
+            match self.root_calculation.take().unwrap() {
+                (
+                    child_trie,
+                    calculate_root::RootMerkleValueCalculation::Finished { hash, cache },
+                ) => {
+                    if child_trie.is_none() {
+                        self.top_trie_root_calculation_cache = Some(cache);
+                    }
+                    return RootCalculationProgress::Finished(child_trie, hash);
+                }
+                (child_trie, calculate_root::RootMerkleValueCalculation::AllKeys(keys)) => {
+                    self.root_calculation = Some((
+                        child_trie,
+                        calculate_root::RootMerkleValueCalculation::AllKeys(keys),
+                    ));
+                    return RootCalculationProgress::PrefixKeys;
+                }
+                (
+                    child_trie,
+                    calculate_root::RootMerkleValueCalculation::StorageValue(value_request),
+                ) => {
+                    let trie_changes = match &child_trie {
+                        Some(child_trie) => self.child_tries_changes.get(child_trie),
+                        None => Some(&self.top_trie_changes),
+                    };
+
+                    // TODO: allocating a Vec, meh
+                    if let Some(overlay) = trie_changes.and_then(|changes| {
+                        changes.diff_get(&value_request.key().collect::<Vec<_>>())
+                    }) {
+                        // TODO: we only support V0 for now, see https://github.com/paritytech/smoldot/issues/1967
+                        self.root_calculation = Some((
+                            child_trie,
+                            value_request.inject(trie::TrieEntryVersion::V0, overlay),
+                        ));
+                    } else {
+                        self.root_calculation = Some((
+                            child_trie,
+                            calculate_root::RootMerkleValueCalculation::StorageValue(value_request),
+                        ));
+                        return RootCalculationProgress::StorageGet;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes in the main trie the root of the given default child trie. The entry is removed
+    /// if the child trie is empty.
+    fn write_child_trie_root(&mut self, child_trie: Vec<u8>, root: &[u8; 32]) {
+        let mut key = b":child_storage:default:".to_vec();
+        key.extend_from_slice(&child_trie);
+
+        let is_empty = *root == trie::empty_trie_merkle_value();
+
+        self.top_trie_root_calculation_cache
+            .as_mut()
+            .unwrap()
+            .storage_value_update(&key, !is_empty);
+
+        let previous_value = if is_empty {
+            self.top_trie_changes.diff_insert_erase(key.clone())
+        } else {
+            self.top_trie_changes.diff_insert(key.clone(), &root[..])
+        };
+
+        if let Some(top_trie_transaction_revert) = self.top_trie_transaction_revert.last_mut() {
+            if let Entry::Vacant(entry) = top_trie_transaction_revert.entry(key) {
+                entry.insert(previous_value);
+            }
+        }
+    }
+}
+
+/// See [`Inner::progress_root_calculation`].
+enum RootCalculationProgress {
+    /// The calculation is over. Contains the child trie whose root has been calculated, or
+    /// `None` for the main trie, and the root.
+    Finished(Option<Vec<u8>>, [u8; 32]),
+    /// The list of keys of the trie must be requested from the user.
+    PrefixKeys,
+    /// A storage value of the trie must be requested from the user.
+    StorageGet,
+}
+
 /// Performs the action described by [`host::HostVm::ExternalStorageAppend`] on an
 /// encoded storage value.
 fn append_to_storage_value(value: &mut Vec<u8>, to_add: &[u8]) {
@@ -892,3 +1379,142 @@ fn append_to_storage_value(value: &mut Vec<u8>, to_add: &[u8]) {
     value[..new_len_encoded_size].copy_from_slice(new_len_encoded.as_ref());
     value.extend_from_slice(to_add);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{run, Config, RuntimeHostVm, Success};
+    use crate::{
+        executor::{host, storage_diff, vm},
+        trie,
+    };
+    use alloc::vec::Vec;
+
+    /// Runs the given function of a module whose exported functions write `value` at `key` in
+    /// the default child trie `foo`, optionally clear that key afterwards, then return the root
+    /// of either the main trie or the child trie. The storage is initially empty.
+    fn run_child_trie_module(function: &str) -> Success {
+        let module = wat::parse_str(
+            r#"
+(module
+    (import "env" "memory" (memory 1))
+    (import "env" "ext_storage_root_version_1" (func $root (result i64)))
+    (import "env" "ext_default_child_storage_set_version_1" (func $child_set (param i64 i64 i64)))
+    (import "env" "ext_default_child_storage_clear_version_1" (func $child_clear (param i64 i64)))
+    (import "env" "ext_default_child_storage_root_version_1" (func $child_root (param i64) (result i64)))
+    (global (export "__heap_base") i32 (i32.const 1024))
+
+    ;; SCALE-encoded runtime version, returned by `Core_version`.
+    (data (i32.const 0) "\10test\10test\00\00\00\00\00\00\00\00\00\00\00\00\00")
+    (data (i32.const 32) "foo")
+    (data (i32.const 40) "key")
+    (data (i32.const 48) "value")
+
+    (func (export "Core_version") (param i32 i32) (result i64)
+        i64.const 0x0000001700000000)
+    (func (export "set_then_root") (param i32 i32) (result i64)
+        (call $child_set (i64.const 0x0000000300000020) (i64.const 0x0000000300000028) (i64.const 0x0000000500000030))
+        (call $root))
+    (func (export "set_clear_then_root") (param i32 i32) (result i64)
+        (call $child_set (i64.const 0x0000000300000020) (i64.const 0x0000000300000028) (i64.const 0x0000000500000030))
+        (call $child_clear (i64.const 0x0000000300000020) (i64.const 0x0000000300000028))
+        (call $root))
+    (func (export "set_then_child_root") (param i32 i32) (result i64)
+        (call $child_set (i64.const 0x0000000300000020) (i64.const 0x0000000300000028) (i64.const 0x0000000500000030))
+        (call $child_root (i64.const 0x0000000300000020)))
+)
+            "#,
+        )
+        .unwrap();
+
+        let virtual_machine = host::HostVmPrototype::new(host::Config {
+            module: &module,
+            heap_pages: host::HeapPages::new(1),
+            exec_hint: vm::ExecHint::ForceWasmi,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+
+        let mut execution = run(Config {
+            virtual_machine,
+            function_to_call: function,
+            parameter: core::iter::empty::<Vec<u8>>(),
+            top_trie_root_calculation_cache: None,
+            storage_top_trie_changes: storage_diff::StorageDiff::empty(),
+            storage_child_tries_changes: Default::default(),
+            offchain_storage_changes: storage_diff::StorageDiff::empty(),
+        })
+        .unwrap();
+
+        loop {
+            execution = match execution {
+                RuntimeHostVm::Finished(Ok(success)) => return success,
+                RuntimeHostVm::StorageGet(get) => {
+                    get.inject_value(None::<core::iter::Empty<Vec<u8>>>)
+                }
+                RuntimeHostVm::PrefixKeys(keys) => {
+                    keys.inject_keys_ordered(core::iter::empty::<Vec<u8>>())
+                }
+                _ => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn child_trie_root_folded_in_main_trie() {
+        let success = run_child_trie_module("set_then_root");
+
+        let child_trie_root = trie::trie_root(trie::TrieEntryVersion::V0, &[(b"key", b"value")]);
+        let main_trie_root = trie::trie_root(
+            trie::TrieEntryVersion::V0,
+            &[(&b":child_storage:default:foo"[..], &child_trie_root[..])],
+        );
+
+        assert_eq!(
+            success.virtual_machine.value().as_ref(),
+            &main_trie_root[..]
+        );
+        assert_eq!(
+            success
+                .storage_top_trie_changes
+                .diff_get(b":child_storage:default:foo"),
+            Some(Some(&child_trie_root[..]))
+        );
+        assert_eq!(
+            success.storage_child_tries_changes[&b"foo"[..]].diff_get(b"key"),
+            Some(Some(&b"value"[..]))
+        );
+    }
+
+    #[test]
+    fn empty_child_trie_removed_from_main_trie() {
+        let success = run_child_trie_module("set_clear_then_root");
+
+        assert_eq!(
+            success.virtual_machine.value().as_ref(),
+            &trie::empty_trie_merkle_value()[..]
+        );
+        assert_eq!(
+            success
+                .storage_top_trie_changes
+                .diff_get(b":child_storage:default:foo"),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn child_trie_root_written_in_main_trie() {
+        let success = run_child_trie_module("set_then_child_root");
+
+        let child_trie_root = trie::trie_root(trie::TrieEntryVersion::V0, &[(b"key", b"value")]);
+        assert_eq!(
+            success.virtual_machine.value().as_ref(),
+            &child_trie_root[..]
+        );
+        assert_eq!(
+            success
+                .storage_top_trie_changes
+                .diff_get(b":child_storage:default:foo"),
+            Some(Some(&child_trie_root[..]))
+        );
+    }
+}
@@ -38,8 +38,6 @@
 //!   later referred to by their index in this table. This is how the concept of "function
 //!   pointers" commonly found in low-level programming languages is translated in WebAssembly.
 //!
-//! > **Note**: At the time of writing, it isn't possible to call the second type of functions yet.
-//!
 //! Use [`VirtualMachinePrototype::start`] in order to start executing a function exported through
 //! an `(export)` statement.
 //!
@@ -49,6 +47,12 @@
 //! is returned and the virtual machine is now paused. Once the logic of the host function has
 //! been executed, call `run` again, passing the return value of that host function.
 //!
+//! While the virtual machine is paused because of a host function call, it is possible to call
+//! [`VirtualMachine::start_nested_indirect`] in order to execute a function of the
+//! `__indirect_function_table` before the host function returns. The nested function is then
+//! executed by calling `run`, exactly like the function it is nested in. At the time of writing,
+//! this is only supported by the interpreter.
+//!
 //! # About `__indirect_function_table`
 //!
 //! At initialization, the virtual machine will look for a table named `__indirect_function_table`.
@@ -255,6 +259,33 @@ impl VirtualMachine {
         }
     }
 
+    /// Starts executing the function found at the given index of the
+    /// `__indirect_function_table`, while the function currently being executed is paused.
+    ///
+    /// After this method has returned `Ok`, call [`VirtualMachine::run`] with `None` in order to
+    /// start executing the nested function. Once the nested function has finished, `run`
+    /// returns [`ExecOutcome::Finished`], and the virtual machine is back in the state it was in
+    /// before calling this method. The function the call was nested in can then be resumed by
+    /// passing to `run` the return value of the host function that it was calling.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the virtual machine isn't interrupted by a call to a host function.
+    ///
+    pub fn start_nested_indirect(
+        &mut self,
+        table_index: u32,
+        params: &[WasmValue],
+    ) -> Result<(), NestedCallErr> {
+        match &mut self.inner {
+            #[cfg(all(target_arch = "x86_64", feature = "std"))]
+            VirtualMachineInner::Jit(inner) => inner.start_nested_indirect(table_index, params),
+            VirtualMachineInner::Interpreter(inner) => {
+                inner.start_nested_indirect(table_index, params)
+            }
+        }
+    }
+
     /// Returns the size of the memory, in bytes.
     ///
     /// > **Note**: This can change over time if the Wasm code uses the `grow` opcode.
@@ -717,6 +748,26 @@ pub enum StartErr {
     SignatureNotSupported,
 }
 
+/// Error that can happen when calling [`VirtualMachine::start_nested_indirect`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum NestedCallErr {
+    /// The virtual machine implementation doesn't support nested calls.
+    #[display(fmt = "Nested calls aren't supported by this virtual machine.")]
+    NotSupported,
+    /// The Wasm module doesn't export any `__indirect_function_table`.
+    #[display(fmt = "No indirect function table.")]
+    NoIndirectTable,
+    /// There isn't any function at the requested index of the table.
+    #[display(fmt = "Function to call was not found.")]
+    FunctionNotFound,
+    /// The requested function has a signature that isn't supported.
+    #[display(fmt = "Function to call uses unsupported signature.")]
+    SignatureNotSupported,
+    /// The parameters don't match the signature of the function.
+    #[display(fmt = "Parameters don't match the signature of the function.")]
+    InvalidParameters,
+}
+
 /// Opaque error indicating an error while parsing or compiling the WebAssembly code.
 #[derive(Debug, derive_more::Display, Clone)]
 #[display(fmt = "{}", _0)]
@@ -18,8 +18,8 @@
 //! Implements the API documented [in the parent module](..).
 
 use super::{
-    ExecOutcome, GlobalValueErr, HeapPages, ModuleError, NewErr, OutOfBoundsError, RunErr,
-    Signature, StartErr, Trap, ValueType, WasmValue,
+    ExecOutcome, GlobalValueErr, HeapPages, ModuleError, NestedCallErr, NewErr, OutOfBoundsError,
+    RunErr, Signature, StartErr, Trap, ValueType, WasmValue,
 };
 
 use alloc::{borrow::ToOwned as _, boxed::Box, format, string::ToString as _, sync::Arc, vec::Vec};
@@ -295,6 +295,7 @@ impl InterpreterPrototype {
             memory: self.memory,
             execution: Some(execution),
             interrupted: false,
+            nested_executions: Vec::new(),
             indirect_table: self.indirect_table,
         })
     }
@@ -346,41 +347,25 @@ pub struct Interpreter {
     /// If false, then one must call `execution.start_execution()` instead of `resume_execution()`.
     /// This is a particularity of the Wasm interpreter that we don't want to expose in our API.
     interrupted: bool,
+
+    /// Executions started with [`Interpreter::start_nested_indirect`], each with a boolean
+    /// equivalent to [`Interpreter::interrupted`]. The last element is the one being executed.
+    /// [`Interpreter::execution`] only continues once this list is empty.
+    nested_executions: Vec<(wasmi::FuncInvocation<'static>, bool)>,
 }
 
 impl Interpreter {
     /// See [`super::VirtualMachine::run`].
     pub fn run(&mut self, value: Option<WasmValue>) -> Result<ExecOutcome, RunErr> {
-        let value = value.map(wasmi::RuntimeValue::from);
-
-        struct DummyExternals;
-        impl wasmi::Externals for DummyExternals {
-            fn invoke_index(
-                &mut self,
-                index: usize,
-                args: wasmi::RuntimeArgs,
-            ) -> Result<Option<wasmi::RuntimeValue>, wasmi::Trap> {
-                Err(wasmi::TrapKind::Host(Box::new(Interrupt {
-                    index,
-                    args: args.as_ref().to_vec(),
-                }))
-                .into())
-            }
-        }
-
-        #[derive(Debug)]
-        struct Interrupt {
-            index: usize,
-            args: Vec<wasmi::RuntimeValue>,
-        }
-        impl fmt::Display for Interrupt {
-            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                write!(f, "Interrupt")
+        if let Some((execution, interrupted)) = self.nested_executions.pop() {
+            let (outcome, execution) = run_execution(execution, interrupted, value)?;
+            if let Some(execution) = execution {
+                self.nested_executions.push((execution, true));
             }
+            return Ok(outcome);
         }
-        impl wasmi::HostError for Interrupt {}
 
-        let mut execution = match self.execution.take() {
+        let execution = match self.execution.take() {
             Some(Ok(e)) => e,
             Some(Err(err)) => {
                 return Ok(ExecOutcome::Finished {
@@ -390,66 +375,51 @@ impl Interpreter {
             None => return Err(RunErr::Poisoned),
         };
 
-        // Since the signature of the function is checked at initialization to be supported, it is
-        // guaranteed that the conversions below won't panic.
-
-        let result = if self.interrupted {
-            let expected_ty = execution
-                .resumable_value_type()
-                .map(|v| ValueType::try_from(v).unwrap());
-            let obtained_ty = value
-                .as_ref()
-                .map(|v| ValueType::try_from(v.value_type()).unwrap());
-            if expected_ty != obtained_ty {
-                return Err(RunErr::BadValueTy {
-                    expected: expected_ty,
-                    obtained: obtained_ty,
-                });
-            }
-            execution.resume_execution(value, &mut DummyExternals)
-        } else {
-            if value.is_some() {
-                return Err(RunErr::BadValueTy {
-                    expected: None,
-                    obtained: value
-                        .as_ref()
-                        .map(|v| ValueType::try_from(v.value_type()).unwrap()),
-                });
-            }
-            self.interrupted = true;
-            execution.start_execution(&mut DummyExternals)
-        };
+        let (outcome, execution) = run_execution(execution, self.interrupted, value)?;
+        self.interrupted = true;
+        self.execution = execution.map(Ok);
+        Ok(outcome)
+    }
 
-        match result {
-            Ok(return_value) => Ok(ExecOutcome::Finished {
-                return_value: Ok(return_value.map(|r| WasmValue::try_from(r).unwrap())),
-            }),
-            Err(wasmi::ResumableError::AlreadyStarted) => unreachable!(),
-            Err(wasmi::ResumableError::NotResumable) => unreachable!(),
-            Err(wasmi::ResumableError::Trap(ref trap)) if trap.kind().is_host() => {
-                let interrupt: &Interrupt = match trap.kind() {
-                    wasmi::TrapKind::Host(err) => match err.downcast_ref() {
-                        Some(e) => e,
-                        None => unreachable!(),
-                    },
-                    _ => unreachable!(),
-                };
-                self.execution = Some(Ok(execution));
-                Ok(ExecOutcome::Interrupted {
-                    id: interrupt.index,
-                    params: interrupt
-                        .args
-                        .iter()
-                        .copied()
-                        .map(TryFrom::try_from)
-                        .collect::<Result<_, _>>()
-                        .unwrap(),
-                })
-            }
-            Err(wasmi::ResumableError::Trap(err)) => Ok(ExecOutcome::Finished {
-                return_value: Err(Trap(err.to_string())),
-            }),
+    /// See [`super::VirtualMachine::start_nested_indirect`].
+    pub fn start_nested_indirect(
+        &mut self,
+        table_index: u32,
+        params: &[WasmValue],
+    ) -> Result<(), NestedCallErr> {
+        // A nested call can only be started while the execution it is nested in is interrupted
+        // by a call to a host function.
+        match self.nested_executions.last() {
+            Some((_, interrupted)) => assert!(*interrupted),
+            None => assert!(self.interrupted && matches!(self.execution, Some(Ok(_)))),
+        }
+
+        let function = self
+            .indirect_table
+            .as_ref()
+            .ok_or(NestedCallErr::NoIndirectTable)?
+            .get(table_index)
+            .ok()
+            .flatten()
+            .ok_or(NestedCallErr::FunctionNotFound)?;
+
+        // Try to convert the signature of the function to call, in order to make sure that the
+        // type of parameters and return value are supported.
+        if Signature::try_from(function.signature()).is_err() {
+            return Err(NestedCallErr::SignatureNotSupported);
         }
+
+        let execution = wasmi::FuncInstance::invoke_resumable(
+            &function,
+            params
+                .iter()
+                .map(|v| wasmi::RuntimeValue::from(*v))
+                .collect::<Vec<_>>(),
+        )
+        .map_err(|_| NestedCallErr::InvalidParameters)?;
+
+        self.nested_executions.push((execution, false));
+        Ok(())
     }
 
     /// See [`super::VirtualMachine::memory_size`].
@@ -538,3 +508,108 @@ impl fmt::Debug for Interpreter {
         f.debug_tuple("Interpreter").finish()
     }
 }
+
+/// Starts or resumes the given execution, depending on `interrupted`, until it finishes or is
+/// interrupted by a call to a host function.
+///
+/// The execution is returned back if it has been interrupted.
+fn run_execution(
+    mut execution: wasmi::FuncInvocation<'static>,
+    interrupted: bool,
+    value: Option<WasmValue>,
+) -> Result<(ExecOutcome, Option<wasmi::FuncInvocation<'static>>), RunErr> {
+    let value = value.map(wasmi::RuntimeValue::from);
+
+    struct DummyExternals;
+    impl wasmi::Externals for DummyExternals {
+        fn invoke_index(
+            &mut self,
+            index: usize,
+            args: wasmi::RuntimeArgs,
+        ) -> Result<Option<wasmi::RuntimeValue>, wasmi::Trap> {
+            Err(wasmi::TrapKind::Host(Box::new(Interrupt {
+                index,
+                args: args.as_ref().to_vec(),
+            }))
+            .into())
+        }
+    }
+
+    #[derive(Debug)]
+    struct Interrupt {
+        index: usize,
+        args: Vec<wasmi::RuntimeValue>,
+    }
+    impl fmt::Display for Interrupt {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Interrupt")
+        }
+    }
+    impl wasmi::HostError for Interrupt {}
+
+    // Since the signature of the function is checked at initialization to be supported, it is
+    // guaranteed that the conversions below won't panic.
+
+    let result = if interrupted {
+        let expected_ty = execution
+            .resumable_value_type()
+            .map(|v| ValueType::try_from(v).unwrap());
+        let obtained_ty = value
+            .as_ref()
+            .map(|v| ValueType::try_from(v.value_type()).unwrap());
+        if expected_ty != obtained_ty {
+            return Err(RunErr::BadValueTy {
+                expected: expected_ty,
+                obtained: obtained_ty,
+            });
+        }
+        execution.resume_execution(value, &mut DummyExternals)
+    } else {
+        if value.is_some() {
+            return Err(RunErr::BadValueTy {
+                expected: None,
+                obtained: value
+                    .as_ref()
+                    .map(|v| ValueType::try_from(v.value_type()).unwrap()),
+            });
+        }
+        execution.start_execution(&mut DummyExternals)
+    };
+
+    match result {
+        Ok(return_value) => Ok((
+            ExecOutcome::Finished {
+                return_value: Ok(return_value.map(|r| WasmValue::try_from(r).unwrap())),
+            },
+            None,
+        )),
+        Err(wasmi::ResumableError::AlreadyStarted) => unreachable!(),
+        Err(wasmi::ResumableError::NotResumable) => unreachable!(),
+        Err(wasmi::ResumableError::Trap(ref trap)) if trap.kind().is_host() => {
+            let interrupt: &Interrupt = match trap.kind() {
+                wasmi::TrapKind::Host(err) => match err.downcast_ref() {
+                    Some(e) => e,
+                    None => unreachable!(),
+                },
+                _ => unreachable!(),
+            };
+            let outcome = ExecOutcome::Interrupted {
+                id: interrupt.index,
+                params: interrupt
+                    .args
+                    .iter()
+                    .copied()
+                    .map(TryFrom::try_from)
+                    .collect::<Result<_, _>>()
+                    .unwrap(),
+            };
+            Ok((outcome, Some(execution)))
+        }
+        Err(wasmi::ResumableError::Trap(err)) => Ok((
+            ExecOutcome::Finished {
+                return_value: Err(Trap(err.to_string())),
+            },
+            None,
+        )),
+    }
+}
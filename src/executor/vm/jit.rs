@@ -18,8 +18,8 @@
 //! Implements the API documented [in the parent module](..).
 
 use super::{
-    ExecOutcome, GlobalValueErr, HeapPages, ModuleError, NewErr, OutOfBoundsError, RunErr,
-    Signature, StartErr, Trap, WasmValue,
+    ExecOutcome, GlobalValueErr, HeapPages, ModuleError, NestedCallErr, NewErr, OutOfBoundsError,
+    RunErr, Signature, StartErr, Trap, WasmValue,
 };
 
 use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
@@ -629,6 +629,17 @@ impl Jit {
         }
     }
 
+    /// See [`super::VirtualMachine::start_nested_indirect`].
+    pub fn start_nested_indirect(
+        &mut self,
+        _table_index: u32,
+        _params: &[WasmValue],
+    ) -> Result<(), NestedCallErr> {
+        // The `Store` is exclusively borrowed by the function call in progress, and can't be
+        // used to start another call until that one has finished.
+        Err(NestedCallErr::NotSupported)
+    }
+
     /// See [`super::VirtualMachine::memory_size`].
     pub fn memory_size(&self) -> HeapPages {
         match &self.inner {
@@ -0,0 +1,1914 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! GrandPa voter.
+//!
+//! GrandPa is the finality protocol of Substrate-based chains. The authorities of the chain
+//! participate in *rounds*, numbered incrementally. During each round, each authority casts a
+//! *prevote*, then a *precommit*, on a block of the chain. Once authorities whose weight
+//! represents more than two thirds of the total weight have precommitted on a block or its
+//! descendants, this block is finalized.
+//!
+//! This module contains the [`Voter`] state machine, which participates in the GrandPa rounds
+//! on behalf of an authority whose private key is available locally.
+//!
+//! # Usage
+//!
+//! The [`Voter`] doesn't perform any networking, signing, or time keeping by itself. It must
+//! instead be kept informed of the state of the chain and of the messages received from the
+//! network, and is driven by calling [`Voter::next_action`]:
+//!
+//! - Call [`Voter::insert_block`] and [`Voter::set_best_block`] whenever the non-finalized
+//!   chain is updated, and [`Voter::set_finalized_block`] whenever a block gets finalized by
+//!   other means than the voter.
+//! - Call [`Voter::inject_vote`], [`Voter::inject_neighbor_packet`] and
+//!   [`Voter::inject_catch_up`] when the corresponding messages are received from the network,
+//!   and answer catch up requests with [`Voter::catch_up_response`].
+//! - Call [`Voter::next_action`] after any of the above, and repeatedly until it returns
+//!   [`Action::Idle`].
+//!
+//! Only one authorities set is supported. When the authorities set changes, the [`Voter`] must
+//! be destroyed and a new one created.
+//!
+//! # Restarts
+//!
+//! Casting two different votes of the same kind during the same round is considered as a
+//! misbehaviour of the authority. In order to avoid this situation after a restart, the state
+//! of the voter returned through [`Action::PersistState`] must be stored and passed back through
+//! [`Config::persisted_state`] when creating a new [`Voter`]. A voter created this way doesn't
+//! cast any vote in the rounds the local authority might have already voted in.
+
+use crate::{
+    finality::{
+        grandpa::commit::decode::CompactCommitRef,
+        justification::decode::{Precommit, PrecommitRef},
+    },
+    header,
+    network::protocol,
+};
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+use core::{cmp, mem, ops, time::Duration};
+
+/// Configuration for a [`Voter`].
+#[derive(Debug)]
+pub struct Config<'a, TNow> {
+    /// Number of bytes used to encode the block number in the networking protocol and in the
+    /// payloads that are signed.
+    pub block_number_bytes: usize,
+
+    /// Identifier of the authorities set the voter participates in.
+    pub set_id: u64,
+
+    /// List of authorities of the set.
+    pub authorities: &'a [header::GrandpaAuthority],
+
+    /// Public key of the authority the voter votes on behalf of. Must be found in
+    /// [`Config::authorities`].
+    pub local_authority_public_key: [u8; 32],
+
+    /// Hash of the latest finalized block.
+    pub finalized_block_hash: [u8; 32],
+
+    /// Number of the latest finalized block.
+    pub finalized_block_number: u64,
+
+    /// If `Some`, no vote is ever cast for a block whose number is strictly superior to this
+    /// value. Must be set to the number of the block where a change of authorities set is
+    /// scheduled, if any.
+    pub voting_limit: Option<u64>,
+
+    /// Expected time necessary for a message to propagate to all the nodes of the network. All
+    /// the timers of the voter are derived from this value.
+    ///
+    /// A typical value is one second.
+    pub gossip_duration: Duration,
+
+    /// Current time. Used as the start of the first round.
+    pub now: TNow,
+
+    /// State previously returned through [`Action::PersistState`], if any. Ignored if it
+    /// concerns a different authorities set than [`Config::set_id`].
+    pub persisted_state: Option<PersistedState>,
+}
+
+/// Error potentially returned by [`Voter::new`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Local authority isn't part of the authorities set")]
+pub struct NotAuthorityError;
+
+/// Number of gossip durations after which the local votes are broadcast again if the round
+/// hasn't finished.
+const REBROADCAST_GOSSIP_DURATIONS: u32 = 8;
+
+/// GrandPa voter state machine. See [the module-level documentation](..).
+pub struct Voter<TNow> {
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+
+    /// See [`Config::set_id`].
+    set_id: u64,
+
+    /// See [`Config::authorities`].
+    authorities: Vec<header::GrandpaAuthority>,
+
+    /// Sum of the weights of all the authorities.
+    total_weight: u64,
+
+    /// Minimum weight of a set of votes in order for it to form a supermajority.
+    threshold: u64,
+
+    /// Index within [`Voter::authorities`] of the local authority.
+    local_authority_index: usize,
+
+    /// See [`Config::voting_limit`].
+    voting_limit: Option<u64>,
+
+    /// See [`Config::gossip_duration`].
+    gossip_duration: Duration,
+
+    /// Hash and number of the latest finalized block.
+    finalized_block: ([u8; 32], u64),
+
+    /// List of all the known blocks that descend from the latest finalized block, including the
+    /// latest finalized block itself. Values are the number and parent hash of the block.
+    blocks: BTreeMap<[u8; 32], (u64, [u8; 32])>,
+
+    /// Hash of the current best block. Always found in [`Voter::blocks`].
+    best_block: [u8; 32],
+
+    /// Round the voter is currently participating in.
+    current_round: Round<TNow>,
+
+    /// Round that precedes [`Voter::current_round`]. Its votes are still tracked, as late votes
+    /// can finalize more blocks. `None` if the voter hasn't finished any round yet.
+    previous_round: Option<Round<TNow>>,
+
+    /// Votes received for the round following [`Voter::current_round`]. Moved to
+    /// [`Voter::current_round`] when the voter advances to the next round.
+    next_round_votes: Vec<(VoteKind, usize, SignedVote)>,
+
+    /// Notifications waiting to be returned through [`Action::Broadcast`].
+    pending_broadcasts: VecDeque<Vec<u8>>,
+
+    /// If `true`, [`Action::LocalStateUpdate`] must be returned.
+    local_state_update: bool,
+
+    /// `true` if a catch up request has been returned by [`Voter::inject_neighbor_packet`]
+    /// recently. Used to avoid sending catch up requests over and over again.
+    catch_up_requested: bool,
+
+    /// Moment when the local votes of the current round should be broadcast again.
+    next_rebroadcast: TNow,
+
+    /// Highest round during which the local authority has cast a vote, or might have cast a
+    /// vote before the voter has been restarted. `0` if none.
+    last_voted_round: u64,
+
+    /// The local authority doesn't cast any vote in rounds inferior or equal to this value, as
+    /// it might have already voted in these rounds before the voter has been restarted.
+    no_vote_up_to_round: u64,
+
+    /// If `true`, [`Action::PersistState`] must be returned.
+    persist_state: bool,
+}
+
+/// State of a round.
+struct Round<TNow> {
+    /// Number of the round.
+    number: u64,
+
+    /// Latest finalized block at the time when the round has been created.
+    base: ([u8; 32], u64),
+
+    /// Moment when the round has started. Used to determine when to prevote and precommit.
+    start: TNow,
+
+    /// Prevote of each authority, indexed the same way as [`Voter::authorities`].
+    prevotes: Vec<Option<SignedVote>>,
+
+    /// Precommit of each authority, indexed the same way as [`Voter::authorities`].
+    precommits: Vec<Option<SignedVote>>,
+
+    /// Hash and number of the block proposed by the primary authority of the round, if any.
+    primary_proposal: Option<([u8; 32], u64)>,
+
+    /// `true` if the local authority has already decided whether to propose a block.
+    proposal_done: bool,
+
+    /// `true` if the local authority has already decided what to prevote.
+    prevote_done: bool,
+
+    /// `true` if the local authority has already decided what to precommit.
+    precommit_done: bool,
+}
+
+/// Vote of an authority, alongside with its signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SignedVote {
+    target_hash: [u8; 32],
+    target_number: u64,
+    signature: [u8; 64],
+}
+
+/// Result of evaluating the votes of a round.
+struct RoundState {
+    /// Highest block that has a supermajority of prevotes.
+    prevote_ghost: Option<([u8; 32], u64)>,
+    /// Highest ancestor of the prevote GHOST that can still get a supermajority of precommits.
+    estimate: Option<([u8; 32], u64)>,
+    /// Highest ancestor of the prevote GHOST that has a supermajority of precommits.
+    finalized: Option<([u8; 32], u64)>,
+    /// `true` if the estimate can't move anymore, in which case the next round can start.
+    completable: bool,
+}
+
+impl<TNow> Voter<TNow>
+where
+    TNow: Clone + ops::Add<Duration, Output = TNow> + Ord,
+{
+    /// Initializes a new voter, starting at round 1.
+    ///
+    /// If the chain is already past round 1, the voter catches up with the rest of the network
+    /// through the catch up mechanism. See [`Voter::inject_neighbor_packet`].
+    pub fn new(config: Config<TNow>) -> Result<Self, NotAuthorityError> {
+        let local_authority_index = config
+            .authorities
+            .iter()
+            .position(|a| a.public_key == config.local_authority_public_key)
+            .ok_or(NotAuthorityError)?;
+
+        let total_weight = config
+            .authorities
+            .iter()
+            .fold(0u64, |sum, a| sum.saturating_add(a.weight.get()));
+        let threshold = total_weight - (total_weight - 1) / 3;
+
+        let finalized_block = (config.finalized_block_hash, config.finalized_block_number);
+
+        let mut blocks = BTreeMap::new();
+        blocks.insert(
+            config.finalized_block_hash,
+            (config.finalized_block_number, [0; 32]),
+        );
+
+        let persisted_state = config
+            .persisted_state
+            .filter(|state| state.set_id == config.set_id);
+
+        // If the voter has been restarted, it resumes in the round following the last completed
+        // round, and restores the votes that the local authority has cast.
+        let (previous_round, current_round, last_voted_round) = match persisted_state {
+            Some(state) => {
+                let restore_round = |number: u64| {
+                    let mut round = Round::new(
+                        number,
+                        finalized_block,
+                        config.now.clone(),
+                        config.authorities.len(),
+                    );
+                    for vote in state
+                        .local_votes
+                        .iter()
+                        .filter(|v| v.round_number == number)
+                    {
+                        let _ = round.insert(
+                            vote.kind,
+                            local_authority_index,
+                            SignedVote {
+                                target_hash: vote.target_hash,
+                                target_number: vote.target_number,
+                                signature: vote.signature,
+                            },
+                        );
+                    }
+                    round
+                };
+
+                let previous_round = if state.last_completed_round != 0 {
+                    Some(restore_round(state.last_completed_round))
+                } else {
+                    None
+                };
+                let current_round = restore_round(state.last_completed_round.saturating_add(1));
+                let last_voted_round = cmp::max(state.last_voted_round, state.last_completed_round);
+                (previous_round, current_round, last_voted_round)
+            }
+            None => (
+                None,
+                Round::new(
+                    1,
+                    finalized_block,
+                    config.now.clone(),
+                    config.authorities.len(),
+                ),
+                0,
+            ),
+        };
+
+        Ok(Voter {
+            block_number_bytes: config.block_number_bytes,
+            set_id: config.set_id,
+            authorities: config.authorities.to_vec(),
+            total_weight,
+            threshold,
+            local_authority_index,
+            voting_limit: config.voting_limit,
+            gossip_duration: config.gossip_duration,
+            finalized_block,
+            blocks,
+            best_block: config.finalized_block_hash,
+            current_round,
+            previous_round,
+            next_round_votes: Vec::new(),
+            pending_broadcasts: VecDeque::new(),
+            local_state_update: true,
+            catch_up_requested: false,
+            next_rebroadcast: config.now + config.gossip_duration * REBROADCAST_GOSSIP_DURATIONS,
+            last_voted_round,
+            no_vote_up_to_round: last_voted_round,
+            persist_state: false,
+        })
+    }
+
+    /// Returns the state of the voter that must be persisted in order to avoid casting
+    /// conflicting votes after a restart.
+    ///
+    /// This state is also returned through [`Action::PersistState`] whenever it changes.
+    pub fn persisted_state(&self) -> PersistedState {
+        let local_votes = self
+            .previous_round
+            .iter()
+            .chain(Some(&self.current_round))
+            .flat_map(|round| {
+                [
+                    (VoteKind::Prevote, &round.prevotes),
+                    (VoteKind::Precommit, &round.precommits),
+                ]
+                .into_iter()
+                .filter_map(move |(kind, votes)| {
+                    let vote = votes[self.local_authority_index].as_ref()?;
+                    Some(LocalVote {
+                        round_number: round.number,
+                        kind,
+                        target_hash: vote.target_hash,
+                        target_number: vote.target_number,
+                        signature: vote.signature,
+                    })
+                })
+            })
+            .collect();
+
+        PersistedState {
+            set_id: self.set_id,
+            last_completed_round: self.previous_round.as_ref().map_or(0, |r| r.number),
+            last_voted_round: self.last_voted_round,
+            local_votes,
+        }
+    }
+
+    /// Returns the identifier of the authorities set the voter participates in.
+    pub fn set_id(&self) -> u64 {
+        self.set_id
+    }
+
+    /// Returns the number of the round the voter is currently participating in.
+    pub fn round_number(&self) -> u64 {
+        self.current_round.number
+    }
+
+    /// Returns the number and hash of the latest block known by the voter to be finalized.
+    pub fn finalized_block(&self) -> (u64, &[u8; 32]) {
+        (self.finalized_block.1, &self.finalized_block.0)
+    }
+
+    /// Returns the public key of the authority the voter votes on behalf of.
+    pub fn local_authority_public_key(&self) -> &[u8; 32] {
+        &self.authorities[self.local_authority_index].public_key
+    }
+
+    /// Inserts a block in the tree of blocks known to the voter.
+    ///
+    /// Blocks must be inserted in ancestry order. Blocks whose parent is unknown, such as blocks
+    /// that don't descend from the latest finalized block, are ignored.
+    pub fn insert_block(&mut self, hash: [u8; 32], number: u64, parent_hash: [u8; 32]) {
+        if self.blocks.contains_key(&hash) {
+            return;
+        }
+
+        if !matches!(self.blocks.get(&parent_hash), Some(&(n, _)) if n.checked_add(1) == Some(number))
+        {
+            return;
+        }
+
+        self.blocks.insert(hash, (number, parent_hash));
+    }
+
+    /// Sets the current best block, which the votes are cast upon.
+    ///
+    /// Does nothing if the block hasn't been inserted with [`Voter::insert_block`].
+    pub fn set_best_block(&mut self, hash: &[u8; 32]) {
+        if self.blocks.contains_key(hash) {
+            self.best_block = *hash;
+        }
+    }
+
+    /// Updates the latest finalized block, after a block has been finalized by other means than
+    /// the voter.
+    ///
+    /// Does nothing if the block hasn't been inserted with [`Voter::insert_block`] or is older
+    /// than the latest block finalized by the voter.
+    pub fn set_finalized_block(&mut self, hash: &[u8; 32]) {
+        let number = match self.blocks.get(hash) {
+            Some(&(n, _)) => n,
+            None => return,
+        };
+
+        if number > self.finalized_block.1 {
+            self.finalize((*hash, number));
+        }
+    }
+
+    /// Injects a vote received from the network.
+    ///
+    /// Only votes concerning the current round, the previous round, or the next round are
+    /// accepted.
+    pub fn inject_vote(&mut self, vote: &protocol::VoteMessageRef) -> Result<(), InjectVoteError> {
+        if vote.set_id != self.set_id {
+            return Err(InjectVoteError::BadSetId);
+        }
+
+        let authority_index = self
+            .authorities
+            .iter()
+            .position(|a| a.public_key == *vote.authority_public_key)
+            .ok_or(InjectVoteError::UnknownAuthority)?;
+
+        let (kind, target_hash, target_number) = match &vote.message {
+            protocol::MessageRef::Prevote(m) => (VoteKind::Prevote, m.target_hash, m.target_number),
+            protocol::MessageRef::Precommit(m) => {
+                (VoteKind::Precommit, m.target_hash, m.target_number)
+            }
+            protocol::MessageRef::PrimaryPropose(m) => {
+                (VoteKind::PrimaryPropose, m.target_hash, m.target_number)
+            }
+        };
+
+        let is_next_round = vote.round_number.checked_sub(1) == Some(self.current_round.number);
+        if vote.round_number != self.current_round.number
+            && !is_next_round
+            && self.previous_round.as_ref().map(|r| r.number) != Some(vote.round_number)
+        {
+            return Err(InjectVoteError::InactiveRound);
+        }
+
+        if matches!(kind, VoteKind::PrimaryPropose)
+            && self.primary_authority_index(vote.round_number) != authority_index
+        {
+            return Err(InjectVoteError::NotPrimary);
+        }
+
+        if !self.verify_signature(
+            kind,
+            target_hash,
+            target_number,
+            vote.round_number,
+            vote.authority_public_key,
+            vote.signature,
+        ) {
+            return Err(InjectVoteError::BadSignature);
+        }
+
+        let signed_vote = SignedVote {
+            target_hash: *target_hash,
+            target_number,
+            signature: *vote.signature,
+        };
+
+        if is_next_round {
+            if let Some((_, _, existing)) = self
+                .next_round_votes
+                .iter()
+                .find(|(k, a, _)| *k == kind && *a == authority_index)
+            {
+                return if *existing == signed_vote {
+                    Ok(())
+                } else {
+                    Err(InjectVoteError::Equivocation)
+                };
+            }
+
+            self.next_round_votes
+                .push((kind, authority_index, signed_vote));
+            Ok(())
+        } else if vote.round_number == self.current_round.number {
+            self.current_round
+                .insert(kind, authority_index, signed_vote)
+        } else {
+            self.previous_round
+                .as_mut()
+                .unwrap()
+                .insert(kind, authority_index, signed_vote)
+        }
+    }
+
+    /// Injects a neighbor packet received from the network.
+    ///
+    /// If the peer that has sent this packet is ahead of the local voter, returns a catch up
+    /// request that should be sent back to this peer. The response must then be passed to
+    /// [`Voter::inject_catch_up`].
+    pub fn inject_neighbor_packet(
+        &mut self,
+        packet: &protocol::NeighborPacket,
+    ) -> Option<protocol::CatchUpRequest> {
+        if packet.set_id != self.set_id
+            || self.catch_up_requested
+            || packet.round_number <= self.current_round.number.saturating_add(1)
+        {
+            return None;
+        }
+
+        self.catch_up_requested = true;
+        Some(protocol::CatchUpRequest {
+            round_number: self.current_round.number,
+            set_id: self.set_id,
+        })
+    }
+
+    /// Builds the response to a catch up request received from the network.
+    ///
+    /// Returns the SCALE-encoded GrandPa notification to send back to the peer, or `None` if the
+    /// local voter isn't ahead of the peer that has sent the request.
+    pub fn catch_up_response(&self, request: &protocol::CatchUpRequest) -> Option<Vec<u8>> {
+        if request.set_id != self.set_id {
+            return None;
+        }
+
+        let round = self.previous_round.as_ref()?;
+        if round.number <= request.round_number {
+            return None;
+        }
+
+        let prevotes = round
+            .prevotes
+            .iter()
+            .zip(&self.authorities)
+            .filter_map(|(vote, authority)| {
+                let vote = vote.as_ref()?;
+                Some(protocol::PrevoteRef {
+                    target_hash: &vote.target_hash,
+                    target_number: vote.target_number,
+                    signature: &vote.signature,
+                    authority_public_key: &authority.public_key,
+                })
+            })
+            .collect();
+
+        let precommits = round
+            .precommits
+            .iter()
+            .zip(&self.authorities)
+            .filter_map(|(vote, authority)| {
+                let vote = vote.as_ref()?;
+                Some(PrecommitRef {
+                    target_hash: &vote.target_hash,
+                    target_number: vote.target_number,
+                    signature: &vote.signature,
+                    authority_public_key: &authority.public_key,
+                })
+            })
+            .collect();
+
+        Some(
+            protocol::GrandpaNotificationRef::CatchUp(protocol::CatchUpRef {
+                set_id: self.set_id,
+                round_number: round.number,
+                prevotes,
+                precommits,
+                base_hash: &round.base.0,
+                base_number: round.base.1,
+            })
+            .scale_encoding(self.block_number_bytes)
+            .fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            }),
+        )
+    }
+
+    /// Injects a catch up response received from the network.
+    ///
+    /// On success, the voter jumps to the round following the one found in the response.
+    pub fn inject_catch_up(
+        &mut self,
+        now: &TNow,
+        catch_up: &protocol::CatchUpRef,
+    ) -> Result<(), InjectCatchUpError> {
+        if catch_up.set_id != self.set_id {
+            return Err(InjectCatchUpError::BadSetId);
+        }
+
+        if catch_up.round_number < self.current_round.number {
+            return Err(InjectCatchUpError::Obsolete);
+        }
+
+        let mut round = Round::new(
+            catch_up.round_number,
+            self.finalized_block,
+            now.clone(),
+            self.authorities.len(),
+        );
+        round.proposal_done = true;
+        round.prevote_done = true;
+        round.precommit_done = true;
+
+        let votes = catch_up
+            .prevotes
+            .iter()
+            .map(|v| {
+                (
+                    VoteKind::Prevote,
+                    v.target_hash,
+                    v.target_number,
+                    v.signature,
+                    v.authority_public_key,
+                )
+            })
+            .chain(catch_up.precommits.iter().map(|v| {
+                (
+                    VoteKind::Precommit,
+                    v.target_hash,
+                    v.target_number,
+                    v.signature,
+                    v.authority_public_key,
+                )
+            }));
+
+        for (kind, target_hash, target_number, signature, authority_public_key) in votes {
+            let authority_index = self
+                .authorities
+                .iter()
+                .position(|a| a.public_key == *authority_public_key)
+                .ok_or(InjectCatchUpError::UnknownAuthority)?;
+
+            if !self.verify_signature(
+                kind,
+                target_hash,
+                target_number,
+                catch_up.round_number,
+                authority_public_key,
+                signature,
+            ) {
+                return Err(InjectCatchUpError::BadSignature);
+            }
+
+            round
+                .insert(
+                    kind,
+                    authority_index,
+                    SignedVote {
+                        target_hash: *target_hash,
+                        target_number,
+                        signature: *signature,
+                    },
+                )
+                .map_err(|_| InjectCatchUpError::Equivocation)?;
+        }
+
+        if !self.evaluate(&round).completable {
+            return Err(InjectCatchUpError::NotCompletable);
+        }
+
+        self.previous_round = Some(round);
+        self.current_round = Round::new(
+            catch_up.round_number + 1,
+            self.finalized_block,
+            now.clone(),
+            self.authorities.len(),
+        );
+        self.next_round_votes.clear();
+        self.catch_up_requested = false;
+        self.local_state_update = true;
+        self.persist_state = true;
+        self.next_rebroadcast = now.clone() + self.gossip_duration * REBROADCAST_GOSSIP_DURATIONS;
+        Ok(())
+    }
+
+    /// Injects the signature of a vote previously returned by [`Action::SignVote`].
+    ///
+    /// The vote is then broadcast through [`Action::Broadcast`], after the new state of the
+    /// voter has been returned through [`Action::PersistState`].
+    ///
+    /// If the vote can't be signed, for example because the private key isn't available
+    /// anymore, the [`VoteToSign`] can simply be dropped, in which case the local authority
+    /// doesn't cast this vote.
+    pub fn inject_vote_signature(&mut self, vote: VoteToSign, signature: [u8; 64]) {
+        debug_assert_eq!(vote.set_id, self.set_id);
+
+        let signed_vote = SignedVote {
+            target_hash: vote.target_hash,
+            target_number: vote.target_number,
+            signature,
+        };
+
+        let round = if vote.round_number == self.current_round.number {
+            &mut self.current_round
+        } else if let Some(round) = self
+            .previous_round
+            .as_mut()
+            .filter(|r| r.number == vote.round_number)
+        {
+            round
+        } else {
+            // The voter has moved on since the vote has been requested.
+            return;
+        };
+
+        if round
+            .insert(vote.kind, self.local_authority_index, signed_vote.clone())
+            .is_err()
+        {
+            return;
+        }
+
+        self.last_voted_round = cmp::max(self.last_voted_round, vote.round_number);
+        self.persist_state = true;
+
+        let notification = self.encode_vote(vote.round_number, vote.kind, &signed_vote);
+        self.pending_broadcasts.push_back(notification);
+    }
+
+    /// Advances the state machine. Must be called repeatedly until it returns [`Action::Idle`],
+    /// and again after any other method has been called or once the moment indicated by
+    /// [`Action::Idle`] has been reached.
+    pub fn next_action(&mut self, now: &TNow) -> Action<TNow> {
+        // The state must be persisted before the votes are broadcast.
+        if mem::take(&mut self.persist_state) {
+            return Action::PersistState(self.persisted_state());
+        }
+
+        if let Some(notification) = self.pending_broadcasts.pop_front() {
+            return Action::Broadcast(notification);
+        }
+
+        if mem::take(&mut self.local_state_update) {
+            return Action::LocalStateUpdate {
+                round_number: self.current_round.number,
+                set_id: self.set_id,
+                commit_finalized_height: self.finalized_block.1,
+            };
+        }
+
+        // Check whether one of the rounds finalizes a new block.
+        for round in self.previous_round.iter().chain(Some(&self.current_round)) {
+            let finalized = match self.evaluate(round).finalized {
+                Some(f) if f.1 > self.finalized_block.1 => f,
+                _ => continue,
+            };
+
+            let commit = self.build_commit(round, finalized);
+            let notification =
+                protocol::GrandpaNotificationRef::Commit(protocol::CommitMessageRef {
+                    round_number: commit.round_number,
+                    set_id: commit.set_id,
+                    message: CompactCommitRef {
+                        target_hash: &commit.target_hash,
+                        target_number: commit.target_number,
+                        precommits: commit
+                            .precommits
+                            .iter()
+                            .map(|p| protocol::UnsignedPrecommitRef {
+                                target_hash: &p.target_hash,
+                                target_number: p.target_number,
+                            })
+                            .collect(),
+                        auth_data: commit
+                            .precommits
+                            .iter()
+                            .map(|p| (&p.signature, &p.authority_public_key))
+                            .collect(),
+                    },
+                })
+                .scale_encoding(self.block_number_bytes)
+                .fold(Vec::new(), |mut a, b| {
+                    a.extend_from_slice(b.as_ref());
+                    a
+                });
+
+            self.finalize(finalized);
+            self.pending_broadcasts.push_back(notification);
+            return Action::Finalized(commit);
+        }
+
+        // After a restart, the local authority might have already voted in the current round.
+        // The voter only follows the votes of the other authorities until the next round.
+        if self.current_round.number <= self.no_vote_up_to_round {
+            self.current_round.proposal_done = true;
+            self.current_round.prevote_done = true;
+            self.current_round.precommit_done = true;
+        }
+
+        let previous_estimate = self
+            .previous_round
+            .as_ref()
+            .and_then(|r| self.evaluate(r).estimate)
+            .unwrap_or(self.finalized_block);
+
+        // The primary authority of the round proposes the estimate of the previous round.
+        if !self.current_round.proposal_done {
+            self.current_round.proposal_done = true;
+            if self.primary_authority_index(self.current_round.number) == self.local_authority_index
+                && previous_estimate.1 > self.finalized_block.1
+            {
+                return Action::SignVote(self.vote_to_sign(
+                    self.current_round.number,
+                    VoteKind::PrimaryPropose,
+                    previous_estimate,
+                ));
+            }
+        }
+
+        let current_state = self.evaluate(&self.current_round);
+        let prevote_timer = self.current_round.start.clone() + self.gossip_duration * 2;
+        let precommit_timer = self.current_round.start.clone() + self.gossip_duration * 4;
+
+        if !self.current_round.prevote_done {
+            if *now >= prevote_timer || current_state.completable {
+                self.current_round.prevote_done = true;
+                let target = self.prevote_target(previous_estimate);
+                return Action::SignVote(self.vote_to_sign(
+                    self.current_round.number,
+                    VoteKind::Prevote,
+                    target,
+                ));
+            }
+        } else if !self.current_round.precommit_done {
+            if let Some(prevote_ghost) = current_state.prevote_ghost {
+                if (*now >= precommit_timer || current_state.completable)
+                    && self.is_descendant_or_equal(prevote_ghost, previous_estimate)
+                {
+                    self.current_round.precommit_done = true;
+                    return Action::SignVote(self.vote_to_sign(
+                        self.current_round.number,
+                        VoteKind::Precommit,
+                        prevote_ghost,
+                    ));
+                }
+            }
+        }
+
+        // Start the next round once the current one is completable.
+        if self.current_round.precommit_done && current_state.completable {
+            let mut new_round = Round::new(
+                self.current_round.number + 1,
+                self.finalized_block,
+                now.clone(),
+                self.authorities.len(),
+            );
+            for (kind, authority_index, vote) in mem::take(&mut self.next_round_votes) {
+                let _ = new_round.insert(kind, authority_index, vote);
+            }
+
+            self.previous_round = Some(mem::replace(&mut self.current_round, new_round));
+            self.catch_up_requested = false;
+            self.persist_state = true;
+            self.next_rebroadcast =
+                now.clone() + self.gossip_duration * REBROADCAST_GOSSIP_DURATIONS;
+            return Action::LocalStateUpdate {
+                round_number: self.current_round.number,
+                set_id: self.set_id,
+                commit_finalized_height: self.finalized_block.1,
+            };
+        }
+
+        // If the round is taking a long time, the votes might have been missed by some peers.
+        // Broadcast them again.
+        if *now >= self.next_rebroadcast {
+            self.next_rebroadcast =
+                now.clone() + self.gossip_duration * REBROADCAST_GOSSIP_DURATIONS;
+            self.catch_up_requested = false;
+
+            for kind in [VoteKind::Prevote, VoteKind::Precommit] {
+                let votes = match kind {
+                    VoteKind::Prevote => &self.current_round.prevotes,
+                    _ => &self.current_round.precommits,
+                };
+                if let Some(vote) = &votes[self.local_authority_index] {
+                    let notification = self.encode_vote(self.current_round.number, kind, vote);
+                    self.pending_broadcasts.push_back(notification);
+                }
+            }
+
+            if let Some(notification) = self.pending_broadcasts.pop_front() {
+                return Action::Broadcast(notification);
+            }
+        }
+
+        let mut wake_up = self.next_rebroadcast.clone();
+        if !self.current_round.prevote_done && prevote_timer > *now {
+            wake_up = cmp::min(wake_up, prevote_timer);
+        } else if !self.current_round.precommit_done && precommit_timer > *now {
+            wake_up = cmp::min(wake_up, precommit_timer);
+        }
+        Action::Idle { wake_up }
+    }
+
+    /// Determines the block the local authority should prevote for.
+    fn prevote_target(&self, previous_estimate: ([u8; 32], u64)) -> ([u8; 32], u64) {
+        // The primary proposal is used as the base if it is between the estimate and the
+        // prevote GHOST of the previous round.
+        let mut base = previous_estimate;
+        if let Some(proposal) = self.current_round.primary_proposal {
+            let previous_prevote_ghost = self
+                .previous_round
+                .as_ref()
+                .and_then(|r| self.evaluate(r).prevote_ghost)
+                .unwrap_or(self.finalized_block);
+            if self.is_descendant_or_equal(proposal, previous_estimate)
+                && self.is_descendant_or_equal(previous_prevote_ghost, proposal)
+            {
+                base = proposal;
+            }
+        }
+
+        // Vote for the best block if it descends from the base.
+        let best_block = (self.best_block, self.blocks[&self.best_block].0);
+        let mut target = if self.is_descendant_or_equal(best_block, base) {
+            best_block
+        } else {
+            base
+        };
+
+        if let Some(voting_limit) = self.voting_limit {
+            while target.1 > voting_limit && target != base {
+                target = (self.blocks[&target.0].1, target.1 - 1);
+            }
+        }
+
+        target
+    }
+
+    /// Builds the commit corresponding to the finalization of the given target in the given
+    /// round.
+    fn build_commit(&self, round: &Round<TNow>, target: ([u8; 32], u64)) -> Commit {
+        let mut precommits = Vec::new();
+        let mut votes_ancestries = Vec::new();
+
+        for (vote, authority) in round.precommits.iter().zip(&self.authorities) {
+            let vote = match vote {
+                Some(v) => v,
+                None => continue,
+            };
+
+            if !self.is_descendant_or_equal((vote.target_hash, vote.target_number), target) {
+                continue;
+            }
+
+            precommits.push(Precommit {
+                target_hash: vote.target_hash,
+                target_number: vote.target_number,
+                signature: vote.signature,
+                authority_public_key: authority.public_key,
+            });
+
+            // The headers between the target of the commit and the target of each precommit
+            // are necessary in order to prove the ancestry.
+            let mut current = (vote.target_hash, vote.target_number);
+            while current != target {
+                if !votes_ancestries.contains(&current.0) {
+                    votes_ancestries.push(current.0);
+                }
+                current = (self.blocks[&current.0].1, current.1 - 1);
+            }
+        }
+
+        Commit {
+            round_number: round.number,
+            set_id: self.set_id,
+            target_hash: target.0,
+            target_number: target.1,
+            precommits,
+            votes_ancestries,
+            block_number_bytes: self.block_number_bytes,
+        }
+    }
+
+    /// Updates the latest finalized block and removes from the tree the blocks that don't
+    /// descend from it.
+    fn finalize(&mut self, new_finalized: ([u8; 32], u64)) {
+        debug_assert!(new_finalized.1 > self.finalized_block.1);
+
+        let to_remove = self
+            .blocks
+            .iter()
+            .filter(|(hash, (number, _))| {
+                !self.is_descendant_or_equal((**hash, *number), new_finalized)
+            })
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+        for hash in to_remove {
+            self.blocks.remove(&hash);
+        }
+
+        self.finalized_block = new_finalized;
+        if !self.blocks.contains_key(&self.best_block) {
+            self.best_block = new_finalized.0;
+        }
+
+        self.local_state_update = true;
+    }
+
+    /// Evaluates the votes of the given round.
+    fn evaluate(&self, round: &Round<TNow>) -> RoundState {
+        let (prevote_weights, _) = self.vote_weights(&round.prevotes);
+        let prevote_ghost = self.ghost(&prevote_weights);
+
+        let (precommit_weights, precommits_total_weight) = self.vote_weights(&round.precommits);
+        let remaining_weight = self.total_weight.saturating_sub(precommits_total_weight);
+        let precommit_weight = |hash: &[u8; 32]| precommit_weights.get(hash).copied().unwrap_or(0);
+        let can_reach_supermajority = |hash: &[u8; 32]| {
+            precommit_weight(hash).saturating_add(remaining_weight) >= self.threshold
+        };
+
+        let prevote_ghost_value = match prevote_ghost {
+            Some(g) => g,
+            None => {
+                return RoundState {
+                    prevote_ghost: None,
+                    estimate: None,
+                    finalized: None,
+                    completable: false,
+                }
+            }
+        };
+
+        let finalized = self.find_ancestor(prevote_ghost_value, |hash| {
+            precommit_weight(hash) >= self.threshold
+        });
+        let estimate = self.find_ancestor(prevote_ghost_value, can_reach_supermajority);
+
+        let completable = precommits_total_weight >= self.threshold
+            && match estimate {
+                None => false,
+                Some(estimate) if estimate != prevote_ghost_value => true,
+                Some(_) => !precommit_weights.keys().any(|hash| {
+                    let (number, parent_hash) = self.blocks[hash];
+                    parent_hash == prevote_ghost_value.0
+                        && number == prevote_ghost_value.1 + 1
+                        && can_reach_supermajority(hash)
+                }),
+            };
+
+        RoundState {
+            prevote_ghost,
+            estimate,
+            finalized,
+            completable,
+        }
+    }
+
+    /// Calculates, for each block of the tree, the total weight of the votes for this block or
+    /// its descendants. Also returns the total weight of all the votes, including the ones for
+    /// blocks that are unknown.
+    fn vote_weights(&self, votes: &[Option<SignedVote>]) -> (BTreeMap<[u8; 32], u64>, u64) {
+        let mut weights = BTreeMap::<[u8; 32], u64>::new();
+        let mut total_weight = 0u64;
+
+        for (vote, authority) in votes.iter().zip(&self.authorities) {
+            let vote = match vote {
+                Some(v) => v,
+                None => continue,
+            };
+
+            total_weight = total_weight.saturating_add(authority.weight.get());
+
+            let mut path = Vec::new();
+            let mut current = (vote.target_hash, vote.target_number);
+            let reaches_finalized = loop {
+                if current == self.finalized_block {
+                    path.push(current.0);
+                    break true;
+                }
+
+                match self.blocks.get(&current.0) {
+                    Some(&(number, parent_hash))
+                        if number == current.1 && number > self.finalized_block.1 =>
+                    {
+                        path.push(current.0);
+                        current = (parent_hash, number - 1);
+                    }
+                    _ => break false,
+                }
+            };
+
+            if reaches_finalized {
+                for hash in path {
+                    let weight = weights.entry(hash).or_insert(0);
+                    *weight = weight.saturating_add(authority.weight.get());
+                }
+            }
+        }
+
+        (weights, total_weight)
+    }
+
+    /// Returns the highest block whose weight is superior or equal to the threshold, or `None`
+    /// if there is none.
+    fn ghost(&self, weights: &BTreeMap<[u8; 32], u64>) -> Option<([u8; 32], u64)> {
+        if !matches!(weights.get(&self.finalized_block.0), Some(w) if *w >= self.threshold) {
+            return None;
+        }
+
+        let mut current = self.finalized_block;
+        'search: loop {
+            for (hash, weight) in weights {
+                if *weight < self.threshold {
+                    continue;
+                }
+
+                let (number, parent_hash) = self.blocks[hash];
+                if parent_hash == current.0 && number == current.1 + 1 {
+                    current = (*hash, number);
+                    continue 'search;
+                }
+            }
+
+            break Some(current);
+        }
+    }
+
+    /// Returns the first block that matches the given predicate, starting from `start` and going
+    /// up the ancestry until the latest finalized block.
+    fn find_ancestor(
+        &self,
+        start: ([u8; 32], u64),
+        mut predicate: impl FnMut(&[u8; 32]) -> bool,
+    ) -> Option<([u8; 32], u64)> {
+        let mut current = start;
+        loop {
+            if predicate(&current.0) {
+                return Some(current);
+            }
+
+            if current.1 <= self.finalized_block.1 {
+                return None;
+            }
+
+            let (_, parent_hash) = self.blocks.get(&current.0)?;
+            current = (*parent_hash, current.1 - 1);
+        }
+    }
+
+    /// Returns `true` if `descendant` is equal to `ancestor` or one of its descendants.
+    fn is_descendant_or_equal(
+        &self,
+        descendant: ([u8; 32], u64),
+        ancestor: ([u8; 32], u64),
+    ) -> bool {
+        let mut current = descendant;
+        loop {
+            if current.1 <= ancestor.1 {
+                return current == ancestor;
+            }
+
+            match self.blocks.get(&current.0) {
+                Some(&(number, parent_hash)) if number == current.1 => {
+                    current = (parent_hash, number - 1);
+                }
+                _ => return false,
+            }
+        }
+    }
+
+    /// Returns the index within [`Voter::authorities`] of the primary authority of the given
+    /// round.
+    fn primary_authority_index(&self, round_number: u64) -> usize {
+        usize::try_from(round_number % u64::try_from(self.authorities.len()).unwrap()).unwrap()
+    }
+
+    fn vote_to_sign(
+        &self,
+        round_number: u64,
+        kind: VoteKind,
+        target: ([u8; 32], u64),
+    ) -> VoteToSign {
+        VoteToSign {
+            round_number,
+            set_id: self.set_id,
+            kind,
+            target_hash: target.0,
+            target_number: target.1,
+            authority_public_key: self.authorities[self.local_authority_index].public_key,
+            block_number_bytes: self.block_number_bytes,
+        }
+    }
+
+    /// Returns `true` if the given signature of a vote is valid.
+    fn verify_signature(
+        &self,
+        kind: VoteKind,
+        target_hash: &[u8; 32],
+        target_number: u64,
+        round_number: u64,
+        authority_public_key: &[u8; 32],
+        signature: &[u8; 64],
+    ) -> bool {
+        let payload = signed_payload(
+            kind,
+            target_hash,
+            target_number,
+            round_number,
+            self.set_id,
+            self.block_number_bytes,
+        );
+
+        match ed25519_zebra::VerificationKey::try_from(*authority_public_key) {
+            Ok(key) => key
+                .verify(&ed25519_zebra::Signature::from(*signature), &payload)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Builds the GrandPa notification containing the given vote of the local authority.
+    fn encode_vote(&self, round_number: u64, kind: VoteKind, vote: &SignedVote) -> Vec<u8> {
+        let message = match kind {
+            VoteKind::Prevote => protocol::MessageRef::Prevote(protocol::UnsignedPrevoteRef {
+                target_hash: &vote.target_hash,
+                target_number: vote.target_number,
+            }),
+            VoteKind::Precommit => {
+                protocol::MessageRef::Precommit(protocol::UnsignedPrecommitRef {
+                    target_hash: &vote.target_hash,
+                    target_number: vote.target_number,
+                })
+            }
+            VoteKind::PrimaryPropose => {
+                protocol::MessageRef::PrimaryPropose(protocol::PrimaryProposeRef {
+                    target_hash: &vote.target_hash,
+                    target_number: vote.target_number,
+                })
+            }
+        };
+
+        protocol::GrandpaNotificationRef::Vote(protocol::VoteMessageRef {
+            round_number,
+            set_id: self.set_id,
+            message,
+            signature: &vote.signature,
+            authority_public_key: &self.authorities[self.local_authority_index].public_key,
+        })
+        .scale_encoding(self.block_number_bytes)
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        })
+    }
+}
+
+impl<TNow> Round<TNow> {
+    fn new(number: u64, base: ([u8; 32], u64), start: TNow, num_authorities: usize) -> Self {
+        Round {
+            number,
+            base,
+            start,
+            prevotes: (0..num_authorities).map(|_| None).collect(),
+            precommits: (0..num_authorities).map(|_| None).collect(),
+            primary_proposal: None,
+            proposal_done: false,
+            prevote_done: false,
+            precommit_done: false,
+        }
+    }
+
+    /// Inserts a vote in the round. Returns an error if the authority has already cast a
+    /// different vote of the same kind.
+    fn insert(
+        &mut self,
+        kind: VoteKind,
+        authority_index: usize,
+        vote: SignedVote,
+    ) -> Result<(), InjectVoteError> {
+        let slot = match kind {
+            VoteKind::Prevote => &mut self.prevotes[authority_index],
+            VoteKind::Precommit => &mut self.precommits[authority_index],
+            VoteKind::PrimaryPropose => {
+                if self.primary_proposal.is_none() {
+                    self.primary_proposal = Some((vote.target_hash, vote.target_number));
+                }
+                return Ok(());
+            }
+        };
+
+        match slot {
+            Some(existing) if *existing != vote => Err(InjectVoteError::Equivocation),
+            Some(_) => Ok(()),
+            None => {
+                *slot = Some(vote);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Builds the payload that authorities sign when casting a vote.
+fn signed_payload(
+    kind: VoteKind,
+    target_hash: &[u8; 32],
+    target_number: u64,
+    round_number: u64,
+    set_id: u64,
+    block_number_bytes: usize,
+) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(1 + 32 + block_number_bytes + 8 + 8);
+    payload.push(match kind {
+        VoteKind::Prevote => 0u8,
+        VoteKind::Precommit => 1u8,
+        VoteKind::PrimaryPropose => 2u8,
+    });
+    payload.extend_from_slice(target_hash);
+    crate::util::encode_varsize_number_u64(&mut payload, target_number, block_number_bytes);
+    payload.extend_from_slice(&round_number.to_le_bytes());
+    payload.extend_from_slice(&set_id.to_le_bytes());
+    payload
+}
+
+/// Action that the user of the [`Voter`] must perform. See [`Voter::next_action`].
+#[derive(Debug)]
+pub enum Action<TNow> {
+    /// A vote must be signed using the private key of the local authority, then passed to
+    /// [`Voter::inject_vote_signature`].
+    SignVote(VoteToSign),
+
+    /// The given SCALE-encoded GrandPa notification must be sent to all the peers.
+    Broadcast(Vec<u8>),
+
+    /// A block has been finalized by the voter.
+    ///
+    /// The corresponding commit message is also returned through [`Action::Broadcast`].
+    Finalized(Commit),
+
+    /// The given state must be persisted, and passed back as [`Config::persisted_state`] if the
+    /// voter is restarted. It must be persisted before [`Voter::next_action`] is called again,
+    /// as this state covers the votes that are about to be broadcast.
+    PersistState(PersistedState),
+
+    /// The state of the voter, as advertised to peers through neighbor packets, has changed.
+    LocalStateUpdate {
+        /// Number of the round the voter is participating in.
+        round_number: u64,
+        /// Identifier of the authorities set.
+        set_id: u64,
+        /// Number of the latest block finalized by the voter.
+        commit_finalized_height: u64,
+    },
+
+    /// Nothing more to do for now. [`Voter::next_action`] must be called again once the given
+    /// moment is reached, or after any other method of the [`Voter`] has been called.
+    Idle {
+        /// Moment when [`Voter::next_action`] must be called again.
+        wake_up: TNow,
+    },
+}
+
+/// Kind of a vote.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VoteKind {
+    /// First vote of a round, indicating which block the authority believes should be
+    /// finalized.
+    Prevote,
+    /// Second vote of a round, based on the prevotes of all the authorities.
+    Precommit,
+    /// Block proposed by the primary authority of the round to the other authorities.
+    PrimaryPropose,
+}
+
+/// Vote of the local authority that must be signed. See [`Action::SignVote`].
+#[derive(Debug, Clone)]
+pub struct VoteToSign {
+    round_number: u64,
+    set_id: u64,
+    kind: VoteKind,
+    target_hash: [u8; 32],
+    target_number: u64,
+    authority_public_key: [u8; 32],
+    block_number_bytes: usize,
+}
+
+impl VoteToSign {
+    /// Returns the number of the round the vote belongs to.
+    pub fn round_number(&self) -> u64 {
+        self.round_number
+    }
+
+    /// Returns the kind of vote.
+    pub fn kind(&self) -> VoteKind {
+        self.kind
+    }
+
+    /// Returns the hash of the block the vote is for.
+    pub fn target_hash(&self) -> &[u8; 32] {
+        &self.target_hash
+    }
+
+    /// Returns the number of the block the vote is for.
+    pub fn target_number(&self) -> u64 {
+        self.target_number
+    }
+
+    /// Returns the Ed25519 public key of the authority whose private key must sign the vote.
+    pub fn authority_public_key(&self) -> &[u8; 32] {
+        &self.authority_public_key
+    }
+
+    /// Returns the payload that must be signed with Ed25519.
+    pub fn payload(&self) -> Vec<u8> {
+        signed_payload(
+            self.kind,
+            &self.target_hash,
+            self.target_number,
+            self.round_number,
+            self.set_id,
+            self.block_number_bytes,
+        )
+    }
+}
+
+/// State of a [`Voter`] that must be persisted across restarts. See [`Action::PersistState`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PersistedState {
+    /// Identifier of the authorities set.
+    pub set_id: u64,
+    /// Number of the latest round that the voter has completed. `0` if none.
+    pub last_completed_round: u64,
+    /// Number of the latest round during which the local authority has cast a vote. `0` if
+    /// none.
+    pub last_voted_round: u64,
+    /// Prevotes and precommits cast by the local authority during the rounds that the voter was
+    /// still tracking.
+    pub local_votes: Vec<LocalVote>,
+}
+
+/// Prevote or precommit cast by the local authority. See [`PersistedState`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalVote {
+    /// Round the vote belongs to.
+    pub round_number: u64,
+    /// Either [`VoteKind::Prevote`] or [`VoteKind::Precommit`].
+    pub kind: VoteKind,
+    /// Hash of the block the vote is for.
+    pub target_hash: [u8; 32],
+    /// Number of the block the vote is for.
+    pub target_number: u64,
+    /// Ed25519 signature of the vote.
+    pub signature: [u8; 64],
+}
+
+impl PersistedState {
+    /// Returns the encoding of the state, which can be decoded with [`PersistedState::decode`].
+    pub fn scale_encoding_vec(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(24 + 1 + self.local_votes.len() * (8 + 1 + 32 + 8 + 64));
+        out.extend_from_slice(&self.set_id.to_le_bytes());
+        out.extend_from_slice(&self.last_completed_round.to_le_bytes());
+        out.extend_from_slice(&self.last_voted_round.to_le_bytes());
+        out.extend_from_slice(
+            crate::util::encode_scale_compact_usize(self.local_votes.len()).as_ref(),
+        );
+        for vote in &self.local_votes {
+            out.extend_from_slice(&vote.round_number.to_le_bytes());
+            out.push(match vote.kind {
+                VoteKind::Prevote => 0,
+                VoteKind::Precommit => 1,
+                VoteKind::PrimaryPropose => 2,
+            });
+            out.extend_from_slice(&vote.target_hash);
+            out.extend_from_slice(&vote.target_number.to_le_bytes());
+            out.extend_from_slice(&vote.signature);
+        }
+        out
+    }
+
+    /// Decodes a state previously encoded with [`PersistedState::scale_encoding_vec`].
+    pub fn decode(encoded: &[u8]) -> Result<Self, PersistedStateDecodeError> {
+        let result: Result<_, nom::Err<nom::error::Error<&[u8]>>> =
+            nom::combinator::all_consuming(nom::combinator::map(
+                nom::sequence::tuple((
+                    nom::number::complete::le_u64,
+                    nom::number::complete::le_u64,
+                    nom::number::complete::le_u64,
+                    nom::multi::length_count(
+                        crate::util::nom_scale_compact_usize,
+                        nom::combinator::map(
+                            nom::sequence::tuple((
+                                nom::number::complete::le_u64,
+                                nom::branch::alt((
+                                    nom::combinator::map(
+                                        nom::bytes::complete::tag(&[0][..]),
+                                        |_| VoteKind::Prevote,
+                                    ),
+                                    nom::combinator::map(
+                                        nom::bytes::complete::tag(&[1][..]),
+                                        |_| VoteKind::Precommit,
+                                    ),
+                                )),
+                                nom::bytes::complete::take(32u32),
+                                nom::number::complete::le_u64,
+                                nom::bytes::complete::take(64u32),
+                            )),
+                            |(round_number, kind, target_hash, target_number, signature)| {
+                                LocalVote {
+                                    round_number,
+                                    kind,
+                                    target_hash: <[u8; 32]>::try_from(target_hash).unwrap(),
+                                    target_number,
+                                    signature: <[u8; 64]>::try_from(signature).unwrap(),
+                                }
+                            },
+                        ),
+                    ),
+                )),
+                |(set_id, last_completed_round, last_voted_round, local_votes)| PersistedState {
+                    set_id,
+                    last_completed_round,
+                    last_voted_round,
+                    local_votes,
+                },
+            ))(encoded);
+
+        match result {
+            Ok((_, state)) => Ok(state),
+            Err(_) => Err(PersistedStateDecodeError),
+        }
+    }
+}
+
+/// Error potentially returned by [`PersistedState::decode`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed to decode the persisted state of the GrandPa voter")]
+pub struct PersistedStateDecodeError;
+
+/// Precommits that have finalized a block. See [`Action::Finalized`].
+#[derive(Debug, Clone)]
+pub struct Commit {
+    /// Round during which the block has been finalized.
+    pub round_number: u64,
+    /// Identifier of the authorities set.
+    pub set_id: u64,
+    /// Hash of the block that has been finalized.
+    pub target_hash: [u8; 32],
+    /// Number of the block that has been finalized.
+    pub target_number: u64,
+    /// Precommits for the finalized block or its descendants.
+    pub precommits: Vec<Precommit>,
+    /// Hashes of the blocks between the finalized block (exclusive) and the targets of the
+    /// precommits (inclusive). The headers of these blocks are necessary in order to build a
+    /// justification. See [`Commit::scale_encoded_justification`].
+    pub votes_ancestries: Vec<[u8; 32]>,
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+}
+
+impl Commit {
+    /// Builds the SCALE-encoded justification that proves the finality of the target block.
+    ///
+    /// Must be passed the SCALE-encoded headers of the blocks whose hash is found in
+    /// [`Commit::votes_ancestries`].
+    pub fn scale_encoded_justification(
+        &self,
+        votes_ancestries: impl ExactSizeIterator<Item = impl AsRef<[u8]>>,
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.round_number.to_le_bytes());
+        out.extend_from_slice(&self.target_hash);
+        crate::util::encode_varsize_number_u64(
+            &mut out,
+            self.target_number,
+            self.block_number_bytes,
+        );
+
+        out.extend_from_slice(
+            crate::util::encode_scale_compact_usize(self.precommits.len()).as_ref(),
+        );
+        for precommit in &self.precommits {
+            out.extend_from_slice(&precommit.target_hash);
+            crate::util::encode_varsize_number_u64(
+                &mut out,
+                precommit.target_number,
+                self.block_number_bytes,
+            );
+            out.extend_from_slice(&precommit.signature);
+            out.extend_from_slice(&precommit.authority_public_key);
+        }
+
+        out.extend_from_slice(
+            crate::util::encode_scale_compact_usize(votes_ancestries.len()).as_ref(),
+        );
+        for header in votes_ancestries {
+            out.extend_from_slice(header.as_ref());
+        }
+
+        out
+    }
+}
+
+/// Error potentially returned by [`Voter::inject_vote`].
+#[derive(Debug, derive_more::Display)]
+pub enum InjectVoteError {
+    /// Vote concerns a different authorities set.
+    BadSetId,
+    /// Vote concerns a round that is neither the current, previous, or next round.
+    InactiveRound,
+    /// Signer of the vote isn't part of the authorities set.
+    UnknownAuthority,
+    /// Primary proposal has been emitted by an authority that isn't the primary of the round.
+    NotPrimary,
+    /// Signature of the vote is invalid.
+    BadSignature,
+    /// Authority has already cast a different vote of the same kind in this round.
+    Equivocation,
+}
+
+/// Error potentially returned by [`Voter::inject_catch_up`].
+#[derive(Debug, derive_more::Display)]
+pub enum InjectCatchUpError {
+    /// Catch up concerns a different authorities set.
+    BadSetId,
+    /// Catch up concerns a round older than the current round.
+    Obsolete,
+    /// Signer of one of the votes isn't part of the authorities set.
+    UnknownAuthority,
+    /// Signature of one of the votes is invalid.
+    BadSignature,
+    /// One authority has cast two different votes of the same kind.
+    Equivocation,
+    /// The votes don't make the round completable, or concern blocks unknown to the voter.
+    NotCompletable,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{signed_payload, Action, Config, LocalVote, PersistedState, VoteKind, Voter};
+    use crate::{header, network::protocol};
+    use core::{num::NonZeroU64, time::Duration};
+
+    /// Builds a voter that is one of four authorities and knows about blocks #1 and #2.
+    fn restarted_voter(persisted_state: Option<PersistedState>) -> Voter<Duration> {
+        let authorities = (0..4u8)
+            .map(|n| header::GrandpaAuthority {
+                public_key: [n; 32],
+                weight: NonZeroU64::new(1).unwrap(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut voter = Voter::new(Config {
+            block_number_bytes: 4,
+            set_id: 3,
+            authorities: &authorities,
+            local_authority_public_key: [0; 32],
+            finalized_block_hash: [0; 32],
+            finalized_block_number: 0,
+            voting_limit: None,
+            gossip_duration: Duration::from_secs(1),
+            now: Duration::new(0, 0),
+            persisted_state,
+        })
+        .unwrap();
+
+        voter.insert_block([1; 32], 1, [0; 32]);
+        voter.insert_block([2; 32], 2, [1; 32]);
+        voter.set_best_block(&[2; 32]);
+        voter
+    }
+
+    fn example_persisted_state() -> PersistedState {
+        PersistedState {
+            set_id: 3,
+            last_completed_round: 4,
+            last_voted_round: 5,
+            local_votes: vec![
+                LocalVote {
+                    round_number: 4,
+                    kind: VoteKind::Precommit,
+                    target_hash: [1; 32],
+                    target_number: 1,
+                    signature: [8; 64],
+                },
+                LocalVote {
+                    round_number: 5,
+                    kind: VoteKind::Prevote,
+                    target_hash: [2; 32],
+                    target_number: 2,
+                    signature: [9; 64],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn persisted_state_encode_decode() {
+        let state = example_persisted_state();
+        let encoded = state.scale_encoding_vec();
+        assert_eq!(PersistedState::decode(&encoded).unwrap(), state);
+
+        assert!(PersistedState::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(PersistedState::decode(&[encoded.clone(), vec![0]].concat()).is_err());
+    }
+
+    #[test]
+    fn no_vote_in_restored_round() {
+        let mut voter = restarted_voter(Some(example_persisted_state()));
+        assert_eq!(voter.round_number(), 5);
+        assert_eq!(voter.persisted_state(), example_persisted_state());
+
+        assert!(matches!(
+            voter.next_action(&Duration::new(0, 0)),
+            Action::LocalStateUpdate {
+                round_number: 5,
+                ..
+            }
+        ));
+
+        // Even once the prevote and precommit timers have been reached, no vote is cast.
+        assert!(matches!(
+            voter.next_action(&Duration::from_secs(5)),
+            Action::Idle { .. }
+        ));
+
+        // The restored vote is broadcast again, as it might not have been received by the
+        // other authorities before the restart.
+        assert!(matches!(
+            voter.next_action(&Duration::from_secs(8)),
+            Action::Broadcast(_)
+        ));
+        assert!(matches!(
+            voter.next_action(&Duration::from_secs(8)),
+            Action::Idle { .. }
+        ));
+    }
+
+    #[test]
+    fn votes_in_round_following_restored_round() {
+        let mut voter = restarted_voter(Some(PersistedState {
+            set_id: 3,
+            last_completed_round: 4,
+            last_voted_round: 4,
+            local_votes: Vec::new(),
+        }));
+        assert_eq!(voter.round_number(), 5);
+
+        assert!(matches!(
+            voter.next_action(&Duration::new(0, 0)),
+            Action::LocalStateUpdate {
+                round_number: 5,
+                ..
+            }
+        ));
+        match voter.next_action(&Duration::from_secs(2)) {
+            Action::SignVote(vote) => {
+                assert_eq!(vote.round_number(), 5);
+                assert_eq!(vote.kind(), VoteKind::Prevote);
+                assert_eq!(*vote.target_hash(), [2; 32]);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn persisted_state_of_other_set_ignored() {
+        let mut voter = restarted_voter(Some(PersistedState {
+            set_id: 2,
+            ..example_persisted_state()
+        }));
+        assert_eq!(voter.round_number(), 1);
+        assert_eq!(voter.persisted_state().last_voted_round, 0);
+
+        assert!(matches!(
+            voter.next_action(&Duration::new(0, 0)),
+            Action::LocalStateUpdate { .. }
+        ));
+        assert!(matches!(
+            voter.next_action(&Duration::from_secs(2)),
+            Action::SignVote(_)
+        ));
+    }
+
+    #[test]
+    fn finalizes_best_block() {
+        let keys = (0..4u8)
+            .map(|n| ed25519_zebra::SigningKey::from([n + 1; 32]))
+            .collect::<Vec<_>>();
+        let authorities = keys
+            .iter()
+            .map(|k| header::GrandpaAuthority {
+                public_key: <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(k)),
+                weight: NonZeroU64::new(1).unwrap(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut voter = Voter::new(Config {
+            block_number_bytes: 4,
+            set_id: 0,
+            authorities: &authorities,
+            local_authority_public_key: authorities[0].public_key,
+            finalized_block_hash: [0; 32],
+            finalized_block_number: 0,
+            voting_limit: None,
+            gossip_duration: Duration::from_secs(1),
+            now: Duration::new(0, 0),
+            persisted_state: None,
+        })
+        .unwrap();
+
+        voter.insert_block([1; 32], 1, [0; 32]);
+        voter.insert_block([2; 32], 2, [1; 32]);
+        voter.set_best_block(&[2; 32]);
+
+        // Signs the vote of the remote authority of the given index and injects it.
+        let inject_remote_vote = |voter: &mut Voter<Duration>, index: usize, kind| {
+            let signature =
+                <[u8; 64]>::from(keys[index].sign(&signed_payload(kind, &[2; 32], 2, 1, 0, 4)));
+            let target = protocol::UnsignedPrevoteRef {
+                target_hash: &[2; 32],
+                target_number: 2,
+            };
+            let message = match kind {
+                VoteKind::Prevote => protocol::MessageRef::Prevote(target),
+                _ => protocol::MessageRef::Precommit(protocol::UnsignedPrecommitRef {
+                    target_hash: target.target_hash,
+                    target_number: target.target_number,
+                }),
+            };
+            voter
+                .inject_vote(&protocol::VoteMessageRef {
+                    round_number: 1,
+                    set_id: 0,
+                    message,
+                    signature: &signature,
+                    authority_public_key: &authorities[index].public_key,
+                })
+                .unwrap();
+        };
+
+        // Signs the vote requested by the voter, and checks that it is then broadcast.
+        let sign_local_vote = |voter: &mut Voter<Duration>, now: Duration, kind| {
+            match voter.next_action(&now) {
+                Action::SignVote(vote) => {
+                    assert_eq!(vote.kind(), kind);
+                    assert_eq!(*vote.target_hash(), [2; 32]);
+                    let signature = <[u8; 64]>::from(keys[0].sign(&vote.payload()));
+                    voter.inject_vote_signature(vote, signature);
+                }
+                _ => panic!(),
+            }
+            assert!(matches!(voter.next_action(&now), Action::PersistState(_)));
+            assert!(matches!(voter.next_action(&now), Action::Broadcast(_)));
+        };
+
+        assert!(matches!(
+            voter.next_action(&Duration::new(0, 0)),
+            Action::LocalStateUpdate {
+                round_number: 1,
+                ..
+            }
+        ));
+        assert!(matches!(
+            voter.next_action(&Duration::new(0, 0)),
+            Action::Idle { wake_up } if wake_up == Duration::from_secs(2)
+        ));
+
+        sign_local_vote(&mut voter, Duration::from_secs(2), VoteKind::Prevote);
+        inject_remote_vote(&mut voter, 1, VoteKind::Prevote);
+        inject_remote_vote(&mut voter, 2, VoteKind::Prevote);
+
+        sign_local_vote(&mut voter, Duration::from_secs(4), VoteKind::Precommit);
+        inject_remote_vote(&mut voter, 1, VoteKind::Precommit);
+        inject_remote_vote(&mut voter, 2, VoteKind::Precommit);
+
+        match voter.next_action(&Duration::from_secs(4)) {
+            Action::Finalized(commit) => {
+                assert_eq!(commit.target_hash, [2; 32]);
+                assert_eq!(commit.precommits.len(), 3);
+                assert!(commit.votes_ancestries.is_empty());
+            }
+            _ => panic!(),
+        }
+        assert_eq!(voter.finalized_block(), (2, &[2; 32]));
+    }
+}
@@ -22,7 +22,9 @@
 //! cryptographic key pairs (i.e. both the public and secret keys).
 //!
 //! Each key pair contained within the keystore is identified as a `(KeyNamespace, [u8; 32])`
-//! tuple, where the `[u8; 32]` is the public key. See [`KeyNamespace`].
+//! tuple, where the `[u8; 32]` is the public key. See [`KeyNamespace`]. ECDSA key pairs, whose
+//! compressed public keys are 33 bytes long, are instead identified as a
+//! `(KeyNamespace, [u8; 33])` tuple and are accessed through dedicated functions.
 //!
 //! A keystore is optionally associated with a directory of the file system into which it will
 //! store secret keys permanently. Keys present in this directory are considered to be the content
@@ -56,6 +58,7 @@ pub enum KeyNamespace {
     Aura,
     AuthorityDiscovery,
     Babe,
+    Beefy,
     Grandpa,
     ImOnline,
     // TODO: there exists other variants in Substrate but it's unclear whether they're in use (see https://github.com/paritytech/substrate/blob/cafe12e7785bf92e5dc04780c10e7f8330a15a4c/primitives/core/src/crypto.rs)
@@ -68,17 +71,25 @@ impl KeyNamespace {
             KeyNamespace::Aura,
             KeyNamespace::AuthorityDiscovery,
             KeyNamespace::Babe,
+            KeyNamespace::Beefy,
             KeyNamespace::Grandpa,
             KeyNamespace::ImOnline,
         ]
         .into_iter()
     }
 
+    /// Returns the namespace corresponding to the given key type identifier, as passed by the
+    /// runtime to the host functions that access the keystore. Returns `None` if unknown.
+    pub fn from_key_type_id(id: &[u8; 4]) -> Option<Self> {
+        Self::from_string(str::from_utf8(id).ok()?)
+    }
+
     fn from_string(str: &str) -> Option<Self> {
         match str {
             "aura" => Some(KeyNamespace::Aura),
             "audi" => Some(KeyNamespace::AuthorityDiscovery),
             "babe" => Some(KeyNamespace::Babe),
+            "beef" => Some(KeyNamespace::Beefy),
             "gran" => Some(KeyNamespace::Grandpa),
             "imon" => Some(KeyNamespace::ImOnline),
             _ => None,
@@ -90,6 +101,7 @@ impl KeyNamespace {
             KeyNamespace::Aura => "aura",
             KeyNamespace::AuthorityDiscovery => "audi",
             KeyNamespace::Babe => "babe",
+            KeyNamespace::Beefy => "beef",
             KeyNamespace::Grandpa => "gran",
             KeyNamespace::ImOnline => "imon",
         }
@@ -124,6 +136,10 @@ impl Keystore {
             SipHasherBuild::new(gen_rng.sample(rand::distributions::Standard))
         });
 
+        let mut ecdsa_keys = hashbrown::HashMap::with_capacity_and_hasher(8, {
+            SipHasherBuild::new(gen_rng.sample(rand::distributions::Standard))
+        });
+
         // Load the keys from the disk.
         // TODO: return some diagnostic about invalid files?
         if let Some(keys_directory) = &keys_directory {
@@ -151,26 +167,17 @@ impl Keystore {
                                 KeyNamespace::from_string,
                             ),
                             nom::bytes::complete::tag("-"),
-                            nom::combinator::map_opt(
-                                nom::bytes::complete::take(7u32),
-                                |b| match b {
-                                    "ed25519" => Some(PrivateKey::FileEd25519),
-                                    "sr25519" => Some(PrivateKey::FileSr25519),
-                                    _ => None,
-                                },
-                            ),
+                            nom::branch::alt((
+                                nom::bytes::complete::tag("ed25519"),
+                                nom::bytes::complete::tag("sr25519"),
+                                nom::bytes::complete::tag("ecdsa"),
+                            )),
                             nom::bytes::complete::tag("-"),
                             nom::combinator::map_opt(
                                 nom::bytes::complete::take_while(|c| {
                                     (c >= '0' && c <= '9') || (c >= 'a' && c <= 'f')
                                 }),
-                                |k: &str| {
-                                    if k.len() == 64 {
-                                        Some(<[u8; 32]>::try_from(hex::decode(k).unwrap()).unwrap())
-                                    } else {
-                                        None
-                                    }
-                                },
+                                |k: &str| hex::decode(k).ok(),
                             ),
                         ))),
                     );
@@ -180,6 +187,39 @@ impl Keystore {
                     Err(_) => continue,
                 };
 
+                // ECDSA keys are stored separately, as their public keys are 33 bytes long.
+                if algorithm == "ecdsa" {
+                    let public_key = match <[u8; 33]>::try_from(&public_key[..]) {
+                        Ok(k) => k,
+                        Err(_) => continue,
+                    };
+
+                    match Self::load_ecdsa_from_file(keys_directory.join(entry.path())).await {
+                        Ok(sk) => {
+                            if libsecp256k1::PublicKey::from_secret_key(&sk).serialize_compressed()
+                                != public_key
+                            {
+                                continue;
+                            }
+                        }
+                        Err(_) => continue,
+                    }
+
+                    ecdsa_keys.insert((namespace, public_key), PrivateKeyEcdsa::File);
+                    continue;
+                }
+
+                let algorithm = match algorithm {
+                    "ed25519" => PrivateKey::FileEd25519,
+                    "sr25519" => PrivateKey::FileSr25519,
+                    _ => unreachable!(),
+                };
+
+                let public_key = match <[u8; 32]>::try_from(&public_key[..]) {
+                    Ok(k) => k,
+                    Err(_) => continue,
+                };
+
                 // Make sure that the content of the file is valid and that it corresponds to
                 // the public key advertised in the file name.
                 match algorithm {
@@ -215,7 +255,11 @@ impl Keystore {
 
         Ok(Keystore {
             keys_directory,
-            guarded: Mutex::new(Guarded { gen_rng, keys }),
+            guarded: Mutex::new(Guarded {
+                gen_rng,
+                keys,
+                ecdsa_keys,
+            }),
         })
     }
 
@@ -346,6 +390,140 @@ impl Keystore {
         Ok(public_key)
     }
 
+    /// Generates a new ECDSA key and inserts it in the keystore.
+    ///
+    /// If `save` is `true`, the generated key is saved in the file system. This function returns
+    /// an error only if `save` is `true` and the key couldn't be written to the file system.
+    /// The value of `save` is silently ignored if no path was provided to [`Keystore::new`].
+    ///
+    /// Returns the corresponding compressed public key.
+    pub async fn generate_ecdsa(
+        &self,
+        namespace: KeyNamespace,
+        save: bool,
+    ) -> Result<[u8; 33], io::Error> {
+        let mut guarded = self.guarded.lock().await;
+
+        // A small fraction of 32 bytes values aren't valid secp256k1 private keys, in which case
+        // we simply try again.
+        let private_key = loop {
+            let bytes: [u8; 32] = guarded.gen_rng.sample(rand::distributions::Standard);
+            if let Ok(key) = libsecp256k1::SecretKey::parse(&bytes) {
+                break key;
+            }
+        };
+        let public_key =
+            libsecp256k1::PublicKey::from_secret_key(&private_key).serialize_compressed();
+
+        let save_path = if save {
+            self.path_of_key_ecdsa(namespace, &public_key)
+        } else {
+            None
+        };
+
+        if let Some(save_path) = save_path {
+            Self::write_to_file_ecdsa(&save_path, &private_key).await?;
+            guarded
+                .ecdsa_keys
+                .insert((namespace, public_key), PrivateKeyEcdsa::File);
+        } else {
+            guarded.ecdsa_keys.insert(
+                (namespace, public_key),
+                PrivateKeyEcdsa::Memory(private_key),
+            );
+        }
+
+        Ok(public_key)
+    }
+
+    /// Inserts in the keystore an ECDSA key derived from the given seed phrase.
+    ///
+    /// The key is not saved on disk.
+    ///
+    /// Returns the corresponding compressed public key.
+    pub async fn insert_ecdsa_from_seed_phrase(
+        &self,
+        namespace: KeyNamespace,
+        phrase: &str,
+    ) -> Result<[u8; 33], EcdsaSeedPhraseError> {
+        let private_key =
+            seed_phrase::decode_ecdsa_private_key(phrase).map_err(EcdsaSeedPhraseError::Parse)?;
+        let private_key = libsecp256k1::SecretKey::parse(&private_key)
+            .map_err(|_| EcdsaSeedPhraseError::InvalidPrivateKey)?;
+        let public_key =
+            libsecp256k1::PublicKey::from_secret_key(&private_key).serialize_compressed();
+
+        self.guarded.lock().await.ecdsa_keys.insert(
+            (namespace, public_key),
+            PrivateKeyEcdsa::Memory(private_key),
+        );
+
+        Ok(public_key)
+    }
+
+    /// Returns the list of all ECDSA keys known to this keystore.
+    ///
+    /// > **Note**: Keep in mind that this function is racy, as keys can be added and removed
+    /// >           in parallel of this function being called.
+    pub async fn keys_ecdsa(&self) -> impl Iterator<Item = (KeyNamespace, [u8; 33])> {
+        let guarded = self.guarded.lock().await;
+        guarded
+            .ecdsa_keys
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Signs the Blake2-256 hash of the given payload using the ECDSA private key associated to
+    /// the public key passed as parameter.
+    ///
+    /// The signature is returned in its 65 bytes format, where the last byte is the recovery ID.
+    ///
+    /// An error is returned if the key-namespace combination is not in the keystore, or if the
+    /// key couldn't be loaded from disk. In the case when a key couldn't be loaded from disk, it
+    /// is automatically removed from the keystore.
+    pub async fn sign_ecdsa(
+        &self,
+        key_namespace: KeyNamespace,
+        public_key: &[u8; 33],
+        payload: &[u8],
+    ) -> Result<[u8; 65], SignError> {
+        let mut guarded = self.guarded.lock().await;
+        let key = guarded
+            .ecdsa_keys
+            .get(&(key_namespace, *public_key))
+            .ok_or(SignError::UnknownPublicKey)?;
+
+        let private_key = match key {
+            PrivateKeyEcdsa::Memory(key) => Cow::Borrowed(key),
+            PrivateKeyEcdsa::File => {
+                match Self::load_ecdsa_from_file(
+                    self.path_of_key_ecdsa(key_namespace, public_key).unwrap(),
+                )
+                .await
+                {
+                    Ok(key) => Cow::Owned(key),
+                    Err(err) => {
+                        guarded.ecdsa_keys.remove(&(key_namespace, *public_key));
+                        return Err(err.into());
+                    }
+                }
+            }
+        };
+
+        // `blake2b` always returns 32 bytes when asked for 32 bytes.
+        let hash = <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], payload).as_bytes())
+            .unwrap();
+        let (signature, recovery_id) =
+            libsecp256k1::sign(&libsecp256k1::Message::parse(&hash), &private_key);
+
+        let mut out = [0; 65];
+        out[..64].copy_from_slice(&signature.serialize());
+        out[64] = recovery_id.serialize();
+        Ok(out)
+    }
+
     /// Signs the given payload using the private key associated to the public key passed as
     /// parameter.
     ///
@@ -464,9 +642,9 @@ impl Keystore {
                         }
                     }
 
-                    let (_in_out, proof, _) = key.vrf_sign(transcript);
+                    let (in_out, proof, _) = key.vrf_sign(transcript);
                     Ok(VrfSignature {
-                        // TODO: should probably output the `_in_out` as well
+                        output: in_out.to_preout().to_bytes(),
                         proof: proof.to_bytes(),
                     })
                 }
@@ -504,6 +682,20 @@ impl Keystore {
             .into())
     }
 
+    async fn load_ecdsa_from_file(
+        path: impl AsRef<path::Path>,
+    ) -> Result<libsecp256k1::SecretKey, KeyLoadError> {
+        // TODO: read asynchronously?
+        let bytes = fs::read(path).map_err(KeyLoadError::Io)?;
+        let phrase =
+            str::from_utf8(&bytes).map_err(|err| KeyLoadError::BadFormat(err.to_string()))?;
+        let private_key = seed_phrase::decode_ecdsa_private_key(phrase)
+            .map_err(|err| KeyLoadError::BadFormat(err.to_string()))?;
+        // TODO: zero memory of the private key on drop ^
+        libsecp256k1::SecretKey::parse(&private_key)
+            .map_err(|err| KeyLoadError::BadFormat(err.to_string()))
+    }
+
     async fn write_to_file_ed25519(
         path: impl AsRef<path::Path>,
         key: &ed25519_zebra::SigningKey,
@@ -520,6 +712,14 @@ impl Keystore {
         Self::write_to_file(path, &phrase).await
     }
 
+    async fn write_to_file_ecdsa(
+        path: impl AsRef<path::Path>,
+        key: &libsecp256k1::SecretKey,
+    ) -> Result<(), io::Error> {
+        let phrase = hex::encode(key.serialize());
+        Self::write_to_file(path, &phrase).await
+    }
+
     async fn write_to_file(
         path: impl AsRef<path::Path>,
         key_phrase: &str,
@@ -551,11 +751,19 @@ impl Keystore {
         self.path_of_key(key_namespace, "sr25519", public_key)
     }
 
+    fn path_of_key_ecdsa(
+        &self,
+        key_namespace: KeyNamespace,
+        public_key: &[u8; 33],
+    ) -> Option<path::PathBuf> {
+        self.path_of_key(key_namespace, "ecdsa", public_key)
+    }
+
     fn path_of_key(
         &self,
         key_namespace: KeyNamespace,
         key_algorithm: &str,
-        public_key: &[u8; 32],
+        public_key: &[u8],
     ) -> Option<path::PathBuf> {
         let keys_directory = match &self.keys_directory {
             Some(k) => k,
@@ -587,9 +795,13 @@ impl Keystore {
 struct Guarded {
     gen_rng: rand_chacha::ChaCha20Rng,
     keys: hashbrown::HashMap<(KeyNamespace, [u8; 32]), PrivateKey, SipHasherBuild>,
+    ecdsa_keys: hashbrown::HashMap<(KeyNamespace, [u8; 33]), PrivateKeyEcdsa, SipHasherBuild>,
 }
 
 pub struct VrfSignature {
+    /// VRF output, also known as "pre-output".
+    pub output: [u8; 32],
+    /// Proof that [`VrfSignature::output`] has been generated by the owner of the private key.
     pub proof: [u8; 64],
 }
 
@@ -617,6 +829,15 @@ pub enum KeyLoadError {
     BadFormat(String),
 }
 
+#[derive(Debug, derive_more::Display)]
+pub enum EcdsaSeedPhraseError {
+    /// Failed to parse the seed phrase.
+    #[display(fmt = "{}", _0)]
+    Parse(seed_phrase::ParsePrivateKeyError),
+    /// The seed phrase doesn't correspond to a valid secp256k1 private key.
+    InvalidPrivateKey,
+}
+
 #[derive(Debug, derive_more::Display)]
 pub enum SignVrfError {
     #[display(fmt = "{}", _0)]
@@ -631,6 +852,11 @@ enum PrivateKey {
     FileSr25519,
 }
 
+enum PrivateKeyEcdsa {
+    Memory(libsecp256k1::SecretKey),
+    File,
+}
+
 impl From<KeyLoadError> for SignError {
     fn from(err: KeyLoadError) -> SignError {
         SignError::KeyLoad(err)
@@ -718,4 +944,46 @@ mod tests {
                 .is_ok());
         });
     }
+
+    #[test]
+    fn disk_storage_works_ecdsa() {
+        futures::executor::block_on(async move {
+            let path = tempfile::tempdir().unwrap();
+
+            let keystore1 = Keystore::new(Some(path.path().to_owned()), rand::random())
+                .await
+                .unwrap();
+            let public_key = keystore1
+                .generate_ecdsa(KeyNamespace::Beefy, true)
+                .await
+                .unwrap();
+            drop(keystore1);
+
+            let keystore2 = Keystore::new(Some(path.path().to_owned()), rand::random())
+                .await
+                .unwrap();
+            assert_eq!(keystore2.keys().await.next(), None);
+            assert_eq!(
+                keystore2.keys_ecdsa().await.next(),
+                Some((KeyNamespace::Beefy, public_key))
+            );
+
+            let signature = keystore2
+                .sign_ecdsa(KeyNamespace::Beefy, &public_key, b"hello world")
+                .await
+                .unwrap();
+
+            let message = libsecp256k1::Message::parse_slice(
+                blake2_rfc::blake2b::blake2b(32, &[], b"hello world").as_bytes(),
+            )
+            .unwrap();
+            let recovered = libsecp256k1::recover(
+                &message,
+                &libsecp256k1::Signature::parse_standard_slice(&signature[..64]).unwrap(),
+                &libsecp256k1::RecoveryId::parse(signature[64]).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(recovered.serialize_compressed(), public_key);
+        });
+    }
 }
@@ -41,7 +41,7 @@ pub fn decode_sr25519_private_key(phrase: &str) -> Result<[u8; 64], ParsePrivate
 
     for junction in parsed.path {
         secret_key = match junction {
-            DeriveJunction::Soft(_) => todo!(), // TODO: return error
+            DeriveJunction::Soft(_) => return Err(ParsePrivateKeyError::SoftDerivation),
             DeriveJunction::Hard(cc) => secret_key
                 .hard_derive_mini_secret_key(Some(schnorrkel::derive::ChainCode(cc)), b"")
                 .0
@@ -59,7 +59,7 @@ pub fn decode_ed25519_private_key(phrase: &str) -> Result<[u8; 32], ParsePrivate
     let mut secret_key = parsed.seed;
     for junction in parsed.path {
         secret_key = match junction {
-            DeriveJunction::Soft(_) => todo!(), // TODO: return error
+            DeriveJunction::Soft(_) => return Err(ParsePrivateKeyError::SoftDerivation),
             DeriveJunction::Hard(cc) => {
                 let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
                 hash.update(crate::util::encode_scale_compact_usize(11).as_ref()); // Length of `"Ed25519HDKD"`
@@ -74,6 +74,31 @@ pub fn decode_ed25519_private_key(phrase: &str) -> Result<[u8; 32], ParsePrivate
     Ok(secret_key)
 }
 
+/// Decodes a human-readable private key (a.k.a. a seed phrase) using the secp256k1 curve.
+///
+/// > **Note**: The returned value isn't guaranteed to be a valid secp256k1 private key, as a tiny
+/// >           fraction of all the possible 32 bytes values aren't.
+pub fn decode_ecdsa_private_key(phrase: &str) -> Result<[u8; 32], ParsePrivateKeyError> {
+    let parsed = parse_private_key(phrase)?;
+
+    let mut secret_key = parsed.seed;
+    for junction in parsed.path {
+        secret_key = match junction {
+            DeriveJunction::Soft(_) => return Err(ParsePrivateKeyError::SoftDerivation),
+            DeriveJunction::Hard(cc) => {
+                let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
+                hash.update(crate::util::encode_scale_compact_usize(13).as_ref()); // Length of `"Secp256k1HDKD"`
+                hash.update(b"Secp256k1HDKD");
+                hash.update(&secret_key);
+                hash.update(&cc);
+                <[u8; 32]>::try_from(hash.finalize().as_bytes()).unwrap()
+            }
+        };
+    }
+
+    Ok(secret_key)
+}
+
 /// Turns a human-readable private key (a.k.a. a seed phrase) into a seed and a derivation path.
 pub fn parse_private_key(phrase: &str) -> Result<ParsedPrivateKey, ParsePrivateKeyError> {
     let parse_result: Result<_, nom::Err<nom::error::Error<&str>>> =
@@ -161,6 +186,9 @@ pub enum ParsePrivateKeyError {
     InvalidFormat,
     /// Failed to decode the provided BIP39 seed phrase.
     Bip39Decode(Bip39ToSeedError),
+    /// The derivation path contains a soft junction, which isn't supported.
+    #[display(fmt = "Soft derivation junctions aren't supported")]
+    SoftDerivation,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -376,6 +404,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn soft_derivation_refused() {
+        assert!(matches!(
+            super::decode_ed25519_private_key("//Alice/soft"),
+            Err(super::ParsePrivateKeyError::SoftDerivation)
+        ));
+        assert!(matches!(
+            super::decode_ecdsa_private_key("//Alice/soft"),
+            Err(super::ParsePrivateKeyError::SoftDerivation)
+        ));
+    }
+
     #[test]
     fn multi_derivation_and_password_ed25519() {
         assert_eq!(
@@ -355,10 +355,10 @@ define_methods! {
     chain_unsubscribeAllHeads(subscription: String) -> bool,
     chain_unsubscribeFinalizedHeads(subscription: String) -> bool [chain_unsubscribeFinalisedHeads],
     chain_unsubscribeNewHeads(subscription: String) -> bool [unsubscribe_newHead, chain_unsubscribeNewHead],
-    childstate_getKeys() -> (), // TODO:
-    childstate_getStorage() -> (), // TODO:
-    childstate_getStorageHash() -> (), // TODO:
-    childstate_getStorageSize() -> (), // TODO:
+    childstate_getKeys(child_storage_key: HexString, prefix: HexString, hash: Option<HashHexString>) -> Vec<HexString>,
+    childstate_getStorage(child_storage_key: HexString, key: HexString, hash: Option<HashHexString>) -> HexString,
+    childstate_getStorageHash(child_storage_key: HexString, key: HexString, hash: Option<HashHexString>) -> HashHexString,
+    childstate_getStorageSize(child_storage_key: HexString, key: HexString, hash: Option<HashHexString>) -> u64,
     grandpa_roundState() -> (), // TODO:
     offchain_localStorageGet() -> (), // TODO:
     offchain_localStorageSet() -> (), // TODO:
@@ -68,7 +68,7 @@ pub use established::{
     ConfigNotifications, ConfigRequestResponse, ConfigRequestResponseIn, InboundError,
     SubstreamFate,
 };
-pub use single_stream_handshake::HandshakeError;
+pub use single_stream_handshake::{HandshakeError, MultiplexingProtocol};
 
 pub use multi_stream::MultiStreamConnectionTask;
 pub use single_stream::SingleStreamConnectionTask;
@@ -79,7 +79,10 @@ mod single_stream;
 /// What kind of handshake to perform on the newly-added connection.
 pub enum SingleStreamHandshakeKind {
     /// Use the multistream-select protocol to negotiate the Noise encryption, then use the
-    /// multistream-select protocol to negotiate the Yamux multiplexing.
+    /// multistream-select protocol to negotiate the multiplexing protocol, following the
+    /// preference order of [`Config::multiplexing_protocols`].
+    ///
+    /// Despite its name, this kind of handshake can lead to either Yamux or Mplex.
     MultistreamSelectNoiseYamux,
 }
 
@@ -124,6 +127,12 @@ pub struct Config {
     /// Name of the ping protocol on the network.
     pub ping_protocol: String,
 
+    /// List of multiplexing protocols to try to negotiate on single-stream connections, in
+    /// decreasing order of preference.
+    ///
+    /// Must not be empty.
+    pub multiplexing_protocols: Vec<MultiplexingProtocol>,
+
     /// Key used for the encryption layer.
     /// This is a Noise static key, according to the Noise specification.
     /// Signed using the actual libp2p key.
@@ -266,6 +275,9 @@ pub struct Network<TConn, TNow> {
 
     /// See [`Config::ping_protocol`].
     ping_protocol: Arc<str>,
+
+    /// See [`Config::multiplexing_protocols`].
+    multiplexing_protocols: Vec<MultiplexingProtocol>,
 }
 
 struct Connection<TConn> {
@@ -317,7 +329,14 @@ where
     TNow: Clone + Add<Duration, Output = TNow> + Sub<TNow, Output = Duration> + Ord,
 {
     /// Initializes a new network data structure.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`Config::multiplexing_protocols`] is empty.
+    ///
     pub fn new(config: Config) -> Self {
+        assert!(!config.multiplexing_protocols.is_empty());
+
         let notification_protocols = config
             .notification_protocols
             .into_iter()
@@ -359,6 +378,7 @@ where
             notification_protocols,
             request_response_protocols: config.request_response_protocols.into_iter().collect(), // TODO: stupid overhead
             ping_protocol: config.ping_protocol.into(),
+            multiplexing_protocols: config.multiplexing_protocols,
         }
     }
 
@@ -383,6 +403,7 @@ where
             self.randomness_seeds.gen(),
             is_initiator,
             handshake_kind,
+            self.multiplexing_protocols.clone(),
             when_connected + self.handshake_timeout,
             self.noise_key.clone(),
             self.max_inbound_substreams,
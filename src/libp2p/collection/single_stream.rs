@@ -24,11 +24,11 @@ use super::{
         read_write::ReadWrite,
     },
     ConnectionToCoordinator, ConnectionToCoordinatorInner, CoordinatorToConnection,
-    CoordinatorToConnectionInner, NotificationsOutErr, OverlayNetwork, ShutdownCause,
-    SingleStreamHandshakeKind, SubstreamId,
+    CoordinatorToConnectionInner, MultiplexingProtocol, NotificationsOutErr, OverlayNetwork,
+    ShutdownCause, SingleStreamHandshakeKind, SubstreamId,
 };
 
-use alloc::{collections::VecDeque, string::ToString as _, sync::Arc};
+use alloc::{collections::VecDeque, string::ToString as _, sync::Arc, vec::Vec};
 use core::{
     mem,
     ops::{Add, Sub},
@@ -138,6 +138,7 @@ where
         randomness_seed: [u8; 32],
         is_initiator: bool,
         handshake_kind: SingleStreamHandshakeKind,
+        multiplexing_protocols: Vec<MultiplexingProtocol>,
         handshake_timeout: TNow,
         noise_key: Arc<NoiseKey>,
         max_inbound_substreams: usize,
@@ -151,7 +152,12 @@ where
 
         SingleStreamConnectionTask {
             connection: SingleStreamConnectionTaskInner::Handshake {
-                handshake: single_stream_handshake::HealthyHandshake::noise_yamux(is_initiator),
+                handshake: single_stream_handshake::HealthyHandshake::new(
+                    single_stream_handshake::Config {
+                        is_initiator,
+                        multiplexing_protocols,
+                    },
+                ),
                 randomness_seed,
                 timeout: handshake_timeout,
                 noise_key,
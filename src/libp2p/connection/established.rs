@@ -16,11 +16,11 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 mod multi_stream;
+mod multiplexer;
 mod single_stream;
 pub mod substream;
 mod tests;
 
-use super::yamux;
 use alloc::{string::String, vec::Vec};
 use core::time::Duration;
 
@@ -37,7 +37,7 @@ pub struct SubstreamId(SubstreamIdInner);
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum SubstreamIdInner {
-    SingleStream(yamux::SubstreamId),
+    SingleStream(multiplexer::SubstreamId),
     MultiStream(u32),
 }
 
@@ -45,12 +45,12 @@ impl SubstreamId {
     /// Returns the value that compares inferior or equal to all possible values.
     pub fn min_value() -> Self {
         debug_assert!(
-            SubstreamIdInner::SingleStream(yamux::SubstreamId::max_value())
+            SubstreamIdInner::SingleStream(multiplexer::SubstreamId::max_value())
                 < SubstreamIdInner::MultiStream(0)
         );
 
         Self(SubstreamIdInner::SingleStream(
-            yamux::SubstreamId::min_value(),
+            multiplexer::SubstreamId::min_value(),
         ))
     }
 
@@ -58,7 +58,7 @@ impl SubstreamId {
     pub fn max_value() -> Self {
         debug_assert!(
             SubstreamIdInner::MultiStream(0)
-                > SubstreamIdInner::SingleStream(yamux::SubstreamId::max_value())
+                > SubstreamIdInner::SingleStream(multiplexer::SubstreamId::max_value())
         );
 
         Self(SubstreamIdInner::MultiStream(u32::max_value()))
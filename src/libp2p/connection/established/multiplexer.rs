@@ -0,0 +1,468 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Multiplexing protocol used by a [`super::SingleStream`].
+//!
+//! The [`Multiplexer`] dispatches all calls to either a [`yamux::Yamux`] or a [`mplex::Mplex`],
+//! depending on which protocol has been negotiated during the handshake. Its API is a subset of
+//! the API of these two state machines.
+
+use super::{
+    super::{mplex, yamux},
+    Error,
+};
+
+use alloc::boxed::Box;
+
+/// Yamux or Mplex state machine.
+///
+/// The Yamux state machine is much larger than the Mplex one, and is boxed so that connections
+/// using Mplex don't pay for its size.
+pub(super) enum Multiplexer<T> {
+    Yamux(Box<yamux::Yamux<T>>),
+    Mplex(mplex::Mplex<T>),
+}
+
+/// Identifier of a substream within a [`Multiplexer`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(super) enum SubstreamId {
+    Yamux(yamux::SubstreamId),
+    Mplex(mplex::SubstreamId),
+}
+
+impl SubstreamId {
+    /// Returns the value that compares inferior or equal to all possible values.
+    pub(super) fn min_value() -> Self {
+        SubstreamId::Yamux(yamux::SubstreamId::min_value())
+    }
+
+    /// Returns the value that compares superior or equal to all possible values.
+    pub(super) fn max_value() -> Self {
+        SubstreamId::Mplex(mplex::SubstreamId::max_value())
+    }
+}
+
+impl<T> Multiplexer<T> {
+    /// Returns `true` if there is no substream in the state machine.
+    pub(super) fn is_empty(&self) -> bool {
+        match self {
+            Multiplexer::Yamux(yamux) => yamux.is_empty(),
+            Multiplexer::Mplex(mplex) => mplex.is_empty(),
+        }
+    }
+
+    /// Returns the number of inbound substreams in the state machine. Includes substreams that
+    /// are dead but haven't been removed yet.
+    pub(super) fn num_inbound(&self) -> usize {
+        match self {
+            Multiplexer::Yamux(yamux) => yamux.num_inbound(),
+            Multiplexer::Mplex(mplex) => mplex.num_inbound(),
+        }
+    }
+
+    /// Opens a new outbound substream.
+    pub(super) fn open_substream(&mut self, user_data: T) -> SubstreamMut<'_, T> {
+        match self {
+            Multiplexer::Yamux(yamux) => SubstreamMut::Yamux(yamux.open_substream(user_data)),
+            Multiplexer::Mplex(mplex) => SubstreamMut::Mplex(mplex.open_substream(user_data)),
+        }
+    }
+
+    /// Returns an iterator to the list of all substream user datas.
+    pub(super) fn user_datas(&self) -> impl Iterator<Item = (SubstreamId, &T)> {
+        match self {
+            Multiplexer::Yamux(yamux) => either::Left(
+                yamux
+                    .user_datas()
+                    .map(|(id, ud)| (SubstreamId::Yamux(id), ud)),
+            ),
+            Multiplexer::Mplex(mplex) => either::Right(
+                mplex
+                    .user_datas()
+                    .map(|(id, ud)| (SubstreamId::Mplex(id), ud)),
+            ),
+        }
+    }
+
+    /// Returns a reference to a substream by its ID. Returns `None` if no substream with this ID
+    /// is open.
+    pub(super) fn substream_by_id(&self, id: SubstreamId) -> Option<SubstreamRef<'_, T>> {
+        match (self, id) {
+            (Multiplexer::Yamux(yamux), SubstreamId::Yamux(id)) => {
+                Some(SubstreamRef::Yamux(yamux.substream_by_id(id)?))
+            }
+            (Multiplexer::Mplex(mplex), SubstreamId::Mplex(id)) => {
+                Some(SubstreamRef::Mplex(mplex.substream_by_id(id)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to a substream by its ID. Returns `None` if no substream with this ID
+    /// is open.
+    pub(super) fn substream_by_id_mut(&mut self, id: SubstreamId) -> Option<SubstreamMut<'_, T>> {
+        match (self, id) {
+            (Multiplexer::Yamux(yamux), SubstreamId::Yamux(id)) => {
+                Some(SubstreamMut::Yamux(yamux.substream_by_id_mut(id)?))
+            }
+            (Multiplexer::Mplex(mplex), SubstreamId::Mplex(id)) => {
+                Some(SubstreamMut::Mplex(mplex.substream_by_id_mut(id)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if [`Multiplexer::deny_new_incoming_substreams`] has been called in the
+    /// past.
+    pub(super) fn new_incoming_substreams_denied(&self) -> bool {
+        match self {
+            Multiplexer::Yamux(yamux) => yamux.goaway_queued_or_sent(),
+            Multiplexer::Mplex(mplex) => mplex.new_incoming_substreams_denied(),
+        }
+    }
+
+    /// Automatically rejects all follow-up requests for new substreams from the remote.
+    ///
+    /// In the case of Yamux, this queues a `GoAway` frame.
+    ///
+    /// # Panic
+    ///
+    /// Panics if this function has already been called in the past.
+    ///
+    pub(super) fn deny_new_incoming_substreams(&mut self) {
+        match self {
+            // TODO: arbitrary yamux error code
+            Multiplexer::Yamux(yamux) => {
+                yamux.send_goaway(yamux::GoAwayErrorCode::NormalTermination)
+            }
+            Multiplexer::Mplex(mplex) => {
+                assert!(!mplex.new_incoming_substreams_denied());
+                mplex.deny_new_incoming_substreams()
+            }
+        }
+    }
+
+    /// Returns `true` if a `GoAway` frame has been sent out to the remote.
+    ///
+    /// Always returns `false` for Mplex, as this protocol has no such frame.
+    pub(super) fn goaway_sent(&self) -> bool {
+        match self {
+            Multiplexer::Yamux(yamux) => yamux.goaway_sent(),
+            Multiplexer::Mplex(_) => false,
+        }
+    }
+
+    /// Returns `true` if a `GoAway` frame has been received from the remote.
+    ///
+    /// Always returns `false` for Mplex, as this protocol has no such frame.
+    pub(super) fn goaway_received(&self) -> bool {
+        match self {
+            Multiplexer::Yamux(yamux) => yamux.received_goaway().is_some(),
+            Multiplexer::Mplex(_) => false,
+        }
+    }
+
+    /// Returns the list of all substreams that have been closed or reset.
+    pub(super) fn dead_substreams(
+        &'_ self,
+    ) -> impl Iterator<Item = (SubstreamId, DeadSubstreamTy, &'_ T)> + '_ {
+        match self {
+            Multiplexer::Yamux(yamux) => {
+                either::Left(yamux.dead_substreams().map(|(id, death_ty, ud)| {
+                    let death_ty = match death_ty {
+                        yamux::DeadSubstreamTy::ClosedGracefully => {
+                            DeadSubstreamTy::ClosedGracefully
+                        }
+                        yamux::DeadSubstreamTy::Reset => DeadSubstreamTy::Reset,
+                    };
+                    (SubstreamId::Yamux(id), death_ty, ud)
+                }))
+            }
+            Multiplexer::Mplex(mplex) => {
+                either::Right(mplex.dead_substreams().map(|(id, death_ty, ud)| {
+                    let death_ty = match death_ty {
+                        mplex::DeadSubstreamTy::ClosedGracefully => {
+                            DeadSubstreamTy::ClosedGracefully
+                        }
+                        mplex::DeadSubstreamTy::Reset => DeadSubstreamTy::Reset,
+                    };
+                    (SubstreamId::Mplex(id), death_ty, ud)
+                }))
+            }
+        }
+    }
+
+    /// Removes a dead substream from the state machine.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the substream with that id doesn't exist or isn't dead.
+    ///
+    pub(super) fn remove_dead_substream(&mut self, id: SubstreamId) -> T {
+        match (self, id) {
+            (Multiplexer::Yamux(yamux), SubstreamId::Yamux(id)) => yamux.remove_dead_substream(id),
+            (Multiplexer::Mplex(mplex), SubstreamId::Mplex(id)) => mplex.remove_dead_substream(id),
+            _ => panic!(),
+        }
+    }
+
+    /// Process some incoming data. See [`yamux::Yamux::incoming_data`] and
+    /// [`mplex::Mplex::incoming_data`].
+    pub(super) fn incoming_data(self, data: &[u8]) -> Result<IncomingDataOutcome<T>, Error> {
+        match self {
+            Multiplexer::Yamux(yamux) => {
+                let outcome = (*yamux).incoming_data(data).map_err(Error::Yamux)?;
+                Ok(IncomingDataOutcome {
+                    multiplexer: Multiplexer::Yamux(Box::new(outcome.yamux)),
+                    bytes_read: outcome.bytes_read,
+                    detail: outcome.detail.map(|detail| match detail {
+                        yamux::IncomingDataDetail::IncomingSubstream => {
+                            IncomingDataDetail::IncomingSubstream
+                        }
+                        yamux::IncomingDataDetail::DataFrame {
+                            start_offset,
+                            substream_id,
+                        } => IncomingDataDetail::DataFrame {
+                            start_offset,
+                            substream_id: SubstreamId::Yamux(substream_id),
+                        },
+                        yamux::IncomingDataDetail::StreamClosed { .. }
+                        | yamux::IncomingDataDetail::StreamReset { .. } => {
+                            IncomingDataDetail::StreamClosedOrReset
+                        }
+                        yamux::IncomingDataDetail::GoAway { .. } => IncomingDataDetail::GoAway,
+                        yamux::IncomingDataDetail::PingResponse => {
+                            // Can only happen if we send out pings, which we never do.
+                            unreachable!()
+                        }
+                    }),
+                })
+            }
+            Multiplexer::Mplex(mplex) => {
+                let outcome = mplex.incoming_data(data).map_err(Error::Mplex)?;
+                Ok(IncomingDataOutcome {
+                    multiplexer: Multiplexer::Mplex(outcome.mplex),
+                    bytes_read: outcome.bytes_read,
+                    detail: outcome.detail.map(|detail| match detail {
+                        mplex::IncomingDataDetail::IncomingSubstream => {
+                            IncomingDataDetail::IncomingSubstream
+                        }
+                        mplex::IncomingDataDetail::DataFrame {
+                            start_offset,
+                            substream_id,
+                        } => IncomingDataDetail::DataFrame {
+                            start_offset,
+                            substream_id: SubstreamId::Mplex(substream_id),
+                        },
+                        mplex::IncomingDataDetail::StreamClosed { .. }
+                        | mplex::IncomingDataDetail::StreamReset { .. } => {
+                            IncomingDataDetail::StreamClosedOrReset
+                        }
+                    }),
+                })
+            }
+        }
+    }
+
+    /// Returns an object that provides an iterator to a list of buffers whose content must be
+    /// sent out on the socket.
+    pub(super) fn extract_out(&mut self, size_bytes: usize) -> ExtractOut<'_, T> {
+        match self {
+            Multiplexer::Yamux(yamux) => ExtractOut::Yamux(yamux.extract_out(size_bytes)),
+            Multiplexer::Mplex(mplex) => ExtractOut::Mplex(mplex.extract_out(size_bytes)),
+        }
+    }
+
+    /// Accepts the incoming substream that is currently pending.
+    pub(super) fn accept_pending_substream(&mut self, user_data: T) {
+        match self {
+            Multiplexer::Yamux(yamux) => {
+                yamux.accept_pending_substream(user_data);
+            }
+            Multiplexer::Mplex(mplex) => {
+                mplex.accept_pending_substream(user_data);
+            }
+        }
+    }
+
+    /// Rejects the incoming substream that is currently pending.
+    pub(super) fn reject_pending_substream(&mut self) {
+        match self {
+            Multiplexer::Yamux(yamux) => yamux.reject_pending_substream(),
+            Multiplexer::Mplex(mplex) => mplex.reject_pending_substream(),
+        }
+    }
+}
+
+/// Outcome of [`Multiplexer::incoming_data`].
+pub(super) struct IncomingDataOutcome<T> {
+    /// Multiplexer object on which [`Multiplexer::incoming_data`] has been called.
+    pub multiplexer: Multiplexer<T>,
+    /// Number of bytes read from the incoming buffer.
+    pub bytes_read: usize,
+    /// Detail about the incoming data. `None` if nothing of interest has happened.
+    pub detail: Option<IncomingDataDetail>,
+}
+
+/// Details about the incoming data.
+pub(super) enum IncomingDataDetail {
+    /// Remote has requested to open a new substream.
+    IncomingSubstream,
+    /// Received data corresponding to a substream.
+    DataFrame {
+        /// Offset in the buffer passed to [`Multiplexer::incoming_data`] where the data frame
+        /// starts. The data frame ends at the offset of [`IncomingDataOutcome::bytes_read`].
+        start_offset: usize,
+        /// Substream the data belongs to. Guaranteed to be valid.
+        substream_id: SubstreamId,
+    },
+    /// Remote has closed its writing side of a substream, or has reset a substream.
+    StreamClosedOrReset,
+    /// Received a Yamux "go away" request. It is now forbidden to open new outbound substreams.
+    GoAway,
+}
+
+/// How a substream has died.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum DeadSubstreamTy {
+    ClosedGracefully,
+    Reset,
+}
+
+/// See [`Multiplexer::extract_out`].
+pub(super) enum ExtractOut<'a, T> {
+    Yamux(yamux::ExtractOut<'a, T>),
+    Mplex(mplex::ExtractOut<'a, T>),
+}
+
+impl<'a, T> ExtractOut<'a, T> {
+    /// Builds the next buffer to send out and returns it.
+    pub(super) fn next(&'_ mut self) -> Option<impl AsRef<[u8]> + '_> {
+        match self {
+            ExtractOut::Yamux(extract_out) => extract_out.next().map(either::Left),
+            ExtractOut::Mplex(extract_out) => extract_out.next_outgoing_data().map(either::Right),
+        }
+    }
+}
+
+/// Reference to a substream within the [`Multiplexer`].
+pub(super) enum SubstreamRef<'a, T> {
+    Yamux(yamux::SubstreamRef<'a, T>),
+    Mplex(mplex::SubstreamRef<'a, T>),
+}
+
+impl<'a, T> SubstreamRef<'a, T> {
+    /// Returns the user data associated to this substream.
+    pub(super) fn into_user_data(self) -> &'a T {
+        match self {
+            SubstreamRef::Yamux(substream) => substream.into_user_data(),
+            SubstreamRef::Mplex(substream) => substream.into_user_data(),
+        }
+    }
+
+    /// Returns the number of bytes queued for writing on this substream.
+    pub(super) fn queued_bytes(&self) -> usize {
+        match self {
+            SubstreamRef::Yamux(substream) => substream.queued_bytes(),
+            SubstreamRef::Mplex(substream) => substream.queued_bytes(),
+        }
+    }
+}
+
+/// Reference to a substream within the [`Multiplexer`].
+pub(super) enum SubstreamMut<'a, T> {
+    Yamux(yamux::SubstreamMut<'a, T>),
+    Mplex(mplex::SubstreamMut<'a, T>),
+}
+
+impl<'a, T> SubstreamMut<'a, T> {
+    /// Identifier of the substream.
+    pub(super) fn id(&self) -> SubstreamId {
+        match self {
+            SubstreamMut::Yamux(substream) => SubstreamId::Yamux(substream.id()),
+            SubstreamMut::Mplex(substream) => SubstreamId::Mplex(substream.id()),
+        }
+    }
+
+    /// Returns the user data associated to this substream.
+    pub(super) fn user_data_mut(&mut self) -> &mut T {
+        match self {
+            SubstreamMut::Yamux(substream) => substream.user_data_mut(),
+            SubstreamMut::Mplex(substream) => substream.user_data_mut(),
+        }
+    }
+
+    /// Returns the user data associated to this substream.
+    pub(super) fn into_user_data(self) -> &'a mut T {
+        match self {
+            SubstreamMut::Yamux(substream) => substream.into_user_data(),
+            SubstreamMut::Mplex(substream) => substream.into_user_data(),
+        }
+    }
+
+    /// Appends data to the buffer of data to send out on this substream.
+    pub(super) fn write(&mut self, data: alloc::vec::Vec<u8>) {
+        match self {
+            SubstreamMut::Yamux(substream) => substream.write(data),
+            SubstreamMut::Mplex(substream) => substream.write(data),
+        }
+    }
+
+    /// Allow the remote to send up to `bytes` bytes at once in the next packet.
+    ///
+    /// Has no effect for Mplex, as this protocol has no flow control mechanism.
+    pub(super) fn reserve_window(&mut self, bytes: u64) {
+        match self {
+            SubstreamMut::Yamux(substream) => substream.reserve_window(bytes),
+            SubstreamMut::Mplex(_) => {}
+        }
+    }
+
+    /// Returns `false` if the remote has closed their writing side of this substream, or if the
+    /// substream has been reset.
+    pub(super) fn can_receive(&self) -> bool {
+        match self {
+            SubstreamMut::Yamux(substream) => substream.can_receive(),
+            SubstreamMut::Mplex(substream) => substream.can_receive(),
+        }
+    }
+
+    /// Returns `false` if the local writing side of this substream has been closed, or if the
+    /// substream has been reset.
+    pub(super) fn can_send(&self) -> bool {
+        match self {
+            SubstreamMut::Yamux(substream) => substream.can_send(),
+            SubstreamMut::Mplex(substream) => substream.can_send(),
+        }
+    }
+
+    /// Marks the substream as closed. It is no longer possible to write data on it.
+    pub(super) fn close(&mut self) {
+        match self {
+            SubstreamMut::Yamux(substream) => substream.close(),
+            SubstreamMut::Mplex(substream) => substream.close(),
+        }
+    }
+
+    /// Abruptly shuts down the substream.
+    pub(super) fn reset(&mut self) {
+        match self {
+            SubstreamMut::Yamux(substream) => substream.reset(),
+            SubstreamMut::Mplex(substream) => substream.reset(),
+        }
+    }
+}
@@ -51,7 +51,8 @@
 // TODO: consider implementing on top of multi_stream
 
 use super::{
-    super::{super::read_write::ReadWrite, noise, yamux},
+    super::{super::read_write::ReadWrite, mplex, noise, single_stream_handshake, yamux},
+    multiplexer::{self, Multiplexer},
     substream::{self, RespondInRequestError},
     Config, ConfigNotifications, ConfigRequestResponse, ConfigRequestResponseIn, Event,
     SubstreamId, SubstreamIdInner,
@@ -85,27 +86,27 @@ struct Inner<TNow, TRqUd, TNotifUd> {
     /// object, or `None` if the substream has been reset.
     /// Also includes, for each substream, a collection of buffers whose data is to be written
     /// out.
-    yamux: yamux::Yamux<Option<substream::Substream<TNow, TRqUd, TNotifUd>>>,
+    multiplexer: Multiplexer<Option<substream::Substream<TNow, TRqUd, TNotifUd>>>,
 
-    /// If `Some`, contains the substream and number of bytes that [`Inner::yamux`] has already
-    /// processed but haven't been consumed from the buffer of decoded data in
+    /// If `Some`, contains the substream and number of bytes that [`Inner::multiplexer`] has
+    /// already processed but haven't been consumed from the buffer of decoded data in
     /// [`SingleStream::encryption`] yet.
     ///
-    /// After Yamux indicates that it has just processed a frame of data belonging to a certain
-    /// substream, we set this value to `Some` but leave the data in the buffer. This way, we can
-    /// process the data at a slower pace than Yamux.
-    current_data_frame: Option<(yamux::SubstreamId, NonZeroUsize)>,
+    /// After the multiplexer indicates that it has just processed a frame of data belonging to a
+    /// certain substream, we set this value to `Some` but leave the data in the buffer. This way,
+    /// we can process the data at a slower pace than the multiplexer.
+    current_data_frame: Option<(multiplexer::SubstreamId, NonZeroUsize)>,
 
-    /// Substream in [`Inner::yamux`] used for outgoing pings.
+    /// Substream in [`Inner::multiplexer`] used for outgoing pings.
     ///
     /// Because of the API of [`substream::Substream`] concerning pings, there is no need to
     /// handle situations where the substream fails to negotiate, as this is handled by making
     /// outgoing pings error. This substream is therefore constant.
     ///
     /// It is possible, however, that the remote resets the ping substream. In other words, this
-    /// substream might not be found in [`Inner::yamux`]. When that happens, all outgoing pings
-    /// are immediately considered as failed.
-    outgoing_pings: yamux::SubstreamId,
+    /// substream might not be found in [`Inner::multiplexer`]. When that happens, all outgoing
+    /// pings are immediately considered as failed.
+    outgoing_pings: multiplexer::SubstreamId,
     /// When to start the next ping attempt.
     next_ping: TNow,
     /// Source of randomness to generate ping payloads.
@@ -166,7 +167,7 @@ where
         // This doesn't read data from `read_write`, but can potential write out data.
         for substream_id in self
             .inner
-            .yamux
+            .multiplexer
             .user_datas()
             .map(|(id, _)| id)
             .collect::<Vec<_>>()
@@ -187,7 +188,7 @@ where
             // substream no longer exists and we immediately consider the ping as failed.
             if let Some(substream) = self
                 .inner
-                .yamux
+                .multiplexer
                 .substream_by_id_mut(self.inner.outgoing_pings)
             {
                 let payload = self
@@ -218,9 +219,9 @@ where
             // to the remote that these new substreams are denied. However, this is not a problem
             // as the remote interprets our GoAway frame as an automatic refusal of all its pending
             // substream requests.
-            if self.inner.yamux.is_empty()
-                && self.inner.yamux.goaway_sent()
-                && self.inner.yamux.received_goaway().is_some()
+            if self.inner.multiplexer.is_empty()
+                && self.inner.multiplexer.goaway_sent()
+                && self.inner.multiplexer.goaway_received()
             {
                 read_write.close_write_if_empty();
             }
@@ -230,13 +231,18 @@ where
             // having nothing more to do.
             let mut must_continue_looping = false;
 
-            // If `self.inner.current_data_frame` is `Some`, that means that yamux has already
-            // processed some of the data in the buffer of decrypted data and has determined that
-            // it was data belonging to a certain substream. We now pass over this data again,
+            // If `self.inner.current_data_frame` is `Some`, that means that the multiplexer has
+            // already processed some of the data in the buffer of decrypted data and has determined
+            // that it was data belonging to a certain substream. We now pass over this data again,
             // but this time update the state machine specific to that substream.
             if let Some((substream_id, bytes_remaining)) = self.inner.current_data_frame {
                 // It might be that the substream has been closed in `process_substream`.
-                if self.inner.yamux.substream_by_id_mut(substream_id).is_none() {
+                if self
+                    .inner
+                    .multiplexer
+                    .substream_by_id_mut(substream_id)
+                    .is_none()
+                {
                     self.encryption.consume_inbound_data(bytes_remaining.get());
                     self.inner.current_data_frame = None;
                     continue;
@@ -285,36 +291,35 @@ where
                 read_write.advance_read(num_read);
             }
 
-            // Ask the Yamux state machine to decode the buffer present in `self.encryption`.
+            // Ask the multiplexer state machine to decode the buffer present in `self.encryption`.
             debug_assert!(self.inner.current_data_frame.is_none());
-            let yamux_decode = self
+            let multiplexer_decode = self
                 .inner
-                .yamux
-                .incoming_data(self.encryption.decoded_inbound_data())
-                .map_err(Error::Yamux)?;
-            self.inner.yamux = yamux_decode.yamux;
-
-            // If bytes_read is 0 and detail is None, then Yamux can't do anything more. On the
-            // other hand, if bytes_read is != 0 or detail is Some, then Yamux might have more
-            // things to do, and we must loop again.
-            if !(yamux_decode.bytes_read == 0 && yamux_decode.detail.is_none()) {
+                .multiplexer
+                .incoming_data(self.encryption.decoded_inbound_data())?;
+            self.inner.multiplexer = multiplexer_decode.multiplexer;
+
+            // If bytes_read is 0 and detail is None, then the multiplexer can't do anything more.
+            // On the other hand, if bytes_read is != 0 or detail is Some, then the multiplexer
+            // might have more things to do, and we must loop again.
+            if !(multiplexer_decode.bytes_read == 0 && multiplexer_decode.detail.is_none()) {
                 must_continue_looping = true;
             }
 
-            // Analyze how Yamux has parsed the data.
+            // Analyze how the multiplexer has parsed the data.
             // This still contains references to the data in `self.encryption`.
-            match yamux_decode.detail {
-                None if yamux_decode.bytes_read == 0 => {}
+            match multiplexer_decode.detail {
+                None if multiplexer_decode.bytes_read == 0 => {}
                 None => {
                     self.encryption
-                        .consume_inbound_data(yamux_decode.bytes_read);
+                        .consume_inbound_data(multiplexer_decode.bytes_read);
                 }
 
-                Some(yamux::IncomingDataDetail::IncomingSubstream) => {
-                    debug_assert!(!self.inner.yamux.goaway_queued_or_sent());
+                Some(multiplexer::IncomingDataDetail::IncomingSubstream) => {
+                    debug_assert!(!self.inner.multiplexer.new_incoming_substreams_denied());
 
                     self.encryption
-                        .consume_inbound_data(yamux_decode.bytes_read);
+                        .consume_inbound_data(multiplexer_decode.bytes_read);
 
                     // Receive a request from the remote for a new incoming substream.
                     // These requests are automatically accepted unless the total limit to the
@@ -323,8 +328,8 @@ where
                     // yet removed from the state machine. This can affect the actual limit in a
                     // subtle way. At the time of writing of this comment the limit should be
                     // properly enforced, however it is not considered problematic if it weren't.
-                    if self.inner.yamux.num_inbound() >= self.inner.max_inbound_substreams {
-                        self.inner.yamux.reject_pending_substream();
+                    if self.inner.multiplexer.num_inbound() >= self.inner.max_inbound_substreams {
+                        self.inner.multiplexer.reject_pending_substream();
                         continue;
                     }
 
@@ -343,22 +348,17 @@ where
                         .chain(iter::once(self.inner.ping_protocol.clone()))
                         .collect::<Vec<_>>();
 
-                    self.inner
-                        .yamux
-                        .accept_pending_substream(Some(substream::Substream::ingoing(
-                            supported_protocols,
-                        )));
+                    self.inner.multiplexer.accept_pending_substream(Some(
+                        substream::Substream::ingoing(supported_protocols),
+                    ));
                 }
 
-                Some(
-                    yamux::IncomingDataDetail::StreamReset { .. }
-                    | yamux::IncomingDataDetail::StreamClosed { .. },
-                ) => {
+                Some(multiplexer::IncomingDataDetail::StreamClosedOrReset) => {
                     self.encryption
-                        .consume_inbound_data(yamux_decode.bytes_read);
+                        .consume_inbound_data(multiplexer_decode.bytes_read);
                 }
 
-                Some(yamux::IncomingDataDetail::DataFrame {
+                Some(multiplexer::IncomingDataDetail::DataFrame {
                     start_offset,
                     substream_id,
                 }) => {
@@ -369,42 +369,41 @@ where
                     // data in the buffer and update our internal state so that it gets processed
                     // during the next loop.
                     debug_assert!(self.inner.current_data_frame.is_none());
-                    if let Some(len) = NonZeroUsize::new(yamux_decode.bytes_read - start_offset) {
+                    if let Some(len) =
+                        NonZeroUsize::new(multiplexer_decode.bytes_read - start_offset)
+                    {
                         self.inner.current_data_frame = Some((substream_id, len));
                     }
                 }
 
-                Some(yamux::IncomingDataDetail::GoAway { .. }) => {
+                Some(multiplexer::IncomingDataDetail::GoAway) => {
                     // TODO: somehow report the GoAway error code on the external API?
                     self.encryption
-                        .consume_inbound_data(yamux_decode.bytes_read);
+                        .consume_inbound_data(multiplexer_decode.bytes_read);
                     return Ok((self, Some(Event::NewOutboundSubstreamsForbidden)));
                 }
-
-                Some(yamux::IncomingDataDetail::PingResponse) => {
-                    // Can only happen if we send out pings, which we never do.
-                    unreachable!()
-                }
             };
 
-            // Substreams that have been closed or reset aren't immediately removed the yamux state
-            // machine. They must be removed manually, which is what is done here.
+            // Substreams that have been closed or reset aren't immediately removed the multiplexer
+            // state machine. They must be removed manually, which is what is done here.
             let dead_substream_ids = self
                 .inner
-                .yamux
+                .multiplexer
                 .dead_substreams()
                 .map(|(id, death_ty, _)| (id, death_ty))
                 .collect::<Vec<_>>();
             for (dead_substream_id, death_ty) in dead_substream_ids {
                 match death_ty {
-                    yamux::DeadSubstreamTy::Reset => {
-                        // If the substream has been reset, we simply remove it from the Yamux
-                        // state machine.
+                    multiplexer::DeadSubstreamTy::Reset => {
+                        // If the substream has been reset, we simply remove it from the
+                        // multiplexer state machine.
 
                         // If the substream was reset by the remote, then the substream state
                         // machine will still be `Some`.
-                        if let Some(state_machine) =
-                            self.inner.yamux.remove_dead_substream(dead_substream_id)
+                        if let Some(state_machine) = self
+                            .inner
+                            .multiplexer
+                            .remove_dead_substream(dead_substream_id)
                         {
                             // TODO: consider changing this `state_machine.reset()` function to be a state transition of the substream state machine (that doesn't take ownership), to simplify the implementation of both the substream state machine and this code
                             if let Some(event) = state_machine.reset() {
@@ -418,21 +417,21 @@ where
                             }
                         };
 
-                        // Removing a dead substream might lead to Yamux being able to process more
-                        // incoming data. As such, we loop again.
+                        // Removing a dead substream might lead to the multiplexer being able to
+                        // process more incoming data. As such, we loop again.
                         must_continue_looping = true;
                     }
-                    yamux::DeadSubstreamTy::ClosedGracefully => {
+                    multiplexer::DeadSubstreamTy::ClosedGracefully => {
                         // If the substream has been closed gracefully, we don't necessarily
                         // remove it instantly. Instead, we continue processing the substream
                         // state machine until it tells us that there are no more events to
                         // return.
 
-                        // Mutable reference to the substream state machine within the yamux
-                        // state machine.
+                        // Mutable reference to the substream state machine within the
+                        // multiplexer state machine.
                         let state_machine_refmut = self
                             .inner
-                            .yamux
+                            .multiplexer
                             .substream_by_id_mut(dead_substream_id)
                             .unwrap()
                             .into_user_data();
@@ -441,12 +440,14 @@ where
                         let state_machine_extracted = match state_machine_refmut.take() {
                             Some(s) => s,
                             None => {
-                                // Substream has already been removed from the Yamux state machine
-                                // previously. We know that it can't yield any more event.
-                                self.inner.yamux.remove_dead_substream(dead_substream_id);
-
-                                // Removing a dead substream might lead to Yamux being able to
-                                // process more incoming data. As such, we loop again.
+                                // Substream has already been removed from the multiplexer state
+                                // machine previously. We know that it can't yield any more event.
+                                self.inner
+                                    .multiplexer
+                                    .remove_dead_substream(dead_substream_id);
+
+                                // Removing a dead substream might lead to the multiplexer being
+                                // able to process more incoming data. As such, we loop again.
                                 must_continue_looping = true;
 
                                 continue;
@@ -480,12 +481,14 @@ where
                             // the next time `read_write` is called.
                             *state_machine_refmut = Some(substream_update);
                         } else {
-                            // Substream has no more events to give us. Remove it from the Yamux
-                            // state machine.
-                            self.inner.yamux.remove_dead_substream(dead_substream_id);
+                            // Substream has no more events to give us. Remove it from the
+                            // multiplexer state machine.
+                            self.inner
+                                .multiplexer
+                                .remove_dead_substream(dead_substream_id);
 
-                            // Removing a dead substream might lead to Yamux being able to process more
-                            // incoming data. As such, we loop again.
+                            // Removing a dead substream might lead to the multiplexer being able
+                            // to process more incoming data. As such, we loop again.
                             must_continue_looping = true;
                         }
 
@@ -499,21 +502,24 @@ where
                 }
             }
 
-            // The yamux state machine contains the data that needs to be written out.
+            // The multiplexer state machine contains the data that needs to be written out.
             // Try to flush it.
 
-            // Calculate number of bytes that we can extract from yamux. This is similar but not
-            // exactly the same as the size of the outgoing buffer, as noise adds some headers to
-            // the data.
+            // Calculate number of bytes that we can extract from the multiplexer. This is similar
+            // but not exactly the same as the size of the outgoing buffer, as noise adds some
+            // headers to the data.
             let unencrypted_bytes_to_extract = self
                 .encryption
                 .encrypt_size_conv(read_write.outgoing_buffer_available());
 
             if unencrypted_bytes_to_extract != 0 {
-                // Extract outgoing data that is buffered within yamux.
+                // Extract outgoing data that is buffered within the multiplexer.
                 // TODO: don't allocate an intermediary buffer, but instead pass them directly to the encryption
                 let mut buffers = Vec::with_capacity(32);
-                let mut extract_out = self.inner.yamux.extract_out(unencrypted_bytes_to_extract);
+                let mut extract_out = self
+                    .inner
+                    .multiplexer
+                    .extract_out(unencrypted_bytes_to_extract);
                 while let Some(buffer) = extract_out.next() {
                     buffers.push(buffer.as_ref().to_vec()); // TODO: copy
                 }
@@ -559,14 +565,14 @@ where
     ///
     fn process_substream(
         inner: &mut Inner<TNow, TRqUd, TNotifUd>,
-        substream_id: yamux::SubstreamId,
+        substream_id: multiplexer::SubstreamId,
         outer_read_write: &mut ReadWrite<TNow>,
         in_data: &[u8],
     ) -> (usize, Option<Event<TRqUd, TNotifUd>>) {
         let mut total_read = 0;
 
         loop {
-            let mut substream = inner.yamux.substream_by_id_mut(substream_id).unwrap();
+            let mut substream = inner.multiplexer.substream_by_id_mut(substream_id).unwrap();
 
             let state_machine = match substream.user_data_mut().take() {
                 Some(s) => s,
@@ -618,7 +624,7 @@ where
                     if !closed_after || !read_is_closed {
                         // TODO: what we do here is definitely correct, but the docs of `reset()` seem sketchy, investigate
                         inner
-                            .yamux
+                            .multiplexer
                             .substream_by_id_mut(substream_id)
                             .unwrap()
                             .reset();
@@ -630,7 +636,7 @@ where
                 None => None,
                 Some(substream::Event::InboundNegotiated(protocol)) => {
                     let substream = inner
-                        .yamux
+                        .multiplexer
                         .substream_by_id_mut(substream_id)
                         .unwrap()
                         .into_user_data()
@@ -685,7 +691,7 @@ where
     /// variant separately.
     ///
     fn pass_through_substream_event(
-        substream_id: yamux::SubstreamId,
+        substream_id: multiplexer::SubstreamId,
         event: substream::Event<TRqUd, TNotifUd>,
     ) -> Event<TRqUd, TNotifUd> {
         match event {
@@ -761,10 +767,7 @@ where
     /// [`SingleStream::deny_new_incoming_substreams`] more than one on the same connections.
     ///
     pub fn deny_new_incoming_substreams(&mut self) {
-        // TODO: arbitrary yamux error code
-        self.inner
-            .yamux
-            .send_goaway(yamux::GoAwayErrorCode::NormalTermination)
+        self.inner.multiplexer.deny_new_incoming_substreams()
     }
 
     /// Sends a request to the remote.
@@ -810,7 +813,7 @@ where
 
         let mut substream =
             self.inner
-                .yamux
+                .multiplexer
                 .open_substream(Some(substream::Substream::request_out(
                     self.inner.request_protocols[protocol_index].name.clone(), // TODO: clone :-/
                     timeout,
@@ -846,7 +849,7 @@ where
         };
 
         self.inner
-            .yamux
+            .multiplexer
             .substream_by_id_mut(id)?
             .into_user_data()
             .as_mut()
@@ -887,7 +890,7 @@ where
 
         let substream =
             self.inner
-                .yamux
+                .multiplexer
                 .open_substream(Some(substream::Substream::notifications_out(
                     timeout,
                     self.inner.notifications_protocols[protocol_index]
@@ -922,7 +925,7 @@ where
         let max_notification_size = 16 * 1024 * 1024; // TODO: hack
                                                       // TODO: self.inner.notifications_protocols[protocol_index].max_notification_size;
         self.inner
-            .yamux
+            .multiplexer
             .substream_by_id_mut(substream_id)
             .unwrap()
             .into_user_data()
@@ -945,7 +948,7 @@ where
         };
 
         self.inner
-            .yamux
+            .multiplexer
             .substream_by_id_mut(substream_id)
             .unwrap()
             .into_user_data()
@@ -982,7 +985,7 @@ where
         };
 
         self.inner
-            .yamux
+            .multiplexer
             .substream_by_id_mut(substream_id)
             .unwrap()
             .into_user_data()
@@ -1006,7 +1009,11 @@ where
             _ => panic!(),
         };
 
-        let substream = self.inner.yamux.substream_by_id(substream_id).unwrap();
+        let substream = self
+            .inner
+            .multiplexer
+            .substream_by_id(substream_id)
+            .unwrap();
         let already_queued = substream.queued_bytes();
         let from_substream = substream
             .into_user_data()
@@ -1035,7 +1042,7 @@ where
         };
 
         self.inner
-            .yamux
+            .multiplexer
             .substream_by_id_mut(substream_id)
             .unwrap()
             .into_user_data()
@@ -1061,7 +1068,7 @@ where
         };
 
         self.inner
-            .yamux
+            .multiplexer
             .substream_by_id_mut(substream_id)
             .ok_or(RespondInRequestError::SubstreamClosed)?
             .into_user_data()
@@ -1077,7 +1084,7 @@ where
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_map()
-            .entries(self.inner.yamux.user_datas())
+            .entries(self.inner.multiplexer.user_datas())
             .finish()
     }
 }
@@ -1091,17 +1098,37 @@ pub enum Error {
     /// Error in the Yamux multiplexing protocol.
     #[display(fmt = "Yamux error: {}", _0)]
     Yamux(yamux::Error),
+    /// Error in the Mplex multiplexing protocol.
+    #[display(fmt = "Mplex error: {}", _0)]
+    Mplex(mplex::Error),
 }
 
 /// Successfully negotiated connection. Ready to be turned into a [`SingleStream`].
 pub struct ConnectionPrototype {
     encryption: noise::Noise,
+    multiplexing_protocol: single_stream_handshake::MultiplexingProtocol,
 }
 
 impl ConnectionPrototype {
     /// Builds a new [`ConnectionPrototype`] of a connection using the Noise and Yamux protocols.
     pub(crate) fn from_noise_yamux(encryption: noise::Noise) -> Self {
-        ConnectionPrototype { encryption }
+        ConnectionPrototype {
+            encryption,
+            multiplexing_protocol: single_stream_handshake::MultiplexingProtocol::Yamux,
+        }
+    }
+
+    /// Builds a new [`ConnectionPrototype`] of a connection using the Noise and Mplex protocols.
+    pub(crate) fn from_noise_mplex(encryption: noise::Noise) -> Self {
+        ConnectionPrototype {
+            encryption,
+            multiplexing_protocol: single_stream_handshake::MultiplexingProtocol::Mplex,
+        }
+    }
+
+    /// Returns the multiplexing protocol that has been negotiated.
+    pub fn multiplexing_protocol(&self) -> single_stream_handshake::MultiplexingProtocol {
+        self.multiplexing_protocol
     }
 
     /// Extracts the Noise state machine from this prototype.
@@ -1121,13 +1148,23 @@ impl ConnectionPrototype {
 
         let mut randomness = rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed);
 
-        let mut yamux = yamux::Yamux::new(yamux::Config {
-            is_initiator: self.encryption.is_initiator(),
-            capacity: 64, // TODO: ?
-            randomness_seed: randomness.sample(rand::distributions::Standard),
-        });
+        let mut multiplexer = match self.multiplexing_protocol {
+            single_stream_handshake::MultiplexingProtocol::Yamux => {
+                Multiplexer::Yamux(Box::new(yamux::Yamux::new(yamux::Config {
+                    is_initiator: self.encryption.is_initiator(),
+                    capacity: 64, // TODO: ?
+                    randomness_seed: randomness.sample(rand::distributions::Standard),
+                })))
+            }
+            single_stream_handshake::MultiplexingProtocol::Mplex => {
+                Multiplexer::Mplex(mplex::Mplex::new(mplex::Config {
+                    capacity: 64, // TODO: ?
+                    randomness_seed: randomness.sample(rand::distributions::Standard),
+                }))
+            }
+        };
 
-        let outgoing_pings = yamux
+        let outgoing_pings = multiplexer
             .open_substream(Some(substream::Substream::ping_out(
                 config.ping_protocol.clone(),
             )))
@@ -1136,7 +1173,7 @@ impl ConnectionPrototype {
         SingleStream {
             encryption: self.encryption,
             inner: Inner {
-                yamux,
+                multiplexer,
                 current_data_frame: None,
                 outgoing_pings,
                 next_ping: config.first_out_ping,
@@ -1155,6 +1192,8 @@ impl ConnectionPrototype {
 
 impl fmt::Debug for ConnectionPrototype {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("ConnectionPrototype").finish()
+        f.debug_struct("ConnectionPrototype")
+            .field("multiplexing_protocol", &self.multiplexing_protocol)
+            .finish()
     }
 }
@@ -0,0 +1,1549 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Mplex multiplexing protocol.
+//!
+//! The Mplex protocol is a multiplexing protocol. As such, it allows dividing a single stream of
+//! data, typically a TCP socket, into multiple individual parallel substreams. The data sent and
+//! received over that single stream is divided into frames, each of which belongs to a specific
+//! substream.
+//!
+//! Contrary to Yamux, Mplex has no flow control, no ping mechanism, and no way to gracefully
+//! shut down the entire connection. Substreams are identified by a number chosen by the side
+//! that has opened them, and each side allocates its numbers independently.
+//!
+//! Specification available at <https://github.com/libp2p/specs/tree/master/mplex>
+//!
+//! # Usage
+//!
+//! The [`Mplex`] object holds the state of all Mplex-specific information, and the list of
+//! all currently-open substreams.
+//!
+//! Call [`Mplex::incoming_data`] when data is available on the socket. This function parses
+//! the received data, updates the internal state machine, and possibly returns an
+//! [`IncomingDataDetail`].
+//! Call [`Mplex::extract_out`] when the remote is ready to accept more data.
+//!
+//! The generic parameter of [`Mplex`] is an opaque "user data" associated to each substream.
+//!
+//! When [`SubstreamMut::write`] is called, the buffer of data to send out is stored within the
+//! [`Mplex`] object. This data will then be progressively returned by [`Mplex::extract_out`].
+//!
+//! It is the responsibility of the user to enforce a bound to the amount of enqueued data, as
+//! the [`Mplex`] itself doesn't enforce any limit. Enforcing such a bound must be done based
+//! on the logic of the higher-level protocols. Failing to do so might lead to potential DoS
+//! attack vectors.
+
+use crate::util::{leb128, SipHasherBuild};
+
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{cmp, fmt, mem, num::NonZeroUsize};
+use hashbrown::hash_map::{Entry, OccupiedEntry};
+use rand::Rng as _;
+use rand_chacha::{rand_core::SeedableRng as _, ChaCha20Rng};
+
+/// Name of the protocol, typically used when negotiated it using *multistream-select*.
+pub const PROTOCOL_NAME: &str = "/mplex/6.7.0";
+
+/// Configuration for a new [`Mplex`].
+#[derive(Debug)]
+pub struct Config {
+    /// Expected number of substreams simultaneously open, both inbound and outbound substreams
+    /// combined.
+    pub capacity: usize,
+
+    /// Seed used for the randomness. Used to avoid HashDoS attack.
+    pub randomness_seed: [u8; 32],
+}
+
+pub struct Mplex<T> {
+    /// List of substreams currently open in the Mplex state machine.
+    ///
+    /// A `SipHasher` is used in order to avoid hash collision attacks on substream IDs.
+    substreams: hashbrown::HashMap<SubstreamId, Substream<T>, SipHasherBuild>,
+
+    /// Number of substreams within [`Mplex::substreams`] that have been opened by the remote.
+    num_inbound: usize,
+
+    /// `true` if [`Mplex::deny_new_incoming_substreams`] has been called in the past.
+    new_incoming_substreams_denied: bool,
+
+    /// What kind of data is expected on the socket next.
+    incoming: Incoming,
+
+    /// What to write to the socket next.
+    outgoing: Outgoing,
+
+    /// Number of the next outgoing substream to open.
+    /// This implementation allocates numbers linearly. Every time a substream is open, its
+    /// value is incremented by one.
+    next_outbound_substream: u64,
+
+    /// List of substream IDs that have been reset locally. For each entry, a reset frame should
+    /// be sent to the remote and the entry removed.
+    resets_to_send: VecDeque<SubstreamId>,
+}
+
+struct Substream<T> {
+    /// State of the substream.
+    state: SubstreamState,
+    /// Data chosen by the user.
+    user_data: T,
+}
+
+enum SubstreamState {
+    Healthy {
+        /// True if the remote has been notified of the existence of this substream. Always
+        /// `true` for substreams opened by the remote. For substreams opened locally, a
+        /// `NewStream` frame must be sent before any other frame.
+        new_stream_frame_queued: bool,
+        local_write: SubstreamStateLocalWrite,
+        /// True if the writing side of the remote node is closed for this substream.
+        remote_write_closed: bool,
+        /// Buffer of buffers to be written out to the socket.
+        // TODO: is it a good idea to have an unbounded Vec?
+        write_buffers: Vec<Vec<u8>>,
+        /// Number of bytes in `self.write_buffers[0]` has have already been written out to the
+        /// socket.
+        first_write_buffer_offset: usize,
+    },
+
+    /// The substream has been reset, either locally or by the remote. Its entire purpose is to
+    /// be removed by the API user.
+    Reset,
+}
+
+enum SubstreamStateLocalWrite {
+    Open,
+    CloseDesired,
+    CloseQueued,
+}
+
+enum Incoming {
+    /// Expect a header. The field might contain some already-read bytes.
+    Header(arrayvec::ArrayVec<u8, 20>),
+    /// Expect the data of a previously-received frame header.
+    Data {
+        /// Identifier of the substream the data belongs to. `None` if the data must be
+        /// discarded.
+        substream_id: Option<SubstreamId>,
+        /// Number of bytes of data remaining before the frame ends.
+        remaining_bytes: usize,
+    },
+
+    /// A `NewStream` frame has been received. The reception of any further data is blocked
+    /// waiting for the API user to accept or reject this substream.
+    ///
+    /// Note that [`Mplex::outgoing`] must always be [`Outgoing::Idle`], in order to give the
+    /// possibility to send back a reset frame for the new substream.
+    PendingIncomingSubstream {
+        /// Identifier of the pending substream.
+        substream_id: SubstreamId,
+        /// Length of the name of the substream, found after the header. This name is ignored.
+        name_len: usize,
+    },
+}
+
+enum Outgoing {
+    /// Nothing to write out.
+    Idle,
+
+    /// Writing out a header.
+    Header {
+        /// Bytes of the header to write out.
+        ///
+        /// Never empty (as otherwise the state must have been transitioned to something else).
+        header: arrayvec::ArrayVec<u8, 20>,
+
+        /// If `Some`, then the header is a message frame header and we must then transition the
+        /// state to [`Outgoing::SubstreamData`].
+        substream_data_frame: Option<(OutgoingSubstreamData, NonZeroUsize)>,
+    },
+
+    /// Writing out data from a substream.
+    ///
+    /// We have sent a message header in the past, and we must now send the associated data.
+    SubstreamData {
+        /// Source of the data to write out.
+        data: OutgoingSubstreamData,
+
+        /// Number of bytes remaining to write.
+        ///
+        /// Always superior or equal to the total length of the data in `write_buffers`.
+        remaining_bytes: NonZeroUsize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OutgoingSubstreamData {
+    /// Data is coming from the given substream.
+    ///
+    /// The substream must **not** be in a "reset" state.
+    Healthy(SubstreamId),
+
+    /// Data is coming from a substream in a reset state.
+    Obsolete {
+        /// Buffer of buffers to be written out to the socket.
+        write_buffers: Vec<Vec<u8>>,
+
+        /// Number of bytes in `self.write_buffers[0]` has have already been written out to the
+        /// socket.
+        first_write_buffer_offset: usize,
+    },
+}
+
+impl<T> Mplex<T> {
+    /// Initializes a new Mplex state machine.
+    pub fn new(config: Config) -> Mplex<T> {
+        let mut randomness = ChaCha20Rng::from_seed(config.randomness_seed);
+
+        Mplex {
+            substreams: hashbrown::HashMap::with_capacity_and_hasher(
+                config.capacity,
+                SipHasherBuild::new(randomness.gen()),
+            ),
+            num_inbound: 0,
+            new_incoming_substreams_denied: false,
+            incoming: Incoming::Header(arrayvec::ArrayVec::new()),
+            outgoing: Outgoing::Idle,
+            next_outbound_substream: 0,
+            resets_to_send: VecDeque::with_capacity(config.capacity),
+        }
+    }
+
+    /// Returns `true` if there is no substream in the state machine.
+    ///
+    /// > **Note**: After a substream has been closed or reset, it must be removed using
+    /// >           [`Mplex::remove_dead_substream`] before this function can return `true`.
+    pub fn is_empty(&self) -> bool {
+        self.substreams.is_empty()
+    }
+
+    /// Returns the number of substreams in the Mplex state machine. Includes substreams that are
+    /// dead but haven't been removed yet.
+    pub fn len(&self) -> usize {
+        self.substreams.len()
+    }
+
+    /// Returns the number of inbound substreams in the Mplex state machine. Includes substreams
+    /// that are dead but haven't been removed yet.
+    pub fn num_inbound(&self) -> usize {
+        debug_assert_eq!(
+            self.num_inbound,
+            self.substreams
+                .keys()
+                .filter(|id| id.remote_initiated)
+                .count()
+        );
+
+        self.num_inbound
+    }
+
+    /// Opens a new substream.
+    ///
+    /// This method only modifies the state of `self` and reserves an identifier. No message needs
+    /// to be sent to the remote before data is actually being sent on the substream.
+    ///
+    /// > **Note**: Importantly, the remote will not be notified of the substream being open
+    /// >           before the local side sends data on this substream. In practice, all
+    /// >           substreams in the context of libp2p start with a multistream-select
+    /// >           negotiation initiated by the opening side, and this is therefore not a
+    /// >           problem.
+    ///
+    /// # Panic
+    ///
+    /// Panics if all possible substream numbers have been used. This can only happen after
+    /// approximately `2^61` substreams have been opened, which is very unlikely to happen unless
+    /// there exists a bug in the code.
+    ///
+    pub fn open_substream(&mut self, user_data: T) -> SubstreamMut<'_, T> {
+        // The substream number is shifted by three bits when encoded in a frame header.
+        assert!(self.next_outbound_substream < (1 << 61));
+
+        let substream_id = SubstreamId {
+            num: self.next_outbound_substream,
+            remote_initiated: false,
+        };
+        self.next_outbound_substream += 1;
+
+        let entry = match self.substreams.entry(substream_id) {
+            Entry::Vacant(e) => e,
+            Entry::Occupied(_) => unreachable!(),
+        };
+
+        entry.insert(Substream {
+            state: SubstreamState::Healthy {
+                new_stream_frame_queued: false,
+                local_write: SubstreamStateLocalWrite::Open,
+                remote_write_closed: false,
+                write_buffers: Vec::with_capacity(16),
+                first_write_buffer_offset: 0,
+            },
+            user_data,
+        });
+
+        match self.substreams.entry(substream_id) {
+            Entry::Occupied(e) => SubstreamMut {
+                substream: e,
+                outgoing: &mut self.outgoing,
+                resets_to_send: &mut self.resets_to_send,
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns an iterator to the list of all substream user datas.
+    pub fn user_datas(&self) -> impl ExactSizeIterator<Item = (SubstreamId, &T)> {
+        self.substreams.iter().map(|(id, s)| (*id, &s.user_data))
+    }
+
+    /// Returns an iterator to the list of all substream user datas.
+    pub fn user_datas_mut(&mut self) -> impl ExactSizeIterator<Item = (SubstreamId, &mut T)> {
+        self.substreams
+            .iter_mut()
+            .map(|(id, s)| (*id, &mut s.user_data))
+    }
+
+    /// Returns a reference to a substream by its ID. Returns `None` if no substream with this ID
+    /// is open.
+    pub fn substream_by_id(&self, id: SubstreamId) -> Option<SubstreamRef<'_, T>> {
+        Some(SubstreamRef {
+            id,
+            substream: self.substreams.get(&id)?,
+        })
+    }
+
+    /// Returns a reference to a substream by its ID. Returns `None` if no substream with this ID
+    /// is open.
+    pub fn substream_by_id_mut(&mut self, id: SubstreamId) -> Option<SubstreamMut<'_, T>> {
+        if let Entry::Occupied(e) = self.substreams.entry(id) {
+            Some(SubstreamMut {
+                substream: e,
+                outgoing: &mut self.outgoing,
+                resets_to_send: &mut self.resets_to_send,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if [`Mplex::deny_new_incoming_substreams`] has been called in the past.
+    pub fn new_incoming_substreams_denied(&self) -> bool {
+        self.new_incoming_substreams_denied
+    }
+
+    /// Automatically rejects all follow-up requests for new substreams from the remote.
+    /// [`IncomingDataDetail::IncomingSubstream`] events can no longer happen.
+    ///
+    /// Contrary to Yamux, Mplex has no way to inform the remote of this decision ahead of time.
+    /// Each new substream is instead individually reset.
+    ///
+    /// If the state of [`Mplex`] is currently waiting for a confirmation to accept/reject a
+    /// substream, then this function automatically implies calling
+    /// [`Mplex::reject_pending_substream`].
+    ///
+    /// Calling this function multiple times has no additional effect.
+    pub fn deny_new_incoming_substreams(&mut self) {
+        self.new_incoming_substreams_denied = true;
+
+        if let Incoming::PendingIncomingSubstream { .. } = self.incoming {
+            self.reject_pending_substream();
+        }
+    }
+
+    /// Returns the list of all substreams that have been closed or reset.
+    ///
+    /// This function does not remove dead substreams from the state machine. In other words, if
+    /// this function is called multiple times in a row, it will always return the same
+    /// substreams. Use [`Mplex::remove_dead_substream`] to remove substreams.
+    pub fn dead_substreams(
+        &'_ self,
+    ) -> impl Iterator<Item = (SubstreamId, DeadSubstreamTy, &'_ T)> + '_ {
+        // TODO: O(n)
+        self.substreams
+            .iter()
+            .filter_map(|(id, substream)| match &substream.state {
+                SubstreamState::Reset => Some((*id, DeadSubstreamTy::Reset, &substream.user_data)),
+                SubstreamState::Healthy {
+                    local_write: SubstreamStateLocalWrite::CloseQueued,
+                    remote_write_closed: true,
+                    write_buffers,
+                    ..
+                } => {
+                    debug_assert!(write_buffers.is_empty());
+                    Some((*id, DeadSubstreamTy::ClosedGracefully, &substream.user_data))
+                }
+                SubstreamState::Healthy { .. } => None,
+            })
+            .inspect(|(dead_id, _, _)| {
+                debug_assert!(!matches!(
+                    self.outgoing,
+                    Outgoing::Header {
+                        substream_data_frame: Some((OutgoingSubstreamData::Healthy(id), _)),
+                        ..
+                    } | Outgoing::SubstreamData {
+                        data: OutgoingSubstreamData::Healthy(id),
+                        ..
+                    } if id == *dead_id
+                ));
+            })
+    }
+
+    /// Removes a dead substream from the state machine.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the substream with that id doesn't exist or isn't dead.
+    ///
+    pub fn remove_dead_substream(&mut self, id: SubstreamId) -> T {
+        let substream = self.substreams.remove(&id).unwrap();
+        // TODO: check whether substream is dead using the same criteria as in dead_substreams()
+
+        if id.remote_initiated {
+            self.num_inbound -= 1;
+        }
+
+        substream.user_data
+    }
+
+    /// Process some incoming data.
+    ///
+    /// This function takes ownership of `self` and yields it back if everything goes well. If,
+    /// on the other hand, a malformed frame is received, an error is yielded and `self` is
+    /// destroyed.
+    ///
+    /// This function might not process all the data available for one of the following reasons:
+    ///
+    /// - Not all outgoing data has been extracted. Rejecting an incoming substream requires
+    ///   queuing a reset frame. In order to avoid queuing an infinite amount of data, processing
+    ///   incoming substreams is blocked if there is data to be sent out.
+    /// - It is currently waiting for either [`Mplex::accept_pending_substream`] or
+    ///   [`Mplex::reject_pending_substream`] to be called.
+    /// - If the remote opens a substream whose ID is equal to a previous substream that is now
+    ///   dead. Use [`Mplex::dead_substreams`] and [`Mplex::remove_dead_substream`] to remove dead
+    ///   substreams before continuing.
+    ///
+    /// If the return value contains [`IncomingDataDetail::IncomingSubstream`], then either
+    /// [`Mplex::accept_pending_substream`] or [`Mplex::reject_pending_substream`] must be called
+    /// in order to accept or reject the pending substream. API users are encouraged to enforce a
+    /// limit to the total number of substreams in order to clamp the memory usage of this state
+    /// machine.
+    pub fn incoming_data(mut self, mut data: &[u8]) -> Result<IncomingDataOutcome<T>, Error> {
+        let mut total_read: usize = 0;
+
+        loop {
+            match self.incoming {
+                Incoming::PendingIncomingSubstream { .. } => break,
+
+                Incoming::Data {
+                    remaining_bytes: 0, ..
+                } => {
+                    self.incoming = Incoming::Header(arrayvec::ArrayVec::new());
+                }
+
+                Incoming::Data {
+                    substream_id,
+                    ref mut remaining_bytes,
+                } if !data.is_empty() => {
+                    // We only enter this block if `data` isn't empty, as we don't want to
+                    // generate a `DataFrame` event if there's no data.
+                    let pulled_data = cmp::min(*remaining_bytes, data.len());
+                    *remaining_bytes -= pulled_data;
+
+                    let start_offset = total_read;
+                    total_read += pulled_data;
+                    data = &data[pulled_data..];
+
+                    // Note that it is possible that we are receiving data corresponding to a
+                    // substream that has been reset by the local node in the meanwhile. Such
+                    // data is silently discarded.
+                    if let Some(substream_id) = substream_id {
+                        if let Some(Substream {
+                            state:
+                                SubstreamState::Healthy {
+                                    remote_write_closed,
+                                    ..
+                                },
+                            ..
+                        }) = self.substreams.get(&substream_id)
+                        {
+                            debug_assert!(!*remote_write_closed);
+                            return Ok(IncomingDataOutcome {
+                                mplex: self,
+                                bytes_read: total_read,
+                                detail: Some(IncomingDataDetail::DataFrame {
+                                    substream_id,
+                                    start_offset,
+                                }),
+                            });
+                        }
+                    }
+                }
+
+                Incoming::Data { .. } => {
+                    debug_assert!(data.is_empty());
+                    break;
+                }
+
+                Incoming::Header(ref mut incoming_header) => {
+                    // Try to copy as much as possible from `data` to `incoming_header`. The
+                    // header consists of two LEB128-encoded numbers, and is thus complete once
+                    // two bytes without their most significant bit have been received.
+                    while !data.is_empty()
+                        && incoming_header.iter().filter(|b| (**b & 0x80) == 0).count() < 2
+                    {
+                        if incoming_header.is_full() {
+                            return Err(Error::InvalidHeader);
+                        }
+
+                        incoming_header.push(data[0]);
+                        total_read += 1;
+                        data = &data[1..];
+                    }
+
+                    // Not enough data to finish receiving header. Nothing more can be done.
+                    if incoming_header.iter().filter(|b| (**b & 0x80) == 0).count() < 2 {
+                        debug_assert!(data.is_empty());
+                        break;
+                    }
+
+                    // Full header available to decode in `incoming_header`.
+                    let (header, length) = match nom::sequence::tuple((
+                        leb128::nom_leb128_u64::<nom::error::Error<&[u8]>>,
+                        leb128::nom_leb128_usize,
+                    ))(&incoming_header[..])
+                    {
+                        Ok((rest, header)) => {
+                            debug_assert!(rest.is_empty());
+                            header
+                        }
+                        Err(_) => return Err(Error::InvalidHeader),
+                    };
+
+                    if length > MAX_FRAME_SIZE {
+                        return Err(Error::FrameTooLarge);
+                    }
+
+                    let flag = u8::try_from(header & 0b111).unwrap();
+                    let num = header >> 3;
+
+                    match flag {
+                        // `NewStream` frame.
+                        0 => {
+                            let substream_id = SubstreamId {
+                                num,
+                                remote_initiated: true,
+                            };
+
+                            match self.substreams.get(&substream_id) {
+                                Some(Substream {
+                                    state: SubstreamState::Healthy { .. },
+                                    ..
+                                }) => {
+                                    return Err(Error::UnexpectedNewStream(num));
+                                }
+                                Some(Substream {
+                                    state: SubstreamState::Reset,
+                                    ..
+                                }) => {
+                                    // Because we don't immediately destroy substreams, the remote
+                                    // might decide to re-use a substream ID that is still
+                                    // allocated locally. If that happens, we block the reading.
+                                    break;
+                                }
+                                None => {}
+                            }
+
+                            // As documented, when in the `Incoming::PendingIncomingSubstream`
+                            // state, the outgoing state must always be `Outgoing::Idle`, in
+                            // order to potentially queue the substream rejection message later.
+                            // If it is not the case, we simply leave the header there and prevent
+                            // any further data from being read.
+                            if !matches!(self.outgoing, Outgoing::Idle) {
+                                break;
+                            }
+
+                            self.incoming = Incoming::PendingIncomingSubstream {
+                                substream_id,
+                                name_len: length,
+                            };
+
+                            // If new substreams are denied, the substream is automatically
+                            // rejected.
+                            if self.new_incoming_substreams_denied {
+                                self.reject_pending_substream();
+                                continue;
+                            }
+
+                            return Ok(IncomingDataOutcome {
+                                mplex: self,
+                                bytes_read: total_read,
+                                detail: Some(IncomingDataDetail::IncomingSubstream),
+                            });
+                        }
+
+                        // `Message` frame.
+                        1 | 2 => {
+                            let substream_id = SubstreamId {
+                                num,
+                                remote_initiated: flag == 2,
+                            };
+
+                            // Note that it is possible that the remote is referring to a substream
+                            // that has been reset by the local node. Since the local state
+                            // machine doesn't keep track of reset substreams, any frame
+                            // concerning a substream that has been reset or with an unknown id
+                            // is discarded and doesn't result in an error, under the presumption
+                            // that we are in this situation.
+                            let is_known = match self.substreams.get(&substream_id) {
+                                Some(Substream {
+                                    state:
+                                        SubstreamState::Healthy {
+                                            remote_write_closed: true,
+                                            ..
+                                        },
+                                    ..
+                                }) => return Err(Error::WriteAfterClose),
+                                Some(Substream {
+                                    state: SubstreamState::Healthy { .. },
+                                    ..
+                                }) => true,
+                                _ => false,
+                            };
+
+                            self.incoming = Incoming::Data {
+                                substream_id: if is_known { Some(substream_id) } else { None },
+                                remaining_bytes: length,
+                            };
+                        }
+
+                        // `Close` frame.
+                        3 | 4 => {
+                            let substream_id = SubstreamId {
+                                num,
+                                remote_initiated: flag == 4,
+                            };
+
+                            // `Close` frames aren't supposed to contain any data. If they do,
+                            // it is discarded.
+                            self.incoming = Incoming::Data {
+                                substream_id: None,
+                                remaining_bytes: length,
+                            };
+
+                            if let Some(Substream {
+                                state:
+                                    SubstreamState::Healthy {
+                                        remote_write_closed: remote_write_closed @ false,
+                                        ..
+                                    },
+                                ..
+                            }) = self.substreams.get_mut(&substream_id)
+                            {
+                                *remote_write_closed = true;
+
+                                return Ok(IncomingDataOutcome {
+                                    mplex: self,
+                                    bytes_read: total_read,
+                                    detail: Some(IncomingDataDetail::StreamClosed { substream_id }),
+                                });
+                            }
+                        }
+
+                        // `Reset` frame.
+                        5 | 6 => {
+                            let substream_id = SubstreamId {
+                                num,
+                                remote_initiated: flag == 6,
+                            };
+
+                            // `Reset` frames aren't supposed to contain any data. If they do,
+                            // it is discarded.
+                            self.incoming = Incoming::Data {
+                                substream_id: None,
+                                remaining_bytes: length,
+                            };
+
+                            // The remote might have sent a reset frame concerning a substream for
+                            // which we have sent a reset frame earlier. Considering that we don't
+                            // always keep traces of old substreams, we have no way to know whether
+                            // this is the case or not.
+                            if let Some(s) = self.substreams.get_mut(&substream_id) {
+                                if let SubstreamState::Reset = s.state {
+                                    continue;
+                                }
+
+                                // We might be currently writing a frame of data of the substream
+                                // being reset. If that happens, we need to update some internal
+                                // state regarding this frame of data.
+                                match (
+                                    &mut self.outgoing,
+                                    mem::replace(&mut s.state, SubstreamState::Reset),
+                                ) {
+                                    (
+                                        Outgoing::Header {
+                                            substream_data_frame:
+                                                Some((data @ OutgoingSubstreamData::Healthy(_), _)),
+                                            ..
+                                        }
+                                        | Outgoing::SubstreamData {
+                                            data: data @ OutgoingSubstreamData::Healthy(_),
+                                            ..
+                                        },
+                                        SubstreamState::Healthy {
+                                            write_buffers,
+                                            first_write_buffer_offset,
+                                            ..
+                                        },
+                                    ) if *data == OutgoingSubstreamData::Healthy(substream_id) => {
+                                        *data = OutgoingSubstreamData::Obsolete {
+                                            write_buffers,
+                                            first_write_buffer_offset,
+                                        };
+                                    }
+                                    _ => {}
+                                }
+
+                                return Ok(IncomingDataOutcome {
+                                    mplex: self,
+                                    bytes_read: total_read,
+                                    detail: Some(IncomingDataDetail::StreamReset { substream_id }),
+                                });
+                            }
+                        }
+
+                        _ => return Err(Error::InvalidHeader),
+                    }
+                }
+            }
+        }
+
+        Ok(IncomingDataOutcome {
+            mplex: self,
+            bytes_read: total_read,
+            detail: None,
+        })
+    }
+
+    /// Returns an object that provides an iterator to a list of buffers whose content must be
+    /// sent out on the socket.
+    ///
+    /// The buffers produced by the iterator will never yield more than `size_bytes` bytes of
+    /// data. The user is expected to pass an exact amount of bytes that the next layer is ready
+    /// to accept.
+    ///
+    /// After the [`ExtractOut`] has been destroyed, the Mplex state machine will automatically
+    /// consider that these `size_bytes` have been sent out, even if the iterator has been
+    /// destroyed before finishing. It is a logic error to `mem::forget` the [`ExtractOut`].
+    pub fn extract_out(&mut self, size_bytes: usize) -> ExtractOut<'_, T> {
+        ExtractOut {
+            mplex: self,
+            size_bytes,
+        }
+    }
+
+    /// Accepts an incoming substream.
+    ///
+    /// Either [`Mplex::accept_pending_substream`] or [`Mplex::reject_pending_substream`] must be
+    /// called after [`IncomingDataDetail::IncomingSubstream`] is returned.
+    ///
+    /// Note that there is no expiration window after [`IncomingDataDetail::IncomingSubstream`]
+    /// is returned until the substream is no longer valid. However, reading will be blocked until
+    /// the substream is either accepted or rejected. This function should thus be called as
+    /// soon as possible.
+    ///
+    /// # Panic
+    ///
+    /// Panics if no incoming substream is currently pending.
+    ///
+    pub fn accept_pending_substream(&mut self, user_data: T) -> SubstreamMut<'_, T> {
+        match self.incoming {
+            Incoming::PendingIncomingSubstream {
+                substream_id,
+                name_len,
+            } => {
+                let _was_before = self.substreams.insert(
+                    substream_id,
+                    Substream {
+                        state: SubstreamState::Healthy {
+                            new_stream_frame_queued: true,
+                            local_write: SubstreamStateLocalWrite::Open,
+                            remote_write_closed: false,
+                            write_buffers: Vec::new(),
+                            first_write_buffer_offset: 0,
+                        },
+                        user_data,
+                    },
+                );
+                debug_assert!(_was_before.is_none());
+
+                self.num_inbound += 1;
+
+                // The name of the substream is discarded.
+                self.incoming = Incoming::Data {
+                    substream_id: None,
+                    remaining_bytes: name_len,
+                };
+
+                SubstreamMut {
+                    substream: match self.substreams.entry(substream_id) {
+                        Entry::Occupied(e) => e,
+                        _ => unreachable!(),
+                    },
+                    outgoing: &mut self.outgoing,
+                    resets_to_send: &mut self.resets_to_send,
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Rejects an incoming substream.
+    ///
+    /// Either [`Mplex::accept_pending_substream`] or [`Mplex::reject_pending_substream`] must be
+    /// called after [`IncomingDataDetail::IncomingSubstream`] is returned.
+    ///
+    /// Note that there is no expiration window after [`IncomingDataDetail::IncomingSubstream`]
+    /// is returned until the substream is no longer valid. However, reading will be blocked until
+    /// the substream is either accepted or rejected. This function should thus be called as
+    /// soon as possible.
+    ///
+    /// # Panic
+    ///
+    /// Panics if no incoming substream is currently pending.
+    ///
+    pub fn reject_pending_substream(&mut self) {
+        // Implementation note: similar to Yamux, the reset frame is immediately put in
+        // `self.outgoing` rather than queued, as queuing it could open a DoS attack vector.
+        match self.incoming {
+            Incoming::PendingIncomingSubstream {
+                substream_id,
+                name_len,
+            } => {
+                self.incoming = Incoming::Data {
+                    substream_id: None,
+                    remaining_bytes: name_len,
+                };
+
+                debug_assert!(matches!(self.outgoing, Outgoing::Idle));
+                self.outgoing = Outgoing::Header {
+                    header: frame_header(substream_id, FrameTy::Reset, 0),
+                    substream_data_frame: None,
+                };
+            }
+            _ => panic!(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Mplex<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct List<'a, T>(&'a Mplex<T>);
+        impl<'a, T> fmt::Debug for List<'a, T>
+        where
+            T: fmt::Debug,
+        {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_list()
+                    .entries(self.0.substreams.values().map(|v| &v.user_data))
+                    .finish()
+            }
+        }
+
+        f.debug_struct("Mplex")
+            .field("substreams", &List(self))
+            .finish()
+    }
+}
+
+/// Reference to a substream within the [`Mplex`].
+pub struct SubstreamRef<'a, T> {
+    id: SubstreamId,
+    substream: &'a Substream<T>,
+}
+
+impl<'a, T> SubstreamRef<'a, T> {
+    /// Identifier of the substream.
+    pub fn id(&self) -> SubstreamId {
+        self.id
+    }
+
+    /// Returns the user data associated to this substream.
+    pub fn user_data(&self) -> &T {
+        &self.substream.user_data
+    }
+
+    /// Returns the user data associated to this substream.
+    pub fn into_user_data(self) -> &'a T {
+        &self.substream.user_data
+    }
+
+    /// Returns the number of bytes queued for writing on this substream.
+    ///
+    /// Returns 0 if the substream is in a reset state.
+    pub fn queued_bytes(&self) -> usize {
+        queued_bytes(&self.substream.state)
+    }
+
+    /// Returns `false` if the remote has closed their writing side of this substream, or if
+    /// [`SubstreamMut::reset`] has been called on this substream, or if the substream has been
+    /// reset by the remote.
+    pub fn can_receive(&self) -> bool {
+        matches!(
+            self.substream.state,
+            SubstreamState::Healthy {
+                remote_write_closed: false,
+                ..
+            }
+        )
+    }
+
+    /// Returns `false` if [`SubstreamMut::close`] or [`SubstreamMut::reset`] has been called on
+    /// this substream, or if the remote has reset it.
+    pub fn can_send(&self) -> bool {
+        matches!(
+            self.substream.state,
+            SubstreamState::Healthy {
+                local_write: SubstreamStateLocalWrite::Open,
+                ..
+            }
+        )
+    }
+}
+
+impl<'a, T> fmt::Debug for SubstreamRef<'a, T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Substream").field(self.user_data()).finish()
+    }
+}
+
+/// Reference to a substream within the [`Mplex`].
+pub struct SubstreamMut<'a, T> {
+    substream: OccupiedEntry<'a, SubstreamId, Substream<T>, SipHasherBuild>,
+    outgoing: &'a mut Outgoing,
+    resets_to_send: &'a mut VecDeque<SubstreamId>,
+}
+
+impl<'a, T> SubstreamMut<'a, T> {
+    /// Identifier of the substream.
+    pub fn id(&self) -> SubstreamId {
+        *self.substream.key()
+    }
+
+    /// Returns the user data associated to this substream.
+    pub fn user_data(&self) -> &T {
+        &self.substream.get().user_data
+    }
+
+    /// Returns the user data associated to this substream.
+    pub fn user_data_mut(&mut self) -> &mut T {
+        &mut self.substream.get_mut().user_data
+    }
+
+    /// Returns the user data associated to this substream.
+    pub fn into_user_data(self) -> &'a mut T {
+        &mut self.substream.into_mut().user_data
+    }
+
+    /// Appends data to the buffer of data to send out on this substream.
+    ///
+    /// Has no effect if [`SubstreamMut::close`] or [`SubstreamMut::reset`] has already been
+    /// called on this substream, or if the remote has reset it.
+    pub fn write(&mut self, data: Vec<u8>) {
+        if data.is_empty() {
+            return;
+        }
+
+        if let SubstreamState::Healthy {
+            local_write: SubstreamStateLocalWrite::Open,
+            write_buffers,
+            ..
+        } = &mut self.substream.get_mut().state
+        {
+            write_buffers.push(data);
+        }
+    }
+
+    /// Returns the number of bytes queued for writing on this substream.
+    ///
+    /// Returns 0 if the substream is in a reset state.
+    pub fn queued_bytes(&self) -> usize {
+        queued_bytes(&self.substream.get().state)
+    }
+
+    /// Returns `false` if the remote has closed their writing side of this substream, or if
+    /// [`SubstreamMut::reset`] has been called on this substream, or if the substream has been
+    /// reset by the remote.
+    pub fn can_receive(&self) -> bool {
+        matches!(
+            self.substream.get().state,
+            SubstreamState::Healthy {
+                remote_write_closed: false,
+                ..
+            }
+        )
+    }
+
+    /// Returns `false` if [`SubstreamMut::close`] or [`SubstreamMut::reset`] has been called on
+    /// this substream, or if the remote has reset it.
+    pub fn can_send(&self) -> bool {
+        matches!(
+            self.substream.get().state,
+            SubstreamState::Healthy {
+                local_write: SubstreamStateLocalWrite::Open,
+                ..
+            }
+        )
+    }
+
+    /// Marks the substream as closed. It is no longer possible to write data on it.
+    ///
+    /// Has no effect if the local writing side is already closed or if the substream has been
+    /// reset.
+    pub fn close(&mut self) {
+        if let SubstreamState::Healthy {
+            local_write: ref mut local_write @ SubstreamStateLocalWrite::Open,
+            ..
+        } = self.substream.get_mut().state
+        {
+            *local_write = SubstreamStateLocalWrite::CloseDesired;
+        }
+    }
+
+    /// Abruptly shuts down the substream. Sends a reset frame to the remote.
+    ///
+    /// Use this method when a protocol error happens on a substream.
+    pub fn reset(&mut self) {
+        let substream_id = *self.substream.key();
+
+        // Add an entry to the list of reset frames to send to the remote. There is no need to
+        // send one if the remote has never been notified of the existence of the substream.
+        if let SubstreamState::Healthy {
+            new_stream_frame_queued: true,
+            ..
+        } = self.substream.get().state
+        {
+            self.resets_to_send.push_back(substream_id);
+        }
+
+        // We might be currently writing a frame of data of the substream being reset.
+        // If that happens, we need to update some internal state regarding this frame of data.
+        match (
+            &mut self.outgoing,
+            mem::replace(&mut self.substream.get_mut().state, SubstreamState::Reset),
+        ) {
+            (
+                Outgoing::Header {
+                    substream_data_frame: Some((data @ OutgoingSubstreamData::Healthy(_), _)),
+                    ..
+                }
+                | Outgoing::SubstreamData {
+                    data: data @ OutgoingSubstreamData::Healthy(_),
+                    ..
+                },
+                SubstreamState::Healthy {
+                    write_buffers,
+                    first_write_buffer_offset,
+                    ..
+                },
+            ) if *data == OutgoingSubstreamData::Healthy(substream_id) => {
+                *data = OutgoingSubstreamData::Obsolete {
+                    write_buffers,
+                    first_write_buffer_offset,
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, T> fmt::Debug for SubstreamMut<'a, T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Substream").field(self.user_data()).finish()
+    }
+}
+
+pub struct ExtractOut<'a, T> {
+    mplex: &'a mut Mplex<T>,
+    size_bytes: usize,
+}
+
+impl<'a, T> ExtractOut<'a, T> {
+    /// Builds the next buffer to send out and returns it.
+    pub fn next_outgoing_data(&'_ mut self) -> Option<impl AsRef<[u8]> + '_> {
+        while self.size_bytes != 0 {
+            match self.mplex.outgoing {
+                Outgoing::Header {
+                    ref mut header,
+                    ref mut substream_data_frame,
+                } => {
+                    // Finish writing the header.
+                    debug_assert!(!header.is_empty());
+                    if self.size_bytes >= header.len() {
+                        self.size_bytes -= header.len();
+                        let out = mem::take(header);
+                        self.mplex.outgoing =
+                            if let Some((data, remaining_bytes)) = substream_data_frame.take() {
+                                Outgoing::SubstreamData {
+                                    data,
+                                    remaining_bytes,
+                                }
+                            } else {
+                                Outgoing::Idle
+                            };
+                        return Some(either::Left(out));
+                    } else {
+                        let to_add = header[..self.size_bytes].to_vec();
+                        for _ in 0..self.size_bytes {
+                            header.remove(0);
+                        }
+                        self.size_bytes = 0;
+                        return Some(either::Right(VecWithOffset(to_add, 0)));
+                    }
+                }
+
+                Outgoing::SubstreamData {
+                    remaining_bytes: ref mut remain,
+                    ref mut data,
+                } => {
+                    let (write_buffers, first_write_buffer_offset) = match data {
+                        OutgoingSubstreamData::Healthy(id) => {
+                            let substream = self.mplex.substreams.get_mut(id).unwrap();
+                            if let SubstreamState::Healthy {
+                                ref mut write_buffers,
+                                ref mut first_write_buffer_offset,
+                                ..
+                            } = &mut substream.state
+                            {
+                                (write_buffers, first_write_buffer_offset)
+                            } else {
+                                unreachable!()
+                            }
+                        }
+                        OutgoingSubstreamData::Obsolete {
+                            ref mut write_buffers,
+                            ref mut first_write_buffer_offset,
+                        } => (write_buffers, first_write_buffer_offset),
+                    };
+
+                    let first_buf_avail = write_buffers[0].len() - *first_write_buffer_offset;
+                    let out = if first_buf_avail <= remain.get()
+                        && first_buf_avail <= self.size_bytes
+                    {
+                        let out =
+                            VecWithOffset(write_buffers.remove(0), *first_write_buffer_offset);
+                        self.size_bytes -= first_buf_avail;
+                        *first_write_buffer_offset = 0;
+                        match NonZeroUsize::new(remain.get() - first_buf_avail) {
+                            Some(r) => *remain = r,
+                            None => self.mplex.outgoing = Outgoing::Idle,
+                        };
+                        either::Right(out)
+                    } else if remain.get() <= self.size_bytes {
+                        self.size_bytes -= remain.get();
+                        let out = VecWithOffset(
+                            write_buffers[0][*first_write_buffer_offset..][..remain.get()].to_vec(),
+                            0,
+                        );
+                        *first_write_buffer_offset += remain.get();
+                        self.mplex.outgoing = Outgoing::Idle;
+                        either::Right(out)
+                    } else {
+                        let out = VecWithOffset(
+                            write_buffers[0][*first_write_buffer_offset..][..self.size_bytes]
+                                .to_vec(),
+                            0,
+                        );
+                        *first_write_buffer_offset += self.size_bytes;
+                        *remain = NonZeroUsize::new(remain.get() - self.size_bytes).unwrap();
+                        self.size_bytes = 0;
+                        either::Right(out)
+                    };
+
+                    return Some(out);
+                }
+
+                Outgoing::Idle => {
+                    // Send reset frames.
+                    if let Some(substream_id) = self.mplex.resets_to_send.pop_front() {
+                        self.mplex.outgoing = Outgoing::Header {
+                            header: frame_header(substream_id, FrameTy::Reset, 0),
+                            substream_data_frame: None,
+                        };
+                        continue;
+                    }
+
+                    // Start writing more data from another substream.
+                    // TODO: O(n)
+                    // TODO: choose substreams in some sort of round-robin way
+                    if let Some((id, sub)) = self
+                        .mplex
+                        .substreams
+                        .iter_mut()
+                        .find(|(_, s)| match &s.state {
+                            SubstreamState::Healthy {
+                                write_buffers,
+                                local_write,
+                                ..
+                            } => {
+                                !write_buffers.is_empty()
+                                    || matches!(local_write, SubstreamStateLocalWrite::CloseDesired)
+                            }
+                            _ => false,
+                        })
+                        .map(|(id, sub)| (*id, sub))
+                    {
+                        if let SubstreamState::Healthy {
+                            new_stream_frame_queued,
+                            local_write,
+                            write_buffers,
+                            ..
+                        } = &mut sub.state
+                        {
+                            // The remote must first be notified of the existence of the
+                            // substream.
+                            if !*new_stream_frame_queued {
+                                *new_stream_frame_queued = true;
+                                self.mplex.outgoing = Outgoing::Header {
+                                    header: frame_header(id, FrameTy::NewStream, 0),
+                                    substream_data_frame: None,
+                                };
+                                continue;
+                            }
+
+                            let pending_len = write_buffers.iter().fold(0, |l, b| l + b.len());
+                            if let Some(len_out) =
+                                NonZeroUsize::new(cmp::min(pending_len, MAX_FRAME_SIZE))
+                            {
+                                self.mplex.outgoing = Outgoing::Header {
+                                    header: frame_header(id, FrameTy::Message, len_out.get()),
+                                    substream_data_frame: Some((
+                                        OutgoingSubstreamData::Healthy(id),
+                                        len_out,
+                                    )),
+                                };
+                            } else {
+                                debug_assert!(matches!(
+                                    local_write,
+                                    SubstreamStateLocalWrite::CloseDesired
+                                ));
+                                *local_write = SubstreamStateLocalWrite::CloseQueued;
+                                self.mplex.outgoing = Outgoing::Header {
+                                    header: frame_header(id, FrameTy::Close, 0),
+                                    substream_data_frame: None,
+                                };
+                            }
+                        } else {
+                            unreachable!()
+                        }
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Clone)]
+struct VecWithOffset(Vec<u8>, usize);
+impl AsRef<[u8]> for VecWithOffset {
+    fn as_ref(&self) -> &[u8] {
+        &self.0[self.1..]
+    }
+}
+
+/// Returns the number of bytes queued for writing in the given substream state.
+fn queued_bytes(state: &SubstreamState) -> usize {
+    match state {
+        SubstreamState::Healthy {
+            write_buffers,
+            first_write_buffer_offset,
+            ..
+        } => write_buffers.iter().fold(0, |n, buf| n + buf.len()) - first_write_buffer_offset,
+        SubstreamState::Reset => 0,
+    }
+}
+
+/// Type of a frame sent by the local node.
+enum FrameTy {
+    NewStream,
+    Message,
+    Close,
+    Reset,
+}
+
+/// Builds the header of a frame to send to the remote.
+///
+/// The flag found in the header depends on which side has opened the substream.
+fn frame_header(
+    substream_id: SubstreamId,
+    ty: FrameTy,
+    length: usize,
+) -> arrayvec::ArrayVec<u8, 20> {
+    let flag: u64 = match (ty, substream_id.remote_initiated) {
+        (FrameTy::NewStream, false) => 0,
+        (FrameTy::NewStream, true) => unreachable!(),
+        (FrameTy::Message, true) => 1,
+        (FrameTy::Message, false) => 2,
+        (FrameTy::Close, true) => 3,
+        (FrameTy::Close, false) => 4,
+        (FrameTy::Reset, true) => 5,
+        (FrameTy::Reset, false) => 6,
+    };
+
+    let mut header = arrayvec::ArrayVec::new();
+    header.extend(leb128::encode((substream_id.num << 3) | flag));
+    header.extend(leb128::encode_usize(length));
+    header
+}
+
+/// Identifier of a substream in the context of a connection.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct SubstreamId {
+    /// Number allocated by the side that has opened the substream.
+    num: u64,
+    /// `true` if the substream has been opened by the remote. Both sides allocate numbers
+    /// independently, and the same number can thus refer to two different substreams.
+    remote_initiated: bool,
+}
+
+impl SubstreamId {
+    /// Returns the value that compares inferior or equal to all possible values.
+    pub fn min_value() -> Self {
+        SubstreamId {
+            num: u64::MIN,
+            remote_initiated: false,
+        }
+    }
+
+    /// Returns the value that compares superior or equal to all possible values.
+    pub fn max_value() -> Self {
+        SubstreamId {
+            num: u64::MAX,
+            remote_initiated: true,
+        }
+    }
+}
+
+#[must_use]
+#[derive(Debug)]
+pub struct IncomingDataOutcome<T> {
+    /// Mplex object on which [`Mplex::incoming_data`] has been called.
+    pub mplex: Mplex<T>,
+    /// Number of bytes read from the incoming buffer. These bytes should no longer be present the
+    /// next time [`Mplex::incoming_data`] is called.
+    pub bytes_read: usize,
+    /// Detail about the incoming data. `None` if nothing of interest has happened.
+    pub detail: Option<IncomingDataDetail>,
+}
+
+/// Details about the incoming data.
+#[must_use]
+#[derive(Debug)]
+pub enum IncomingDataDetail {
+    /// Remote has requested to open a new substream.
+    ///
+    /// After this has been received, either [`Mplex::accept_pending_substream`] or
+    /// [`Mplex::reject_pending_substream`] needs to be called in order to accept or reject
+    /// this substream. Calling [`Mplex::incoming_data`] before this is done will lead to a
+    /// panic.
+    ///
+    /// Note that this can never happen after [`Mplex::deny_new_incoming_substreams`] has been
+    /// called, as all substreams are then automatically rejected.
+    IncomingSubstream,
+
+    /// Received data corresponding to a substream.
+    DataFrame {
+        /// Offset in the buffer passed to [`Mplex::incoming_data`] where the data frame
+        /// starts. The data frame ends at the offset of [`IncomingDataOutcome::bytes_read`].
+        start_offset: usize,
+        /// Substream the data belongs to. Guaranteed to be valid.
+        substream_id: SubstreamId,
+    },
+
+    /// Remote has closed its writing side of the substream.
+    StreamClosed {
+        /// Substream that got closed.
+        substream_id: SubstreamId,
+    },
+
+    /// Remote has asked to reset a substream.
+    StreamReset {
+        /// Substream that has been reset.
+        substream_id: SubstreamId,
+    },
+}
+
+/// Error while decoding the Mplex stream.
+#[derive(Debug, derive_more::Display)]
+pub enum Error {
+    /// Failed to decode the header of an incoming frame.
+    InvalidHeader,
+    /// Remote has sent a frame larger than the maximum allowed size.
+    FrameTooLarge,
+    /// Received a `NewStream` frame with a known substream ID.
+    #[display(fmt = "Received a NewStream frame with a known substream ID")]
+    UnexpectedNewStream(u64),
+    /// Remote sent additional data on a substream after having closed it.
+    WriteAfterClose,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeadSubstreamTy {
+    ClosedGracefully,
+    Reset,
+}
+
+/// Maximum size of the data of a frame, as defined in the specification.
+const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, DeadSubstreamTy, IncomingDataDetail, Mplex};
+
+    fn new_mplex() -> Mplex<()> {
+        Mplex::new(Config {
+            capacity: 4,
+            randomness_seed: [0; 32],
+        })
+    }
+
+    fn extract_all(mplex: &mut Mplex<()>) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut extract = mplex.extract_out(usize::MAX);
+        while let Some(buffer) = extract.next_outgoing_data() {
+            out.extend_from_slice(buffer.as_ref());
+        }
+        out
+    }
+
+    #[test]
+    fn outbound_substream_frames() {
+        let mut mplex = new_mplex();
+        let mut substream = mplex.open_substream(());
+        substream.write(b"hello".to_vec());
+        substream.close();
+
+        assert_eq!(
+            extract_all(&mut mplex),
+            vec![0x00, 0x00, 0x02, 0x05, b'h', b'e', b'l', b'l', b'o', 0x04, 0x00]
+        );
+    }
+
+    #[test]
+    fn incoming_substream_data_and_close() {
+        let mplex = new_mplex();
+
+        // `NewStream` frame for the substream `1`, with the name `a`.
+        let outcome = mplex.incoming_data(&[0x08, 0x01, b'a']).unwrap();
+        assert_eq!(outcome.bytes_read, 2);
+        assert!(matches!(
+            outcome.detail,
+            Some(IncomingDataDetail::IncomingSubstream)
+        ));
+        let mut mplex = outcome.mplex;
+        let substream_id = mplex.accept_pending_substream(()).id();
+        assert_eq!(mplex.num_inbound(), 1);
+
+        // Remainder of the previous frame, then a `MessageInitiator` frame.
+        let outcome = mplex.incoming_data(&[b'a', 0x0a, 0x02, 1, 2]).unwrap();
+        assert_eq!(outcome.bytes_read, 5);
+        match outcome.detail {
+            Some(IncomingDataDetail::DataFrame {
+                start_offset: 3,
+                substream_id: id,
+            }) => assert_eq!(id, substream_id),
+            _ => panic!(),
+        }
+
+        // `CloseInitiator` frame.
+        let outcome = outcome.mplex.incoming_data(&[0x0c, 0x00]).unwrap();
+        assert_eq!(outcome.bytes_read, 2);
+        assert!(matches!(
+            outcome.detail,
+            Some(IncomingDataDetail::StreamClosed { substream_id: id }) if id == substream_id
+        ));
+        let mut mplex = outcome.mplex;
+
+        // Answer and close the substream.
+        let mut substream = mplex.substream_by_id_mut(substream_id).unwrap();
+        assert!(!substream.can_receive());
+        substream.write(vec![3]);
+        substream.close();
+        assert_eq!(extract_all(&mut mplex), vec![0x09, 0x01, 3, 0x0b, 0x00]);
+
+        assert_eq!(
+            mplex.dead_substreams().collect::<Vec<_>>(),
+            vec![(substream_id, DeadSubstreamTy::ClosedGracefully, &())]
+        );
+        mplex.remove_dead_substream(substream_id);
+        assert!(mplex.is_empty());
+    }
+
+    #[test]
+    fn rejected_substream_is_reset() {
+        let mut mplex = new_mplex();
+        mplex.deny_new_incoming_substreams();
+
+        let outcome = mplex.incoming_data(&[0x10, 0x00]).unwrap();
+        assert_eq!(outcome.bytes_read, 2);
+        assert!(outcome.detail.is_none());
+        let mut mplex = outcome.mplex;
+        assert!(mplex.is_empty());
+
+        // `ResetReceiver` frame for the substream `2`.
+        assert_eq!(extract_all(&mut mplex), vec![0x15, 0x00]);
+    }
+
+    #[test]
+    fn remote_reset() {
+        let mut mplex = new_mplex();
+        let substream_id = mplex.open_substream(()).id();
+
+        // `ResetReceiver` frame for the substream `0` opened locally.
+        let outcome = mplex.incoming_data(&[0x05, 0x00]).unwrap();
+        assert!(matches!(
+            outcome.detail,
+            Some(IncomingDataDetail::StreamReset { substream_id: id }) if id == substream_id
+        ));
+        let mplex = outcome.mplex;
+        assert_eq!(
+            mplex.dead_substreams().collect::<Vec<_>>(),
+            vec![(substream_id, DeadSubstreamTy::Reset, &())]
+        );
+    }
+
+    #[test]
+    fn frame_too_large() {
+        // `MessageInitiator` frame with a length of 2 MiB.
+        assert!(new_mplex()
+            .incoming_data(&[0x0a, 0x80, 0x80, 0x80, 0x01])
+            .is_err());
+    }
+}
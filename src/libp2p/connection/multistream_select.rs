@@ -125,6 +125,9 @@ enum InProgressState<P> {
     SendProtocolRequest {
         /// Number of bytes of the request already written out.
         num_bytes_written: usize,
+        /// `true` if the handshake of the remote has already been received, in which case the
+        /// next step after the request has been written out is to wait for its answer.
+        handshake_received: bool,
     },
     SendProtocolOk {
         /// Number of bytes of the response already written out.
@@ -190,6 +193,21 @@ where
         }
     }
 
+    /// Initializes a new state machine for the dialing side, after a previous negotiation on the
+    /// same stream has ended with [`Negotiation::NotAvailable`].
+    ///
+    /// This makes it possible to request another protocol after the remote has refused the
+    /// previous one. Contrary to [`InProgress::new`], the multistream-select handshake is neither
+    /// sent nor expected, as it has already been exchanged during the previous negotiation.
+    pub fn new_dialer_retry(requested_protocol: P) -> Self {
+        let mut in_progress = InProgress::new(Config::Dialer { requested_protocol });
+        in_progress.state = InProgressState::SendProtocolRequest {
+            num_bytes_written: 0,
+            handshake_received: true,
+        };
+        in_progress
+    }
+
     /// If this function returns true, then the multistream-select handshake has finished writing
     /// all its data, and the API user can now start writing the protocol-specific data if it
     /// desires, even though the multistream-handshake isn't finished.
@@ -251,6 +269,7 @@ where
                         (true, Config::Dialer { .. }) => {
                             self.state = InProgressState::SendProtocolRequest {
                                 num_bytes_written: 0,
+                                handshake_received: false,
                             }
                         }
                         (true, Config::Listener { .. }) => {
@@ -262,6 +281,7 @@ where
                 (
                     InProgressState::SendProtocolRequest {
                         mut num_bytes_written,
+                        handshake_received,
                     },
                     Some(Config::Dialer { requested_protocol }),
                 ) => {
@@ -277,10 +297,15 @@ where
                     let done = message.write_out(num_bytes_written, read_write);
                     num_bytes_written += read_write.written_bytes - written_before;
 
-                    if done {
+                    if done && handshake_received {
+                        self.state = InProgressState::ProtocolRequestAnswerExpected;
+                    } else if done {
                         self.state = InProgressState::HandshakeExpected;
                     } else {
-                        self.state = InProgressState::SendProtocolRequest { num_bytes_written };
+                        self.state = InProgressState::SendProtocolRequest {
+                            num_bytes_written,
+                            handshake_received,
+                        };
                         break;
                     }
                 }
@@ -682,7 +707,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{super::super::read_write::ReadWrite, Config, MessageOut, Negotiation};
+    use super::{super::super::read_write::ReadWrite, Config, InProgress, MessageOut, Negotiation};
     use core::iter;
 
     #[test]
@@ -830,4 +855,80 @@ mod tests {
         test_with_buffer_sizes(1, 2048);
         test_with_buffer_sizes(2048, 1);
     }
+
+    #[test]
+    fn dialer_retry_after_not_available() {
+        fn step(
+            negotiation: Negotiation<iter::Once<&'static str>, &'static str>,
+            incoming: &mut Vec<u8>,
+            outgoing: &mut Vec<u8>,
+        ) -> Negotiation<iter::Once<&'static str>, &'static str> {
+            let nego = match negotiation {
+                Negotiation::InProgress(nego) => nego,
+                other => return other,
+            };
+
+            let mut out_buffer = vec![0; 256];
+            let mut read_write = ReadWrite {
+                now: 0,
+                incoming_buffer: Some(incoming),
+                outgoing_buffer: Some((&mut out_buffer, &mut [])),
+                read_bytes: 0,
+                written_bytes: 0,
+                wake_up_after: None,
+            };
+            let negotiation = nego.read_write(&mut read_write).unwrap();
+            let (read_bytes, written_bytes) = (read_write.read_bytes, read_write.written_bytes);
+            incoming.drain(..read_bytes);
+            outgoing.extend_from_slice(&out_buffer[..written_bytes]);
+            negotiation
+        }
+
+        let mut dialer = Negotiation::new(Config::Dialer {
+            requested_protocol: "/foo",
+        });
+        let mut listener = Negotiation::new(Config::Listener {
+            supported_protocols: iter::once("/bar"),
+        });
+
+        let mut buf_dialer_to_listener = Vec::new();
+        let mut buf_listener_to_dialer = Vec::new();
+
+        // The listener doesn't support `/foo`.
+        while !matches!(dialer, Negotiation::NotAvailable) {
+            dialer = step(
+                dialer,
+                &mut buf_listener_to_dialer,
+                &mut buf_dialer_to_listener,
+            );
+            listener = step(
+                listener,
+                &mut buf_dialer_to_listener,
+                &mut buf_listener_to_dialer,
+            );
+            assert!(matches!(listener, Negotiation::InProgress(_)));
+        }
+
+        // Try again with `/bar` on the same stream.
+        dialer = Negotiation::InProgress(InProgress::new_dialer_retry("/bar"));
+        while !matches!(
+            (&dialer, &listener),
+            (Negotiation::Success(_), Negotiation::Success(_))
+        ) {
+            dialer = step(
+                dialer,
+                &mut buf_listener_to_dialer,
+                &mut buf_dialer_to_listener,
+            );
+            listener = step(
+                listener,
+                &mut buf_dialer_to_listener,
+                &mut buf_listener_to_dialer,
+            );
+            assert!(!matches!(dialer, Negotiation::NotAvailable));
+        }
+
+        assert!(matches!(dialer, Negotiation::Success("/bar")));
+        assert!(matches!(listener, Negotiation::Success("/bar")));
+    }
 }
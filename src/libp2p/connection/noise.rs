@@ -39,6 +39,9 @@
 //! both the static and ephemeral keys, which is then used to encrypt communications. Note that
 //! the libp2p key isn't used in the key derivation.
 //!
+//! The libp2p key of the local node is always an Ed25519 key, while the remote's libp2p key can
+//! use any of the algorithms supported by [`PublicKey`].
+//!
 //! # Usage
 //!
 //! While this is out of scope of this module, the noise protocol must typically first be
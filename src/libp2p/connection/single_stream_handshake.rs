@@ -20,11 +20,13 @@
 //! A connection handshake consists of three steps:
 //!
 //! - A multistream-select negotiation to negotiate the encryption protocol. Only the noise
-//! protocol is supported at the moment.
+//!   protocol is supported at the moment.
 //! - A noise protocol handshake, where public keys are exchanged and symmetric encryption is
-//! initialized.
-//! - A multistream-select negotiation to negotiate the Yamux protocol. Only the Yamux protocol is
-//! supported at the moment. This negotiation is performed on top of the noise cipher.
+//!   initialized.
+//! - A multistream-select negotiation to negotiate the multiplexing protocol, either Yamux or
+//!   Mplex. This negotiation is performed on top of the noise cipher. The dialing side requests
+//!   the protocols one by one in the order of preference found in [`Config`], until the remote
+//!   accepts one.
 //!
 //! This entire handshake requires in total either three or five TCP packets (not including the
 //! TCP handshake), depending on the strategy used for the multistream-select protocol.
@@ -36,12 +38,12 @@ use super::{
     super::peer_id::PeerId,
     super::read_write::ReadWrite,
     established::ConnectionPrototype,
-    multistream_select,
+    mplex, multistream_select,
     noise::{self, NoiseKey},
     yamux,
 };
 
-use alloc::{boxed::Box, vec};
+use alloc::{boxed::Box, vec, vec::Vec};
 use core::{fmt, iter};
 
 mod tests;
@@ -64,12 +66,52 @@ pub enum Handshake {
 }
 
 impl Handshake {
+    /// Shortcut for [`HealthyHandshake::new`] wrapped in a [`Handshake`].
+    pub fn new(config: Config) -> Self {
+        HealthyHandshake::new(config).into()
+    }
+
     /// Shortcut for [`HealthyHandshake::noise_yamux`] wrapped in a [`Handshake`].
     pub fn noise_yamux(is_initiator: bool) -> Self {
         HealthyHandshake::noise_yamux(is_initiator).into()
     }
 }
 
+/// Configuration of a connection handshake.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `true` if the connection has been opened by the local machine, or `false` if it has been
+    /// opened by the remote.
+    pub is_initiator: bool,
+
+    /// List of multiplexing protocols supported by the local node, in decreasing order of
+    /// preference.
+    ///
+    /// If the local node is the initiator, the protocols are requested one after the other
+    /// until the remote accepts one. Otherwise, the remote can pick any protocol of this list.
+    ///
+    /// Must not be empty.
+    pub multiplexing_protocols: Vec<MultiplexingProtocol>,
+}
+
+/// Multiplexing protocol that can be negotiated after the encryption layer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MultiplexingProtocol {
+    /// Yamux protocol. See the [`yamux`] module.
+    Yamux,
+    /// Mplex protocol. See the [`mplex`] module.
+    Mplex,
+}
+
+impl AsRef<str> for MultiplexingProtocol {
+    fn as_ref(&self) -> &str {
+        match self {
+            MultiplexingProtocol::Yamux => yamux::PROTOCOL_NAME,
+            MultiplexingProtocol::Mplex => mplex::PROTOCOL_NAME,
+        }
+    }
+}
+
 /// Connection handshake in progress.
 pub struct HealthyHandshake {
     state: NegotiationState,
@@ -79,24 +121,37 @@ enum NegotiationState {
     EncryptionProtocol {
         negotiation: multistream_select::InProgress<iter::Once<&'static str>, &'static str>,
         is_initiator: bool,
+        multiplexing_protocols: Vec<MultiplexingProtocol>,
     },
     Encryption {
         handshake: Box<noise::HandshakeInProgress>,
+        multiplexing_protocols: Vec<MultiplexingProtocol>,
     },
     Multiplexing {
         peer_id: PeerId,
         encryption: Box<noise::Noise>,
-        negotiation: multistream_select::InProgress<iter::Once<&'static str>, &'static str>,
+        negotiation: multistream_select::InProgress<
+            vec::IntoIter<MultiplexingProtocol>,
+            MultiplexingProtocol,
+        >,
+        /// Protocols to request if the remote refuses the one currently being negotiated.
+        /// Always empty if the local node isn't the initiator.
+        fallback_protocols: vec::IntoIter<MultiplexingProtocol>,
     },
 }
 
 impl HealthyHandshake {
-    /// Initializes a new state machine for a Noise + Yamux handshake.
+    /// Initializes a new state machine for a Noise handshake followed with the negotiation of
+    /// one of the multiplexing protocols of the [`Config`].
     ///
-    /// Must pass `true` if the connection has been opened by the local machine, or `false` if it
-    /// has been opened by the remote.
-    pub fn noise_yamux(is_initiator: bool) -> Self {
-        let negotiation = multistream_select::InProgress::new(if is_initiator {
+    /// # Panic
+    ///
+    /// Panics if [`Config::multiplexing_protocols`] is empty.
+    ///
+    pub fn new(config: Config) -> Self {
+        assert!(!config.multiplexing_protocols.is_empty());
+
+        let negotiation = multistream_select::InProgress::new(if config.is_initiator {
             multistream_select::Config::Dialer {
                 requested_protocol: noise::PROTOCOL_NAME,
             }
@@ -109,11 +164,23 @@ impl HealthyHandshake {
         HealthyHandshake {
             state: NegotiationState::EncryptionProtocol {
                 negotiation,
-                is_initiator,
+                is_initiator: config.is_initiator,
+                multiplexing_protocols: config.multiplexing_protocols,
             },
         }
     }
 
+    /// Initializes a new state machine for a Noise + Yamux handshake.
+    ///
+    /// Must pass `true` if the connection has been opened by the local machine, or `false` if it
+    /// has been opened by the remote.
+    pub fn noise_yamux(is_initiator: bool) -> Self {
+        Self::new(Config {
+            is_initiator,
+            multiplexing_protocols: vec![MultiplexingProtocol::Yamux],
+        })
+    }
+
     /// Feeds data coming from a socket and writes back data to send up.
     ///
     /// On success, returns the new state of the negotiation.
@@ -129,6 +196,7 @@ impl HealthyHandshake {
                 NegotiationState::EncryptionProtocol {
                     negotiation,
                     is_initiator,
+                    multiplexing_protocols,
                 } => {
                     // Earliest point of the handshake. The encryption is being negotiated.
                     // Delegating read/write to the negotiation.
@@ -142,6 +210,7 @@ impl HealthyHandshake {
                                 state: NegotiationState::EncryptionProtocol {
                                     negotiation: updated,
                                     is_initiator,
+                                    multiplexing_protocols,
                                 },
                             }))
                         }
@@ -150,6 +219,7 @@ impl HealthyHandshake {
                             // continue. This Noise key is requested from the user.
                             Ok(Handshake::NoiseKeyRequired(NoiseKeyRequired {
                                 is_initiator,
+                                multiplexing_protocols,
                             }))
                         }
                         multistream_select::Negotiation::NotAvailable => {
@@ -158,7 +228,10 @@ impl HealthyHandshake {
                     };
                 }
 
-                NegotiationState::Encryption { handshake } => {
+                NegotiationState::Encryption {
+                    handshake,
+                    multiplexing_protocols,
+                } => {
                     // Delegating read/write to the Noise handshake state machine.
                     let updated = handshake.read_write(read_write).map_err(|err| {
                         debug_assert!(!matches!(err, noise::HandshakeError::WriteClosed));
@@ -172,21 +245,27 @@ impl HealthyHandshake {
                         } => {
                             // Encryption layer has been successfully negotiated. Start the
                             // handshake for the multiplexing protocol negotiation.
-                            let negotiation =
-                                multistream_select::InProgress::new(if cipher.is_initiator() {
-                                    multistream_select::Config::Dialer {
-                                        requested_protocol: yamux::PROTOCOL_NAME,
-                                    }
-                                } else {
+                            let (negotiation, fallback_protocols) = if cipher.is_initiator() {
+                                let mut protocols = multiplexing_protocols.into_iter();
+                                let requested_protocol = protocols.next().unwrap();
+                                let negotiation = multistream_select::InProgress::new(
+                                    multistream_select::Config::Dialer { requested_protocol },
+                                );
+                                (negotiation, protocols)
+                            } else {
+                                let negotiation = multistream_select::InProgress::new(
                                     multistream_select::Config::Listener {
-                                        supported_protocols: iter::once(yamux::PROTOCOL_NAME),
-                                    }
-                                });
+                                        supported_protocols: multiplexing_protocols.into_iter(),
+                                    },
+                                );
+                                (negotiation, Vec::new().into_iter())
+                            };
 
                             self.state = NegotiationState::Multiplexing {
                                 peer_id: remote_peer_id,
                                 encryption: Box::new(cipher),
                                 negotiation,
+                                fallback_protocols,
                             };
 
                             continue;
@@ -195,6 +274,7 @@ impl HealthyHandshake {
                             return Ok(Handshake::Healthy(HealthyHandshake {
                                 state: NegotiationState::Encryption {
                                     handshake: Box::new(updated),
+                                    multiplexing_protocols,
                                 },
                             }));
                         }
@@ -205,6 +285,7 @@ impl HealthyHandshake {
                     negotiation,
                     mut encryption,
                     peer_id,
+                    mut fallback_protocols,
                 } => {
                     // During the multiplexing protocol negotiation, all exchanges have to go
                     // through the Noise cipher.
@@ -283,14 +364,38 @@ impl HealthyHandshake {
                                     negotiation: updated,
                                     encryption,
                                     peer_id,
+                                    fallback_protocols,
                                 },
                             }))
                         }
-                        multistream_select::Negotiation::Success(_) => Ok(Handshake::Success {
-                            connection: ConnectionPrototype::from_noise_yamux(*encryption),
-                            remote_peer_id: peer_id,
-                        }),
+                        multistream_select::Negotiation::Success(protocol) => {
+                            Ok(Handshake::Success {
+                                connection: match protocol {
+                                    MultiplexingProtocol::Yamux => {
+                                        ConnectionPrototype::from_noise_yamux(*encryption)
+                                    }
+                                    MultiplexingProtocol::Mplex => {
+                                        ConnectionPrototype::from_noise_mplex(*encryption)
+                                    }
+                                },
+                                remote_peer_id: peer_id,
+                            })
+                        }
                         multistream_select::Negotiation::NotAvailable => {
+                            // The remote has refused the requested protocol. Request the next
+                            // one, if any.
+                            if let Some(requested_protocol) = fallback_protocols.next() {
+                                self.state = NegotiationState::Multiplexing {
+                                    negotiation: multistream_select::InProgress::new_dialer_retry(
+                                        requested_protocol,
+                                    ),
+                                    encryption,
+                                    peer_id,
+                                    fallback_protocols,
+                                };
+                                continue;
+                            }
+
                             Err(HandshakeError::NoMultiplexingProtocol)
                         }
                     };
@@ -310,6 +415,7 @@ impl fmt::Debug for HealthyHandshake {
 /// key in order to proceed.
 pub struct NoiseKeyRequired {
     is_initiator: bool,
+    multiplexing_protocols: Vec<MultiplexingProtocol>,
 }
 
 impl NoiseKeyRequired {
@@ -322,6 +428,7 @@ impl NoiseKeyRequired {
                     is_initiator: self.is_initiator,
                     prologue: &[],
                 })),
+                multiplexing_protocols: self.multiplexing_protocols,
             },
         }
     }
@@ -17,7 +17,9 @@
 
 #![cfg(test)]
 
-use super::{super::super::read_write::ReadWrite, Handshake, NoiseKey};
+use super::{
+    super::super::read_write::ReadWrite, Config, Handshake, MultiplexingProtocol, NoiseKey,
+};
 
 #[test]
 fn handshake_basic_works() {
@@ -119,3 +121,70 @@ fn handshake_basic_works() {
     //test_with_buffer_sizes(1, 2048);
     //test_with_buffer_sizes(2048, 1);
 }
+
+#[test]
+fn multiplexing_falls_back_to_next_protocol() {
+    fn step(
+        handshake: Handshake,
+        key: &NoiseKey,
+        incoming: &mut Vec<u8>,
+        outgoing: &mut Vec<u8>,
+    ) -> Handshake {
+        match handshake {
+            Handshake::Success { .. } => handshake,
+            Handshake::NoiseKeyRequired(req) => req.resume(key).into(),
+            Handshake::Healthy(nego) => {
+                let mut out_buffer = vec![0; 256];
+                let mut read_write = ReadWrite {
+                    now: 0,
+                    incoming_buffer: Some(incoming),
+                    outgoing_buffer: Some((&mut out_buffer, &mut [])),
+                    read_bytes: 0,
+                    written_bytes: 0,
+                    wake_up_after: None,
+                };
+                let handshake = nego.read_write(&mut read_write).unwrap();
+                let (read_bytes, written_bytes) = (read_write.read_bytes, read_write.written_bytes);
+                incoming.drain(..read_bytes);
+                outgoing.extend_from_slice(&out_buffer[..written_bytes]);
+                handshake
+            }
+        }
+    }
+
+    let key1 = NoiseKey::new(&rand::random());
+    let key2 = NoiseKey::new(&rand::random());
+
+    // The dialer prefers Yamux, but the listener only supports Mplex.
+    let mut handshake1 = Handshake::new(Config {
+        is_initiator: true,
+        multiplexing_protocols: vec![MultiplexingProtocol::Yamux, MultiplexingProtocol::Mplex],
+    });
+    let mut handshake2 = Handshake::new(Config {
+        is_initiator: false,
+        multiplexing_protocols: vec![MultiplexingProtocol::Mplex],
+    });
+
+    let mut buf_1_to_2 = Vec::new();
+    let mut buf_2_to_1 = Vec::new();
+
+    while !matches!(
+        (&handshake1, &handshake2),
+        (Handshake::Success { .. }, Handshake::Success { .. })
+    ) {
+        handshake1 = step(handshake1, &key1, &mut buf_2_to_1, &mut buf_1_to_2);
+        handshake2 = step(handshake2, &key2, &mut buf_1_to_2, &mut buf_2_to_1);
+    }
+
+    for handshake in [handshake1, handshake2] {
+        match handshake {
+            Handshake::Success { connection, .. } => {
+                assert_eq!(
+                    connection.multiplexing_protocol(),
+                    MultiplexingProtocol::Mplex
+                )
+            }
+            _ => unreachable!(),
+        }
+    }
+}
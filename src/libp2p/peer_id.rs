@@ -22,6 +22,9 @@ use sha2::Digest as _;
 use super::multihash;
 use crate::util::protobuf;
 
+mod ecdsa;
+mod rsa;
+
 /// Public key of a node's identity.
 ///
 /// Libp2p specifies multiple different possible algorithms, but only Ed25519 support is
@@ -30,6 +33,13 @@ use crate::util::protobuf;
 pub enum PublicKey {
     /// An Ed25519 public key.
     Ed25519([u8; 32]),
+    /// A Secp256k1 public key, in its 33 bytes compressed form.
+    Secp256k1([u8; 33]),
+    /// An ECDSA public key on the NIST P-256 curve, as a DER-encoded `SubjectPublicKeyInfo`
+    /// structure.
+    Ecdsa(Vec<u8>),
+    /// An RSA public key, as a DER-encoded `SubjectPublicKeyInfo` structure.
+    Rsa(Vec<u8>),
 }
 
 impl PublicKey {
@@ -40,20 +50,24 @@ impl PublicKey {
     ///
     /// See <https://github.com/libp2p/specs/blob/master/peer-ids/peer-ids.md#keys>.
     pub fn to_protobuf_encoding(&self) -> Vec<u8> {
-        match self {
-            PublicKey::Ed25519(key) => {
-                const CAPACITY: usize = 32 + 4;
-                let mut out = Vec::with_capacity(CAPACITY);
-                for slice in protobuf::enum_tag_encode(1, 1) {
-                    out.extend_from_slice(slice.as_ref());
-                }
-                for slice in protobuf::bytes_tag_encode(2, key) {
-                    out.extend_from_slice(slice.as_ref());
-                }
-                debug_assert_eq!(out.len(), CAPACITY);
-                out
-            }
+        let (key_type, key): (u64, &[u8]) = match self {
+            PublicKey::Rsa(key) => (0, key),
+            PublicKey::Ed25519(key) => (1, key),
+            PublicKey::Secp256k1(key) => (2, key),
+            PublicKey::Ecdsa(key) => (3, key),
+        };
+
+        // The key type always fits in one byte, and the length prefix of the key in three.
+        let capacity = key.len() + 6;
+        let mut out = Vec::with_capacity(capacity);
+        for slice in protobuf::enum_tag_encode(1, key_type) {
+            out.extend_from_slice(slice.as_ref());
         }
+        for slice in protobuf::bytes_tag_encode(2, key) {
+            out.extend_from_slice(slice.as_ref());
+        }
+        debug_assert!(out.len() <= capacity);
+        out
     }
 
     /// Decode a public key from a Protobuf structure, e.g. read from storage or received from
@@ -97,18 +111,30 @@ impl PublicKey {
                         protobuf::tag_decode,
                         |(field_num, _)| *field_num == 2,
                     )),
-                    nom::combinator::map_res(protobuf::bytes_tag_decode, |d| {
-                        <[u8; 32]>::try_from(d)
-                            .map_err(|_| FromProtobufEncodingError::BadEd25519Key)
-                    }),
+                    protobuf::bytes_tag_decode,
                 ),
             ))),
         );
 
-        match nom::Finish::finish(parser(bytes)) {
-            Ok((_, (1, key))) => Ok(PublicKey::Ed25519(key)),
-            Ok((_, (_, _))) => Err(FromProtobufEncodingError::UnsupportedAlgorithm),
-            Err(err) => Err(err.0),
+        let (key_type, key) = match nom::Finish::finish(parser(bytes)) {
+            Ok((_, out)) => out,
+            Err(err) => return Err(err.0),
+        };
+
+        match key_type {
+            0 if rsa::is_valid_public_key(key) => Ok(PublicKey::Rsa(key.to_vec())),
+            0 => Err(FromProtobufEncodingError::BadRsaKey),
+            1 => <[u8; 32]>::try_from(key)
+                .map(PublicKey::Ed25519)
+                .map_err(|_| FromProtobufEncodingError::BadEd25519Key),
+            2 => <[u8; 33]>::try_from(key)
+                .ok()
+                .filter(|key| libsecp256k1::PublicKey::parse_compressed(key).is_ok())
+                .map(PublicKey::Secp256k1)
+                .ok_or(FromProtobufEncodingError::BadSecp256k1Key),
+            3 if ecdsa::is_valid_public_key(key) => Ok(PublicKey::Ecdsa(key.to_vec())),
+            3 => Err(FromProtobufEncodingError::BadEcdsaKey),
+            _ => unreachable!(),
         }
     }
 
@@ -119,16 +145,49 @@ impl PublicKey {
 
     /// Verifies whether the given signature is valid for the given message using `self` as the
     /// public key.
+    ///
+    /// The format of the signature depends on the algorithm, as indicated in the libp2p
+    /// specification. In particular, Secp256k1, ECDSA and RSA signatures are performed on the
+    /// SHA-256 hash of the message.
     pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), SignatureVerifyFailed> {
-        let PublicKey::Ed25519(public_key) = self;
-        let public_key = ed25519_zebra::VerificationKey::try_from(*public_key)
-            .map_err(|_| SignatureVerifyFailed())?;
-        let signature =
-            ed25519_zebra::Signature::try_from(signature).map_err(|_| SignatureVerifyFailed())?;
-        public_key
-            .verify(&signature, message)
-            .map_err(|_| SignatureVerifyFailed())?;
-        Ok(())
+        match self {
+            PublicKey::Ed25519(public_key) => {
+                let public_key = ed25519_zebra::VerificationKey::try_from(*public_key)
+                    .map_err(|_| SignatureVerifyFailed())?;
+                let signature = ed25519_zebra::Signature::try_from(signature)
+                    .map_err(|_| SignatureVerifyFailed())?;
+                public_key
+                    .verify(&signature, message)
+                    .map_err(|_| SignatureVerifyFailed())?;
+                Ok(())
+            }
+            PublicKey::Secp256k1(public_key) => {
+                let public_key = libsecp256k1::PublicKey::parse_compressed(public_key)
+                    .map_err(|_| SignatureVerifyFailed())?;
+                let signature = libsecp256k1::Signature::parse_der(signature)
+                    .map_err(|_| SignatureVerifyFailed())?;
+                let message = libsecp256k1::Message::parse(&sha2::Sha256::digest(message).into());
+                if libsecp256k1::verify(&message, &signature, &public_key) {
+                    Ok(())
+                } else {
+                    Err(SignatureVerifyFailed())
+                }
+            }
+            PublicKey::Ecdsa(public_key) => {
+                if ecdsa::verify(public_key, message, signature) {
+                    Ok(())
+                } else {
+                    Err(SignatureVerifyFailed())
+                }
+            }
+            PublicKey::Rsa(public_key) => {
+                if rsa::verify(public_key, message, signature) {
+                    Ok(())
+                } else {
+                    Err(SignatureVerifyFailed())
+                }
+            }
+        }
     }
 }
 
@@ -141,8 +200,12 @@ pub enum FromProtobufEncodingError {
     UnknownAlgorithm,
     /// Ed25519 key doesn't have a correct length.
     BadEd25519Key,
-    /// Algorithms other than Ed25519 aren't supported.
-    UnsupportedAlgorithm,
+    /// Secp256k1 key isn't a valid compressed public key.
+    BadSecp256k1Key,
+    /// ECDSA key isn't a valid DER-encoded P-256 public key.
+    BadEcdsaKey,
+    /// RSA key isn't a valid DER-encoded public key, or is too large.
+    BadRsaKey,
 }
 
 /// Call to [`PublicKey::verify`] has failed. No reason is provided for security reasons.
@@ -176,7 +239,7 @@ impl PeerId {
         } else {
             let mut out = Vec::with_capacity(34);
             out.push(0x12);
-            out.push(0x32);
+            out.push(0x20);
 
             let mut hasher = sha2::Sha256::new();
             hasher.update(&key_enc);
@@ -353,4 +416,104 @@ mod tests {
             pub_key
         );
     }
+
+    // Test vectors below are taken from
+    // <https://github.com/libp2p/specs/blob/master/peer-ids/peer-ids.md#test-vectors>.
+
+    #[test]
+    fn spec_ed25519_vector() {
+        let encoded =
+            hex::decode("080112201ed1e8fae2c4a144b8be8fd4b47bf3d3b34b871c3cacf6010f0e42d474fce27e")
+                .unwrap();
+        let key = super::PublicKey::from_protobuf_encoding(&encoded).unwrap();
+        assert!(matches!(key, super::PublicKey::Ed25519(_)));
+        assert_eq!(key.to_protobuf_encoding(), encoded);
+    }
+
+    #[test]
+    fn spec_secp256k1_vector() {
+        let encoded = hex::decode(
+            "08021221037777e994e452c21604f91de093ce415f5432f701dd8cd1a7a6fea0e630bfca99",
+        )
+        .unwrap();
+        let key = super::PublicKey::from_protobuf_encoding(&encoded).unwrap();
+        assert!(matches!(key, super::PublicKey::Secp256k1(_)));
+        assert_eq!(key.to_protobuf_encoding(), encoded);
+    }
+
+    #[test]
+    fn spec_ecdsa_vector() {
+        let encoded = hex::decode(
+            "0803125b3059301306072a8648ce3d020106082a8648ce3d03010703420004de3d300fa36ae0e8f5d530899d83abab44abf3161f162a4bc901d8e6ecda020e8b6d5f8da30525e71d6851510c098e5c47c646a597fb4dcec034e9f77c409e62",
+        )
+        .unwrap();
+        let key = super::PublicKey::from_protobuf_encoding(&encoded).unwrap();
+        assert!(matches!(key, super::PublicKey::Ecdsa(_)));
+        assert_eq!(key.to_protobuf_encoding(), encoded);
+        // Keys longer than 42 bytes are hashed in order to obtain the `PeerId`.
+        assert_eq!(key.into_peer_id().as_bytes()[..2], [0x12, 0x20]);
+    }
+
+    #[test]
+    fn secp256k1_signature() {
+        // Key of `spec_secp256k1_vector`.
+        let key = super::PublicKey::from_protobuf_encoding(
+            &hex::decode(
+                "08021221037777e994e452c21604f91de093ce415f5432f701dd8cd1a7a6fea0e630bfca99",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let signature = hex::decode("304502207f859faccab238971c40f8fb2b8578853f0a8870ad2f959083467d0de845f8ab0221008069535ec6c02341467d791733700a0ae7e4254c94fdb3c232af20cd1eed15ea").unwrap();
+        assert!(key.verify(b"hello world", &signature).is_ok());
+        assert!(key.verify(b"hello world!", &signature).is_err());
+    }
+
+    #[test]
+    fn ecdsa_signature() {
+        // Key of `spec_ecdsa_vector`.
+        let key = super::PublicKey::from_protobuf_encoding(&hex::decode("0803125b3059301306072a8648ce3d020106082a8648ce3d03010703420004de3d300fa36ae0e8f5d530899d83abab44abf3161f162a4bc901d8e6ecda020e8b6d5f8da30525e71d6851510c098e5c47c646a597fb4dcec034e9f77c409e62").unwrap()).unwrap();
+        let signature = hex::decode("30440220486990fe846b0a08db981f95bfd6defa9dacba7e63f2aa080861eda12068f7be022007c30f8e2f007bd2af86f4d2134603c5cc59d0dfb4cc24a0fab59c0f9e9f7808").unwrap();
+        assert!(key.verify(b"hello world", &signature).is_ok());
+        assert!(key.verify(b"hello world!", &signature).is_err());
+    }
+
+    #[test]
+    fn rsa_signature() {
+        let encoded = hex::decode("080012a60230820122300d06092a864886f70d01010105000382010f003082010a0282010100ae840401ef92dfc07bde29786b5c13377e178d643b07947f114edd41798867f56c8b92c9b1f1e8460bcf072468f5d9fc99fe1c913e8d2810dcf79301ab314d0011be8da0ea0094e54f6b18310661e1dda29bfe988fa199c7b7a16ee607056e99097c65e6d9347bdb08c04061bcd30976733281d98103a81059016278de6f6e5e45a4ab8490d96d840f68995dc385b9137ef6a33fd98ffba3637901610c7c51328986344b0534e70aa376ea78512380f26746e245d12729325090784212ee16150b11dbcfb5e9b4b1348ae9fcfc7a2e7ed93c8fb435761724c86732bbe21867a4c1a0c91e67cdc808df06fd9a468d13a9ecf799e24c8de1fde75f96879af1b5950203010001").unwrap();
+        let key = super::PublicKey::from_protobuf_encoding(&encoded).unwrap();
+        assert!(matches!(key, super::PublicKey::Rsa(_)));
+        assert_eq!(key.to_protobuf_encoding(), encoded);
+
+        let signature = hex::decode("4b7193870c662f226d7fffc2179c90a94c022ce5958e48e72b9e7a6317ebdf4a4d58796775c08131c10a2c0ff1757fb4d922de3b4ad40869454709572fc0b8b41c3c8f25a3e442b76e7496a4303c8df02abec72be6d6dcb292892ba759fa7d5803c423c45f72275ca0337913b1422df6bb5b994ed052e028c2ab5d1b6f6fa144365878d356eb35d63f75a9b3344e3935448a93a99acebbffbe20c0f349e6830f2c2c57b3e11f439077e6d3610c7662b1aa3142a90a829094c50efd2f7b1186417cbf8bdf8682296d11ffdd2704b40b851b22687e1b568fca6d7a03a50306b0e5e6f4ba633404d3e9ee21f1f3c1b5f3a85588c40ed158daf12da0716b2f99e18c").unwrap();
+        assert!(key.verify(b"hello world", &signature).is_ok());
+        assert!(key.verify(b"hello world!", &signature).is_err());
+    }
+
+    #[test]
+    fn bad_keys_rejected() {
+        // Secp256k1 key with an invalid prefix.
+        let mut encoded = hex::decode(
+            "08021221037777e994e452c21604f91de093ce415f5432f701dd8cd1a7a6fea0e630bfca99",
+        )
+        .unwrap();
+        encoded[4] = 0x05;
+        assert!(matches!(
+            super::PublicKey::from_protobuf_encoding(&encoded),
+            Err(super::FromProtobufEncodingError::BadSecp256k1Key)
+        ));
+
+        // ECDSA point that isn't on the curve.
+        let mut encoded = hex::decode("0803125b3059301306072a8648ce3d020106082a8648ce3d03010703420004de3d300fa36ae0e8f5d530899d83abab44abf3161f162a4bc901d8e6ecda020e8b6d5f8da30525e71d6851510c098e5c47c646a597fb4dcec034e9f77c409e62").unwrap();
+        *encoded.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            super::PublicKey::from_protobuf_encoding(&encoded),
+            Err(super::FromProtobufEncodingError::BadEcdsaKey)
+        ));
+
+        assert!(matches!(
+            super::PublicKey::from_protobuf_encoding(&[0x08, 0x00, 0x12, 0x01, 0x30]),
+            Err(super::FromProtobufEncodingError::BadRsaKey)
+        ));
+    }
 }
@@ -0,0 +1,43 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! ECDSA signatures verification on the NIST P-256 curve.
+//!
+//! As indicated in the libp2p specification, public keys are DER-encoded `SubjectPublicKeyInfo`
+//! structures, signatures are DER-encoded, and the message is hashed with SHA-256.
+
+use p256::{ecdsa::signature::Verifier as _, pkcs8::DecodePublicKey as _};
+
+/// Returns `true` if `public_key` is a valid DER-encoded P-256 public key.
+pub(super) fn is_valid_public_key(public_key: &[u8]) -> bool {
+    p256::ecdsa::VerifyingKey::from_public_key_der(public_key).is_ok()
+}
+
+/// Returns `true` if `signature` is a valid signature of `message` by `public_key`.
+pub(super) fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    let public_key = match p256::ecdsa::VerifyingKey::from_public_key_der(public_key) {
+        Ok(k) => k,
+        Err(_) => return false,
+    };
+
+    let signature = match p256::ecdsa::Signature::from_der(signature) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+
+    public_key.verify(message, &signature).is_ok()
+}
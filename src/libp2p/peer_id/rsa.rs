@@ -0,0 +1,101 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! RSA signatures verification.
+//!
+//! As indicated in the libp2p specification, public keys are DER-encoded `SubjectPublicKeyInfo`
+//! structures, and signatures use the RSASSA-PKCS1-v1_5 scheme with SHA-256.
+
+use rsa::{pkcs8::DecodePublicKey as _, signature::Verifier as _, traits::PublicKeyParts as _};
+
+/// Maximum size, in bits, of the modulus of the public keys that are accepted.
+///
+/// Verifying a signature gets more expensive as the modulus grows. Capping it prevents remotes
+/// from making the local node perform arbitrarily expensive computations.
+const MAX_MODULUS_BITS: usize = 4096;
+
+/// Maximum value of the public exponent of the public keys that are accepted.
+///
+/// Similar to [`MAX_MODULUS_BITS`], the cost of verifying a signature grows with the exponent.
+/// In practice, the public exponent is almost always 65537.
+const MAX_EXPONENT: u64 = 1 << 32;
+
+/// Returns `true` if `public_key` is a valid DER-encoded RSA public key.
+pub(super) fn is_valid_public_key(public_key: &[u8]) -> bool {
+    decode_public_key(public_key).is_some()
+}
+
+/// Returns `true` if `signature` is a valid signature of `message` by `public_key`.
+pub(super) fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    let public_key = match decode_public_key(public_key) {
+        Some(k) => k,
+        None => return false,
+    };
+
+    let signature = match rsa::pkcs1v15::Signature::try_from(signature) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+
+    rsa::pkcs1v15::VerifyingKey::<sha2::Sha256>::new(public_key)
+        .verify(message, &signature)
+        .is_ok()
+}
+
+/// Decodes a DER-encoded public key and checks that it is within the accepted bounds.
+fn decode_public_key(public_key: &[u8]) -> Option<rsa::RsaPublicKey> {
+    let public_key = rsa::RsaPublicKey::from_public_key_der(public_key).ok()?;
+    if public_key.n().bits() > MAX_MODULUS_BITS
+        || *public_key.e() > rsa::BigUint::from(MAX_EXPONENT)
+    {
+        return None;
+    }
+
+    Some(public_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use rsa::{pkcs8::EncodePublicKey as _, BigUint};
+
+    fn encode(modulus: &BigUint, exponent: u64) -> Vec<u8> {
+        rsa::RsaPublicKey::new_unchecked(modulus.clone(), BigUint::from(exponent))
+            .to_public_key_der()
+            .unwrap()
+            .into_vec()
+    }
+
+    #[test]
+    fn exponent_limit() {
+        let modulus = (BigUint::from(1u32) << 2047) + BigUint::from(1u32);
+        assert!(super::is_valid_public_key(&encode(&modulus, 3)));
+        assert!(super::is_valid_public_key(&encode(&modulus, 65537)));
+        assert!(super::is_valid_public_key(&encode(&modulus, (1 << 32) - 1)));
+        assert!(!super::is_valid_public_key(&encode(
+            &modulus,
+            (1 << 32) + 1
+        )));
+    }
+
+    #[test]
+    fn modulus_limit() {
+        let modulus = (BigUint::from(1u32) << 4095) + BigUint::from(1u32);
+        assert!(super::is_valid_public_key(&encode(&modulus, 65537)));
+        let modulus = (BigUint::from(1u32) << 4096) + BigUint::from(1u32);
+        assert!(!super::is_valid_public_key(&encode(&modulus, 65537)));
+    }
+}
@@ -60,8 +60,9 @@ use rand::{Rng as _, SeedableRng as _};
 pub use collection::{
     ConfigRequestResponse, ConfigRequestResponseIn, ConnectionId, ConnectionToCoordinator,
     CoordinatorToConnection, MultiStreamConnectionTask, MultiStreamHandshakeKind,
-    NotificationProtocolConfig, NotificationsInClosedErr, NotificationsOutErr, ReadWrite,
-    RequestError, SingleStreamConnectionTask, SingleStreamHandshakeKind, SubstreamId,
+    MultiplexingProtocol, NotificationProtocolConfig, NotificationsInClosedErr,
+    NotificationsOutErr, ReadWrite, RequestError, SingleStreamConnectionTask,
+    SingleStreamHandshakeKind, SubstreamId,
 };
 
 /// Configuration for a [`Peers`].
@@ -92,6 +93,12 @@ pub struct Config {
     /// Name of the ping protocol on the network.
     pub ping_protocol: String,
 
+    /// List of multiplexing protocols to try to negotiate on single-stream connections, in
+    /// decreasing order of preference.
+    ///
+    /// Must not be empty.
+    pub multiplexing_protocols: Vec<MultiplexingProtocol>,
+
     /// Amount of time after which a connection handshake is considered to have taken too long
     /// and must be aborted.
     pub handshake_timeout: Duration,
@@ -224,6 +231,7 @@ where
                 notification_protocols: config.notification_protocols,
                 request_response_protocols: config.request_response_protocols,
                 ping_protocol: config.ping_protocol,
+                multiplexing_protocols: config.multiplexing_protocols,
                 handshake_timeout: config.handshake_timeout,
                 randomness_seed: randomness.sample(rand::distributions::Standard),
             }),
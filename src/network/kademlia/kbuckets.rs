@@ -157,7 +157,13 @@ where
 
     /// Returns the list of entries in the k-buckets, ordered by increasing distance with the
     /// target.
-    pub fn closest_entries(&self, target: &K) -> impl Iterator<Item = (&K, &V)> {
+    ///
+    /// The target doesn't need to be of type `K`, as Kademlia also makes it possible to look for
+    /// the entries closest to arbitrary keys.
+    pub fn closest_entries(
+        &self,
+        target: &(impl AsRef<[u8]> + ?Sized),
+    ) -> impl Iterator<Item = (&K, &V)> {
         // TODO: this is extremely unoptimized
         let target_hashed = Key::new(target.as_ref());
         let mut list = self.iter_ordered().collect::<Vec<_>>();
@@ -27,6 +27,7 @@
 // Implementation note: each protocol goes into a different sub-module whose content is
 // re-exported here.
 
+mod authority_discovery;
 mod block_announces;
 mod block_request;
 mod grandpa;
@@ -35,7 +36,9 @@ mod identify;
 mod kademlia;
 mod state_request;
 mod storage_call_proof;
+mod transactions;
 
+pub use self::authority_discovery::*;
 pub use self::block_announces::*;
 pub use self::block_request::*;
 pub use self::grandpa::*;
@@ -44,3 +47,4 @@ pub use self::identify::*;
 pub use self::kademlia::*;
 pub use self::state_request::*;
 pub use self::storage_call_proof::*;
+pub use self::transactions::*;
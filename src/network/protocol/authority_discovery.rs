@@ -0,0 +1,276 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Authority discovery makes it possible to find the network addresses of the authorities of
+//! the chain (typically the validators) from their authority discovery public key.
+//!
+//! Each authority periodically stores in the Kademlia DHT a record containing its addresses,
+//! signed with its sr25519 authority discovery key. The key of this record in the DHT is the
+//! SHA-256 hash of the authority discovery public key. See [`authority_discovery_dht_key`].
+//!
+//! The value of the record is a [`build_signed_authority_record`]. Its content must first be
+//! built with [`build_authority_record`] and signed by the authority.
+//!
+//! The list of the authority discovery public keys of the current authorities can be obtained by
+//! calling the [`AUTHORITY_DISCOVERY_AUTHORITIES_FUNCTION`] runtime function, whose output can
+//! be decoded with [`decode_authority_discovery_authorities`].
+
+use crate::{
+    libp2p::{
+        peer_id::{FromProtobufEncodingError, PeerId, PublicKey},
+        Multiaddr,
+    },
+    util::protobuf,
+};
+
+use alloc::vec::Vec;
+use sha2::Digest as _;
+
+// See https://github.com/paritytech/substrate/blob/master/client/authority-discovery/src/worker/schema/dht-v2.proto
+// for the protobuf message format.
+
+/// Name of the runtime function that returns the authority discovery public keys of the current
+/// authorities. Its parameter is empty.
+pub const AUTHORITY_DISCOVERY_AUTHORITIES_FUNCTION: &str = "AuthorityDiscoveryApi_authorities";
+
+/// Decodes the output of a call to [`AUTHORITY_DISCOVERY_AUTHORITIES_FUNCTION`].
+pub fn decode_authority_discovery_authorities(
+    scale_encoded: &[u8],
+) -> Result<Vec<[u8; 32]>, DecodeAuthorityDiscoveryAuthoritiesError> {
+    let result: nom::IResult<_, _> = nom::combinator::all_consuming(nom::combinator::complete(
+        nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+            nom::multi::many_m_n(
+                num_elems,
+                num_elems,
+                nom::combinator::map(nom::bytes::complete::take(32u32), |b| {
+                    <[u8; 32]>::try_from(b).unwrap()
+                }),
+            )
+        }),
+    ))(scale_encoded);
+
+    match result {
+        Ok((_, authorities)) => Ok(authorities),
+        Err(_) => Err(DecodeAuthorityDiscoveryAuthoritiesError),
+    }
+}
+
+/// Error potentially returned by [`decode_authority_discovery_authorities`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed to decode the list of authorities")]
+pub struct DecodeAuthorityDiscoveryAuthoritiesError;
+
+/// Returns the key in the Kademlia DHT under which the addresses of the authority with the
+/// given authority discovery public key are stored.
+pub fn authority_discovery_dht_key(authority_public_key: &[u8; 32]) -> [u8; 32] {
+    sha2::Sha256::digest(authority_public_key).into()
+}
+
+/// Builds the record containing the given addresses.
+///
+/// The returned bytes must be signed with the authority discovery key of the authority, then
+/// passed to [`build_signed_authority_record`].
+///
+/// The addresses are expected to end with `/p2p/` followed with the identity of the node of the
+/// authority, as otherwise remotes will be unable to connect to them.
+pub fn build_authority_record<'a>(addresses: impl Iterator<Item = &'a Multiaddr>) -> Vec<u8> {
+    addresses
+        .flat_map(|addr| protobuf::bytes_tag_encode(1, addr))
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        })
+}
+
+/// Builds the value to store in the Kademlia DHT from a record built with
+/// [`build_authority_record`] and its sr25519 signature by the authority discovery key of the
+/// authority.
+pub fn build_signed_authority_record(record: &[u8], auth_signature: &[u8; 64]) -> Vec<u8> {
+    protobuf::bytes_tag_encode(1, record)
+        .chain(protobuf::bytes_tag_encode(2, &auth_signature[..]))
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        })
+}
+
+/// Decodes a value found in the Kademlia DHT under the key returned by
+/// [`authority_discovery_dht_key`], and verifies that it has been signed by the given authority.
+pub fn decode_signed_authority_record(
+    signed_record: &[u8],
+    authority_public_key: &[u8; 32],
+) -> Result<AuthorityRecord, DecodeSignedAuthorityRecordError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[required] record = 1 => protobuf::bytes_tag_decode,
+            #[required] auth_signature = 2 => protobuf::bytes_tag_decode,
+            #[optional] peer_signature = 3 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] signature = 1 => protobuf::bytes_tag_decode,
+                #[required] public_key = 2 => protobuf::bytes_tag_decode,
+            }),
+        }),
+    );
+
+    let signed_record = match nom::Finish::finish(parser(signed_record)) {
+        Ok((_, out)) => out,
+        Err(_) => return Err(DecodeSignedAuthorityRecordError::ProtobufDecode),
+    };
+
+    // Authority discovery keys are sr25519 keys, and signatures use the same signing context as
+    // all the other Substrate keys.
+    let auth_signature_valid = match (
+        schnorrkel::Signature::from_bytes(signed_record.auth_signature),
+        schnorrkel::PublicKey::from_bytes(authority_public_key),
+    ) {
+        (Ok(signature), Ok(public_key)) => public_key
+            .verify_simple(b"substrate", signed_record.record, &signature)
+            .is_ok(),
+        _ => false,
+    };
+    if !auth_signature_valid {
+        return Err(DecodeSignedAuthorityRecordError::BadAuthoritySignature);
+    }
+
+    // The peer signature is optional. If present, it is a signature of the record by the libp2p
+    // identity of the node of the authority.
+    let peer_id = if let Some(peer_signature) = signed_record.peer_signature {
+        let public_key = PublicKey::from_protobuf_encoding(peer_signature.public_key)
+            .map_err(DecodeSignedAuthorityRecordError::InvalidPeerPublicKey)?;
+        public_key
+            .verify(signed_record.record, peer_signature.signature)
+            .map_err(|_| DecodeSignedAuthorityRecordError::BadPeerSignature)?;
+        Some(public_key.into_peer_id())
+    } else {
+        None
+    };
+
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[repeated(max = 1024)] addresses = 1 => protobuf::bytes_tag_decode,
+        }),
+    );
+
+    let record = match nom::Finish::finish(parser(signed_record.record)) {
+        Ok((_, out)) => out,
+        Err(_) => return Err(DecodeSignedAuthorityRecordError::ProtobufDecode),
+    };
+
+    Ok(AuthorityRecord {
+        addresses: record
+            .addresses
+            .into_iter()
+            .map(|a| Multiaddr::try_from(a.to_vec()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| DecodeSignedAuthorityRecordError::InvalidMultiaddr)?,
+        peer_id,
+    })
+}
+
+/// Decoded and verified record published by an authority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorityRecord {
+    /// Addresses of the node of the authority, normally ending with `/p2p/` followed with the
+    /// identity of the node.
+    pub addresses: Vec<Multiaddr>,
+    /// Identity of the node of the authority, if the record has also been signed by it.
+    pub peer_id: Option<PeerId>,
+}
+
+/// Error potentially returned by [`decode_signed_authority_record`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeSignedAuthorityRecordError {
+    /// Error while decoding the Protobuf encoding.
+    ProtobufDecode,
+    /// Record hasn't been signed by the authority.
+    BadAuthoritySignature,
+    /// Failed to decode the public key of the node that has signed the record.
+    #[display(fmt = "Failed to decode peer public key: {}", _0)]
+    InvalidPeerPublicKey(FromProtobufEncodingError),
+    /// Invalid signature of the record by the node of the authority.
+    BadPeerSignature,
+    /// Couldn't decode one of the multiaddresses.
+    InvalidMultiaddr,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::libp2p::Multiaddr;
+
+    #[test]
+    fn dht_key() {
+        // Key of Alice in the development chains.
+        let public_key: [u8; 32] =
+            hex::decode("d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d")
+                .unwrap()
+                .try_into()
+                .unwrap();
+        assert_eq!(
+            super::authority_discovery_dht_key(&public_key).to_vec(),
+            hex::decode("46208798c80be6531c6a6454312db7d1505962376b3cca16cd18f910d6fea85e")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn authorities_decode() {
+        let mut encoded = vec![8];
+        encoded.extend_from_slice(&[1; 32]);
+        encoded.extend_from_slice(&[2; 32]);
+        assert_eq!(
+            super::decode_authority_discovery_authorities(&encoded).unwrap(),
+            vec![[1; 32], [2; 32]]
+        );
+
+        encoded.push(0);
+        assert!(super::decode_authority_discovery_authorities(&encoded).is_err());
+    }
+
+    #[test]
+    fn record_encode_decode() {
+        let keypair = schnorrkel::MiniSecretKey::from_bytes(&[7; 32])
+            .unwrap()
+            .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519);
+        let public_key = keypair.public.to_bytes();
+
+        let addresses = vec![
+            "/ip4/1.2.3.4/tcp/30333".parse::<Multiaddr>().unwrap(),
+            "/dns/example.com/tcp/30333/ws"
+                .parse::<Multiaddr>()
+                .unwrap(),
+        ];
+
+        let record = super::build_authority_record(addresses.iter());
+        let signature = keypair
+            .sign(schnorrkel::signing_context(b"substrate").bytes(&record))
+            .to_bytes();
+        let signed_record = super::build_signed_authority_record(&record, &signature);
+
+        let decoded = super::decode_signed_authority_record(&signed_record, &public_key).unwrap();
+        assert_eq!(decoded.addresses, addresses);
+        assert!(decoded.peer_id.is_none());
+
+        let other_public_key = schnorrkel::MiniSecretKey::from_bytes(&[8; 32])
+            .unwrap()
+            .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519)
+            .public
+            .to_bytes();
+        assert!(matches!(
+            super::decode_signed_authority_record(&signed_record, &other_public_key),
+            Err(super::DecodeSignedAuthorityRecordError::BadAuthoritySignature)
+        ));
+    }
+}
@@ -42,9 +42,107 @@ impl<'a> GrandpaNotificationRef<'a> {
         block_number_bytes: usize,
     ) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone {
         match self {
-            GrandpaNotificationRef::Neighbor(n) => iter::once(either::Left(&[2u8]))
-                .chain(n.scale_encoding(block_number_bytes).map(either::Right)),
-            _ => todo!(),
+            GrandpaNotificationRef::Neighbor(n) => either::Left(
+                iter::once(either::Left(&[2u8]))
+                    .chain(n.scale_encoding(block_number_bytes).map(either::Right))
+                    .map(either::Left),
+            ),
+            GrandpaNotificationRef::Vote(vote) => {
+                let mut out = Vec::with_capacity(1 + 8 + 8 + 1 + 32 + block_number_bytes + 64 + 32);
+                out.push(0u8);
+                out.extend_from_slice(&vote.round_number.to_le_bytes());
+                out.extend_from_slice(&vote.set_id.to_le_bytes());
+                let (message_ty, target_hash, target_number) = match &vote.message {
+                    MessageRef::Prevote(m) => (0u8, m.target_hash, m.target_number),
+                    MessageRef::Precommit(m) => (1u8, m.target_hash, m.target_number),
+                    MessageRef::PrimaryPropose(m) => (2u8, m.target_hash, m.target_number),
+                };
+                out.push(message_ty);
+                out.extend_from_slice(target_hash);
+                crate::util::encode_varsize_number_u64(&mut out, target_number, block_number_bytes);
+                out.extend_from_slice(vote.signature);
+                out.extend_from_slice(vote.authority_public_key);
+                either::Right(iter::once(either::Right(out)))
+            }
+            GrandpaNotificationRef::Commit(commit) => {
+                let mut out = Vec::new();
+                out.push(1u8);
+                out.extend_from_slice(&commit.round_number.to_le_bytes());
+                out.extend_from_slice(&commit.set_id.to_le_bytes());
+                out.extend_from_slice(commit.message.target_hash);
+                crate::util::encode_varsize_number_u64(
+                    &mut out,
+                    commit.message.target_number,
+                    block_number_bytes,
+                );
+                out.extend_from_slice(
+                    crate::util::encode_scale_compact_usize(commit.message.precommits.len())
+                        .as_ref(),
+                );
+                for precommit in &commit.message.precommits {
+                    out.extend_from_slice(precommit.target_hash);
+                    crate::util::encode_varsize_number_u64(
+                        &mut out,
+                        precommit.target_number,
+                        block_number_bytes,
+                    );
+                }
+                out.extend_from_slice(
+                    crate::util::encode_scale_compact_usize(commit.message.auth_data.len())
+                        .as_ref(),
+                );
+                for (signature, public_key) in &commit.message.auth_data {
+                    out.extend_from_slice(*signature);
+                    out.extend_from_slice(*public_key);
+                }
+                either::Right(iter::once(either::Right(out)))
+            }
+            GrandpaNotificationRef::CatchUpRequest(request) => {
+                let mut out = Vec::with_capacity(1 + 8 + 8);
+                out.push(3u8);
+                out.extend_from_slice(&request.round_number.to_le_bytes());
+                out.extend_from_slice(&request.set_id.to_le_bytes());
+                either::Right(iter::once(either::Right(out)))
+            }
+            GrandpaNotificationRef::CatchUp(catch_up) => {
+                let mut out = Vec::new();
+                out.push(4u8);
+                out.extend_from_slice(&catch_up.set_id.to_le_bytes());
+                out.extend_from_slice(&catch_up.round_number.to_le_bytes());
+                out.extend_from_slice(
+                    crate::util::encode_scale_compact_usize(catch_up.prevotes.len()).as_ref(),
+                );
+                for prevote in &catch_up.prevotes {
+                    out.extend_from_slice(prevote.target_hash);
+                    crate::util::encode_varsize_number_u64(
+                        &mut out,
+                        prevote.target_number,
+                        block_number_bytes,
+                    );
+                    out.extend_from_slice(prevote.signature);
+                    out.extend_from_slice(prevote.authority_public_key);
+                }
+                out.extend_from_slice(
+                    crate::util::encode_scale_compact_usize(catch_up.precommits.len()).as_ref(),
+                );
+                for precommit in &catch_up.precommits {
+                    out.extend_from_slice(precommit.target_hash);
+                    crate::util::encode_varsize_number_u64(
+                        &mut out,
+                        precommit.target_number,
+                        block_number_bytes,
+                    );
+                    out.extend_from_slice(precommit.signature);
+                    out.extend_from_slice(precommit.authority_public_key);
+                }
+                out.extend_from_slice(catch_up.base_hash);
+                crate::util::encode_varsize_number_u64(
+                    &mut out,
+                    catch_up.base_number,
+                    block_number_bytes,
+                );
+                either::Right(iter::once(either::Right(out)))
+            }
         }
     }
 }
@@ -443,4 +541,60 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn vote_encode_decode() {
+        let notification = super::GrandpaNotificationRef::Vote(super::VoteMessageRef {
+            round_number: 12,
+            set_id: 3,
+            message: super::MessageRef::Precommit(super::UnsignedPrecommitRef {
+                target_hash: &[5; 32],
+                target_number: 1234,
+            }),
+            signature: &[7; 64],
+            authority_public_key: &[9; 32],
+        });
+
+        let encoded = notification.scale_encoding(4).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        assert_eq!(
+            super::decode_grandpa_notification(&encoded, 4).unwrap(),
+            notification
+        );
+    }
+
+    #[test]
+    fn catch_up_encode_decode() {
+        let notification = super::GrandpaNotificationRef::CatchUp(super::CatchUpRef {
+            set_id: 3,
+            round_number: 12,
+            prevotes: vec![super::PrevoteRef {
+                target_hash: &[5; 32],
+                target_number: 1234,
+                signature: &[7; 64],
+                authority_public_key: &[9; 32],
+            }],
+            precommits: vec![super::PrecommitRef {
+                target_hash: &[6; 32],
+                target_number: 1233,
+                signature: &[8; 64],
+                authority_public_key: &[9; 32],
+            }],
+            base_hash: &[1; 32],
+            base_number: 1200,
+        });
+
+        let encoded = notification.scale_encoding(4).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        assert_eq!(
+            super::decode_grandpa_notification(&encoded, 4).unwrap(),
+            notification
+        );
+    }
 }
@@ -45,8 +45,8 @@ use alloc::vec::{self, Vec};
 pub struct IdentifyResponse<'a, TLaIter, TProtoIter> {
     pub protocol_version: &'a str,
     pub agent_version: &'a str,
-    /// Ed25519 public key of the local node.
-    pub ed25519_public_key: [u8; 32],
+    /// Public key of the identity of the local node.
+    pub public_key: PublicKey,
     /// List of addresses the local node is listening on. This should include first and foremost
     /// addresses that are publicly-reachable.
     pub listen_addrs: TLaIter,
@@ -78,13 +78,10 @@ pub fn build_identify_response<'a>(
                 .map(either::Left),
         )
         .chain(
-            protobuf::bytes_tag_encode(
-                1,
-                PublicKey::Ed25519(config.ed25519_public_key).to_protobuf_encoding(),
-            )
-            .map(either::Left)
-            .map(either::Right)
-            .map(either::Left),
+            protobuf::bytes_tag_encode(1, config.public_key.to_protobuf_encoding())
+                .map(either::Left)
+                .map(either::Right)
+                .map(either::Left),
         )
         .chain(
             config
@@ -119,7 +116,7 @@ pub fn decode_identify_response(
         nom::combinator::complete(protobuf::message_decode! {
             #[optional] protocol_version = 5 => protobuf::string_tag_decode,
             #[optional] agent_version = 6 => protobuf::string_tag_decode,
-            #[optional] ed25519_public_key = 1 => protobuf::bytes_tag_decode,
+            #[optional] public_key = 1 => protobuf::bytes_tag_decode,
             #[repeated(max = 1024)] listen_addrs = 2 => protobuf::bytes_tag_decode,
             #[optional] observed_addr = 4 => protobuf::bytes_tag_decode,
             #[repeated(max = 1024)] protocols = 3 => protobuf::string_tag_decode,
@@ -134,13 +131,8 @@ pub fn decode_identify_response(
     Ok(IdentifyResponse {
         agent_version: decoded.agent_version.unwrap_or_default(),
         protocol_version: decoded.protocol_version.unwrap_or_default(),
-        ed25519_public_key: match PublicKey::from_protobuf_encoding(
-            decoded.ed25519_public_key.unwrap_or_default(),
-        )
-        .map_err(DecodeIdentifyResponseError::InvalidPublicKey)?
-        {
-            PublicKey::Ed25519(key) => key,
-        },
+        public_key: PublicKey::from_protobuf_encoding(decoded.public_key.unwrap_or_default())
+            .map_err(DecodeIdentifyResponseError::InvalidPublicKey)?,
         listen_addrs: decoded
             .listen_addrs
             .into_iter()
@@ -39,6 +39,53 @@ pub fn build_find_node_request(peer_id: &[u8]) -> Vec<u8> {
     out
 }
 
+/// Decodes a request built using [`build_find_node_request`] and sent by a remote.
+///
+/// Returns the key whose closest nodes are requested. According to the specification, this key
+/// is always a peer ID, but no such verification is performed.
+pub fn decode_find_node_request(request_bytes: &[u8]) -> Result<&[u8], DecodeFindNodeRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] request_ty = 1 => protobuf::enum_tag_decode,
+            #[required] key = 2 => protobuf::bytes_tag_decode,
+        }),
+    );
+
+    match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, out)) if out.request_ty.unwrap_or(0) == 4 => Ok(out.key),
+        Ok((_, _)) => Err(DecodeFindNodeRequestError::BadRequestTy),
+        Err(_) => Err(DecodeFindNodeRequestError::ProtobufDecode(
+            ProtobufDecodeError,
+        )),
+    }
+}
+
+/// Builds a wire message to send back on the Kademlia request-response protocol in response to
+/// a request decoded with [`decode_find_node_request`].
+pub fn build_find_node_response(
+    closer_peers: &[(peer_id::PeerId, Vec<multiaddr::Multiaddr>)],
+) -> Vec<u8> {
+    // The capacity is arbitrary but large enough to avoid Vec reallocations in most situations.
+    let mut out = Vec::with_capacity(64 * (1 + closer_peers.len()));
+    for slice in protobuf::enum_tag_encode(1, 4) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    for (peer_id, addrs) in closer_peers {
+        let peer = protobuf::bytes_tag_encode(1, peer_id.as_bytes())
+            .map(either::Left)
+            .chain(
+                addrs
+                    .iter()
+                    .flat_map(|addr| protobuf::bytes_tag_encode(2, addr))
+                    .map(either::Right),
+            );
+        for slice in protobuf::message_tag_encode(8, peer) {
+            out.extend_from_slice(slice.as_ref());
+        }
+    }
+    out
+}
+
 /// Decodes a response to a request built using [`build_find_node_request`].
 // TODO: return a borrow of the response bytes ; we're limited by protobuf library
 pub fn decode_find_node_response(
@@ -64,15 +111,151 @@ pub fn decode_find_node_response(
         }
     };
 
-    let mut result = Vec::with_capacity(closer_peers.len());
-    for peer in closer_peers {
-        let peer_id = peer_id::PeerId::from_bytes(peer.peer_id.to_vec())
-            .map_err(|(err, _)| DecodeFindNodeResponseError::BadPeerId(err))?;
+    decode_peers(
+        closer_peers
+            .into_iter()
+            .map(|peer| (peer.peer_id, peer.addrs)),
+    )
+    .map_err(|err| match err {
+        DecodePeerError::BadPeerId(err) => DecodeFindNodeResponseError::BadPeerId(err),
+        DecodePeerError::BadMultiaddr(err) => DecodeFindNodeResponseError::BadMultiaddr(err),
+    })
+}
+
+/// Builds a wire message to send on the Kademlia request-response protocol to ask the target to
+/// return the record stored under the given key, if any.
+pub fn build_get_value_request(key: &[u8]) -> Vec<u8> {
+    // The capacity is arbitrary but large enough to avoid Vec reallocations.
+    let mut out = Vec::with_capacity(64 + key.len());
+    for slice in protobuf::enum_tag_encode(1, 1) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    for slice in protobuf::bytes_tag_encode(2, key) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    out
+}
+
+/// Decodes a response to a request built using [`build_get_value_request`].
+pub fn decode_get_value_response(
+    response_bytes: &[u8],
+) -> Result<GetValueResponse, DecodeGetValueResponseError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] response_ty = 1 => protobuf::enum_tag_decode,
+            #[optional] record = 3 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] key = 1 => protobuf::bytes_tag_decode,
+                #[optional] value = 2 => protobuf::bytes_tag_decode,
+            }),
+            #[repeated(max = 1024)] peers = 8 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] peer_id = 1 => protobuf::bytes_tag_decode,
+                #[repeated(max = 1024)] addrs = 2 => protobuf::bytes_tag_decode,
+            }),
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(response_bytes)) {
+        Ok((_, out)) if out.response_ty.unwrap_or(0) == 1 => out,
+        Ok((_, _)) => return Err(DecodeGetValueResponseError::BadResponseTy),
+        Err(_) => {
+            return Err(DecodeGetValueResponseError::ProtobufDecode(
+                ProtobufDecodeError,
+            ))
+        }
+    };
+
+    let closer_peers = decode_peers(
+        decoded
+            .peers
+            .into_iter()
+            .map(|peer| (peer.peer_id, peer.addrs)),
+    )
+    .map_err(|err| match err {
+        DecodePeerError::BadPeerId(err) => DecodeGetValueResponseError::BadPeerId(err),
+        DecodePeerError::BadMultiaddr(err) => DecodeGetValueResponseError::BadMultiaddr(err),
+    })?;
+
+    Ok(GetValueResponse {
+        record: decoded.record.map(|record| Record {
+            key: record.key.to_vec(),
+            value: record.value.unwrap_or(&[]).to_vec(),
+        }),
+        closer_peers,
+    })
+}
+
+/// Decoded response to a request built using [`build_get_value_request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetValueResponse {
+    /// Record found by the remote, if any.
+    ///
+    /// Note that the key of the record isn't verified to match the requested key.
+    pub record: Option<Record>,
+
+    /// Nodes that the remote considers to be closer to the requested key than itself.
+    pub closer_peers: Vec<(peer_id::PeerId, Vec<multiaddr::Multiaddr>)>,
+}
+
+/// Record stored in the Kademlia DHT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Key under which the record is stored.
+    pub key: Vec<u8>,
+    /// Opaque value of the record.
+    pub value: Vec<u8>,
+}
+
+/// Builds a wire message to send on the Kademlia request-response protocol to ask the target to
+/// store the given record.
+pub fn build_put_value_request(key: &[u8], value: &[u8]) -> Vec<u8> {
+    // The capacity is arbitrary but large enough to avoid Vec reallocations.
+    let mut out = Vec::with_capacity(64 + 2 * key.len() + value.len());
+    for slice in protobuf::enum_tag_encode(1, 0) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    for slice in protobuf::bytes_tag_encode(2, key) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    let record = protobuf::bytes_tag_encode(1, key).chain(protobuf::bytes_tag_encode(2, value));
+    for slice in protobuf::message_tag_encode(3, record) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    out
+}
+
+/// Decodes a response to a request built using [`build_put_value_request`].
+///
+/// The response to a put value request is, in principle, a copy of the request. Only its type is
+/// verified.
+pub fn decode_put_value_response(response_bytes: &[u8]) -> Result<(), DecodePutValueResponseError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] response_ty = 1 => protobuf::enum_tag_decode,
+        }),
+    );
+
+    match nom::Finish::finish(parser(response_bytes)) {
+        Ok((_, out)) if out.response_ty.unwrap_or(0) == 0 => Ok(()),
+        Ok((_, _)) => Err(DecodePutValueResponseError::BadResponseTy),
+        Err(_) => Err(DecodePutValueResponseError::ProtobufDecode(
+            ProtobufDecodeError,
+        )),
+    }
+}
 
-        let mut multiaddrs = Vec::with_capacity(peer.addrs.len());
-        for addr in peer.addrs {
+/// Parses the list of peers and addresses found in a response.
+fn decode_peers<'a>(
+    peers: impl ExactSizeIterator<Item = (&'a [u8], Vec<&'a [u8]>)>,
+) -> Result<Vec<(peer_id::PeerId, Vec<multiaddr::Multiaddr>)>, DecodePeerError> {
+    let mut result = Vec::with_capacity(peers.len());
+    for (peer_id, addrs) in peers {
+        let peer_id = peer_id::PeerId::from_bytes(peer_id.to_vec())
+            .map_err(|(err, _)| DecodePeerError::BadPeerId(err))?;
+
+        let mut multiaddrs = Vec::with_capacity(addrs.len());
+        for addr in addrs {
             let addr = multiaddr::Multiaddr::try_from(addr.to_vec())
-                .map_err(DecodeFindNodeResponseError::BadMultiaddr)?;
+                .map_err(DecodePeerError::BadMultiaddr)?;
             multiaddrs.push(addr);
         }
 
@@ -82,6 +265,22 @@ pub fn decode_find_node_response(
     Ok(result)
 }
 
+/// Error potentially returned by [`decode_peers`].
+enum DecodePeerError {
+    BadPeerId(peer_id::FromBytesError),
+    BadMultiaddr(multiaddr::FromVecError),
+}
+
+/// Error potentially returned by [`decode_find_node_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeFindNodeRequestError {
+    /// Error while decoding the Protobuf encoding.
+    #[display(fmt = "Error decoding the request: {}", _0)]
+    ProtobufDecode(ProtobufDecodeError),
+    /// Request isn't a find node request.
+    BadRequestTy,
+}
+
 /// Error potentially returned by [`decode_find_node_response`].
 #[derive(Debug, derive_more::Display)]
 pub enum DecodeFindNodeResponseError {
@@ -98,6 +297,142 @@ pub enum DecodeFindNodeResponseError {
     BadMultiaddr(multiaddr::FromVecError),
 }
 
+/// Error potentially returned by [`decode_get_value_response`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeGetValueResponseError {
+    /// Error while decoding the Protobuf encoding.
+    #[display(fmt = "Error decoding the response: {}", _0)]
+    ProtobufDecode(ProtobufDecodeError),
+    /// Response isn't a response to a get value request.
+    BadResponseTy,
+    /// Error while parsing a [`peer_id::PeerId`] in the response.
+    #[display(fmt = "Invalid PeerId: {}", _0)]
+    BadPeerId(peer_id::FromBytesError),
+    /// Error while parsing a [`multiaddr::Multiaddr`] in the response.
+    #[display(fmt = "Invalid multiaddress: {}", _0)]
+    BadMultiaddr(multiaddr::FromVecError),
+}
+
+/// Error potentially returned by [`decode_put_value_response`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodePutValueResponseError {
+    /// Error while decoding the Protobuf encoding.
+    #[display(fmt = "Error decoding the response: {}", _0)]
+    ProtobufDecode(ProtobufDecodeError),
+    /// Response isn't a response to a put value request.
+    BadResponseTy,
+}
+
 /// Error while decoding the Protobuf encoding.
 #[derive(Debug, derive_more::Display)]
 pub struct ProtobufDecodeError;
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        libp2p::{multiaddr, peer_id},
+        util::protobuf,
+    };
+
+    #[test]
+    fn request_encode_decode() {
+        let key = peer_id::PublicKey::Ed25519([5; 32]).into_peer_id();
+        let encoded = super::build_find_node_request(key.as_bytes());
+        assert_eq!(
+            super::decode_find_node_request(&encoded).unwrap(),
+            key.as_bytes()
+        );
+    }
+
+    #[test]
+    fn response_encode_decode() {
+        let closer_peers = vec![
+            (
+                peer_id::PublicKey::Ed25519([1; 32]).into_peer_id(),
+                vec![
+                    "/ip4/1.2.3.4/tcp/30333"
+                        .parse::<multiaddr::Multiaddr>()
+                        .unwrap(),
+                    "/dns/example.com/tcp/30333/ws"
+                        .parse::<multiaddr::Multiaddr>()
+                        .unwrap(),
+                ],
+            ),
+            (peer_id::PublicKey::Ed25519([2; 32]).into_peer_id(), vec![]),
+        ];
+
+        let encoded = super::build_find_node_response(&closer_peers);
+        assert_eq!(
+            super::decode_find_node_response(&encoded).unwrap(),
+            closer_peers
+        );
+    }
+
+    #[test]
+    fn response_is_not_request() {
+        let encoded = super::build_find_node_response(&[]);
+        assert!(super::decode_find_node_request(&encoded).is_err());
+    }
+
+    #[test]
+    fn get_value_response_decode() {
+        let peer_id = peer_id::PublicKey::Ed25519([3; 32]).into_peer_id();
+        let addr = "/ip4/1.2.3.4/tcp/30333"
+            .parse::<multiaddr::Multiaddr>()
+            .unwrap();
+
+        let mut encoded = Vec::new();
+        for slice in protobuf::enum_tag_encode(1, 1) {
+            encoded.extend_from_slice(slice.as_ref());
+        }
+        let record = protobuf::bytes_tag_encode(1, &b"foo"[..])
+            .chain(protobuf::bytes_tag_encode(2, &b"bar"[..]));
+        for slice in protobuf::message_tag_encode(3, record) {
+            encoded.extend_from_slice(slice.as_ref());
+        }
+        let peer = protobuf::bytes_tag_encode(1, peer_id.as_bytes())
+            .chain(protobuf::bytes_tag_encode(2, addr.as_ref()));
+        for slice in protobuf::message_tag_encode(8, peer) {
+            encoded.extend_from_slice(slice.as_ref());
+        }
+
+        assert_eq!(
+            super::decode_get_value_response(&encoded).unwrap(),
+            super::GetValueResponse {
+                record: Some(super::Record {
+                    key: b"foo".to_vec(),
+                    value: b"bar".to_vec(),
+                }),
+                closer_peers: vec![(peer_id, vec![addr])],
+            }
+        );
+    }
+
+    #[test]
+    fn get_value_response_without_record() {
+        let encoded = protobuf::enum_tag_encode(1, 1).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+        assert_eq!(
+            super::decode_get_value_response(&encoded).unwrap(),
+            super::GetValueResponse {
+                record: None,
+                closer_peers: Vec::new(),
+            }
+        );
+
+        let encoded = super::build_find_node_response(&[]);
+        assert!(super::decode_get_value_response(&encoded).is_err());
+    }
+
+    #[test]
+    fn put_value_response_decode() {
+        // Remotes answer put value requests by sending back the request.
+        let encoded = super::build_put_value_request(b"foo", b"bar");
+        assert!(super::decode_put_value_response(&encoded).is_ok());
+
+        let encoded = super::build_get_value_request(b"foo");
+        assert!(super::decode_put_value_response(&encoded).is_err());
+    }
+}
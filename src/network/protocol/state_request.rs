@@ -109,6 +109,70 @@ pub fn build_state_request(
         .chain(protobuf::bool_tag_encode(3, false).map(either::Left))
 }
 
+/// Decodes a state request.
+///
+/// > **Note**: Requests that use the "no proof" mode (see the module-level documentation) are
+/// >           considered as invalid.
+pub fn decode_state_request(
+    request_bytes: &[u8],
+) -> Result<StateRequest<'_>, DecodeStateRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[required] block = 1 => protobuf::bytes_tag_decode,
+            #[repeated(max = 2)] start = 2 => protobuf::bytes_tag_decode,
+            #[optional] no_proof = 3 => protobuf::bool_tag_decode,
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, rq)) => rq,
+        Err(_) => return Err(DecodeStateRequestError::ProtobufDecode),
+    };
+
+    if decoded.no_proof.unwrap_or(false) {
+        return Err(DecodeStateRequestError::NoProofUnsupported);
+    }
+
+    Ok(StateRequest {
+        block_hash: <&[u8; 32]>::try_from(decoded.block)
+            .map_err(|_| DecodeStateRequestError::InvalidBlockHashLength)?,
+        start_key: match &decoded.start[..] {
+            [] => StateRequestStart::MainTrie(&[]),
+            [key] => StateRequestStart::MainTrie(key),
+            [child_trie, key] => StateRequestStart::ChildTrieDefault {
+                child_trie: child_trie
+                    .strip_prefix(b":child_storage:default:")
+                    .ok_or(DecodeStateRequestError::UnsupportedChildTrie)?,
+                key,
+            },
+            _ => unreachable!(),
+        },
+    })
+}
+
+/// Error potentially returned by [`decode_state_request`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum DecodeStateRequestError {
+    /// Error while decoding the Protobuf encoding.
+    ProtobufDecode,
+    /// Block hash doesn't have the correct length.
+    InvalidBlockHashLength,
+    /// Start key concerns a child trie that isn't a default child trie.
+    UnsupportedChildTrie,
+    /// Request asks for the entries to be returned without a proof.
+    NoProofUnsupported,
+}
+
+/// Builds the bytes corresponding to a response to a state request.
+///
+/// `proof` must be a SCALE-encoded Merkle proof, in other words the number of entries followed
+/// with each length-prefixed entry.
+pub fn build_state_response<'a>(
+    proof: impl AsRef<[u8]> + Clone + 'a,
+) -> impl Iterator<Item = impl AsRef<[u8]> + 'a> + 'a {
+    protobuf::bytes_tag_encode(2, proof)
+}
+
 /// Decodes a response to a state request.
 ///
 /// On success, contains a list of Merkle proof entries.
@@ -141,3 +205,38 @@ pub enum DecodeStateResponseError {
     /// Error while decoding the Protobuf encoding.
     ProtobufDecode,
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn request_encode_decode() {
+        let request = super::StateRequest {
+            block_hash: &[0xaa; 32],
+            start_key: super::StateRequestStart::ChildTrieDefault {
+                child_trie: b"child",
+                key: b"foo",
+            },
+        };
+
+        let encoded = super::build_state_request(request.clone()).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        assert_eq!(super::decode_state_request(&encoded).unwrap(), request);
+    }
+
+    #[test]
+    fn response_encode_decode() {
+        let encoded =
+            super::build_state_response(&[8, 8, 1, 2, 4, 3][..]).fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+
+        assert_eq!(
+            super::decode_state_response(&encoded).unwrap(),
+            vec![&[1, 2][..], &[3][..]]
+        );
+    }
+}
@@ -17,7 +17,8 @@
 
 use crate::util::protobuf;
 
-use alloc::vec::Vec;
+use alloc::vec::{self, Vec};
+use core::iter;
 
 /// Description of a storage proof request that can be sent to a peer.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -26,6 +27,13 @@ pub struct StorageProofRequestConfig<TKeysIter> {
     pub block_hash: [u8; 32],
     /// List of storage keys to query.
     pub keys: TKeysIter,
+    /// If `Some`, [`StorageProofRequestConfig::keys`] are keys of the given default child trie
+    /// rather than of the main trie. The child trie identifier doesn't include the
+    /// `:child_storage:default:` prefix.
+    ///
+    /// The proof returned by the remote then also contains the entries of the main trie
+    /// necessary to find the root of the child trie.
+    pub child_trie: Option<Vec<u8>>,
 }
 
 // See https://github.com/paritytech/substrate/blob/c8653447fc8ef8d95a92fe164c96dffb37919e85/client/network/sync/src/schema/api.v1.proto
@@ -35,15 +43,32 @@ pub struct StorageProofRequestConfig<TKeysIter> {
 pub fn build_storage_proof_request<'a>(
     config: StorageProofRequestConfig<impl Iterator<Item = impl AsRef<[u8]> + Clone + 'a> + 'a>,
 ) -> impl Iterator<Item = impl AsRef<[u8]> + 'a> + 'a {
+    // Requests concerning a child trie use a different message, where the keys are found in a
+    // different field.
+    let (message_field, keys_field, child_trie_key) = match config.child_trie {
+        None => (2, 3, None),
+        Some(child_trie) => {
+            let mut child_trie_key = b":child_storage:default:".to_vec();
+            child_trie_key.extend_from_slice(&child_trie);
+            (4, 6, Some(child_trie_key))
+        }
+    };
+
     protobuf::message_tag_encode(
-        2,
+        message_field,
         protobuf::bytes_tag_encode(2, config.block_hash)
             .map(either::Left)
+            .chain(
+                child_trie_key
+                    .into_iter()
+                    .flat_map(|key| protobuf::bytes_tag_encode(3, key))
+                    .map(|b| either::Right(either::Left(b))),
+            )
             .chain(
                 config
                     .keys
-                    .flat_map(|key| protobuf::bytes_tag_encode(3, key))
-                    .map(either::Right),
+                    .flat_map(move |key| protobuf::bytes_tag_encode(keys_field, key))
+                    .map(|b| either::Right(either::Right(b))),
             ),
     )
 }
@@ -92,6 +117,108 @@ pub fn build_call_proof_request<'a>(
     )
 }
 
+/// Storage proof or call proof request decoded by [`decode_storage_or_call_proof_request`].
+#[derive(Debug, Clone)]
+pub enum StorageOrCallProofRequest<'a> {
+    /// Request for a storage proof.
+    StorageProof(StorageProofRequestConfig<vec::IntoIter<&'a [u8]>>),
+    /// Request for a call proof.
+    CallProof(CallProofRequestConfig<'a, iter::Once<&'a [u8]>>),
+}
+
+/// Decodes a storage proof request or a call proof request.
+pub fn decode_storage_or_call_proof_request(
+    request_bytes: &[u8],
+) -> Result<StorageOrCallProofRequest<'_>, DecodeStorageCallProofRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] call = 1 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] block = 2 => protobuf::bytes_tag_decode,
+                #[required] method = 3 => protobuf::string_tag_decode,
+                #[optional] data = 4 => protobuf::bytes_tag_decode,
+            }),
+            #[optional] read = 2 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] block = 2 => protobuf::bytes_tag_decode,
+                #[repeated(max = 1024)] keys = 3 => protobuf::bytes_tag_decode,
+            }),
+            #[optional] read_child = 4 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] block = 2 => protobuf::bytes_tag_decode,
+                #[required] storage_key = 3 => protobuf::bytes_tag_decode,
+                #[repeated(max = 1024)] keys = 6 => protobuf::bytes_tag_decode,
+            }),
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, rq)) => rq,
+        Err(_) => return Err(DecodeStorageCallProofRequestError::ProtobufDecode),
+    };
+
+    match (decoded.call, decoded.read, decoded.read_child) {
+        (Some(call), None, None) => Ok(StorageOrCallProofRequest::CallProof(
+            CallProofRequestConfig {
+                block_hash: <[u8; 32]>::try_from(call.block)
+                    .map_err(|_| DecodeStorageCallProofRequestError::InvalidBlockHashLength)?,
+                method: call.method,
+                parameter_vectored: iter::once(call.data.unwrap_or(&[])),
+            },
+        )),
+        (None, Some(read), None) => Ok(StorageOrCallProofRequest::StorageProof(
+            StorageProofRequestConfig {
+                block_hash: <[u8; 32]>::try_from(read.block)
+                    .map_err(|_| DecodeStorageCallProofRequestError::InvalidBlockHashLength)?,
+                keys: read.keys.into_iter(),
+                child_trie: None,
+            },
+        )),
+        (None, None, Some(read_child)) => Ok(StorageOrCallProofRequest::StorageProof(
+            StorageProofRequestConfig {
+                block_hash: <[u8; 32]>::try_from(read_child.block)
+                    .map_err(|_| DecodeStorageCallProofRequestError::InvalidBlockHashLength)?,
+                keys: read_child.keys.into_iter(),
+                child_trie: Some(
+                    read_child
+                        .storage_key
+                        .strip_prefix(b":child_storage:default:")
+                        .ok_or(DecodeStorageCallProofRequestError::UnsupportedChildTrie)?
+                        .to_vec(),
+                ),
+            },
+        )),
+        (None, None, None) => Err(DecodeStorageCallProofRequestError::UnsupportedRequest),
+        _ => Err(DecodeStorageCallProofRequestError::ProtobufDecode),
+    }
+}
+
+/// Error potentially returned by [`decode_storage_or_call_proof_request`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum DecodeStorageCallProofRequestError {
+    /// Error while decoding the Protobuf encoding.
+    ProtobufDecode,
+    /// Block hash doesn't have the correct length.
+    InvalidBlockHashLength,
+    /// Request concerns a child trie that isn't a default child trie.
+    UnsupportedChildTrie,
+    /// Request is neither a storage proof request nor a call proof request.
+    UnsupportedRequest,
+}
+
+/// Builds the bytes corresponding to a response to a storage proof request or a call proof
+/// request.
+///
+/// `proof` must be a SCALE-encoded Merkle proof.
+pub fn build_storage_or_call_proof_response<'a>(
+    ty: StorageOrCallProof,
+    proof: impl AsRef<[u8]> + Clone + 'a,
+) -> impl Iterator<Item = impl AsRef<[u8]> + 'a> + 'a {
+    let field_num = match ty {
+        StorageOrCallProof::CallProof => 1,
+        StorageOrCallProof::StorageProof => 2,
+    };
+
+    protobuf::message_tag_encode(field_num, protobuf::bytes_tag_encode(2, proof))
+}
+
 /// Decodes a response to a storage proof request or a call proof request.
 ///
 /// On success, returns a SCALE-encoded Merkle proof, or `None` if the remote couldn't answer
@@ -134,10 +261,82 @@ pub enum DecodeStorageCallProofResponseError {
     ProofDecodeError,
 }
 
-/// Passed as parameter to [`decode_storage_or_call_proof_response`] to indicate what kind of
-/// request the response corresponds to.
+/// Passed as parameter to [`decode_storage_or_call_proof_response`] and
+/// [`build_storage_or_call_proof_response`] to indicate what kind of request the response
+/// corresponds to.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum StorageOrCallProof {
     StorageProof,
     CallProof,
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn storage_proof_request_encode_decode() {
+        let encoded = super::build_storage_proof_request(super::StorageProofRequestConfig {
+            block_hash: [0xaa; 32],
+            keys: [&b"foo"[..], &b"bar"[..]].into_iter(),
+            child_trie: Some(b"child".to_vec()),
+        })
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        match super::decode_storage_or_call_proof_request(&encoded).unwrap() {
+            super::StorageOrCallProofRequest::StorageProof(rq) => {
+                assert_eq!(rq.block_hash, [0xaa; 32]);
+                assert_eq!(rq.keys.collect::<Vec<_>>(), vec![&b"foo"[..], &b"bar"[..]]);
+                assert_eq!(rq.child_trie.as_deref(), Some(&b"child"[..]));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn call_proof_request_encode_decode() {
+        let encoded = super::build_call_proof_request(super::CallProofRequestConfig {
+            block_hash: [0xbb; 32],
+            method: "Core_version",
+            parameter_vectored: [&b"he"[..], &b"llo"[..]].into_iter(),
+        })
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        match super::decode_storage_or_call_proof_request(&encoded).unwrap() {
+            super::StorageOrCallProofRequest::CallProof(rq) => {
+                assert_eq!(rq.block_hash, [0xbb; 32]);
+                assert_eq!(rq.method, "Core_version");
+                assert_eq!(
+                    rq.parameter_vectored.collect::<Vec<_>>(),
+                    vec![&b"hello"[..]]
+                );
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn response_encode_decode() {
+        for ty in [
+            super::StorageOrCallProof::StorageProof,
+            super::StorageOrCallProof::CallProof,
+        ] {
+            let encoded = super::build_storage_or_call_proof_response(ty, &b"proof"[..]).fold(
+                Vec::new(),
+                |mut a, b| {
+                    a.extend_from_slice(b.as_ref());
+                    a
+                },
+            );
+
+            assert_eq!(
+                super::decode_storage_or_call_proof_response(ty, &encoded).unwrap(),
+                Some(&b"proof"[..])
+            );
+        }
+    }
+}
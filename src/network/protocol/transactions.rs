@@ -0,0 +1,95 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The transactions protocol is a notifications protocol.
+//!
+//! Each notification consists in a SCALE-encoded list of transactions. Each transaction is
+//! itself SCALE-encoded, meaning that it starts with its length.
+
+use crate::util;
+
+use alloc::vec::Vec;
+use nom::Finish as _;
+
+/// Builds the bytes of a transactions notification containing the given list of
+/// SCALE-encoded transactions.
+pub fn encode_transactions_notification<'a>(
+    scale_encoded_transactions: impl ExactSizeIterator<Item = &'a [u8]> + 'a,
+) -> impl Iterator<Item = impl AsRef<[u8]> + 'a> + 'a {
+    let num_transactions = util::encode_scale_compact_usize(scale_encoded_transactions.len());
+
+    [either::Left(num_transactions)]
+        .into_iter()
+        .chain(scale_encoded_transactions.map(either::Right))
+}
+
+/// Decodes a transactions notification.
+///
+/// Returns the list of SCALE-encoded transactions found in the notification. Each item includes
+/// its length prefix, and can thus be passed as is to the runtime.
+pub fn decode_transactions_notification(
+    scale_encoded: &[u8],
+) -> Result<Vec<&[u8]>, DecodeTransactionsNotificationError> {
+    let result: Result<_, nom::error::Error<&[u8]>> = nom::combinator::all_consuming(
+        nom::combinator::flat_map(util::nom_scale_compact_usize, |num_elems| {
+            nom::multi::many_m_n(
+                num_elems,
+                num_elems,
+                nom::combinator::recognize(nom::combinator::flat_map(
+                    util::nom_scale_compact_usize,
+                    nom::bytes::complete::take,
+                )),
+            )
+        }),
+    )(scale_encoded)
+    .finish();
+
+    match result {
+        Ok((_, transactions)) => Ok(transactions),
+        Err(err) => Err(DecodeTransactionsNotificationError(err.code)),
+    }
+}
+
+/// Error potentially returned by [`decode_transactions_notification`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed to decode a transactions notification")]
+pub struct DecodeTransactionsNotificationError(nom::error::ErrorKind);
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn encode_decode() {
+        let transactions: [&[u8]; 2] = [&[8, 1, 2], &[0]];
+
+        let encoded = super::encode_transactions_notification(transactions.iter().copied()).fold(
+            Vec::new(),
+            |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            },
+        );
+        assert_eq!(encoded, &[8, 8, 1, 2, 0]);
+
+        let decoded = super::decode_transactions_notification(&encoded).unwrap();
+        assert_eq!(decoded, transactions);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        assert!(super::decode_transactions_notification(&[4, 12, 1, 2]).is_err());
+    }
+}
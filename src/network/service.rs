@@ -38,25 +38,32 @@ pub use crate::libp2p::{
     collection::ReadWrite,
     peers::{
         ConnectionId, ConnectionToCoordinator, CoordinatorToConnection, InRequestId, InboundError,
-        MultiStreamConnectionTask, MultiStreamHandshakeKind, OutRequestId,
+        MultiStreamConnectionTask, MultiStreamHandshakeKind, MultiplexingProtocol, OutRequestId,
         SingleStreamConnectionTask, SingleStreamHandshakeKind,
     },
 };
 
 mod addresses;
 mod notifications;
+mod reputation;
 mod requests_responses;
 
 pub use notifications::{
-    EncodedBlockAnnounce, EncodedBlockAnnounceHandshake, EncodedGrandpaCommitMessage, GrandpaState,
-    NotificationsOutErr,
+    EncodedBlockAnnounce, EncodedBlockAnnounceHandshake, EncodedGrandpaCommitMessage,
+    EncodedGrandpaNotification, EncodedTransactions, GrandpaState, NotificationsOutErr,
+};
+
+pub use reputation::{
+    BlocksRequestErrorPenalties, Misbehaviour, MisbehaviourPenalties, ProtocolErrorPenalties,
+    ReputationConfig,
 };
 
 pub use requests_responses::{
     BlocksRequestError, BlocksRequestResponseEntryError, CallProofRequestError, DiscoveryError,
-    EncodedGrandpaWarpSyncResponse, EncodedMerkleProof, EncodedStateResponse,
-    GrandpaWarpSyncRequestError, KademliaFindNodeError, KademliaOperationId, RequestResult,
-    StateRequestError, StorageProofRequestError,
+    EncodedCallProofRequest, EncodedGrandpaWarpSyncResponse, EncodedMerkleProof,
+    EncodedStateRequest, EncodedStateResponse, EncodedStorageProofRequest,
+    GrandpaWarpSyncRequestError, KademliaFindNodeError, KademliaOperationId, KademliaValueError,
+    RequestResult, StateRequestError, StorageProofRequestError,
 };
 
 /// Configuration for a [`ChainNetwork`].
@@ -96,12 +103,21 @@ pub struct Config<TNow> {
     /// and must be aborted.
     pub handshake_timeout: Duration,
 
+    /// List of multiplexing protocols to try to negotiate on single-stream connections (i.e. TCP
+    /// and WebSocket), in decreasing order of preference.
+    ///
+    /// Must not be empty.
+    pub multiplexing_protocols: Vec<MultiplexingProtocol>,
+
     /// Maximum number of addresses kept in memory per network identity.
     ///
     /// > **Note**: As the number of network identities kept in memory is capped, having a
     /// >           maximum number of addresses per peer ensures that the total number of
     /// >           addresses is capped as well.
     pub max_addresses_per_peer: NonZeroUsize,
+
+    /// Configuration of the reputation of peers and of their banning.
+    pub reputation: ReputationConfig,
 }
 
 /// Configuration for a specific overlay network.
@@ -123,6 +139,16 @@ pub struct ChainConfig {
     /// `true` if incoming block requests are allowed.
     pub allow_inbound_block_requests: bool,
 
+    /// `true` if incoming storage proof and call proof requests are allowed.
+    pub allow_inbound_light_requests: bool,
+
+    /// `true` if incoming state requests are allowed.
+    pub allow_inbound_state_requests: bool,
+
+    /// `true` if incoming Kademlia requests are allowed. If `false`, the local node will not be
+    /// inserted in the DHT of other nodes.
+    pub allow_inbound_kademlia_requests: bool,
+
     pub in_slots: u32,
 
     pub out_slots: u32,
@@ -152,6 +178,13 @@ pub struct ChainNetwork<TNow> {
     /// See [`Config::max_addresses_per_peer`].
     max_addresses_per_peer: NonZeroUsize,
 
+    /// See [`Config::reputation`].
+    reputation_config: ReputationConfig,
+
+    /// Reputation of the peers that have misbehaved recently. Peers that aren't in this list
+    /// have a neutral reputation.
+    reputations: hashbrown::HashMap<PeerId, reputation::PeerReputation<TNow>, SipHasherBuild>,
+
     /// Contains an entry for each peer present in at least one k-bucket of a chain.
     kbuckets_peers: hashbrown::HashMap<PeerId, KBucketsPeer, SipHasherBuild>,
 
@@ -174,8 +207,12 @@ pub struct ChainNetwork<TNow> {
     /// Identifier to assign to the next Kademlia operation that is started.
     next_kademlia_operation_id: KademliaOperationId,
 
-    /// Errors during a Kademlia operation that is yet to be reported to the user.
-    pending_kademlia_errors: VecDeque<(KademliaOperationId, DiscoveryError)>,
+    /// Outcomes of Kademlia operations that are yet to be reported to the user.
+    pending_kademlia_events: VecDeque<Event>,
+
+    /// List of Kademlia get value and put value operations that are in progress.
+    kademlia_value_operations:
+        hashbrown::HashMap<KademliaOperationId, KademliaValueOperation, fnv::FnvBuildHasher>,
 
     /// For each item in [`Config::chains`], the corresponding chain state.
     ///
@@ -238,13 +275,42 @@ struct KBucketsPeer {
     addresses: addresses::Addresses,
 }
 
+struct KademliaValueOperation {
+    /// Index of the chain whose k-buckets are used to find the peers to query.
+    chain_index: usize,
+
+    /// Key of the record to find or to store.
+    key: Vec<u8>,
+
+    /// If `Some`, the operation stores a record with the given value. If `None`, the operation
+    /// looks for the record.
+    put_value: Option<Vec<u8>>,
+
+    /// Peers that have been sent a request as part of this operation.
+    queried_peers: hashbrown::HashSet<PeerId, SipHasherBuild>,
+
+    /// Number of requests that have been started and that haven't finished yet.
+    num_in_progress: usize,
+
+    /// Number of requests that have been successfully answered.
+    num_successes: usize,
+
+    /// Distinct values of the records found so far. Always empty if `put_value` is `Some`.
+    values: Vec<Vec<u8>>,
+}
+
 enum InRequestTy {
     Identify { observed_addr: multiaddr::Multiaddr },
     Blocks,
+    StorageProof,
+    CallProof,
+    State,
+    KademliaFindNode,
 }
 
 enum OutRequestTy {
     Blocks {
+        target: PeerId,
         checked: Option<protocol::BlocksRequestConfig>,
     },
     GrandpaWarpSync,
@@ -253,6 +319,7 @@ enum OutRequestTy {
     CallProof,
     KademliaFindNode,
     KademliaDiscoveryFindNode(KademliaOperationId),
+    KademliaValue(KademliaOperationId),
 }
 
 // Update this when a new notifications protocol is added.
@@ -317,6 +384,7 @@ where
                 randomness_seed: randomness.sample(rand::distributions::Standard),
                 notification_protocols,
                 ping_protocol: "/ipfs/ping/1.0.0".into(),
+                multiplexing_protocols: config.multiplexing_protocols,
                 handshake_timeout: config.handshake_timeout,
             }),
             open_chains: hashbrown::HashSet::with_capacity_and_hasher(
@@ -332,11 +400,20 @@ where
                 SipHasherBuild::new(randomness.gen()),
             ),
             pending_ids: slab::Slab::with_capacity(config.peers_capacity),
+            reputations: hashbrown::HashMap::with_capacity_and_hasher(
+                config.peers_capacity,
+                SipHasherBuild::new(randomness.gen()),
+            ),
             next_kademlia_operation_id: KademliaOperationId(0),
-            pending_kademlia_errors: VecDeque::with_capacity(4),
+            pending_kademlia_events: VecDeque::with_capacity(4),
+            kademlia_value_operations: hashbrown::HashMap::with_capacity_and_hasher(
+                4,
+                Default::default(),
+            ),
             chains,
             handshake_timeout: config.handshake_timeout,
             max_addresses_per_peer: config.max_addresses_per_peer,
+            reputation_config: config.reputation,
             out_requests_types: hashbrown::HashMap::with_capacity_and_hasher(
                 config.peers_capacity,
                 Default::default(),
@@ -636,13 +713,23 @@ where
     }
 
     /// Returns the next event produced by the service.
+    ///
+    /// The reputation of the peers that are reported in [`Event::ProtocolError`]s is decreased
+    /// according to [`ReputationConfig::protocol_error_penalties`].
     // TODO: this `now` parameter, it's a hack
     pub fn next_event(&mut self, now: TNow) -> Option<Event> {
-        if let Some((kademlia_operation_id, error)) = self.pending_kademlia_errors.pop_front() {
-            return Some(Event::KademliaDiscoveryResult {
-                operation_id: kademlia_operation_id,
-                result: Err(error),
-            });
+        let event = self.next_event_inner(now.clone())?;
+
+        if let Event::ProtocolError { peer_id, error } = &event {
+            self.on_protocol_error(&now, peer_id, error);
+        }
+
+        Some(event)
+    }
+
+    fn next_event_inner(&mut self, now: TNow) -> Option<Event> {
+        if let Some(event) = self.pending_kademlia_events.pop_front() {
+            return Some(event);
         }
 
         let event_to_return = loop {
@@ -838,13 +925,15 @@ where
                     request_payload,
                     ..
                 } => {
-                    break Some(self.on_request_in(
+                    if let Some(event) = self.on_request_in(
                         request_id,
                         peer_id,
                         connection_id,
                         protocol_index,
                         request_payload,
-                    ))
+                    ) {
+                        break Some(event);
+                    }
                 }
 
                 // Remote is no longer interested in the response.
@@ -870,7 +959,11 @@ where
                 peers::Event::Response {
                     request_id,
                     response,
-                } => break Some(self.on_response(request_id, response)),
+                } => {
+                    if let Some(event) = self.on_response(&now, request_id, response) {
+                        break Some(event);
+                    }
+                }
 
                 peers::Event::NotificationsOutClose {
                     notifications_protocol_index,
@@ -914,6 +1007,7 @@ where
                     notifications_protocol_index,
                 } => {
                     if let Some(event) = self.on_notifications_in_open(
+                        &now,
                         id,
                         peer_id,
                         notifications_protocol_index,
@@ -992,9 +1086,13 @@ where
     /// The returned [`StartConnect`] contains the [`StartConnect::timeout`] field. It is the
     /// responsibility of the API user to ensure that [`ChainNetwork::pending_outcome_err`] is
     /// called if this timeout is reached.
+    ///
+    /// Banned peers (see [`ChainNetwork::is_banned`]) are never connected to.
     // TODO: give more control, with number of slots and node choice
     // TODO: this API with now is a bit hacky?
     pub fn next_start_connect(&mut self, now: impl FnOnce() -> TNow) -> Option<StartConnect<TNow>> {
+        let now = now();
+
         // Ask the underlying state machine which nodes are desired but don't have any
         // associated connection attempt yet.
         // Since the underlying state machine is only made aware of connections when
@@ -1003,6 +1101,14 @@ where
         let unfulfilled_desired_peers = self.inner.unfulfilled_desired_peers();
 
         for peer_id in unfulfilled_desired_peers {
+            let is_banned = match self.reputations.get(peer_id) {
+                Some(reputation) => reputation.is_banned(&now),
+                None => false,
+            };
+            if is_banned {
+                continue;
+            }
+
             // TODO: allow more than one simultaneous dial per peer, and distribute the dials so that we don't just return the same peer multiple times in a row while there are other peers waiting
             // TODO: cloning the peer_id :-/
             let entry = match self.num_pending_per_peer.entry(peer_id.clone()) {
@@ -1030,7 +1136,6 @@ where
                 }
             };
 
-            let now = now();
             let pending_id = PendingId(self.pending_ids.insert((
                 entry.key().clone(),
                 multiaddr.clone(),
@@ -1060,7 +1165,11 @@ where
     }
 
     // TODO: docs and appropriate naming
-    pub fn slots_to_assign(&'_ self, chain_index: usize) -> impl Iterator<Item = &'_ PeerId> + '_ {
+    pub fn slots_to_assign<'a>(
+        &'a self,
+        chain_index: usize,
+        now: &'a TNow,
+    ) -> impl Iterator<Item = &'a PeerId> + 'a {
         let chain = &self.chains[chain_index];
 
         // Check if maximum number of slots is reached.
@@ -1079,6 +1188,13 @@ where
                 .filter(|peer_id| {
                     // Don't assign slots to peers that already have a slot.
                     !chain.out_peers.contains(*peer_id) && !chain.in_peers.contains(*peer_id)
+                })
+                .filter(move |peer_id| {
+                    // Don't assign slots to banned peers.
+                    match self.reputations.get(*peer_id) {
+                        Some(reputation) => !reputation.is_banned(now),
+                        None => true,
+                    }
                 }),
         )
     }
@@ -1231,6 +1347,19 @@ pub enum Event {
         message: EncodedGrandpaCommitMessage,
     },
 
+    /// Received a GrandPa notification other than a commit message from the network. This
+    /// includes votes, neighbor packets, and catch up requests and responses.
+    ///
+    /// Can only happen after a [`Event::ChainConnected`] with the given `PeerId` and chain index
+    /// combination has happened.
+    GrandpaNotification {
+        /// Identity of the sender of the notification.
+        peer_id: PeerId,
+        /// Index of the chain the notification relates to.
+        chain_index: usize,
+        notification: EncodedGrandpaNotification,
+    },
+
     /// Error in the protocol in a connection, such as failure to decode a message. This event
     /// doesn't have any consequence on the health of the connection, and is purely for diagnostic
     /// purposes.
@@ -1265,6 +1394,68 @@ pub enum Event {
         /// Identifier of the request. Necessary to send back the answer.
         request_id: InRequestId,
     },
+    /// A remote has sent a request for a storage proof.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_light_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_storage_proof`].
+    StorageProofRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Information about the request.
+        config: EncodedStorageProofRequest,
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
+    /// A remote has sent a request for a call proof.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_light_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_call_proof`].
+    CallProofRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Information about the request.
+        config: EncodedCallProofRequest,
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
+    /// A remote has sent a state request.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_state_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_state`].
+    StateRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Information about the request.
+        config: EncodedStateRequest,
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
+
+    /// A remote has sent a Kademlia request for the nodes closest to a key.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_kademlia_requests`] is
+    /// `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_kademlia_find_node`].
+    KademliaFindNodeRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Key whose closest nodes are requested.
+        target: Vec<u8>,
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
 
     RequestInCancel {
         request_id: InRequestId,
@@ -1274,10 +1465,33 @@ pub enum Event {
         operation_id: KademliaOperationId,
         result: Result<Vec<(PeerId, Vec<multiaddr::Multiaddr>)>, DiscoveryError>,
     },
-    /*Transactions {
+
+    /// An operation started with [`ChainNetwork::start_kademlia_get_value`] has finished.
+    KademliaGetValueResult {
+        operation_id: KademliaOperationId,
+        /// If successful, contains the distinct values of the records that have been found. Can
+        /// be empty if no peer knows the record.
+        result: Result<Vec<Vec<u8>>, KademliaValueError>,
+    },
+
+    /// An operation started with [`ChainNetwork::start_kademlia_put_value`] has finished.
+    KademliaPutValueResult {
+        operation_id: KademliaOperationId,
+        /// If successful, contains the number of peers that have accepted to store the record.
+        result: Result<NonZeroUsize, KademliaValueError>,
+    },
+
+    /// Received a transactions notification from the network.
+    ///
+    /// Can only happen after a [`Event::ChainConnected`] with the given `PeerId` and chain index
+    /// combination has happened.
+    Transactions {
+        /// Identity of the sender of the notification.
         peer_id: PeerId,
+        /// Index of the chain the transactions relate to.
+        chain_index: usize,
         transactions: EncodedTransactions,
-    }*/
+    },
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -1304,9 +1518,30 @@ pub enum ProtocolError {
     /// Error while decoding a received Grandpa notification.
     #[display(fmt = "Error while decoding a received Grandpa notification: {}", _0)]
     BadGrandpaNotification(protocol::DecodeGrandpaNotificationError),
+    /// Error while decoding a received transactions notification.
+    #[display(
+        fmt = "Error while decoding a received transactions notification: {}",
+        _0
+    )]
+    BadTransactionsNotification(protocol::DecodeTransactionsNotificationError),
     /// Received an invalid identify request.
     BadIdentifyRequest,
     /// Error while decoding a received blocks request.
     #[display(fmt = "Error while decoding a received blocks request: {}", _0)]
     BadBlocksRequest(protocol::DecodeBlockRequestError),
+    /// Error while decoding a received storage proof or call proof request.
+    #[display(
+        fmt = "Error while decoding a received storage proof or call proof request: {}",
+        _0
+    )]
+    BadStorageOrCallProofRequest(protocol::DecodeStorageCallProofRequestError),
+    /// Error while decoding a received state request.
+    #[display(fmt = "Error while decoding a received state request: {}", _0)]
+    BadStateRequest(protocol::DecodeStateRequestError),
+    /// Error while decoding a received Kademlia find node request.
+    #[display(
+        fmt = "Error while decoding a received Kademlia find node request: {}",
+        _0
+    )]
+    BadKademliaFindNodeRequest(protocol::DecodeFindNodeRequestError),
 }
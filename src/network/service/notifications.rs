@@ -20,7 +20,6 @@ use crate::libp2p::{
     PeerId,
 };
 use crate::network::protocol;
-use crate::util;
 
 use alloc::vec::Vec;
 use core::{
@@ -337,6 +336,7 @@ where
     /// [`peers::Event::NotificationsInOpen`].
     pub(super) fn on_notifications_in_open(
         &mut self,
+        now: &TNow,
         substream_id: peers::SubstreamId,
         peer_id: PeerId,
         notifications_protocol_index: usize,
@@ -361,6 +361,12 @@ where
                 });
             }
 
+            // Banned peers are never allocated a slot.
+            if self.is_banned(now, &peer_id) {
+                self.inner.in_notification_refuse(substream_id);
+                return None;
+            }
+
             // If the peer doesn't already have an outbound slot, check whether we can
             // allocate an inbound slot for it.
             let has_out_slot = self.chains[chain_index].out_peers.contains(&peer_id);
@@ -544,8 +550,21 @@ where
                 return None;
             }
 
-            // TODO: this is unimplemented
-            None
+            // Check the format of the notification.
+            if let Err(err) = protocol::decode_transactions_notification(&notification) {
+                return Some(Event::ProtocolError {
+                    error: ProtocolError::BadTransactionsNotification(err),
+                    peer_id,
+                });
+            }
+
+            Some(Event::Transactions {
+                chain_index,
+                peer_id,
+                transactions: EncodedTransactions {
+                    message: notification,
+                },
+            })
         } else if notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 2 {
             let chain_index = notifications_protocol_index / NOTIFICATIONS_PROTOCOLS_PER_CHAIN;
             let block_number_bytes = self.chains[chain_index].chain_config.block_number_bytes;
@@ -568,7 +587,7 @@ where
                 };
 
             // Commit messages are the only type of message that is important for
-            // light clients. Anything else is presently ignored.
+            // light clients, and are thus reported through a separate event.
             if let protocol::GrandpaNotificationRef::Commit(_) = decoded_notif {
                 Some(Event::GrandpaCommitMessage {
                     chain_index,
@@ -579,7 +598,14 @@ where
                     },
                 })
             } else {
-                None
+                Some(Event::GrandpaNotification {
+                    chain_index,
+                    peer_id,
+                    notification: EncodedGrandpaNotification {
+                        message: notification,
+                        block_number_bytes,
+                    },
+                })
             }
         } else {
             // Unrecognized notifications protocol.
@@ -655,6 +681,89 @@ where
             .unwrap() = grandpa_state;
     }
 
+    /// Sends a GrandPa notification, such as a vote or a catch up request, to the given peer.
+    ///
+    /// Must only be called if [`ChainNetwork::can_send_grandpa_notifications`] returns `true`.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range, or if the chain has GrandPa disabled.
+    ///
+    pub fn send_grandpa_notification(
+        &mut self,
+        target: &PeerId,
+        chain_index: usize,
+        notification: protocol::GrandpaNotificationRef,
+    ) -> Result<(), QueueNotificationError> {
+        assert!(self.chains[chain_index]
+            .chain_config
+            .grandpa_protocol_config
+            .is_some());
+
+        // In order to provide clarity about the problem, check ahead of time whether calling
+        // `queue_notification` will panic below.
+        debug_assert!(self
+            .inner
+            .can_queue_notification(target, chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 2));
+
+        let notification = notification
+            .scale_encoding(self.chains[chain_index].chain_config.block_number_bytes)
+            .fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+
+        self.inner.queue_notification(
+            target,
+            chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 2,
+            notification,
+        )
+    }
+
+    /// Returns `true` if it is allowed to call [`ChainNetwork::send_grandpa_notification`], in
+    /// other words if there is an outbound GrandPa substream currently open with the target.
+    pub fn can_send_grandpa_notifications(&self, target: &PeerId, chain_index: usize) -> bool {
+        self.inner
+            .can_queue_notification(target, chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 2)
+    }
+
+    /// Sends a GrandPa notification, such as a vote or a commit message, to all the peers we
+    /// have an outbound GrandPa substream with.
+    ///
+    /// This function might generate messages destined to connections. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process these messages after it has
+    /// returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range, or if the chain has GrandPa disabled.
+    ///
+    pub fn broadcast_grandpa_notification(
+        &mut self,
+        chain_index: usize,
+        notification: protocol::GrandpaNotificationRef,
+    ) {
+        assert!(self.chains[chain_index]
+            .chain_config
+            .grandpa_protocol_config
+            .is_some());
+
+        let notification = notification
+            .scale_encoding(self.chains[chain_index].chain_config.block_number_bytes)
+            .fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+
+        self.inner.broadcast_notification(
+            chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 2,
+            notification,
+        );
+    }
+
     ///
     /// This function might generate a message destined a connection. Use
     /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
@@ -728,9 +837,13 @@ where
             .inner
             .can_queue_notification(target, chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 1));
 
-        let mut val = Vec::with_capacity(1 + extrinsic.len());
-        val.extend_from_slice(util::encode_scale_compact_usize(1).as_ref());
-        val.extend_from_slice(extrinsic);
+        let val = protocol::encode_transactions_notification(iter::once(extrinsic)).fold(
+            Vec::with_capacity(1 + extrinsic.len()),
+            |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            },
+        );
         self.inner.queue_notification(
             target,
             chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 1,
@@ -830,3 +943,42 @@ impl fmt::Debug for EncodedGrandpaCommitMessage {
         fmt::Debug::fmt(&self.decode(), f)
     }
 }
+
+/// Undecoded but valid GrandPa notification other than a commit message.
+#[derive(Clone)]
+pub struct EncodedGrandpaNotification {
+    message: Vec<u8>,
+    block_number_bytes: usize,
+}
+
+impl EncodedGrandpaNotification {
+    /// Returns the decoded version of the notification.
+    pub fn decode(&self) -> protocol::GrandpaNotificationRef<'_> {
+        protocol::decode_grandpa_notification(&self.message, self.block_number_bytes).unwrap()
+    }
+}
+
+impl fmt::Debug for EncodedGrandpaNotification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
+
+/// Undecoded but valid transactions notification.
+#[derive(Clone)]
+pub struct EncodedTransactions {
+    message: Vec<u8>,
+}
+
+impl EncodedTransactions {
+    /// Returns the list of SCALE-encoded transactions found in the notification.
+    pub fn decode(&self) -> Vec<&[u8]> {
+        protocol::decode_transactions_notification(&self.message).unwrap()
+    }
+}
+
+impl fmt::Debug for EncodedTransactions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.decode()).finish()
+    }
+}
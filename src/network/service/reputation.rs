@@ -0,0 +1,422 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::{BlocksRequestError, ChainNetwork, ProtocolError};
+use crate::libp2p::PeerId;
+
+use core::{
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+/// Configuration of the reputation system of a [`ChainNetwork`].
+///
+/// Each peer has a reputation score, starting at 0. Misbehaviours decrease this score by a
+/// configurable penalty, and the score then slowly goes back towards 0 over time. Every second,
+/// the score loses 2% of its value, or 1 if that is more.
+///
+/// If the score of a peer reaches [`ReputationConfig::ban_threshold`], the peer is banned for
+/// [`ReputationConfig::ban_duration`]. Banned peers lose their slots, are never assigned a slot
+/// and are never connected to.
+#[derive(Debug, Clone)]
+pub struct ReputationConfig {
+    /// Score at or below which a peer gets banned. Should be negative.
+    pub ban_threshold: i32,
+
+    /// Amount of time during which a peer is banned after its score has reached
+    /// [`ReputationConfig::ban_threshold`].
+    pub ban_duration: Duration,
+
+    /// Penalties applied when a [`ProtocolError`] is reported.
+    pub protocol_error_penalties: ProtocolErrorPenalties,
+
+    /// Penalties applied when a blocks request fails with a [`BlocksRequestError`].
+    pub blocks_request_error_penalties: BlocksRequestErrorPenalties,
+
+    /// Penalties applied when a [`Misbehaviour`] is reported through
+    /// [`ChainNetwork::report_misbehaviour`].
+    pub misbehaviour_penalties: MisbehaviourPenalties,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        ReputationConfig {
+            ban_threshold: -1000,
+            ban_duration: Duration::from_secs(5 * 60),
+            protocol_error_penalties: Default::default(),
+            blocks_request_error_penalties: Default::default(),
+            misbehaviour_penalties: Default::default(),
+        }
+    }
+}
+
+/// Penalty applied for each kind of [`ProtocolError`]. Penalties are subtracted from the score.
+///
+/// See [`ReputationConfig::protocol_error_penalties`].
+#[derive(Debug, Clone)]
+pub struct ProtocolErrorPenalties {
+    /// See [`ProtocolError::InboundError`].
+    pub inbound_error: i32,
+    /// See [`ProtocolError::BadBlockAnnouncesHandshake`].
+    pub bad_block_announces_handshake: i32,
+    /// See [`ProtocolError::BadBlockAnnounce`].
+    pub bad_block_announce: i32,
+    /// See [`ProtocolError::BadGrandpaNotification`].
+    pub bad_grandpa_notification: i32,
+    /// See [`ProtocolError::BadTransactionsNotification`].
+    pub bad_transactions_notification: i32,
+    /// See [`ProtocolError::BadIdentifyRequest`].
+    pub bad_identify_request: i32,
+    /// See [`ProtocolError::BadBlocksRequest`].
+    pub bad_blocks_request: i32,
+    /// See [`ProtocolError::BadStorageOrCallProofRequest`].
+    pub bad_storage_or_call_proof_request: i32,
+    /// See [`ProtocolError::BadStateRequest`].
+    pub bad_state_request: i32,
+    /// See [`ProtocolError::BadKademliaFindNodeRequest`].
+    pub bad_kademlia_find_node_request: i32,
+}
+
+impl Default for ProtocolErrorPenalties {
+    fn default() -> Self {
+        ProtocolErrorPenalties {
+            inbound_error: 10,
+            bad_block_announces_handshake: 200,
+            bad_block_announce: 300,
+            bad_grandpa_notification: 200,
+            bad_transactions_notification: 100,
+            bad_identify_request: 100,
+            bad_blocks_request: 100,
+            bad_storage_or_call_proof_request: 100,
+            bad_state_request: 100,
+            bad_kademlia_find_node_request: 100,
+        }
+    }
+}
+
+/// Penalty applied for each kind of [`BlocksRequestError`]. Penalties are subtracted from the
+/// score.
+///
+/// [`BlocksRequestError::NotVerifiable`] is caused by the local node and never leads to a
+/// penalty.
+///
+/// See [`ReputationConfig::blocks_request_error_penalties`].
+#[derive(Debug, Clone)]
+pub struct BlocksRequestErrorPenalties {
+    /// See [`BlocksRequestError::Request`]. Only applied if the error is caused by a faulty
+    /// behavior of the remote.
+    pub request: i32,
+    /// See [`BlocksRequestError::Decode`].
+    pub decode: i32,
+    /// See [`BlocksRequestError::EmptyResponse`].
+    pub empty_response: i32,
+    /// See [`BlocksRequestError::InvalidStart`].
+    pub invalid_start: i32,
+    /// See [`BlocksRequestError::Entry`].
+    pub entry: i32,
+}
+
+impl Default for BlocksRequestErrorPenalties {
+    fn default() -> Self {
+        BlocksRequestErrorPenalties {
+            request: 100,
+            decode: 300,
+            empty_response: 50,
+            invalid_start: 300,
+            entry: 300,
+        }
+    }
+}
+
+/// Penalty applied for each kind of [`Misbehaviour`]. Penalties are subtracted from the score.
+///
+/// See [`ReputationConfig::misbehaviour_penalties`].
+#[derive(Debug, Clone)]
+pub struct MisbehaviourPenalties {
+    /// See [`Misbehaviour::BadBlock`].
+    pub bad_block: i32,
+    /// See [`Misbehaviour::BadJustification`].
+    pub bad_justification: i32,
+    /// See [`Misbehaviour::BadResponse`].
+    pub bad_response: i32,
+}
+
+impl Default for MisbehaviourPenalties {
+    fn default() -> Self {
+        MisbehaviourPenalties {
+            bad_block: 1000,
+            bad_justification: 500,
+            bad_response: 200,
+        }
+    }
+}
+
+/// Misbehaviour of a peer detected outside of the networking, typically by the syncing.
+///
+/// See [`ChainNetwork::report_misbehaviour`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Misbehaviour {
+    /// Peer has announced or sent a block that has failed to verify, or that is part of a fork
+    /// that isn't acceptable.
+    BadBlock,
+    /// Peer has sent a justification that has failed to verify.
+    BadJustification,
+    /// Peer has sent a response to a request that is well-formatted but whose content is
+    /// invalid, such as an invalid proof.
+    BadResponse,
+}
+
+impl<TNow> ChainNetwork<TNow>
+where
+    TNow: Clone + Add<Duration, Output = TNow> + Sub<TNow, Output = Duration> + Ord,
+{
+    /// Reports that the given peer has misbehaved. Decreases its reputation according to
+    /// [`ReputationConfig::misbehaviour_penalties`], and bans it if the score becomes too low.
+    ///
+    /// Returns `true` if the peer is banned after this call.
+    pub fn report_misbehaviour(
+        &mut self,
+        now: &TNow,
+        peer_id: &PeerId,
+        misbehaviour: Misbehaviour,
+    ) -> bool {
+        let penalties = &self.reputation_config.misbehaviour_penalties;
+        let penalty = match misbehaviour {
+            Misbehaviour::BadBlock => penalties.bad_block,
+            Misbehaviour::BadJustification => penalties.bad_justification,
+            Misbehaviour::BadResponse => penalties.bad_response,
+        };
+
+        self.apply_penalty(now, peer_id, penalty);
+        self.is_banned(now, peer_id)
+    }
+
+    /// Returns the current reputation score of the given peer. Peers that have never misbehaved
+    /// have a score of 0.
+    pub fn peer_reputation(&self, now: &TNow, peer_id: &PeerId) -> i32 {
+        self.reputations
+            .get(peer_id)
+            .map_or(0, |reputation| reputation.score(now))
+    }
+
+    /// Returns `true` if the given peer is currently banned.
+    ///
+    /// Banned peers are never assigned a slot, and no connection is opened to them.
+    pub fn is_banned(&self, now: &TNow, peer_id: &PeerId) -> bool {
+        match self.reputations.get(peer_id) {
+            Some(reputation) => reputation.is_banned(now),
+            None => false,
+        }
+    }
+
+    /// Called when a [`ProtocolError`] is about to be reported to the API user.
+    pub(super) fn on_protocol_error(
+        &mut self,
+        now: &TNow,
+        peer_id: &PeerId,
+        error: &ProtocolError,
+    ) {
+        let penalties = &self.reputation_config.protocol_error_penalties;
+        let penalty = match error {
+            ProtocolError::InboundError(_) => penalties.inbound_error,
+            ProtocolError::BadBlockAnnouncesHandshake(_) => penalties.bad_block_announces_handshake,
+            ProtocolError::BadBlockAnnounce(_) => penalties.bad_block_announce,
+            ProtocolError::BadGrandpaNotification(_) => penalties.bad_grandpa_notification,
+            ProtocolError::BadTransactionsNotification(_) => {
+                penalties.bad_transactions_notification
+            }
+            ProtocolError::BadIdentifyRequest => penalties.bad_identify_request,
+            ProtocolError::BadBlocksRequest(_) => penalties.bad_blocks_request,
+            ProtocolError::BadStorageOrCallProofRequest(_) => {
+                penalties.bad_storage_or_call_proof_request
+            }
+            ProtocolError::BadStateRequest(_) => penalties.bad_state_request,
+            ProtocolError::BadKademliaFindNodeRequest(_) => {
+                penalties.bad_kademlia_find_node_request
+            }
+        };
+
+        self.apply_penalty(now, peer_id, penalty);
+    }
+
+    /// Called when a blocks request sent to the given peer has failed.
+    pub(super) fn on_blocks_request_error(
+        &mut self,
+        now: &TNow,
+        peer_id: &PeerId,
+        error: &BlocksRequestError,
+    ) {
+        let penalties = &self.reputation_config.blocks_request_error_penalties;
+        let penalty = match error {
+            BlocksRequestError::Request(err) if err.is_protocol_error() => penalties.request,
+            BlocksRequestError::Request(_) | BlocksRequestError::NotVerifiable => return,
+            BlocksRequestError::Decode(_) => penalties.decode,
+            BlocksRequestError::EmptyResponse => penalties.empty_response,
+            BlocksRequestError::InvalidStart => penalties.invalid_start,
+            BlocksRequestError::Entry { .. } => penalties.entry,
+        };
+
+        self.apply_penalty(now, peer_id, penalty);
+    }
+
+    /// Decreases the score of the given peer, and bans it if necessary.
+    fn apply_penalty(&mut self, now: &TNow, peer_id: &PeerId, penalty: i32) {
+        if penalty == 0 {
+            return;
+        }
+
+        if self.reputations.len() >= self.reputations.capacity() {
+            // Clean up the peers whose reputation has gone back to neutral, in order to avoid
+            // this list growing forever.
+            // TODO: O(n)
+            self.reputations
+                .retain(|_, reputation| reputation.score(now) != 0 || reputation.is_banned(now));
+        }
+
+        let reputation =
+            self.reputations
+                .entry(peer_id.clone())
+                .or_insert_with(|| PeerReputation {
+                    score: 0,
+                    last_update: now.clone(),
+                    banned_until: None,
+                });
+
+        let was_banned = reputation.is_banned(now);
+        reputation.apply_penalty(now, penalty);
+
+        if reputation.score <= self.reputation_config.ban_threshold {
+            let ban_end = now.clone() + self.reputation_config.ban_duration;
+            match &mut reputation.banned_until {
+                Some(banned_until) if *banned_until >= ban_end => {}
+                banned_until => *banned_until = Some(ban_end),
+            }
+        }
+
+        // Newly-banned peers lose all their slots, which causes the substreams with them to be
+        // closed.
+        if !was_banned && reputation.is_banned(now) {
+            for chain_index in 0..self.chains.len() {
+                self.unassign_slot(chain_index, peer_id);
+            }
+        }
+    }
+}
+
+/// Reputation of a single peer.
+pub(super) struct PeerReputation<TNow> {
+    /// Score of the peer, as of [`PeerReputation::last_update`].
+    score: i32,
+
+    /// Moment when the decay of [`PeerReputation::score`] was last applied. Always a whole
+    /// number of seconds after the moment when the entry was created.
+    last_update: TNow,
+
+    /// If `Some`, the peer is banned until the given moment.
+    banned_until: Option<TNow>,
+}
+
+impl<TNow> PeerReputation<TNow>
+where
+    TNow: Clone + Add<Duration, Output = TNow> + Sub<TNow, Output = Duration> + Ord,
+{
+    /// Returns the score of the peer, after decay.
+    fn score(&self, now: &TNow) -> i32 {
+        decay(self.score, self.elapsed_secs(now))
+    }
+
+    /// Returns `true` if the peer is banned at the given moment.
+    pub(super) fn is_banned(&self, now: &TNow) -> bool {
+        matches!(&self.banned_until, Some(banned_until) if *banned_until > *now)
+    }
+
+    /// Applies the decay then subtracts the given penalty from the score.
+    fn apply_penalty(&mut self, now: &TNow, penalty: i32) {
+        let elapsed_secs = self.elapsed_secs(now);
+        self.score = decay(self.score, elapsed_secs).saturating_sub(penalty);
+        self.last_update = self.last_update.clone() + Duration::from_secs(elapsed_secs);
+    }
+
+    /// Returns the number of whole seconds elapsed since [`PeerReputation::last_update`].
+    fn elapsed_secs(&self, now: &TNow) -> u64 {
+        if *now <= self.last_update {
+            return 0;
+        }
+
+        (now.clone() - self.last_update.clone()).as_secs()
+    }
+}
+
+/// Returns the value of `score` after `elapsed_secs` seconds of decay.
+///
+/// Every second, the score gets closer to 0 by 2% of its value, or by 1 if that is more.
+fn decay(mut score: i32, elapsed_secs: u64) -> i32 {
+    // Note that reaching 0 takes at most slightly more than a thousand iterations, no matter
+    // the initial value of the score.
+    for _ in 0..elapsed_secs {
+        if score == 0 {
+            break;
+        }
+
+        let diff = score / 50;
+        score -= if diff == 0 { score.signum() } else { diff };
+    }
+
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decay, PeerReputation};
+    use core::time::Duration;
+
+    #[test]
+    fn decay_goes_to_zero() {
+        assert_eq!(decay(-1000, 0), -1000);
+        assert_eq!(decay(-1000, 1), -980);
+        assert_eq!(decay(-10, 3), -7);
+        assert_eq!(decay(10, 3), 7);
+        assert_eq!(decay(i32::min_value(), u64::max_value()), 0);
+        assert_eq!(decay(i32::max_value(), u64::max_value()), 0);
+    }
+
+    #[test]
+    fn fractions_of_seconds_not_lost() {
+        let mut reputation = PeerReputation {
+            score: 0,
+            last_update: Duration::new(0, 0),
+            banned_until: None,
+        };
+
+        reputation.apply_penalty(&Duration::new(0, 0), 100);
+        reputation.apply_penalty(&Duration::from_millis(600), 100);
+        assert_eq!(reputation.score(&Duration::from_millis(600)), -200);
+        assert_eq!(reputation.score(&Duration::from_millis(1200)), -196);
+    }
+
+    #[test]
+    fn ban_expires() {
+        let reputation = PeerReputation {
+            score: -2000,
+            last_update: Duration::new(0, 0),
+            banned_until: Some(Duration::from_secs(10)),
+        };
+
+        assert!(reputation.is_banned(&Duration::from_secs(9)));
+        assert!(!reputation.is_banned(&Duration::from_secs(10)));
+    }
+}
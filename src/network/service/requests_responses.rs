@@ -25,7 +25,10 @@ use crate::network::{kademlia, protocol};
 
 use super::*;
 
-use alloc::{format, vec::Vec};
+use alloc::{
+    format,
+    vec::{self, Vec},
+};
 use core::{
     fmt,
     hash::Hash,
@@ -48,6 +51,12 @@ pub use crate::libp2p::{
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct KademliaOperationId(pub(super) u64);
 
+/// Maximum number of peers that are queried during a Kademlia get value or put value operation.
+const KADEMLIA_VALUE_MAX_QUERIED_PEERS: usize = 20;
+
+/// Maximum number of simultaneous requests during a Kademlia get value operation.
+const KADEMLIA_VALUE_PARALLELISM: usize = 3;
+
 // Update this when a new request response protocol is added.
 pub(super) const REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN: usize = 5;
 
@@ -76,15 +85,15 @@ pub(super) fn protocols<'a>(
                 max_size: 1024 * 512,
             },
             max_response_size: 10 * 1024 * 1024,
-            // TODO: make this configurable
-            inbound_allowed: false,
+            inbound_allowed: chain.allow_inbound_light_requests,
         }))
         .chain(iter::once(peers::ConfigRequestResponse {
             name: format!("/{}/kad", chain.protocol_id),
             inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 1024 },
             max_response_size: 1024 * 1024,
-            // TODO: `false` here means we don't insert ourselves in the DHT, which is the polite thing to do for as long as Kad isn't implemented
-            inbound_allowed: false,
+            // `false` here means we don't insert ourselves in the DHT, which is the polite thing
+            // to do for nodes that don't want to be part of it.
+            inbound_allowed: chain.allow_inbound_kademlia_requests,
         }))
         .chain(iter::once(peers::ConfigRequestResponse {
             name: format!("/{}/sync/warp", chain.protocol_id),
@@ -100,8 +109,7 @@ pub(super) fn protocols<'a>(
             // is larger than 2MiB, the response is allowed to be bigger, as otherwise it
             // wouldn't be possible to make progress.
             max_response_size: 16 * 1024 * 1024,
-            // We don't support inbound state requests (yet).
-            inbound_allowed: false,
+            inbound_allowed: chain.allow_inbound_state_requests,
         }))
     }))
     .collect()
@@ -114,11 +122,12 @@ where
     /// Called when the underlying state machine has generated a [`peers::Event::Response`].
     pub(super) fn on_response(
         &mut self,
+        now: &TNow,
         request_id: peers::OutRequestId,
         response: Result<Vec<u8>, peers::RequestError>,
-    ) -> Event {
-        match self.out_requests_types.remove(&request_id).unwrap() {
-            (OutRequestTy::Blocks { checked }, chain_index) => {
+    ) -> Option<Event> {
+        Some(match self.out_requests_types.remove(&request_id).unwrap() {
+            (OutRequestTy::Blocks { target, checked }, chain_index) => {
                 let mut response =
                     response
                         .map_err(BlocksRequestError::Request)
@@ -137,6 +146,10 @@ where
                     }
                 }
 
+                if let Err(err) = &response {
+                    self.on_blocks_request_error(now, &target, err);
+                }
+
                 Event::RequestResult {
                     request_id,
                     response: RequestResult::Blocks(response),
@@ -254,10 +267,61 @@ where
                     result,
                 }
             }
+            (OutRequestTy::KademliaValue(operation_id), chain_index) => {
+                return self.on_kademlia_value_response(now, operation_id, chain_index, response)
+            }
+        })
+    }
+
+    /// Called when a response to a request belonging to a Kademlia get value or put value
+    /// operation has been received, or when such a request has failed.
+    fn on_kademlia_value_response(
+        &mut self,
+        now: &TNow,
+        operation_id: KademliaOperationId,
+        chain_index: usize,
+        response: Result<Vec<u8>, peers::RequestError>,
+    ) -> Option<Event> {
+        let operation = self
+            .kademlia_value_operations
+            .get_mut(&operation_id)
+            .unwrap();
+        operation.num_in_progress -= 1;
+
+        // Errors aren't reported individually. Failing to reach some of the peers is normal, and
+        // the operation as a whole is only considered as failed if all the requests fail.
+        let mut closer_peers = Vec::new();
+        match (&operation.put_value, response) {
+            (_, Err(_)) => {}
+            (Some(_), Ok(payload)) => {
+                if protocol::decode_put_value_response(&payload).is_ok() {
+                    operation.num_successes += 1;
+                }
+            }
+            (None, Ok(payload)) => {
+                if let Ok(decoded) = protocol::decode_get_value_response(&payload) {
+                    operation.num_successes += 1;
+                    if let Some(record) = decoded.record {
+                        if record.key == operation.key && !operation.values.contains(&record.value)
+                        {
+                            operation.values.push(record.value);
+                        }
+                    }
+                    closer_peers = decoded.closer_peers;
+                }
+            }
+        }
+
+        for (peer_id, addrs) in closer_peers {
+            self.discover(now, chain_index, peer_id, addrs);
         }
+
+        self.kademlia_value_operation_progress(now, operation_id)
     }
 
     /// Called when the underlying state machine has generated a [`peers::Event::RequestIn`].
+    ///
+    /// Returns `None` if the request has been refused and nothing needs to be reported.
     pub(super) fn on_request_in(
         &mut self,
         request_id: InRequestId,
@@ -265,8 +329,8 @@ where
         connection_id: ConnectionId,
         protocol_index: usize,
         request_payload: Vec<u8>,
-    ) -> Event {
-        if protocol_index == 0 {
+    ) -> Option<Event> {
+        Some(if protocol_index == 0 {
             if request_payload.is_empty() {
                 let observed_addr = self.inner[connection_id].clone();
                 let _prev_value = self
@@ -285,42 +349,128 @@ where
                     error: ProtocolError::BadIdentifyRequest,
                 }
             }
-        } else if ((protocol_index - 1) % requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN)
-            != 0
-        {
-            // Protocols that receive requests are whitelisted, meaning that no other protocol
-            // indices can reach here.
-            unreachable!()
         } else {
             let chain_index =
                 (protocol_index - 1) / requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN;
 
-            match protocol::decode_block_request(
-                self.chains[chain_index].chain_config.block_number_bytes,
-                &request_payload,
-            ) {
-                Ok(config) => {
-                    let _prev_value = self
-                        .in_requests_types
-                        .insert(request_id, InRequestTy::Blocks);
-                    debug_assert!(_prev_value.is_none());
-
-                    Event::BlocksRequestIn {
-                        peer_id,
-                        chain_index,
-                        config,
-                        request_id,
+            match (protocol_index - 1) % requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN {
+                0 => match protocol::decode_block_request(
+                    self.chains[chain_index].chain_config.block_number_bytes,
+                    &request_payload,
+                ) {
+                    Ok(config) => {
+                        let _prev_value = self
+                            .in_requests_types
+                            .insert(request_id, InRequestTy::Blocks);
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::BlocksRequestIn {
+                            peer_id,
+                            chain_index,
+                            config,
+                            request_id,
+                        }
                     }
-                }
-                Err(error) => {
-                    let _ = self.inner.respond_in_request(request_id, Err(()));
-                    Event::ProtocolError {
-                        peer_id,
-                        error: ProtocolError::BadBlocksRequest(error),
+                    Err(error) => {
+                        self.inner.respond_in_request(request_id, Err(()));
+                        Event::ProtocolError {
+                            peer_id,
+                            error: ProtocolError::BadBlocksRequest(error),
+                        }
                     }
-                }
+                },
+                1 => match protocol::decode_storage_or_call_proof_request(&request_payload) {
+                    Ok(protocol::StorageOrCallProofRequest::StorageProof(_)) => {
+                        let _prev_value = self
+                            .in_requests_types
+                            .insert(request_id, InRequestTy::StorageProof);
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::StorageProofRequestIn {
+                            peer_id,
+                            chain_index,
+                            config: EncodedStorageProofRequest(request_payload),
+                            request_id,
+                        }
+                    }
+                    Ok(protocol::StorageOrCallProofRequest::CallProof(_)) => {
+                        let _prev_value = self
+                            .in_requests_types
+                            .insert(request_id, InRequestTy::CallProof);
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::CallProofRequestIn {
+                            peer_id,
+                            chain_index,
+                            config: EncodedCallProofRequest(request_payload),
+                            request_id,
+                        }
+                    }
+                    Err(error) => {
+                        self.inner.respond_in_request(request_id, Err(()));
+                        Event::ProtocolError {
+                            peer_id,
+                            error: ProtocolError::BadStorageOrCallProofRequest(error),
+                        }
+                    }
+                },
+                2 => match protocol::decode_find_node_request(&request_payload) {
+                    Ok(target) => {
+                        let target = target.to_vec();
+                        let _prev_value = self
+                            .in_requests_types
+                            .insert(request_id, InRequestTy::KademliaFindNode);
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::KademliaFindNodeRequestIn {
+                            peer_id,
+                            chain_index,
+                            target,
+                            request_id,
+                        }
+                    }
+                    Err(protocol::DecodeFindNodeRequestError::BadRequestTy) => {
+                        // Other types of Kademlia requests, such as the ones related to records,
+                        // are legitimate but not supported. They are refused without being
+                        // considered as a misbehaviour of the remote.
+                        self.inner.respond_in_request(request_id, Err(()));
+                        return None;
+                    }
+                    Err(error) => {
+                        self.inner.respond_in_request(request_id, Err(()));
+                        Event::ProtocolError {
+                            peer_id,
+                            error: ProtocolError::BadKademliaFindNodeRequest(error),
+                        }
+                    }
+                },
+                4 => match protocol::decode_state_request(&request_payload) {
+                    Ok(_) => {
+                        let _prev_value = self
+                            .in_requests_types
+                            .insert(request_id, InRequestTy::State);
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::StateRequestIn {
+                            peer_id,
+                            chain_index,
+                            config: EncodedStateRequest(request_payload),
+                            request_id,
+                        }
+                    }
+                    Err(error) => {
+                        self.inner.respond_in_request(request_id, Err(()));
+                        Event::ProtocolError {
+                            peer_id,
+                            error: ProtocolError::BadStateRequest(error),
+                        }
+                    }
+                },
+                // Protocols that receive requests are whitelisted, meaning that no other
+                // protocol indices can reach here.
+                _ => unreachable!(),
             }
-        }
+        })
     }
 
     /// Called when the underlying state machine has generated a [`peers::Event::RequestInCancel`].
@@ -390,6 +540,7 @@ where
             id,
             (
                 OutRequestTy::Blocks {
+                    target: target.clone(),
                     checked: if checked { Some(config) } else { None },
                 },
                 chain_index,
@@ -727,8 +878,11 @@ where
                 Some(kademlia_operation_id),
             );
         } else {
-            self.pending_kademlia_errors
-                .push_back((kademlia_operation_id, DiscoveryError::NoPeer))
+            self.pending_kademlia_events
+                .push_back(Event::KademliaDiscoveryResult {
+                    operation_id: kademlia_operation_id,
+                    result: Err(DiscoveryError::NoPeer),
+                })
         }
 
         kademlia_operation_id
@@ -786,6 +940,181 @@ where
         id
     }
 
+    /// Starts looking for the record stored in the Kademlia DHT under the given key.
+    ///
+    /// The peers of the k-buckets of the given chain that are the closest to the key are queried,
+    /// a few at a time, until a record is found or enough peers have been queried. The peers
+    /// that are reported as closer to the key by the remotes are inserted in the k-buckets, but
+    /// only the peers the local node is connected to can be queried.
+    ///
+    /// A [`Event::KademliaGetValueResult`] is later generated with the outcome of the operation.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn start_kademlia_get_value(
+        &mut self,
+        now: TNow,
+        chain_index: usize,
+        key: Vec<u8>,
+    ) -> KademliaOperationId {
+        self.start_kademlia_value_operation(now, chain_index, key, None)
+    }
+
+    /// Starts storing a record in the Kademlia DHT.
+    ///
+    /// The record is sent to the peers of the k-buckets of the given chain that are the closest
+    /// to the key and that the local node is connected to.
+    ///
+    /// A [`Event::KademliaPutValueResult`] is later generated with the outcome of the operation.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn start_kademlia_put_value(
+        &mut self,
+        now: TNow,
+        chain_index: usize,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> KademliaOperationId {
+        self.start_kademlia_value_operation(now, chain_index, key, Some(value))
+    }
+
+    fn start_kademlia_value_operation(
+        &mut self,
+        now: TNow,
+        chain_index: usize,
+        key: Vec<u8>,
+        put_value: Option<Vec<u8>>,
+    ) -> KademliaOperationId {
+        let operation_id = self.next_kademlia_operation_id;
+        self.next_kademlia_operation_id.0 += 1;
+
+        let queried_peers = hashbrown::HashSet::with_capacity_and_hasher(
+            KADEMLIA_VALUE_MAX_QUERIED_PEERS,
+            SipHasherBuild::new(self.randomness.gen()),
+        );
+
+        let _prev_value = self.kademlia_value_operations.insert(
+            operation_id,
+            KademliaValueOperation {
+                chain_index,
+                key,
+                put_value,
+                queried_peers,
+                num_in_progress: 0,
+                num_successes: 0,
+                values: Vec::new(),
+            },
+        );
+        debug_assert!(_prev_value.is_none());
+
+        if let Some(event) = self.kademlia_value_operation_progress(&now, operation_id) {
+            self.pending_kademlia_events.push_back(event);
+        }
+
+        operation_id
+    }
+
+    /// Starts new requests for the given Kademlia get value or put value operation if
+    /// appropriate. If the operation is over, removes it and returns the corresponding event.
+    fn kademlia_value_operation_progress(
+        &mut self,
+        now: &TNow,
+        operation_id: KademliaOperationId,
+    ) -> Option<Event> {
+        let chain_index = self.kademlia_value_operations[&operation_id].chain_index;
+        let protocol_index = self.protocol_index(chain_index, 2);
+        let operation = self
+            .kademlia_value_operations
+            .get_mut(&operation_id)
+            .unwrap();
+
+        // Records are stored on all the closest peers at once, while looking for a record
+        // stops as soon as one has been found.
+        let max_in_progress = if operation.put_value.is_some() {
+            KADEMLIA_VALUE_MAX_QUERIED_PEERS
+        } else {
+            KADEMLIA_VALUE_PARALLELISM
+        };
+
+        while operation.num_in_progress < max_in_progress
+            && operation.queried_peers.len() < KADEMLIA_VALUE_MAX_QUERIED_PEERS
+            && operation.values.is_empty()
+        {
+            let next_peer = self.chains[chain_index]
+                .kbuckets
+                .closest_entries(&operation.key)
+                .map(|(peer_id, _)| peer_id)
+                .find(|peer_id| {
+                    !operation.queried_peers.contains(*peer_id)
+                        && self.inner.can_start_requests(peer_id)
+                })
+                .cloned();
+            let next_peer = match next_peer {
+                Some(p) => p,
+                None => break,
+            };
+
+            let request_data = match &operation.put_value {
+                Some(value) => protocol::build_put_value_request(&operation.key, value),
+                None => protocol::build_get_value_request(&operation.key),
+            };
+
+            // The timeout needs to be long enough to potentially download the maximum
+            // response size of 1 MiB. Assuming a 128 kiB/sec connection, that's 8 seconds.
+            let request_id = self.inner.start_request(
+                &next_peer,
+                protocol_index,
+                request_data,
+                now.clone() + Duration::from_secs(8),
+            );
+
+            let _prev_value = self.out_requests_types.insert(
+                request_id,
+                (OutRequestTy::KademliaValue(operation_id), chain_index),
+            );
+            debug_assert!(_prev_value.is_none());
+
+            operation.queried_peers.insert(next_peer);
+            operation.num_in_progress += 1;
+        }
+
+        if operation.num_in_progress != 0 {
+            return None;
+        }
+
+        let operation = self
+            .kademlia_value_operations
+            .remove(&operation_id)
+            .unwrap();
+
+        let error = if operation.queried_peers.is_empty() {
+            Some(KademliaValueError::NoPeer)
+        } else if operation.num_successes == 0 {
+            Some(KademliaValueError::AllRequestsFailed)
+        } else {
+            None
+        };
+
+        Some(if operation.put_value.is_some() {
+            Event::KademliaPutValueResult {
+                operation_id,
+                result: match error {
+                    Some(error) => Err(error),
+                    None => Ok(NonZeroUsize::new(operation.num_successes).unwrap()),
+                },
+            }
+        } else {
+            Event::KademliaGetValueResult {
+                operation_id,
+                result: match error {
+                    Some(error) => Err(error),
+                    None => Ok(operation.values),
+                },
+            }
+        })
+    }
+
     /// Returns `true` if if it possible to send requests (i.e. through
     /// [`ChainNetwork::start_grandpa_warp_sync_request`],
     /// [`ChainNetwork::start_blocks_request`], etc.) to the given peer.
@@ -811,7 +1140,9 @@ where
             protocol::build_identify_response(protocol::IdentifyResponse {
                 protocol_version: "/substrate/1.0", // TODO: same value as in Substrate
                 agent_version,
-                ed25519_public_key: *self.inner.noise_key().libp2p_public_ed25519_key(),
+                public_key: peer_id::PublicKey::Ed25519(
+                    *self.inner.noise_key().libp2p_public_ed25519_key(),
+                ),
                 listen_addrs: iter::empty(), // TODO:
                 observed_addr,
                 protocols: self
@@ -865,6 +1196,141 @@ where
 
         let _ = self.inner.respond_in_request(request_id, response);
     }
+
+    /// Queue the response to send back.
+    ///
+    /// `response` must be a SCALE-encoded Merkle proof. Pass `None` in order to deny the
+    /// request. Do this if the storage of the requested block isn't available locally.
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_storage_proof(&mut self, request_id: InRequestId, response: Option<Vec<u8>>) {
+        match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::StorageProof) => {}
+            _ => panic!(),
+        };
+
+        self.respond_storage_or_call_proof(
+            request_id,
+            protocol::StorageOrCallProof::StorageProof,
+            response,
+        );
+    }
+
+    /// Queue the response to send back.
+    ///
+    /// `response` must be a SCALE-encoded Merkle proof. Pass `None` in order to deny the
+    /// request. Do this if the storage of the requested block isn't available locally.
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_call_proof(&mut self, request_id: InRequestId, response: Option<Vec<u8>>) {
+        match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::CallProof) => {}
+            _ => panic!(),
+        };
+
+        self.respond_storage_or_call_proof(
+            request_id,
+            protocol::StorageOrCallProof::CallProof,
+            response,
+        );
+    }
+
+    fn respond_storage_or_call_proof(
+        &mut self,
+        request_id: InRequestId,
+        ty: protocol::StorageOrCallProof,
+        response: Option<Vec<u8>>,
+    ) {
+        let response = if let Some(response) = response {
+            Ok(
+                protocol::build_storage_or_call_proof_response(ty, response).fold(
+                    Vec::new(),
+                    |mut a, b| {
+                        a.extend_from_slice(b.as_ref());
+                        a
+                    },
+                ),
+            )
+        } else {
+            Err(())
+        };
+
+        self.inner.respond_in_request(request_id, response);
+    }
+
+    /// Queue the response to send back.
+    ///
+    /// `response` must be a SCALE-encoded Merkle proof. Pass `None` in order to deny the
+    /// request. Do this if the storage of the requested block isn't available locally.
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_state(&mut self, request_id: InRequestId, response: Option<Vec<u8>>) {
+        match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::State) => {}
+            _ => panic!(),
+        };
+
+        let response = if let Some(response) = response {
+            Ok(
+                protocol::build_state_response(response).fold(Vec::new(), |mut a, b| {
+                    a.extend_from_slice(b.as_ref());
+                    a
+                }),
+            )
+        } else {
+            Err(())
+        };
+
+        self.inner.respond_in_request(request_id, response);
+    }
+
+    /// Returns the peers of the k-buckets of the given chain and their known addresses, ordered
+    /// by increasing distance with the given key.
+    ///
+    /// Typically used in order to answer a [`Event::KademliaFindNodeRequestIn`].
+    pub fn kademlia_closest_peers(
+        &'_ self,
+        chain_index: usize,
+        key: &[u8],
+    ) -> impl Iterator<Item = (&'_ PeerId, impl Iterator<Item = &'_ multiaddr::Multiaddr>)> + '_
+    {
+        self.chains[chain_index]
+            .kbuckets
+            .closest_entries(key)
+            .map(move |(peer_id, _)| (peer_id, self.kbuckets_peers[peer_id].addresses.iter()))
+    }
+
+    /// Queue the response to send back.
+    ///
+    /// `closer_peers` is the list of peers to send back, and is typically obtained by calling
+    /// [`ChainNetwork::kademlia_closest_peers`].
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_kademlia_find_node(
+        &mut self,
+        request_id: InRequestId,
+        closer_peers: &[(PeerId, Vec<multiaddr::Multiaddr>)],
+    ) {
+        match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::KademliaFindNode) => {}
+            _ => panic!(),
+        };
+
+        let response = protocol::build_find_node_response(closer_peers);
+        self.inner.respond_in_request(request_id, Ok(response));
+    }
 }
 
 /// Response to an outgoing request.
@@ -961,6 +1427,20 @@ impl EncodedStateResponse {
             Err(_) => unreachable!(),
         }
     }
+
+    /// Returns the Merkle proof contained in the state response, SCALE-encoded in the format
+    /// accepted by [`crate::trie::proof_decode::decode_and_verify_proof`].
+    pub fn merkle_proof(&self) -> Vec<u8> {
+        let entries = self.decode();
+        let mut out = crate::util::encode_scale_compact_usize(entries.len())
+            .as_ref()
+            .to_vec();
+        for entry in entries {
+            out.extend_from_slice(crate::util::encode_scale_compact_usize(entry.len()).as_ref());
+            out.extend_from_slice(entry);
+        }
+        out
+    }
 }
 
 impl fmt::Debug for EncodedStateResponse {
@@ -969,6 +1449,66 @@ impl fmt::Debug for EncodedStateResponse {
     }
 }
 
+/// Undecoded but valid storage proof request.
+#[derive(Clone)]
+pub struct EncodedStorageProofRequest(Vec<u8>);
+
+impl EncodedStorageProofRequest {
+    /// Returns the decoded version of the storage proof request.
+    pub fn decode(&self) -> protocol::StorageProofRequestConfig<vec::IntoIter<&[u8]>> {
+        match protocol::decode_storage_or_call_proof_request(&self.0) {
+            Ok(protocol::StorageOrCallProofRequest::StorageProof(r)) => r,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedStorageProofRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
+
+/// Undecoded but valid call proof request.
+#[derive(Clone)]
+pub struct EncodedCallProofRequest(Vec<u8>);
+
+impl EncodedCallProofRequest {
+    /// Returns the decoded version of the call proof request.
+    pub fn decode(&self) -> protocol::CallProofRequestConfig<'_, iter::Once<&[u8]>> {
+        match protocol::decode_storage_or_call_proof_request(&self.0) {
+            Ok(protocol::StorageOrCallProofRequest::CallProof(r)) => r,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedCallProofRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
+
+/// Undecoded but valid state request.
+#[derive(Clone)]
+pub struct EncodedStateRequest(Vec<u8>);
+
+impl EncodedStateRequest {
+    /// Returns the decoded version of the state request.
+    pub fn decode(&self) -> protocol::StateRequest<'_> {
+        match protocol::decode_state_request(&self.0) {
+            Ok(r) => r,
+            Err(_) => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedStateRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
+
 /// Error during [`ChainNetwork::start_kademlia_discovery_round`].
 #[derive(Debug, derive_more::Display)]
 pub enum DiscoveryError {
@@ -979,6 +1519,16 @@ pub enum DiscoveryError {
     FindNode(KademliaFindNodeError),
 }
 
+/// Error during [`ChainNetwork::start_kademlia_get_value`] or
+/// [`ChainNetwork::start_kademlia_put_value`].
+#[derive(Debug, derive_more::Display)]
+pub enum KademliaValueError {
+    /// Not currently connected to any node of the k-buckets.
+    NoPeer,
+    /// All the requests sent to the peers have failed.
+    AllRequestsFailed,
+}
+
 /// Error during [`ChainNetwork::start_kademlia_find_node`].
 #[derive(Debug, derive_more::Display)]
 pub enum KademliaFindNodeError {
@@ -38,7 +38,7 @@ use crate::{
     verify,
 };
 
-use alloc::{borrow::Cow, vec::Vec};
+use alloc::{borrow::Cow, collections::BTreeMap, vec::Vec};
 use core::{
     cmp, iter, marker, mem,
     num::{NonZeroU32, NonZeroU64},
@@ -98,6 +98,16 @@ pub struct Config {
     /// block requests.
     pub download_ahead_blocks: NonZeroU32,
 
+    /// List of hashes of blocks that are known to be bad and that must never be added to the
+    /// chain. Their descendants are considered as bad as well.
+    ///
+    /// Sources that announce one of these blocks are reported through
+    /// [`BlockAnnounceOutcome::BannedBlock`].
+    ///
+    /// > **Note**: This list is typically filled with the list of bad blocks found in the chain
+    /// >           specification.
+    pub banned_blocks: Vec<[u8; 32]>,
+
     /// If `Some`, the block bodies and storage are also synchronized. Contains the extra
     /// configuration.
     pub full: Option<ConfigFull>,
@@ -108,6 +118,13 @@ pub struct Config {
 pub struct ConfigFull {
     /// Compiled runtime code of the finalized block.
     pub finalized_runtime: host::HostVmPrototype,
+
+    /// If `true` and the chain uses GrandPa, the state machine starts by warp syncing to the
+    /// latest finalized block of the chain and downloading its entire storage, rather than
+    /// verifying every block that follows the finalized block of [`Config::chain_information`].
+    /// The downloaded storage, including the default child tries, is reported through
+    /// [`ProcessOne::WarpSyncFinished`].
+    pub state_sync: bool,
 }
 
 /// Identifier for a source in the [`AllSync`].
@@ -155,6 +172,18 @@ pub enum Status<'a, TSrc> {
         /// Height of the block indicated by [`Status::ChainInformation::finalized_block_hash`].
         finalized_block_number: u64,
     },
+    /// Warp syncing algorithm has obtained the chain information and is downloading the storage
+    /// of the finalized block. Only happens if [`ConfigFull::state_sync`] is `true`.
+    WarpSyncStorage {
+        /// Source from which the storage is currently being downloaded, if any.
+        source: Option<(SourceId, &'a TSrc)>,
+        /// Hash of the finalized block whose storage is being downloaded.
+        finalized_block_hash: [u8; 32],
+        /// Height of the block indicated by [`Status::WarpSyncStorage::finalized_block_hash`].
+        finalized_block_number: u64,
+        /// Number of storage entries that have been downloaded so far.
+        downloaded_entries: usize,
+    },
 }
 
 pub struct AllSync<TRq, TSrc, TBl> {
@@ -166,27 +195,32 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
     /// Initializes a new state machine.
     pub fn new(config: Config) -> Self {
         let is_full = config.full.is_some();
+        let banned_blocks = config
+            .banned_blocks
+            .into_iter()
+            .collect::<hashbrown::HashSet<_, _>>();
 
         AllSync {
-            inner: if let Some(config_full) = config.full {
-                AllSyncInner::Optimistic {
+            inner: match config.full {
+                Some(config_full) if !config_full.state_sync => AllSyncInner::Optimistic {
                     inner: optimistic::OptimisticSync::new(optimistic::Config {
                         chain_information: config.chain_information,
                         block_number_bytes: config.block_number_bytes,
                         sources_capacity: config.sources_capacity,
                         blocks_capacity: config.blocks_capacity,
                         download_ahead_blocks: config.download_ahead_blocks,
+                        banned_blocks: banned_blocks.iter().copied().collect(),
                         full: Some(optimistic::ConfigFull {
                             finalized_runtime: config_full.finalized_runtime,
                         }),
                     }),
-                }
-            } else {
-                match warp_sync::start_warp_sync(warp_sync::Config {
+                },
+                full => match warp_sync::start_warp_sync(warp_sync::Config {
                     start_chain_information: config.chain_information,
                     block_number_bytes: config.block_number_bytes,
                     sources_capacity: config.sources_capacity,
                     requests_capacity: config.sources_capacity, // TODO: ?! add as config?
+                    download_storage: full.is_some(),
                 }) {
                     Ok(inner) => AllSyncInner::GrandpaWarpSync { inner },
                     Err((
@@ -203,11 +237,14 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                                 sources_capacity: config.sources_capacity,
                                 blocks_capacity: config.blocks_capacity,
                                 download_ahead_blocks: config.download_ahead_blocks,
-                                full: None,
+                                banned_blocks: banned_blocks.iter().copied().collect(),
+                                full: full.map(|config_full| optimistic::ConfigFull {
+                                    finalized_runtime: config_full.finalized_runtime,
+                                }),
                             }),
                         }
                     }
-                }
+                },
             },
             shared: Shared {
                 sources: slab::Slab::with_capacity(config.sources_capacity),
@@ -217,8 +254,10 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 blocks_capacity: config.blocks_capacity,
                 max_disjoint_headers: config.max_disjoint_headers,
                 max_requests_per_block: config.max_requests_per_block,
+                download_ahead_blocks: config.download_ahead_blocks,
                 block_number_bytes: config.block_number_bytes,
                 allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+                banned_blocks,
             },
         }
     }
@@ -276,6 +315,18 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                     finalized_block_hash,
                     finalized_block_number,
                 },
+                warp_sync::Status::Storage {
+                    source,
+                    finalized_block_hash,
+                    finalized_block_number,
+                    downloaded_entries,
+                } => Status::WarpSyncStorage {
+                    source: source
+                        .map(|(_, user_data)| (user_data.outer_source_id, &user_data.user_data)),
+                    finalized_block_hash,
+                    finalized_block_number,
+                    downloaded_entries,
+                },
             },
             AllSyncInner::Optimistic { .. } => Status::Sync, // TODO: right now we don't differentiate between AllForks and Optimistic, as they're kind of similar anyway
             AllSyncInner::Poisoned => unreachable!(),
@@ -910,6 +961,17 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                                 function_name,
                                 parameter_vectored,
                             },
+                            warp_sync::DesiredRequest::StateRequest {
+                                block_hash,
+                                state_trie_root,
+                                child_trie,
+                                start_key,
+                            } => DesiredRequest::StateRequest {
+                                block_hash,
+                                state_trie_root,
+                                child_trie,
+                                start_key,
+                            },
                         };
 
                         (
@@ -1095,6 +1157,38 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 request_mapping_entry.insert(RequestMapping::WarpSync(inner_request_id));
                 return outer_request_id;
             }
+            (
+                AllSyncInner::GrandpaWarpSync { inner },
+                RequestDetail::StateRequest {
+                    block_hash,
+                    child_trie,
+                    start_key,
+                },
+            ) => {
+                let inner_source_id = match self.shared.sources.get(source_id.0).unwrap() {
+                    SourceMapping::GrandpaWarpSync(inner_source_id) => *inner_source_id,
+                    _ => unreachable!(),
+                };
+
+                let request_mapping_entry = self.shared.requests.vacant_entry();
+                let outer_request_id = RequestId(request_mapping_entry.key());
+
+                let inner_request_id = inner.add_request(
+                    inner_source_id,
+                    GrandpaWarpSyncRequestExtra {
+                        outer_request_id,
+                        user_data,
+                    },
+                    warp_sync::RequestDetail::StateRequest {
+                        block_hash: *block_hash,
+                        child_trie: child_trie.clone(), // TODO: don't clone
+                        start_key: start_key.clone(),   // TODO: don't clone
+                    },
+                );
+
+                request_mapping_entry.insert(RequestMapping::WarpSync(inner_request_id));
+                return outer_request_id;
+            }
             (AllSyncInner::AllForks { .. }, _) => {}
             (AllSyncInner::Optimistic { .. }, _) => {}
             (AllSyncInner::GrandpaWarpSync { .. }, _) => {}
@@ -1149,12 +1243,49 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// Switches to regular syncing after the warp syncing has finished.
+    fn warp_sync_finished(
+        mut self,
+        mut success: warp_sync::Success<
+            GrandpaWarpSyncSourceExtra<TSrc>,
+            GrandpaWarpSyncRequestExtra<TRq>,
+        >,
+    ) -> ProcessOne<TRq, TSrc, TBl> {
+        let finalized_storage = success.finalized_storage.take();
+        let finalized_storage_child_tries = mem::take(&mut success.finalized_storage_child_tries);
+        debug_assert_eq!(finalized_storage.is_some(), self.shared.is_full);
+
+        let (finalized_block_runtime, finalized_storage_code, finalized_storage_heap_pages) =
+            if self.shared.is_full {
+                let storage_code = success.finalized_storage_code.take();
+                let storage_heap_pages = success.finalized_storage_heap_pages.take();
+                let (new_inner, runtime) =
+                    self.shared.transition_grandpa_warp_sync_optimistic(success);
+                self.inner = AllSyncInner::Optimistic { inner: new_inner };
+                (runtime, storage_code, storage_heap_pages)
+            } else {
+                let (new_inner, runtime, storage_code, storage_heap_pages) =
+                    self.shared.transition_grandpa_warp_sync_all_forks(success);
+                self.inner = AllSyncInner::AllForks(new_inner);
+                (runtime, storage_code, storage_heap_pages)
+            };
+
+        ProcessOne::WarpSyncFinished {
+            sync: self,
+            finalized_block_runtime,
+            finalized_storage_code,
+            finalized_storage_heap_pages,
+            finalized_storage,
+            finalized_storage_child_tries,
+        }
+    }
+
     /// Process the next block in the queue of verification.
     ///
     /// This method takes ownership of the [`AllSync`] and starts a verification process. The
     /// [`AllSync`] is yielded back at the end of this process.
     pub fn process_one(mut self) -> ProcessOne<TRq, TSrc, TBl> {
-        match self.inner {
+        match mem::replace(&mut self.inner, AllSyncInner::Poisoned) {
             AllSyncInner::GrandpaWarpSync { inner } => {
                 match inner.process_one() {
                     warp_sync::ProcessOne::Idle(inner) => {
@@ -1177,22 +1308,23 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                                 ProcessOne::AllSync(self)
                             }
                             warp_sync::WarpSync::Finished(success) => {
-                                let (
-                                    new_inner,
-                                    finalized_block_runtime,
-                                    finalized_storage_code,
-                                    finalized_storage_heap_pages,
-                                ) = self.shared.transition_grandpa_warp_sync_all_forks(success);
-                                self.inner = AllSyncInner::AllForks(new_inner);
-                                ProcessOne::WarpSyncFinished {
-                                    sync: self,
-                                    finalized_block_runtime,
-                                    finalized_storage_code,
-                                    finalized_storage_heap_pages,
-                                }
+                                self.warp_sync_finished(success)
                             }
                         }
                     }
+                    warp_sync::ProcessOne::VerifyStateResponse(inner) => match inner.verify() {
+                        (warp_sync::WarpSync::InProgress(inner), None) => {
+                            self.inner = AllSyncInner::GrandpaWarpSync { inner };
+                            ProcessOne::AllSync(self)
+                        }
+                        (warp_sync::WarpSync::InProgress(inner), Some(error)) => {
+                            self.inner = AllSyncInner::GrandpaWarpSync { inner };
+                            ProcessOne::WarpSyncError { sync: self, error }
+                        }
+                        (warp_sync::WarpSync::Finished(success), _) => {
+                            self.warp_sync_finished(success)
+                        }
+                    },
                     warp_sync::ProcessOne::BuildChainInformation(inner) => {
                         match inner.build().0 {
                             // TODO: errors not reported to upper layer
@@ -1201,19 +1333,7 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                                 ProcessOne::AllSync(self)
                             }
                             warp_sync::WarpSync::Finished(success) => {
-                                let (
-                                    new_inner,
-                                    finalized_block_runtime,
-                                    finalized_storage_code,
-                                    finalized_storage_heap_pages,
-                                ) = self.shared.transition_grandpa_warp_sync_all_forks(success);
-                                self.inner = AllSyncInner::AllForks(new_inner);
-                                ProcessOne::WarpSyncFinished {
-                                    sync: self,
-                                    finalized_block_runtime,
-                                    finalized_storage_code,
-                                    finalized_storage_heap_pages,
-                                }
+                                self.warp_sync_finished(success)
                             }
                         }
                     }
@@ -1290,10 +1410,23 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                     all_forks::BlockAnnounceOutcome::InvalidHeader(error) => {
                         BlockAnnounceOutcome::InvalidHeader(error)
                     }
+                    all_forks::BlockAnnounceOutcome::BannedBlock => {
+                        BlockAnnounceOutcome::BannedBlock
+                    }
                 }
             }
             (AllSyncInner::Optimistic { inner }, &SourceMapping::Optimistic(source_id)) => {
                 match header::decode(&announced_scale_encoded_header, inner.block_number_bytes()) {
+                    Ok(header)
+                        if self.shared.banned_blocks.contains(header.parent_hash)
+                            || self.shared.banned_blocks.contains(
+                                &header::hash_from_scale_encoded_header(
+                                    &announced_scale_encoded_header,
+                                ),
+                            ) =>
+                    {
+                        BlockAnnounceOutcome::BannedBlock
+                    }
                     Ok(header) => {
                         if is_best {
                             inner.raise_source_best_block(source_id, header.number);
@@ -1314,6 +1447,15 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 let block_number_bytes = sync.block_number_bytes();
                 match header::decode(&announced_scale_encoded_header, block_number_bytes) {
                     Err(err) => BlockAnnounceOutcome::InvalidHeader(err),
+                    Ok(header)
+                        if self.shared.banned_blocks.contains(header.parent_hash)
+                            || self
+                                .shared
+                                .banned_blocks
+                                .contains(&header.hash(block_number_bytes)) =>
+                    {
+                        BlockAnnounceOutcome::BannedBlock
+                    }
                     Ok(header) => {
                         // If GrandPa warp syncing is in progress, the best block of the source is stored
                         // in the user data. It will be useful later when transitioning to another
@@ -1373,6 +1515,47 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// Update the state machine with a Grandpa justification, for example one that has been
+    /// built locally by a Grandpa voter.
+    ///
+    /// This function only inserts the justification into the state machine, and does not
+    /// immediately verify it.
+    ///
+    /// At the moment, justifications are only accepted by the optimistic syncing strategy, and
+    /// only if they finalize the current best block. Anything else is silently discarded.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `source_id` is invalid.
+    ///
+    pub fn grandpa_justification(
+        &mut self,
+        source_id: SourceId,
+        scale_encoded_justification: Vec<u8>,
+    ) -> GrandpaJustificationOutcome {
+        let source_id = self.shared.sources.get(source_id.0).unwrap();
+
+        match (&mut self.inner, source_id) {
+            (AllSyncInner::Optimistic { inner }, SourceMapping::Optimistic(source_id)) => {
+                match inner.grandpa_justification(*source_id, scale_encoded_justification) {
+                    optimistic::GrandpaJustificationOutcome::Queued => {
+                        GrandpaJustificationOutcome::Queued
+                    }
+                    optimistic::GrandpaJustificationOutcome::ParseError
+                    | optimistic::GrandpaJustificationOutcome::NotBestBlock => {
+                        GrandpaJustificationOutcome::Discarded
+                    }
+                }
+            }
+            (AllSyncInner::AllForks(_), _) => GrandpaJustificationOutcome::Discarded,
+            (AllSyncInner::GrandpaWarpSync { .. }, _) => GrandpaJustificationOutcome::Discarded,
+
+            // Invalid internal states.
+            (AllSyncInner::Optimistic { .. }, _) => unreachable!(),
+            (AllSyncInner::Poisoned, _) => unreachable!(),
+        }
+    }
+
     /// Inject a response to a previously-emitted blocks request.
     ///
     /// # Panic
@@ -1675,6 +1858,59 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
             }
         }
     }
+
+    /// Inject a response to a previously-emitted state request.
+    ///
+    /// On success, must contain the encoded Merkle proof found in the response. See the
+    /// [`trie`](crate::trie) module for a description of the format of Merkle proofs.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`RequestId`] doesn't correspond to any request, or corresponds to a request
+    /// of a different type.
+    ///
+    pub fn state_response(
+        &mut self,
+        request_id: RequestId,
+        response: Result<Vec<u8>, ()>,
+    ) -> (TRq, ResponseOutcome) {
+        debug_assert!(self.shared.requests.contains(request_id.0));
+        let request = self.shared.requests.remove(request_id.0);
+
+        match (
+            mem::replace(&mut self.inner, AllSyncInner::Poisoned),
+            response,
+            request,
+        ) {
+            (
+                AllSyncInner::GrandpaWarpSync { inner: mut sync },
+                Ok(response),
+                RequestMapping::WarpSync(request_id),
+            ) => {
+                let user_data = sync.state_request_success(request_id, response);
+                self.inner = AllSyncInner::GrandpaWarpSync { inner: sync };
+                (user_data.user_data, ResponseOutcome::Queued)
+            }
+            (
+                AllSyncInner::GrandpaWarpSync { inner: mut sync },
+                Err(_),
+                RequestMapping::WarpSync(request_id),
+            ) => {
+                let user_data = sync.fail_request(request_id);
+                self.inner = AllSyncInner::GrandpaWarpSync { inner: sync };
+                (user_data.user_data, ResponseOutcome::Queued)
+            }
+            // Only the GrandPa warp syncing ever starts state requests.
+            (other, _, RequestMapping::Inline(_, _, user_data)) => {
+                self.inner = other;
+                (user_data, ResponseOutcome::Outdated)
+            }
+            (_, _, _) => {
+                // Type of request doesn't correspond to a state request.
+                panic!()
+            }
+        }
+    }
 }
 
 impl<TRq, TSrc, TBl> ops::Index<SourceId> for AllSync<TRq, TSrc, TBl> {
@@ -1796,6 +2032,20 @@ pub enum DesiredRequest {
         /// Concatenated SCALE-encoded parameters to provide to the call.
         parameter_vectored: Cow<'static, [u8]>,
     },
+
+    /// Sending a state request is requested.
+    StateRequest {
+        /// Hash of the block whose storage is requested.
+        block_hash: [u8; 32],
+        /// Merkle value of the root of the storage trie of the block.
+        state_trie_root: [u8; 32],
+        /// If `Some`, the request starts within the default child trie with this identifier
+        /// (without the `:child_storage:default:` prefix).
+        child_trie: Option<Vec<u8>>,
+        /// Key of the main trie, or of the child trie if `child_trie` is `Some`, to start the
+        /// request at.
+        start_key: Vec<u8>,
+    },
 }
 
 impl DesiredRequest {
@@ -1867,6 +2117,18 @@ pub enum RequestDetail {
         /// Concatenated SCALE-encoded parameters to provide to the call.
         parameter_vectored: Cow<'static, [u8]>,
     },
+
+    /// Sending a state request is requested.
+    StateRequest {
+        /// Hash of the block whose storage is requested.
+        block_hash: [u8; 32],
+        /// If `Some`, the request starts within the default child trie with this identifier
+        /// (without the `:child_storage:default:` prefix).
+        child_trie: Option<Vec<u8>>,
+        /// Key of the main trie, or of the child trie if `child_trie` is `Some`, to start the
+        /// request at.
+        start_key: Vec<u8>,
+    },
 }
 
 impl RequestDetail {
@@ -1921,6 +2183,16 @@ impl From<DesiredRequest> for RequestDetail {
                 function_name,
                 parameter_vectored,
             },
+            DesiredRequest::StateRequest {
+                block_hash,
+                child_trie,
+                start_key,
+                ..
+            } => RequestDetail::StateRequest {
+                block_hash,
+                child_trie,
+                start_key,
+            },
         }
     }
 }
@@ -1959,6 +2231,10 @@ pub enum BlockAnnounceOutcome {
     StoredForLater,
     /// Failed to decode announce header.
     InvalidHeader(header::Error),
+    /// Announced block is in the list of banned blocks, or is a descendant of a banned block.
+    /// See [`Config::banned_blocks`]. The block has been discarded, and the source should be
+    /// considered as misbehaving.
+    BannedBlock,
 
     /// Header cannot be verified now and has been silently discarded.
     Discarded,
@@ -2016,6 +2292,66 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
             }
         }
     }
+
+    /// Returns the key in the storage that immediately follows the given key, if any.
+    ///
+    /// `or_finalized` is called with a key and must return the key in the storage of the
+    /// finalized block that immediately follows it. It might be called multiple times.
+    pub fn next_key<'val: 'a>(
+        &'val self, // TODO: unclear lifetime
+        key: &[u8],
+        or_finalized: impl FnMut(&[u8]) -> Option<&'val [u8]>,
+    ) -> Option<&'val [u8]> {
+        match &self.inner {
+            BlockStorageInner::Optimistic(inner) => inner.next_key(key, or_finalized),
+        }
+    }
+
+    /// Returns the storage value at the given key of the given default child trie. `None` if
+    /// this key doesn't have any value.
+    pub fn child_trie_get<'val: 'a>(
+        &'val self, // TODO: unclear lifetime
+        child_trie: &[u8],
+        key: &[u8],
+        or_finalized: impl FnOnce() -> Option<&'val [u8]>,
+    ) -> Option<&'val [u8]> {
+        match &self.inner {
+            BlockStorageInner::Optimistic(inner) => {
+                inner.child_trie_get(child_trie, key, or_finalized)
+            }
+        }
+    }
+
+    /// Returns the key in the given default child trie that immediately follows the given key,
+    /// if any.
+    ///
+    /// `or_finalized` is called with a key and must return the key in the child trie of the
+    /// finalized block that immediately follows it. It might be called multiple times.
+    pub fn child_trie_next_key<'val: 'a>(
+        &'val self, // TODO: unclear lifetime
+        child_trie: &[u8],
+        key: &[u8],
+        or_finalized: impl FnMut(&[u8]) -> Option<&'val [u8]>,
+    ) -> Option<&'val [u8]> {
+        match &self.inner {
+            BlockStorageInner::Optimistic(inner) => {
+                inner.child_trie_next_key(child_trie, key, or_finalized)
+            }
+        }
+    }
+
+    pub fn child_trie_prefix_keys_ordered<'k: 'a>(
+        &'k self, // TODO: unclear lifetime
+        child_trie: &[u8],
+        prefix: &'k [u8],
+        in_finalized_ordered: impl Iterator<Item = impl AsRef<[u8]> + 'k> + 'k,
+    ) -> impl Iterator<Item = impl AsRef<[u8]> + 'k> + 'k {
+        match &self.inner {
+            BlockStorageInner::Optimistic(inner) => {
+                inner.child_trie_prefix_keys_ordered(child_trie, prefix, in_finalized_ordered)
+            }
+        }
+    }
 }
 
 /// Outcome of calling [`AllSync::process_one`].
@@ -2046,6 +2382,16 @@ pub enum ProcessOne<TRq, TSrc, TBl> {
 
         /// Storage value at the `:heappages` key of the finalized block.
         finalized_storage_heap_pages: Option<Vec<u8>>,
+
+        /// Entire storage of the main trie of the finalized block. `Some` if and only if
+        /// [`ConfigFull::state_sync`] was `true`, in which case the syncing continues by
+        /// verifying the blocks that follow this finalized block.
+        finalized_storage: Option<BTreeMap<Vec<u8>, Vec<u8>>>,
+
+        /// Entire storage of the default child tries of the finalized block, indexed by child
+        /// trie identifier (without the `:child_storage:default:` prefix). Always empty if
+        /// `finalized_storage` is `None`.
+        finalized_storage_child_tries: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Vec<u8>>>,
     },
 
     /// Ready to start verifying a header.
@@ -2096,6 +2442,15 @@ pub enum GrandpaCommitMessageOutcome {
     Queued,
 }
 
+/// See [`AllSync::grandpa_justification`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrandpaJustificationOutcome {
+    /// Justification has been silently discarded.
+    Discarded,
+    /// Justification has been queued for later verification.
+    Queued,
+}
+
 // TODO: doc
 #[derive(Debug, Clone)]
 pub struct Block<TBl> {
@@ -2121,6 +2476,10 @@ pub struct BlockFull {
     /// Changes to the storage made by this block compared to its parent.
     pub storage_top_trie_changes: storage_diff::StorageDiff,
 
+    /// Changes to the default child tries made by this block compared to its parent. Keys are
+    /// the identifiers of the child tries, without the `:child_storage:default:` prefix.
+    pub storage_child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
+
     /// List of changes to the off-chain storage that this block performs.
     pub offchain_storage_changes: storage_diff::StorageDiff,
 }
@@ -2194,6 +2553,9 @@ impl<TRq, TSrc, TBl> HeaderVerify<TRq, TSrc, TBl> {
                                 all_forks::HeaderVerifyError::ConsensusMismatch => {
                                     HeaderVerifyError::ConsensusMismatch
                                 }
+                                all_forks::HeaderVerifyError::BannedBlock => {
+                                    HeaderVerifyError::BannedBlock
+                                }
                             },
                             user_data,
                         }
@@ -2235,6 +2597,8 @@ pub enum HeaderVerifyError {
     /// The block verification has failed. The block is invalid and should be thrown away.
     #[display(fmt = "{}", _0)]
     VerificationFailed(verify::header_only::Error),
+    /// Block is in the list of banned blocks. See [`Config::banned_blocks`].
+    BannedBlock,
 }
 
 // TODO: should be used by the optimistic syncing as well
@@ -2333,6 +2697,7 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
                                     body: b.body,
                                     offchain_storage_changes: b.offchain_storage_changes,
                                     storage_top_trie_changes: b.storage_top_trie_changes,
+                                    storage_child_tries_changes: b.storage_child_tries_changes,
                                 }),
                             })
                             .collect(),
@@ -2446,6 +2811,15 @@ impl<TRq, TSrc, TBl> HeaderBodyVerify<TRq, TSrc, TBl> {
         }
     }
 
+    /// Returns the list of SCALE-encoded extrinsics of the block about to be verified.
+    pub fn scale_encoded_extrinsics(
+        &'_ self,
+    ) -> impl ExactSizeIterator<Item = impl AsRef<[u8]> + '_> + '_ {
+        match &self.inner {
+            HeaderBodyVerifyInner::Optimistic(verify) => verify.scale_encoded_extrinsics(),
+        }
+    }
+
     /// Start the verification process.
     pub fn start(
         self,
@@ -2509,6 +2883,8 @@ pub enum BlockVerificationError {
     /// Error while verifying a header and body.
     #[display(fmt = "{}", _0)]
     HeaderBodyError(blocks_tree::BodyVerifyError),
+    /// Block is in the list of banned blocks. See [`Config::banned_blocks`].
+    BannedBlock,
 }
 
 impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
@@ -2553,6 +2929,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                             verify::header_only::Error::NonSequentialBlockNumber,
                         ),
                     ),
+                    optimistic::ResetCause::BannedBlock => BlockVerificationError::BannedBlock,
                 },
                 user_data,
             },
@@ -2602,6 +2979,12 @@ impl<TRq, TSrc, TBl> StorageGet<TRq, TSrc, TBl> {
         self.inner.key()
     }
 
+    /// If `Some`, the request concerns the given default child trie rather than the main trie.
+    /// The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Injects the corresponding storage value.
     pub fn inject_value(self, value: Option<&[u8]>) -> BlockVerification<TRq, TSrc, TBl> {
         let inner = self.inner.inject_value(value);
@@ -2627,6 +3010,12 @@ impl<TRq, TSrc, TBl> StoragePrefixKeys<TRq, TSrc, TBl> {
         self.inner.prefix()
     }
 
+    /// If `Some`, the request concerns the given default child trie rather than the main trie.
+    /// The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Injects the list of keys ordered lexicographically.
     pub fn inject_keys_ordered(
         self,
@@ -2651,6 +3040,12 @@ impl<TRq, TSrc, TBl> StorageNextKey<TRq, TSrc, TBl> {
         self.inner.key()
     }
 
+    /// If `Some`, the request concerns the given default child trie rather than the main trie.
+    /// The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Injects the key.
     ///
     /// # Panic
@@ -2752,10 +3147,14 @@ struct Shared<TRq> {
     max_disjoint_headers: usize,
     /// Value passed through [`Config::max_requests_per_block`].
     max_requests_per_block: NonZeroU32,
+    /// Value passed through [`Config::download_ahead_blocks`].
+    download_ahead_blocks: NonZeroU32,
     /// Value passed through [`Config::block_number_bytes`].
     block_number_bytes: usize,
     /// Value passed through [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// Value passed through [`Config::banned_blocks`].
+    banned_blocks: hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>,
 }
 
 impl<TRq> Shared<TRq> {
@@ -2781,6 +3180,7 @@ impl<TRq> Shared<TRq> {
             max_disjoint_headers: self.max_disjoint_headers,
             max_requests_per_block: self.max_requests_per_block,
             allow_unknown_consensus_engines: self.allow_unknown_consensus_engines,
+            banned_blocks: self.banned_blocks.iter().copied().collect(),
             full: false,
         });
 
@@ -2789,6 +3189,114 @@ impl<TRq> Shared<TRq> {
             .iter()
             .all(|(_, s)| matches!(s, SourceMapping::GrandpaWarpSync(_))));
 
+        self.inline_grandpa_warp_sync_requests(grandpa.in_progress_requests);
+
+        for source in grandpa.sources {
+            let source_user_data = AllForksSourceExtra {
+                user_data: source.user_data,
+                outer_source_id: source.outer_source_id,
+            };
+
+            let updated_source_id = match all_forks
+                .prepare_add_source(source.best_block_number, source.best_block_hash)
+            {
+                all_forks::AddSource::BestBlockAlreadyVerified(b)
+                | all_forks::AddSource::BestBlockPendingVerification(b) => {
+                    b.add_source(source_user_data)
+                }
+                all_forks::AddSource::OldBestBlock(b) => b.add_source(source_user_data),
+                all_forks::AddSource::UnknownBestBlock(b) => {
+                    b.add_source_and_insert_block(source_user_data, None)
+                }
+            };
+
+            self.sources[source.outer_source_id.0] = SourceMapping::AllForks(updated_source_id);
+        }
+
+        debug_assert!(self
+            .sources
+            .iter()
+            .all(|(_, s)| matches!(s, SourceMapping::AllForks(_))));
+        debug_assert!(self
+            .requests
+            .iter()
+            .all(|(_, s)| matches!(s, RequestMapping::AllForks(..) | RequestMapping::Inline(..))));
+
+        (
+            all_forks,
+            grandpa.finalized_runtime,
+            grandpa.finalized_storage_code,
+            grandpa.finalized_storage_heap_pages,
+        )
+    }
+
+    /// Transitions the sync state machine from the grandpa warp strategy to the optimistic
+    /// strategy, in the situation where the storage of the finalized block has been downloaded.
+    fn transition_grandpa_warp_sync_optimistic<TSrc, TBl>(
+        &mut self,
+        grandpa: warp_sync::Success<
+            GrandpaWarpSyncSourceExtra<TSrc>,
+            GrandpaWarpSyncRequestExtra<TRq>,
+        >,
+    ) -> (
+        optimistic::OptimisticSync<OptimisticRequestExtra<TRq>, OptimisticSourceExtra<TSrc>, TBl>,
+        host::HostVmPrototype,
+    ) {
+        let mut optimistic = optimistic::OptimisticSync::new(optimistic::Config {
+            chain_information: grandpa.chain_information,
+            block_number_bytes: self.block_number_bytes,
+            sources_capacity: self.sources_capacity,
+            blocks_capacity: self.blocks_capacity,
+            download_ahead_blocks: self.download_ahead_blocks,
+            banned_blocks: self.banned_blocks.iter().copied().collect(),
+            full: Some(optimistic::ConfigFull {
+                finalized_runtime: grandpa.finalized_runtime.clone(),
+            }),
+        });
+
+        debug_assert!(self
+            .sources
+            .iter()
+            .all(|(_, s)| matches!(s, SourceMapping::GrandpaWarpSync(_))));
+
+        self.inline_grandpa_warp_sync_requests(grandpa.in_progress_requests);
+
+        for source in grandpa.sources {
+            let updated_source_id = optimistic.add_source(
+                OptimisticSourceExtra {
+                    user_data: source.user_data,
+                    outer_source_id: source.outer_source_id,
+                    best_block_hash: source.best_block_hash,
+                },
+                source.best_block_number,
+            );
+
+            self.sources[source.outer_source_id.0] = SourceMapping::Optimistic(updated_source_id);
+        }
+
+        debug_assert!(self
+            .sources
+            .iter()
+            .all(|(_, s)| matches!(s, SourceMapping::Optimistic(_))));
+        debug_assert!(self
+            .requests
+            .iter()
+            .all(|(_, s)| matches!(s, RequestMapping::Inline(..))));
+
+        (optimistic, grandpa.finalized_runtime)
+    }
+
+    /// Turns the requests that were in progress in the grandpa warp sync strategy into inline
+    /// requests, as the strategy that follows isn't interested in them.
+    fn inline_grandpa_warp_sync_requests(
+        &mut self,
+        in_progress_requests: Vec<(
+            warp_sync::SourceId,
+            warp_sync::RequestId,
+            GrandpaWarpSyncRequestExtra<TRq>,
+            warp_sync::RequestDetail,
+        )>,
+    ) {
         for (
             source_id,
             _,
@@ -2797,9 +3305,8 @@ impl<TRq> Shared<TRq> {
                 user_data,
             },
             detail,
-        ) in grandpa.in_progress_requests
+        ) in in_progress_requests
         {
-            // TODO: DRY
             let detail = match detail {
                 warp_sync::RequestDetail::WarpSyncRequest { block_hash } => {
                     RequestDetail::GrandpaWarpSync {
@@ -2818,6 +3325,15 @@ impl<TRq> Shared<TRq> {
                     function_name,
                     parameter_vectored,
                 },
+                warp_sync::RequestDetail::StateRequest {
+                    block_hash,
+                    child_trie,
+                    start_key,
+                } => RequestDetail::StateRequest {
+                    block_hash,
+                    child_trie,
+                    start_key,
+                },
             };
 
             // TODO: O(n2)
@@ -2833,44 +3349,6 @@ impl<TRq> Shared<TRq> {
             self.requests[outer_request_id.0] =
                 RequestMapping::Inline(SourceId(source_id), detail, user_data);
         }
-
-        for source in grandpa.sources {
-            let source_user_data = AllForksSourceExtra {
-                user_data: source.user_data,
-                outer_source_id: source.outer_source_id,
-            };
-
-            let updated_source_id = match all_forks
-                .prepare_add_source(source.best_block_number, source.best_block_hash)
-            {
-                all_forks::AddSource::BestBlockAlreadyVerified(b)
-                | all_forks::AddSource::BestBlockPendingVerification(b) => {
-                    b.add_source(source_user_data)
-                }
-                all_forks::AddSource::OldBestBlock(b) => b.add_source(source_user_data),
-                all_forks::AddSource::UnknownBestBlock(b) => {
-                    b.add_source_and_insert_block(source_user_data, None)
-                }
-            };
-
-            self.sources[source.outer_source_id.0] = SourceMapping::AllForks(updated_source_id);
-        }
-
-        debug_assert!(self
-            .sources
-            .iter()
-            .all(|(_, s)| matches!(s, SourceMapping::AllForks(_))));
-        debug_assert!(self
-            .requests
-            .iter()
-            .all(|(_, s)| matches!(s, RequestMapping::AllForks(..) | RequestMapping::Inline(..))));
-
-        (
-            all_forks,
-            grandpa.finalized_runtime,
-            grandpa.finalized_storage_code,
-            grandpa.finalized_storage_heap_pages,
-        )
     }
 }
 
@@ -159,6 +159,13 @@ pub struct Config {
     /// The higher the value, the more bandwidth is potentially wasted.
     pub max_requests_per_block: NonZeroU32,
 
+    /// List of hashes of blocks that are known to be bad and that must never be added to the
+    /// chain. Their descendants are considered as bad as well.
+    ///
+    /// > **Note**: This list is typically filled with the list of bad blocks found in the chain
+    /// >           specification.
+    pub banned_blocks: Vec<[u8; 32]>,
+
     /// If true, the block bodies and storage are also synchronized.
     pub full: bool,
 }
@@ -176,6 +183,9 @@ pub struct AllForksSync<TBl, TRq, TSrc> {
 /// Extra fields. In a separate structure in order to be moved around.
 struct Inner<TBl, TRq, TSrc> {
     blocks: pending_blocks::PendingBlocks<PendingBlock<TBl>, TRq, Source<TSrc>>,
+
+    /// See [`Config::banned_blocks`].
+    banned_blocks: hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>,
 }
 
 struct PendingBlock<TBl> {
@@ -433,6 +443,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                     sources_capacity: config.sources_capacity,
                     verify_bodies: config.full,
                 }),
+                banned_blocks: config.banned_blocks.into_iter().collect(),
             },
         }
     }
@@ -838,6 +849,24 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         let announced_header_parent_hash = *announced_header.parent_hash;
         let announced_header_hash = announced_header.hash(self.chain.block_number_bytes());
 
+        // Blocks that are banned, or whose parent is banned or known to be bad, are rejected
+        // altogether.
+        if self.inner.banned_blocks.contains(&announced_header_hash)
+            || self
+                .inner
+                .banned_blocks
+                .contains(&announced_header_parent_hash)
+            || matches!(
+                announced_header_number.checked_sub(1),
+                Some(parent_number) if self
+                    .inner
+                    .blocks
+                    .is_unverified_block_bad(parent_number, &announced_header_parent_hash)
+            )
+        {
+            return BlockAnnounceOutcome::BannedBlock;
+        }
+
         // It is assumed that all sources will eventually agree on the same finalized chain. If
         // the block number is lower or equal than the locally-finalized block number, it is
         // assumed that this source is simply late compared to the local node, and that the block
@@ -1473,6 +1502,10 @@ pub enum BlockAnnounceOutcome<'a, TBl, TRq, TSrc> {
 
     /// Failed to decode announce header.
     InvalidHeader(header::Error),
+
+    /// Announced block is in the list of banned blocks, or is a descendant of a banned block.
+    /// The block has been discarded, and the source should be considered as misbehaving.
+    BannedBlock,
 }
 
 /// See [`BlockAnnounceOutcome`] and [`AllForksSync::block_announce`].
@@ -1855,6 +1888,25 @@ impl<TBl, TRq, TSrc> HeaderVerify<TBl, TRq, TSrc> {
 
     /// Perform the verification.
     pub fn perform(mut self, now_from_unix_epoch: Duration) -> HeaderVerifyOutcome<TBl, TRq, TSrc> {
+        // Banned blocks are marked as bad without even being verified. Marking a block as bad
+        // also marks all its descendants as bad, guaranteeing that they are never verified.
+        if self
+            .parent
+            .inner
+            .banned_blocks
+            .contains(&self.block_to_verify.block_hash)
+        {
+            self.parent.inner.blocks.mark_unverified_block_as_bad(
+                self.block_to_verify.block_number,
+                &self.block_to_verify.block_hash,
+            );
+
+            return HeaderVerifyOutcome::Error {
+                sync: self.parent,
+                error: HeaderVerifyError::BannedBlock,
+            };
+        }
+
         let to_verify_scale_encoded_header = self
             .parent
             .inner
@@ -2141,6 +2193,8 @@ pub enum HeaderVerifyError {
     /// The block verification has failed. The block is invalid and should be thrown away.
     #[display(fmt = "{}", _0)]
     VerificationFailed(verify::header_only::Error),
+    /// Block is in the list of banned blocks passed at initialization.
+    BannedBlock,
 }
 
 /// Information about the outcome of verifying a finality proof.
@@ -2209,3 +2263,146 @@ pub enum BlockBodyVerify<TBl, TRq, TSrc> {
     /// order to continue.
     FinalizedStorageNextKey(StorageNextKey<TBl, TRq, TSrc>),*/
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{chain::chain_information, header};
+    use core::{iter, num::NonZeroU32, time::Duration};
+
+    fn genesis_chain_information() -> chain_information::ValidChainInformation {
+        chain_information::ChainInformation {
+            finalized_block_header: header::Header {
+                parent_hash: [0; 32],
+                number: 0,
+                state_root: [1; 32],
+                extrinsics_root: [2; 32],
+                digest: header::DigestRef::empty().into(),
+            },
+            consensus: chain_information::ChainInformationConsensus::Unknown,
+            finality: chain_information::ChainInformationFinality::Outsourced,
+        }
+        .try_into()
+        .unwrap()
+    }
+
+    fn child_header(parent: &header::Header) -> header::Header {
+        header::Header {
+            parent_hash: parent.hash(4),
+            number: parent.number + 1,
+            state_root: [1; 32],
+            extrinsics_root: [2; 32],
+            digest: header::DigestRef::empty().into(),
+        }
+    }
+
+    /// Builds an [`super::AllForksSync`] whose finalized block is a genesis block, plus a chain
+    /// of four blocks built on top of it. The first of these blocks is banned.
+    fn sync_with_banned_block() -> (super::AllForksSync<(), (), ()>, Vec<header::Header>) {
+        let chain_information = genesis_chain_information();
+        let mut blocks = vec![chain_information.as_ref().finalized_block_header.into()];
+        for _ in 0..4 {
+            let child = child_header(blocks.last().unwrap());
+            blocks.push(child);
+        }
+        blocks.remove(0);
+
+        let sync = super::AllForksSync::new(super::Config {
+            chain_information,
+            block_number_bytes: 4,
+            allow_unknown_consensus_engines: true,
+            sources_capacity: 16,
+            blocks_capacity: 16,
+            max_disjoint_headers: 16,
+            max_requests_per_block: NonZeroU32::new(1).unwrap(),
+            banned_blocks: vec![blocks[0].hash(4)],
+            full: false,
+        });
+
+        (sync, blocks)
+    }
+
+    fn add_source(sync: &mut super::AllForksSync<(), (), ()>) -> super::SourceId {
+        match sync.prepare_add_source(0, sync.finalized_block_header().hash(4)) {
+            super::AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn banned_block_announce_rejected() {
+        let (mut sync, blocks) = sync_with_banned_block();
+        let source_id = add_source(&mut sync);
+
+        assert!(matches!(
+            sync.block_announce(source_id, blocks[0].scale_encoding_vec(4), true),
+            super::BlockAnnounceOutcome::BannedBlock
+        ));
+    }
+
+    #[test]
+    fn banned_block_child_announce_rejected() {
+        let (mut sync, blocks) = sync_with_banned_block();
+        let source_id = add_source(&mut sync);
+
+        assert!(matches!(
+            sync.block_announce(source_id, blocks[1].scale_encoding_vec(4), true),
+            super::BlockAnnounceOutcome::BannedBlock
+        ));
+    }
+
+    #[test]
+    fn banned_block_descendant_rejected_after_verification() {
+        // The banned block is only discovered through an ancestry search. Verifying it fails and
+        // marks all its known descendants as bad, after which these descendants and their
+        // children are rejected when announced.
+        let (mut sync, blocks) = sync_with_banned_block();
+        let source_id = add_source(&mut sync);
+
+        match sync.block_announce(source_id, blocks[2].scale_encoding_vec(4), true) {
+            super::BlockAnnounceOutcome::Unknown(announce) => announce.insert_and_update_source(()),
+            _ => panic!(),
+        }
+
+        let (_, _, request_params) = sync.desired_requests().next().unwrap();
+        assert_eq!(request_params.first_block_hash, blocks[1].hash(4));
+        let request_id = sync.add_request(source_id, request_params, ());
+
+        let (_, mut ancestry_search) = sync.finish_ancestry_search(request_id);
+        for block in [&blocks[1], &blocks[0]] {
+            ancestry_search = match ancestry_search
+                .add_block(
+                    &block.scale_encoding_vec(4),
+                    iter::empty::<([u8; 4], Vec<u8>)>(),
+                )
+                .unwrap_or_else(|_| panic!())
+            {
+                super::AddBlock::UnknownBlock(add) => add.insert(()),
+                _ => panic!(),
+            };
+        }
+        let sync = ancestry_search.finish();
+
+        let mut sync = match sync.process_one() {
+            super::ProcessOne::HeaderVerify(verify) => {
+                assert_eq!(*verify.hash(), blocks[0].hash(4));
+                match verify.perform(Duration::new(0, 0)) {
+                    super::HeaderVerifyOutcome::Error {
+                        sync,
+                        error: super::HeaderVerifyError::BannedBlock,
+                    } => sync,
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        };
+
+        assert!(matches!(
+            sync.block_announce(source_id, blocks[2].scale_encoding_vec(4), true),
+            super::BlockAnnounceOutcome::BannedBlock
+        ));
+        assert!(matches!(
+            sync.block_announce(source_id, blocks[3].scale_encoding_vec(4), true),
+            super::BlockAnnounceOutcome::BannedBlock
+        ));
+    }
+}
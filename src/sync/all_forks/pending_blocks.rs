@@ -670,6 +670,12 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         self.blocks.set_block_bad(height, hash);
     }
 
+    /// Returns `true` if the given unverified block is in the data structure and has been marked
+    /// as bad, either directly or because one of its ancestors is bad.
+    pub fn is_unverified_block_bad(&self, height: u64, hash: &[u8; 32]) -> bool {
+        self.blocks.is_bad(height, hash).unwrap_or(false)
+    }
+
     /// Returns the number of unverified blocks stored in the data structure.
     pub fn num_unverified_blocks(&self) -> usize {
         self.blocks.len()
@@ -1131,3 +1137,56 @@ pub struct RequestParams {
     /// than requested.
     pub num_blocks: NonZeroU64,
 }
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU32;
+
+    #[test]
+    fn descendants_of_bad_block_are_bad() {
+        let mut collection = super::PendingBlocks::<(), (), ()>::new(super::Config {
+            blocks_capacity: 16,
+            sources_capacity: 16,
+            finalized_block_height: 0,
+            verify_bodies: false,
+            max_requests_per_block: NonZeroU32::new(1).unwrap(),
+        });
+
+        collection.insert_unverified_block(
+            1,
+            [1; 32],
+            super::UnverifiedBlockState::HeaderKnown {
+                parent_hash: [0; 32],
+            },
+            (),
+        );
+        collection.insert_unverified_block(
+            2,
+            [2; 32],
+            super::UnverifiedBlockState::HeaderKnown {
+                parent_hash: [1; 32],
+            },
+            (),
+        );
+        assert!(!collection.is_unverified_block_bad(1, &[1; 32]));
+        assert!(!collection.is_unverified_block_bad(2, &[2; 32]));
+
+        collection.mark_unverified_block_as_bad(1, &[1; 32]);
+        assert!(collection.is_unverified_block_bad(1, &[1; 32]));
+        assert!(collection.is_unverified_block_bad(2, &[2; 32]));
+
+        // Blocks inserted afterwards whose parent is bad are bad as well.
+        collection.insert_unverified_block(
+            3,
+            [3; 32],
+            super::UnverifiedBlockState::HeaderKnown {
+                parent_hash: [2; 32],
+            },
+            (),
+        );
+        assert!(collection.is_unverified_block_bad(3, &[3; 32]));
+
+        // Unknown blocks aren't considered as bad.
+        assert!(!collection.is_unverified_block_bad(3, &[4; 32]));
+    }
+}
@@ -48,6 +48,7 @@
 use crate::{
     chain::{blocks_tree, chain_information},
     executor::{host, storage_diff},
+    finality::justification,
     header,
     trie::calculate_root,
 };
@@ -55,7 +56,7 @@ use crate::{
 use alloc::{
     borrow::ToOwned as _,
     boxed::Box,
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     vec::{self, Vec},
 };
 use core::{
@@ -98,6 +99,11 @@ pub struct Config {
     /// block requests.
     pub download_ahead_blocks: NonZeroU32,
 
+    /// List of hashes of blocks that are known to be bad and that must never be added to the
+    /// chain. Encountering one of these blocks resets the chain, similar to a verification
+    /// failure.
+    pub banned_blocks: Vec<[u8; 32]>,
+
     /// If `Some`, the block bodies and storage are also synchronized. Contains the extra
     /// configuration.
     pub full: Option<ConfigFull>,
@@ -159,6 +165,11 @@ struct OptimisticSyncInner<TRq, TSrc, TBl> {
     /// value has been erased from the storage.
     best_to_finalized_storage_diff: storage_diff::StorageDiff,
 
+    /// Same as [`OptimisticSyncInner::best_to_finalized_storage_diff`], but for the default
+    /// child tries. Keys are the identifiers of the child tries, without the
+    /// `:child_storage:default:` prefix.
+    best_to_finalized_child_tries_diffs: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
+
     /// Compiled runtime code of the best block. `None` if it is the same as
     /// [`OptimisticSyncInner::finalized_runtime`].
     best_runtime: Option<host::HostVmPrototype>,
@@ -170,6 +181,9 @@ struct OptimisticSyncInner<TRq, TSrc, TBl> {
     /// See [`Config::download_ahead_blocks`].
     download_ahead_blocks: NonZeroU32,
 
+    /// See [`Config::banned_blocks`].
+    banned_blocks: hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>,
+
     /// List of sources of blocks.
     sources: HashMap<SourceId, Source<TSrc>, fnv::FnvBuildHasher>,
 
@@ -266,6 +280,10 @@ pub struct BlockFull {
     /// Changes to the storage made by this block compared to its parent.
     pub storage_top_trie_changes: storage_diff::StorageDiff,
 
+    /// Changes to the default child tries made by this block compared to its parent. Keys are
+    /// the identifiers of the child tries, without the `:child_storage:default:` prefix.
+    pub storage_child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
+
     /// List of changes to the off-chain storage that this block performs.
     pub offchain_storage_changes: storage_diff::StorageDiff,
 }
@@ -294,6 +312,7 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
                 finalized_chain_information: blocks_tree_config,
                 finalized_runtime: config.full.map(|f| f.finalized_runtime),
                 best_to_finalized_storage_diff: storage_diff::StorageDiff::empty(),
+                best_to_finalized_child_tries_diffs: BTreeMap::new(),
                 best_runtime: None,
                 top_trie_root_calculation_cache: None,
                 sources: HashMap::with_capacity_and_hasher(
@@ -306,6 +325,7 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
                 ),
                 pending_encoded_justifications: Vec::new().into_iter(),
                 download_ahead_blocks: config.download_ahead_blocks,
+                banned_blocks: config.banned_blocks.into_iter().collect(),
                 next_request_id: RequestId(0),
                 obsolete_requests: HashMap::with_capacity_and_hasher(0, Default::default()),
                 obsolete_requests_by_source: BTreeSet::new(),
@@ -711,6 +731,51 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         user_data
     }
 
+    /// Queues a Grandpa justification, for example one that has been built locally by a
+    /// Grandpa voter, for verification.
+    ///
+    /// Only justifications that finalize the current best block can be verified by this state
+    /// machine. This function does nothing and returns
+    /// [`GrandpaJustificationOutcome::NotBestBlock`] if that is not the case.
+    ///
+    /// The justification is verified the next time [`OptimisticSync::process_one`] is called.
+    /// If it turns out to be invalid, `source_id` is banned and the chain is reset to the
+    /// latest finalized block.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `source_id` is invalid.
+    ///
+    pub fn grandpa_justification(
+        &mut self,
+        source_id: SourceId,
+        scale_encoded_justification: Vec<u8>,
+    ) -> GrandpaJustificationOutcome {
+        assert!(self.inner.sources.contains_key(&source_id));
+
+        let target_hash = match justification::decode::decode_grandpa(
+            &scale_encoded_justification,
+            self.chain.block_number_bytes(),
+        ) {
+            Ok(j) => *j.target_hash,
+            Err(_) => return GrandpaJustificationOutcome::ParseError,
+        };
+
+        if self.chain.is_empty() || target_hash != self.chain.best_block_hash() {
+            return GrandpaJustificationOutcome::NotBestBlock;
+        }
+
+        let mut pending = mem::replace(
+            &mut self.inner.pending_encoded_justifications,
+            Vec::new().into_iter(),
+        )
+        .collect::<Vec<_>>();
+        pending.push((*b"FRNK", scale_encoded_justification, source_id));
+        self.inner.pending_encoded_justifications = pending.into_iter();
+
+        GrandpaJustificationOutcome::Queued
+    }
+
     /// Process the next block in the queue of verification.
     ///
     /// This method takes ownership of the [`OptimisticSync`]. The [`OptimisticSync`] is yielded
@@ -765,6 +830,18 @@ pub struct RequestSuccessBlock<TBl> {
     pub user_data: TBl,
 }
 
+/// Outcome of calling [`OptimisticSync::grandpa_justification`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrandpaJustificationOutcome {
+    /// Justification has been queued for later verification.
+    Queued,
+    /// Failed to decode the justification.
+    ParseError,
+    /// The justification doesn't target the current best block, or the current best block is
+    /// already finalized.
+    NotBestBlock,
+}
+
 /// State of the processing of blocks.
 pub enum ProcessOne<TRq, TSrc, TBl> {
     /// No processing is necessary.
@@ -819,6 +896,102 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
             .best_to_finalized_storage_diff
             .storage_prefix_keys_ordered(prefix, in_finalized_ordered)
     }
+
+    /// Returns the key in the storage that immediately follows the given key, if any.
+    ///
+    /// `or_finalized` is called with a key and must return the key in the storage of the
+    /// finalized block that immediately follows it. It might be called multiple times.
+    pub fn next_key<'val: 'a>(
+        &'val self, // TODO: unclear lifetime
+        key: &[u8],
+        or_finalized: impl FnMut(&[u8]) -> Option<&'val [u8]>,
+    ) -> Option<&'val [u8]> {
+        storage_diff_next_key(
+            &self.inner.inner.best_to_finalized_storage_diff,
+            key,
+            or_finalized,
+        )
+    }
+
+    /// Returns the storage value at the given key of the given default child trie. `None` if
+    /// this key doesn't have any value.
+    pub fn child_trie_get<'val: 'a>(
+        &'val self, // TODO: unclear lifetime
+        child_trie: &[u8],
+        key: &[u8],
+        or_finalized: impl FnOnce() -> Option<&'val [u8]>,
+    ) -> Option<&'val [u8]> {
+        match self
+            .inner
+            .inner
+            .best_to_finalized_child_tries_diffs
+            .get(child_trie)
+        {
+            Some(diff) => diff.storage_get(key, or_finalized),
+            None => or_finalized(),
+        }
+    }
+
+    /// Returns the key in the given default child trie that immediately follows the given key,
+    /// if any.
+    ///
+    /// `or_finalized` is called with a key and must return the key in the child trie of the
+    /// finalized block that immediately follows it. It might be called multiple times.
+    pub fn child_trie_next_key<'val: 'a>(
+        &'val self, // TODO: unclear lifetime
+        child_trie: &[u8],
+        key: &[u8],
+        mut or_finalized: impl FnMut(&[u8]) -> Option<&'val [u8]>,
+    ) -> Option<&'val [u8]> {
+        match self
+            .inner
+            .inner
+            .best_to_finalized_child_tries_diffs
+            .get(child_trie)
+        {
+            Some(diff) => storage_diff_next_key(diff, key, or_finalized),
+            None => or_finalized(key),
+        }
+    }
+
+    /// Returns the keys of the given default child trie that start with the given prefix.
+    pub fn child_trie_prefix_keys_ordered<'k: 'a>(
+        &'k self, // TODO: unclear lifetime
+        child_trie: &[u8],
+        prefix: &'k [u8],
+        in_finalized_ordered: impl Iterator<Item = impl AsRef<[u8]> + 'k> + 'k,
+    ) -> impl Iterator<Item = impl AsRef<[u8]> + 'k> + 'k {
+        match self
+            .inner
+            .inner
+            .best_to_finalized_child_tries_diffs
+            .get(child_trie)
+        {
+            Some(diff) => either::Left(
+                diff.storage_prefix_keys_ordered(prefix, in_finalized_ordered)
+                    .map(either::Left),
+            ),
+            None => either::Right(in_finalized_ordered.map(either::Right)),
+        }
+    }
+}
+
+/// Returns the key that immediately follows `key` after `diff` has been applied on top of the
+/// storage whose next keys are provided by `in_parent_next_key`.
+fn storage_diff_next_key<'a>(
+    diff: &'a storage_diff::StorageDiff,
+    key: &[u8],
+    mut in_parent_next_key: impl FnMut(&[u8]) -> Option<&'a [u8]>,
+) -> Option<&'a [u8]> {
+    let mut search = diff.storage_next_key(key, in_parent_next_key(key));
+    loop {
+        match search {
+            storage_diff::StorageNextKey::Found(k) => return k,
+            storage_diff::StorageNextKey::NextOf(next) => {
+                search = diff.storage_next_key(next, in_parent_next_key(next));
+            }
+        }
+    }
 }
 
 /// Start the processing of a block verification.
@@ -856,6 +1029,18 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
             .scale_encoded_header
     }
 
+    /// Returns the list of SCALE-encoded extrinsics of the block about to be verified.
+    pub fn scale_encoded_extrinsics(
+        &'_ self,
+    ) -> impl ExactSizeIterator<Item = impl AsRef<[u8]> + '_> + '_ {
+        self.inner
+            .verification_queue
+            .first_block()
+            .unwrap()
+            .scale_encoded_extrinsics
+            .iter()
+    }
+
     /// Start the verification of the block.
     ///
     /// Must be passed the current UNIX time in order to verify that the block doesn't pretend to
@@ -866,6 +1051,43 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
         // Be aware that `source_id` might refer to an obsolete source.
         let (block, source_id) = self.inner.verification_queue.pop_first_block().unwrap();
 
+        // Banned blocks are treated the same way as blocks that fail to verify, except that the
+        // verification isn't even attempted.
+        if self
+            .inner
+            .banned_blocks
+            .contains(&header::hash_from_scale_encoded_header(
+                &block.scale_encoded_header,
+            ))
+        {
+            if let Some(src) = self.inner.sources.get_mut(&source_id) {
+                src.banned = true;
+            }
+
+            // If all sources are banned, unban them.
+            if self.inner.sources.iter().all(|(_, s)| s.banned) {
+                for src in self.inner.sources.values_mut() {
+                    src.banned = false;
+                }
+            }
+
+            let previous_best_height = self.chain.best_block_header().number;
+            let chain =
+                blocks_tree::NonFinalizedTree::new(self.inner.finalized_chain_information.clone());
+
+            let mut inner = self.inner.with_requests_obsoleted(&chain);
+            inner.best_to_finalized_storage_diff = Default::default();
+            inner.best_to_finalized_child_tries_diffs = Default::default();
+            inner.best_runtime = None;
+            inner.top_trie_root_calculation_cache = None;
+
+            return BlockVerification::Reset {
+                sync: OptimisticSync { chain, inner },
+                previous_best_height,
+                reason: ResetCause::BannedBlock,
+            };
+        }
+
         debug_assert!(self
             .inner
             .pending_encoded_justifications
@@ -934,6 +1156,7 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
 
                 self.inner.make_requests_obsolete(&self.chain);
                 self.inner.best_to_finalized_storage_diff = Default::default();
+                self.inner.best_to_finalized_child_tries_diffs = Default::default();
                 self.inner.best_runtime = None;
                 self.inner.top_trie_root_calculation_cache = None;
 
@@ -1054,6 +1277,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
 
                 Inner::Step2(blocks_tree::BodyVerifyStep2::Finished {
                     storage_top_trie_changes,
+                    storage_child_tries_changes,
                     offchain_storage_changes,
                     top_trie_root_calculation_cache,
                     parent_runtime,
@@ -1095,6 +1319,14 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                         .inner
                         .best_to_finalized_storage_diff
                         .merge(&storage_top_trie_changes);
+                    for (child_trie, changes) in &storage_child_tries_changes {
+                        shared
+                            .inner
+                            .best_to_finalized_child_tries_diffs
+                            .entry(child_trie.clone())
+                            .or_default()
+                            .merge(changes);
+                    }
 
                     let chain = {
                         let header = insert.header().into();
@@ -1105,6 +1337,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                             full: Some(BlockFull {
                                 body: mem::take(&mut shared.block_body),
                                 storage_top_trie_changes,
+                                storage_child_tries_changes,
                                 offchain_storage_changes,
                             }),
                         })
@@ -1131,10 +1364,17 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                     // As such, the requested value is either found in one of this diff, in which
                     // case it can be returned immediately to continue the verification, or in
                     // the finalized block, in which case the user needs to be queried.
-                    let value = shared
-                        .inner
-                        .best_to_finalized_storage_diff
-                        .diff_get(req.key().as_ref());
+                    let value = match req.child_trie() {
+                        Some(child_trie) => shared
+                            .inner
+                            .best_to_finalized_child_tries_diffs
+                            .get(child_trie.as_ref())
+                            .and_then(|diff| diff.diff_get(req.key().as_ref())),
+                        None => shared
+                            .inner
+                            .best_to_finalized_storage_diff
+                            .diff_get(req.key().as_ref()),
+                    };
                     if let Some(value) = value {
                         inner = Inner::Step2(
                             req.inject_value(value.as_ref().map(|v| iter::once(&v[..]))),
@@ -1206,6 +1446,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
 
                     let mut inner = shared.inner.with_requests_obsoleted(&chain);
                     inner.best_to_finalized_storage_diff = Default::default();
+                    inner.best_to_finalized_child_tries_diffs = Default::default();
                     inner.best_runtime = None;
                     inner.top_trie_root_calculation_cache = None;
 
@@ -1237,6 +1478,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
 
                     let mut inner = shared.inner.with_requests_obsoleted(&chain);
                     inner.best_to_finalized_storage_diff = Default::default();
+                    inner.best_to_finalized_child_tries_diffs = Default::default();
                     inner.best_runtime = None;
                     inner.top_trie_root_calculation_cache = None;
 
@@ -1270,6 +1512,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
 
                     let mut inner = shared.inner.with_requests_obsoleted(&chain);
                     inner.best_to_finalized_storage_diff = Default::default();
+                    inner.best_to_finalized_child_tries_diffs = Default::default();
                     inner.best_runtime = None;
                     inner.top_trie_root_calculation_cache = None;
 
@@ -1329,6 +1572,7 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
 
                 let mut inner = self.inner.with_requests_obsoleted(&chain);
                 inner.best_to_finalized_storage_diff = Default::default();
+                inner.best_to_finalized_child_tries_diffs = Default::default();
                 inner.best_runtime = None;
                 inner.top_trie_root_calculation_cache = None;
 
@@ -1369,6 +1613,7 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
         // diff.
         debug_assert!(self.chain.is_empty());
         self.inner.best_to_finalized_storage_diff.clear();
+        self.inner.best_to_finalized_child_tries_diffs.clear();
 
         if let Some(runtime) = self.inner.best_runtime.take() {
             self.inner.finalized_runtime = Some(runtime);
@@ -1421,6 +1666,12 @@ impl<TRq, TSrc, TBl> StorageGet<TRq, TSrc, TBl> {
         self.inner.key()
     }
 
+    /// If `Some`, the key must be read from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Injects the corresponding storage value.
     pub fn inject_value(self, value: Option<&[u8]>) -> BlockVerification<TRq, TSrc, TBl> {
         let inner = self.inner.inject_value(value.map(iter::once));
@@ -1441,6 +1692,12 @@ impl<TRq, TSrc, TBl> StoragePrefixKeys<TRq, TSrc, TBl> {
         self.inner.prefix()
     }
 
+    /// If `Some`, the keys must be loaded from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Injects the list of keys ordered lexicographically.
     pub fn inject_keys_ordered(
         self,
@@ -1449,11 +1706,21 @@ impl<TRq, TSrc, TBl> StoragePrefixKeys<TRq, TSrc, TBl> {
         // We need to turn the prefix into a Vec, as otherwise the iterator would borrow
         // self.inner.
         let owned_prefix = self.inner.prefix().as_ref().to_owned();
-        let list_after_diff = self
-            .shared
-            .inner
-            .best_to_finalized_storage_diff
-            .storage_prefix_keys_ordered(&owned_prefix, keys);
+        let diff = match self.inner.child_trie() {
+            Some(child_trie) => self
+                .shared
+                .inner
+                .best_to_finalized_child_tries_diffs
+                .get(child_trie.as_ref()),
+            None => Some(&self.shared.inner.best_to_finalized_storage_diff),
+        };
+        let list_after_diff = match diff {
+            Some(diff) => either::Left(
+                diff.storage_prefix_keys_ordered(&owned_prefix, keys)
+                    .map(either::Left),
+            ),
+            None => either::Right(keys.map(either::Right)),
+        };
         let inner = self.inner.inject_keys_ordered(list_after_diff);
         BlockVerification::from(Inner::Step2(inner), self.shared)
     }
@@ -1479,6 +1746,13 @@ impl<TRq, TSrc, TBl> StorageNextKey<TRq, TSrc, TBl> {
         }
     }
 
+    /// If `Some`, the next key must be searched in the given default child trie rather than in
+    /// the main trie. The child trie identifier doesn't include the `:child_storage:default:`
+    /// prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Injects the key.
     ///
     /// # Panic
@@ -1495,17 +1769,23 @@ impl<TRq, TSrc, TBl> StorageNextKey<TRq, TSrc, TBl> {
 
         let search = {
             let inner_key = self.inner.key();
-            self.shared
-                .inner
-                .best_to_finalized_storage_diff
-                .storage_next_key(
-                    if let Some(key_overwrite) = &self.key_overwrite {
-                        key_overwrite
-                    } else {
-                        inner_key.as_ref()
-                    },
-                    key,
-                )
+            let requested_key = if let Some(key_overwrite) = &self.key_overwrite {
+                key_overwrite
+            } else {
+                inner_key.as_ref()
+            };
+            let diff = match self.inner.child_trie() {
+                Some(child_trie) => self
+                    .shared
+                    .inner
+                    .best_to_finalized_child_tries_diffs
+                    .get(child_trie.as_ref()),
+                None => Some(&self.shared.inner.best_to_finalized_storage_diff),
+            };
+            match diff {
+                Some(diff) => diff.storage_next_key(requested_key, key),
+                None => storage_diff::StorageNextKey::Found(key),
+            }
         };
 
         match search {
@@ -1603,6 +1883,8 @@ pub enum ResetCause {
     HeaderBodyError(blocks_tree::BodyVerifyError),
     /// Received block isn't a child of the current best block.
     NonCanonical,
+    /// Received block is in the list of banned blocks.
+    BannedBlock,
 }
 
 /// Output of [`OptimisticSync::disassemble`].
@@ -1631,3 +1913,86 @@ pub struct DisassembleSource<TSrc> {
     /// Best block that the source has reported having.
     pub best_block_number: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{chain::chain_information, header};
+    use core::{
+        num::{NonZeroU32, NonZeroU64},
+        time::Duration,
+    };
+
+    #[test]
+    fn banned_block_resets_and_bans_source() {
+        let chain_information: chain_information::ValidChainInformation =
+            chain_information::ChainInformation {
+                finalized_block_header: header::Header {
+                    parent_hash: [0; 32],
+                    number: 0,
+                    state_root: [1; 32],
+                    extrinsics_root: [2; 32],
+                    digest: header::DigestRef::empty().into(),
+                },
+                consensus: chain_information::ChainInformationConsensus::Unknown,
+                finality: chain_information::ChainInformationFinality::Outsourced,
+            }
+            .try_into()
+            .unwrap();
+
+        let banned_block = header::Header {
+            parent_hash: chain_information.as_ref().finalized_block_header.hash(4),
+            number: 1,
+            state_root: [1; 32],
+            extrinsics_root: [2; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+
+        let mut sync = super::OptimisticSync::<(), (), ()>::new(super::Config {
+            chain_information,
+            block_number_bytes: 4,
+            sources_capacity: 16,
+            blocks_capacity: 16,
+            download_ahead_blocks: NonZeroU32::new(16).unwrap(),
+            banned_blocks: vec![banned_block.hash(4)],
+            full: None,
+        });
+
+        let misbehaving_source = sync.add_source((), 1);
+        let other_source = sync.add_source((), 1);
+
+        let request_id = sync.insert_request(
+            super::RequestDetail {
+                source_id: misbehaving_source,
+                block_height: NonZeroU64::new(1).unwrap(),
+                num_blocks: NonZeroU32::new(1).unwrap(),
+            },
+            (),
+        );
+        sync.finish_request_success(
+            request_id,
+            [super::RequestSuccessBlock {
+                scale_encoded_header: banned_block.scale_encoding_vec(4),
+                scale_encoded_justifications: Vec::new(),
+                scale_encoded_extrinsics: Vec::new(),
+                user_data: (),
+            }]
+            .into_iter(),
+        );
+
+        let sync = match sync.process_one() {
+            super::ProcessOne::VerifyBlock(verify) => match verify.start(Duration::new(0, 0)) {
+                super::BlockVerification::Reset {
+                    sync,
+                    previous_best_height: 0,
+                    reason: super::ResetCause::BannedBlock,
+                } => sync,
+                _ => panic!(),
+            },
+            _ => panic!(),
+        };
+
+        assert_eq!(sync.best_block_number(), 0);
+        assert!(sync.inner.sources[&misbehaving_source].banned);
+        assert!(!sync.inner.sources[&other_source].banned);
+    }
+}
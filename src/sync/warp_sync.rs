@@ -66,6 +66,9 @@
 //! - Performing some runtime calls in order to obtain the current consensus-related parameters
 //! of the chain. This might require obtaining some storage items, in which case they must also
 //! be downloaded from a source.
+//! - Optionally, downloading the entire storage of the final block of the proof. The storage
+//!   is downloaded through state requests, each response containing a Merkle proof of the
+//!   entries that follow a certain key.
 //!
 //! At the end of the syncing, a [`ValidChainInformation`] corresponding to the head of the chain
 //! is yielded.
@@ -103,11 +106,13 @@ use crate::{
     },
     finality::grandpa::warp_sync,
     header::{self, Header},
-    trie::proof_decode,
+    trie::{self, proof_decode},
 };
 
 use alloc::{
     borrow::{Cow, ToOwned as _},
+    boxed::Box,
+    collections::BTreeMap,
     vec,
     vec::Vec,
 };
@@ -136,9 +141,6 @@ pub enum Error {
     /// Merkle proof is missing the necessary entries.
     // TODO: this is a non-fatal error contrary to all the other errors in this enum
     MerkleProofEntriesMissing,
-    /// Warp sync requires fetching the key that follows another one. This isn't implemented in
-    /// smoldot.
-    NextKeyUnimplemented,
 }
 
 /// The configuration for [`start_warp_sync()`].
@@ -155,6 +157,11 @@ pub struct Config {
 
     /// The initial capacity of the list of requests.
     pub requests_capacity: usize,
+
+    /// If `true`, the entire storage of the finalized block is downloaded once the chain
+    /// information has been obtained. See [`Success::finalized_storage`] and
+    /// [`Success::finalized_storage_child_tries`].
+    pub download_storage: bool,
 }
 
 /// Initializes the warp sync state machine.
@@ -186,6 +193,7 @@ pub fn start_warp_sync<TSrc, TRq>(
     Ok(InProgressWarpSync {
         start_chain_information: config.start_chain_information,
         block_number_bytes: config.block_number_bytes,
+        download_storage: config.download_storage,
         sources: slab::Slab::with_capacity(config.sources_capacity),
         in_progress_requests: slab::Slab::with_capacity(config.requests_capacity),
         phase: Phase::DownloadFragments {
@@ -224,6 +232,15 @@ pub struct Success<TSrc, TRq> {
     /// Storage value at the `:heappages` key of the finalized block.
     pub finalized_storage_heap_pages: Option<Vec<u8>>,
 
+    /// Entire storage of the main trie of the finalized block. `Some` if and only if
+    /// [`Config::download_storage`] was `true`.
+    pub finalized_storage: Option<BTreeMap<Vec<u8>, Vec<u8>>>,
+
+    /// Entire storage of the default child tries of the finalized block, indexed by child trie
+    /// identifier (without the `:child_storage:default:` prefix). Always empty if
+    /// [`Config::download_storage`] was `false`.
+    pub finalized_storage_child_tries: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Vec<u8>>>,
+
     /// The list of sources that were added to the state machine.
     pub sources: Vec<TSrc>,
 
@@ -266,6 +283,8 @@ pub struct InProgressWarpSync<TSrc, TRq> {
     start_chain_information: ValidChainInformation,
     /// Number of bytes used to encode the block number in headers.
     block_number_bytes: usize,
+    /// See [`Config::download_storage`].
+    download_storage: bool,
     /// List of requests that have been added using [`InProgressWarpSync::add_source`].
     sources: slab::Slab<Source<TSrc>>,
     /// List of requests that have been added using [`InProgressWarpSync::add_request`].
@@ -332,9 +351,47 @@ enum Phase {
             Option<Vec<u8>>,
             fnv::FnvBuildHasher,
         >,
+        /// If `Some`, the chain information builder is waiting for the key that follows a given
+        /// key, and the Merkle proofs downloaded so far don't contain enough information to
+        /// determine it. Contains the key whose storage Merkle proof must be downloaded in order
+        /// to continue, and this proof if it has been downloaded yet.
+        next_key_proof: Option<(Vec<u8>, Option<Vec<u8>>)>,
+    },
+    /// The chain information has been built, and we are now downloading the entire storage of
+    /// the finalized block.
+    ///
+    /// Contrary to the previous phases, the storage can be downloaded from any source, and a
+    /// source failing a request doesn't make the warp syncing go back to downloading fragments.
+    StorageDownload {
+        /// Outcome of the previous phases.
+        /// Always `Some`, but wrapped within an `Option` in order to allow extraction.
+        built: Option<Box<BuiltChainInformation>>,
+        /// Storage entries of the main trie downloaded so far.
+        storage: BTreeMap<Vec<u8>, Vec<u8>>,
+        /// Storage entries of the default child tries downloaded so far, indexed by child trie
+        /// identifier.
+        child_tries: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Vec<u8>>>,
+        /// Key of the last entry of [`Phase::StorageDownload::storage`], or `None` if no entry
+        /// has been downloaded yet. The next state request starts at this key.
+        last_key: Option<Vec<u8>>,
+        /// If `Some`, the child trie whose entries are being downloaded, and the key of the last
+        /// entry of this child trie that has been downloaded, if any. The next state request
+        /// starts at this key within this child trie.
+        child_trie_last_key: Option<(Vec<u8>, Option<Vec<u8>>)>,
+        /// State response that has been downloaded but not verified yet, and source it has been
+        /// downloaded from.
+        downloaded_response: Option<(SourceId, Vec<u8>)>,
     },
 }
 
+/// Information about the finalized block obtained at the end of the
+/// [`Phase::ChainInformationDownload`] phase.
+struct BuiltChainInformation {
+    chain_information: ValidChainInformation,
+    finalized_runtime: HostVmPrototype,
+    downloaded_runtime: DownloadedRuntime,
+}
+
 struct DownloadedRuntime {
     /// Storage item at the `:code` key. `None` if there is no entry at that key.
     storage_code: Option<Vec<u8>>,
@@ -373,9 +430,48 @@ pub enum Status<'a, TSrc> {
         /// Height of the block indicated by [`Status::ChainInformation::finalized_block_hash`].
         finalized_block_number: u64,
     },
+    /// Warp syncing algorithm has obtained the chain information and is downloading the storage
+    /// of the finalized block.
+    Storage {
+        /// Source from which the storage is currently being downloaded, if any.
+        source: Option<(SourceId, &'a TSrc)>,
+        /// Hash of the finalized block whose storage is being downloaded.
+        finalized_block_hash: [u8; 32],
+        /// Height of the block indicated by [`Status::Storage::finalized_block_hash`].
+        finalized_block_number: u64,
+        /// Number of storage entries, including the ones of child tries, that have been
+        /// downloaded so far.
+        downloaded_entries: usize,
+    },
 }
 
 impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
+    /// Turns the state machine into a [`Success`], once everything has been downloaded.
+    fn into_success(
+        mut self,
+        built: BuiltChainInformation,
+        finalized_storage: Option<BTreeMap<Vec<u8>, Vec<u8>>>,
+        finalized_storage_child_tries: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Vec<u8>>>,
+    ) -> Success<TSrc, TRq> {
+        Success {
+            chain_information: built.chain_information,
+            finalized_runtime: built.finalized_runtime,
+            finalized_storage_code: built.downloaded_runtime.storage_code,
+            finalized_storage_heap_pages: built.downloaded_runtime.storage_heap_pages,
+            finalized_storage,
+            finalized_storage_child_tries,
+            sources: self
+                .sources
+                .drain()
+                .map(|source| source.user_data)
+                .collect(),
+            in_progress_requests: mem::take(&mut self.in_progress_requests)
+                .into_iter()
+                .map(|(id, (src_id, user_data, detail))| (src_id, RequestId(id), user_data, detail))
+                .collect(),
+        }
+    }
+
     /// Returns the value that was initially passed in [`Config::block_number_bytes`].
     pub fn block_number_bytes(&self) -> usize {
         self.block_number_bytes
@@ -466,6 +562,40 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
                 finalized_block_hash: header.hash(self.block_number_bytes),
                 finalized_block_number: header.number,
             },
+            Phase::StorageDownload {
+                ref built,
+                ref storage,
+                ref child_tries,
+                ..
+            } => {
+                let header = built
+                    .as_ref()
+                    .unwrap()
+                    .chain_information
+                    .as_ref()
+                    .finalized_block_header;
+                let finalized_block_hash = header.hash(self.block_number_bytes);
+
+                let source_id =
+                    self.in_progress_requests
+                        .iter()
+                        .find_map(|(_, (source_id, _, rq))| match rq {
+                            RequestDetail::StateRequest { block_hash, .. }
+                                if *block_hash == finalized_block_hash =>
+                            {
+                                Some(*source_id)
+                            }
+                            _ => None,
+                        });
+
+                Status::Storage {
+                    source: source_id.map(|id| (id, &self.sources[id.0].user_data)),
+                    finalized_block_hash,
+                    finalized_block_number: header.number,
+                    downloaded_entries: storage.len()
+                        + child_tries.values().map(|t| t.len()).sum::<usize>(),
+                }
+            }
         }
     }
 
@@ -533,6 +663,16 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
                     previous_verifier_values: previous_verifier_values.take(),
                 }
             }
+        } else if let Phase::StorageDownload {
+            downloaded_response,
+            ..
+        } = &mut self.phase
+        {
+            // Similar to the situation above, the response is discarded in order to not leave
+            // invalid source IDs in the state of `self`.
+            if matches!(downloaded_response, Some((source_id, _)) if *source_id == to_remove) {
+                *downloaded_response = None;
+            }
         }
 
         let obsolete_requests_indices = self
@@ -698,10 +838,112 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
             either::Right(iter::empty())
         };
 
+        // If we are in the appropriate phase, and the chain information builder is waiting for
+        // a next key that can't be determined with the proofs downloaded so far, return a
+        // storage proof request for the key in question.
+        let next_key_proof = if let Phase::ChainInformationDownload {
+            header,
+            warp_sync_source_id,
+            next_key_proof: Some((key, None)),
+            ..
+        } = &self.phase
+        {
+            // TODO: O(n)
+            if !self.in_progress_requests.iter().any(|(_, rq)| {
+                rq.0 == *warp_sync_source_id
+                    && matches!(
+                        &rq.2,
+                        RequestDetail::StorageGetMerkleProof { block_hash: b, keys }
+                            if *b == header.hash(self.block_number_bytes)
+                                && keys.iter().any(|k| k == key)
+                    )
+            }) {
+                Some((
+                    *warp_sync_source_id,
+                    &self.sources[warp_sync_source_id.0].user_data,
+                    DesiredRequest::StorageGetMerkleProof {
+                        block_hash: header.hash(self.block_number_bytes),
+                        state_trie_root: header.state_root,
+                        keys: vec![key.clone()],
+                    },
+                ))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        // If we are downloading the storage and no state request is in progress, return a state
+        // request starting where the previous response has ended, combined with every single
+        // available source.
+        let state_request = if let Phase::StorageDownload {
+            built,
+            last_key,
+            child_trie_last_key,
+            downloaded_response: None,
+            ..
+        } = &self.phase
+        {
+            let header = built
+                .as_ref()
+                .unwrap()
+                .chain_information
+                .as_ref()
+                .finalized_block_header;
+            let block_hash = header.hash(self.block_number_bytes);
+            let (child_trie, start_key) = match child_trie_last_key {
+                Some((child_trie, key)) => {
+                    (Some(child_trie.clone()), key.clone().unwrap_or_default())
+                }
+                None => (None, last_key.clone().unwrap_or_default()),
+            };
+
+            // TODO: O(n)
+            if !self
+                .in_progress_requests
+                .iter()
+                .any(|(_, (_, _, rq))| match rq {
+                    RequestDetail::StateRequest {
+                        block_hash: b,
+                        child_trie: c,
+                        start_key: k,
+                    } => *b == block_hash && *c == child_trie && *k == start_key,
+                    _ => false,
+                })
+            {
+                let all_sources_already_tried = self.sources.iter().all(|(_, s)| s.already_tried);
+                let state_trie_root = *header.state_root;
+
+                either::Left(self.sources.iter().filter_map(move |(src_id, src)| {
+                    if all_sources_already_tried || !src.already_tried {
+                        Some((
+                            SourceId(src_id),
+                            &src.user_data,
+                            DesiredRequest::StateRequest {
+                                block_hash,
+                                state_trie_root,
+                                child_trie: child_trie.clone(),
+                                start_key: start_key.clone(),
+                            },
+                        ))
+                    } else {
+                        None
+                    }
+                }))
+            } else {
+                either::Right(iter::empty())
+            }
+        } else {
+            either::Right(iter::empty())
+        };
+
         // Chain all these demanded requests together.
         warp_sync_request
             .chain(runtime_parameters_get.into_iter())
             .chain(call_proofs)
+            .chain(next_key_proof)
+            .chain(state_request)
     }
 
     /// Inserts a new request in the data structure.
@@ -740,6 +982,11 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
                 self.sources[source_id.0].already_tried = true;
                 user_data
             }
+            ((source_id, user_data, RequestDetail::StateRequest { .. }), _) => {
+                // The state can be downloaded from any source. Other sources are tried first.
+                self.sources[source_id.0].already_tried = true;
+                user_data
+            }
             (
                 (
                     source_id,
@@ -806,13 +1053,44 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
             {
                 user_data
             }
+            (
+                (
+                    _,
+                    user_data,
+                    RequestDetail::StorageGetMerkleProof {
+                        ref block_hash,
+                        ref keys,
+                    },
+                ),
+                Phase::ChainInformationDownload {
+                    header,
+                    next_key_proof: Some((key, None)),
+                    ..
+                },
+            ) if *block_hash == header.hash(self.block_number_bytes)
+                && keys.iter().any(|k| k == key) =>
+            {
+                if let Phase::ChainInformationDownload {
+                    next_key_proof: Some((_, proof @ None)),
+                    ..
+                } = &mut self.phase
+                {
+                    *proof = Some(merkle_proof);
+                } else {
+                    // This is checked above.
+                    unreachable!()
+                }
+
+                return user_data;
+            }
             ((_, user_data, RequestDetail::StorageGetMerkleProof { .. }), _) => return user_data,
             (
                 (
                     _,
                     _,
                     RequestDetail::RuntimeCallMerkleProof { .. }
-                    | RequestDetail::WarpSyncRequest { .. },
+                    | RequestDetail::WarpSyncRequest { .. }
+                    | RequestDetail::StateRequest { .. },
                 ),
                 _,
             ) => panic!(),
@@ -882,7 +1160,8 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
             // Wrong request type.
             (
                 (_, _, RequestDetail::StorageGetMerkleProof { .. })
-                | (_, _, RequestDetail::WarpSyncRequest { .. }),
+                | (_, _, RequestDetail::WarpSyncRequest { .. })
+                | (_, _, RequestDetail::StateRequest { .. }),
                 _,
             ) => panic!(),
         }
@@ -962,14 +1241,83 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
         }
     }
 
+    /// Injects a successful response and removes the given request from the state machine. Returns
+    /// the user data that was associated to it.
+    ///
+    /// The response must be the SCALE-encoded Merkle proof contained in the state response. It
+    /// is verified later, when calling [`InProgressWarpSync::process_one`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`RequestId`] is invalid.
+    /// Panics if the [`RequestId`] doesn't correspond to a state request.
+    ///
+    pub fn state_request_success(&mut self, request_id: RequestId, merkle_proof: Vec<u8>) -> TRq {
+        match (
+            self.in_progress_requests.remove(request_id.0),
+            &mut self.phase,
+        ) {
+            (
+                (
+                    source_id,
+                    user_data,
+                    RequestDetail::StateRequest {
+                        block_hash,
+                        child_trie,
+                        start_key,
+                    },
+                ),
+                Phase::StorageDownload {
+                    built,
+                    last_key,
+                    child_trie_last_key,
+                    downloaded_response: downloaded_response @ None,
+                    ..
+                },
+            ) if block_hash
+                == built
+                    .as_ref()
+                    .unwrap()
+                    .chain_information
+                    .as_ref()
+                    .finalized_block_header
+                    .hash(self.block_number_bytes)
+                && match &*child_trie_last_key {
+                    Some((expected_child_trie, key)) => {
+                        child_trie.as_ref() == Some(expected_child_trie)
+                            && start_key[..] == *key.as_deref().unwrap_or_default()
+                    }
+                    None => {
+                        child_trie.is_none()
+                            && start_key[..] == *last_key.as_deref().unwrap_or_default()
+                    }
+                } =>
+            {
+                *downloaded_response = Some((source_id, merkle_proof));
+                user_data
+            }
+
+            // Uninteresting request.
+            ((_, user_data, RequestDetail::StateRequest { .. }), _) => user_data,
+
+            // Wrong request type.
+            ((_, _, _), _) => panic!(),
+        }
+    }
+
     /// Start processing one CPU operation.
     ///
     /// This function takes ownership of `self` and yields it back after the operation is finished.
     pub fn process_one(self) -> ProcessOne<TSrc, TRq> {
-        if let Phase::ChainInformationDownload { calls, .. } = &self.phase {
+        if let Phase::ChainInformationDownload {
+            calls,
+            next_key_proof,
+            ..
+        } = &self.phase
+        {
             // If we've downloaded everything that was needed, switch to "build chain information"
             // mode.
-            if calls.values().all(Option::is_some) {
+            if calls.values().all(Option::is_some) && !matches!(next_key_proof, Some((_, None))) {
                 return ProcessOne::BuildChainInformation(BuildChainInformation { inner: self });
             }
         }
@@ -986,6 +1334,14 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
             return ProcessOne::VerifyWarpSyncFragment(VerifyWarpSyncFragment { inner: self });
         }
 
+        if let Phase::StorageDownload {
+            downloaded_response: Some(_),
+            ..
+        } = &self.phase
+        {
+            return ProcessOne::VerifyStateResponse(VerifyStateResponse { inner: self });
+        }
+
         ProcessOne::Idle(self)
     }
 }
@@ -1028,6 +1384,21 @@ pub enum DesiredRequest {
         /// Parameters of the call.
         parameter_vectored: Cow<'static, [u8]>,
     },
+    /// A state request should be started.
+    StateRequest {
+        /// Hash of the block whose storage is requested.
+        block_hash: [u8; 32],
+        /// State trie root hash found in the header of the block.
+        state_trie_root: [u8; 32],
+        /// If `Some`, the request starts within the default child trie with this identifier
+        /// (without the `:child_storage:default:` prefix), and
+        /// [`DesiredRequest::StateRequest::start_key`] is a key of this child trie. The response
+        /// must then contain the rest of this child trie followed with the rest of the main trie.
+        child_trie: Option<Vec<u8>>,
+        /// Key to start the request at. The response must contain the entries whose key is
+        /// superior or equal to this key.
+        start_key: Vec<u8>,
+    },
 }
 
 /// Information about a request to add to the state machine.
@@ -1056,6 +1427,15 @@ pub enum RequestDetail {
         /// See [`DesiredRequest::RuntimeCallMerkleProof::parameter_vectored`].
         parameter_vectored: Cow<'static, [u8]>,
     },
+    /// See [`DesiredRequest::StateRequest`].
+    StateRequest {
+        /// See [`DesiredRequest::StateRequest::block_hash`].
+        block_hash: [u8; 32],
+        /// See [`DesiredRequest::StateRequest::child_trie`].
+        child_trie: Option<Vec<u8>>,
+        /// See [`DesiredRequest::StateRequest::start_key`].
+        start_key: Vec<u8>,
+    },
 }
 
 /// Identifier for a request in the warp sync state machine.
@@ -1072,6 +1452,8 @@ pub enum ProcessOne<TSrc, TRq> {
     BuildRuntime(BuildRuntime<TSrc, TRq>),
     /// Ready to verify the parameters of the chain against the finalized block.
     BuildChainInformation(BuildChainInformation<TSrc, TRq>),
+    /// Ready to verify a response to a state request.
+    VerifyStateResponse(VerifyStateResponse<TSrc, TRq>),
 }
 
 /// Ready to verify a warp sync fragment.
@@ -1317,28 +1699,35 @@ impl<TSrc, TRq> BuildRuntime<TSrc, TRq> {
                     result: Ok(chain_information),
                     virtual_machine,
                 } => {
-                    return (
-                        WarpSync::Finished(Success {
-                            chain_information,
-                            finalized_runtime: virtual_machine,
-                            finalized_storage_code: Some(finalized_storage_code.to_owned()),
-                            finalized_storage_heap_pages: finalized_storage_heappages
-                                .map(|v| v.to_vec()),
-                            sources: self
-                                .inner
-                                .sources
-                                .drain()
-                                .map(|source| source.user_data)
-                                .collect(),
-                            in_progress_requests: mem::take(&mut self.inner.in_progress_requests)
-                                .into_iter()
-                                .map(|(id, (src_id, user_data, detail))| {
-                                    (src_id, RequestId(id), user_data, detail)
-                                })
-                                .collect(),
-                        }),
-                        None,
-                    );
+                    let built = BuiltChainInformation {
+                        chain_information,
+                        finalized_runtime: virtual_machine,
+                        downloaded_runtime: DownloadedRuntime {
+                            storage_code: Some(finalized_storage_code.to_owned()),
+                            storage_heap_pages: finalized_storage_heappages.map(|v| v.to_vec()),
+                        },
+                    };
+
+                    if !self.inner.download_storage {
+                        return (
+                            WarpSync::Finished(self.inner.into_success(
+                                built,
+                                None,
+                                BTreeMap::new(),
+                            )),
+                            None,
+                        );
+                    }
+
+                    self.inner.phase = Phase::StorageDownload {
+                        built: Some(Box::new(built)),
+                        storage: BTreeMap::new(),
+                        child_tries: BTreeMap::new(),
+                        last_key: None,
+                        child_trie_last_key: None,
+                        downloaded_response: None,
+                    };
+                    return (WarpSync::InProgress(self.inner), None);
                 }
                 chain_information::build::ChainInformationBuild::Finished {
                     result: Err(err),
@@ -1374,6 +1763,7 @@ impl<TSrc, TRq> BuildRuntime<TSrc, TRq> {
                 }),
                 chain_info_builder: Some(chain_info_builder),
                 calls,
+                next_key_proof: None,
             };
 
             (WarpSync::InProgress(self.inner), None)
@@ -1397,23 +1787,61 @@ impl<TSrc, TRq> BuildChainInformation<TSrc, TRq> {
         if let Phase::ChainInformationDownload {
             header,
             chain_information_finality,
-            chain_info_builder,
+            chain_info_builder: chain_info_builder_slot,
             downloaded_runtime,
             calls,
+            next_key_proof,
             ..
         } = &mut self.inner.phase
         {
             debug_assert!(calls.values().all(Option::is_some));
 
+            let mut chain_info_builder = chain_info_builder_slot.take().unwrap();
+
+            // If the chain information builder was waiting for a next key, the storage proof that
+            // has been downloaded in order to determine it is added to the proof of the call in
+            // progress.
+            let previous_next_key_request = match next_key_proof.take() {
+                Some((key, Some(proof))) => {
+                    let call_proof = calls
+                        .get_mut(&chain_info_builder.call_in_progress())
+                        .unwrap()
+                        .as_mut()
+                        .unwrap();
+                    match trie::proof_encode::merge_proofs(call_proof, &proof) {
+                        Some(merged) => *call_proof = merged,
+                        None => {
+                            self.inner.phase = Phase::DownloadFragments {
+                                previous_verifier_values: Some((
+                                    header.clone(),
+                                    chain_information_finality.clone(),
+                                )),
+                            };
+                            return (
+                                WarpSync::InProgress(self.inner),
+                                Some(Error::InvalidMerkleProof(
+                                    proof_decode::Error::InvalidFormat,
+                                )),
+                            );
+                        }
+                    }
+                    Some(key)
+                }
+                Some((_, None)) => unreachable!(),
+                None => None,
+            };
+
             // Decode all the Merkle proofs that have been received.
-            let calls = {
+            // The proofs are kept in `calls`, as they might be needed again later if a storage
+            // proof has to be downloaded in order to continue.
+            let decoded_calls = {
                 let mut decoded_proofs = hashbrown::HashMap::with_capacity_and_hasher(
                     calls.len(),
                     fnv::FnvBuildHasher::default(),
                 );
 
-                for (call, proof) in calls {
-                    let proof = proof.take().unwrap();
+                for (call, proof) in calls.iter() {
+                    let proof = proof.as_ref().unwrap().clone();
                     let decoded_proof =
                         match proof_decode::decode_and_verify_proof(proof_decode::Config {
                             trie_root_hash: &header.state_root,
@@ -1439,12 +1867,10 @@ impl<TSrc, TRq> BuildChainInformation<TSrc, TRq> {
                 decoded_proofs
             };
 
-            let mut chain_info_builder = chain_info_builder.take().unwrap();
-
             loop {
-                match chain_info_builder {
+                let build_outcome = match chain_info_builder {
                     chain_information::build::InProgress::StorageGet(get) => {
-                        let proof = calls.get(&get.call_in_progress()).unwrap();
+                        let proof = decoded_calls.get(&get.call_in_progress()).unwrap();
                         let value = match proof.storage_value(get.key().as_ref()) {
                             Some(v) => v,
                             None => {
@@ -1461,62 +1887,75 @@ impl<TSrc, TRq> BuildChainInformation<TSrc, TRq> {
                             }
                         };
 
-                        match get.inject_value(value.map(iter::once)) {
-                            chain_information::build::ChainInformationBuild::Finished {
-                                result: Ok(chain_information),
-                                virtual_machine,
-                            } => {
-                                let downloaded_runtime = downloaded_runtime.take().unwrap();
+                        get.inject_value(value.map(iter::once))
+                    }
+                    chain_information::build::InProgress::NextKey(nk) => {
+                        let proof = decoded_calls.get(&nk.call_in_progress()).unwrap();
+                        let next_key = next_key_from_proof(proof, nk.key().as_ref());
+                        match next_key {
+                            Ok(next_key) => nk.inject_key(next_key),
+                            Err(missing_key) => {
+                                // If the node is still missing after a storage proof of that
+                                // exact key has been downloaded, the source didn't send a
+                                // complete proof.
+                                if previous_next_key_request.as_ref() == Some(&missing_key) {
+                                    self.inner.phase = Phase::DownloadFragments {
+                                        previous_verifier_values: Some((
+                                            header.clone(),
+                                            chain_information_finality.clone(),
+                                        )),
+                                    };
+                                    return (
+                                        WarpSync::InProgress(self.inner),
+                                        Some(Error::MerkleProofEntriesMissing),
+                                    );
+                                }
+
+                                *chain_info_builder_slot =
+                                    Some(chain_information::build::InProgress::NextKey(nk));
+                                *next_key_proof = Some((missing_key, None));
+                                return (WarpSync::InProgress(self.inner), None);
+                            }
+                        }
+                    }
+                };
 
-                                return (
-                                    WarpSync::Finished(Success {
-                                        chain_information,
-                                        finalized_runtime: virtual_machine,
-                                        finalized_storage_code: downloaded_runtime.storage_code,
-                                        finalized_storage_heap_pages: downloaded_runtime
-                                            .storage_heap_pages,
-                                        sources: self
-                                            .inner
-                                            .sources
-                                            .drain()
-                                            .map(|source| source.user_data)
-                                            .collect(),
-                                        in_progress_requests: mem::take(
-                                            &mut self.inner.in_progress_requests,
-                                        )
-                                        .into_iter()
-                                        .map(|(id, (src_id, user_data, detail))| {
-                                            (src_id, RequestId(id), user_data, detail)
-                                        })
-                                        .collect(),
-                                    }),
+                match build_outcome {
+                    chain_information::build::ChainInformationBuild::Finished {
+                        result: Ok(chain_information),
+                        virtual_machine,
+                    } => {
+                        let built = BuiltChainInformation {
+                            chain_information,
+                            finalized_runtime: virtual_machine,
+                            downloaded_runtime: downloaded_runtime.take().unwrap(),
+                        };
+
+                        if !self.inner.download_storage {
+                            return (
+                                WarpSync::Finished(self.inner.into_success(
+                                    built,
                                     None,
-                                );
-                            }
-                            chain_information::build::ChainInformationBuild::Finished {
-                                result: Err(err),
-                                ..
-                            } => {
-                                self.inner.phase = Phase::DownloadFragments {
-                                    previous_verifier_values: Some((
-                                        header.clone(),
-                                        chain_information_finality.clone(),
-                                    )),
-                                };
-                                return (
-                                    WarpSync::InProgress(self.inner),
-                                    Some(Error::ChainInformationBuild(err)),
-                                );
-                            }
-                            chain_information::build::ChainInformationBuild::InProgress(
-                                in_progress,
-                            ) => {
-                                chain_info_builder = in_progress;
-                            }
+                                    BTreeMap::new(),
+                                )),
+                                None,
+                            );
                         }
+
+                        self.inner.phase = Phase::StorageDownload {
+                            built: Some(Box::new(built)),
+                            storage: BTreeMap::new(),
+                            child_tries: BTreeMap::new(),
+                            last_key: None,
+                            child_trie_last_key: None,
+                            downloaded_response: None,
+                        };
+                        return (WarpSync::InProgress(self.inner), None);
                     }
-                    chain_information::build::InProgress::NextKey(_) => {
-                        // TODO: implement
+                    chain_information::build::ChainInformationBuild::Finished {
+                        result: Err(err),
+                        ..
+                    } => {
                         self.inner.phase = Phase::DownloadFragments {
                             previous_verifier_values: Some((
                                 header.clone(),
@@ -1525,9 +1964,12 @@ impl<TSrc, TRq> BuildChainInformation<TSrc, TRq> {
                         };
                         return (
                             WarpSync::InProgress(self.inner),
-                            Some(Error::NextKeyUnimplemented),
+                            Some(Error::ChainInformationBuild(err)),
                         );
                     }
+                    chain_information::build::ChainInformationBuild::InProgress(in_progress) => {
+                        chain_info_builder = in_progress;
+                    }
                 }
             }
         } else {
@@ -1536,6 +1978,187 @@ impl<TSrc, TRq> BuildChainInformation<TSrc, TRq> {
     }
 }
 
+/// Ready to verify a response to a state request.
+pub struct VerifyStateResponse<TSrc, TRq> {
+    inner: InProgressWarpSync<TSrc, TRq>,
+}
+
+impl<TSrc, TRq> VerifyStateResponse<TSrc, TRq> {
+    /// Returns the source that has sent the response that we are about to verify, and its user
+    /// data.
+    pub fn response_sender(&self) -> (SourceId, &TSrc) {
+        if let Phase::StorageDownload {
+            downloaded_response: Some((source_id, _)),
+            ..
+        } = &self.inner.phase
+        {
+            (*source_id, &self.inner.sources[source_id.0].user_data)
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// Verifies the response against the state trie root of the finalized block, and adds the
+    /// storage entries it contains to the ones downloaded so far.
+    ///
+    /// This function might return a [`WarpSync::Finished`], indicating the end of the warp sync.
+    pub fn verify(mut self) -> (WarpSync<TSrc, TRq>, Option<Error>) {
+        if let Phase::StorageDownload {
+            built,
+            storage,
+            child_tries,
+            last_key,
+            child_trie_last_key,
+            downloaded_response,
+        } = &mut self.inner.phase
+        {
+            let (source_id, response) = downloaded_response.take().unwrap();
+            let state_root = *built
+                .as_ref()
+                .unwrap()
+                .chain_information
+                .as_ref()
+                .finalized_block_header
+                .state_root;
+
+            let decoded = match proof_decode::decode_and_verify_proof(proof_decode::Config {
+                trie_root_hash: &state_root,
+                proof: &response[..],
+            }) {
+                Ok(d) => d,
+                Err(err) => {
+                    self.inner.sources[source_id.0].already_tried = true;
+                    return (
+                        WarpSync::InProgress(self.inner),
+                        Some(Error::InvalidMerkleProof(err)),
+                    );
+                }
+            };
+
+            let (finished, num_new_entries) = storage_entries_from_proof(
+                &decoded,
+                storage,
+                child_tries,
+                last_key,
+                child_trie_last_key,
+            );
+
+            if finished {
+                let built = *built.take().unwrap();
+                let storage = mem::take(storage);
+                let child_tries = mem::take(child_tries);
+                return (
+                    WarpSync::Finished(self.inner.into_success(built, Some(storage), child_tries)),
+                    None,
+                );
+            }
+
+            // A source that sends a response that doesn't allow progressing is either malicious
+            // or doesn't have the storage of the block.
+            if num_new_entries == 0 {
+                self.inner.sources[source_id.0].already_tried = true;
+                return (
+                    WarpSync::InProgress(self.inner),
+                    Some(Error::MerkleProofEntriesMissing),
+                );
+            }
+
+            (WarpSync::InProgress(self.inner), None)
+        } else {
+            unreachable!()
+        }
+    }
+}
+
+/// Iterates over the storage entries found in the given state response, starting after the
+/// last entry that has been downloaded, and adds them to `storage` and `child_tries`. Updates
+/// `last_key` and `child_trie_last_key` accordingly.
+///
+/// The entries of a default child trie immediately follow the main trie entry containing the
+/// root of this child trie.
+///
+/// Returns `true` if the end of the main trie has been reached, in addition to the number of
+/// entries that have been added.
+fn storage_entries_from_proof<T: AsRef<[u8]>>(
+    decoded: &proof_decode::DecodedTrieProof<T>,
+    storage: &mut BTreeMap<Vec<u8>, Vec<u8>>,
+    child_tries: &mut BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Vec<u8>>>,
+    last_key: &mut Option<Vec<u8>>,
+    child_trie_last_key: &mut Option<(Vec<u8>, Option<Vec<u8>>)>,
+) -> (bool, usize) {
+    let mut num_new_entries = 0;
+
+    // Iterate over the keys of the proof, until either the end of the main trie is reached or
+    // the proof no longer contains enough information.
+    loop {
+        if let Some((child_trie, child_last_key)) = child_trie_last_key {
+            let iter_key = child_last_key
+                .as_ref()
+                .map(|k| trie::bytes_to_nibbles(k.iter().copied()).collect::<Vec<_>>())
+                .unwrap_or_default();
+            let key = match decoded.child_trie_next_key(
+                child_trie,
+                &iter_key,
+                child_last_key.is_none(),
+            ) {
+                Ok(Some(key)) => key,
+                Ok(None) => {
+                    // The end of the child trie has been reached. Continue with the main trie.
+                    *child_trie_last_key = None;
+                    continue;
+                }
+                Err(_) => return (false, num_new_entries),
+            };
+
+            let key_bytes =
+                trie::nibbles_to_bytes_suffix_extend(key.iter().copied()).collect::<Vec<_>>();
+            match decoded.child_trie_storage_value(child_trie, &key_bytes) {
+                Some(Some(value)) => {
+                    child_tries
+                        .entry(child_trie.clone())
+                        .or_default()
+                        .insert(key_bytes.clone(), value.to_vec());
+                }
+                // The value itself is missing from the proof.
+                Some(None) | None => return (false, num_new_entries),
+            }
+
+            num_new_entries += 1;
+            *child_last_key = Some(key_bytes);
+            continue;
+        }
+
+        let iter_key = last_key
+            .as_ref()
+            .map(|k| trie::bytes_to_nibbles(k.iter().copied()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let key = match decoded.next_key(&iter_key, last_key.is_none()) {
+            Ok(Some(key)) => key,
+            Ok(None) => return (true, num_new_entries),
+            Err(_) => return (false, num_new_entries),
+        };
+
+        let key_bytes =
+            trie::nibbles_to_bytes_suffix_extend(key.iter().copied()).collect::<Vec<_>>();
+        match decoded.storage_value(&key_bytes) {
+            Some(Some(value)) => {
+                storage.insert(key_bytes.clone(), value.to_vec());
+            }
+            // The value itself is missing from the proof.
+            Some(None) | None => return (false, num_new_entries),
+        }
+
+        // The value of this key is the root of a default child trie, whose entries are
+        // downloaded before continuing with the main trie.
+        if let Some(child_trie) = key_bytes.strip_prefix(b":child_storage:default:") {
+            *child_trie_last_key = Some((child_trie.to_vec(), None));
+        }
+
+        num_new_entries += 1;
+        *last_key = Some(key_bytes);
+    }
+}
+
 /// Returns `true` if `a` and `b` are equal.
 fn parameters_equal(mut a: &[u8], b: impl Iterator<Item = impl AsRef<[u8]>>) -> bool {
     for slice in b {
@@ -1554,3 +2177,218 @@ fn parameters_equal(mut a: &[u8], b: impl Iterator<Item = impl AsRef<[u8]>>) ->
 
     true
 }
+
+/// Determines the key that follows `key` in the storage, using the given Merkle proof.
+///
+/// Returns an error if the proof doesn't contain enough information. The error contains a key
+/// whose storage proof is guaranteed to contain the trie node that is missing.
+fn next_key_from_proof<T: AsRef<[u8]>>(
+    proof: &proof_decode::DecodedTrieProof<T>,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>, Vec<u8>> {
+    let key = trie::bytes_to_nibbles(key.iter().copied()).collect::<Vec<_>>();
+    match proof.next_key(&key, false) {
+        Ok(next_key) => Ok(next_key
+            .map(|k| trie::nibbles_to_bytes_suffix_extend(k.iter().copied()).collect::<Vec<_>>())),
+        Err(err) => {
+            // The proof doesn't contain the trie node that might contain the next key. Requesting
+            // a storage proof of a key that starts with the prefix of this node guarantees that
+            // the node will be included in the proof.
+            Err(
+                trie::nibbles_to_bytes_suffix_extend(err.missing_node_prefix.into_iter())
+                    .collect::<Vec<_>>(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trie::{proof_decode, proof_encode, TrieEntryVersion};
+
+    use alloc::collections::BTreeMap;
+
+    // The values are large enough that the trie nodes are never inlined within their parent,
+    // and thus that a proof only contains the nodes necessary to prove the requested keys.
+    const ENTRIES: [(&[u8], [u8; 64]); 3] = [
+        (&[0x01, 0x01], [1; 64]),
+        (&[0x01, 0x02], [2; 64]),
+        (&[0x05, 0x05], [3; 64]),
+    ];
+
+    fn proof_of(keys: &[&[u8]]) -> ([u8; 32], Vec<u8>) {
+        let mut builder = proof_encode::EntriesProofBuilder::new(TrieEntryVersion::V0, keys);
+        for (key, value) in ENTRIES {
+            builder.push(key, &value);
+        }
+        builder.build_to_vec()
+    }
+
+    #[test]
+    fn next_key_through_additional_proof() {
+        // Mimics what happens when the chain information builder requests the next key of
+        // `ENTRIES[0]`: the proof of the runtime call only covers that key, and the proof of the
+        // key indicated by the error has to be downloaded and merged in order to continue.
+        let (trie_root_hash, call_proof) = proof_of(&[ENTRIES[0].0]);
+
+        let missing_key = {
+            let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+                trie_root_hash: &trie_root_hash,
+                proof: &call_proof,
+            })
+            .unwrap();
+            super::next_key_from_proof(&decoded, ENTRIES[0].0).unwrap_err()
+        };
+        assert_eq!(missing_key, ENTRIES[1].0);
+
+        let (_, next_key_proof) = proof_of(&[&missing_key]);
+        let merged = proof_encode::merge_proofs(&call_proof, &next_key_proof).unwrap();
+        let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+            trie_root_hash: &trie_root_hash,
+            proof: &merged,
+        })
+        .unwrap();
+
+        assert_eq!(
+            super::next_key_from_proof(&decoded, ENTRIES[0].0).unwrap(),
+            Some(ENTRIES[1].0.to_vec())
+        );
+        assert_eq!(
+            super::next_key_from_proof(&decoded, ENTRIES[1].0).unwrap_err(),
+            vec![0x05]
+        );
+    }
+
+    #[test]
+    fn next_key_not_covered_by_proof() {
+        // A proof that doesn't cover the requested key must not be used to deduce a next key,
+        // even though it contains entries that are after the requested key.
+        let (trie_root_hash, proof) = proof_of(&[ENTRIES[2].0]);
+        let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+            trie_root_hash: &trie_root_hash,
+            proof: &proof,
+        })
+        .unwrap();
+
+        assert!(super::next_key_from_proof(&decoded, ENTRIES[0].0).is_err());
+        assert!(super::next_key_from_proof(&decoded, ENTRIES[1].0).is_err());
+
+        // Keys that are after the entries missing from the proof can be resolved.
+        assert_eq!(
+            super::next_key_from_proof(&decoded, &[0x02]).unwrap(),
+            Some(ENTRIES[2].0.to_vec())
+        );
+    }
+
+    const CHILD_TRIE_ENTRIES: [(&[u8], [u8; 64]); 3] =
+        [(&[0x01], [4; 64]), (&[0x02], [5; 64]), (&[0x03], [6; 64])];
+
+    /// Returns the entries of a main trie that contains a default child trie `foo` whose
+    /// entries are [`CHILD_TRIE_ENTRIES`].
+    fn main_trie_with_child_trie() -> BTreeMap<Vec<u8>, Vec<u8>> {
+        let (child_trie_root, _) = proof_encode::build_proof_from_trie_entries(
+            TrieEntryVersion::V0,
+            CHILD_TRIE_ENTRIES,
+            [&[0x01]],
+        );
+
+        [
+            (vec![0x01, 0x01], vec![1; 64]),
+            (
+                b":child_storage:default:foo".to_vec(),
+                child_trie_root.to_vec(),
+            ),
+            (vec![0x5f, 0x5f], vec![2; 64]),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    /// Builds a state response containing the given keys of the main trie and of the child
+    /// trie `foo`.
+    fn state_response(
+        main_trie: &BTreeMap<Vec<u8>, Vec<u8>>,
+        main_keys: &[&[u8]],
+        child_trie_keys: &[&[u8]],
+    ) -> ([u8; 32], Vec<u8>) {
+        let (state_root, main_proof) =
+            proof_encode::build_proof_from_trie_entries(TrieEntryVersion::V0, main_trie, main_keys);
+        let (_, child_trie_proof) = proof_encode::build_proof_from_trie_entries(
+            TrieEntryVersion::V0,
+            CHILD_TRIE_ENTRIES,
+            child_trie_keys,
+        );
+        (
+            state_root,
+            proof_encode::merge_proofs(&main_proof, &child_trie_proof).unwrap(),
+        )
+    }
+
+    #[test]
+    fn state_with_child_trie_downloaded() {
+        let main_trie = main_trie_with_child_trie();
+        let all_main_keys = main_trie.keys().map(|k| &k[..]).collect::<Vec<_>>();
+        let all_child_trie_keys = CHILD_TRIE_ENTRIES.map(|(k, _)| k);
+
+        let mut storage = BTreeMap::new();
+        let mut child_tries = BTreeMap::new();
+        let mut last_key = None;
+        let mut child_trie_last_key = None;
+
+        // The first response ends in the middle of the child trie.
+        let (state_root, response) =
+            state_response(&main_trie, &all_main_keys[..2], &all_child_trie_keys[..1]);
+        let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+            trie_root_hash: &state_root,
+            proof: &response,
+        })
+        .unwrap();
+        assert_eq!(
+            super::storage_entries_from_proof(
+                &decoded,
+                &mut storage,
+                &mut child_tries,
+                &mut last_key,
+                &mut child_trie_last_key,
+            ),
+            (false, 3)
+        );
+        assert_eq!(
+            last_key.as_deref(),
+            Some(&b":child_storage:default:foo"[..])
+        );
+        assert_eq!(
+            child_trie_last_key,
+            Some((b"foo".to_vec(), Some(CHILD_TRIE_ENTRIES[0].0.to_vec())))
+        );
+
+        // The second response starts at the last downloaded key of the child trie, and contains
+        // the rest of the child trie followed with the rest of the main trie.
+        let (_, response) = state_response(&main_trie, &all_main_keys[1..], &all_child_trie_keys);
+        let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+            trie_root_hash: &state_root,
+            proof: &response,
+        })
+        .unwrap();
+        assert_eq!(
+            super::storage_entries_from_proof(
+                &decoded,
+                &mut storage,
+                &mut child_tries,
+                &mut last_key,
+                &mut child_trie_last_key,
+            ),
+            (true, 3)
+        );
+
+        assert_eq!(storage, main_trie);
+        assert_eq!(child_tries.len(), 1);
+        assert_eq!(
+            child_tries[&b"foo"[..]],
+            CHILD_TRIE_ENTRIES
+                .iter()
+                .map(|(k, v)| (k.to_vec(), v.to_vec()))
+                .collect::<BTreeMap<_, _>>()
+        );
+    }
+}
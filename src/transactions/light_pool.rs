@@ -328,6 +328,17 @@ where
     /// Panics if the transaction with the given id is invalid.
     ///
     pub fn is_included_best_chain(&self, id: TransactionId) -> bool {
+        self.best_chain_inclusion(id).is_some()
+    }
+
+    /// If the given transaction has been included in the past in an ancestor of the current best
+    /// block, returns the hash of this block and the index of the transaction within its body.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the transaction with the given id is invalid.
+    ///
+    pub fn best_chain_inclusion(&self, id: TransactionId) -> Option<(&[u8; 32], usize)> {
         let mut iter = self
             .included_transactions
             .range((id, [0; 32])..=(id, [0xff; 32]))
@@ -338,11 +349,13 @@ where
                 })
             });
 
-        let outcome = iter.next().is_some();
-        if outcome {
-            debug_assert!(iter.next().is_none());
-        }
-        outcome
+        let (_, block_hash) = iter.next()?;
+        debug_assert!(iter.next().is_none());
+        let index_in_block = *self
+            .transactions_by_inclusion
+            .get(&(*block_hash, id))
+            .unwrap();
+        Some((block_hash, index_in_block))
     }
 
     /// Returns `true` if the given transaction has been successfully validated in the past against
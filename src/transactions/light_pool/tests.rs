@@ -140,6 +140,40 @@ fn transaction_retracted_after_reorg() {
     assert!(set_best_block.retracted_transactions.is_empty());
 }
 
+#[test]
+fn best_chain_inclusion() {
+    let mut pool = LightPool::<_, _, ()>::new(Config {
+        blocks_capacity: 16,
+        finalized_block_hash: [0; 32],
+        transactions_capacity: 16,
+    });
+
+    let tx_id = pool.add_unvalidated(vec![0], ());
+    assert!(pool.best_chain_inclusion(tx_id).is_none());
+
+    // Add blocks 1 and 2, both children of block 0. Only block 1 contains the transaction.
+    pool.add_block([1; 32], &[0; 32], ());
+    pool.add_block([2; 32], &[0; 32], ());
+    let _ = pool
+        .set_block_body(&[1; 32], vec![vec![1], vec![0]].into_iter())
+        .count();
+    let _ = pool
+        .set_block_body(&[2; 32], Vec::<Vec<u8>>::new().into_iter())
+        .count();
+
+    // Transaction isn't included in the best chain as long as block 1 isn't the best block.
+    assert!(pool.best_chain_inclusion(tx_id).is_none());
+
+    let _ = pool.set_best_block(&[1; 32]);
+    assert_eq!(pool.best_chain_inclusion(tx_id), Some((&[1; 32], 1)));
+
+    let _ = pool.set_best_block(&[2; 32]);
+    assert!(pool.best_chain_inclusion(tx_id).is_none());
+
+    let _ = pool.set_best_block(&[1; 32]);
+    assert_eq!(pool.best_chain_inclusion(tx_id), Some((&[1; 32], 1)));
+}
+
 #[test]
 fn longevity_works_non_finalized() {
     let mut pool = LightPool::<_, _, ()>::new(Config {
@@ -56,7 +56,8 @@
 //! - A so-called user data, an opaque field controller by the API user.
 //!
 //! Use [`Pool::add_unvalidated`] to add to the pool a transaction that should be included in a
-//! block at a later point in time.
+//! block at a later point in time. The number of transactions waiting to be included and their
+//! total size are bounded by the limits found in the [`Config`].
 //!
 //! Use [`Pool::append_block`] and [`Pool::retract_blocks`] when a new block is considered as
 //! best in order to let the [`Pool`] track the state of the best block of the chain. The
@@ -66,9 +67,31 @@
 //! validated. Validation should be performed using the [`validate`](../validate) module, and
 //! the result reported with [`Pool::set_validation_result`].
 //!
+//! Use [`Pool::inclusion_order`] to obtain the list of transactions that are ready to be
+//! included in a new block, in the order in which they should be included.
+//!
+//! Use [`Pool::remove_expired`] after a new block has been appended in order to remove from the
+//! pool the transactions whose longevity has expired.
+//!
 //! Use [`Pool::remove_included`] when a block has been finalized to remove from the pool the
 //! transactions that are present in the finalized block and below.
 //!
+//! # Ready transactions
+//!
+//! A transaction that isn't included in any block is *ready* if it has been successfully
+//! validated, if its longevity hasn't expired, and if all the tags it *requires* are *provided*
+//! by other ready transactions. The runtime only reports as required the tags that aren't
+//! provided by the chain yet.
+//!
+//! Ready transactions are ordered by decreasing priority, with the exception that a transaction
+//! always comes after the transactions that provide the tags it requires. Two transactions that
+//! provide the same tag are mutually exclusive, and only the one that comes first is considered
+//! as ready.
+//!
+//! Because the tags required by a transaction might be provided by the transactions of a new
+//! block, the transactions that require tags must be validated again every time a block is
+//! appended.
+//!
 //! # Out of scope
 //!
 //! The following are examples of things that are related transactions pool to but out of scope of
@@ -78,13 +101,16 @@
 //! - Sending transactions to other peers.
 //!
 
-// TODO: this code is completely untested
-
 use super::validate::{TransactionValidityError, ValidTransaction};
 
-use alloc::{collections::BTreeSet, vec::Vec};
-use core::fmt;
-use hashbrown::HashSet;
+use alloc::{
+    collections::{BTreeSet, BinaryHeap},
+    vec::Vec,
+};
+use core::{cmp, fmt};
+use hashbrown::{HashMap, HashSet};
+
+mod tests;
 
 /// Identifier of a transaction stored within the [`Pool`].
 ///
@@ -114,6 +140,28 @@ pub struct Config {
     /// Non-finalized blocks should be added to the pool after initialization using
     /// [`Pool::append_block`].
     pub finalized_block_height: u64,
+
+    /// Maximum number of transactions that aren't included in any block that the pool can
+    /// contain. [`Pool::add_unvalidated`] returns an error if this limit has been reached.
+    ///
+    /// > **Note**: Transactions that are no longer included in any block after a call to
+    /// >           [`Pool::retract_blocks`] are never refused, and can make the pool go over
+    /// >           this limit.
+    pub max_pending_transactions: usize,
+
+    /// Maximum total size, in bytes, of the transactions that aren't included in any block.
+    /// [`Pool::add_unvalidated`] returns an error if this limit would be exceeded.
+    ///
+    /// Similar to [`Config::max_pending_transactions`], this limit can be exceeded after a call
+    /// to [`Pool::retract_blocks`].
+    pub max_pending_bytes: usize,
+
+    /// Maximum number of transactions that aren't included in any block and that have been
+    /// added by the same sender. See the `sender` parameter of [`Pool::add_unvalidated`].
+    ///
+    /// Similar to [`Config::max_pending_transactions`], this limit can be exceeded after a call
+    /// to [`Pool::retract_blocks`].
+    pub max_pending_transactions_per_sender: usize,
 }
 
 /// Data structure containing transactions. See the module-level documentation for more info.
@@ -135,6 +183,22 @@ pub struct Pool<TTx> {
 
     /// Height of the latest best block, as known from the pool.
     best_block_height: u64,
+
+    /// Sum of the sizes of the transactions that aren't included in any block.
+    pending_bytes: usize,
+
+    /// For each sender, number of transactions of this sender that aren't included in any
+    /// block. Senders with zero transactions are absent from this map.
+    pending_per_sender: HashMap<Vec<u8>, usize, fnv::FnvBuildHasher>,
+
+    /// See [`Config::max_pending_transactions`].
+    max_pending_transactions: usize,
+
+    /// See [`Config::max_pending_bytes`].
+    max_pending_bytes: usize,
+
+    /// See [`Config::max_pending_transactions_per_sender`].
+    max_pending_transactions_per_sender: usize,
 }
 
 impl<TTx> Pool<TTx> {
@@ -146,6 +210,11 @@ impl<TTx> Pool<TTx> {
             by_hash: BTreeSet::new(),
             by_height: BTreeSet::new(),
             best_block_height: config.finalized_block_height,
+            pending_bytes: 0,
+            pending_per_sender: HashMap::with_capacity_and_hasher(0, Default::default()),
+            max_pending_transactions: config.max_pending_transactions,
+            max_pending_bytes: config.max_pending_bytes,
+            max_pending_transactions_per_sender: config.max_pending_transactions_per_sender,
         }
     }
 
@@ -159,15 +228,50 @@ impl<TTx> Pool<TTx> {
         self.transactions.len()
     }
 
+    /// Returns the number of transactions in the pool that aren't included in any block.
+    pub fn num_pending(&self) -> usize {
+        self.transactions.len() - self.by_height.len()
+    }
+
     /// Inserts a new unverified transaction in the pool.
-    pub fn add_unvalidated(&mut self, scale_encoded: Vec<u8>, user_data: TTx) -> TransactionId {
-        self.add_unvalidated_inner(scale_encoded, None, user_data)
+    ///
+    /// `sender` is an opaque identifier of the entity that has submitted the transaction, for
+    /// example the networking peer that has sent it. It is used in order to enforce
+    /// [`Config::max_pending_transactions_per_sender`]. Transactions whose sender is `None` aren't
+    /// subject to this limit.
+    ///
+    /// Returns an error if one of the limits of the pool has been reached, in which case the
+    /// transaction isn't inserted.
+    pub fn add_unvalidated(
+        &mut self,
+        scale_encoded: Vec<u8>,
+        sender: Option<&[u8]>,
+        user_data: TTx,
+    ) -> Result<TransactionId, AddUnvalidatedError> {
+        if self.num_pending() >= self.max_pending_transactions {
+            return Err(AddUnvalidatedError::TooManyTransactions);
+        }
+
+        if self.pending_bytes.saturating_add(scale_encoded.len()) > self.max_pending_bytes {
+            return Err(AddUnvalidatedError::TooManyBytes);
+        }
+
+        if let Some(sender) = sender {
+            if self.pending_per_sender.get(sender).copied().unwrap_or(0)
+                >= self.max_pending_transactions_per_sender
+            {
+                return Err(AddUnvalidatedError::TooManyTransactionsFromSender);
+            }
+        }
+
+        Ok(self.add_unvalidated_inner(scale_encoded, sender.map(|s| s.to_vec()), None, user_data))
     }
 
     /// Inserts a new unverified transaction in the pool.
     fn add_unvalidated_inner(
         &mut self,
         scale_encoded: impl AsRef<[u8]> + Into<Vec<u8>>,
+        sender: Option<Vec<u8>>,
         included_block_height: Option<u64>,
         user_data: TTx,
     ) -> TransactionId {
@@ -175,11 +279,20 @@ impl<TTx> Pool<TTx> {
 
         let tx_id = TransactionId(self.transactions.insert(Transaction {
             scale_encoded: scale_encoded.into(),
+            sender,
             validation: None,
             included_block_height,
             user_data,
         }));
 
+        if included_block_height.is_none() {
+            pending_stats_insert(
+                &mut self.pending_bytes,
+                &mut self.pending_per_sender,
+                &self.transactions[tx_id.0],
+            );
+        }
+
         let _was_inserted = self.by_hash.insert((hash, tx_id));
         debug_assert!(_was_inserted);
 
@@ -212,6 +325,8 @@ impl<TTx> Pool<TTx> {
         if let Some(included_block_height) = tx.included_block_height {
             let _removed = self.by_height.remove(&(included_block_height, id));
             debug_assert!(_removed);
+        } else {
+            pending_stats_remove(&mut self.pending_bytes, &mut self.pending_per_sender, &tx);
         }
 
         let _removed = self.by_hash.remove(&(blake2_hash(&tx.scale_encoded), id));
@@ -258,11 +373,40 @@ impl<TTx> Pool<TTx> {
                 .by_hash
                 .remove(&(blake2_hash(&tx.scale_encoded), tx_id));
             debug_assert!(_removed);
+
+            let _removed = self
+                .by_height
+                .remove(&(tx.included_block_height.unwrap(), tx_id));
+            debug_assert!(_removed);
         }
 
         out.into_iter()
     }
 
+    /// Removes from the pool all the transactions that aren't included in any block and whose
+    /// longevity has expired, in other words that have been successfully validated against a
+    /// block whose height plus the longevity of the transaction is inferior or equal to
+    /// [`Pool::best_block_height`].
+    ///
+    /// Use this method after a block has been appended with [`Pool::append_block`].
+    pub fn remove_expired(&mut self) -> impl Iterator<Item = (TransactionId, TTx)> {
+        // TODO: O(n) :-/
+        let to_remove = self
+            .transactions
+            .iter()
+            .filter(|(_, tx)| {
+                tx.included_block_height.is_none() && tx.is_expired(self.best_block_height)
+            })
+            .map(|(tx_id, _)| TransactionId(tx_id))
+            .collect::<Vec<_>>();
+
+        let mut out = Vec::with_capacity(to_remove.len());
+        for tx_id in to_remove {
+            out.push((tx_id, self.remove(tx_id)));
+        }
+        out.into_iter()
+    }
+
     /// Returns a list of transactions whose state is "not validated", their user data, and the
     /// height of the block they should be validated against.
     ///
@@ -284,12 +428,80 @@ impl<TTx> Pool<TTx> {
 
     /// Returns the transactions from the pool that haven't been included yet in the order in
     /// which they should be inserted in authored blocks.
+    ///
+    /// Only ready transactions are returned. See the module-level documentation for more
+    /// information.
     pub fn inclusion_order(&'_ self) -> impl Iterator<Item = TransactionId> + '_ {
-        // FIXME: /!\
-        // TODO: /!\
-        #![allow(unreachable_code)]
-        let _i: core::iter::Empty<_> = todo!();
-        _i
+        // Transactions whose required tags are all provided, ordered by priority. Transactions
+        // with the same priority are ordered by identifier, which is arbitrary but deterministic.
+        let mut ready = BinaryHeap::new();
+
+        // For each transaction that requires tags, the number of required tags that haven't been
+        // provided yet.
+        let mut num_missing_tags =
+            HashMap::<_, _, fnv::FnvBuildHasher>::with_capacity_and_hasher(0, Default::default());
+
+        // For each tag, the transactions that require this tag and that aren't ready yet.
+        let mut waiting_for_tag = HashMap::<&[u8], Vec<(u64, TransactionId)>, fnv::FnvBuildHasher>::with_capacity_and_hasher(0, Default::default());
+
+        for (tx_id, tx) in &self.transactions {
+            if tx.included_block_height.is_some() {
+                continue;
+            }
+
+            let valid = match tx.valid_at(self.best_block_height) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let tx_id = TransactionId(tx_id);
+            if valid.requires.is_empty() {
+                ready.push((valid.priority, cmp::Reverse(tx_id)));
+            } else {
+                num_missing_tags.insert(tx_id, valid.requires.len());
+                for tag in &valid.requires {
+                    waiting_for_tag
+                        .entry(&tag[..])
+                        .or_default()
+                        .push((valid.priority, tx_id));
+                }
+            }
+        }
+
+        let mut provided_tags = HashSet::<&[u8], fnv::FnvBuildHasher>::default();
+        let mut out = Vec::with_capacity(ready.len());
+
+        while let Some((_, cmp::Reverse(tx_id))) = ready.pop() {
+            let valid = self.transactions[tx_id.0]
+                .valid_at(self.best_block_height)
+                .unwrap();
+
+            // Transactions that provide the same tag are mutually exclusive.
+            if valid
+                .provides
+                .iter()
+                .any(|tag| provided_tags.contains(&tag[..]))
+            {
+                continue;
+            }
+
+            out.push(tx_id);
+
+            for tag in &valid.provides {
+                provided_tags.insert(&tag[..]);
+                for (priority, waiting_tx_id) in
+                    waiting_for_tag.remove(&tag[..]).into_iter().flatten()
+                {
+                    let num_missing = num_missing_tags.get_mut(&waiting_tx_id).unwrap();
+                    *num_missing -= 1;
+                    if *num_missing == 0 {
+                        ready.push((priority, cmp::Reverse(waiting_tx_id)));
+                    }
+                }
+            }
+        }
+
+        out.into_iter()
     }
 
     /// Returns the list of all transactions within the pool.
@@ -365,22 +577,19 @@ impl<TTx> Pool<TTx> {
     pub fn append_block(mut self) -> AppendBlock<TTx> {
         self.best_block_height = self.best_block_height.checked_add(1).unwrap();
 
-        // Un-validate non-included transactions whose longevity has expired.
+        // Un-validate the non-included transactions that require tags. These tags might be
+        // provided by the transactions of the new block, in which case they are no longer
+        // required.
         // TODO: O(n) :-/
-        for (_, tx) in &mut self.transactions {
+        for (tx_id, tx) in &mut self.transactions {
             if tx.included_block_height.is_some() {
                 continue;
             }
 
-            match tx.validation {
-                Some((block_validated, Ok(ValidTransaction { longevity, .. })))
-                    if block_validated.saturating_add(longevity.get())
-                        <= self.best_block_height =>
-                {
-                    tx.validation = None;
-                }
-                _ => {}
-            };
+            if matches!(&tx.validation, Some((_, Ok(valid))) if !valid.requires.is_empty()) {
+                tx.validation = None;
+                self.not_validated.insert(TransactionId(tx_id));
+            }
         }
 
         AppendBlock { inner: self }
@@ -430,15 +639,23 @@ impl<TTx> Pool<TTx> {
             .collect::<Vec<_>>();
 
         // Set `included_block_height` to `None` for each of them.
-        for (transaction_id, _) in &transactions_to_retract {
+        for (transaction_id, block_height) in &transactions_to_retract {
             let mut tx_data = self.transactions.get_mut(transaction_id.0).unwrap();
             debug_assert!(tx_data.included_block_height.unwrap() > self.best_block_height);
             tx_data.included_block_height = None;
+            pending_stats_insert(
+                &mut self.pending_bytes,
+                &mut self.pending_per_sender,
+                tx_data,
+            );
+
+            let _removed = self.by_height.remove(&(*block_height, *transaction_id));
+            debug_assert!(_removed);
         }
 
         // Must cancel validation results against blocks that have been retracted.
         // TODO: this is O(n), do better
-        for (_, transaction) in &mut self.transactions {
+        for (tx_id, transaction) in &mut self.transactions {
             let best_block_height = self.best_block_height;
             if transaction
                 .validation
@@ -446,6 +663,7 @@ impl<TTx> Pool<TTx> {
                 .map_or(false, |(b, _)| *b > best_block_height)
             {
                 transaction.validation = None;
+                self.not_validated.insert(TransactionId(tx_id));
             }
         }
 
@@ -482,6 +700,11 @@ impl<TTx> Pool<TTx> {
             return;
         }
 
+        if tx.validation.is_none() {
+            let _removed = self.not_validated.remove(&id);
+            debug_assert!(_removed);
+        }
+
         tx.validation = Some((block_number_validated_against, result));
     }
 }
@@ -555,6 +778,14 @@ impl<TTx> AppendBlock<TTx> {
 
                 debug_assert!(tx.included_block_height.is_none());
                 tx.included_block_height = Some(best_block_height);
+                pending_stats_remove(
+                    &mut self.inner.pending_bytes,
+                    &mut self.inner.pending_per_sender,
+                    tx,
+                );
+
+                let _was_inserted = self.inner.by_height.insert((best_block_height, id));
+                debug_assert!(_was_inserted);
 
                 if tx
                     .validation
@@ -562,6 +793,7 @@ impl<TTx> AppendBlock<TTx> {
                     .map_or(false, |(b, _)| *b + 1 != best_block_height)
                 {
                     tx.validation = None;
+                    self.inner.not_validated.insert(id);
                 }
 
                 let user_data = &mut tx.user_data;
@@ -611,8 +843,12 @@ pub struct Vacant<'a, 'b, TTx> {
 impl<'a, 'b, TTx> Vacant<'a, 'b, TTx> {
     /// Inserts the transaction in the pool.
     pub fn insert(self, user_data: TTx) -> TransactionId {
-        self.inner
-            .add_unvalidated_inner(self.bytes, Some(self.inner.best_block_height), user_data)
+        self.inner.add_unvalidated_inner(
+            self.bytes,
+            None,
+            Some(self.inner.best_block_height),
+            user_data,
+        )
     }
 }
 
@@ -622,11 +858,31 @@ impl<'a, 'b, TTx: fmt::Debug> fmt::Debug for Vacant<'a, 'b, TTx> {
     }
 }
 
+/// Error potentially returned by [`Pool::add_unvalidated`].
+#[derive(Debug, derive_more::Display, Clone, PartialEq, Eq)]
+pub enum AddUnvalidatedError {
+    /// The pool already contains [`Config::max_pending_transactions`] transactions that aren't
+    /// included in any block.
+    #[display(fmt = "Maximum number of pending transactions reached")]
+    TooManyTransactions,
+    /// Adding the transaction would make the total size of the transactions that aren't
+    /// included in any block go over [`Config::max_pending_bytes`].
+    #[display(fmt = "Maximum size of pending transactions reached")]
+    TooManyBytes,
+    /// The pool already contains [`Config::max_pending_transactions_per_sender`] transactions
+    /// from this sender that aren't included in any block.
+    #[display(fmt = "Maximum number of pending transactions of this sender reached")]
+    TooManyTransactionsFromSender,
+}
+
 /// Entry in [`Pool::transactions`].
 struct Transaction<TTx> {
     /// Bytes corresponding to the SCALE-encoded transaction.
     scale_encoded: Vec<u8>,
 
+    /// Sender of the transaction, as passed to [`Pool::add_unvalidated`].
+    sender: Option<Vec<u8>>,
+
     /// If `Some`, contains the outcome of the validation of this transaction and the block height
     /// it was validated against.
     validation: Option<(u64, Result<ValidTransaction, TransactionValidityError>)>,
@@ -638,9 +894,58 @@ struct Transaction<TTx> {
     user_data: TTx,
 }
 
+impl<TTx> Transaction<TTx> {
+    /// Returns the outcome of the validation of this transaction if it has been successfully
+    /// validated and its longevity hasn't expired at the given best block height.
+    fn valid_at(&self, best_block_height: u64) -> Option<&ValidTransaction> {
+        match &self.validation {
+            Some((_, Ok(valid))) if !self.is_expired(best_block_height) => Some(valid),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this transaction has been successfully validated and its longevity has
+    /// expired at the given best block height.
+    fn is_expired(&self, best_block_height: u64) -> bool {
+        matches!(
+            &self.validation,
+            Some((block_validated, Ok(ValidTransaction { longevity, .. })))
+                if block_validated.saturating_add(longevity.get()) <= best_block_height
+        )
+    }
+}
+
+/// Updates [`Pool::pending_bytes`] and [`Pool::pending_per_sender`] after the given transaction
+/// has switched to being non-included.
+fn pending_stats_insert<TTx>(
+    pending_bytes: &mut usize,
+    pending_per_sender: &mut HashMap<Vec<u8>, usize, fnv::FnvBuildHasher>,
+    tx: &Transaction<TTx>,
+) {
+    *pending_bytes += tx.scale_encoded.len();
+    if let Some(sender) = &tx.sender {
+        *pending_per_sender.entry(sender.clone()).or_insert(0) += 1;
+    }
+}
+
+/// Updates [`Pool::pending_bytes`] and [`Pool::pending_per_sender`] after the given transaction
+/// has stopped being non-included.
+fn pending_stats_remove<TTx>(
+    pending_bytes: &mut usize,
+    pending_per_sender: &mut HashMap<Vec<u8>, usize, fnv::FnvBuildHasher>,
+    tx: &Transaction<TTx>,
+) {
+    *pending_bytes -= tx.scale_encoded.len();
+    if let Some(sender) = &tx.sender {
+        let num = pending_per_sender.get_mut(sender).unwrap();
+        *num -= 1;
+        if *num == 0 {
+            pending_per_sender.remove(sender);
+        }
+    }
+}
+
 /// Utility. Calculates the BLAKE2 hash of the given bytes.
 fn blake2_hash(bytes: &[u8]) -> [u8; 32] {
     <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], bytes).as_bytes()).unwrap()
 }
-
-// TODO: needs tests
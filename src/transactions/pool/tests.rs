@@ -0,0 +1,252 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use core::num::NonZeroU64;
+
+use super::super::validate;
+use super::{Config, Pool};
+
+fn config() -> Config {
+    Config {
+        capacity: 16,
+        finalized_block_height: 0,
+        max_pending_transactions: 16,
+        max_pending_bytes: 1024,
+        max_pending_transactions_per_sender: 4,
+    }
+}
+
+fn valid_transaction(priority: u64) -> validate::ValidTransaction {
+    validate::ValidTransaction {
+        priority,
+        requires: Vec::new(),
+        provides: vec![vec![priority as u8]],
+        longevity: NonZeroU64::new(16).unwrap(),
+        propagate: true,
+    }
+}
+
+fn valid_transaction_tags(
+    priority: u64,
+    requires: &[u8],
+    provides: &[u8],
+) -> validate::ValidTransaction {
+    validate::ValidTransaction {
+        priority,
+        requires: requires.iter().map(|t| vec![*t]).collect(),
+        provides: provides.iter().map(|t| vec![*t]).collect(),
+        longevity: NonZeroU64::new(16).unwrap(),
+        propagate: true,
+    }
+}
+
+#[test]
+fn regular_path() {
+    let mut pool = Pool::new(config());
+
+    let tx_id = pool.add_unvalidated(vec![0], None, ()).unwrap();
+    assert_eq!(pool.unvalidated_transactions().count(), 1);
+    assert_eq!(pool.inclusion_order().count(), 0);
+
+    pool.set_validation_result(tx_id, 0, Ok(valid_transaction(1)));
+    assert_eq!(pool.unvalidated_transactions().count(), 0);
+    assert_eq!(pool.inclusion_order().collect::<Vec<_>>(), vec![tx_id]);
+
+    let mut append = pool.append_block();
+    match append.block_transaction(&[0]) {
+        super::AppendBlockTransaction::NonIncludedUpdated { id, .. } => assert_eq!(id, tx_id),
+        super::AppendBlockTransaction::Unknown(_) => panic!(),
+    }
+    let mut pool = append.finish();
+    assert_eq!(pool.included_block_height(tx_id), Some(1));
+    assert_eq!(pool.inclusion_order().count(), 0);
+
+    assert_eq!(pool.remove_included(1).count(), 1);
+    assert!(pool.is_empty());
+    assert_eq!(pool.unvalidated_transactions().count(), 0);
+}
+
+#[test]
+fn retract_invalidates() {
+    let pool = Pool::new(config());
+
+    let mut pool = pool.append_block().finish();
+    let tx_id = pool.add_unvalidated(vec![0], None, ()).unwrap();
+    pool.set_validation_result(tx_id, 1, Ok(valid_transaction(1)));
+    assert_eq!(pool.unvalidated_transactions().count(), 0);
+
+    assert_eq!(pool.retract_blocks(1).count(), 0);
+    assert_eq!(
+        pool.unvalidated_transactions()
+            .map(|(id, _, height)| (id, height))
+            .collect::<Vec<_>>(),
+        vec![(tx_id, 0)]
+    );
+
+    pool.remove(tx_id);
+    assert!(pool.is_empty());
+    assert_eq!(pool.unvalidated_transactions().count(), 0);
+}
+
+#[test]
+fn inclusion_order_priority() {
+    let mut pool = Pool::new(config());
+
+    let tx1 = pool.add_unvalidated(vec![1], None, ()).unwrap();
+    let tx2 = pool.add_unvalidated(vec![2], None, ()).unwrap();
+    let tx3 = pool.add_unvalidated(vec![3], None, ()).unwrap();
+    pool.set_validation_result(tx1, 0, Ok(valid_transaction(5)));
+    pool.set_validation_result(tx2, 0, Ok(valid_transaction(10)));
+    pool.set_validation_result(
+        tx3,
+        0,
+        Err(validate::TransactionValidityError::Invalid(
+            validate::InvalidTransaction::Call,
+        )),
+    );
+
+    assert_eq!(pool.inclusion_order().collect::<Vec<_>>(), vec![tx2, tx1]);
+}
+
+#[test]
+fn requires_and_provides() {
+    let mut pool = Pool::new(config());
+
+    // `tx3` requires a tag provided by `tx2`, which requires a tag provided by `tx1`. The
+    // priorities are the opposite of the dependency order.
+    let tx1 = pool.add_unvalidated(vec![1], None, ()).unwrap();
+    let tx2 = pool.add_unvalidated(vec![2], None, ()).unwrap();
+    let tx3 = pool.add_unvalidated(vec![3], None, ()).unwrap();
+    let tx4 = pool.add_unvalidated(vec![4], None, ()).unwrap();
+    pool.set_validation_result(tx1, 0, Ok(valid_transaction_tags(1, &[], &[10])));
+    pool.set_validation_result(tx2, 0, Ok(valid_transaction_tags(2, &[10], &[11])));
+    pool.set_validation_result(tx3, 0, Ok(valid_transaction_tags(3, &[11], &[12])));
+    pool.set_validation_result(tx4, 0, Ok(valid_transaction_tags(2, &[], &[20])));
+
+    assert_eq!(
+        pool.inclusion_order().collect::<Vec<_>>(),
+        vec![tx4, tx1, tx2, tx3]
+    );
+}
+
+#[test]
+fn missing_required_tag() {
+    let mut pool = Pool::new(config());
+
+    let tx1 = pool.add_unvalidated(vec![1], None, ()).unwrap();
+    let tx2 = pool.add_unvalidated(vec![2], None, ()).unwrap();
+    pool.set_validation_result(tx1, 0, Ok(valid_transaction_tags(1, &[30], &[10])));
+    pool.set_validation_result(tx2, 0, Ok(valid_transaction_tags(1, &[10], &[11])));
+
+    assert_eq!(pool.inclusion_order().count(), 0);
+}
+
+#[test]
+fn conflicting_provides() {
+    let mut pool = Pool::new(config());
+
+    let tx1 = pool.add_unvalidated(vec![1], None, ()).unwrap();
+    let tx2 = pool.add_unvalidated(vec![2], None, ()).unwrap();
+    pool.set_validation_result(tx1, 0, Ok(valid_transaction_tags(1, &[], &[10])));
+    pool.set_validation_result(tx2, 0, Ok(valid_transaction_tags(5, &[], &[10])));
+
+    assert_eq!(pool.inclusion_order().collect::<Vec<_>>(), vec![tx2]);
+}
+
+#[test]
+fn requires_unvalidated_on_new_block() {
+    let mut pool = Pool::new(config());
+
+    let tx1 = pool.add_unvalidated(vec![1], None, ()).unwrap();
+    let tx2 = pool.add_unvalidated(vec![2], None, ()).unwrap();
+    pool.set_validation_result(tx1, 0, Ok(valid_transaction_tags(1, &[], &[10])));
+    pool.set_validation_result(tx2, 0, Ok(valid_transaction_tags(1, &[10], &[11])));
+
+    let pool = pool.append_block().finish();
+    assert_eq!(
+        pool.unvalidated_transactions()
+            .map(|(id, _, height)| (id, height))
+            .collect::<Vec<_>>(),
+        vec![(tx2, 1)]
+    );
+    assert_eq!(pool.inclusion_order().collect::<Vec<_>>(), vec![tx1]);
+}
+
+#[test]
+fn longevity_expires() {
+    let mut pool = Pool::new(config());
+
+    let tx_id = pool.add_unvalidated(vec![0], None, ()).unwrap();
+    pool.set_validation_result(
+        tx_id,
+        0,
+        Ok(validate::ValidTransaction {
+            longevity: NonZeroU64::new(2).unwrap(),
+            ..valid_transaction(1)
+        }),
+    );
+
+    let mut pool = pool.append_block().finish();
+    assert_eq!(pool.remove_expired().count(), 0);
+    assert_eq!(pool.inclusion_order().count(), 1);
+
+    let mut pool = pool.append_block().finish();
+    assert_eq!(pool.inclusion_order().count(), 0);
+    assert_eq!(
+        pool.remove_expired().map(|(id, _)| id).collect::<Vec<_>>(),
+        vec![tx_id]
+    );
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn limits() {
+    let mut pool = Pool::new(Config {
+        max_pending_transactions: 3,
+        max_pending_bytes: 8,
+        max_pending_transactions_per_sender: 1,
+        ..config()
+    });
+
+    pool.add_unvalidated(vec![0; 4], Some(&[1]), ()).unwrap();
+    assert_eq!(
+        pool.add_unvalidated(vec![1], Some(&[1]), ()),
+        Err(super::AddUnvalidatedError::TooManyTransactionsFromSender)
+    );
+    assert_eq!(
+        pool.add_unvalidated(vec![1; 5], Some(&[2]), ()),
+        Err(super::AddUnvalidatedError::TooManyBytes)
+    );
+    pool.add_unvalidated(vec![2], Some(&[2]), ()).unwrap();
+    let tx_id = pool.add_unvalidated(vec![3], None, ()).unwrap();
+    assert_eq!(
+        pool.add_unvalidated(vec![4], None, ()),
+        Err(super::AddUnvalidatedError::TooManyTransactions)
+    );
+
+    // Including a transaction in a block frees space.
+    let mut append = pool.append_block();
+    assert!(matches!(
+        append.block_transaction(&[3]),
+        super::AppendBlockTransaction::NonIncludedUpdated { id, .. } if id == tx_id
+    ));
+    let mut pool = append.finish();
+    assert_eq!(pool.num_pending(), 2);
+    pool.add_unvalidated(vec![4], None, ()).unwrap();
+}
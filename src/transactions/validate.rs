@@ -324,6 +324,7 @@ pub fn validate_transaction(
                 .scale_encoding(config.block_number_bytes),
                 top_trie_root_calculation_cache: None,
                 storage_top_trie_changes: storage_diff::StorageDiff::empty(),
+                storage_child_tries_changes: Default::default(),
                 offchain_storage_changes: storage_diff::StorageDiff::empty(),
             });
 
@@ -360,6 +361,7 @@ pub fn validate_transaction(
                 ),
                 top_trie_root_calculation_cache: None,
                 storage_top_trie_changes: storage_diff::StorageDiff::empty(),
+                storage_child_tries_changes: Default::default(),
                 offchain_storage_changes: storage_diff::StorageDiff::empty(),
             });
 
@@ -448,6 +450,7 @@ impl Query {
                             info.transaction_source,
                         ),
                         storage_top_trie_changes: success.storage_top_trie_changes,
+                        storage_child_tries_changes: success.storage_child_tries_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
                         top_trie_root_calculation_cache: Some(
                             success.top_trie_root_calculation_cache,
@@ -479,6 +482,10 @@ impl Query {
                     inner = sig.verify_and_resume();
                     continue;
                 }
+                runtime_host::RuntimeHostVm::Offchain(ctx) => {
+                    inner = runtime_host::RuntimeHostVm::Finished(Err(ctx.forbid()));
+                    continue;
+                }
             };
         }
     }
@@ -537,6 +544,10 @@ impl Query {
                     inner = sig.verify_and_resume();
                     continue;
                 }
+                runtime_host::RuntimeHostVm::Offchain(ctx) => {
+                    inner = runtime_host::RuntimeHostVm::Finished(Err(ctx.forbid()));
+                    continue;
+                }
             };
         }
     }
@@ -569,6 +580,15 @@ impl StorageGet {
         }
     }
 
+    /// If `Some`, the key must be read from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        match &self.0 {
+            StorageGetInner::Stage1(inner, _) => inner.child_trie().map(either::Left),
+            StorageGetInner::Stage2(inner, _) => inner.child_trie().map(either::Right),
+        }
+    }
+
     /// Injects the corresponding storage value.
     pub fn inject_value(self, value: Option<impl Iterator<Item = impl AsRef<[u8]>>>) -> Query {
         match self.0 {
@@ -600,6 +620,16 @@ impl NextKey {
         }
     }
 
+    /// If `Some`, the next key must be searched in the given default child trie rather than in
+    /// the main trie. The child trie identifier doesn't include the `:child_storage:default:`
+    /// prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        match &self.0 {
+            NextKeyInner::Stage1(inner, _) => inner.child_trie().map(either::Left),
+            NextKeyInner::Stage2(inner, _) => inner.child_trie().map(either::Right),
+        }
+    }
+
     /// Injects the key.
     ///
     /// # Panic
@@ -633,6 +663,15 @@ impl PrefixKeys {
         }
     }
 
+    /// If `Some`, the keys must be loaded from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        match &self.0 {
+            PrefixKeysInner::Stage1(inner, _) => inner.child_trie().map(either::Left),
+            PrefixKeysInner::Stage2(inner, _) => inner.child_trie().map(either::Right),
+        }
+    }
+
     /// Injects the list of keys ordered lexicographically.
     pub fn inject_keys_ordered(self, keys: impl Iterator<Item = impl AsRef<[u8]>>) -> Query {
         match self.0 {
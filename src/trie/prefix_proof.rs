@@ -41,12 +41,20 @@ pub struct Config<'a> {
     ///
     /// > **Note**: The Merkle value and node value are always the same for the root node.
     pub trie_root_hash: [u8; 32],
+
+    /// If `Some`, the keys are searched in the given default child trie instead of the main trie.
+    /// The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    ///
+    /// [`Config::trie_root_hash`] must still be the root of the main trie, and the proofs must
+    /// contain the entries necessary to find the root of the child trie.
+    pub child_trie: Option<&'a [u8]>,
 }
 
 /// Start a new scanning process.
 pub fn prefix_scan(config: Config<'_>) -> PrefixScan {
     PrefixScan {
         trie_root_hash: config.trie_root_hash,
+        child_trie: config.child_trie.map(|child_trie| child_trie.to_vec()),
         next_queries: vec![nibble::bytes_to_nibbles(config.prefix.iter().copied()).collect()],
         final_result: Vec::with_capacity(32),
     }
@@ -55,6 +63,7 @@ pub fn prefix_scan(config: Config<'_>) -> PrefixScan {
 /// Scan of a prefix in progress.
 pub struct PrefixScan {
     trie_root_hash: [u8; 32],
+    child_trie: Option<Vec<u8>>,
     // TODO: we have lots of Vecs here; maybe find a way to optimize
     next_queries: Vec<Vec<nibble::Nibble>>,
     // TODO: we have lots of Vecs here; maybe find a way to optimize
@@ -63,6 +72,8 @@ pub struct PrefixScan {
 
 impl PrefixScan {
     /// Returns the list of keys whose storage proof must be queried.
+    ///
+    /// If [`Config::child_trie`] was `Some`, these keys are keys of the child trie.
     pub fn requested_keys(
         &'_ self,
     ) -> impl Iterator<Item = impl Iterator<Item = nibble::Nibble> + '_> + '_ {
@@ -97,7 +108,11 @@ impl PrefixScan {
                     None => break,
                 };
 
-                let info = match decoded_proof.trie_node_info(&query) {
+                let info = match &self.child_trie {
+                    Some(child_trie) => decoded_proof.child_trie_node_info(child_trie, &query),
+                    None => decoded_proof.trie_node_info(&query),
+                };
+                let info = match info {
                     Some(info) => info,
                     None if !is_first_iteration => {
                         // Node not in the proof. There's no point in adding this node to `next`
@@ -61,8 +61,11 @@ pub struct Config<'a, I> {
 ///
 /// Due to the generic nature of this function, the proof can be either a `Vec<u8>` or a `&[u8]`.
 ///
+/// The proof can also contain the nodes of default child tries whose root is found in the main
+/// trie.
+///
 /// Returns an error if the proof is invalid, or if the proof contains entries that are
-/// disconnected from the root node of the trie.
+/// disconnected from the root node of the trie and from the root nodes of these child tries.
 pub fn decode_and_verify_proof<'a, T>(config: Config<'a, T>) -> Result<DecodedTrieProof<T>, Error>
 where
     T: AsRef<[u8]>,
@@ -138,6 +141,7 @@ where
         return Ok(DecodedTrieProof {
             proof: config.proof,
             entries: BTreeMap::new(),
+            child_tries: BTreeMap::new(),
         });
     }
 
@@ -149,16 +153,87 @@ where
         (0..merkle_values.len()).collect::<hashbrown::HashSet<_, fnv::FnvBuildHasher>>();
 
     // Find the expected trie root in the proof. This is the starting point of the verification.
-    let mut remain_iterate = {
+    let root_range = {
         let (root_position, root_range) = merkle_values
             .get(&config.trie_root_hash[..])
             .ok_or(Error::TrieRootNotFound)?
             .clone();
         let _ = unvisited_proof_entries.remove(&root_position);
-        vec![(root_range, Vec::new())]
+        root_range
     };
 
-    // Keep track of all the entries found in the proof.
+    // Keep track of all the entries of the main trie found in the proof.
+    let entries = traverse_trie(
+        proof_as_ref,
+        &merkle_values,
+        &mut unvisited_proof_entries,
+        root_range,
+    )?;
+
+    // The main trie contains, under the `:child_storage:default:` prefix, the Merkle values of
+    // the root nodes of the default child tries. If the root node of a child trie is found in the
+    // proof, then this child trie is traversed as well.
+    let mut child_tries = BTreeMap::new();
+    let child_tries_prefix =
+        nibble::bytes_to_nibbles(CHILD_TRIE_PREFIX.iter().copied()).collect::<Vec<_>>();
+    for (_, (storage_value, _, _)) in entries
+        .range::<[nibble::Nibble], _>((
+            ops::Bound::Included(&child_tries_prefix[..]),
+            ops::Bound::Unbounded,
+        ))
+        .take_while(|(k, _)| k.starts_with(&child_tries_prefix))
+    {
+        let child_trie_root = match storage_value {
+            StorageValueInner::Known { offset, len, .. } if *len == 32 => {
+                <[u8; 32]>::try_from(&proof_as_ref[*offset..][..32]).unwrap()
+            }
+            _ => continue,
+        };
+
+        // Identical child tries share the same root.
+        if child_tries.contains_key(&child_trie_root) {
+            continue;
+        }
+
+        let (child_root_position, child_root_range) = match merkle_values.get(&child_trie_root[..])
+        {
+            Some(v) => v.clone(),
+            None => continue,
+        };
+        let _ = unvisited_proof_entries.remove(&child_root_position);
+
+        let child_entries = traverse_trie(
+            proof_as_ref,
+            &merkle_values,
+            &mut unvisited_proof_entries,
+            child_root_range,
+        )?;
+        child_tries.insert(child_trie_root, child_entries);
+    }
+
+    // The entire reason why we track the unvisited proof entries is to return this error if
+    // necessary.
+    if !unvisited_proof_entries.is_empty() {
+        return Err(Error::UnusedProofEntry);
+    }
+
+    Ok(DecodedTrieProof {
+        proof: config.proof,
+        entries,
+        child_tries,
+    })
+}
+
+/// Iterates down the tree of nodes starting at the given root node, and returns the list of
+/// all the nodes that were found in the proof. The proof entries that are traversed are removed
+/// from `unvisited_proof_entries`.
+fn traverse_trie(
+    proof_as_ref: &[u8],
+    merkle_values: &hashbrown::HashMap<[u8; 32], (usize, ops::Range<usize>), fnv::FnvBuildHasher>,
+    unvisited_proof_entries: &mut hashbrown::HashSet<usize, fnv::FnvBuildHasher>,
+    root_range: ops::Range<usize>,
+) -> Result<TrieEntries, Error> {
+    let mut remain_iterate = vec![(root_range, Vec::new())];
     let mut entries = BTreeMap::new();
 
     while !remain_iterate.is_empty() {
@@ -292,18 +367,17 @@ where
         }
     }
 
-    // The entire reason why we track the unvisited proof entries is to return this error if
-    // necessary.
-    if !unvisited_proof_entries.is_empty() {
-        return Err(Error::UnusedProofEntry);
-    }
-
-    Ok(DecodedTrieProof {
-        proof: config.proof,
-        entries,
-    })
+    Ok(entries)
 }
 
+/// For each storage key, contains the entry found in the proof, the range at which to find its
+/// node value, and the children bitmap.
+type TrieEntries = BTreeMap<Vec<nibble::Nibble>, (StorageValueInner, ops::Range<usize>, u16)>;
+
+/// Prefix of the keys of the main trie under which the roots of the default child tries are
+/// found.
+const CHILD_TRIE_PREFIX: &[u8] = b":child_storage:default:";
+
 /// Equivalent to [`StorageValue`] but contains offsets indexing [`DecodedTrieProof::proof`].
 #[derive(Debug, Copy, Clone)]
 enum StorageValueInner {
@@ -328,10 +402,19 @@ pub struct DecodedTrieProof<T> {
     /// For each storage key, contains the entry found in the proof, the range at which to find
     /// its node value, and the children bitmap.
     // TODO: a BTreeMap is actually kind of stupid since `proof` is itself in a tree format
-    entries: BTreeMap<Vec<nibble::Nibble>, (StorageValueInner, ops::Range<usize>, u16)>,
+    entries: TrieEntries,
+
+    /// Same as [`DecodedTrieProof::entries`], but for each default child trie found in the
+    /// proof. Indexed by the Merkle value of the root node of the child trie.
+    child_tries: BTreeMap<[u8; 32], TrieEntries>,
 }
 
 impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
+    /// Returns the proof that has been passed to [`decode_and_verify_proof`].
+    pub fn encoded_proof(&self) -> &T {
+        &self.proof
+    }
+
     /// Returns a list of all elements of the proof, ordered by key in lexicographic order.
     ///
     /// This function is a convenient wrapper around [`DecodedTrieProof::iter_ordered`] that
@@ -451,10 +534,54 @@ impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
     /// This function might return `Some` even if there is no node in the trie for `key`, in which
     /// case the returned [`TrieNodeInfo`] will indicate no storage value and no children.
     pub fn trie_node_info(&'_ self, key: &[nibble::Nibble]) -> Option<TrieNodeInfo<'_>> {
+        self.trie_node_info_inner(&self.entries, key)
+    }
+
+    /// Returns information about a trie node of the given default child trie.
+    ///
+    /// `child_trie` is the identifier of the child trie, without the `:child_storage:default:`
+    /// prefix.
+    ///
+    /// Returns `None` if the proof doesn't contain enough information about this trie node, which
+    /// includes the situation where the root of the child trie isn't known.
+    ///
+    /// If the child trie doesn't exist, then the returned [`TrieNodeInfo`] will indicate no
+    /// storage value and no children.
+    pub fn child_trie_node_info(
+        &'_ self,
+        child_trie: &[u8],
+        key: &[nibble::Nibble],
+    ) -> Option<TrieNodeInfo<'_>> {
+        let child_trie_root_key = CHILD_TRIE_PREFIX
+            .iter()
+            .chain(child_trie.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        let child_trie_root = match self.storage_value(&child_trie_root_key)? {
+            Some(root) => root,
+            None => {
+                return Some(TrieNodeInfo {
+                    storage_value: StorageValue::None,
+                    children: Children { children_bitmap: 0 },
+                })
+            }
+        };
+
+        let entries = self
+            .child_tries
+            .get(<&[u8; 32]>::try_from(child_trie_root).ok()?)?;
+        self.trie_node_info_inner(entries, key)
+    }
+
+    fn trie_node_info_inner(
+        &'_ self,
+        entries: &TrieEntries,
+        key: &[nibble::Nibble],
+    ) -> Option<TrieNodeInfo<'_>> {
         // If the proof is empty, then we have no information about the node whatsoever.
         // This check is necessary because we assume below that a lack of ancestor means that the
         // key is outside of the trie.
-        if self.entries.is_empty() {
+        if entries.is_empty() {
             return None;
         }
 
@@ -467,8 +594,7 @@ impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
         loop {
             debug_assert!(key.starts_with(to_search));
 
-            match self
-                .entries
+            match entries
                 .range::<[nibble::Nibble], _>((
                     ops::Bound::Unbounded,
                     ops::Bound::Included(to_search),
@@ -476,7 +602,7 @@ impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
                 .next_back()
             {
                 None => {
-                    debug_assert!(!self.entries.is_empty());
+                    debug_assert!(!entries.is_empty());
                     // The requested key doesn't have any ancestor in the trie. This means that
                     // it doesn't share any prefix with any other entry in the trie. This means
                     // that it doesn't exist.
@@ -516,8 +642,7 @@ impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
                             storage_value: StorageValue::None,
                             children: Children { children_bitmap: 0 },
                         });
-                    } else if self
-                        .entries
+                    } else if entries
                         .range::<[nibble::Nibble], _>((
                             ops::Bound::Included(&key[..found_key.len() + 1]),
                             ops::Bound::Unbounded,
@@ -580,7 +705,178 @@ impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
         }
     }
 
-    // TODO: add a ̀`next_key` and a `prefix_keys` function
+    /// Queries from the proof the storage value at the given key of the given default child
+    /// trie.
+    ///
+    /// `child_trie` is the identifier of the child trie, without the `:child_storage:default:`
+    /// prefix.
+    ///
+    /// Returns `None` if the storage value couldn't be determined from the proof. Returns
+    /// `Some(None)` if the storage value is known to have no value.
+    ///
+    /// > **Note**: This function is a convenient wrapper around
+    /// >           [`DecodedTrieProof::child_trie_node_info`].
+    pub fn child_trie_storage_value(
+        &'_ self,
+        child_trie: &[u8],
+        key: &[u8],
+    ) -> Option<Option<&'_ [u8]>> {
+        let key = nibble::bytes_to_nibbles(key.iter().copied()).collect::<Vec<_>>();
+        match self.child_trie_node_info(child_trie, &key)?.storage_value {
+            StorageValue::Known(v) => Some(Some(v)),
+            StorageValue::HashKnownValueMissing(_) => None,
+            StorageValue::None => Some(None),
+        }
+    }
+
+    /// Returns the key of the first storage value of the main trie that is strictly superior to
+    /// `key`, or superior or equal to `key` if `or_equal` is `true`.
+    ///
+    /// Only the storage values at keys that consist in an even number of nibbles are considered.
+    /// See [`DecodedTrieProof::iter_runtime_context_ordered`] for an explanation.
+    ///
+    /// Returns `Ok(None)` if it has been proven that there is no such key.
+    ///
+    /// Returns an error if the proof doesn't contain enough information to determine the next
+    /// key. The error indicates the prefix of the keys of the trie node that is missing from the
+    /// proof, and that could contain the next key. All the keys that are lexicographically
+    /// inferior to this prefix are guaranteed to not be the next key.
+    pub fn next_key(
+        &'_ self,
+        key: &[nibble::Nibble],
+        or_equal: bool,
+    ) -> Result<Option<&'_ [nibble::Nibble]>, IncompleteProofError> {
+        // The root node is the node whose key is the smallest, as its key is a prefix of the keys
+        // of all the other nodes.
+        let Some((root_key, _)) = self.entries.iter().next() else {
+            return Err(IncompleteProofError {
+                missing_node_prefix: Vec::new(),
+            });
+        };
+
+        Self::next_key_inner(&self.entries, root_key, key, or_equal)
+    }
+
+    /// Returns the key of the first storage value of the given default child trie that is
+    /// strictly superior to `key`, or superior or equal to `key` if `or_equal` is `true`.
+    ///
+    /// `child_trie` is the identifier of the child trie, without the `:child_storage:default:`
+    /// prefix.
+    ///
+    /// Returns `Ok(None)` if it has been proven that there is no such key, which includes the
+    /// situation where the child trie doesn't exist.
+    ///
+    /// Returns an error if the proof doesn't contain enough information to determine the next
+    /// key. See [`DecodedTrieProof::next_key`]. If the root of the child trie can't be found in
+    /// the proof, the missing node prefix is empty.
+    pub fn child_trie_next_key(
+        &'_ self,
+        child_trie: &[u8],
+        key: &[nibble::Nibble],
+        or_equal: bool,
+    ) -> Result<Option<&'_ [nibble::Nibble]>, IncompleteProofError> {
+        let child_trie_root_key = CHILD_TRIE_PREFIX
+            .iter()
+            .chain(child_trie.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        let child_trie_root = match self.storage_value(&child_trie_root_key) {
+            Some(Some(root)) => root,
+            Some(None) => return Ok(None),
+            None => {
+                return Err(IncompleteProofError {
+                    missing_node_prefix: Vec::new(),
+                })
+            }
+        };
+
+        let Some(entries) = <&[u8; 32]>::try_from(child_trie_root)
+            .ok()
+            .and_then(|root| self.child_tries.get(root))
+        else {
+            return Err(IncompleteProofError {
+                missing_node_prefix: Vec::new(),
+            });
+        };
+
+        let Some((root_key, _)) = entries.iter().next() else {
+            return Err(IncompleteProofError {
+                missing_node_prefix: Vec::new(),
+            });
+        };
+
+        Self::next_key_inner(entries, root_key, key, or_equal)
+    }
+
+    /// Searches for the next key within the given node and its descendants, in lexicographic
+    /// order. See [`DecodedTrieProof::next_key`].
+    fn next_key_inner<'a>(
+        entries: &'a TrieEntries,
+        node_key: &'a [nibble::Nibble],
+        key: &[nibble::Nibble],
+        or_equal: bool,
+    ) -> Result<Option<&'a [nibble::Nibble]>, IncompleteProofError> {
+        let (storage_value, _, children_bitmap) = entries.get(node_key).unwrap();
+
+        // The key of the node is inferior to the keys of all its descendants. If the node has a
+        // storage value that matches, then it is the next key. Keys with an odd number of nibbles
+        // can't be storage keys in the context of the runtime.
+        let odd_length = !node_key.len().is_multiple_of(2);
+        if !odd_length
+            && !matches!(storage_value, StorageValueInner::None)
+            && (node_key > key || (or_equal && node_key == key))
+        {
+            return Ok(Some(node_key));
+        }
+
+        for nibble in nibble::all_nibbles() {
+            if children_bitmap & (1 << u8::from(nibble)) == 0 {
+                continue;
+            }
+
+            let mut child_prefix = node_key.to_vec();
+            child_prefix.push(nibble);
+
+            // Skip the children whose descendants are all inferior to `key`.
+            if &child_prefix[..] < key && !key.starts_with(&child_prefix) {
+                continue;
+            }
+
+            // The child node is the entry of the proof with the smallest key that starts with
+            // `child_prefix`. If there isn't any, the child is missing from the proof.
+            let child_key = match entries
+                .range::<[nibble::Nibble], _>((
+                    ops::Bound::Included(&child_prefix[..]),
+                    ops::Bound::Unbounded,
+                ))
+                .next()
+            {
+                Some((k, _)) if k.starts_with(&child_prefix) => k,
+                _ => {
+                    return Err(IncompleteProofError {
+                        missing_node_prefix: child_prefix,
+                    })
+                }
+            };
+
+            if let Some(next_key) = Self::next_key_inner(entries, child_key, key, or_equal)? {
+                return Ok(Some(next_key));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // TODO: add a `prefix_keys` function
+}
+
+/// Error potentially returned by [`DecodedTrieProof::next_key`] and
+/// [`DecodedTrieProof::child_trie_next_key`].
+#[derive(Debug, Clone, derive_more::Display)]
+#[display(fmt = "Proof doesn't contain enough information")]
+pub struct IncompleteProofError {
+    /// Prefix of the keys of the trie node that is missing from the proof.
+    pub missing_node_prefix: Vec<nibble::Nibble>,
 }
 
 /// Storage value of the node.
@@ -955,4 +1251,187 @@ mod tests {
         })
         .unwrap();
     }
+
+    #[test]
+    fn next_key_works() {
+        use super::super::{nibble, proof_encode, TrieEntryVersion};
+
+        let entries = [
+            (&b"a"[..], &[1; 40][..]),
+            (&b"ab"[..], &[2; 40][..]),
+            (&b"b"[..], &[3; 40][..]),
+            (&b"bc"[..], &[4; 40][..]),
+        ];
+        let to_nibbles =
+            |key: &[u8]| nibble::bytes_to_nibbles(key.iter().copied()).collect::<Vec<_>>();
+
+        let (trie_root_hash, proof) = proof_encode::build_proof_from_trie_entries(
+            TrieEntryVersion::V0,
+            entries,
+            entries.iter().map(|(k, _)| k),
+        );
+        let decoded = super::decode_and_verify_proof(super::Config {
+            trie_root_hash: &trie_root_hash,
+            proof,
+        })
+        .unwrap();
+
+        assert_eq!(
+            decoded.next_key(&[], false).unwrap(),
+            Some(&to_nibbles(b"a")[..])
+        );
+        assert_eq!(
+            decoded.next_key(&to_nibbles(b"a"), true).unwrap(),
+            Some(&to_nibbles(b"a")[..])
+        );
+        assert_eq!(
+            decoded.next_key(&to_nibbles(b"a"), false).unwrap(),
+            Some(&to_nibbles(b"ab")[..])
+        );
+        assert_eq!(
+            decoded.next_key(&to_nibbles(b"ab"), false).unwrap(),
+            Some(&to_nibbles(b"b")[..])
+        );
+        assert_eq!(decoded.next_key(&to_nibbles(b"bc"), false).unwrap(), None);
+
+        // Build a proof that only contains the information about `a`. The values are large
+        // enough for the nodes to not be inlined in their parent.
+        let (trie_root_hash, proof) =
+            proof_encode::build_proof_from_trie_entries(TrieEntryVersion::V0, entries, [b"a"]);
+        let decoded = super::decode_and_verify_proof(super::Config {
+            trie_root_hash: &trie_root_hash,
+            proof,
+        })
+        .unwrap();
+
+        assert_eq!(
+            decoded.next_key(&[], false).unwrap(),
+            Some(&to_nibbles(b"a")[..])
+        );
+        let missing = decoded.next_key(&to_nibbles(b"a"), false).unwrap_err();
+        assert!(to_nibbles(b"ab").starts_with(&missing.missing_node_prefix));
+    }
+
+    #[test]
+    fn child_trie_works() {
+        use super::super::{nibble, proof_encode, TrieEntryVersion};
+
+        let to_nibbles =
+            |key: &[u8]| nibble::bytes_to_nibbles(key.iter().copied()).collect::<Vec<_>>();
+
+        // The values are large enough for the nodes to not be inlined in their parent.
+        let child_entries = [
+            (&b"a"[..], &[1; 40][..]),
+            (&b"ab"[..], &[2; 40][..]),
+            (&b"b"[..], &[3; 40][..]),
+        ];
+        let (child_trie_root, child_proof) = proof_encode::build_proof_from_trie_entries(
+            TrieEntryVersion::V0,
+            child_entries,
+            child_entries.iter().map(|(k, _)| k),
+        );
+
+        let main_entries = [
+            (&b":child_storage:default:foo"[..], &child_trie_root[..]),
+            (&b"x"[..], &[4; 40][..]),
+        ];
+        let (trie_root_hash, main_proof) = proof_encode::build_proof_from_trie_entries(
+            TrieEntryVersion::V0,
+            main_entries,
+            main_entries.iter().map(|(k, _)| k),
+        );
+
+        let decoded = super::decode_and_verify_proof(super::Config {
+            trie_root_hash: &trie_root_hash,
+            proof: proof_encode::merge_proofs(&main_proof, &child_proof).unwrap(),
+        })
+        .unwrap();
+
+        // The child trie can be found through `child_tries`.
+        assert_eq!(decoded.child_tries.len(), 1);
+        assert!(decoded.child_tries.contains_key(&child_trie_root));
+
+        for (key, value) in child_entries {
+            assert_eq!(
+                decoded.child_trie_storage_value(b"foo", key),
+                Some(Some(value))
+            );
+            assert!(matches!(
+                decoded
+                    .child_trie_node_info(b"foo", &to_nibbles(key))
+                    .unwrap()
+                    .storage_value,
+                super::StorageValue::Known(v) if v == value
+            ));
+        }
+        assert_eq!(decoded.child_trie_storage_value(b"foo", b"c"), Some(None));
+        assert_eq!(decoded.child_trie_storage_value(b"foo", b"x"), Some(None));
+
+        // Child tries that don't exist are proven to be empty.
+        assert_eq!(decoded.child_trie_storage_value(b"bar", b"a"), Some(None));
+
+        // The keys of the child trie can be iterated, independently of the main trie.
+        assert_eq!(
+            decoded.child_trie_next_key(b"foo", &[], false).unwrap(),
+            Some(&to_nibbles(b"a")[..])
+        );
+        assert_eq!(
+            decoded
+                .child_trie_next_key(b"foo", &to_nibbles(b"ab"), false)
+                .unwrap(),
+            Some(&to_nibbles(b"b")[..])
+        );
+        assert_eq!(
+            decoded
+                .child_trie_next_key(b"foo", &to_nibbles(b"b"), false)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            decoded.child_trie_next_key(b"bar", &[], true).unwrap(),
+            None
+        );
+
+        // The entries of the child trie aren't mixed with the ones of the main trie. Below
+        // `CHILD_TRIE_PREFIX`, the main trie only contains the root of the child trie.
+        assert_eq!(
+            decoded
+                .iter_runtime_context_ordered()
+                .filter(|(_, value)| !matches!(value, super::StorageValue::None))
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>(),
+            vec![b":child_storage:default:foo".to_vec(), b"x".to_vec()]
+        );
+        let child_tries_prefix = to_nibbles(super::CHILD_TRIE_PREFIX);
+        assert_eq!(
+            decoded
+                .iter_ordered()
+                .filter(|(key, _)| key.starts_with(&child_tries_prefix))
+                .filter(|(_, entry)| {
+                    !matches!(
+                        entry.trie_node_info.storage_value,
+                        super::StorageValue::None
+                    )
+                })
+                .count(),
+            1
+        );
+        assert_eq!(
+            decoded.storage_value(b":child_storage:default:foo"),
+            Some(Some(&child_trie_root[..]))
+        );
+        assert_eq!(decoded.storage_value(b"a"), Some(None));
+
+        // A proof that contains the nodes of a child trie whose root isn't proven to be in the
+        // main trie is invalid.
+        let (trie_root_hash, main_proof) =
+            proof_encode::build_proof_from_trie_entries(TrieEntryVersion::V0, main_entries, [b"x"]);
+        assert!(matches!(
+            super::decode_and_verify_proof(super::Config {
+                trie_root_hash: &trie_root_hash,
+                proof: proof_encode::merge_proofs(&main_proof, &child_proof).unwrap(),
+            }),
+            Err(super::Error::UnusedProofEntry)
+        ));
+    }
 }
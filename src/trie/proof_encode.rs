@@ -15,10 +15,14 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use super::{nibble, proof_node_codec, trie_structure};
+use super::{nibble, proof_node_codec, trie_structure, TrieEntryVersion};
 
-use alloc::{borrow::ToOwned as _, vec::Vec};
-use core::{array, iter};
+use alloc::{
+    borrow::ToOwned as _,
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+use core::{array, iter, num::NonZeroUsize, ops};
 
 pub use super::nibble::Nibble;
 
@@ -392,7 +396,9 @@ impl ProofBuilder {
                     .user_data()
                     .take()
                 // Ignore nodes whose value is missing.
-                else { return either::Right(iter::empty()); };
+                else {
+                    return either::Right(iter::empty());
+                };
 
                 // Nodes of length < 32 should have been inlined within their parent or ancestor.
                 // We thus skip them, unless they're the root node.
@@ -434,6 +440,553 @@ impl ProofBuilder {
     }
 }
 
+/// Builds a Merkle proof of a list of keys out of all the entries of a trie.
+///
+/// The entries of the trie must be passed one by one using [`EntriesProofBuilder::push`], in
+/// lexicographic order of their keys. Only the nodes found between the root of the trie and
+/// the latest entry are kept in memory, meaning that the memory usage doesn't depend on the
+/// number of entries.
+///
+/// The proof contains the node values required in order to prove the storage value of each of
+/// the requested keys, or the absence of a storage value.
+pub struct EntriesProofBuilder {
+    /// Version of the trie entries, used to calculate the node values.
+    version: TrieEntryVersion,
+
+    /// Keys whose storage value must be proven, as nibbles.
+    keys: BTreeSet<Vec<Nibble>>,
+
+    /// Nodes between the root of the trie and the latest entry that has been pushed, ordered
+    /// from the root to the latest entry. The key of each node is a prefix of the key of the
+    /// next node.
+    ///
+    /// Nodes are popped from this stack as soon as their node value can be calculated, in
+    /// other words once all their descendants have been pushed.
+    stack: Vec<EntriesProofBuilderNode>,
+
+    /// Proof being built.
+    proof: ProofBuilder,
+
+    /// If `Some`, the Merkle value of each node with at least this number of entries in its
+    /// subtree is inserted in [`EntriesProofBuilder::recorded_subtrees`] when it is calculated.
+    recorded_subtrees_min_entries: Option<usize>,
+
+    /// See [`EntriesProofBuilder::recorded_subtrees_min_entries`].
+    recorded_subtrees: BTreeMap<Vec<Nibble>, Vec<u8>>,
+}
+
+struct EntriesProofBuilderNode {
+    /// Full key of the node.
+    key: Vec<Nibble>,
+    /// Storage value of the node, or `None` for branch nodes.
+    storage_value: Option<Vec<u8>>,
+    /// Merkle values of the children of the node that have been popped from the stack.
+    children: [Option<Vec<u8>>; 16],
+    /// If `Some`, the node and all its descendants have been pushed at once, and this is the
+    /// Merkle value of the node. The other fields are then irrelevant.
+    subtree_merkle_value: Option<Vec<u8>>,
+    /// Number of entries in the node and in its children that have been popped from the stack.
+    num_entries: usize,
+}
+
+impl EntriesProofBuilder {
+    /// Initializes a new builder. The proof will contain the keys passed as parameter.
+    pub fn new(
+        version: TrieEntryVersion,
+        keys: impl IntoIterator<Item = impl AsRef<[u8]>>,
+    ) -> Self {
+        EntriesProofBuilder {
+            version,
+            keys: keys
+                .into_iter()
+                .map(|key| nibble::bytes_to_nibbles(key.as_ref().iter().copied()).collect())
+                .collect(),
+            stack: Vec::with_capacity(32),
+            proof: ProofBuilder::new(),
+            recorded_subtrees_min_entries: None,
+            recorded_subtrees: BTreeMap::new(),
+        }
+    }
+
+    /// Adds an entry of the trie.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `key` isn't strictly superior to the key of the previous entry.
+    ///
+    pub fn push(&mut self, key: &[u8], value: &[u8]) {
+        self.push_node(EntriesProofBuilderNode {
+            key: nibble::bytes_to_nibbles(key.iter().copied()).collect(),
+            storage_value: Some(value.to_vec()),
+            children: Default::default(),
+            subtree_merkle_value: None,
+            num_entries: 1,
+        });
+    }
+
+    /// Adds an entry of the trie, and adds its key to the keys that the proof contains.
+    fn push_proven(&mut self, key: &[u8], value: &[u8]) {
+        let key = nibble::bytes_to_nibbles(key.iter().copied()).collect::<Vec<_>>();
+        self.keys.insert(key.clone());
+        self.push_node(EntriesProofBuilderNode {
+            key,
+            storage_value: Some(value.to_vec()),
+            children: Default::default(),
+            subtree_merkle_value: None,
+            num_entries: 1,
+        });
+    }
+
+    /// Adds at once the node of the trie whose key is `node_key` and all its descendants. None
+    /// of the keys that the proof contains must be found in this subtree.
+    fn push_subtree(&mut self, node_key: &[Nibble], merkle_value: &[u8]) {
+        self.push_node(EntriesProofBuilderNode {
+            key: node_key.to_vec(),
+            storage_value: None,
+            children: Default::default(),
+            subtree_merkle_value: Some(merkle_value.to_vec()),
+            num_entries: 0,
+        });
+    }
+
+    fn push_node(&mut self, node: EntriesProofBuilderNode) {
+        let key = node.key;
+
+        // The node at the top of the stack is always the previous entry. All the nodes whose
+        // key isn't a prefix of the new key have no more descendants to come.
+        if let Some(previous) = self.stack.last() {
+            assert!(key > previous.key);
+            assert!(previous.subtree_merkle_value.is_none() || !key.starts_with(&previous.key));
+            let common_prefix_len = previous
+                .key
+                .iter()
+                .zip(key.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            while matches!(self.stack.last(), Some(node) if node.key.len() > common_prefix_len) {
+                // If the parent of the node to pop isn't in the stack, then the node and the
+                // new entry are the children of a branch node that needs to be inserted.
+                if !matches!(self.stack.iter().rev().nth(1), Some(parent) if parent.key.len() >= common_prefix_len)
+                {
+                    let node = self.stack.pop().unwrap();
+                    self.stack.push(EntriesProofBuilderNode {
+                        key: node.key[..common_prefix_len].to_vec(),
+                        storage_value: None,
+                        children: Default::default(),
+                        subtree_merkle_value: None,
+                        num_entries: 0,
+                    });
+                    self.stack.push(node);
+                }
+
+                self.pop_node();
+            }
+        }
+
+        self.stack.push(EntriesProofBuilderNode { key, ..node });
+    }
+
+    /// Finishes building the proof.
+    ///
+    /// Returns the hash of the trie root and the proof.
+    pub fn build_to_vec(mut self) -> ([u8; 32], Vec<u8>) {
+        let trie_root_hash = self.finish();
+        (trie_root_hash, self.proof.build_to_vec())
+    }
+
+    /// Pops all the nodes of the stack and returns the hash of the trie root.
+    fn finish(&mut self) -> [u8; 32] {
+        while self.stack.len() >= 2 {
+            self.pop_node();
+        }
+
+        match self.stack.pop() {
+            Some(root) => <[u8; 32]>::try_from(self.node_merkle_value(root, None)).unwrap(),
+            None => super::empty_trie_merkle_value(),
+        }
+    }
+
+    /// Pops the node at the top of the stack and stores its Merkle value in its parent, which
+    /// must be the node right below it in the stack.
+    fn pop_node(&mut self) {
+        let node = self.stack.pop().unwrap();
+        let parent_key_len = self.stack.last().unwrap().key.len();
+        let child_index = usize::from(u8::from(node.key[parent_key_len]));
+        let num_entries = node.num_entries;
+        let merkle_value = self.node_merkle_value(node, Some(parent_key_len));
+        let parent = self.stack.last_mut().unwrap();
+        parent.children[child_index] = Some(merkle_value);
+        parent.num_entries += num_entries;
+    }
+
+    /// Calculates the node value of the given node, adds it to the proof if necessary, and
+    /// returns its Merkle value. `parent_key_len` is `None` for the root node.
+    fn node_merkle_value(
+        &mut self,
+        node: EntriesProofBuilderNode,
+        parent_key_len: Option<usize>,
+    ) -> Vec<u8> {
+        // Subtrees pushed at once never contain any of the keys of the proof.
+        if let Some(merkle_value) = node.subtree_merkle_value {
+            return merkle_value;
+        }
+
+        let partial_key = match parent_key_len {
+            Some(parent_key_len) => &node.key[parent_key_len + 1..],
+            None => &node.key[..],
+        };
+
+        let storage_value_hash;
+        let storage_value = match (&node.storage_value, self.version) {
+            (Some(value), TrieEntryVersion::V1) if value.len() >= 33 => {
+                storage_value_hash = blake2_hash(value);
+                proof_node_codec::StorageValue::Hashed(&storage_value_hash)
+            }
+            (Some(value), _) => proof_node_codec::StorageValue::Unhashed(value),
+            (None, _) => proof_node_codec::StorageValue::None,
+        };
+
+        let node_value = proof_node_codec::encode_to_vec(proof_node_codec::Decoded {
+            children: array::from_fn(|nibble| node.children[nibble].as_deref()),
+            partial_key: partial_key.iter().copied(),
+            storage_value,
+        });
+
+        // A node is part of the proof if it is found when descending from the root node towards
+        // one of the requested keys. This includes the node whose key diverges from the
+        // requested one, if any, in order to prove the absence of a storage value.
+        let in_proof = match parent_key_len {
+            Some(parent_key_len) => {
+                let prefix = &node.key[..=parent_key_len];
+                matches!(self.keys.range(prefix.to_vec()..).next(), Some(key) if key.starts_with(prefix))
+            }
+            None => !self.keys.is_empty(),
+        };
+
+        if in_proof {
+            self.proof.set_node_value(
+                &node.key,
+                &node_value,
+                if self.keys.contains(&node.key) {
+                    node.storage_value.as_deref()
+                } else {
+                    None
+                },
+            );
+        }
+
+        // The Merkle value of the root node is always hashed, while the Merkle value of other
+        // nodes is hashed only if the node value is at least 32 bytes long.
+        let merkle_value = if parent_key_len.is_none() || node_value.len() >= 32 {
+            blake2_hash(&node_value).to_vec()
+        } else {
+            node_value
+        };
+
+        if let Some(min_entries) = self.recorded_subtrees_min_entries {
+            if node.num_entries >= min_entries {
+                self.recorded_subtrees
+                    .insert(node.key, merkle_value.clone());
+            }
+        }
+
+        merkle_value
+    }
+}
+
+/// Merkle values of the nodes of a trie that have a large number of descendants.
+///
+/// Building a proof requires calculating the Merkle values of all the nodes of the trie. Thanks
+/// to this cache, [`build_range_proof`] doesn't need to read the entries of the subtrees whose
+/// Merkle value is known.
+pub struct SubtreesMerkleValues {
+    /// Version of the trie entries that has been used to calculate the Merkle values.
+    version: TrieEntryVersion,
+    /// Hash of the root of the trie.
+    trie_root_hash: [u8; 32],
+    /// Key of each node with its Merkle value. If a node is in this list, then all of its
+    /// ancestors are in this list as well.
+    nodes: BTreeMap<Vec<Nibble>, Vec<u8>>,
+}
+
+impl SubtreesMerkleValues {
+    /// Returns the hash of the root of the trie.
+    pub fn trie_root_hash(&self) -> &[u8; 32] {
+        &self.trie_root_hash
+    }
+
+    /// Returns the number of nodes whose Merkle value is known.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the Merkle value of no node is known, which is the case if the trie
+    /// has fewer entries than the `min_entries` passed to
+    /// [`SubtreesMerkleValuesBuilder::new`].
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the key of the node closest to the root whose key starts with `prefix` and whose
+    /// Merkle value is known, in other words the node at the root of the subtree of all the
+    /// entries that start with `prefix`.
+    fn subtree_root(&self, prefix: &[Nibble]) -> Option<(&[Nibble], &[u8])> {
+        // A node is always lexicographically inferior to its descendants.
+        self.nodes
+            .range::<[Nibble], _>((ops::Bound::Included(prefix), ops::Bound::Unbounded))
+            .next()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, merkle_value)| (&key[..], &merkle_value[..]))
+    }
+}
+
+/// Calculates a [`SubtreesMerkleValues`] from all the entries of a trie.
+///
+/// The entries of the trie must be passed one by one using
+/// [`SubtreesMerkleValuesBuilder::push`], in lexicographic order of their keys. The memory usage
+/// is proportional to the number of recorded nodes, and not to the number of entries.
+pub struct SubtreesMerkleValuesBuilder {
+    inner: EntriesProofBuilder,
+}
+
+impl SubtreesMerkleValuesBuilder {
+    /// Initializes a new builder. The Merkle value of every node with at least `min_entries`
+    /// entries in its subtree, including itself, is recorded.
+    ///
+    /// A higher `min_entries` reduces the memory usage of the [`SubtreesMerkleValues`], but
+    /// increases the number of entries that [`build_range_proof`] has to read.
+    pub fn new(version: TrieEntryVersion, min_entries: NonZeroUsize) -> Self {
+        let mut inner = EntriesProofBuilder::new(version, iter::empty::<Vec<u8>>());
+        inner.recorded_subtrees_min_entries = Some(min_entries.get());
+        SubtreesMerkleValuesBuilder { inner }
+    }
+
+    /// Adds an entry of the trie.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `key` isn't strictly superior to the key of the previous entry.
+    ///
+    pub fn push(&mut self, key: &[u8], value: &[u8]) {
+        self.inner.push(key, value)
+    }
+
+    /// Finishes calculating the Merkle values.
+    pub fn build(mut self) -> SubtreesMerkleValues {
+        let trie_root_hash = self.inner.finish();
+        SubtreesMerkleValues {
+            version: self.inner.version,
+            trie_root_hash,
+            nodes: self.inner.recorded_subtrees,
+        }
+    }
+}
+
+/// Builds a Merkle proof of the entries of a trie whose key is superior or equal to
+/// `start_key`, in lexicographic order, for a total size of keys and values of at most
+/// `max_size`. At least one entry is always included, even if it is larger than `max_size`.
+///
+/// `subtrees` must have been calculated from the same trie. `for_each_entry` is called with a
+/// key and a callback, and must call this callback with each entry of the trie whose key is
+/// superior or equal to the key, in lexicographic order, until the callback returns `false`.
+///
+/// Apart from the entries included in the proof, the entries that are read are the ones of the
+/// subtrees that aren't in `subtrees` and that are next to the beginning or the end of the
+/// range of the proof.
+pub fn build_range_proof<E>(
+    subtrees: &SubtreesMerkleValues,
+    start_key: &[u8],
+    max_size: usize,
+    mut for_each_entry: impl FnMut(&[u8], &mut dyn FnMut(&[u8], &[u8]) -> bool) -> Result<(), E>,
+) -> Result<RangeProof, E> {
+    let mut range_proof = RangeProofBuild {
+        builder: EntriesProofBuilder::new(subtrees.version, iter::empty::<Vec<u8>>()),
+        start_key,
+        start_key_nibbles: nibble::bytes_to_nibbles(start_key.iter().copied()).collect(),
+        max_size,
+        range: RangeProofBuildRange::Before,
+    };
+
+    range_proof.add_subtree(subtrees, &[], &mut for_each_entry)?;
+
+    let complete = !matches!(range_proof.range, RangeProofBuildRange::After);
+    let (trie_root_hash, proof) = range_proof.builder.build_to_vec();
+    Ok(RangeProof {
+        trie_root_hash,
+        proof,
+        complete,
+    })
+}
+
+/// Proof generated by [`build_range_proof`].
+#[derive(Debug, Clone)]
+pub struct RangeProof {
+    /// Hash of the root of the trie, as calculated while building the proof.
+    pub trie_root_hash: [u8; 32],
+    /// SCALE-encoded Merkle proof.
+    pub proof: Vec<u8>,
+    /// `true` if the proof contains all the entries of the trie starting at the requested key.
+    /// `false` if the size limit has been reached.
+    pub complete: bool,
+}
+
+/// In-progress [`build_range_proof`].
+struct RangeProofBuild<'a> {
+    builder: EntriesProofBuilder,
+    start_key: &'a [u8],
+    start_key_nibbles: Vec<Nibble>,
+    max_size: usize,
+    range: RangeProofBuildRange,
+}
+
+/// Position of the entries being added relative to the range of entries of the proof.
+enum RangeProofBuildRange {
+    /// Entries are before the start key.
+    Before,
+    /// Entries are in the proof. Contains the total size of the keys and values so far.
+    In(usize),
+    /// The size limit has been reached.
+    After,
+}
+
+impl<'a> RangeProofBuild<'a> {
+    /// Adds all the entries of the trie whose key starts with `prefix`.
+    fn add_subtree<E>(
+        &mut self,
+        subtrees: &SubtreesMerkleValues,
+        prefix: &[Nibble],
+        for_each_entry: &mut impl FnMut(&[u8], &mut dyn FnMut(&[u8], &[u8]) -> bool) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let (node_key, merkle_value) = match subtrees.subtree_root(prefix) {
+            Some(node) => node,
+            None => {
+                // The Merkle value of the subtree isn't known, in which case all of its entries
+                // are read one by one.
+                let start = nibble::nibbles_to_bytes_suffix_extend(prefix.iter().copied())
+                    .collect::<Vec<_>>();
+                return for_each_entry(&start, &mut |key, value| {
+                    if !nibble::bytes_to_nibbles(key.iter().copied())
+                        .take(prefix.len())
+                        .eq(prefix.iter().copied())
+                    {
+                        return false;
+                    }
+                    self.add_entry(key, value);
+                    true
+                });
+            }
+        };
+
+        // Subtrees that aren't part of the range of the proof are added at once.
+        if matches!(self.range, RangeProofBuildRange::After)
+            || (*node_key < *self.start_key_nibbles
+                && !self.start_key_nibbles.starts_with(node_key))
+        {
+            self.builder.push_subtree(node_key, merkle_value);
+            return Ok(());
+        }
+
+        if node_key.len() % 2 == 0 {
+            let node_key_bytes = nibble::nibbles_to_bytes_suffix_extend(node_key.iter().copied())
+                .collect::<Vec<_>>();
+            for_each_entry(&node_key_bytes, &mut |key, value| {
+                if key == node_key_bytes {
+                    self.add_entry(key, value);
+                }
+                false
+            })?;
+        }
+
+        let mut child_prefix = node_key.to_vec();
+        for nibble in nibble::all_nibbles() {
+            child_prefix.push(nibble);
+            self.add_subtree(subtrees, &child_prefix, for_each_entry)?;
+            child_prefix.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Adds an entry of the trie, and includes it in the proof if it is part of the range.
+    fn add_entry(&mut self, key: &[u8], value: &[u8]) {
+        if matches!(self.range, RangeProofBuildRange::Before) && key >= self.start_key {
+            self.range = RangeProofBuildRange::In(0);
+        }
+
+        if let RangeProofBuildRange::In(total_size) = &mut self.range {
+            if *total_size != 0 && *total_size + key.len() + value.len() > self.max_size {
+                self.range = RangeProofBuildRange::After;
+            } else {
+                *total_size += key.len() + value.len();
+                self.builder.push_proven(key, value);
+                return;
+            }
+        }
+
+        self.builder.push(key, value);
+    }
+}
+
+/// Builds a Merkle proof of the given keys out of all the entries of a trie.
+///
+/// `entries` must contain all the `(key, value)` pairs of the trie, in lexicographic order of
+/// the keys. Returns the hash of the trie root and the proof.
+///
+/// This is a convenient wrapper around [`EntriesProofBuilder`].
+///
+/// # Panic
+///
+/// Panics if `entries` isn't in strictly increasing order of keys.
+///
+pub fn build_proof_from_trie_entries(
+    version: TrieEntryVersion,
+    entries: impl IntoIterator<Item = (impl AsRef<[u8]>, impl AsRef<[u8]>)>,
+    keys: impl IntoIterator<Item = impl AsRef<[u8]>>,
+) -> ([u8; 32], Vec<u8>) {
+    let mut builder = EntriesProofBuilder::new(version, keys);
+    for (key, value) in entries {
+        builder.push(key.as_ref(), value.as_ref());
+    }
+    builder.build_to_vec()
+}
+
+/// Merges two SCALE-encoded Merkle proofs into one, ignoring the entries of `b` that are already
+/// found in `a`.
+///
+/// Returns `None` if one of the two proofs isn't in a valid format.
+pub fn merge_proofs(a: &[u8], b: &[u8]) -> Option<Vec<u8>> {
+    fn decode(proof: &[u8]) -> Option<Vec<&[u8]>> {
+        nom::combinator::all_consuming(nom::combinator::flat_map(
+            crate::util::nom_scale_compact_usize,
+            |num_elems| nom::multi::many_m_n(num_elems, num_elems, crate::util::nom_bytes_decode),
+        ))(proof)
+        .map(|(_, entries)| entries)
+        .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| ())
+        .ok()
+    }
+
+    let mut entries = decode(a)?;
+    let existing = entries
+        .iter()
+        .copied()
+        .collect::<hashbrown::HashSet<_, fnv::FnvBuildHasher>>();
+    let new_entries = decode(b)?
+        .into_iter()
+        .filter(|entry| !existing.contains(entry))
+        .collect::<Vec<_>>();
+    entries.extend(new_entries);
+
+    let mut out = crate::util::encode_scale_compact_usize(entries.len())
+        .as_ref()
+        .to_vec();
+    for entry in entries {
+        out.extend_from_slice(crate::util::encode_scale_compact_usize(entry.len()).as_ref());
+        out.extend_from_slice(entry);
+    }
+    Some(out)
+}
+
 fn blake2_hash(data: &[u8]) -> [u8; 32] {
     *&<[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], data).as_bytes()).unwrap()
 }
@@ -441,7 +994,8 @@ fn blake2_hash(data: &[u8]) -> [u8; 32] {
 #[cfg(test)]
 mod tests {
     use super::super::{nibble, proof_decode, proof_node_codec, trie_structure};
-    use core::array;
+    use alloc::collections::BTreeMap;
+    use core::{array, convert::Infallible, num::NonZeroUsize};
     use rand::distributions::{Distribution as _, Uniform};
 
     #[test]
@@ -659,4 +1213,258 @@ mod tests {
         })
         .unwrap();
     }
+
+    #[test]
+    fn build_proof_from_trie_entries_verifies() {
+        for version in [super::TrieEntryVersion::V0, super::TrieEntryVersion::V1] {
+            let entries = [
+                (&b"foo"[..], &b"bar"[..]),
+                (&b"food"[..], &[0x55; 40][..]),
+                (&b"fooz"[..], &b"baz"[..]),
+                (&b"hello"[..], &b"world"[..]),
+            ];
+
+            let (trie_root_hash, proof) = super::build_proof_from_trie_entries(
+                version,
+                entries,
+                [&b"food"[..], &b"foobar"[..], &b"hello"[..]],
+            );
+
+            assert_eq!(trie_root_hash, super::super::trie_root(version, &entries));
+
+            let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+                trie_root_hash: &trie_root_hash,
+                proof,
+            })
+            .unwrap();
+
+            assert_eq!(decoded.storage_value(b"food"), Some(Some(&[0x55; 40][..])));
+            assert_eq!(decoded.storage_value(b"foobar"), Some(None));
+            assert_eq!(decoded.storage_value(b"hello"), Some(Some(&b"world"[..])));
+        }
+    }
+
+    #[test]
+    fn build_proof_from_random_trie_entries() {
+        // We repeat the test many times due to its random factor.
+        for _ in 0..500 {
+            // Keys use a small alphabet in order for them to often share a prefix.
+            let random_bytes = |min: usize, max: usize, alphabet: u8| {
+                let len = Uniform::new_inclusive(min, max).sample(&mut rand::thread_rng());
+                (0..len)
+                    .map(|_| Uniform::new(0, alphabet).sample(&mut rand::thread_rng()))
+                    .collect::<Vec<u8>>()
+            };
+
+            let entries = (0..Uniform::new_inclusive(0, 48).sample(&mut rand::thread_rng()))
+                .map(|_| (random_bytes(0, 4, 3), random_bytes(0, 64, 255)))
+                .collect::<alloc::collections::BTreeMap<_, _>>();
+            let requested_keys = (0..8).map(|_| random_bytes(0, 5, 3)).collect::<Vec<_>>();
+
+            for version in [super::TrieEntryVersion::V0, super::TrieEntryVersion::V1] {
+                let (trie_root_hash, proof) =
+                    super::build_proof_from_trie_entries(version, &entries, &requested_keys);
+
+                assert_eq!(
+                    trie_root_hash,
+                    super::super::trie_root(version, &entries.iter().collect::<Vec<_>>())
+                );
+
+                if requested_keys.is_empty() || entries.is_empty() {
+                    continue;
+                }
+
+                let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+                    trie_root_hash: &trie_root_hash,
+                    proof,
+                })
+                .unwrap();
+
+                for key in &requested_keys {
+                    assert_eq!(
+                        decoded.storage_value(key),
+                        Some(entries.get(key).map(|v| &v[..]))
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn build_proof_from_unordered_trie_entries() {
+        super::build_proof_from_trie_entries(
+            super::TrieEntryVersion::V0,
+            [(&b"b"[..], &b"1"[..]), (&b"a"[..], &b"2"[..])],
+            [&b"a"[..]],
+        );
+    }
+
+    #[test]
+    fn merge_proofs_contains_both() {
+        let entries = [
+            (&b"foo"[..], &[1; 40][..]),
+            (&b"hello"[..], &[2; 40][..]),
+            (&b"world"[..], &[3; 40][..]),
+        ];
+
+        let (trie_root_hash, proof1) =
+            super::build_proof_from_trie_entries(super::TrieEntryVersion::V0, entries, [b"foo"]);
+        let (_, proof2) =
+            super::build_proof_from_trie_entries(super::TrieEntryVersion::V0, entries, [b"world"]);
+
+        let merged = super::merge_proofs(&proof1, &proof2).unwrap();
+        let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+            trie_root_hash: &trie_root_hash,
+            proof: merged,
+        })
+        .unwrap();
+
+        assert_eq!(decoded.storage_value(b"foo"), Some(Some(&[1; 40][..])));
+        assert_eq!(decoded.storage_value(b"world"), Some(Some(&[3; 40][..])));
+        assert!(super::merge_proofs(&proof1, &[0xff]).is_none());
+    }
+
+    /// Calls [`super::build_range_proof`] with the given entries, and returns the proof and the
+    /// number of entries that have been read.
+    fn build_range_proof(
+        entries: &BTreeMap<Vec<u8>, Vec<u8>>,
+        subtrees: &super::SubtreesMerkleValues,
+        start_key: &[u8],
+        max_size: usize,
+    ) -> (super::RangeProof, usize) {
+        let mut num_read = 0;
+        let proof = super::build_range_proof(subtrees, start_key, max_size, |start, callback| {
+            for (key, value) in entries.range(start.to_vec()..) {
+                num_read += 1;
+                if !callback(key, value) {
+                    break;
+                }
+            }
+            Ok::<_, Infallible>(())
+        })
+        .unwrap();
+        (proof, num_read)
+    }
+
+    #[test]
+    fn range_proof_identical_to_entries_proof() {
+        // We repeat the test many times due to its random factor.
+        for _ in 0..500 {
+            // Keys use a small alphabet in order for them to often share a prefix.
+            let random_bytes = |min: usize, max: usize, alphabet: u8| {
+                let len = Uniform::new_inclusive(min, max).sample(&mut rand::thread_rng());
+                (0..len)
+                    .map(|_| Uniform::new(0, alphabet).sample(&mut rand::thread_rng()))
+                    .collect::<Vec<u8>>()
+            };
+
+            let entries = (0..Uniform::new_inclusive(0, 96).sample(&mut rand::thread_rng()))
+                .map(|_| (random_bytes(0, 5, 3), random_bytes(0, 64, 255)))
+                .collect::<BTreeMap<_, _>>();
+            let start_key = random_bytes(0, 5, 3);
+            let max_size = Uniform::new_inclusive(0, 256).sample(&mut rand::thread_rng());
+            let min_entries = Uniform::new_inclusive(1, 8).sample(&mut rand::thread_rng());
+
+            for version in [super::TrieEntryVersion::V0, super::TrieEntryVersion::V1] {
+                let mut subtrees = super::SubtreesMerkleValuesBuilder::new(
+                    version,
+                    NonZeroUsize::new(min_entries).unwrap(),
+                );
+                for (key, value) in &entries {
+                    subtrees.push(key, value);
+                }
+                let subtrees = subtrees.build();
+
+                let (range_proof, _) = build_range_proof(&entries, &subtrees, &start_key, max_size);
+
+                // Determine which keys are supposed to be in the proof.
+                let mut expected_keys = Vec::new();
+                let mut total_size = 0;
+                let mut complete = true;
+                for (key, value) in entries.range(start_key.clone()..) {
+                    if total_size != 0 && total_size + key.len() + value.len() > max_size {
+                        complete = false;
+                        break;
+                    }
+                    total_size += key.len() + value.len();
+                    expected_keys.push(key.clone());
+                }
+
+                let (trie_root_hash, proof) =
+                    super::build_proof_from_trie_entries(version, &entries, &expected_keys);
+                assert_eq!(*subtrees.trie_root_hash(), trie_root_hash);
+                assert_eq!(range_proof.trie_root_hash, trie_root_hash);
+                assert_eq!(range_proof.proof, proof);
+                assert_eq!(range_proof.complete, complete);
+            }
+        }
+    }
+
+    #[test]
+    fn large_range_proof_cut_off() {
+        let entries = (0..20000u32)
+            .map(|n| {
+                let key = super::blake2_hash(&n.to_le_bytes()).to_vec();
+                (key, vec![0xaa; 100])
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let mut subtrees = super::SubtreesMerkleValuesBuilder::new(
+            super::TrieEntryVersion::V1,
+            NonZeroUsize::new(64).unwrap(),
+        );
+        for (key, value) in &entries {
+            subtrees.push(key, value);
+        }
+        let subtrees = subtrees.build();
+
+        // Download all the entries using proofs of at most 16kiB, as a state sync would do.
+        let mut downloaded = BTreeMap::new();
+        let mut start_key = Vec::new();
+        loop {
+            let (range_proof, num_read) =
+                build_range_proof(&entries, &subtrees, &start_key, 16 * 1024);
+            assert_eq!(range_proof.trie_root_hash, *subtrees.trie_root_hash());
+
+            // Only the entries around the range of the proof must have been read.
+            assert!(num_read < 2000, "{}", num_read);
+
+            let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+                trie_root_hash: subtrees.trie_root_hash(),
+                proof: &range_proof.proof,
+            })
+            .unwrap();
+
+            let mut iter_key =
+                nibble::bytes_to_nibbles(start_key.iter().copied()).collect::<Vec<_>>();
+            let mut or_equal = true;
+            let mut num_entries = 0;
+            let finished = loop {
+                let key = match decoded.next_key(&iter_key, or_equal) {
+                    Ok(Some(key)) => key.to_vec(),
+                    Ok(None) => break true,
+                    Err(_) => break false,
+                };
+                let key_bytes =
+                    nibble::nibbles_to_bytes_suffix_extend(key.iter().copied()).collect::<Vec<_>>();
+                let value = decoded.storage_value(&key_bytes).unwrap().unwrap();
+                downloaded.insert(key_bytes.clone(), value.to_vec());
+                num_entries += 1;
+                start_key = key_bytes;
+                iter_key = key;
+                or_equal = false;
+            };
+
+            assert_eq!(finished, range_proof.complete);
+            if finished {
+                break;
+            }
+
+            // Each entry is 132 bytes long.
+            assert_eq!(num_entries, 16 * 1024 / 132);
+        }
+
+        assert_eq!(downloaded, entries);
+    }
 }
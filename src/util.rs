@@ -18,6 +18,7 @@
 //! Internal module. Contains functions that aren't Substrate/Polkadot-specific and should ideally
 //! be found in third party libraries, but that aren't worth a third-party library.
 
+use alloc::vec::Vec;
 use core::{cmp, str};
 
 pub(crate) mod leb128;
@@ -112,6 +113,16 @@ pub(crate) fn nom_varsize_number_decode_u64<'a, E: nom::error::ParseError<&'a [u
     )
 }
 
+/// Appends to `out` the little endian encoding of a number whose number of bytes isn't known at
+/// compile-time. Counterpart of [`nom_varsize_number_decode_u64`].
+pub(crate) fn encode_varsize_number_u64(out: &mut Vec<u8>, value: u64, num_bytes: usize) {
+    let bytes = value.to_le_bytes();
+    // TODO: unclear what to do if the number doesn't fit in `num_bytes`
+    debug_assert!(!bytes.iter().skip(num_bytes).any(|b| *b != 0));
+    out.extend_from_slice(&bytes[..cmp::min(bytes.len(), num_bytes)]);
+    out.resize(out.len() + num_bytes.saturating_sub(bytes.len()), 0);
+}
+
 macro_rules! decode_scale_compact {
     ($fn_name:ident, $num_ty:ty) => {
         /// Decodes a SCALE-compact-encoded integer.
@@ -459,23 +459,13 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
     // claim. If the block is a secondary slot claim, we need to make sure that the author
     // is indeed the one that is expected.
     if !primary_slot_claim {
-        // Expected author is determined based on `blake2(randomness | slot_number)`.
-        let hash = {
-            let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
-            hash.update(block_epoch_info.randomness);
-            hash.update(&slot_number.to_le_bytes());
-            hash.finalize()
-        };
-
-        // The expected authority index is `hash % num_authorities`.
-        let expected_authority_index = {
-            let hash = num_bigint::BigUint::from_bytes_be(hash.as_bytes());
-            let authorities_len = num_bigint::BigUint::from(block_epoch_info.authorities.len());
-            debug_assert_ne!(block_epoch_info.authorities.len(), 0);
-            hash % authorities_len
-        };
+        let expected_authority_index = calculate_secondary_slot_author(
+            block_epoch_info.randomness,
+            slot_number,
+            block_epoch_info.authorities.len(),
+        );
 
-        if u32::try_from(expected_authority_index).map_or(true, |v| v != authority_index) {
+        if usize::try_from(authority_index) != Ok(expected_authority_index) {
             return Err(VerifyError::BadSecondarySlotAuthor);
         }
     }
@@ -487,6 +477,37 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
     })
 }
 
+/// Calculates the index, within the list of authorities of the epoch, of the authority allowed
+/// to claim the given slot as a secondary slot claim.
+///
+/// The expected author is determined based on `blake2(randomness | slot_number)`, modulo the
+/// number of authorities.
+///
+/// # Panic
+///
+/// Panics if `num_authorities` is 0.
+///
+pub(crate) fn calculate_secondary_slot_author(
+    randomness: &[u8; 32],
+    slot_number: u64,
+    num_authorities: usize,
+) -> usize {
+    assert_ne!(num_authorities, 0);
+
+    let hash = {
+        let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
+        hash.update(randomness);
+        hash.update(&slot_number.to_le_bytes());
+        hash.finalize()
+    };
+
+    let hash = num_bigint::BigUint::from_bytes_be(hash.as_bytes());
+    let authorities_len = num_bigint::BigUint::from(num_authorities);
+    // The result of the modulo is always inferior to `num_authorities`, and thus always fits
+    // in a `usize`.
+    (hash % authorities_len).to_usize().unwrap()
+}
+
 /// Calculates the primary selection threshold for a given authority, taking
 /// into account `c` (`1 - c` represents the probability of a slot being empty).
 ///
@@ -500,7 +521,7 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
 /// Panics if `authorities_weights` is empty.
 /// Panics if `authority_weight` is 0.
 ///
-fn calculate_primary_threshold(
+pub(crate) fn calculate_primary_threshold(
     c: (u64, u64),
     authorities_weights: impl ExactSizeIterator<Item = u64>,
     authority_weight: u64, // TODO: use a NonZeroU64 once crate::header also has weights that use NonZeroU64
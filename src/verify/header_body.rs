@@ -24,7 +24,7 @@ use crate::{
     verify::{aura, babe, inherents},
 };
 
-use alloc::{string::String, vec::Vec};
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
 use core::{iter, num::NonZeroU64, time::Duration};
 
 /// Configuration for a block verification.
@@ -115,6 +115,10 @@ pub struct Success {
     /// List of changes to the storage top trie that the block performs.
     pub storage_top_trie_changes: storage_diff::StorageDiff,
 
+    /// List of changes to the default child tries that the block performs. Keys are the
+    /// identifiers of the child tries, without the `:child_storage:default:` prefix.
+    pub storage_child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
+
     /// List of changes to the off-chain storage that this block performs.
     pub offchain_storage_changes: storage_diff::StorageDiff,
 
@@ -364,6 +368,7 @@ pub fn verify(
             },
             top_trie_root_calculation_cache: config.top_trie_root_calculation_cache,
             storage_top_trie_changes: Default::default(),
+            storage_child_tries_changes: Default::default(),
             offchain_storage_changes: Default::default(),
         });
 
@@ -443,6 +448,7 @@ impl VerifyInner {
                                 success.top_trie_root_calculation_cache,
                             ),
                             storage_top_trie_changes: success.storage_top_trie_changes,
+                            storage_child_tries_changes: success.storage_child_tries_changes,
                             offchain_storage_changes: success.offchain_storage_changes,
                         });
 
@@ -512,6 +518,7 @@ impl VerifyInner {
                                 logs: success.logs,
                                 offchain_storage_changes: success.offchain_storage_changes,
                                 storage_top_trie_changes: success.storage_top_trie_changes,
+                                storage_child_tries_changes: success.storage_child_tries_changes,
                                 top_trie_root_calculation_cache: success
                                     .top_trie_root_calculation_cache,
                             });
@@ -523,6 +530,7 @@ impl VerifyInner {
                         new_runtime: None,
                         consensus: self.consensus_success,
                         storage_top_trie_changes: success.storage_top_trie_changes,
+                        storage_child_tries_changes: success.storage_child_tries_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
                         top_trie_root_calculation_cache: success.top_trie_root_calculation_cache,
                         logs: success.logs,
@@ -552,6 +560,9 @@ impl VerifyInner {
                 runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
                     self.inner = sig.verify_and_resume();
                 }
+                runtime_host::RuntimeHostVm::Offchain(ctx) => {
+                    self.inner = runtime_host::RuntimeHostVm::Finished(Err(ctx.forbid()));
+                }
             }
         }
     }
@@ -572,6 +583,12 @@ impl StorageGet {
         self.inner.key()
     }
 
+    /// If `Some`, the key must be read from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Injects the corresponding storage value.
     pub fn inject_value(self, value: Option<impl Iterator<Item = impl AsRef<[u8]>>>) -> Verify {
         VerifyInner {
@@ -598,6 +615,12 @@ impl StoragePrefixKeys {
         self.inner.prefix()
     }
 
+    /// If `Some`, the keys must be loaded from the given default child trie rather than from the
+    /// main trie. The child trie identifier doesn't include the `:child_storage:default:` prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Injects the list of keys ordered lexicographically.
     pub fn inject_keys_ordered(self, keys: impl Iterator<Item = impl AsRef<[u8]>>) -> Verify {
         VerifyInner {
@@ -624,6 +647,13 @@ impl StorageNextKey {
         self.inner.key()
     }
 
+    /// If `Some`, the next key must be searched in the given default child trie rather than in
+    /// the main trie. The child trie identifier doesn't include the `:child_storage:default:`
+    /// prefix.
+    pub fn child_trie(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        self.inner.child_trie()
+    }
+
     /// Injects the key.
     ///
     /// # Panic
@@ -648,6 +678,7 @@ impl StorageNextKey {
 pub struct RuntimeCompilation {
     parent_runtime: host::HostVmPrototype,
     storage_top_trie_changes: storage_diff::StorageDiff,
+    storage_child_tries_changes: BTreeMap<Vec<u8>, storage_diff::StorageDiff>,
     offchain_storage_changes: storage_diff::StorageDiff,
     top_trie_root_calculation_cache: calculate_root::CalculationCache,
     logs: String,
@@ -686,6 +717,7 @@ impl RuntimeCompilation {
             new_runtime: Some(new_runtime),
             consensus: self.consensus_success,
             storage_top_trie_changes: self.storage_top_trie_changes,
+            storage_child_tries_changes: self.storage_child_tries_changes,
             offchain_storage_changes: self.offchain_storage_changes,
             top_trie_root_calculation_cache: self.top_trie_root_calculation_cache,
             logs: self.logs,